@@ -0,0 +1,95 @@
+//! Times intersecting rays with a sphere and with scenes of growing size,
+//! through each of the acceleration structures:
+//!
+//! ```sh
+//! cargo bench --bench intersect
+//! cargo bench --bench intersect --features simd
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use tiny_raytracer::accel::AcceleratorKind;
+use tiny_raytracer::material::Material;
+use tiny_raytracer::object::{Hittable, Object, Sphere, Triangle};
+use tiny_raytracer::ray::Ray;
+use tiny_raytracer::scene::Scene;
+use tiny_raytracer::vector::{Float, Vec3f};
+
+const RAYS: usize = 1 << 14;
+const ROUNDS: usize = 20;
+
+/// A number in `-1..1` from a cheap hash of `i` and `k`.
+fn hash(i: usize, k: u32) -> Float {
+    ((i as u32).wrapping_mul(2654435761).rotate_left(k) % 2000) as Float / 1000.0 - 1.0
+}
+
+/// Runs `f` over the rays `ROUNDS` times and prints the time per ray.
+fn bench(name: &str, rays: &[Ray], f: impl Fn(&Ray) -> bool) {
+    let start = Instant::now();
+    let mut hits = 0;
+    for _ in 0..ROUNDS {
+        for ray in black_box(rays) {
+            hits += f(ray) as usize;
+        }
+    }
+    black_box(hits);
+    let per_ray = start.elapsed().as_secs_f64() * 1e9 / (ROUNDS * rays.len()) as f64;
+    println!("{:<24} {:8.2} ns", name, per_ray);
+}
+
+/// `count` spheres or triangles scattered in front of the origin.
+fn objects(count: usize, triangles: bool) -> Vec<Box<dyn Object>> {
+    (0..count)
+        .map(|i| {
+            let center = Vec3f::new(
+                hash(i, 0) * 8.0,
+                hash(i, 11) * 6.0,
+                -12.0 + hash(i, 22) * 4.0,
+            );
+            let size = 1.5 / (count as Float).cbrt();
+            if triangles {
+                Box::new(Triangle::new(
+                    center,
+                    center + Vec3f::new(size, 0.0, 0.0),
+                    center + Vec3f::new(0.0, size, 0.0),
+                    Material::default(),
+                )) as Box<dyn Object>
+            } else {
+                Box::new(Sphere::new(center, size, Material::default()))
+            }
+        })
+        .collect()
+}
+
+fn main() {
+    // Rays from the origin, spread over the view of the tutorial camera
+    let rays: Vec<_> = (0..RAYS)
+        .map(|i| {
+            let dir = Vec3f::new(hash(i, 3) * 0.6, hash(i, 17) * 0.45, -1.0);
+            Ray::new(Vec3f::new(0.0, 0.0, 0.0), dir.normalize())
+        })
+        .collect();
+
+    let sphere = Sphere::new(Vec3f::new(0.0, 0.0, -16.0), 4.0, Material::default());
+    bench("sphere", &rays, |ray| sphere.ray_intersect(ray).is_some());
+
+    for &count in &[10, 100, 1000] {
+        // Spheres are intersected in packets, whatever the accelerator
+        let scene = Scene::new(objects(count, false), vec![]);
+        bench(&format!("spheres {}", count), &rays, |ray| {
+            scene.hit(ray).is_some()
+        });
+        for &accel in &[
+            AcceleratorKind::Bvh,
+            AcceleratorKind::KdTree,
+            AcceleratorKind::Grid,
+            AcceleratorKind::None,
+        ] {
+            let scene = Scene::with_accelerator(objects(count, true), vec![], accel);
+            bench(&format!("triangles {} {}", count, accel), &rays, |ray| {
+                scene.hit(ray).is_some()
+            });
+        }
+    }
+}
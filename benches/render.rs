@@ -0,0 +1,47 @@
+//! Times whole frames of the example scenes at a low resolution, with the
+//! direct and the path tracing integrators:
+//!
+//! ```sh
+//! cargo bench --bench render
+//! ```
+
+use std::time::{Duration, Instant};
+
+use tiny_raytracer::integrator::Integrator;
+use tiny_raytracer::render::{self, RenderOptions, RenderState};
+use tiny_raytracer::scene::Scene;
+
+const WIDTH: usize = 160;
+const HEIGHT: usize = 120;
+const ROUNDS: usize = 5;
+
+/// Renders the scene `ROUNDS` times and prints the fastest frame, which is
+/// the least disturbed by whatever else the machine is doing.
+fn bench(name: &str, scene: &Scene, options: &RenderOptions) {
+    let mut fastest = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let state = RenderState::new(WIDTH, HEIGHT, options.tile_size);
+        render::resume(scene, options, state, |_, _| {});
+        fastest = fastest.min(start.elapsed());
+    }
+    println!("{:<24} {:8.2} ms", name, fastest.as_secs_f64() * 1e3);
+}
+
+fn main() {
+    let scenes = [
+        ("tutorial", Scene::tutorial()),
+        ("primitives", Scene::load("scenes/primitives.json").unwrap()),
+        ("mesh", Scene::load("scenes/mesh.json").unwrap()),
+        ("emissive", Scene::load("scenes/emissive.json").unwrap()),
+    ];
+    for (name, scene) in &scenes {
+        bench(name, scene, &RenderOptions::default());
+        let options = RenderOptions {
+            integrator: Integrator::Path,
+            samples_per_pixel: 4,
+            ..RenderOptions::default()
+        };
+        bench(&format!("{} path", name), scene, &options);
+    }
+}
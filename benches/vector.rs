@@ -0,0 +1,53 @@
+//! Times the vector math of the hot shading loop, for comparing builds with
+//! and without the `portable-simd` feature:
+//!
+//! ```sh
+//! cargo bench --bench vector
+//! cargo +nightly bench --bench vector --features portable-simd
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use tiny_raytracer::vector::{Float, Vec3f};
+
+const COUNT: usize = 1 << 16;
+const ROUNDS: usize = 200;
+
+/// Runs `f` over the vectors `ROUNDS` times and prints the time per vector.
+fn bench(name: &str, vectors: &[Vec3f], f: impl Fn(&Vec3f, &Vec3f) -> Vec3f) {
+    let start = Instant::now();
+    let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+    for _ in 0..ROUNDS {
+        for pair in black_box(vectors).windows(2) {
+            sum += f(&pair[0], &pair[1]);
+        }
+    }
+    black_box(sum);
+    let per_vector = start.elapsed().as_secs_f64() * 1e9 / (ROUNDS * (COUNT - 1)) as f64;
+    println!("{:<12} {:6.2} ns", name, per_vector);
+}
+
+fn main() {
+    // Scattered unit-ish vectors, from a cheap hash
+    let vectors: Vec<_> = (0..COUNT as u32)
+        .map(|i| {
+            let h =
+                |k: u32| (i.wrapping_mul(2654435761).rotate_left(k) % 2000) as Float / 1000.0 - 1.0;
+            Vec3f::new(h(0), h(11), h(22))
+        })
+        .collect();
+
+    bench("dot", &vectors, |a, b| *a * a.dot(b));
+    bench("normalize", &vectors, |a, b| (*a + *b).normalize());
+    bench("cross", &vectors, |a, b| a.cross(b));
+    bench("blend", &vectors, |a, b| a.lerp(b, 0.25) * *b + *a * 0.5);
+    bench("shade", &vectors, |n, l| {
+        // The diffuse and specular terms of the Phong model
+        let l = l.normalize();
+        let diffuse = n.dot(&l).max(0.0);
+        let reflected = l - *n * (2.0 * n.dot(&l));
+        let specular = reflected.dot(n).max(0.0).powi(8);
+        Vec3f::new(0.8, 0.6, 0.4) * diffuse + Vec3f::new(1.0, 1.0, 1.0) * specular
+    });
+}
@@ -0,0 +1,20 @@
+use std::process::Command;
+
+// The commit the binary is built from, for the images it writes to record,
+// when it is built from a git checkout. It is described again whenever the
+// sources change, staged or not, so that `-dirty` stays true.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    let described = Command::new("git")
+        .args(["describe", "--always", "--dirty", "--abbrev=12"])
+        .output();
+    if let Ok(output) = described {
+        let commit = String::from_utf8_lossy(&output.stdout);
+        if output.status.success() && !commit.trim().is_empty() {
+            println!("cargo:rustc-env=TINY_RAYTRACER_COMMIT={}", commit.trim());
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Feeds arbitrary bytes to the checkpoint reader, which should turn down
+//! whatever it can't make sense of with an error rather than panic or run
+//! out of memory on the size a header claims:
+//!
+//! ```sh
+//! cargo +nightly fuzz run checkpoint
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tiny_raytracer::checkpoint;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = checkpoint::read(data);
+});
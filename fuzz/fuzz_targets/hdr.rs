@@ -0,0 +1,17 @@
+//! Feeds arbitrary bytes to the Radiance `.hdr` decoder, which should turn down
+//! whatever it can't make sense of with an error rather than panic, hang or
+//! run out of memory on the size a header claims:
+//!
+//! ```sh
+//! cargo +nightly fuzz run hdr
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tiny_raytracer::input::{self, Format};
+use tiny_raytracer::output::Transfer;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = input::decode(data, Format::Hdr, Transfer::Srgb);
+});
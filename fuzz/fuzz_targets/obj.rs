@@ -0,0 +1,33 @@
+//! Feeds arbitrary bytes to the Wavefront OBJ loader, which should turn
+//! down whatever it can't make sense of with an error rather than panic or
+//! hang. Starting from the example model finds the interesting inputs
+//! sooner:
+//!
+//! ```sh
+//! cargo +nightly fuzz run obj fuzz/corpus/obj scenes
+//! ```
+
+#![no_main]
+
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+use tiny_raytracer::accel::AcceleratorKind;
+use tiny_raytracer::files;
+use tiny_raytracer::mesh::{self, Format};
+
+fuzz_target!(|data: &[u8]| {
+    // Files the model refers to are looked for next to the example scenes,
+    // and only there, so that it can't have the whole disk read
+    let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("../scenes");
+    let _ = files::within(&scenes, || {
+        mesh::parse(
+            data,
+            Format::Obj,
+            &scenes,
+            None,
+            Some(true),
+            AcceleratorKind::Bvh,
+        )
+    });
+});
@@ -0,0 +1,22 @@
+//! Feeds arbitrary text to the scene loader, which should turn down
+//! whatever it can't make sense of with an error rather than panic or hang.
+//! Starting from the example scenes finds the interesting inputs sooner:
+//!
+//! ```sh
+//! cargo +nightly fuzz run scene fuzz/corpus/scene scenes
+//! ```
+
+#![no_main]
+
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+use tiny_raytracer::scene::Scene;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        // Meshes and textures are looked for next to the example scenes
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("../scenes");
+        let _ = Scene::parse(source, &scenes);
+    }
+});
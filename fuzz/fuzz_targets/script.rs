@@ -0,0 +1,19 @@
+//! Feeds arbitrary text to the scene script interpreter, which should turn
+//! down whatever it can't make sense of with an error rather than panic,
+//! overflow the stack or loop forever. Starting from the example scripts
+//! finds the interesting inputs sooner:
+//!
+//! ```sh
+//! cargo +nightly fuzz run script fuzz/corpus/script scenes
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tiny_raytracer::script;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = script::run(source, 0.0);
+    }
+});
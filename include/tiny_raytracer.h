@@ -0,0 +1,66 @@
+/*
+ * The C interface of tiny-raytracer, for linking with libtiny_raytracer.so
+ * or libtiny_raytracer.a as built by `cargo build --release`. See src/ffi.rs
+ * for what each function does; tests/ffi.rs checks that the two agree.
+ *
+ * Functions returning int return 0 on success and -1 otherwise, with the
+ * reason left for trt_last_error.
+ */
+
+#ifndef TINY_RAYTRACER_H
+#define TINY_RAYTRACER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A material of the Phong model. */
+typedef struct TrtMaterial {
+    double diffuse_color[3];
+    /* The weights of the diffuse and specular light */
+    double albedo[2];
+    double specular_exponent;
+    /* Light the surface gives off by itself */
+    double emission[3];
+} TrtMaterial;
+
+/* A scene being put together, of spheres and point lights. */
+typedef struct TrtScene TrtScene;
+
+/* The reason the last function to fail on this thread failed. */
+const char *trt_last_error(void);
+
+/* The material of the tutorial named `ivory` or `red_rubber`. */
+int trt_material_preset(const char *name, TrtMaterial *material);
+
+/* A scene without objects or lights, seen from the origin down -z. */
+TrtScene *trt_scene_new(void);
+void trt_scene_free(TrtScene *scene);
+
+int trt_scene_add_sphere(TrtScene *scene, double x, double y, double z, double radius,
+                         const TrtMaterial *material);
+int trt_scene_add_light(TrtScene *scene, double x, double y, double z, double red,
+                        double green, double blue);
+/* The vectors are arrays of three, and fov is in degrees. */
+int trt_scene_set_camera(TrtScene *scene, const double *position, const double *target,
+                         const double *up, double fov);
+
+/* Renders into width * height * 4 bytes of sRGB RGBA with straight alpha. */
+int trt_render(const TrtScene *scene, size_t width, size_t height, size_t samples_per_pixel,
+               uint8_t *rgba);
+/* Renders the length bytes of a JSON scene file at source like trt_render. */
+int trt_render_source(const uint8_t *source, size_t length, size_t width, size_t height,
+                      size_t samples_per_pixel, uint8_t *rgba);
+
+/* Zeroed bytes for callers without an allocator, such as WebAssembly hosts. */
+uint8_t *trt_alloc(size_t length);
+void trt_free(uint8_t *bytes, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,176 @@
+use crate::vector::{Float, Vec3f};
+
+/// An axis-aligned bounding box.
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Self {
+        Self { min, max }
+    }
+
+    /// The box containing nothing, which is the identity for `union`.
+    pub fn empty() -> Self {
+        Self::new(
+            Vec3f::new(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            Vec3f::new(
+                Float::NEG_INFINITY,
+                Float::NEG_INFINITY,
+                Float::NEG_INFINITY,
+            ),
+        )
+    }
+
+    /// The smallest box containing all the points.
+    pub fn from_points<I: IntoIterator<Item = Vec3f>>(points: I) -> Self {
+        points
+            .into_iter()
+            .fold(Self::empty(), |bounds, p| bounds.union(&Self::new(p, p)))
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.min.min(&other.min), self.max.max(&other.max))
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self::new(self.min.max(&other.min), self.max.min(&other.max))
+    }
+
+    /// Grows the box by `amount` on every side, which keeps flat objects from
+    /// producing degenerate boxes.
+    pub fn padded(&self, amount: Float) -> Self {
+        let pad = Vec3f::new(amount, amount, amount);
+        Self::new(self.min - pad, self.max + pad)
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let d = self.max - self.min;
+        if d.0 < 0.0 || d.1 < 0.0 || d.2 < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.0 * d.1 + d.1 * d.2 + d.2 * d.0)
+    }
+
+    pub fn center(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Slab test, returning the distances at which the ray enters and leaves
+    /// the box. The entry distance is negative when the ray starts inside.
+    pub fn intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<(Float, Float)> {
+        let mut t_near = Float::NEG_INFINITY;
+        let mut t_far = Float::INFINITY;
+        for (o, d, min, max) in [
+            (orig.0, dir.0, self.min.0, self.max.0),
+            (orig.1, dir.1, self.min.1, self.max.1),
+            (orig.2, dir.2, self.min.2, self.max.2),
+        ] {
+            let inv_d = d.recip();
+            let mut t0 = (min - o) * inv_d;
+            let mut t1 = (max - o) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Written so that NaNs from rays lying in a slab plane are ignored
+            t_near = if t0 > t_near { t0 } else { t_near };
+            t_far = if t1 < t_far { t1 } else { t_far };
+            if t_far < t_near {
+                return None;
+            }
+        }
+
+        if t_far < 0.0 {
+            None
+        } else {
+            Some((t_near, t_far))
+        }
+    }
+
+    /// The outward normal of the face closest to `point`.
+    pub fn normal(&self, point: &Vec3f) -> Vec3f {
+        let c = self.center();
+        let half = (self.max - self.min) * 0.5;
+        let d = *point - c;
+        let (x, y, z) = (
+            (d.0 / half.0).abs(),
+            (d.1 / half.1).abs(),
+            (d.2 / half.2).abs(),
+        );
+        if x >= y && x >= z {
+            Vec3f::new(d.0.signum(), 0.0, 0.0)
+        } else if y >= z {
+            Vec3f::new(0.0, d.1.signum(), 0.0)
+        } else {
+            Vec3f::new(0.0, 0.0, d.2.signum())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3f::new(-1.0, -1.0, -1.0), Vec3f::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn enters_and_leaves() {
+        let (near, far) = unit_box()
+            .intersect(&Vec3f::new(0.0, 0.0, 5.0), &Vec3f::new(0.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!((near, far), (4.0, 6.0));
+    }
+
+    #[test]
+    fn starts_inside() {
+        let (near, far) = unit_box()
+            .intersect(&Vec3f::new(0.0, 0.0, 0.0), &Vec3f::new(1.0, 0.0, 0.0))
+            .unwrap();
+        assert!(near < 0.0);
+        assert_eq!(far, 1.0);
+    }
+
+    #[test]
+    fn misses() {
+        let bounds = unit_box();
+        let dir = Vec3f::new(0.0, 0.0, -1.0);
+        assert!(bounds.intersect(&Vec3f::new(2.0, 0.0, 5.0), &dir).is_none());
+        // Behind the origin
+        assert!(bounds
+            .intersect(&Vec3f::new(0.0, 0.0, -5.0), &dir)
+            .is_none());
+    }
+
+    #[test]
+    fn rays_along_a_face() {
+        // The zero components of the direction make NaNs in the face's slab
+        let along = Vec3f::new(0.0, 0.0, -1.0);
+        let (near, far) = unit_box()
+            .intersect(&Vec3f::new(1.0, 0.0, 5.0), &along)
+            .unwrap();
+        assert_eq!((near, far), (4.0, 6.0));
+        assert!(unit_box()
+            .intersect(&Vec3f::new(1.5, 0.0, 5.0), &along)
+            .is_none());
+    }
+
+    #[test]
+    fn union_and_intersection() {
+        let other = Aabb::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(2.0, 3.0, 4.0));
+        let union = unit_box().union(&other);
+        assert_eq!(<[Float; 3]>::from(union.min), [-1.0, -1.0, -1.0]);
+        assert_eq!(<[Float; 3]>::from(union.max), [2.0, 3.0, 4.0]);
+        let intersection = unit_box().intersection(&other);
+        assert_eq!(<[Float; 3]>::from(intersection.min), [0.0, 0.0, 0.0]);
+        assert_eq!(<[Float; 3]>::from(intersection.max), [1.0, 1.0, 1.0]);
+        assert_eq!(
+            Aabb::empty().union(&other).surface_area(),
+            other.surface_area()
+        );
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+    }
+}
@@ -0,0 +1,283 @@
+//! A bounding volume hierarchy over anything that has bounds, built with the
+//! surface area heuristic over binned centroids.
+
+use super::Accelerator;
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vector::{Float, Vec3f};
+
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        first: usize,
+        count: usize,
+    },
+    Inner {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Inner { bounds, .. } => bounds,
+        }
+    }
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Primitive indices, ordered so that every leaf references a range
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy over the primitives with the given bounds. The
+    /// indices into `bounds` are what traversal hands back.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(bounds.len() * 2),
+            indices: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            bvh.build_node(bounds, 0, bounds.len());
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], first: usize, count: usize) -> usize {
+        let indices = &mut self.indices[first..first + count];
+        let node_bounds = indices
+            .iter()
+            .fold(Aabb::empty(), |b, &i| b.union(&bounds[i]));
+
+        let node = self.nodes.len();
+        self.nodes.push(Node::Leaf {
+            bounds: node_bounds,
+            first,
+            count,
+        });
+        if count <= MAX_LEAF_SIZE {
+            return node;
+        }
+
+        let centroids = Aabb::from_points(indices.iter().map(|&i| bounds[i].center()));
+        let split = match best_split(bounds, indices, &centroids) {
+            Some(split) if split.cost < count as Float * node_bounds.surface_area() => split,
+            _ => return node,
+        };
+
+        // Partition the indices around the chosen bin boundary
+        let mut mid = 0;
+        for k in 0..indices.len() {
+            if bin_of(&bounds[indices[k]].center(), &centroids, split.axis) < split.bin {
+                indices.swap(k, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == count {
+            return node;
+        }
+
+        let left = self.build_node(bounds, first, mid);
+        let right = self.build_node(bounds, first + mid, count - mid);
+        self.nodes[node] = Node::Inner {
+            bounds: node_bounds,
+            left,
+            right,
+        };
+        node
+    }
+}
+
+impl Accelerator for Bvh {
+    fn traverse(&self, ray: &Ray, intersect: &mut dyn FnMut(usize) -> Option<Float>) {
+        let (orig, dir) = (&ray.origin, &ray.dir);
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut closest = ray.t_max;
+        let mut stack = vec![0];
+        let mut visits = 0;
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            visits += 1;
+            match node.bounds().intersect(orig, dir) {
+                Some((t_near, t_far)) if t_near <= closest && t_far >= ray.t_min => {}
+                _ => continue,
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for &index in &self.indices[*first..*first + *count] {
+                        if let Some(t) = intersect(index) {
+                            closest = closest.min(t);
+                        }
+                    }
+                }
+                Node::Inner { left, right, .. } => {
+                    // Push the farther child first so the nearer one is
+                    // visited first and tightens the closest distance
+                    let near = |child: usize| {
+                        self.nodes[child]
+                            .bounds()
+                            .intersect(orig, dir)
+                            .map_or(Float::INFINITY, |(t, _)| t)
+                    };
+                    if near(*left) < near(*right) {
+                        stack.push(*right);
+                        stack.push(*left);
+                    } else {
+                        stack.push(*left);
+                        stack.push(*right);
+                    }
+                }
+            }
+        }
+        stats::count(|stats| stats.node_visits += visits);
+    }
+
+    fn traverse_packet(
+        &self,
+        rays: &[Ray],
+        intersect: &mut dyn FnMut(usize, usize) -> Option<Float>,
+    ) {
+        assert!(rays.len() <= 64, "packets are limited to 64 rays");
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // Nodes are visited once for the whole packet, testing primitives
+        // only against the rays that reach them
+        let mut closest = [Float::INFINITY; 64];
+        for (closest, ray) in closest.iter_mut().zip(rays) {
+            *closest = ray.t_max;
+        }
+        let mut stack = vec![0];
+        let mut visits = 0;
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            visits += 1;
+            let mut active = 0u64;
+            for (index, ray) in rays.iter().enumerate() {
+                if let Some((t_near, t_far)) = node.bounds().intersect(&ray.origin, &ray.dir) {
+                    if t_near <= closest[index] && t_far >= ray.t_min {
+                        active |= 1 << index;
+                    }
+                }
+            }
+            if active == 0 {
+                continue;
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for &index in &self.indices[*first..*first + *count] {
+                        for ray in (0..rays.len()).filter(|ray| active & (1 << ray) != 0) {
+                            if let Some(t) = intersect(index, ray) {
+                                closest[ray] = closest[ray].min(t);
+                            }
+                        }
+                    }
+                }
+                Node::Inner { left, right, .. } => {
+                    // Order the children by the first active ray, as rays in
+                    // a coherent packet mostly agree on it
+                    let ray = &rays[active.trailing_zeros() as usize];
+                    let near = |child: usize| {
+                        self.nodes[child]
+                            .bounds()
+                            .intersect(&ray.origin, &ray.dir)
+                            .map_or(Float::INFINITY, |(t, _)| t)
+                    };
+                    if near(*left) < near(*right) {
+                        stack.push(*right);
+                        stack.push(*left);
+                    } else {
+                        stack.push(*left);
+                        stack.push(*right);
+                    }
+                }
+            }
+        }
+        stats::count(|stats| stats.node_visits += visits);
+    }
+
+    fn visit_nodes(&self, ray: &Ray, visit: &mut dyn FnMut(&Aabb, usize)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![(0, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let node = &self.nodes[node];
+            match node.bounds().intersect(&ray.origin, &ray.dir) {
+                Some((t_near, t_far)) if t_near <= ray.t_max && t_far >= ray.t_min => {}
+                _ => continue,
+            }
+            visit(node.bounds(), depth);
+            if let Node::Inner { left, right, .. } = node {
+                stack.push((*right, depth + 1));
+                stack.push((*left, depth + 1));
+            }
+        }
+    }
+}
+
+struct Split {
+    axis: usize,
+    bin: usize,
+    cost: Float,
+}
+
+fn bin_of(centroid: &Vec3f, centroids: &Aabb, split_axis: usize) -> usize {
+    let min = centroids.min[split_axis];
+    let extent = centroids.max[split_axis] - min;
+    if extent <= 0.0 {
+        return 0;
+    }
+    let bin = ((centroid[split_axis] - min) / extent * BINS as Float) as usize;
+    bin.min(BINS - 1)
+}
+
+/// Evaluates the surface area heuristic at every bin boundary of every axis.
+fn best_split(bounds: &[Aabb], indices: &[usize], centroids: &Aabb) -> Option<Split> {
+    let mut best: Option<Split> = None;
+    for split_axis in 0..3 {
+        let mut bin_bounds = [Aabb::empty(); BINS];
+        let mut bin_counts = [0; BINS];
+        for &i in indices {
+            let bin = bin_of(&bounds[i].center(), centroids, split_axis);
+            bin_bounds[bin] = bin_bounds[bin].union(&bounds[i]);
+            bin_counts[bin] += 1;
+        }
+
+        for bin in 1..BINS {
+            let (left, left_count) = (0..bin).fold((Aabb::empty(), 0), |(b, c), k| {
+                (b.union(&bin_bounds[k]), c + bin_counts[k])
+            });
+            let (right, right_count) = (bin..BINS).fold((Aabb::empty(), 0), |(b, c), k| {
+                (b.union(&bin_bounds[k]), c + bin_counts[k])
+            });
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = left.surface_area() * left_count as Float
+                + right.surface_area() * right_count as Float;
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(Split {
+                    axis: split_axis,
+                    bin,
+                    cost,
+                });
+            }
+        }
+    }
+    best
+}
@@ -0,0 +1,170 @@
+//! A uniform grid over anything that has bounds, walked cell by cell with a
+//! 3D digital differential analyzer. Cheap to build, and fast when the
+//! primitives are evenly spread and of similar size.
+
+use super::Accelerator;
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vector::{Float, Vec3f};
+
+/// Targeted number of cells per primitive
+const DENSITY: Float = 2.0;
+const MAX_RESOLUTION: usize = 128;
+
+pub struct Grid {
+    bounds: Aabb,
+    resolution: [usize; 3],
+    cell_size: Vec3f,
+    /// Where each cell's primitives start in `indices`, with one extra entry
+    /// marking the end of the last cell
+    cell_starts: Vec<usize>,
+    indices: Vec<usize>,
+}
+
+impl Grid {
+    /// Builds the grid over the primitives with the given bounds. The indices
+    /// into `bounds` are what traversal hands back.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let grid_bounds = bounds
+            .iter()
+            .fold(Aabb::empty(), |b, p| b.union(p))
+            .padded(1e-4);
+        let extent = grid_bounds.max - grid_bounds.min;
+
+        // Cube-ish cells, as many as the density asks for
+        let volume = (extent.0 * extent.1 * extent.2).max(Float::MIN_POSITIVE);
+        let cells_per_unit = (DENSITY * bounds.len() as Float / volume).cbrt();
+        let resolution = <[Float; 3]>::from(extent)
+            .map(|e| ((e * cells_per_unit).round() as usize).clamp(1, MAX_RESOLUTION));
+        let cell_size = Vec3f::new(
+            extent.0 / resolution[0] as Float,
+            extent.1 / resolution[1] as Float,
+            extent.2 / resolution[2] as Float,
+        );
+
+        let mut grid = Self {
+            bounds: grid_bounds,
+            resolution,
+            cell_size,
+            cell_starts: vec![],
+            indices: vec![],
+        };
+        if bounds.is_empty() {
+            return grid;
+        }
+
+        // Count the primitives overlapping each cell, then fill them in
+        let cell_count = resolution.iter().product();
+        let mut counts = vec![0; cell_count];
+        for b in bounds {
+            grid.for_each_cell(b, |cell| counts[cell] += 1);
+        }
+        let mut starts = Vec::with_capacity(cell_count + 1);
+        starts.push(0);
+        for count in &counts {
+            starts.push(starts.last().unwrap() + count);
+        }
+        let mut next = starts.clone();
+        let mut indices = vec![0; *starts.last().unwrap()];
+        for (i, b) in bounds.iter().enumerate() {
+            grid.for_each_cell(b, |cell| {
+                indices[next[cell]] = i;
+                next[cell] += 1;
+            });
+        }
+        grid.cell_starts = starts;
+        grid.indices = indices;
+        grid
+    }
+
+    fn cell_coordinate(&self, p: &Vec3f, a: usize) -> usize {
+        let offset = (p[a] - self.bounds.min[a]) / self.cell_size[a];
+        (offset.max(0.0) as usize).min(self.resolution[a] - 1)
+    }
+
+    fn cell_index(&self, cell: [usize; 3]) -> usize {
+        cell[0] + self.resolution[0] * (cell[1] + self.resolution[1] * cell[2])
+    }
+
+    fn for_each_cell<F: FnMut(usize)>(&self, bounds: &Aabb, mut f: F) {
+        let min = [0, 1, 2].map(|a| self.cell_coordinate(&bounds.min, a));
+        let max = [0, 1, 2].map(|a| self.cell_coordinate(&bounds.max, a));
+        for z in min[2]..=max[2] {
+            for y in min[1]..=max[1] {
+                for x in min[0]..=max[0] {
+                    f(self.cell_index([x, y, z]));
+                }
+            }
+        }
+    }
+}
+
+impl Accelerator for Grid {
+    fn traverse(&self, ray: &Ray, intersect: &mut dyn FnMut(usize) -> Option<Float>) {
+        let (orig, dir) = (&ray.origin, &ray.dir);
+        if self.indices.is_empty() {
+            return;
+        }
+        let (t_near, t_far) = match self.bounds.intersect(orig, dir) {
+            Some(t) => t,
+            None => return,
+        };
+        let t_enter = t_near.max(ray.t_min);
+        if t_enter > t_far.min(ray.t_max) {
+            return;
+        }
+
+        // Per axis: the cell the ray is in, which way it steps, the distance
+        // at which it crosses into the next cell and the distance between
+        // crossings
+        let start = *orig + (*dir * t_enter);
+        let mut cell = [0; 3];
+        let mut step = [0isize; 3];
+        let mut t_next = [Float::INFINITY; 3];
+        let mut t_delta = [Float::INFINITY; 3];
+        for a in 0..3 {
+            cell[a] = self.cell_coordinate(&start, a);
+            let d = dir[a];
+            let size = self.cell_size[a];
+            let cell_min = self.bounds.min[a] + cell[a] as Float * size;
+            if d > 0.0 {
+                step[a] = 1;
+                t_next[a] = t_enter + (cell_min + size - start[a]) / d;
+                t_delta[a] = size / d;
+            } else if d < 0.0 {
+                step[a] = -1;
+                t_next[a] = t_enter + (cell_min - start[a]) / d;
+                t_delta[a] = -size / d;
+            }
+        }
+
+        let mut closest = ray.t_max;
+        let mut visits = 0;
+        loop {
+            visits += 1;
+            let index = self.cell_index(cell);
+            for &primitive in &self.indices[self.cell_starts[index]..self.cell_starts[index + 1]] {
+                if let Some(t) = intersect(primitive) {
+                    closest = closest.min(t);
+                }
+            }
+
+            // Step into the neighbour across the nearest cell boundary, unless
+            // the hit found so far, or the end of the ray, lies before it
+            let a = (0..3)
+                .min_by(|&a, &b| t_next[a].total_cmp(&t_next[b]))
+                .unwrap();
+            if closest <= t_next[a] {
+                break;
+            }
+            let next = cell[a] as isize + step[a];
+            if next < 0 || next >= self.resolution[a] as isize {
+                break;
+            }
+            cell[a] = next as usize;
+            t_next[a] += t_delta[a];
+        }
+        stats::count(|stats| stats.node_visits += visits);
+    }
+}
@@ -0,0 +1,265 @@
+//! A kd-tree over anything that has bounds, splitting at primitive bound
+//! edges chosen with the surface area heuristic.
+
+use super::Accelerator;
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vector::{Float, Vec3f};
+
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECT_COST: Float = 80.0;
+/// Discount for splits that leave one side empty, which rays skip for free
+const EMPTY_BONUS: Float = 0.5;
+const MAX_LEAF_SIZE: usize = 2;
+const MAX_BAD_REFINES: usize = 3;
+
+enum Node {
+    Leaf {
+        first: usize,
+        count: usize,
+    },
+    Inner {
+        axis: usize,
+        split: Float,
+        below: usize,
+        above: usize,
+    },
+}
+
+pub struct KdTree {
+    nodes: Vec<Node>,
+    /// Primitive indices, ordered so that every leaf references a range.
+    /// Primitives straddling a split plane appear in several leaves.
+    indices: Vec<usize>,
+    bounds: Aabb,
+}
+
+fn with_axis(v: &Vec3f, axis: usize, value: Float) -> Vec3f {
+    let mut v = *v;
+    v[axis] = value;
+    v
+}
+
+struct Split {
+    axis: usize,
+    position: Float,
+    cost: Float,
+}
+
+impl KdTree {
+    /// Builds the tree over the primitives with the given bounds. The indices
+    /// into `bounds` are what traversal hands back.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut tree = Self {
+            nodes: vec![],
+            indices: vec![],
+            bounds: bounds.iter().fold(Aabb::empty(), |b, p| b.union(p)),
+        };
+        if !bounds.is_empty() {
+            let max_depth = (8.0 + 1.3 * (bounds.len() as Float).log2()).round() as usize;
+            let primitives = (0..bounds.len()).collect();
+            tree.build_node(bounds, tree.bounds, primitives, max_depth, 0);
+        }
+        tree
+    }
+
+    fn build_node(
+        &mut self,
+        bounds: &[Aabb],
+        node_bounds: Aabb,
+        primitives: Vec<usize>,
+        depth: usize,
+        mut bad_refines: usize,
+    ) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(Node::Leaf {
+            first: self.indices.len(),
+            count: primitives.len(),
+        });
+        if primitives.len() <= MAX_LEAF_SIZE || depth == 0 {
+            self.indices.extend(&primitives);
+            return node;
+        }
+
+        let leaf_cost = INTERSECT_COST * primitives.len() as Float;
+        let split = match best_split(bounds, &primitives, &node_bounds) {
+            Some(split) => split,
+            None => {
+                self.indices.extend(&primitives);
+                return node;
+            }
+        };
+        if split.cost > leaf_cost {
+            bad_refines += 1;
+        }
+        if (split.cost > 4.0 * leaf_cost && primitives.len() < 16) || bad_refines == MAX_BAD_REFINES
+        {
+            self.indices.extend(&primitives);
+            return node;
+        }
+
+        // Primitives lying flat in the split plane go below it
+        let (mut below, mut above) = (vec![], vec![]);
+        for &i in &primitives {
+            let min = bounds[i].min[split.axis];
+            let max = bounds[i].max[split.axis];
+            if min < split.position || max <= split.position {
+                below.push(i);
+            }
+            if max > split.position {
+                above.push(i);
+            }
+        }
+        drop(primitives);
+
+        let below_bounds = Aabb::new(
+            node_bounds.min,
+            with_axis(&node_bounds.max, split.axis, split.position),
+        );
+        let above_bounds = Aabb::new(
+            with_axis(&node_bounds.min, split.axis, split.position),
+            node_bounds.max,
+        );
+        let below = self.build_node(bounds, below_bounds, below, depth - 1, bad_refines);
+        let above = self.build_node(bounds, above_bounds, above, depth - 1, bad_refines);
+        self.nodes[node] = Node::Inner {
+            axis: split.axis,
+            split: split.position,
+            below,
+            above,
+        };
+        node
+    }
+}
+
+/// Sweeps the bound edges of the primitives along every axis, evaluating the
+/// surface area heuristic at each edge inside the node.
+fn best_split(bounds: &[Aabb], primitives: &[usize], node_bounds: &Aabb) -> Option<Split> {
+    let area = node_bounds.surface_area();
+    if area <= 0.0 {
+        return None;
+    }
+    let extent = node_bounds.max - node_bounds.min;
+
+    let mut best: Option<Split> = None;
+    let mut edges = Vec::with_capacity(primitives.len() * 2);
+    for split_axis in 0..3 {
+        let (min, max) = (node_bounds.min[split_axis], node_bounds.max[split_axis]);
+
+        // Edges sort by position, with starts before ends at the same place
+        edges.clear();
+        for &i in primitives {
+            edges.push((bounds[i].min[split_axis], false));
+            edges.push((bounds[i].max[split_axis], true));
+        }
+        edges.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let (mut below, mut above) = (0, primitives.len());
+        for &(position, is_end) in &edges {
+            if is_end {
+                above -= 1;
+            }
+            if position > min && position < max {
+                let below_extent = with_axis(&extent, split_axis, position - min);
+                let above_extent = with_axis(&extent, split_axis, max - position);
+                let side_area = |e: Vec3f| 2.0 * (e.0 * e.1 + e.1 * e.2 + e.2 * e.0) / area;
+                let bonus = if below == 0 || above == 0 {
+                    EMPTY_BONUS
+                } else {
+                    0.0
+                };
+                let cost = TRAVERSAL_COST
+                    + INTERSECT_COST
+                        * (1.0 - bonus)
+                        * (side_area(below_extent) * below as Float
+                            + side_area(above_extent) * above as Float);
+                if best.as_ref().is_none_or(|best| cost < best.cost) {
+                    best = Some(Split {
+                        axis: split_axis,
+                        position,
+                        cost,
+                    });
+                }
+            }
+            if !is_end {
+                below += 1;
+            }
+        }
+    }
+    best
+}
+
+impl Accelerator for KdTree {
+    fn traverse(&self, ray: &Ray, intersect: &mut dyn FnMut(usize) -> Option<Float>) {
+        let (orig, dir) = (&ray.origin, &ray.dir);
+        if self.nodes.is_empty() {
+            return;
+        }
+        let (t_min, t_max) = match self.bounds.intersect(orig, dir) {
+            Some((t_near, t_far)) => (t_near.max(ray.t_min), t_far.min(ray.t_max)),
+            None => return,
+        };
+        if t_min > t_max {
+            return;
+        }
+
+        // Nodes still to visit, along with the stretch of the ray inside them.
+        // They are popped front to back, so the first leaf with a hit before
+        // its far end holds the closest one.
+        let mut closest = ray.t_max;
+        let mut stack = vec![(0, t_min, t_max)];
+        let mut visits = 0;
+        while let Some((mut node, t_min, mut t_max)) = stack.pop() {
+            if closest < t_min {
+                break;
+            }
+            loop {
+                visits += 1;
+                match self.nodes[node] {
+                    Node::Inner {
+                        axis: split_axis,
+                        split,
+                        below,
+                        above,
+                    } => {
+                        let o = orig[split_axis];
+                        let d = dir[split_axis];
+                        let t_plane = if d != 0.0 {
+                            (split - o) / d
+                        } else {
+                            Float::INFINITY
+                        };
+                        let (first, second) = if o < split || (o == split && d <= 0.0) {
+                            (below, above)
+                        } else {
+                            (above, below)
+                        };
+
+                        if t_plane > t_max || t_plane <= 0.0 {
+                            node = first;
+                        } else if t_plane < t_min {
+                            node = second;
+                        } else {
+                            stack.push((second, t_plane, t_max));
+                            node = first;
+                            t_max = t_plane;
+                        }
+                    }
+                    Node::Leaf { first, count } => {
+                        for &index in &self.indices[first..first + count] {
+                            if let Some(t) = intersect(index) {
+                                closest = closest.min(t);
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+            if closest <= t_max {
+                break;
+            }
+        }
+        stats::count(|stats| stats.node_visits += visits);
+    }
+}
@@ -0,0 +1,153 @@
+//! Acceleration structures that narrow down which primitives a ray has to be
+//! tested against.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::vector::Float;
+
+mod bvh;
+mod grid;
+mod kdtree;
+
+pub use self::bvh::Bvh;
+pub use self::grid::Grid;
+pub use self::kdtree::KdTree;
+
+pub trait Accelerator: Send + Sync {
+    /// Visits the primitives the ray may hit within its range. `intersect`
+    /// tests a primitive and returns the distance of its hit, which lets the
+    /// traversal skip everything farther away.
+    fn traverse(&self, ray: &Ray, intersect: &mut dyn FnMut(usize) -> Option<Float>);
+
+    /// Visits the primitives any of the rays may hit. `intersect` tests a
+    /// primitive against the ray with the given index. Structures that can't
+    /// do better trace the rays one by one.
+    fn traverse_packet(
+        &self,
+        rays: &[Ray],
+        intersect: &mut dyn FnMut(usize, usize) -> Option<Float>,
+    ) {
+        for (index, ray) in rays.iter().enumerate() {
+            self.traverse(ray, &mut |primitive| intersect(primitive, index));
+        }
+    }
+
+    /// Visits the bounds of the nodes the ray passes through within its
+    /// range, along with how deep in the structure each is, for looking at
+    /// how well it was built. Structures without bounded nodes visit none.
+    fn visit_nodes(&self, _ray: &Ray, _visit: &mut dyn FnMut(&Aabb, usize)) {}
+}
+
+impl dyn Accelerator + '_ {
+    /// Finds the closest hit, calling `intersect` for each primitive the ray
+    /// may hit. The ray it is called with ends at the closest hit so far, so
+    /// primitives only have to report hits within the ray's range, and those
+    /// are always closer.
+    pub fn intersect<T, F>(&self, ray: &Ray, mut intersect: F) -> Option<(Float, T)>
+    where
+        F: FnMut(usize, &Ray) -> Option<(Float, T)>,
+    {
+        let mut closest: Option<(Float, T)> = None;
+        let mut bounded = *ray;
+        self.traverse(ray, &mut |index| {
+            let (t, hit) = intersect(index, &bounded)?;
+            bounded.t_max = t;
+            closest = Some((t, hit));
+            Some(t)
+        });
+        closest
+    }
+
+    /// Finds the closest hit of every ray in the packet, calling `intersect`
+    /// with the primitive and the ray, ending at its closest hit so far, for
+    /// each pair that may hit.
+    pub fn intersect_packet<T, F>(&self, rays: &[Ray], mut intersect: F) -> Vec<Option<(Float, T)>>
+    where
+        F: FnMut(usize, &Ray) -> Option<(Float, T)>,
+    {
+        let mut closest: Vec<Option<(Float, T)>> = rays.iter().map(|_| None).collect();
+        let mut bounded = rays.to_vec();
+        self.traverse_packet(rays, &mut |index, ray| {
+            let (t, hit) = intersect(index, &bounded[ray])?;
+            bounded[ray].t_max = t;
+            closest[ray] = Some((t, hit));
+            Some(t)
+        });
+        closest
+    }
+}
+
+/// No acceleration at all: every primitive is tested against every ray.
+pub struct Linear {
+    count: usize,
+}
+
+impl Linear {
+    pub fn build(bounds: &[Aabb]) -> Self {
+        Self {
+            count: bounds.len(),
+        }
+    }
+}
+
+impl Accelerator for Linear {
+    fn traverse(&self, _: &Ray, intersect: &mut dyn FnMut(usize) -> Option<Float>) {
+        for index in 0..self.count {
+            intersect(index);
+        }
+    }
+}
+
+/// The available acceleration structures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AcceleratorKind {
+    #[default]
+    Bvh,
+    KdTree,
+    Grid,
+    None,
+}
+
+impl AcceleratorKind {
+    /// Builds the structure over the primitives with the given bounds. The
+    /// indices into `bounds` are what traversal hands back.
+    pub fn build(self, bounds: &[Aabb]) -> Box<dyn Accelerator> {
+        match self {
+            AcceleratorKind::Bvh => Box::new(Bvh::build(bounds)),
+            AcceleratorKind::KdTree => Box::new(KdTree::build(bounds)),
+            AcceleratorKind::Grid => Box::new(Grid::build(bounds)),
+            AcceleratorKind::None => Box::new(Linear::build(bounds)),
+        }
+    }
+}
+
+impl FromStr for AcceleratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bvh" => Ok(AcceleratorKind::Bvh),
+            "kdtree" => Ok(AcceleratorKind::KdTree),
+            "grid" => Ok(AcceleratorKind::Grid),
+            "none" => Ok(AcceleratorKind::None),
+            _ => Err(format!(
+                "unknown accelerator `{}`, expected bvh, kdtree, grid or none",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AcceleratorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AcceleratorKind::Bvh => "bvh",
+            AcceleratorKind::KdTree => "kdtree",
+            AcceleratorKind::Grid => "grid",
+            AcceleratorKind::None => "none",
+        })
+    }
+}
@@ -0,0 +1,142 @@
+//! Arbitrary output variables: passes the renderer writes alongside the
+//! image, for denoising and compositing. Pixels where rays hit nothing are
+//! zero in every pass but the cost.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::object::{surface_material, HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::stats;
+use crate::vector::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// Distance from the camera to the hit, in every channel
+    Depth,
+    /// Surface normal at the hit, in world space
+    Normal,
+    /// Diffuse color of the material
+    Albedo,
+    /// One more than the index of the object hit, in every channel. Taken
+    /// from the first pass rather than averaged.
+    Id,
+    /// Nodes of the acceleration structures visited and triangles tested
+    /// finding what the primary ray hit, in every channel
+    Cost,
+    /// The light of the light group of the name alone, as the integrator
+    /// finds it for the same samples as the image, so that the passes of
+    /// all the groups and the light outside any add up to the image
+    Lights(&'static str),
+}
+
+pub const ALL: [Aov; 5] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::Id, Aov::Cost];
+
+impl Aov {
+    /// The pass of the light group of the name.
+    pub fn lights(group: &str) -> Self {
+        Aov::Lights(intern(group))
+    }
+
+    /// Whether the passes are averaged like the image. Object ids are not,
+    /// as the average of two ids means nothing.
+    pub fn is_averaged(self) -> bool {
+        self != Aov::Id
+    }
+
+    /// The value of the pass for a primary ray, given what it hit, for the
+    /// passes that don't go by the light.
+    pub fn value(self, ray: &Ray, hit: Option<HitRecord>, scene: &Scene) -> Vec3f {
+        if self == Aov::Cost {
+            // Traced again on its own, as the rays of the image are traced
+            // in packets that share the work
+            let (_, work) = stats::measure(|| scene.hit(ray));
+            let cost = (work.node_visits + work.triangle_tests) as Float;
+            return Vec3f::new(cost, cost, cost);
+        }
+        let orig = &ray.origin;
+        let hit = match hit {
+            Some(hit) => hit,
+            None => return Vec3f::new(0.0, 0.0, 0.0),
+        };
+        let (n, point) = (hit.normal, hit.point);
+        match self {
+            Aov::Depth => {
+                let depth = point.distance(orig);
+                Vec3f::new(depth, depth, depth)
+            }
+            Aov::Normal => n,
+            Aov::Albedo => surface_material(&hit, 0.0).diffuse_color,
+            Aov::Id => {
+                let id = hit.handle.map_or(0.0, |h| h.index() as Float + 1.0);
+                Vec3f::new(id, id, id)
+            }
+            Aov::Cost | Aov::Lights(_) => unreachable!(),
+        }
+    }
+}
+
+/// The name of a light group, kept for as long as the program runs so that
+/// passes can name their group and still be copied around freely. Each name
+/// is only kept once, however often it is parsed.
+fn intern(group: &str) -> &'static str {
+    static NAMES: Mutex<Vec<&'static str>> = Mutex::new(vec![]);
+    let mut names = NAMES.lock().unwrap();
+    match names.iter().find(|&&name| name == group) {
+        Some(name) => name,
+        None => {
+            let name = Box::leak(group.to_string().into_boxed_str());
+            names.push(name);
+            name
+        }
+    }
+}
+
+/// Parses a comma-separated list of passes, such as `depth,normal`.
+pub fn parse_list(s: &str) -> Result<Vec<Aov>, String> {
+    let mut aovs = vec![];
+    for name in s.split(',') {
+        let aov = name.trim().parse()?;
+        if !aovs.contains(&aov) {
+            aovs.push(aov);
+        }
+    }
+    Ok(aovs)
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(Aov::Depth),
+            "normal" => Ok(Aov::Normal),
+            "albedo" => Ok(Aov::Albedo),
+            "id" => Ok(Aov::Id),
+            "cost" => Ok(Aov::Cost),
+            _ => match s.strip_prefix("lights.") {
+                Some(group) if !group.is_empty() => Ok(Aov::lights(group)),
+                _ => Err(format!(
+                    "unknown output variable `{}`, expected depth, normal, albedo, id, cost or \
+                     lights.GROUP",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Id => "id",
+            Aov::Cost => "cost",
+            Aov::Lights(group) => return write!(f, "lights.{}", group),
+        })
+    }
+}
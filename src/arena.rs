@@ -0,0 +1,153 @@
+//! Storage that hands out typed handles, small indices into one array, for
+//! the objects of a scene. Hits, emitters, the sources of photons and the
+//! primitives of the acceleration structure refer to objects by them, and a
+//! handle can't be mistaken for an index into anything else, such as the
+//! primitives themselves, which may be packets of several objects. Objects
+//! keep the order the scene was built in, so that a handle is also the id
+//! of its object.
+//!
+//! Only the objects are stored this way. Each still holds its own geometry,
+//! material and textures, and a scene is sent or resumed from its scene
+//! file and the files it reads rather than serialized.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ops::{Index, Range};
+
+/// Refers to an item of an [`Arena`] of `T`s by its place in it.
+pub struct Handle<T> {
+    index: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    /// The handle of the item at `index`, which only the arena the index
+    /// comes from may be indexed with.
+    pub(crate) fn new(index: usize) -> Self {
+        Self {
+            index: u32::try_from(index).expect("more items than handles"),
+            marker: PhantomData,
+        }
+    }
+
+    /// The place of the item in its arena, as it is stored or sent.
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// Derived, these would ask the same of `T`
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+/// Items stored one after another, each referred to by its [`Handle`].
+/// Items are never removed, so handles stay valid as long as the arena.
+pub struct Arena<T> {
+    items: Vec<T>,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self { items: vec![] }
+    }
+
+    /// Stores the item, returning its handle.
+    pub fn push(&mut self, item: T) -> Handle<T> {
+        self.items.push(item);
+        Handle::new(self.items.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The items in the order they were stored.
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    /// The handles of the items stored `range` of the way through the arena.
+    pub fn handles(&self, range: Range<usize>) -> impl Iterator<Item = Handle<T>> {
+        (range.start..range.end.min(self.items.len())).map(Handle::new)
+    }
+
+    /// The items with their handles, in the order they were stored.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (Handle::new(i), item))
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<Handle<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, handle: Handle<T>) -> &T {
+        &self.items[handle.index()]
+    }
+}
+
+impl<T> FromIterator<T> for Arena<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        Self {
+            items: items.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_out_handles_in_order() {
+        let mut arena = Arena::new();
+        let (a, b) = (arena.push("a"), arena.push("b"));
+        assert_eq!((a.index(), b.index()), (0, 1));
+        assert_eq!((arena[a], arena[b]), ("a", "b"));
+        assert_ne!(a, b);
+        let listed: Vec<_> = arena.iter().map(|(handle, &item)| (handle, item)).collect();
+        assert_eq!(listed, [(a, "a"), (b, "b")]);
+        // Handles past the end aren't made up
+        assert_eq!(arena.handles(1..5).collect::<Vec<_>>(), [b]);
+    }
+}
@@ -0,0 +1,250 @@
+//! What rays that leave the scene see.
+
+use crate::material::Material;
+use crate::object::{Object, Quad};
+use crate::ray::Ray;
+use crate::render::Image;
+use crate::sky::Sky;
+use crate::vector::{consts::PI, Float, Vec3f};
+
+#[allow(clippy::large_enum_variant)] // There is only one per scene
+pub enum Background {
+    /// The same color in every direction
+    Color(Vec3f),
+    /// A blend from the `bottom` color straight down to the `top` color
+    /// straight up
+    Gradient { top: Vec3f, bottom: Vec3f },
+    /// An image of the surroundings all around
+    Environment(EnvironmentMap),
+    /// A daytime sky, without its sun
+    Sky(Sky),
+}
+
+impl Default for Background {
+    /// The light blue of the tutorial.
+    fn default() -> Self {
+        Background::Color(Vec3f::new(0.2, 0.7, 0.8))
+    }
+}
+
+impl Background {
+    /// The color seen along the unit direction `dir`.
+    pub fn color(&self, dir: &Vec3f) -> Vec3f {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { top, bottom } => {
+                let t = 0.5 * (dir.1 + 1.0);
+                bottom.lerp(top, t)
+            }
+            Background::Environment(map) => map.lookup(dir),
+            Background::Sky(sky) => sky.color(dir),
+        }
+    }
+
+    /// A direction to sample the background's light from, for the point
+    /// `uv` of the unit square, and its density per solid angle. Only
+    /// environment maps and skies are sampled, as the light of plain colors
+    /// and gradients comes from everywhere much alike.
+    pub fn sample(&self, uv: (Float, Float)) -> Option<(Vec3f, Float)> {
+        match self {
+            Background::Color(_) | Background::Gradient { .. } => None,
+            Background::Environment(map) => map.sample(uv),
+            Background::Sky(sky) => sky.sample(uv),
+        }
+    }
+
+    /// The density `sample` picks the unit direction `dir` with, if it
+    /// samples the background at all.
+    pub fn pdf(&self, dir: &Vec3f) -> Option<Float> {
+        match self {
+            Background::Color(_) | Background::Gradient { .. } => None,
+            Background::Environment(map) => map.pdf(dir),
+            Background::Sky(sky) => sky.pdf(dir),
+        }
+    }
+}
+
+/// A window or doorway the background lights the scene through. Inside a
+/// room, most directions see walls, which sampling the whole background
+/// wastes its rays on, so with portals the background is sampled through
+/// them instead. Portals aren't seen, and don't block anything.
+pub struct Portal(Quad);
+
+impl Portal {
+    /// The parallelogram spanned by the edges `u` and `v` from `corner`.
+    pub fn new(corner: Vec3f, u: Vec3f, v: Vec3f) -> Self {
+        Self(Quad::new(corner, u, v, Material::default()))
+    }
+}
+
+/// A direction from `point` through one of the portals, picked by the
+/// point `uv` of the unit square, each portal as likely and each point of
+/// it as likely, and its density per solid angle.
+pub fn sample_portals(
+    portals: &[Portal],
+    point: &Vec3f,
+    (u, v): (Float, Float),
+) -> Option<(Vec3f, Float)> {
+    let count = portals.len();
+    let scaled = u * count as Float;
+    let i = (scaled as usize).min(count.checked_sub(1)?);
+    let (target, _) = portals[i].0.sample_surface((scaled - i as Float, v))?;
+    let dir = (target - *point).normalize();
+    let pdf = portals_pdf(portals, point, &dir);
+    if pdf > 0.0 {
+        Some((dir, pdf))
+    } else {
+        None
+    }
+}
+
+/// The density `sample_portals` picks the unit direction `dir` from `point`
+/// with, going by every portal it passes through, or zero if it passes
+/// through none.
+pub fn portals_pdf(portals: &[Portal], point: &Vec3f, dir: &Vec3f) -> Float {
+    let ray = Ray::new(*point, *dir);
+    let sum: Float = portals
+        .iter()
+        .filter_map(|portal| {
+            let (t, n) = portal.0.ray_intersect(&ray)?;
+            let cos = n.dot(dir).abs();
+            Some(t * t / (cos.max(1e-6) * portal.0.area()?))
+        })
+        .sum();
+    sum / portals.len().max(1) as Float
+}
+
+/// Running sums of a row of weights, from 0 up to their total, to pick
+/// among them in proportion to their weight.
+fn cumulative(weights: impl Iterator<Item = Float>) -> Vec<Float> {
+    let mut sums = vec![0.0];
+    for weight in weights {
+        sums.push(sums.last().unwrap() + weight);
+    }
+    sums
+}
+
+/// Picks the index whose share of the running sums `u` in `0..1` falls
+/// in, and how far along it `u` falls.
+fn pick(sums: &[Float], u: Float) -> (usize, Float) {
+    let target = u * sums.last().unwrap();
+    let i = sums
+        .partition_point(|&sum| sum <= target)
+        .clamp(1, sums.len() - 1)
+        - 1;
+    let width = sums[i + 1] - sums[i];
+    let t = if width > 0.0 {
+        (target - sums[i]) / width
+    } else {
+        0.5
+    };
+    (i, t.clamp(0.0, 1.0))
+}
+
+/// An equirectangular image of the surroundings: longitude across, from
+/// straight behind the camera round to the left, and latitude down, from
+/// straight up. The middle of the image is straight ahead, down -Z.
+///
+/// Its pixels are sampled as lights, the brighter ones more often, so that
+/// the map lights a scene without all the light having to be found by
+/// chance.
+pub struct EnvironmentMap {
+    image: Image,
+    /// Scales the colors of the image
+    intensity: Float,
+    /// Running sums of the weight of each row, and of each pixel in each
+    /// row, for picking pixels in proportion to how much light they give
+    rows: Vec<Float>,
+    columns: Vec<Vec<Float>>,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Image, intensity: Float) -> Self {
+        // Rows near the poles are squeezed into less of the sphere
+        let (width, height) = (image.width, image.height);
+        let columns: Vec<_> = (0..height)
+            .map(|y| {
+                let sin = (PI * (y as Float + 0.5) / height as Float).sin();
+                cumulative(
+                    (0..width).map(|x| image.pixels[y * width + x].luminance().max(0.0) * sin),
+                )
+            })
+            .collect();
+        let rows = cumulative(columns.iter().map(|sums| *sums.last().unwrap()));
+        Self {
+            image,
+            intensity,
+            rows,
+            columns,
+        }
+    }
+
+    /// The unit direction a point of the image, in `0..1` both ways, looks
+    /// in, and the sine of its angle from straight up.
+    pub(crate) fn direction(u: Float, v: Float) -> (Vec3f, Float) {
+        let (phi, theta) = ((u - 0.5) * 2.0 * PI, v * PI);
+        let sin = theta.sin();
+        (
+            Vec3f::new(sin * phi.sin(), theta.cos(), -sin * phi.cos()),
+            sin,
+        )
+    }
+
+    pub(crate) fn sample(&self, (u, v): (Float, Float)) -> Option<(Vec3f, Float)> {
+        if *self.rows.last().unwrap() <= 0.0 {
+            return None;
+        }
+        let (y, ty) = pick(&self.rows, v);
+        let (x, tx) = pick(&self.columns[y], u);
+        let (width, height) = (self.image.width as Float, self.image.height as Float);
+        let (dir, sin) = Self::direction((x as Float + tx) / width, (y as Float + ty) / height);
+        let pdf = self.pixel_pdf(x, y) / (2.0 * PI * PI * sin.max(1e-6));
+        Some((dir, pdf))
+    }
+
+    pub(crate) fn pdf(&self, dir: &Vec3f) -> Option<Float> {
+        let total = *self.rows.last().unwrap();
+        if total <= 0.0 {
+            return None;
+        }
+        let (u, v) = Self::uv(dir);
+        let x = ((u * self.image.width as Float) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as Float) as usize).min(self.image.height - 1);
+        let sin = (v * PI).sin().max(1e-6);
+        Some(self.pixel_pdf(x, y) / (2.0 * PI * PI * sin))
+    }
+
+    /// The density of picking the pixel at `x` and `y`, per area of the
+    /// image taken as the unit square.
+    fn pixel_pdf(&self, x: usize, y: usize) -> Float {
+        let row = &self.columns[y];
+        let weight = row[x + 1] - row[x];
+        weight * (self.image.width * self.image.height) as Float / self.rows.last().unwrap()
+    }
+
+    /// Where the unit direction `dir` falls on the image, in `0..1` both
+    /// ways.
+    fn uv(dir: &Vec3f) -> (Float, Float) {
+        let u = 0.5 + dir.0.atan2(-dir.2) / (2.0 * PI);
+        let v = dir.1.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    /// The color of the map along `dir`, interpolated between the nearest
+    /// pixels. The map wraps around from its right edge to its left.
+    pub fn lookup(&self, dir: &Vec3f) -> Vec3f {
+        let (u, v) = Self::uv(dir);
+        let (width, height) = (self.image.width, self.image.height);
+        let x = u * width as Float - 0.5;
+        let y = (v * height as Float - 0.5).clamp(0.0, (height - 1) as Float);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let column = |x: Float| (x as isize).rem_euclid(width as isize) as usize;
+        let (x0, x1) = (column(x0), column(x0 + 1.0));
+        let (y0, y1) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+        let pixel = |x: usize, y: usize| self.image.pixels[y * width + x];
+        let top = pixel(x0, y0).lerp(&pixel(x1, y0), tx);
+        let bottom = pixel(x0, y1).lerp(&pixel(x1, y1), tx);
+        Vec3f::from(top.lerp(&bottom, ty)) * self.intensity
+    }
+}
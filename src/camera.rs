@@ -0,0 +1,564 @@
+//! The camera the scene is seen through, and the paths it may move along.
+
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+use crate::ray::Ray;
+use crate::vector::{consts::PI, Float, Vec3f};
+
+/// How the camera maps directions onto the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Through a pinhole, or a lens, as the eye sees
+    Perspective,
+    /// Along parallel rays, from a plane through the camera `width` across,
+    /// so that things look as large however far away they are
+    Orthographic { width: Float },
+    /// Through a fisheye lens, onto a circle as large as the image is
+    /// high or wide, whichever is smaller, that spans the field of view
+    /// across, which may be all the way around. Pixels outside the circle
+    /// see nothing.
+    Fisheye(FisheyeMapping),
+    /// Every direction, by longitude across and latitude down, with the
+    /// line of sight in the middle: the layout of environment maps, which
+    /// is undistorted in images twice as wide as high
+    Equirectangular,
+}
+
+/// How far from the center of a fisheye image each angle away from the line
+/// of sight ends up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    /// In proportion to the angle, as dome masters are made
+    #[default]
+    Equidistant,
+    /// Keeping areas in proportion to the solid angles they see
+    Equisolid,
+}
+
+impl FromStr for FisheyeMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "equidistant" => Ok(FisheyeMapping::Equidistant),
+            "equisolid" => Ok(FisheyeMapping::Equisolid),
+            _ => Err(format!(
+                "unknown fisheye mapping `{}`, expected equidistant or equisolid",
+                s
+            )),
+        }
+    }
+}
+
+/// How the images of the two eyes of a stereo camera share the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// The left eye's on the left half, the right eye's on the right one
+    #[default]
+    SideBySide,
+    /// The left eye's on the top half, the right eye's on the bottom one
+    TopBottom,
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(StereoLayout::SideBySide),
+            "top-bottom" => Ok(StereoLayout::TopBottom),
+            _ => Err(format!(
+                "unknown stereo layout `{}`, expected side-by-side or top-bottom",
+                s
+            )),
+        }
+    }
+}
+
+/// The eyes of a stereo camera, either side of its position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    /// How far apart the eyes are
+    pub eye_distance: Float,
+    /// How far ahead the views of the eyes meet, where things appear at the
+    /// depth of the screen, or `None` for the distance of the target.
+    /// Panoramas look straight ahead each way instead.
+    pub convergence: Option<Float>,
+    pub layout: StereoLayout,
+}
+
+/// A camera at `position` looking towards `target`, rolled so that `up`
+/// points as far up the image as it can. It is a pinhole camera, sharp at
+/// any distance, unless it has an aperture: a thin lens then blurs what is
+/// nearer or further than the focus distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    /// The angle the image spans from its bottom edge to its top edge, in
+    /// radians; the width follows from the aspect ratio of the image. Under
+    /// 180 degrees for perspective, and up to 360 for fisheye lenses, whose
+    /// circle it spans.
+    pub fov: Float,
+    pub projection: Projection,
+    /// The diameter of the lens, 0 for a pinhole. Fisheye and panoramic
+    /// cameras are always pinholes.
+    pub aperture: Float,
+    /// How far ahead of the camera the plane in focus is, or `None` for
+    /// the plane of the target
+    pub focus_distance: Option<Float>,
+    /// How long the shutter stays open, from the time 0 on, which moving
+    /// objects are blurred over
+    pub shutter: Float,
+    /// Two eyes seeing the scene instead of one
+    pub stereo: Option<Stereo>,
+    position: Vec3f,
+    target: Vec3f,
+    up: Vec3f,
+    /// The unit vectors to the right and up the image plane, and the one
+    /// straight ahead
+    basis: (Vec3f, Vec3f, Vec3f),
+}
+
+/// The eye of the tutorial: a pinhole at the origin, looking down -Z with Y
+/// up, with a field of view of 90 degrees.
+impl Default for Camera {
+    fn default() -> Self {
+        Self::look_at(
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 0.0, -1.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        )
+    }
+}
+
+/// The unit vectors to the right, up and ahead of a camera at `position`
+/// looking at `target`. When `up` is along the line of sight, any direction
+/// across it is taken instead.
+fn basis(position: Vec3f, target: Vec3f, up: Vec3f) -> (Vec3f, Vec3f, Vec3f) {
+    let forward = (target - position).normalize();
+    let right = forward.cross(&up);
+    let right = if right.norm() > 1e-12 {
+        right.normalize()
+    } else {
+        forward.perpendiculars().0
+    };
+    (right, right.cross(&forward), forward)
+}
+
+/// `x` to four decimal places, without the zeros at the end.
+pub(crate) fn decimal(x: Float) -> String {
+    let s = format!("{:.4}", x);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+impl Camera {
+    /// A pinhole camera at `position` looking at `target`.
+    pub fn look_at(position: Vec3f, target: Vec3f, up: Vec3f) -> Self {
+        Self {
+            fov: PI / 2.0,
+            projection: Projection::Perspective,
+            aperture: 0.0,
+            focus_distance: None,
+            shutter: 0.0,
+            stereo: None,
+            position,
+            target,
+            up,
+            basis: basis(position, target, up),
+        }
+    }
+
+    /// Moves the camera to `position` and turns it to look at `target`,
+    /// keeping its lens.
+    pub fn aim(&mut self, position: Vec3f, target: Vec3f, up: Vec3f) {
+        self.position = position;
+        self.target = target;
+        self.up = up;
+        self.basis = basis(position, target, up);
+    }
+
+    pub fn position(&self) -> Vec3f {
+        self.position
+    }
+
+    pub fn target(&self) -> Vec3f {
+        self.target
+    }
+
+    pub fn up(&self) -> Vec3f {
+        self.up
+    }
+
+    /// Moves the camera and its target together, by `right`, `up` and
+    /// `forward` along the directions of the image, as if flying through
+    /// the scene.
+    pub fn fly(&mut self, right: Float, up: Float, forward: Float) {
+        let (r, u, f) = self.basis;
+        let offset = r * right + u * up + f * forward;
+        self.aim(self.position + offset, self.target + offset, self.up);
+    }
+
+    /// Circles the camera around its target, by the angles `azimuth` about
+    /// its up direction and `elevation` towards it, stopping short of
+    /// looking straight down or up along it.
+    pub fn orbit(&mut self, azimuth: Float, elevation: Float) {
+        let up = self.up.normalize();
+        let offset = self.position - self.target;
+        let height = offset.dot(&up);
+        let across = offset - up * height;
+        let start = if across.norm() > 1e-12 {
+            across.normalize()
+        } else {
+            up.perpendiculars().0
+        };
+        let steepest = PI / 2.0 - 0.01;
+        let elevation = (height.atan2(across.len()) + elevation).clamp(-steepest, steepest);
+        let around = start * azimuth.cos() + up.cross(&start) * azimuth.sin();
+        let direction = around * elevation.cos() + up * elevation.sin();
+        self.aim(self.target + direction * offset.len(), self.target, self.up);
+    }
+
+    /// Moves the camera along its line of sight to `factor` times as far
+    /// from its target.
+    pub fn dolly(&mut self, factor: Float) {
+        let position = self.target + (self.position - self.target) * factor;
+        self.aim(position, self.target, self.up);
+    }
+
+    /// The camera as the `camera` of a scene file, with its field of view
+    /// in degrees. Keyframes it may have followed are left out.
+    pub fn to_json(&self) -> String {
+        let vector = |v: Vec3f| format!("[{}, {}, {}]", decimal(v.0), decimal(v.1), decimal(v.2));
+        let mut fields = vec![
+            format!("\"position\": {}", vector(self.position)),
+            format!("\"target\": {}", vector(self.target)),
+            format!("\"up\": {}", vector(self.up)),
+        ];
+        match self.projection {
+            Projection::Perspective => {}
+            Projection::Orthographic { width } => {
+                fields.push("\"projection\": \"orthographic\"".to_string());
+                fields.push(format!("\"view_width\": {}", decimal(width)));
+            }
+            Projection::Fisheye(mapping) => {
+                fields.push("\"projection\": \"fisheye\"".to_string());
+                let mapping = match mapping {
+                    FisheyeMapping::Equidistant => "equidistant",
+                    FisheyeMapping::Equisolid => "equisolid",
+                };
+                fields.push(format!("\"mapping\": \"{}\"", mapping));
+            }
+            Projection::Equirectangular => {
+                fields.push("\"projection\": \"equirectangular\"".to_string());
+            }
+        }
+        fields.push(format!("\"fov\": {}", decimal(self.fov.to_degrees())));
+        if self.aperture > 0.0 {
+            fields.push(format!("\"aperture\": {}", decimal(self.aperture)));
+        }
+        if let Some(distance) = self.focus_distance {
+            fields.push(format!("\"focus_distance\": {}", decimal(distance)));
+        }
+        if self.shutter > 0.0 {
+            fields.push(format!("\"shutter\": {}", decimal(self.shutter)));
+        }
+        if let Some(stereo) = &self.stereo {
+            let mut eyes = vec![format!(
+                "\"eye_distance\": {}",
+                decimal(stereo.eye_distance)
+            )];
+            if let Some(convergence) = stereo.convergence {
+                eyes.push(format!("\"convergence\": {}", decimal(convergence)));
+            }
+            let layout = match stereo.layout {
+                StereoLayout::SideBySide => "side_by_side",
+                StereoLayout::TopBottom => "top_bottom",
+            };
+            eyes.push(format!("\"layout\": \"{}\"", layout));
+            fields.push(format!("\"stereo\": {{{}}}", eyes.join(", ")));
+        }
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    /// The ray from the camera through the point `(x, y)` of an image of
+    /// `width` by `height` pixels, counted from its top left corner, with a
+    /// unit direction, or `None` if the point sees nothing. Pixels are
+    /// square whatever the aspect ratio. With an aperture, the ray leaves
+    /// from the point `lens` of the unit square mapped onto the lens.
+    /// Stereo cameras see the image as the two images of their eyes.
+    pub fn ray(
+        &self,
+        (x, y): (Float, Float),
+        lens: (Float, Float),
+        width: usize,
+        height: usize,
+    ) -> Option<Ray> {
+        let (orig, dir) = match self.stereo {
+            None => self.pinhole_ray((x, y), width, height)?,
+            Some(stereo) => {
+                // The left eye's image comes first
+                let (x, y, width, height, side) = match stereo.layout {
+                    StereoLayout::SideBySide if x < (width / 2) as Float => {
+                        (x, y, width / 2, height, -1.0)
+                    }
+                    StereoLayout::SideBySide => {
+                        (x - (width / 2) as Float, y, width - width / 2, height, 1.0)
+                    }
+                    StereoLayout::TopBottom if y < (height / 2) as Float => {
+                        (x, y, width, height / 2, -1.0)
+                    }
+                    StereoLayout::TopBottom => (
+                        x,
+                        y - (height / 2) as Float,
+                        width,
+                        height - height / 2,
+                        1.0,
+                    ),
+                };
+                let (orig, dir) = self.pinhole_ray((x, y), width, height)?;
+                self.eye_ray(&stereo, side, orig, dir)
+            }
+        };
+        let pinhole = matches!(
+            self.projection,
+            Projection::Fisheye(_) | Projection::Equirectangular
+        );
+        if self.aperture <= 0.0 || pinhole {
+            return Some(Ray::new(orig, dir));
+        }
+
+        // Every ray through the pixel meets where the pinhole one crosses
+        // the plane in focus
+        let (right, up, forward) = self.basis;
+        let focus_distance = self
+            .focus_distance
+            .unwrap_or_else(|| self.target_distance());
+        let focus = orig + dir * (focus_distance / dir.dot(&forward));
+        let (r, phi) = (0.5 * self.aperture * lens.0.sqrt(), 2.0 * PI * lens.1);
+        let orig = orig + right * (r * phi.cos()) + up * (r * phi.sin());
+        Some(Ray::new(orig, (focus - orig).normalize()))
+    }
+
+    /// How far ahead the target is.
+    fn target_distance(&self) -> Float {
+        (self.target - self.position).dot(&self.basis.2)
+    }
+
+    /// The origin and direction of the ray through the point of the image
+    /// through a pinhole at the camera, as in `ray`.
+    fn pinhole_ray(
+        &self,
+        (x, y): (Float, Float),
+        width: usize,
+        height: usize,
+    ) -> Option<(Vec3f, Vec3f)> {
+        let (right, up, forward) = self.basis;
+        match self.projection {
+            Projection::Perspective => {
+                let scale = (self.fov / 2.0).tan();
+                let x = (2.0 * x / width as Float - 1.0) * scale * width as Float / height as Float;
+                let y = -(2.0 * y / height as Float - 1.0) * scale;
+                Some((self.position, (right * x + up * y + forward).normalize()))
+            }
+            Projection::Orthographic { width: view_width } => {
+                let scale = 0.5 * view_width / width as Float;
+                let x = (2.0 * x - width as Float) * scale;
+                let y = (height as Float - 2.0 * y) * scale;
+                Some((self.position + right * x + up * y, forward))
+            }
+            Projection::Fisheye(mapping) => {
+                let radius = 0.5 * width.min(height) as Float;
+                let x = (x - 0.5 * width as Float) / radius;
+                let y = (0.5 * height as Float - y) / radius;
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                // The angle away from the line of sight
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * self.fov / 2.0,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (self.fov / 4.0).sin()).asin(),
+                };
+                let across = if r > 0.0 {
+                    (right * x + up * y) * (theta.sin() / r)
+                } else {
+                    Vec3f::new(0.0, 0.0, 0.0)
+                };
+                Some((self.position, (forward * theta.cos() + across).normalize()))
+            }
+            Projection::Equirectangular => {
+                let phi = (x / width as Float - 0.5) * 2.0 * PI;
+                let theta = y / height as Float * PI;
+                let dir = right * (phi.sin() * theta.sin())
+                    + up * theta.cos()
+                    + forward * (phi.cos() * theta.sin());
+                Some((self.position, dir.normalize()))
+            }
+        }
+    }
+
+    /// The ray of the left eye, on the `side` -1, or of the right one, on
+    /// the side 1, for the ray from between them.
+    fn eye_ray(&self, stereo: &Stereo, side: Float, orig: Vec3f, dir: Vec3f) -> (Vec3f, Vec3f) {
+        let (right, up, forward) = self.basis;
+        let half = 0.5 * side * stereo.eye_distance;
+        if self.projection == Projection::Equirectangular {
+            // The eyes turn with the head to look each way around, so they
+            // are side by side across every direction
+            let across = dir.cross(&up);
+            if across.norm() < 1e-12 {
+                return (orig, dir);
+            }
+            return (orig + across.normalize() * half, dir);
+        }
+
+        // Both eyes see the same point on the plane they converge on
+        let eye = orig + right * half;
+        let ahead = dir.dot(&forward);
+        if ahead <= 0.0 {
+            return (eye, dir);
+        }
+        let convergence = stereo.convergence.unwrap_or_else(|| self.target_distance());
+        let point = orig + dir * (convergence / ahead);
+        (eye, (point - eye).normalize())
+    }
+}
+
+/// Where the camera is and what it looks at, at a point in time.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: Float,
+    pub position: Vec3f,
+    pub target: Vec3f,
+    /// The field of view, in radians
+    pub fov: Float,
+}
+
+/// How a camera path gets from one keyframe to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// In a straight line, at a steady speed, turning sharply at keyframes
+    Linear,
+    /// Along a curve through the keyframes that eases from one to the next
+    /// without sudden turns or changes of speed
+    #[default]
+    Smooth,
+}
+
+/// A camera moving through keyframes, staying at the first before its
+/// time and at the last after it.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    /// The keyframes, sorted by time
+    pub keyframes: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+}
+
+/// The value of a cubic curve through `p0` at `t0` and `p1` at `t1`, with
+/// the slopes `m0` and `m1` there, at the time `t`.
+fn hermite<T>(t: Float, (t0, p0, m0): (Float, T, T), (t1, p1, m1): (Float, T, T)) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Float, Output = T>,
+{
+    let h = t1 - t0;
+    let s = (t - t0) / h;
+    let (s2, s3) = (s * s, s * s * s);
+    p0 * (2.0 * s3 - 3.0 * s2 + 1.0)
+        + m0 * ((s3 - 2.0 * s2 + s) * h)
+        + p1 * (3.0 * s2 - 2.0 * s3)
+        + m1 * ((s3 - s2) * h)
+}
+
+/// The value `get` takes of the keyframes, at `time` between keyframe `i`
+/// and the next, along a curve whose slope at each keyframe is that between
+/// its neighbours, or towards its one neighbour at the ends.
+fn smooth<T>(keyframes: &[Keyframe], i: usize, time: Float, get: impl Fn(&Keyframe) -> T) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Float, Output = T>,
+{
+    let slope = |j: usize| {
+        let before = &keyframes[j.max(1) - 1];
+        let after = &keyframes[(j + 1).min(keyframes.len() - 1)];
+        (get(after) - get(before)) * (1.0 / (after.time - before.time))
+    };
+    let (a, b) = (&keyframes[i], &keyframes[i + 1]);
+    hermite(
+        time,
+        (a.time, get(a), slope(i)),
+        (b.time, get(b), slope(i + 1)),
+    )
+}
+
+impl CameraPath {
+    /// Moves `camera` to where the path has it at `time`.
+    pub fn apply(&self, camera: &mut Camera, time: Float) {
+        let keyframes = &self.keyframes;
+        let (first, last) = match (keyframes.first(), keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
+        let (position, target, fov) = if time <= first.time {
+            (first.position, first.target, first.fov)
+        } else if time >= last.time {
+            (last.position, last.target, last.fov)
+        } else {
+            let i = keyframes.partition_point(|k| k.time <= time) - 1;
+            let (a, b) = (&keyframes[i], &keyframes[i + 1]);
+            match self.interpolation {
+                Interpolation::Linear => {
+                    let s = (time - a.time) / (b.time - a.time);
+                    (
+                        a.position + (b.position - a.position) * s,
+                        a.target + (b.target - a.target) * s,
+                        a.fov + (b.fov - a.fov) * s,
+                    )
+                }
+                Interpolation::Smooth => (
+                    smooth(keyframes, i, time, |k| k.position),
+                    smooth(keyframes, i, time, |k| k.target),
+                    smooth(keyframes, i, time, |k| k.fov),
+                ),
+            }
+        };
+        camera.aim(position, target, camera.up());
+        camera.fov = fov;
+    }
+}
+
+/// An orbit of the camera around its target, about its up direction, at a
+/// steady distance and height: the turntable models are shown on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Turntable {
+    /// How far from the target the camera stays, or as far as it starts
+    pub radius: Option<Float>,
+    /// The angle of the camera above the target, in radians, or the one
+    /// it starts at
+    pub elevation: Option<Float>,
+}
+
+impl Turntable {
+    /// Moves `camera` the fraction `turn` of the way around its target,
+    /// from where it is.
+    pub fn apply(&self, camera: &mut Camera, turn: Float) {
+        let (target, up) = (camera.target(), camera.up().normalize());
+        let offset = camera.position() - target;
+        let height = offset.dot(&up);
+        let across = offset - up * height;
+        let start = if across.norm() > 1e-12 {
+            across.normalize()
+        } else {
+            up.perpendiculars().0
+        };
+        let radius = self.radius.unwrap_or_else(|| offset.len());
+        let elevation = self.elevation.unwrap_or_else(|| height.atan2(across.len()));
+        let azimuth = 2.0 * PI * turn;
+        let around = start * azimuth.cos() + up.cross(&start) * azimuth.sin();
+        let direction = around * elevation.cos() + up * elevation.sin();
+        camera.aim(target + direction * radius, target, camera.up());
+    }
+}
@@ -0,0 +1,444 @@
+//! Saving and loading the state of a render, so that it can be resumed after
+//! being interrupted.
+//!
+//! A checkpoint is a little-endian binary file: an 8 byte magic, then the
+//! [`Fingerprint`] of the render, as the seed and samples per pixel as
+//! `u32`, the names of the integrator and sampler, each as its length as
+//! `u32` followed by its bytes, and the hash of the scene as `u64`, then the
+//! width, height, tile size and tile count as `u32`, the finished passes of
+//! every tile as `u32`, the spread of the brightness of the pixels of every
+//! tile over its passes as `f32`, the averaged pixels as `f32` red, green,
+//! blue and alpha, and finally the number of output variables as `u32`
+//! followed by each one's index in `aov::ALL`, or one past the last for the
+//! pass of a light group followed by the length and bytes of the name of the
+//! group, and values, as three `f32` per pixel. Values are stored in single
+//! precision whatever `Float` is.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::aov::{self, Aov};
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::integrator::Integrator;
+use crate::render::{Image, RenderOptions, RenderState};
+use crate::sampler::SamplerKind;
+use crate::vector::{from_f32, to_f32, Float, Vec3f};
+
+const MAGIC: &[u8; 8] = b"TRCKPT05";
+/// The longest name of a light group read back
+const MAX_GROUP_LENGTH: usize = 1 << 10;
+/// The longest name of an integrator or sampler read back
+const MAX_NAME_LENGTH: usize = 64;
+
+/// What a render depends on besides the size of the image, its tiles and
+/// its output variables, so that it isn't resumed into a different image.
+/// The samplers are deterministic, so their kind and seed, along with the
+/// passes every tile has finished, are all the state they have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub seed: u32,
+    pub samples_per_pixel: usize,
+    pub integrator: Integrator,
+    pub sampler: SamplerKind,
+    /// Hash of the source of the scene
+    pub scene: u64,
+}
+
+impl Fingerprint {
+    /// The fingerprint of a render with `options` of the scene described by
+    /// `source`.
+    pub fn new(options: &RenderOptions, source: &[u8]) -> Self {
+        Self {
+            seed: options.seed,
+            samples_per_pixel: options.samples_per_pixel,
+            integrator: options.integrator,
+            sampler: options.sampler,
+            scene: hash(source),
+        }
+    }
+}
+
+/// The 64 bit FNV-1a hash of `bytes`, which unlike the hashers of the
+/// standard library stays the same from one build to the next.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::parse("checkpoint", message)
+}
+
+fn write_u32<W: Write>(w: &mut W, value: usize) -> error::Result<()> {
+    let value = u32::try_from(value).map_err(|_| invalid("value out of range"))?;
+    Ok(w.write_all(&value.to_le_bytes())?)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> error::Result<usize> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn write_string<W: Write>(w: &mut W, value: &str) -> error::Result<()> {
+    write_u32(w, value.len())?;
+    Ok(w.write_all(value.as_bytes())?)
+}
+
+/// Reads a string as [`write_string`] writes it, of at most `max` bytes.
+fn read_string<R: Read>(r: &mut R, max: usize, what: &str) -> error::Result<String> {
+    let length = read_u32(r)?;
+    if length > max {
+        return Err(invalid(format!("{} too long", what)));
+    }
+    let mut bytes = vec![0; length];
+    r.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid(format!("{} isn't UTF-8", what)))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> error::Result<Float> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(from_f32(f32::from_le_bytes(bytes)))
+}
+
+/// Writes the state of the render with `fingerprint` to `path`. The file is
+/// written next to it first and then moved into place, so that an
+/// interrupted save leaves the previous checkpoint intact.
+pub fn save<P: AsRef<Path>>(
+    state: &RenderState,
+    fingerprint: &Fingerprint,
+    path: P,
+) -> error::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut f = BufWriter::new(File::create(&temporary)?);
+    f.write_all(MAGIC)?;
+    write_u32(&mut f, fingerprint.seed as usize)?;
+    write_u32(&mut f, fingerprint.samples_per_pixel)?;
+    write_string(&mut f, &fingerprint.integrator.to_string())?;
+    write_string(&mut f, &fingerprint.sampler.to_string())?;
+    f.write_all(&fingerprint.scene.to_le_bytes())?;
+    write_u32(&mut f, state.image.width)?;
+    write_u32(&mut f, state.image.height)?;
+    write_u32(&mut f, state.tile_size)?;
+    write_u32(&mut f, state.tile_passes.len())?;
+    for &passes in &state.tile_passes {
+        write_u32(&mut f, passes)?;
+    }
+    for &spread in &state.tile_spread {
+        f.write_all(&to_f32(spread).to_le_bytes())?;
+    }
+    for (pixel, &alpha) in state.image.pixels.iter().zip(&state.image.alpha) {
+        for c in [pixel.r, pixel.g, pixel.b, alpha] {
+            f.write_all(&to_f32(c).to_le_bytes())?;
+        }
+    }
+    write_u32(&mut f, state.image.aovs.len())?;
+    for (kind, values) in &state.image.aovs {
+        match kind {
+            Aov::Lights(group) => {
+                write_u32(&mut f, aov::ALL.len())?;
+                write_string(&mut f, group)?;
+            }
+            _ => write_u32(&mut f, aov::ALL.iter().position(|a| a == kind).unwrap())?,
+        }
+        for value in values {
+            for c in *value {
+                f.write_all(&to_f32(c).to_le_bytes())?;
+            }
+        }
+    }
+    f.into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()?;
+    Ok(fs::rename(&temporary, path)?)
+}
+
+/// Checks that the state and `fingerprint` loaded are those of a render
+/// with the fingerprint `expected`, of `width` by `height` pixels in tiles
+/// of `tile_size`, with the output variables `aovs`, so that a render isn't
+/// resumed with options other than those it was started with.
+pub fn check(
+    (state, fingerprint): (&RenderState, &Fingerprint),
+    expected: &Fingerprint,
+    (width, height): (usize, usize),
+    tile_size: usize,
+    aovs: &[Aov],
+) -> error::Result<()> {
+    let started = |what: &str, then: &dyn fmt::Display, now: &dyn fmt::Display| {
+        Err(invalid(format!(
+            "the render was started with {} {}, not {}",
+            what, then, now
+        )))
+    };
+    if fingerprint.seed != expected.seed {
+        return started("the seed", &fingerprint.seed, &expected.seed);
+    }
+    if fingerprint.samples_per_pixel != expected.samples_per_pixel {
+        return started(
+            "samples per pixel of",
+            &fingerprint.samples_per_pixel,
+            &expected.samples_per_pixel,
+        );
+    }
+    if fingerprint.integrator != expected.integrator {
+        return started(
+            "the integrator",
+            &fingerprint.integrator,
+            &expected.integrator,
+        );
+    }
+    if fingerprint.sampler != expected.sampler {
+        return started("the sampler", &fingerprint.sampler, &expected.sampler);
+    }
+    if fingerprint.scene != expected.scene {
+        return Err(invalid("the render was started with a different scene"));
+    }
+    let image = &state.image;
+    if (image.width, image.height) != (width, height) {
+        return Err(invalid(format!(
+            "the render was started at {} by {} pixels, not {} by {}",
+            image.width, image.height, width, height
+        )));
+    }
+    if state.tile_size != tile_size {
+        return Err(invalid(format!(
+            "the render was started with tiles of {} pixels, not {}",
+            state.tile_size, tile_size
+        )));
+    }
+    let stored: Vec<_> = image.aovs.iter().map(|(aov, _)| aov).collect();
+    if stored.len() != aovs.len() || !aovs.iter().all(|aov| stored.contains(&aov)) {
+        let names = |aovs: &mut dyn Iterator<Item = &Aov>| {
+            let names: Vec<_> = aovs.map(Aov::to_string).collect();
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        };
+        return Err(invalid(format!(
+            "the render was started with the output variables {}, not {}",
+            names(&mut stored.iter().copied()),
+            names(&mut aovs.iter())
+        )));
+    }
+    Ok(())
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> error::Result<(RenderState, Fingerprint)> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Reads a checkpoint as [`save`] writes it. What is read is kept as it
+/// comes rather than made room for up front, so that a header can't ask
+/// for more memory than the checkpoint holds.
+pub fn read<R: Read>(mut f: R) -> error::Result<(RenderState, Fingerprint)> {
+    let mut magic = [0; 8];
+    f.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+
+    let seed = read_u32(&mut f)? as u32;
+    let samples_per_pixel = read_u32(&mut f)?;
+    let integrator = read_string(&mut f, MAX_NAME_LENGTH, "integrator name")?
+        .parse()
+        .map_err(invalid)?;
+    let sampler = read_string(&mut f, MAX_NAME_LENGTH, "sampler name")?
+        .parse()
+        .map_err(invalid)?;
+    let mut scene = [0; 8];
+    f.read_exact(&mut scene)?;
+    let fingerprint = Fingerprint {
+        seed,
+        samples_per_pixel,
+        integrator,
+        sampler,
+        scene: u64::from_le_bytes(scene),
+    };
+
+    let width = read_u32(&mut f)?;
+    let height = read_u32(&mut f)?;
+    let tile_size = read_u32(&mut f)?;
+    let tile_count = read_u32(&mut f)?;
+    if width == 0 || height == 0 || width.checked_mul(height).is_none_or(|n| n > 1 << 28) {
+        return Err(invalid("image size out of range"));
+    }
+    if tile_size == 0 || width.div_ceil(tile_size) * height.div_ceil(tile_size) != tile_count {
+        return Err(invalid("tiles don't match the image size"));
+    }
+    let tile_passes = (0..tile_count)
+        .map(|_| read_u32(&mut f))
+        .collect::<error::Result<_>>()?;
+    let tile_spread = (0..tile_count)
+        .map(|_| read_f32(&mut f))
+        .collect::<error::Result<_>>()?;
+
+    let (mut pixels, mut alpha) = (vec![], vec![]);
+    for _ in 0..width * height {
+        pixels.push(Color::new(
+            read_f32(&mut f)?,
+            read_f32(&mut f)?,
+            read_f32(&mut f)?,
+        ));
+        alpha.push(read_f32(&mut f)?);
+    }
+    let mut image = Image {
+        width,
+        height,
+        pixels,
+        alpha,
+        aovs: vec![],
+    };
+    for _ in 0..read_u32(&mut f)? {
+        let kind = match read_u32(&mut f)? {
+            i if i == aov::ALL.len() => {
+                Aov::lights(&read_string(&mut f, MAX_GROUP_LENGTH, "light group name")?)
+            }
+            i => *aov::ALL
+                .get(i)
+                .ok_or_else(|| invalid("unknown output variable"))?,
+        };
+        let values = (0..width * height)
+            .map(|_| {
+                Ok(Vec3f::new(
+                    read_f32(&mut f)?,
+                    read_f32(&mut f)?,
+                    read_f32(&mut f)?,
+                ))
+            })
+            .collect::<error::Result<_>>()?;
+        image.aovs.push((kind, values));
+    }
+    let state = RenderState {
+        image,
+        tile_size,
+        tile_passes,
+        tile_spread,
+    };
+    Ok((state, fingerprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    fn components<T: Copy + Into<[Float; 3]>>(values: &[T]) -> Vec<[Float; 3]> {
+        values.iter().map(|&v| v.into()).collect()
+    }
+
+    fn fingerprint() -> Fingerprint {
+        let options = RenderOptions {
+            seed: 7,
+            samples_per_pixel: 4,
+            integrator: Integrator::Path,
+            sampler: SamplerKind::Sobol,
+            ..RenderOptions::default()
+        };
+        Fingerprint::new(&options, b"sphere 0 0 -16 2 ivory")
+    }
+
+    #[test]
+    fn round_trips() {
+        let aovs = [Aov::Depth, Aov::lights("key")];
+        let mut state = RenderState::with_aovs(5, 3, 2, &aovs);
+        for (i, pixel) in state.image.pixels.iter_mut().enumerate() {
+            *pixel = Color::new(i as Float, 0.5, -0.25);
+        }
+        state.image.alpha[4] = 0.75;
+        state.image.aovs[1].1[2] = Vec3f::new(1.0, 2.0, 3.0);
+        state.tile_passes[1] = 3;
+        state.tile_spread[1] = 0.125;
+
+        let path = env::temp_dir().join(format!("tiny-raytracer-{}.ckpt", process::id()));
+        save(&state, &fingerprint(), &path).unwrap();
+        let (loaded, stored) = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(stored, fingerprint());
+        check((&loaded, &stored), &fingerprint(), (5, 3), 2, &aovs).unwrap();
+        assert_eq!(loaded.tile_passes, state.tile_passes);
+        assert_eq!(loaded.tile_spread, state.tile_spread);
+        assert_eq!(
+            components(&loaded.image.pixels),
+            components(&state.image.pixels)
+        );
+        assert_eq!(loaded.image.alpha, state.image.alpha);
+        for ((a, values), (b, expected)) in loaded.image.aovs.iter().zip(&state.image.aovs) {
+            assert!(a == b && components(values) == components(expected));
+        }
+        let loaded = (&loaded, &stored);
+        assert!(check(loaded, &fingerprint(), (5, 4), 2, &aovs).is_err());
+        assert!(check(loaded, &fingerprint(), (5, 3), 4, &aovs).is_err());
+        assert!(check(loaded, &fingerprint(), (5, 3), 2, &aovs[..1]).is_err());
+    }
+
+    #[test]
+    fn refuses_renders_with_other_settings() {
+        let state = RenderState::with_aovs(5, 3, 2, &[]);
+        let stored = fingerprint();
+        let refused = |change: &dyn Fn(&mut Fingerprint), message: &str| {
+            let mut expected = fingerprint();
+            change(&mut expected);
+            let error = check((&state, &stored), &expected, (5, 3), 2, &[]).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        };
+        refused(&|f| f.seed = 8, "the seed 7, not 8");
+        refused(
+            &|f| f.samples_per_pixel = 1,
+            "samples per pixel of 4, not 1",
+        );
+        refused(
+            &|f| f.integrator = Integrator::Direct,
+            "the integrator path, not direct",
+        );
+        refused(
+            &|f| f.sampler = SamplerKind::R2,
+            "the sampler sobol, not r2",
+        );
+        refused(
+            &|f| f.scene = hash(b"sphere 0 0 -16 3 ivory"),
+            "a different scene",
+        );
+        check((&state, &stored), &fingerprint(), (5, 3), 2, &[]).unwrap();
+    }
+
+    #[test]
+    fn refuses_unknown_integrators() {
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, 0).unwrap();
+        write_u32(&mut bytes, 1).unwrap();
+        write_string(&mut bytes, "photon").unwrap();
+        assert!(read(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn refuses_checkpoints_cut_short() {
+        // The header of a 16k square in one tile, without its pixels
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, 0).unwrap();
+        write_u32(&mut bytes, 1).unwrap();
+        write_string(&mut bytes, "path").unwrap();
+        write_string(&mut bytes, "r2").unwrap();
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        let header = bytes.len();
+        for value in &[1 << 14, 1 << 14, 1 << 14, 1, 0] {
+            write_u32(&mut bytes, *value).unwrap();
+        }
+        bytes.extend_from_slice(&0f32.to_le_bytes());
+        assert!(read(&bytes[..]).is_err());
+        assert!(read(&bytes[..header + 12]).is_err());
+    }
+}
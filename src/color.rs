@@ -0,0 +1,169 @@
+//! Colors as images hold them: linear RGB with the sRGB primaries, the
+//! light itself rather than how a file encodes it.
+//!
+//! Colors only come into an image decoded, from the sRGB or gamma encoded
+//! values of a file or from the light the renderer worked out, which is
+//! linear already, and only leave it encoded again for a file or a window,
+//! so that an image can't be written twice over encoded or shaded with
+//! values that were never decoded.
+
+use std::ops::{Add, AddAssign, Mul, MulAssign, Sub};
+
+use crate::output::Transfer;
+use crate::vector::{Float, Vec3f};
+
+/// A linear RGB color.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Color {
+    pub r: Float,
+    pub g: Float,
+    pub b: Float,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
+
+    pub const fn new(r: Float, g: Float, b: Float) -> Self {
+        Self { r, g, b }
+    }
+
+    /// The color the values of a file in `0..1` stand for, encoded with
+    /// `transfer`.
+    pub fn decode([r, g, b]: [Float; 3], transfer: Transfer) -> Self {
+        Self::new(transfer.decode(r), transfer.decode(g), transfer.decode(b))
+    }
+
+    /// The values in `0..1` a file encoded with `transfer` stores for the
+    /// color, cut off at black and white.
+    pub fn encode(self, transfer: Transfer) -> [Float; 3] {
+        let channel = |c: Float| transfer.encode(c.clamp(0.0, 1.0));
+        [channel(self.r), channel(self.g), channel(self.b)]
+    }
+
+    /// How bright the color looks, by the Rec. 709 weights of its channels.
+    pub fn luminance(self) -> Float {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// The brightest of the channels.
+    pub fn max_channel(self) -> Float {
+        self.r.max(self.g).max(self.b)
+    }
+
+    /// The squared distance between the colors, as points of the RGB cube.
+    pub fn distance_squared(self, other: Self) -> Float {
+        let d = self - other;
+        d.r * d.r + d.g * d.g + d.b * d.b
+    }
+
+    /// The color `t` of the way from this one to `other`.
+    pub fn lerp(&self, other: &Self, t: Float) -> Self {
+        *self + (*other - *self) * t
+    }
+
+    /// Each channel passed through `f`.
+    pub fn map(self, f: impl Fn(Float) -> Float) -> Self {
+        Self::new(f(self.r), f(self.g), f(self.b))
+    }
+}
+
+/// The light the renderer worked out, which is linear RGB.
+impl From<Vec3f> for Color {
+    fn from(light: Vec3f) -> Self {
+        Self::new(light.0, light.1, light.2)
+    }
+}
+
+/// The color as the light the renderer shades with.
+impl From<Color> for Vec3f {
+    fn from(color: Color) -> Self {
+        Vec3f::new(color.r, color.g, color.b)
+    }
+}
+
+impl From<Color> for [Float; 3] {
+    fn from(color: Color) -> Self {
+        [color.r, color.g, color.b]
+    }
+}
+
+impl From<[Float; 3]> for Color {
+    fn from([r, g, b]: [Float; 3]) -> Self {
+        Self::new(r, g, b)
+    }
+}
+
+impl Add for Color {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl Sub for Color {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
+}
+
+impl Mul for Color {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(self.r * other.r, self.g * other.g, self.b * other.b)
+    }
+}
+
+impl Mul<Float> for Color {
+    type Output = Self;
+
+    fn mul(self, scale: Float) -> Self {
+        self.map(|c| c * scale)
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl MulAssign<Float> for Color {
+    fn mul_assign(&mut self, scale: Float) {
+        *self = *self * scale;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_and_encodes_srgb() {
+        let color = Color::decode([0.0, 0.5, 1.0], Transfer::Srgb);
+        assert_eq!(color.r, 0.0);
+        assert!((color.g - 0.214).abs() < 1e-3, "{:?}", color);
+        assert!((color.b - 1.0).abs() < 1e-6);
+        let encoded = color.encode(Transfer::Srgb);
+        for (encoded, original) in encoded.iter().zip(&[0.0, 0.5, 1.0]) {
+            assert!((encoded - original).abs() < 1e-4, "{:?}", encoded);
+        }
+        // Linear data is kept as it is, and light beyond white cut off
+        assert_eq!(Color::decode([0.25; 3], Transfer::Gamma(1.0)).g, 0.25);
+        assert_eq!(
+            Color::new(4.0, -1.0, 0.5).encode(Transfer::Gamma(1.0)),
+            [1.0, 0.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn weighs_channels_by_brightness() {
+        assert!((Color::WHITE.luminance() - 1.0).abs() < 1e-6);
+        assert!(Color::new(0.0, 1.0, 0.0).luminance() > Color::new(1.0, 0.0, 1.0).luminance());
+        assert_eq!(Color::new(0.2, 0.9, 0.4).max_channel(), 0.9);
+    }
+}
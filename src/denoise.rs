@@ -0,0 +1,106 @@
+//! Smoothing out the noise of renders with few samples per pixel. Each pixel
+//! becomes an average of its neighbours, weighted by how close they are and
+//! by how much they look like the same surface: a joint bilateral filter
+//! guided by the normal and albedo output variables, which are free of the
+//! noise of the lighting, so that edges and changes of material stay sharp.
+
+use crate::aov::Aov;
+use crate::color::Color;
+use crate::render::Image;
+use crate::vector::{Float, Vec3f};
+
+/// The passes the filter is guided by. Renders to be denoised should have
+/// them; without them only the colors and the alpha tell surfaces apart.
+pub const FEATURES: [Aov; 2] = [Aov::Normal, Aov::Albedo];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// Pixels on each side of the one being filtered that it takes in
+    pub radius: usize,
+    /// How different normals can be, as a distance between unit vectors,
+    /// before the pixels count as different surfaces
+    pub normal_sigma: Float,
+    /// The same for the albedo
+    pub albedo_sigma: Float,
+    /// The same for the colors themselves, loose enough to look past the
+    /// noise but still keep the edges of shadows and highlights
+    pub color_sigma: Float,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            radius: 4,
+            normal_sigma: 0.2,
+            albedo_sigma: 0.1,
+            color_sigma: 2.0,
+        }
+    }
+}
+
+/// `exp(-d²/2σ²)` for the squared distance `d²`.
+fn gaussian(distance_squared: Float, sigma: Float) -> Float {
+    (-distance_squared / (2.0 * sigma * sigma)).exp()
+}
+
+impl Denoiser {
+    /// The image with its colors filtered. The alpha and the output variables
+    /// are left as they are.
+    pub fn apply(&self, image: &Image) -> Image {
+        let features: Vec<(&[Vec3f], Float)> = image
+            .aovs
+            .iter()
+            .filter_map(|(aov, values)| match aov {
+                Aov::Normal => Some((values.as_slice(), self.normal_sigma)),
+                Aov::Albedo => Some((values.as_slice(), self.albedo_sigma)),
+                _ => None,
+            })
+            .collect();
+        let radius = self.radius as isize;
+        let spatial_sigma = (self.radius as Float / 2.0).max(0.5);
+
+        let mut pixels = Vec::with_capacity(image.pixels.len());
+        for y in 0..image.height as isize {
+            for x in 0..image.width as isize {
+                let p = y as usize * image.width + x as usize;
+                let mut sum = Color::BLACK;
+                let mut total = 0.0;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let (qx, qy) = (x + dx, y + dy);
+                        if qx < 0
+                            || qy < 0
+                            || qx >= image.width as isize
+                            || qy >= image.height as isize
+                        {
+                            continue;
+                        }
+                        let q = qy as usize * image.width + qx as usize;
+                        let coverage = image.alpha[p] - image.alpha[q];
+                        let mut weight = gaussian((dx * dx + dy * dy) as Float, spatial_sigma)
+                            * gaussian(coverage * coverage, 0.1)
+                            * gaussian(
+                                image.pixels[p].distance_squared(image.pixels[q]),
+                                self.color_sigma,
+                            );
+                        for &(values, sigma) in &features {
+                            weight *= gaussian((values[p] - values[q]).norm(), sigma);
+                        }
+                        sum += image.pixels[q] * weight;
+                        total += weight;
+                    }
+                }
+                // The pixel itself has a weight of one, so the total is never zero
+                pixels.push(sum * (1.0 / total));
+            }
+        }
+
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels,
+            alpha: image.alpha.clone(),
+            aovs: image.aovs.clone(),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! The error of loading scenes, meshes, images and checkpoints and of
+//! writing images.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::json::ParseError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// A file doesn't hold what its `format`, such as `scene` or `obj`, says
+    /// it should. Where known, `line` and `column` are where in the file
+    /// the problem is, and `field` which field of a scene, such as
+    /// `objects[2].radius`.
+    Parse {
+        format: &'static str,
+        line: Option<usize>,
+        column: Option<usize>,
+        field: Option<String>,
+        message: String,
+    },
+    /// A file of a format that can't be read or written, going by its
+    /// extension, if it has one, along with the extensions that can.
+    UnsupportedFormat {
+        extension: Option<String>,
+        expected: &'static str,
+    },
+    /// An error in another file the one being read refers to, such as the
+    /// material library of a mesh.
+    File { path: PathBuf, error: Box<Error> },
+    /// Every problem found in a scene that would load but render wrongly,
+    /// each an error in one of its fields, so that all of them can be fixed
+    /// at once.
+    Invalid(Vec<Error>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// A parse error of a file of the given format, with no more idea where
+    /// in the file it is.
+    pub(crate) fn parse(format: &'static str, message: impl Into<String>) -> Self {
+        Self::parse_at(format, None, None, None, message.into())
+    }
+
+    /// A parse error at `line` of a text file of the given format.
+    pub(crate) fn at_line(format: &'static str, line: usize, message: impl Into<String>) -> Self {
+        Self::parse_at(format, Some(line), None, None, message.into())
+    }
+
+    /// A parse error in `field` of a file of the given format.
+    pub(crate) fn in_field(format: &'static str, field: &str, message: impl Into<String>) -> Self {
+        Self::parse_at(format, None, None, Some(field.to_string()), message.into())
+    }
+
+    /// A syntax error of a JSON file or a script of the given format.
+    pub(crate) fn json(format: &'static str, e: ParseError) -> Self {
+        Self::parse_at(format, Some(e.line), Some(e.column), None, e.message)
+    }
+
+    fn parse_at(
+        format: &'static str,
+        line: Option<usize>,
+        column: Option<usize>,
+        field: Option<String>,
+        message: String,
+    ) -> Self {
+        Error::Parse {
+            format,
+            line,
+            column,
+            field,
+            message,
+        }
+    }
+
+    /// The error of a file at `path` of a format that can't be read or
+    /// written, where `expected` lists the extensions that can.
+    pub(crate) fn unsupported_format(path: &Path, expected: &'static str) -> Self {
+        Error::UnsupportedFormat {
+            extension: path
+                .extension()
+                .map(|extension| extension.to_string_lossy().into_owned()),
+            expected,
+        }
+    }
+
+    /// The error, as having happened in the file at `path`.
+    pub(crate) fn in_file(self, path: impl Into<PathBuf>) -> Self {
+        Error::File {
+            path: path.into(),
+            error: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Parse {
+                format,
+                line,
+                column,
+                field,
+                message,
+            } => {
+                f.write_str(format)?;
+                match (line, column) {
+                    (Some(line), Some(column)) => write!(f, " {}:{}", line, column)?,
+                    (Some(line), None) => write!(f, " line {}", line)?,
+                    _ => {}
+                }
+                if let Some(field) = field {
+                    write!(f, ": {}", field)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Error::UnsupportedFormat {
+                extension: Some(extension),
+                expected,
+            } => write!(
+                f,
+                "unsupported format `.{}`, expected {}",
+                extension, expected
+            ),
+            Error::UnsupportedFormat {
+                extension: None,
+                expected,
+            } => write!(f, "unsupported format, expected {}", expected),
+            Error::File { path, error } => write!(f, "{}: {}", path.display(), error),
+            Error::Invalid(problems) => match &problems[..] {
+                [problem] => problem.fmt(f),
+                _ => {
+                    write!(f, "{} problems", problems.len())?;
+                    for problem in problems {
+                        write!(f, "\n    {}", problem)?;
+                    }
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::File { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn says_where_the_problem_is() {
+        assert_eq!(
+            Error::at_line("obj", 3, "bad face").to_string(),
+            "obj line 3: bad face"
+        );
+        assert_eq!(
+            Error::in_field("scene", "objects[2].radius", "expected a number").to_string(),
+            "scene: objects[2].radius: expected a number"
+        );
+        assert_eq!(
+            Error::unsupported_format(Path::new("out.bmp"), ".ppm or .png")
+                .in_file("scene.json")
+                .to_string(),
+            "scene.json: unsupported format `.bmp`, expected .ppm or .png"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Functions for embedding the renderer in C and C++ programs, declared in
+//! `include/tiny_raytracer.h`. A scene is put together from spheres and
+//! point lights, seen through a camera, or given as the source of a scene
+//! file, and rendered into a buffer of 8-bit RGBA pixels of the caller's.
+//! Numbers are `double` whatever `Float` is.
+//!
+//! Built for `wasm32-unknown-unknown`, the same functions are the exports
+//! of the WebAssembly module, with `trt_alloc` and `trt_free` for the
+//! buffers, which is how `web/index.html` renders into a canvas.
+//!
+//! Functions that can fail return 0 on success and -1 otherwise, with the
+//! reason left for `trt_last_error`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_double, c_int};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use crate::camera::{Camera, Projection};
+use crate::light::{Light, PointLight};
+use crate::material::Material;
+use crate::object::{Object, Sphere};
+use crate::output::{self, Alpha, BitDepth, OutputOptions};
+use crate::render::{self, RenderOptions, RenderState};
+use crate::scene::Scene;
+use crate::vector::{consts::PI, from_f64, to_f64, Vec2f, Vec3f};
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(message: &str) -> c_int {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    -1
+}
+
+fn vector(x: c_double, y: c_double, z: c_double) -> Vec3f {
+    Vec3f::new(from_f64(x), from_f64(y), from_f64(z))
+}
+
+/// A material of the Phong model, as the `materials` of a scene file
+/// describe it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TrtMaterial {
+    pub diffuse_color: [c_double; 3],
+    /// The weights of the diffuse and specular light
+    pub albedo: [c_double; 2],
+    pub specular_exponent: c_double,
+    /// Light the surface gives off by itself
+    pub emission: [c_double; 3],
+}
+
+impl From<&TrtMaterial> for Material {
+    fn from(m: &TrtMaterial) -> Self {
+        let [r, g, b] = m.diffuse_color;
+        let mut material = Material::new(
+            Vec2f::new(from_f64(m.albedo[0]), from_f64(m.albedo[1])),
+            vector(r, g, b),
+            from_f64(m.specular_exponent),
+        );
+        let [r, g, b] = m.emission;
+        material.emission = vector(r, g, b);
+        material
+    }
+}
+
+/// A scene being put together. The scene is built anew for every render,
+/// so that objects can be added between renders.
+pub struct TrtScene {
+    spheres: Vec<(Vec3f, c_double, Material)>,
+    lights: Vec<(Vec3f, Vec3f)>,
+    camera: Camera,
+}
+
+/// The reason the last function to fail on this thread failed, valid until
+/// the next one fails. Empty if none has.
+#[no_mangle]
+pub extern "C" fn trt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Writes the material of the tutorial named `name`, `ivory` or
+/// `red_rubber`, to `material`.
+///
+/// # Safety
+///
+/// `name` must be a string ending in a nul, and `material` valid for
+/// writing.
+#[no_mangle]
+pub unsafe extern "C" fn trt_material_preset(
+    name: *const c_char,
+    material: *mut TrtMaterial,
+) -> c_int {
+    if name.is_null() || material.is_null() {
+        return fail("null argument");
+    }
+    let name = CStr::from_ptr(name).to_string_lossy();
+    let preset = match Material::preset(&name) {
+        Some(preset) if preset.metallic_roughness.is_none() => preset,
+        _ => return fail(&format!("no Phong material `{}`", name)),
+    };
+    let (color, albedo) = (preset.diffuse_color, preset.albedo);
+    *material = TrtMaterial {
+        diffuse_color: [to_f64(color.0), to_f64(color.1), to_f64(color.2)],
+        albedo: [to_f64(albedo.0), to_f64(albedo.1)],
+        specular_exponent: to_f64(preset.specular_exponent),
+        emission: [0.0; 3],
+    };
+    0
+}
+
+/// A new scene without objects or lights, seen by the camera of the
+/// tutorial: at the origin, looking down the negative z axis with a field
+/// of view of 90 degrees. Freed with `trt_scene_free`.
+#[no_mangle]
+pub extern "C" fn trt_scene_new() -> *mut TrtScene {
+    let camera = Camera::look_at(
+        Vec3f::new(0.0, 0.0, 0.0),
+        Vec3f::new(0.0, 0.0, -1.0),
+        Vec3f::new(0.0, 1.0, 0.0),
+    );
+    Box::into_raw(Box::new(TrtScene {
+        spheres: vec![],
+        lights: vec![],
+        camera,
+    }))
+}
+
+/// Frees a scene made by `trt_scene_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `scene` must be null or a scene not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn trt_scene_free(scene: *mut TrtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// Adds a sphere of `material`, centered at `x`, `y` and `z`.
+///
+/// # Safety
+///
+/// `scene` must be a scene of `trt_scene_new`, and `material` valid for
+/// reading.
+#[no_mangle]
+pub unsafe extern "C" fn trt_scene_add_sphere(
+    scene: *mut TrtScene,
+    x: c_double,
+    y: c_double,
+    z: c_double,
+    radius: c_double,
+    material: *const TrtMaterial,
+) -> c_int {
+    let (scene, material) = match (scene.as_mut(), material.as_ref()) {
+        (Some(scene), Some(material)) => (scene, material),
+        _ => return fail("null argument"),
+    };
+    if !(radius > 0.0 && radius.is_finite()) {
+        return fail("the radius must be positive");
+    }
+    scene
+        .spheres
+        .push((vector(x, y, z), radius, Material::from(material)));
+    0
+}
+
+/// Adds a point light at `x`, `y` and `z`, as bright as `red`, `green` and
+/// `blue` in each channel.
+///
+/// # Safety
+///
+/// `scene` must be a scene of `trt_scene_new`.
+#[no_mangle]
+pub unsafe extern "C" fn trt_scene_add_light(
+    scene: *mut TrtScene,
+    x: c_double,
+    y: c_double,
+    z: c_double,
+    red: c_double,
+    green: c_double,
+    blue: c_double,
+) -> c_int {
+    match scene.as_mut() {
+        Some(scene) => {
+            scene
+                .lights
+                .push((vector(x, y, z), vector(red, green, blue)));
+            0
+        }
+        None => fail("null argument"),
+    }
+}
+
+/// Puts the camera at `position` looking at `target`, rolled so that `up`
+/// points up the image, with a field of view of `fov` degrees from the
+/// bottom of the image to the top. The vectors are arrays of three.
+///
+/// # Safety
+///
+/// `scene` must be a scene of `trt_scene_new`, and the vectors valid for
+/// reading three numbers each.
+#[no_mangle]
+pub unsafe extern "C" fn trt_scene_set_camera(
+    scene: *mut TrtScene,
+    position: *const c_double,
+    target: *const c_double,
+    up: *const c_double,
+    fov: c_double,
+) -> c_int {
+    let scene = match scene.as_mut() {
+        Some(scene) if !position.is_null() && !target.is_null() && !up.is_null() => scene,
+        _ => return fail("null argument"),
+    };
+    let read = |v: *const c_double| {
+        let v = slice::from_raw_parts(v, 3);
+        vector(v[0], v[1], v[2])
+    };
+    let (position, target, up) = (read(position), read(target), read(up));
+    if (target - position).norm() == 0.0 {
+        return fail("the camera target is at its position");
+    }
+    if !(fov > 0.0 && fov < 180.0) {
+        return fail("the field of view must be between 0 and 180 degrees");
+    }
+    let mut camera = Camera::look_at(position, target, up);
+    camera.fov = from_f64(fov) * PI / 180.0;
+    camera.projection = Projection::Perspective;
+    scene.camera = camera;
+    0
+}
+
+/// Renders the scene into `rgba`, `width` by `height` pixels in rows from
+/// the top left, 4 bytes each: red, green and blue encoded with the sRGB
+/// curve, and straight alpha, 0 where the background shows. Each pixel
+/// averages `samples_per_pixel` samples, on as many threads as there are
+/// processors.
+///
+/// # Safety
+///
+/// `scene` must be a scene of `trt_scene_new`, and `rgba` valid for writing
+/// `width * height * 4` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn trt_render(
+    scene: *const TrtScene,
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+    rgba: *mut u8,
+) -> c_int {
+    let description = match scene.as_ref() {
+        Some(scene) if !rgba.is_null() => scene,
+        _ => return fail("null argument"),
+    };
+    let length = match rgba_length(width, height, samples_per_pixel) {
+        Ok(length) => length,
+        Err(message) => return fail(message),
+    };
+
+    let objects: Vec<Box<dyn Object>> = description
+        .spheres
+        .iter()
+        .map(|(center, radius, material)| {
+            Box::new(Sphere::new(*center, from_f64(*radius), material.clone())) as _
+        })
+        .collect();
+    let lights: Vec<Box<dyn Light>> = description
+        .lights
+        .iter()
+        .map(|&(position, intensity)| Box::new(PointLight::new(position, intensity)) as _)
+        .collect();
+    let mut scene = Scene::new(objects, lights);
+    scene.camera = description.camera;
+
+    render_rgba(
+        &scene,
+        (width, height),
+        samples_per_pixel,
+        slice::from_raw_parts_mut(rgba, length),
+    );
+    0
+}
+
+/// Renders the scene described by the `length` bytes of UTF-8 at `source`,
+/// as a scene file would describe it, into `rgba` as `trt_render` does.
+/// Files the scene refers to are looked for in the current directory, so
+/// that in a browser, where there are none, the scene has to hold all it
+/// needs.
+///
+/// # Safety
+///
+/// `source` must be valid for reading `length` bytes, and `rgba` for
+/// writing `width * height * 4`.
+#[no_mangle]
+pub unsafe extern "C" fn trt_render_source(
+    source: *const u8,
+    length: usize,
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+    rgba: *mut u8,
+) -> c_int {
+    if source.is_null() || rgba.is_null() {
+        return fail("null argument");
+    }
+    let rgba_length = match rgba_length(width, height, samples_per_pixel) {
+        Ok(rgba_length) => rgba_length,
+        Err(message) => return fail(message),
+    };
+    let source = match std::str::from_utf8(slice::from_raw_parts(source, length)) {
+        Ok(source) => source,
+        Err(_) => return fail("the scene isn't UTF-8"),
+    };
+    let scene = match Scene::parse(source, Path::new("")) {
+        Ok(scene) => scene,
+        Err(e) => return fail(&e.to_string()),
+    };
+    render_rgba(
+        &scene,
+        (width, height),
+        samples_per_pixel,
+        slice::from_raw_parts_mut(rgba, rgba_length),
+    );
+    0
+}
+
+/// `length` zeroed bytes, for callers without an allocator of their own to
+/// pass to the other functions, such as JavaScript calling a WebAssembly
+/// build of the library. Freed with `trt_free`.
+#[no_mangle]
+pub extern "C" fn trt_alloc(length: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; length].into_boxed_slice()).cast()
+}
+
+/// Frees the `length` bytes of `trt_alloc` at `bytes`. Null is ignored.
+///
+/// # Safety
+///
+/// `bytes` must be null or bytes of `trt_alloc` not freed already, of
+/// `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn trt_free(bytes: *mut u8, length: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, length)));
+    }
+}
+
+/// The number of bytes of an RGBA image of `width` by `height` pixels, if
+/// it can be rendered with `samples_per_pixel`.
+fn rgba_length(
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+) -> Result<usize, &'static str> {
+    if width == 0 || height == 0 || samples_per_pixel == 0 {
+        return Err("the size and samples per pixel must be positive");
+    }
+    width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(4))
+        .ok_or("the image is too large")
+}
+
+/// Renders `scene` into the `rgba` bytes of `width` by `height` pixels, as
+/// `trt_render` describes.
+fn render_rgba(
+    scene: &Scene,
+    (width, height): (usize, usize),
+    samples_per_pixel: usize,
+    rgba: &mut [u8],
+) {
+    let options = RenderOptions {
+        samples_per_pixel,
+        transparent_background: true,
+        ..RenderOptions::default()
+    };
+    let state = RenderState::new(width, height, options.tile_size);
+    let image = render::resume(scene, &options, state, |_, _| {}).image;
+    let output = OutputOptions {
+        alpha: Alpha::Straight,
+        bit_depth: BitDepth::Eight,
+        ..OutputOptions::default()
+    };
+    let samples = output::samples(&image, &output);
+    for (byte, &sample) in rgba.iter_mut().zip(&samples) {
+        *byte = sample as u8;
+    }
+}
@@ -0,0 +1,144 @@
+//! Opening the files scenes refer to. The loaders of meshes, textures and
+//! profiles read their files through here, so that the files a scene may
+//! read can be kept to a directory, for scenes that come from elsewhere,
+//! such as those posted to `serve`, and the files it did read listed, such
+//! as the materials and textures of its meshes, for sending to workers.
+//!
+//! Files outside the directory are refused without looking at them, so
+//! that whether they exist doesn't show either.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+thread_local! {
+    /// The directory the loading on this thread is kept to, if it is
+    static ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    /// The files read on this thread, once each, if they are being listed
+    static READ: RefCell<Option<Vec<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Runs `load` with the files read on this thread kept to the directory
+/// `root` and below it.
+pub fn within<T>(root: &Path, load: impl FnOnce() -> T) -> io::Result<T> {
+    let root = root.canonicalize()?;
+    let before = ROOT.with(|r| r.replace(Some(root)));
+    let result = load();
+    ROOT.with(|r| r.replace(before));
+    Ok(result)
+}
+
+/// Runs `load`, returning what it returns and the files it read on this
+/// thread, by the paths they were read at.
+pub fn recording<T>(load: impl FnOnce() -> T) -> (T, Vec<PathBuf>) {
+    let before = READ.with(|r| r.replace(Some(vec![])));
+    let result = load();
+    let read = READ.with(|r| r.replace(before)).unwrap_or_default();
+    (result, read)
+}
+
+/// `path` made absolute and with its `.` and `..` taken out, without
+/// looking at the file system for links.
+fn normalize(path: &Path) -> io::Result<PathBuf> {
+    let mut normal = PathBuf::new();
+    for component in env::current_dir()?.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    Ok(normal)
+}
+
+/// Refuses `path` if the loading on this thread is kept to a directory it
+/// isn't in, and lists it otherwise, if the files read are being listed.
+fn check(path: &Path) -> io::Result<()> {
+    if let Some(root) = ROOT.with(|r| r.borrow().clone()) {
+        keep_within(&root, path)?;
+    }
+    READ.with(|r| {
+        if let Some(read) = r.borrow_mut().as_mut() {
+            if !read.iter().any(|file| file == path) {
+                read.push(path.to_path_buf());
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Refuses `path` unless it is in `root`: first by its name, then by where
+/// its links lead.
+fn keep_within(root: &Path, path: &Path) -> io::Result<()> {
+    let refused = || {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is outside the directory files are read from",
+                path.display()
+            ),
+        )
+    };
+    if !normalize(path)?.starts_with(root) {
+        return Err(refused());
+    }
+    if !path.canonicalize()?.starts_with(root) {
+        return Err(refused());
+    }
+    Ok(())
+}
+
+/// Reads the whole file at `path`.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    check(path)?;
+    fs::read(path)
+}
+
+/// Opens the file at `path` for reading.
+pub fn open(path: &Path) -> io::Result<File> {
+    check(path)?;
+    File::open(path)
+}
+
+/// Whether there is a file at `path` that may be read.
+pub fn is_file(path: &Path) -> bool {
+    check(path).is_ok() && path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn keeps_reads_within_the_root() {
+        let root = env::temp_dir().join(format!("tiny-raytracer-files-{}", process::id()));
+        fs::create_dir_all(root.join("textures")).unwrap();
+        fs::write(root.join("textures/a.ppm"), "inside").unwrap();
+        let outside = env::temp_dir().join(format!("tiny-raytracer-{}.txt", process::id()));
+        fs::write(&outside, "outside").unwrap();
+
+        let reads = within(&root, || {
+            (
+                read(&root.join("textures/../textures/a.ppm")).map_err(|e| e.kind()),
+                read(&outside).map_err(|e| e.kind()),
+                read(&root.join("../missing")).map_err(|e| e.kind()),
+                is_file(&outside),
+            )
+        })
+        .unwrap();
+        assert_eq!(reads.0, Ok(b"inside".to_vec()));
+        assert_eq!(reads.1, Err(io::ErrorKind::PermissionDenied));
+        assert_eq!(reads.2, Err(io::ErrorKind::PermissionDenied));
+        assert!(!reads.3);
+        // Outside `within`, anything goes
+        assert_eq!(read(&outside).unwrap(), b"outside");
+
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&outside).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+//! IES photometric profiles (LM-63): how bright a real fixture is in each
+//! direction, measured by its maker, for lights that spread their light the
+//! way the fixtures of a building do. Only type C photometry is read, which
+//! is what nearly every profile of indoor and street lighting uses: vertical
+//! angles from straight down, the nadir of the fixture, at 0° to straight
+//! up at 180°, and horizontal angles around it.
+
+use std::path::Path;
+
+use crate::error::{self, Error};
+use crate::files;
+use crate::vector::{consts::PI, Float};
+
+/// The most vertical or horizontal angles read, far more than any profile
+/// is measured at
+const MAX_ANGLES: usize = 1 << 12;
+/// The most candelas read, of all the angles together
+const MAX_CANDELAS: usize = 1 << 20;
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::parse("ies", message)
+}
+
+/// The candelas of a fixture at a grid of vertical and horizontal angles,
+/// in degrees.
+pub struct Profile {
+    vertical: Vec<Float>,
+    horizontal: Vec<Float>,
+    /// The candelas at each vertical angle for each horizontal one
+    candelas: Vec<Vec<Float>>,
+    peak: Float,
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> error::Result<Self> {
+        let source = files::read(path)?;
+        Self::parse(&String::from_utf8_lossy(&source))
+    }
+
+    pub fn parse(source: &str) -> error::Result<Self> {
+        // Keyword lines come before the tilt, and numbers after it,
+        // separated by white space or commas across any number of lines
+        let mut lines = source.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(line) if line.trim_start().starts_with("TILT=") => {
+                    break line.trim_start()["TILT=".len()..].trim();
+                }
+                Some(_) => continue,
+                None => return Err(invalid("missing the `TILT=` line")),
+            }
+        };
+        let numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                word.parse::<Float>()
+                    .map_err(|_| invalid(format!("expected a number, not `{}`", word)))
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        let mut numbers = numbers.into_iter();
+        let mut take = |n: usize| -> error::Result<Vec<Float>> {
+            let taken: Vec<_> = numbers.by_ref().take(n).collect();
+            if taken.len() < n {
+                return Err(invalid("file is truncated"));
+            }
+            Ok(taken)
+        };
+        let count = |n: Float, least: usize, what: &str| {
+            if n >= least as Float && n <= MAX_ANGLES as Float && n.fract() == 0.0 {
+                Ok(n as usize)
+            } else {
+                Err(invalid(format!(
+                    "expected {} to {} {}, not {}",
+                    least, MAX_ANGLES, what, n
+                )))
+            }
+        };
+        // How the light of the lamp changes as the fixture is tilted, which
+        // only matters for fixtures mounted at an angle they weren't measured
+        // at, so it is skipped
+        if tilt == "INCLUDE" {
+            let angles = count(take(2)?[1], 1, "tilt angles")?;
+            let values = angles
+                .checked_mul(2)
+                .ok_or_else(|| invalid("too many tilt angles"))?;
+            take(values)?;
+        }
+
+        // The lamps, their lumens, the multiplier, the number of vertical
+        // and horizontal angles, the photometric type, the units and size of
+        // the fixture, the ballast factor, a number for future use and the
+        // input watts
+        let header = take(13)?;
+        let (multiplier, ballast_factor) = (header[2], header[10]);
+        if header[5] != 1.0 {
+            return Err(invalid(format!(
+                "photometric type {} isn't supported, only type C (1) is",
+                header[5]
+            )));
+        }
+        let vertical_count = count(header[3], 2, "vertical angles")?;
+        let horizontal_count = count(header[4], 1, "horizontal angles")?;
+        if vertical_count
+            .checked_mul(horizontal_count)
+            .is_none_or(|n| n > MAX_CANDELAS)
+        {
+            return Err(invalid(format!(
+                "expected at most {} candelas, not {} by {}",
+                MAX_CANDELAS, vertical_count, horizontal_count
+            )));
+        }
+        let vertical = take(vertical_count)?;
+        let horizontal = take(horizontal_count)?;
+        let scale = multiplier * ballast_factor;
+        let candelas = (0..horizontal_count)
+            .map(|_| Ok(take(vertical_count)?.iter().map(|c| c * scale).collect()))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        for angles in [&vertical, &horizontal].iter() {
+            if angles.windows(2).any(|pair| pair[0] >= pair[1]) {
+                return Err(invalid("expected angles in increasing order"));
+            }
+        }
+        let peak = candelas
+            .iter()
+            .flatten()
+            .fold(0.0, |peak: Float, &candela| peak.max(candela));
+        if peak <= 0.0 {
+            return Err(invalid("the fixture gives off no light"));
+        }
+        Ok(Self {
+            vertical,
+            horizontal,
+            candelas,
+            peak,
+        })
+    }
+
+    /// The brightest the fixture is in any direction, in candelas.
+    pub fn peak(&self) -> Float {
+        self.peak
+    }
+
+    /// The candelas at `vertical` degrees from the nadir and `horizontal`
+    /// degrees around it, interpolated between the measured angles. Profiles
+    /// measured over only a half or a quarter of the way around stand for
+    /// fixtures that are symmetric about those planes, and profiles of a
+    /// single horizontal angle for fixtures that are the same all the way
+    /// around. Beyond the vertical angles measured it is dark.
+    pub fn candela(&self, vertical: Float, horizontal: Float) -> Float {
+        let (v0, v1, tv) = match between(&self.vertical, vertical) {
+            Some(v) => v,
+            None => return 0.0,
+        };
+        let mut horizontal = horizontal.rem_euclid(360.0);
+        let last = self.horizontal[self.horizontal.len() - 1];
+        if last <= 180.0 && horizontal > 180.0 {
+            horizontal = 360.0 - horizontal;
+        }
+        if last <= 90.0 && horizontal > 90.0 {
+            horizontal = 180.0 - horizontal;
+        }
+        let horizontal = horizontal.clamp(self.horizontal[0], last);
+        let (h0, h1, th) = between(&self.horizontal, horizontal).unwrap_or((0, 0, 0.0));
+        let at = |h: usize| {
+            let candelas = &self.candelas[h];
+            candelas[v0] + (candelas[v1] - candelas[v0]) * tv
+        };
+        at(h0) + (at(h1) - at(h0)) * th
+    }
+
+    /// The candelas summed over every direction, as a fraction of the peak:
+    /// the solid angle the fixture would fill if it were as bright as its
+    /// peak wherever it shines at all, for spreading a given power over it.
+    pub fn solid_angle(&self) -> Float {
+        const STEPS: usize = 180;
+        let step = PI / STEPS as Float;
+        let mut sum = 0.0;
+        for i in 0..STEPS {
+            let theta = (i as Float + 0.5) * step;
+            for j in 0..2 * STEPS {
+                let phi = (j as Float + 0.5) * step;
+                sum += self.candela(theta.to_degrees(), phi.to_degrees()) * theta.sin();
+            }
+        }
+        sum * step * step / self.peak
+    }
+}
+
+/// The two angles of those in increasing order that `angle` falls between,
+/// and how far along from the first it is, if it is within them.
+fn between(angles: &[Float], angle: Float) -> Option<(usize, usize, Float)> {
+    let last = angles.len() - 1;
+    if !(angles[0]..=angles[last]).contains(&angle) {
+        return None;
+    }
+    if last == 0 {
+        return Some((0, 0, 0.0));
+    }
+    let i = angles.partition_point(|&a| a <= angle).clamp(1, last);
+    let t = (angle - angles[i - 1]) / (angles[i] - angles[i - 1]);
+    Some((i - 1, i, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fixture shining straight down, fading out to the side
+    const PROFILE: &str = "IESNA:LM-63-2002
+[TEST] spot
+TILT=NONE
+1 1000 2 3 1 1 2 0 0 0
+0.5 1 100
+0 45 90
+0
+100, 50, 0
+";
+
+    fn error(source: &str) -> String {
+        match Profile::parse(source) {
+            Ok(_) => panic!("parsed {:?}", source),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let profile = Profile::parse(PROFILE).unwrap();
+        // Scaled by the multiplier and the ballast factor
+        assert_eq!(profile.peak(), 100.0);
+        assert_eq!(profile.candela(0.0, 0.0), 100.0);
+        assert_eq!(profile.candela(22.5, 123.0), 75.0);
+        assert_eq!(profile.candela(90.0, 0.0), 0.0);
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn skips_included_tilts() {
+        let source = PROFILE.replace("TILT=NONE", "TILT=INCLUDE\n1 2\n0 90\n1 1");
+        assert_eq!(Profile::parse(&source).unwrap().peak(), 100.0);
+    }
+
+    #[test]
+    fn refuses_truncated_profiles() {
+        let source = PROFILE.trim_end().trim_end_matches("50, 0");
+        assert!(error(source).contains("truncated"));
+        assert!(error("TILT=NONE\n1 1000 1").contains("truncated"));
+        assert!(error("IESNA:LM-63-2002\n").contains("TILT="));
+    }
+
+    #[test]
+    fn refuses_huge_angle_counts() {
+        let source = PROFILE.replace("1 1000 2 3 1", "1 1000 2 1e30 1");
+        assert!(error(&source).contains("vertical angles"));
+        let source = PROFILE.replace("1 1000 2 3 1", "1 1000 2 4096 4096");
+        assert!(error(&source).contains("candelas"));
+        let source = PROFILE.replace("TILT=NONE", "TILT=INCLUDE\n1 18446744073709551615");
+        assert!(error(&source).contains("tilt angles"));
+    }
+
+    #[test]
+    fn refuses_other_photometry() {
+        let source = PROFILE.replace("1 1000 2 3 1 1", "1 1000 2 3 1 2");
+        assert!(error(&source).contains("type C"));
+    }
+}
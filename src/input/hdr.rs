@@ -0,0 +1,225 @@
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::render::Image;
+use crate::vector::Float;
+
+fn invalid(message: &str) -> Error {
+    Error::parse("hdr", message)
+}
+
+/// The color an RGBE pixel stands for.
+fn color(rgbe: &[u8]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::BLACK;
+    }
+    // The mantissas are fractions of 256, on the shared exponent
+    let scale = Float::powi(2.0, rgbe[3] as i32 - 128 - 8);
+    Color::new(
+        (rgbe[0] as Float + 0.5) * scale,
+        (rgbe[1] as Float + 0.5) * scale,
+        (rgbe[2] as Float + 0.5) * scale,
+    )
+}
+
+/// Undoes the run-length encoding of one channel of a scanline into every
+/// fourth byte of `line`.
+fn decode_channel(data: &mut &[u8], line: &mut [u8], channel: usize) -> error::Result<()> {
+    let truncated = || invalid("truncated scanline");
+    let width = line.len() / 4;
+    let mut x = 0;
+    while x < width {
+        let (&count, rest) = data.split_first().ok_or_else(truncated)?;
+        *data = rest;
+        if count > 128 {
+            let run = count as usize - 128;
+            let (&value, rest) = data.split_first().ok_or_else(truncated)?;
+            *data = rest;
+            if x + run > width {
+                return Err(invalid("run past the end of a scanline"));
+            }
+            (x..x + run).for_each(|x| line[x * 4 + channel] = value);
+            x += run;
+        } else {
+            let count = count as usize;
+            if count == 0 || x + count > width || data.len() < count {
+                return Err(invalid("bad literal in a scanline"));
+            }
+            for (i, &value) in data[..count].iter().enumerate() {
+                line[(x + i) * 4 + channel] = value;
+            }
+            *data = &data[count..];
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a Radiance RGBE `.hdr` image with its rows from the top, flat or
+/// with run-length encoded scanlines.
+pub fn decode(bytes: &[u8]) -> error::Result<Image> {
+    if !bytes.starts_with(b"#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+
+    // Header lines up to an empty one, then the resolution
+    let mut data = bytes;
+    let mut line = || -> error::Result<&[u8]> {
+        let end = data
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| invalid("truncated header"))?;
+        let (line, rest) = data.split_at(end);
+        data = &rest[1..];
+        Ok(line)
+    };
+    loop {
+        let header = line()?;
+        if header.is_empty() {
+            break;
+        }
+        if header.starts_with(b"FORMAT=") && header != b"FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("only RGBE pixels are supported"));
+        }
+    }
+    let resolution = String::from_utf8_lossy(line()?).into_owned();
+    let fields: Vec<_> = resolution.split_whitespace().collect();
+    let (height, width) = match fields.as_slice() {
+        ["-Y", height, "+X", width] => (height.parse(), width.parse()),
+        _ => {
+            return Err(invalid(
+                "only images stored from the top left are supported",
+            ))
+        }
+    };
+    let (height, width): (usize, usize) = match (height, width) {
+        (Ok(height), Ok(width)) => (height, width),
+        _ => return Err(invalid("bad resolution")),
+    };
+
+    super::pixel_count("hdr", width, height)?;
+    // Run-length encoded scanlines take at least a run of each channel for
+    // every 127 pixels, and flat ones four bytes a pixel
+    let least = if (8..=0x7fff).contains(&width) {
+        4 + 8 * width.div_ceil(127)
+    } else {
+        4 * width
+    };
+    if data.len() / least < height {
+        return Err(invalid("truncated pixels"));
+    }
+
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![0u8; width * 4];
+    for y in 0..height {
+        let encoded = (8..=0x7fff).contains(&width)
+            && data.len() >= 4
+            && data[0] == 2
+            && data[1] == 2
+            && ((data[2] as usize) << 8 | data[3] as usize) == width;
+        if encoded {
+            data = &data[4..];
+            for channel in 0..4 {
+                decode_channel(&mut data, &mut scanline, channel)?;
+            }
+        } else {
+            if data.len() < width * 4 {
+                return Err(invalid("truncated pixels"));
+            }
+            scanline.copy_from_slice(&data[..width * 4]);
+            data = &data[width * 4..];
+        }
+        for (x, rgbe) in scanline.chunks(4).enumerate() {
+            image.pixels[y * width + x] = color(rgbe);
+        }
+    }
+    image.alpha.iter_mut().for_each(|a| *a = 1.0);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        match decode(bytes) {
+            Ok(_) => panic!("decoded {:?}", bytes),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    /// An encoded scanline of 8 pixels: a run of the first channel, literals
+    /// of the second, and runs split in two of the others.
+    const SCANLINE: [u8; 23] = [
+        2, 2, 0, 8, // Marker and width
+        0x88, 128, // Red
+        8, 0, 1, 2, 3, 4, 5, 6, 7, // Green
+        0x83, 0, 0x85, 64, // Blue
+        0x84, 129, 0x84, 0, // Exponent
+    ];
+
+    #[test]
+    fn decodes_flat_scanlines() {
+        let image = decode(&file("-Y 1 +X 2", &[128, 64, 0, 129, 255, 255, 255, 0])).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(
+            image.pixels[0],
+            Color::new(128.5 / 128.0, 64.5 / 128.0, 0.5 / 128.0)
+        );
+        assert_eq!(image.pixels[1], Color::BLACK);
+        assert_eq!(image.alpha, vec![1.0; 2]);
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_scanlines() {
+        let image = decode(&file("-Y 1 +X 8", &SCANLINE)).unwrap();
+        let scale = 2.0 / 256.0;
+        let expected = |x: usize| {
+            let blue = if x < 3 { 0.5 } else { 64.5 };
+            Color::new(128.5 * scale, (x as Float + 0.5) * scale, blue * scale)
+        };
+        for (x, pixel) in image.pixels[..4].iter().enumerate() {
+            assert_eq!(*pixel, expected(x));
+        }
+        assert!(image.pixels[4..].iter().all(|&pixel| pixel == Color::BLACK));
+    }
+
+    #[test]
+    fn refuses_truncated_runs() {
+        // The second scanline stops before the value of its last run
+        let mut pixels = SCANLINE.to_vec();
+        pixels.extend_from_slice(&SCANLINE[..SCANLINE.len() - 1]);
+        assert!(error(&file("-Y 2 +X 8", &pixels)).contains("truncated scanline"));
+        // Or doesn't come at all
+        assert!(error(&file("-Y 2 +X 8", &SCANLINE)).contains("truncated pixels"));
+        assert!(error(&file("-Y 2 +X 8", &[])).contains("truncated pixels"));
+    }
+
+    #[test]
+    fn refuses_runs_past_the_end_of_a_scanline() {
+        let mut pixels = SCANLINE.to_vec();
+        pixels[4] = 0x89;
+        assert!(error(&file("-Y 1 +X 8", &pixels)).contains("past the end"));
+        let mut pixels = SCANLINE.to_vec();
+        pixels[6] = 9;
+        assert!(error(&file("-Y 1 +X 8", &pixels)).contains("bad literal"));
+    }
+
+    #[test]
+    fn refuses_bad_headers() {
+        assert!(error(&file("-Y 0 +X 0", &[])).contains("empty"));
+        assert!(error(&file("-Y 4 +X 0", &[])).contains("empty"));
+        assert!(error(&file("-Y 1048576 +X 1048576", &[])).contains("too large"));
+        assert!(error(&file("+Y 1 +X 1", &[0; 4])).contains("top left"));
+        assert!(error(&file("-Y one +X 1", &[0; 4])).contains("resolution"));
+        assert!(error(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n").contains("RGBE"));
+        assert!(error(b"#?RADIANCE\n").contains("truncated header"));
+        assert!(error(b"P6\n1 1\n255\n").contains("not a Radiance"));
+    }
+}
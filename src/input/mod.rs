@@ -0,0 +1,90 @@
+//! Reading images from files, for environment maps and textures. Pixels
+//! come out as linear colors, whatever the file stores, unless they are
+//! read as data.
+
+use std::path::Path;
+
+use crate::error::{self, Error};
+use crate::files;
+use crate::output::Transfer;
+use crate::render::Image;
+
+mod hdr;
+mod png;
+mod ppm;
+mod zlib;
+
+/// The most pixels an image read or rendered may have, a 16k square, so
+/// that a header or the options can't ask for more memory than there is
+pub const MAX_PIXELS: usize = 1 << 28;
+
+/// The number of pixels of an image of `width` by `height` the header of a
+/// file of `format` gives, unless the image is empty or too large.
+fn pixel_count(format: &'static str, width: usize, height: usize) -> error::Result<usize> {
+    if width == 0 || height == 0 {
+        return Err(Error::parse(format, "the image is empty"));
+    }
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(pixels),
+        _ => Err(Error::parse(
+            format,
+            format!("{} by {} pixels is too large", width, height),
+        )),
+    }
+}
+
+/// Reads a Radiance `.hdr`, a PNG or a PPM image, going by the extension of
+/// the path. PNG and PPM colors are taken to be sRGB encoded.
+pub fn read<P: AsRef<Path>>(path: P) -> error::Result<Image> {
+    read_with(path.as_ref(), Transfer::Srgb)
+}
+
+/// Reads an image of data rather than colors, such as a normal map, whose
+/// PNG and PPM values are kept as they are.
+pub fn read_data<P: AsRef<Path>>(path: P) -> error::Result<Image> {
+    read_with(path.as_ref(), Transfer::Gamma(1.0))
+}
+
+/// The image file formats that can be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Hdr,
+    Png,
+    Ppm,
+}
+
+impl Format {
+    /// The format of files with the extension, in any case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "hdr" => Some(Format::Hdr),
+            "png" => Some(Format::Png),
+            "ppm" => Some(Format::Ppm),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the bytes of an image file of `format`, decoding the values of
+/// the integer formats with the given transfer function.
+pub fn decode(bytes: &[u8], format: Format, transfer: Transfer) -> error::Result<Image> {
+    match format {
+        Format::Hdr => hdr::decode(bytes),
+        Format::Png => png::decode(bytes, transfer),
+        Format::Ppm => ppm::decode(bytes, transfer),
+    }
+}
+
+/// Reads an image, decoding the values of the integer formats with the
+/// given transfer function.
+fn read_with(path: &Path, transfer: Transfer) -> error::Result<Image> {
+    let format = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(Format::from_extension);
+    // Only files that can be read are opened at all
+    match format {
+        Some(format) => decode(&files::read(path)?, format, transfer),
+        None => Err(Error::unsupported_format(path, ".hdr, .png or .ppm")),
+    }
+}
@@ -0,0 +1,344 @@
+use super::zlib;
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::output::Transfer;
+use crate::render::Image;
+use crate::vector::Float;
+
+fn invalid(message: &str) -> Error {
+    Error::parse("png", message)
+}
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undoes the filter given at the start of each row, returning the rows
+/// without their filter bytes.
+fn unfilter(
+    data: &[u8],
+    stride: usize,
+    bytes_per_pixel: usize,
+    height: usize,
+) -> error::Result<Vec<u8>> {
+    if (stride + 1)
+        .checked_mul(height)
+        .is_none_or(|size| data.len() < size)
+    {
+        return Err(invalid("truncated pixels"));
+    }
+    let mut rows = vec![0; stride * height];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let line = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (above, row) = rows.split_at_mut(y * stride);
+        let above = if y > 0 {
+            &above[(y - 1) * stride..]
+        } else {
+            &[][..]
+        };
+        let row = &mut row[..stride];
+        for x in 0..stride {
+            let a = if x >= bytes_per_pixel {
+                row[x - bytes_per_pixel]
+            } else {
+                0
+            };
+            let b = above.get(x).copied().unwrap_or(0);
+            let c = if x >= bytes_per_pixel {
+                above.get(x - bytes_per_pixel).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            row[x] = line[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid("bad filter type")),
+            });
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads a PNG of any color type and bit depth, without interlacing,
+/// decoding the colors to linear ones with the transfer function. The alpha
+/// channel, if any, ends up in the alpha of the image.
+pub fn decode(bytes: &[u8], transfer: Transfer) -> error::Result<Image> {
+    if !bytes.starts_with(SIGNATURE) {
+        return Err(invalid("not a PNG file"));
+    }
+    let mut data = &bytes[SIGNATURE.len()..];
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = vec![];
+    while data.len() >= 12 {
+        let length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if data.len() < 12 + length {
+            return Err(invalid("truncated chunk"));
+        }
+        let (kind, contents) = (&data[4..8], &data[8..8 + length]);
+        data = &data[12 + length..];
+        match kind {
+            b"IHDR" if length == 13 => header = Some(contents),
+            b"PLTE" => palette = contents,
+            b"IDAT" => compressed.extend_from_slice(contents),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let header = header.ok_or_else(|| invalid("missing header"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let (depth, color_type) = (header[8] as usize, header[9]);
+    if header[12] != 0 {
+        return Err(invalid("interlaced PNG files are not supported"));
+    }
+    let channels = match (color_type, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) | (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8 | 16) => 2,
+        (2, 8 | 16) => 3,
+        (6, 8 | 16) => 4,
+        _ => return Err(invalid("bad color type or bit depth")),
+    };
+
+    super::pixel_count("png", width, height)?;
+
+    let too_large = || invalid("image too large");
+    let stride = (width.checked_mul(channels * depth).ok_or_else(too_large)?).div_ceil(8);
+    let bytes_per_pixel = (channels * depth).div_ceil(8);
+    let size = (stride + 1).checked_mul(height).ok_or_else(too_large)?;
+    let rows = unfilter(
+        &zlib::decompress(&compressed, size)?,
+        stride,
+        bytes_per_pixel,
+        height,
+    )?;
+
+    // Each sample as a fraction of the largest value its depth can hold
+    let max = ((1u32 << depth) - 1) as Float;
+    let sample = |row: &[u8], i: usize| -> Float {
+        match depth {
+            16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]) as Float / max,
+            8 => row[i] as Float / max,
+            _ => {
+                let bit = i * depth;
+                ((row[bit / 8] >> (8 - depth - bit % 8)) as u32 & ((1 << depth) - 1)) as Float / max
+            }
+        }
+    };
+
+    let mut image = Image::new(width, height);
+    for y in 0..height {
+        let row = &rows[y * stride..(y + 1) * stride];
+        for x in 0..width {
+            let i = x * channels;
+            let (rgb, alpha) = match color_type {
+                0 | 4 => {
+                    let gray = sample(row, i);
+                    (
+                        [gray, gray, gray],
+                        (channels == 2).then(|| sample(row, i + 1)),
+                    )
+                }
+                3 => {
+                    let index = (sample(row, i) * max).round() as usize;
+                    let color = palette
+                        .get(3 * index..3 * index + 3)
+                        .ok_or_else(|| invalid("palette index out of range"))?;
+                    let channel = |c: u8| c as Float / 255.0;
+                    (
+                        [channel(color[0]), channel(color[1]), channel(color[2])],
+                        None,
+                    )
+                }
+                _ => (
+                    [sample(row, i), sample(row, i + 1), sample(row, i + 2)],
+                    (channels == 4).then(|| sample(row, i + 3)),
+                ),
+            };
+            image.pixels[y * width + x] = Color::decode(rgb, transfer);
+            image.alpha[y * width + x] = alpha.unwrap_or(1.0);
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::zlib::compress;
+
+    /// A PNG of the given header fields and palette around rows already
+    /// filtered. The decoder doesn't check the CRCs, so they are left zero.
+    fn png(size: (u32, u32), depth: u8, color_type: u8, palette: &[u8], rows: &[u8]) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        let mut chunk = |kind: &[u8], contents: &[u8]| {
+            bytes.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(contents);
+            bytes.extend_from_slice(&[0; 4]);
+        };
+        let mut header = vec![];
+        header.extend_from_slice(&size.0.to_be_bytes());
+        header.extend_from_slice(&size.1.to_be_bytes());
+        header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        chunk(b"IHDR", &header);
+        if !palette.is_empty() {
+            chunk(b"PLTE", palette);
+        }
+        chunk(b"IDAT", &compress(rows));
+        chunk(b"IEND", &[]);
+        bytes
+    }
+
+    /// Applies `filter` to each of the raw rows, the way an encoder would.
+    fn filter(raw: &[u8], stride: usize, bytes_per_pixel: usize, filter: u8) -> Vec<u8> {
+        let mut filtered = vec![];
+        for y in 0..raw.len() / stride {
+            filtered.push(filter);
+            for x in 0..stride {
+                let at = |y: usize, x: usize| raw[y * stride + x];
+                let a = if x >= bytes_per_pixel {
+                    at(y, x - bytes_per_pixel)
+                } else {
+                    0
+                };
+                let b = if y > 0 { at(y - 1, x) } else { 0 };
+                let c = if x >= bytes_per_pixel && y > 0 {
+                    at(y - 1, x - bytes_per_pixel)
+                } else {
+                    0
+                };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                filtered.push(at(y, x).wrapping_sub(predicted));
+            }
+        }
+        filtered
+    }
+
+    fn decode_linear(bytes: &[u8]) -> error::Result<Image> {
+        decode(bytes, Transfer::Gamma(1.0))
+    }
+
+    /// Why the decoder refuses the bytes.
+    fn refusal(bytes: &[u8]) -> String {
+        match decode_linear(bytes) {
+            Ok(_) => panic!("decoded an invalid PNG"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn rgb(image: &Image) -> Vec<[u8; 3]> {
+        let byte = |c: Float| (c * 255.0).round() as u8;
+        image
+            .pixels
+            .iter()
+            .map(|&p| <[Float; 3]>::from(p).map(byte))
+            .collect()
+    }
+
+    #[test]
+    fn undoes_each_filter() {
+        let raw: Vec<u8> = (0..4 * 3 * 3).map(|i: u32| (i * 37 % 256) as u8).collect();
+        for kind in 0..5 {
+            let image = decode_linear(&png((4, 3), 8, 2, &[], &filter(&raw, 12, 3, kind))).unwrap();
+            let expected: Vec<[u8; 3]> = raw.chunks(3).map(|p| [p[0], p[1], p[2]]).collect();
+            assert_eq!(rgb(&image), expected, "filter {}", kind);
+        }
+    }
+
+    #[test]
+    fn reads_alpha_and_sixteen_bits() {
+        let raw = [0x12, 0x34, 0xff, 0xff, 0x00, 0x00, 0x80, 0x00];
+        let image = decode_linear(&png((2, 1), 16, 4, &[], &filter(&raw, 8, 4, 4))).unwrap();
+        let gray = image.pixels.iter().map(|&p| <[Float; 3]>::from(p)[0]);
+        let gray: Vec<Float> = gray.collect();
+        assert!((gray[0] - 0x1234 as Float / 65535.0).abs() < 1e-6);
+        assert_eq!(image.alpha[0], 1.0);
+        assert_eq!(gray[1], 0.0);
+        assert!((image.alpha[1] - 0x8000 as Float / 65535.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reads_palettes_of_each_depth() {
+        let palette: Vec<u8> = (0..16u8).flat_map(|i| [i, 16 * i, 255 - i]).collect();
+        for depth in [1u8, 2, 4, 8].iter().copied() {
+            // Five pixels counting up through the indices the depth can hold
+            let indices: Vec<u8> = (0..5).map(|i| i % (1 << depth.min(4))).collect();
+            let mut row = vec![0u8; (5 * depth as usize).div_ceil(8)];
+            for (i, &index) in indices.iter().enumerate() {
+                let bit = i * depth as usize;
+                row[bit / 8] |= index << (8 - depth as usize - bit % 8);
+            }
+            let image = decode_linear(&png(
+                (5, 1),
+                depth,
+                3,
+                &palette,
+                &filter(&row, row.len(), 1, 0),
+            ))
+            .unwrap();
+            let expected: Vec<[u8; 3]> = indices.iter().map(|&i| [i, 16 * i, 255 - i]).collect();
+            assert_eq!(rgb(&image), expected, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn reads_gray_below_a_byte() {
+        // Three pixels of 0, 1 and 3 in two bits, then padding
+        let image = decode_linear(&png((3, 1), 2, 0, &[], &[0, 0b0001_1100])).unwrap();
+        assert_eq!(rgb(&image), vec![[0; 3], [85; 3], [255; 3]]);
+        let image = decode_linear(&png((9, 1), 1, 0, &[], &[0, 0b1010_0000, 0b1000_0000])).unwrap();
+        let expected: Vec<u8> = vec![255, 0, 255, 0, 0, 0, 0, 0, 255];
+        assert_eq!(
+            rgb(&image).iter().map(|p| p[0]).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn refuses_palette_indices_out_of_range() {
+        let e = refusal(&png((1, 1), 8, 3, &[1, 2, 3], &[0, 1]));
+        assert_eq!(e, "png: palette index out of range");
+    }
+
+    #[test]
+    fn refuses_empty_and_oversized_images() {
+        assert_eq!(
+            refusal(&png((0, 0), 8, 2, &[], &[])),
+            "png: the image is empty"
+        );
+        assert!(decode_linear(&png((0, 5), 8, 2, &[], &[])).is_err());
+        assert!(decode_linear(&png((u32::MAX, u32::MAX), 16, 6, &[], &[0])).is_err());
+    }
+
+    #[test]
+    fn refuses_more_data_than_the_image_holds() {
+        // A thousand rows of zeros for an image of one
+        let rows = vec![0; 1000 * 4];
+        assert!(decode_linear(&png((3, 1), 8, 0, &[], &rows)).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::output::Transfer;
+use crate::render::Image;
+use crate::vector::Float;
+
+fn invalid(message: &str) -> Error {
+    Error::parse("ppm", message)
+}
+
+/// Splits off the next whitespace separated token of the header, skipping
+/// comments.
+fn token<'a>(data: &mut &'a [u8]) -> error::Result<&'a [u8]> {
+    loop {
+        let start = data
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .ok_or_else(|| invalid("truncated header"))?;
+        *data = &data[start..];
+        if data[0] == b'#' {
+            let end = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+            *data = &data[end..];
+            continue;
+        }
+        let end = data
+            .iter()
+            .position(|b| b.is_ascii_whitespace())
+            .unwrap_or(data.len());
+        let (token, rest) = data.split_at(end);
+        *data = rest;
+        return Ok(token);
+    }
+}
+
+fn number(data: &mut &[u8]) -> error::Result<usize> {
+    std::str::from_utf8(token(data)?)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid("bad number"))
+}
+
+/// Reads a binary or plain text PPM of 8 or 16 bits per channel, decoding
+/// the colors to linear ones with the transfer function.
+pub fn decode(bytes: &[u8], transfer: Transfer) -> error::Result<Image> {
+    let mut data = bytes;
+    let magic = token(&mut data)?;
+    if magic != b"P6" && magic != b"P3" {
+        return Err(invalid("not a PPM file"));
+    }
+    let (width, height, max) = (number(&mut data)?, number(&mut data)?, number(&mut data)?);
+    if max == 0 || max > 65535 {
+        return Err(invalid("bad maximum value"));
+    }
+
+    let count = super::pixel_count("ppm", width, height)? * 3;
+    let samples: Vec<usize> = if magic == b"P6" {
+        // A single whitespace byte separates the header from the samples
+        data = data.get(1..).unwrap_or(&[]);
+        let bytes = if max < 256 { 1 } else { 2 };
+        if data.len() < count * bytes {
+            return Err(invalid("truncated pixels"));
+        }
+        data[..count * bytes]
+            .chunks(bytes)
+            .map(|b| b.iter().fold(0, |v, &b| v << 8 | b as usize))
+            .collect()
+    } else {
+        (0..count)
+            .map(|_| number(&mut data))
+            .collect::<error::Result<_>>()?
+    };
+
+    let mut image = Image::new(width, height);
+    let value = |v: usize| v.min(max) as Float / max as Float;
+    for (pixel, rgb) in image.pixels.iter_mut().zip(samples.chunks(3)) {
+        *pixel = Color::decode([value(rgb[0]), value(rgb[1]), value(rgb[2])], transfer);
+    }
+    image.alpha.iter_mut().for_each(|a| *a = 1.0);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINEAR: Transfer = Transfer::Gamma(1.0);
+
+    fn error(bytes: &[u8]) -> String {
+        match decode(bytes, LINEAR) {
+            Ok(_) => panic!("decoded {:?}", bytes),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn decodes_binary_pixels() {
+        let image = decode(b"P6\n2 1\n255\n\xff\x00\x33\x00\x00\x00", LINEAR).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, vec![Color::new(1.0, 0.0, 0.2), Color::BLACK]);
+        assert_eq!(image.alpha, vec![1.0; 2]);
+
+        // Two bytes a sample past 255, most significant first
+        let image = decode(b"P6 1 1 1000 \x01\xf4\x03\xe8\x00\x00", LINEAR).unwrap();
+        assert_eq!(image.pixels, vec![Color::new(0.5, 1.0, 0.0)]);
+    }
+
+    #[test]
+    fn decodes_plain_text_pixels() {
+        let source = b"P3\n# A comment\n2 1 # after the size\n4\n4 0 2\n1 2 3\n";
+        let image = decode(source, LINEAR).unwrap();
+        assert_eq!(
+            image.pixels,
+            vec![Color::new(1.0, 0.0, 0.5), Color::new(0.25, 0.5, 0.75)]
+        );
+    }
+
+    #[test]
+    fn decodes_srgb() {
+        let image = decode(b"P3 1 1 255 255 0 188", Transfer::Srgb).unwrap();
+        let Color { r, g, b } = image.pixels[0];
+        assert!((r - 1.0).abs() < 1e-6 && g == 0.0 && (b - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn refuses_truncated_pixels() {
+        assert!(error(b"P6\n2 1\n255\n\xff\x00\x33\x00\x00").contains("truncated pixels"));
+        assert!(error(b"P6\n1 1\n65535\n\x00\x00").contains("truncated pixels"));
+        assert!(error(b"P3\n1 1\n255\n1 2").contains("truncated header"));
+        assert!(error(b"P6\n1 1\n").contains("truncated header"));
+    }
+
+    #[test]
+    fn refuses_bad_headers() {
+        assert!(error(b"P6\n0 0\n255\n").contains("empty"));
+        assert!(error(b"P6\n1048576 1048576\n255\n").contains("too large"));
+        assert!(error(b"P6\n1 1\n0\n\x00\x00\x00").contains("maximum value"));
+        assert!(error(b"P6\n1 1\n65536\n\x00\x00\x00").contains("maximum value"));
+        assert!(error(b"P6\n-1 1\n255\n\x00\x00\x00").contains("bad number"));
+        assert!(error(b"P5\n1 1\n255\n\x00").contains("not a PPM"));
+    }
+}
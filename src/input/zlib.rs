@@ -0,0 +1,291 @@
+//! zlib decompression, with the fixed and dynamic Huffman codes and the
+//! stored blocks of deflate, for reading PNG files.
+
+use crate::error::{self, Error};
+use crate::output::zlib::{DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+
+fn invalid(message: &str) -> Error {
+    Error::parse("zlib", message)
+}
+
+/// Order the code lengths of the code length alphabet are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads bits least significant first, the way deflate packs them.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> error::Result<u32> {
+        while self.count < count {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or_else(|| invalid("truncated compressed data"))?;
+            self.buffer |= (byte as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let bits = (self.buffer & ((1 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(bits)
+    }
+
+    /// Skips to the next byte boundary, as stored blocks start at one.
+    fn align(&mut self) {
+        let extra = self.count % 8;
+        self.buffer >>= extra;
+        self.count -= extra;
+    }
+}
+
+/// A canonical Huffman code, decoded a bit at a time by counting the codes
+/// of each length.
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; 16],
+    /// Symbols in order of their codes
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> error::Result<u16> {
+        // The first code of each length, and the index of its symbol
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+/// The literal/length and distance codes of a dynamic block.
+fn dynamic_codes(reader: &mut BitReader) -> error::Result<(Huffman, Huffman)> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+    let mut lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeated code length without a previous one"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend((0..repeat).map(|_| length));
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("too many code lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// The fixed literal/length and distance codes.
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+/// Decompresses a zlib stream, which is taken to be bad if it holds more
+/// than `limit` bytes.
+pub fn decompress(data: &[u8], limit: usize) -> error::Result<Vec<u8>> {
+    if data.len() < 2
+        || data[0] & 0x0f != 8
+        || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31)
+    {
+        return Err(invalid("not a zlib stream"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid("preset zlib dictionaries are not supported"));
+    }
+    let mut reader = BitReader {
+        bytes: &data[2..],
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let too_much = || invalid("more data than expected");
+    let mut out = vec![];
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = reader.bits(16)? as usize;
+                if reader.bits(16)? as usize != !length & 0xffff {
+                    return Err(invalid("bad stored block length"));
+                }
+                if out.len() + length > limit {
+                    return Err(too_much());
+                }
+                for _ in 0..length {
+                    out.push(reader.bits(8)? as u8);
+                }
+            }
+            kind @ (1 | 2) => {
+                let (literals, distances) = if kind == 1 {
+                    fixed_codes()
+                } else {
+                    dynamic_codes(&mut reader)?
+                };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    if symbol < 256 {
+                        if out.len() == limit {
+                            return Err(too_much());
+                        }
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let i = symbol - 257;
+                    if i >= LENGTH_BASE.len() {
+                        return Err(invalid("bad length code"));
+                    }
+                    let length =
+                        LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i] as u32)? as usize;
+                    let i = distances.decode(&mut reader)? as usize;
+                    if i >= DISTANCE_BASE.len() {
+                        return Err(invalid("bad distance code"));
+                    }
+                    let distance =
+                        DISTANCE_BASE[i] as usize + reader.bits(DISTANCE_EXTRA[i] as u32)? as usize;
+                    if distance > out.len() {
+                        return Err(invalid("distance reaches before the start"));
+                    }
+                    if out.len() + length > limit {
+                        return Err(too_much());
+                    }
+                    // Matches may overlap the bytes they produce
+                    let start = out.len() - distance;
+                    for k in 0..length {
+                        out.push(out[start + k]);
+                    }
+                }
+            }
+            _ => return Err(invalid("bad block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reads_stored_blocks() {
+        let data = hex("7801011900e6ff68656c6c6f2068656c6c6f2068656c6c6f2c2073746f726564798c095a");
+        assert_eq!(
+            decompress(&data, 100).unwrap(),
+            b"hello hello hello, stored"
+        );
+    }
+
+    #[test]
+    fn reads_fixed_blocks() {
+        let data = hex("78014b4c4a4e44420a699915a92900576707ef");
+        assert_eq!(decompress(&data, 100).unwrap(), b"abcabcabcabcabc fixed");
+    }
+
+    #[test]
+    fn reads_dynamic_blocks() {
+        // 300 bytes drawn from a few letters, as zlib compresses them
+        let data = hex(concat!(
+            "78da2d9081158040084257613540f75f21f0aadecbe4736832a8bb4d523b1438",
+            "92d04a694ec485484fb9e15d555ccb5cb1d155d2f0c97da4274731e1731a9b24",
+            "ff8734367921e50d5c5fdb6126497a9fbeb1aa2151690eace97105e2dbd8079d",
+            "e9adf1e2535ea78cba534703f142660fdd23f30ac15ba9bb9cf9fd9166ec9bf6",
+            "03d0c36ee9",
+        ));
+        assert_eq!((data[2] >> 1) & 3, 2, "not a dynamic block");
+        let mut x: u32 = 1;
+        let expected: Vec<u8> = (0..300)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12345) & 0x7fff_ffff;
+                b"aaaaaaaabbbbccd e"[(x >> 16) as usize % 17]
+            })
+            .collect();
+        assert_eq!(decompress(&data, 300).unwrap(), expected);
+    }
+
+    #[test]
+    fn reads_what_the_encoder_writes() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * i / 7 % 251) as u8).collect();
+        let compressed = crate::output::zlib::compress(&data);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        let data = crate::output::zlib::compress(&[0; 1000]);
+        let e = decompress(&data, 999).unwrap_err();
+        assert_eq!(e.to_string(), "zlib: more data than expected");
+        let stored =
+            hex("7801011900e6ff68656c6c6f2068656c6c6f2068656c6c6f2c2073746f726564798c095a");
+        assert!(decompress(&stored, 24).is_err());
+    }
+}
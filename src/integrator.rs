@@ -0,0 +1,819 @@
+//! Ways of working out the light arriving along a camera ray.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::background;
+use crate::camera::Camera;
+use crate::light;
+use crate::material::{Absorption, Glass};
+use crate::object::{surface_material, HitRecord, Hittable, Object};
+use crate::photon;
+use crate::ray::Ray;
+use crate::render::{direct_light, direct_light_lobes, occluded, occluded_along, RenderOptions};
+use crate::sampler::{self, PixelSample};
+use crate::scene::{self, Scene};
+use crate::spectrum;
+use crate::stats;
+use crate::vector::{consts::PI, Float, Vec3f};
+use crate::volume;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Integrator {
+    /// Light straight from the lights, without shadows, as in the tutorial
+    #[default]
+    Direct,
+    /// Monte Carlo path tracing: direct light with shadows at every hit, and
+    /// light bouncing between diffuse surfaces
+    Path,
+    /// The path tracer following a single wavelength of light with each
+    /// sample, so that glass bends each color its own way
+    Spectral,
+    /// How open the surroundings of each hit are, in gray, white where
+    /// nothing is nearby
+    AmbientOcclusion,
+}
+
+/// Views of the scene shown instead of its light, for finding out what is
+/// wrong with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// The normal of the surface hit as a color, its coordinates from -1 to 1
+    /// mapped to 0 to 1, and black where nothing is hit
+    Normals,
+    /// The image with the outlines of the bounds of the objects drawn over
+    /// it, and of the nodes of the acceleration structure
+    Bounds,
+}
+
+/// Settings of the rays that look for surfaces near a hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    /// Rays per sample of the pixel
+    pub samples: usize,
+    /// Surfaces further away than this don't occlude
+    pub distance: Float,
+    /// Darkens the diffuse light of the direct integrator by the occlusion
+    pub diffuse: bool,
+}
+
+/// How points are lifted off surfaces before tracing rays from them, so that
+/// the rays don't hit the surface they start on, which shows as specks of
+/// shadow.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bias {
+    /// How far points are lifted
+    pub epsilon: Float,
+    /// Lifts points by `epsilon` for every unit of distance the ray hitting
+    /// them came from, past the first, as the error of the hit grows with it
+    pub scaled: bool,
+    /// Lifts points along the normal of the surface itself rather than the
+    /// one bent by normal and bump maps, which may point into it
+    pub geometric: bool,
+}
+
+impl Default for Bias {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-3,
+            scaled: false,
+            geometric: false,
+        }
+    }
+}
+
+impl Bias {
+    /// How far the point of a hit `distance` along its ray is lifted.
+    pub fn distance(&self, distance: Float) -> Float {
+        if self.scaled {
+            self.epsilon * distance.max(1.0)
+        } else {
+            self.epsilon
+        }
+    }
+
+    /// The point of the hit lifted off the surface, to the side the ray
+    /// along `dir` came from.
+    pub fn lift(&self, hit: &HitRecord, dir: &Vec3f) -> Vec3f {
+        let n = if self.geometric {
+            hit.geometric_normal
+        } else {
+            hit.normal
+        };
+        let n = if n.dot(dir) > 0.0 { -n } else { n };
+        hit.point + n * self.distance(hit.distance)
+    }
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            samples: 8,
+            distance: 1.0,
+            diffuse: false,
+        }
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "direct" => Ok(Integrator::Direct),
+            "path" => Ok(Integrator::Path),
+            "spectral" => Ok(Integrator::Spectral),
+            "ao" => Ok(Integrator::AmbientOcclusion),
+            _ => Err(format!(
+                "unknown integrator `{}`, expected direct, path, spectral or ao",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Integrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Integrator::Direct => "direct",
+            Integrator::Path => "path",
+            Integrator::Spectral => "spectral",
+            Integrator::AmbientOcclusion => "ao",
+        })
+    }
+}
+
+impl FromStr for DebugView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normals" => Ok(DebugView::Normals),
+            "bounds" => Ok(DebugView::Bounds),
+            _ => Err(format!(
+                "unknown debug view `{}`, expected normals or bounds",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DebugView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DebugView::Normals => "normals",
+            DebugView::Bounds => "bounds",
+        })
+    }
+}
+
+/// A direction in the hemisphere around `n`, more likely the closer it is
+/// to `n`, in proportion to the cosine of the angle between them.
+fn cosine_hemisphere(n: &Vec3f, (u, v): (Float, Float)) -> Vec3f {
+    let (tangent, bitangent) = n.perpendiculars();
+    let (r, phi) = (u.sqrt(), 2.0 * PI * v);
+    (tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + *n * (1.0 - u).max(0.0).sqrt())
+        .normalize()
+}
+
+/// The light an object gives off towards `dir`, from the side its normal
+/// `n` faces, if it shines.
+fn emission(object: &dyn Object, n: &Vec3f, dir: &Vec3f) -> Vec3f {
+    let material = object.material();
+    if n.dot(dir) < 0.0 && light::shines(material.light_group.as_deref()) {
+        material.emission
+    } else {
+        Vec3f::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// The density of a point picked evenly over the area of a light, at the
+/// squared distance `distance2` and tilted by `cos_light` from facing the
+/// other end, as seen from there: per solid angle.
+fn light_pdf(distance2: Float, cos_light: Float, area: Float) -> Float {
+    distance2 / (cos_light * area)
+}
+
+/// The light arriving at `point`, facing `n`, from the emissive objects of
+/// the scene, each sampled at one point of its surface in the given
+/// dimension, and weighted as a diffuse surface takes it in: multiplied by
+/// the reflected color, it is the light the surface scatters to the eye.
+///
+/// Paths (`traced`) leave out the points other objects hide, and weight
+/// every sample against the chance of a bounce finding the same point, with
+/// the balance heuristic, as they also pick up the emission their bounces
+/// hit. The points on the emitters are lifted off them by `lift`.
+fn emitted_light(
+    scene: &Scene,
+    n: &Vec3f,
+    point: &Vec3f,
+    sample: &PixelSample,
+    dimension: usize,
+    traced: bool,
+    lift: Float,
+) -> Vec3f {
+    let count = scene.emitters().count();
+    let mut light = Vec3f::new(0.0, 0.0, 0.0);
+    for (i, emitter) in scene.emitters().enumerate() {
+        if !light::shines(emitter.material().light_group.as_deref()) {
+            continue;
+        }
+        let uv = sample.split(count, i).get_2d(dimension);
+        let (target, light_n) = match emitter.sample_surface(uv) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let to_light = target - *point;
+        let distance2 = to_light.norm();
+        let light_dir = to_light.normalize();
+        let (cos, cos_light) = (n.dot(&light_dir), -light_n.dot(&light_dir));
+        if distance2 <= 0.0 || cos <= 0.0 || cos_light <= 0.0 {
+            continue;
+        }
+        // The emitter itself shouldn't count as being in the way
+        if traced && occluded(scene, point, &(target + light_n * lift)) {
+            continue;
+        }
+
+        let pdf = light_pdf(distance2, cos_light, emitter.area().unwrap());
+        let weight = if traced { pdf / (pdf + cos / PI) } else { 1.0 };
+        light += emitter.material().emission * (cos / PI / pdf * weight);
+    }
+    light
+}
+
+/// The light arriving at `point`, facing `n`, from the background, sampled
+/// in one direction picked in the given dimension where it is brightest,
+/// for backgrounds that are sampled, or through one of the portals of the
+/// scene if it has any, whatever the background. Like `emitted_light`, it is weighted
+/// against bounces finding the same direction and as a diffuse surface
+/// takes it in.
+fn environment_light(
+    scene: &Scene,
+    n: &Vec3f,
+    point: &Vec3f,
+    sample: &PixelSample,
+    dimension: usize,
+) -> Vec3f {
+    let black = Vec3f::new(0.0, 0.0, 0.0);
+    let uv = sample.get_2d(dimension);
+    let sampled = if scene.portals.is_empty() {
+        scene.background.sample(uv)
+    } else {
+        background::sample_portals(&scene.portals, point, uv)
+    };
+    let (dir, pdf) = match sampled {
+        Some(sample) => sample,
+        None => return black,
+    };
+    let cos = n.dot(&dir);
+    if cos <= 0.0 || pdf <= 0.0 || occluded_along(scene, point, &dir, Float::INFINITY) {
+        return black;
+    }
+    let weight = pdf / (pdf + cos / PI);
+    background_color(scene, &dir) * (cos / PI / pdf * weight)
+}
+
+/// The color of the background along `dir`, if it shines.
+fn background_color(scene: &Scene, dir: &Vec3f) -> Vec3f {
+    if light::shines(scene.background_group.as_deref()) {
+        scene.background.color(dir)
+    } else {
+        Vec3f::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// The density `environment_light` picks the unit direction `dir` from
+/// `point` with, if it samples the background.
+fn background_pdf(scene: &Scene, point: &Vec3f, dir: &Vec3f) -> Option<Float> {
+    if scene.portals.is_empty() {
+        scene.background.pdf(dir)
+    } else {
+        Some(background::portals_pdf(&scene.portals, point, dir))
+    }
+}
+
+/// Follows a path from the camera ray and what it hit, adding up the light
+/// from the lights and emissive objects at each bounce and the background
+/// where it leaves the scene, which is sampled like a light too where it
+/// can be. Bounces are diffuse, in cosine-weighted directions, so that the
+/// reflected color is all that scales the light from further along, or off
+/// the microfacets of metallic-roughness surfaces, for glossy reflections,
+/// or off or through glass, by how much of the light goes each way.
+///
+/// From `roulette_depth` bounces on, paths carry on with a chance going by
+/// how much light they still carry, and carry that much more when they do,
+/// so that dim paths end early without darkening the image. Bounces leave
+/// from the hits lifted off the surface by `bias`.
+///
+/// With an irradiance cache in the scene, the light bouncing off the first
+/// diffuse surface is looked up in it instead where it covers the hit.
+pub fn path(
+    scene: &Scene,
+    dir: &Vec3f,
+    hit: Option<HitRecord>,
+    sample: &PixelSample,
+    max_depth: usize,
+    roulette_depth: usize,
+    bias: &Bias,
+) -> Vec3f {
+    path_from(
+        scene,
+        dir,
+        hit,
+        sample,
+        (max_depth, roulette_depth),
+        bias,
+        None,
+    )
+}
+
+/// The path as `path` follows it, but having left along `dir` from the
+/// point and with the direction density of `bounce`, if it did, so that
+/// emitters and backgrounds it hits first are weighted against their
+/// sampling from there.
+fn path_from(
+    scene: &Scene,
+    dir: &Vec3f,
+    hit: Option<HitRecord>,
+    sample: &PixelSample,
+    (max_depth, roulette_depth): (usize, usize),
+    bias: &Bias,
+    mut bounce: Option<(Vec3f, Float)>,
+) -> Vec3f {
+    let mut radiance = Vec3f::new(0.0, 0.0, 0.0);
+    let mut throughput = Vec3f::new(1.0, 1.0, 1.0);
+    let (mut dir, mut hit) = (*dir, hit);
+    // Whether the path came from the camera, and the cache may stand in
+    // for its first diffuse bounce
+    let cached = bounce.is_none();
+    // How far along the path its hits are, for how wide the pixel is there
+    let mut travelled = 0.0;
+    // Whether the path scattered off a diffuse surface and has only been
+    // reflected by shiny ones since, the way the photons of caustics arrive
+    let mut caustic = false;
+    // How the glass the path is going through, if it is, dims the light on
+    // the way to the next hit
+    let mut inside: Option<Absorption> = None;
+    for depth in 0..max_depth.max(1) {
+        let surface = match hit {
+            Some(hit) => hit,
+            None => {
+                // Sampled backgrounds were already weighted in from the last
+                // bounce
+                let weight = match bounce {
+                    Some((from, pdf)) => background_pdf(scene, &from, &dir)
+                        .map_or(1.0, |background_pdf| pdf / (pdf + background_pdf)),
+                    None => 1.0,
+                };
+                return radiance + throughput * background_color(scene, &dir) * weight;
+            }
+        };
+        if let Some(absorption) = inside {
+            throughput *= absorption.transmittance(surface.distance);
+        }
+        let (object, n, point) = (surface.object, surface.normal, surface.point);
+        // Emitters sampled as lights were already weighted in from the last
+        // bounce
+        let emitted = emission(object, &n, &dir);
+        let weight = match bounce {
+            Some((from, pdf)) if scene::is_emitter(object) => {
+                let (distance2, cos_light) = ((point - from).norm(), -n.dot(&dir));
+                pdf / (pdf + light_pdf(distance2, cos_light, object.area().unwrap()))
+            }
+            _ => 1.0,
+        };
+        radiance += throughput * emitted * weight;
+
+        // Light the side of the surface the ray came from
+        travelled += surface.distance;
+        let material = surface_material(&surface, sample.width(travelled));
+        let n = if n.dot(&dir) > 0.0 { -n } else { n };
+        let (point, lift) = (bias.lift(&surface, &dir), bias.distance(surface.distance));
+        let reflected = material.diffuse_reflectance();
+        if let Some(caustics) = &scene.caustics {
+            radiance += throughput * reflected * caustics.irradiance(&surface.point, &n);
+        }
+        let emitted = emitted_light(
+            scene,
+            &n,
+            &point,
+            sample,
+            sampler::bounce(depth, sampler::EMITTER),
+            true,
+            lift,
+        );
+        let emitted = emitted
+            + environment_light(
+                scene,
+                &n,
+                &point,
+                sample,
+                sampler::bounce(depth, sampler::ENVIRONMENT),
+            );
+        let (diffuse, specular) = direct_light_lobes(
+            scene,
+            &material,
+            &n,
+            (&point, lift),
+            &dir,
+            Some((sample, sampler::bounce(depth, sampler::LIGHT))),
+            1.0,
+        );
+        // The light the photons left on the diffuse surface before already
+        // came off the highlights of the surfaces they started out from
+        let shiny = photon::shiny(&material).is_some();
+        let photons = caustic
+            && shiny
+            && scene
+                .caustics
+                .as_ref()
+                .is_some_and(|map| map.is_source(surface.handle));
+        let specular = if photons {
+            Vec3f::new(0.0, 0.0, 0.0)
+        } else {
+            specular
+        };
+        radiance += throughput * (diffuse + specular + reflected * emitted);
+
+        // Metallic-roughness surfaces reflect some paths off their
+        // microfacets instead, which nothing was sampled for along the way,
+        // so that they stay unweighted
+        let specular = material.metallic_roughness.map(|pbr| {
+            (
+                pbr,
+                pbr.specular_chance(&material.diffuse_color, -n.dot(&dir)),
+            )
+        });
+        let chance = specular.map_or(0.0, |(_, chance)| chance);
+        let uv = sample.get_2d(sampler::bounce(depth, sampler::DIRECTION));
+        let mut from = point;
+        let (next, weight, pdf) = match (material.glass, specular) {
+            // Glass reflects or lets through the path by how much light goes
+            // each way, which the photons of caustics don't follow
+            (Some(glass), _) => {
+                let glass = glass.at_wavelength(sample.wavelength);
+                let (reflected, refracted, reflectance) = glass.split(&dir, &surface.normal);
+                let entering = surface.normal.dot(&dir) < 0.0;
+                caustic = false;
+                match refracted {
+                    Some(refracted) if uv.0 >= reflectance => {
+                        from = bias.lift(&surface, &-refracted);
+                        inside = if entering { glass.absorption } else { None };
+                        (refracted, Vec3f::new(1.0, 1.0, 1.0), None)
+                    }
+                    _ => {
+                        inside = if entering { None } else { glass.absorption };
+                        (reflected, Vec3f::new(1.0, 1.0, 1.0), None)
+                    }
+                }
+            }
+            (None, Some((pbr, chance)))
+                if sample.get_2d(sampler::bounce(depth, sampler::LOBE)).0 < chance =>
+            {
+                caustic &= shiny;
+                match pbr.sample_specular(&material.diffuse_color, &n, &-dir, uv) {
+                    Some((next, weight)) => (next, weight * (1.0 / chance), None),
+                    None => break,
+                }
+            }
+            _ => {
+                let weight = reflected * (1.0 / (1.0 - chance));
+                // The cache holds the light of every light group together
+                let indirect = match &scene.irradiance_cache {
+                    Some(cache) if cached && depth == 0 && max_depth > 1 && !light::grouped() => {
+                        cache.lookup(&surface.point, &n)
+                    }
+                    _ => None,
+                };
+                if let Some(indirect) = indirect {
+                    radiance += throughput * weight * indirect;
+                    break;
+                }
+                caustic = true;
+                let next = cosine_hemisphere(&n, uv);
+                let pdf = n.dot(&next).max(1e-6) / PI;
+                (next, weight, Some(pdf))
+            }
+        };
+
+        throughput *= weight;
+        let brightest = throughput.0.max(throughput.1).max(throughput.2);
+        if brightest <= 0.0 {
+            break;
+        }
+        if depth + 1 >= roulette_depth {
+            let survival = brightest.min(1.0);
+            if sample.get_2d(sampler::bounce(depth, sampler::ROULETTE)).0 >= survival {
+                break;
+            }
+            throughput *= 1.0 / survival;
+        }
+        dir = next;
+        bounce = pdf.map(|pdf| (from, pdf));
+        stats::count(|stats| stats.secondary_rays += 1);
+        hit = scene.hit(&Ray::new(from, dir));
+    }
+    radiance
+}
+
+/// The light arriving at `point` of a surface facing `n` from other
+/// surfaces and the background, averaged over `samples` paths leaving in
+/// cosine-weighted directions, as the path tracer would find it bouncing
+/// diffusely off the surface, along with the harmonic mean of how far the
+/// paths went before hitting anything, infinite if none did.
+pub(crate) fn indirect_light(
+    scene: &Scene,
+    (point, n): (&Vec3f, &Vec3f),
+    sample: &PixelSample,
+    samples: usize,
+    options: &RenderOptions,
+) -> (Vec3f, Float) {
+    let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+    let mut inverse_distances = 0.0;
+    for i in 0..samples {
+        let sample = sample.split(samples, i);
+        let next = cosine_hemisphere(n, sample.get_2d(sampler::PIXEL));
+        let pdf = n.dot(&next).max(1e-6) / PI;
+        stats::count(|stats| stats.secondary_rays += 1);
+        let hit = scene.hit(&Ray::new(*point, next));
+        if let Some(hit) = &hit {
+            inverse_distances += 1.0 / hit.distance.max(1e-6);
+        }
+        let light = path_from(
+            scene,
+            &next,
+            hit,
+            &sample,
+            (
+                options.max_depth.saturating_sub(1),
+                options.roulette_depth.saturating_sub(1),
+            ),
+            &options.bias,
+            Some((*point, pdf)),
+        );
+        sum += clamp(light, options.clamp);
+    }
+    (
+        sum * (1.0 / samples as Float),
+        samples as Float / inverse_distances,
+    )
+}
+
+/// The fraction of cosine-weighted rays from `point`, facing `n`, that get
+/// further than the occlusion distance without hitting anything.
+pub fn occlusion(
+    scene: &Scene,
+    n: &Vec3f,
+    point: &Vec3f,
+    sample: &PixelSample,
+    settings: &AmbientOcclusion,
+) -> Float {
+    let samples = settings.samples.max(1);
+    let open = (0..samples)
+        .filter(|&i| {
+            let uv = sample
+                .split(samples, i)
+                .get_2d(sampler::bounce(0, sampler::OCCLUSION));
+            let target = *point + cosine_hemisphere(n, uv) * settings.distance;
+            !occluded(scene, point, &target)
+        })
+        .count();
+    open as Float / samples as Float
+}
+
+/// The color scaled down, if need be, so no channel is brighter than `max`.
+fn clamp(color: Vec3f, max: Option<Float>) -> Vec3f {
+    let brightest = color.max_component();
+    match max {
+        Some(max) if brightest > max => color * (max / brightest),
+        _ => color,
+    }
+}
+
+/// The light arriving along a camera ray from `orig`, going by what it hit,
+/// with the integrator of the options, through the fog and volumes of the
+/// scene. The normals are shown instead if the options ask for them.
+pub fn radiance(
+    scene: &Scene,
+    orig: &Vec3f,
+    dir: &Vec3f,
+    hit: Option<HitRecord>,
+    sample: &PixelSample,
+    options: &RenderOptions,
+) -> Vec3f {
+    if options.debug == Some(DebugView::Normals) {
+        return normal_color(hit);
+    }
+    let color = surface_radiance(scene, dir, hit, sample, options, 0);
+    if options.integrator == Integrator::AmbientOcclusion {
+        return color;
+    }
+    let distance = hit.map(|hit| hit.point.distance(orig));
+    let jitter = sample.get_2d(sampler::bounce(0, sampler::MEDIUM)).0;
+    volume::along_ray(scene, orig, dir, distance, color, jitter)
+}
+
+/// The normal of what the camera ray hit as a color.
+fn normal_color(hit: Option<HitRecord>) -> Vec3f {
+    match hit {
+        // Not turned towards the camera, so that normals pointing into
+        // surfaces show
+        Some(hit) => (hit.normal + Vec3f::new(1.0, 1.0, 1.0)) * 0.5,
+        None => Vec3f::new(0.0, 0.0, 0.0),
+    }
+}
+
+/// How wide a pixel is along the camera rays, at the camera and growing by
+/// as much again with every unit of distance, measured between the rays
+/// through two pixels next to each other in the middle of the image.
+pub fn pixel_footprint(camera: &Camera, (width, height): (usize, usize)) -> (Float, Float) {
+    let (x, y) = ((width / 2) as Float, (height / 2) as Float + 0.5);
+    let ray = |x: Float| camera.ray((x, y), (0.5, 0.5), width, height);
+    match (ray(x), ray(x + 1.0)) {
+        (Some(a), Some(b)) => (a.origin.distance(&b.origin), a.dir.distance(&b.dir)),
+        _ => (0.0, 0.0),
+    }
+}
+
+/// The color the outlines of nodes of the acceleration structure are drawn
+/// in, changing with how deep they are.
+fn node_color(depth: usize) -> Vec3f {
+    match depth % 4 {
+        0 => Vec3f::new(0.2, 0.6, 1.0),
+        1 => Vec3f::new(0.2, 1.0, 0.4),
+        2 => Vec3f::new(1.0, 0.3, 0.8),
+        _ => Vec3f::new(1.0, 1.0, 0.3),
+    }
+}
+
+/// The color with the outlines of the bounds the camera ray passes through
+/// drawn over it, those of objects in orange and those of the nodes of the
+/// acceleration structure by their depth, fainter behind what the ray hit.
+/// The outlines are as wide as the `footprint` of a pixel.
+pub fn outline_bounds(
+    scene: &Scene,
+    ray: &Ray,
+    hit: Option<HitRecord>,
+    color: Vec3f,
+    (width, spread): (Float, Float),
+) -> Vec3f {
+    let hit_distance = hit.map_or(Float::INFINITY, |hit| hit.point.distance(&ray.origin));
+    let mut color = color;
+    scene.visit_bounds(ray, &mut |bounds, depth| {
+        let (t_near, t_far) = match bounds.intersect(&ray.origin, &ray.dir) {
+            Some(t) => t,
+            None => return,
+        };
+        for t in [t_near, t_far].iter().copied().filter(|&t| t >= 0.0) {
+            let (p, line) = (ray.at(t), width + spread * t);
+            let near =
+                |v: Float, min: Float, max: Float| (v - min).abs().min((max - v).abs()) < line;
+            // On an edge where the point is near the sides of two slabs
+            let sides = [
+                near(p.0, bounds.min.0, bounds.max.0),
+                near(p.1, bounds.min.1, bounds.max.1),
+                near(p.2, bounds.min.2, bounds.max.2),
+            ];
+            if sides.iter().filter(|&&near| near).count() < 2 {
+                continue;
+            }
+            let tint = depth.map_or(Vec3f::new(1.0, 0.5, 0.0), node_color);
+            let opacity = if t <= hit_distance { 0.8 } else { 0.3 };
+            color = color * (1.0 - opacity) + tint * opacity;
+        }
+    });
+    color
+}
+
+/// The light the direct integrator finds reflected off and refracted
+/// through the glass of the `hit` along `dir`, following the rays on from
+/// there, `depth` times in already, and dimming those going through the
+/// glass by its absorption. Like a path, the rays end dark once they have
+/// bounced off as many surfaces as the options allow.
+fn glass_light(
+    scene: &Scene,
+    glass: &Glass,
+    hit: &HitRecord,
+    dir: &Vec3f,
+    sample: &PixelSample,
+    options: &RenderOptions,
+    depth: usize,
+) -> Vec3f {
+    if depth + 1 >= options.max_depth {
+        return Vec3f::new(0.0, 0.0, 0.0);
+    }
+    let (reflected, refracted, reflectance) = glass.split(dir, &hit.normal);
+    let entering = hit.normal.dot(dir) < 0.0;
+    // The light along `next`, dimmed on the way if it goes through the glass
+    let follow = |next: Vec3f, through: bool| {
+        // From the side of the surface the ray goes on to
+        let from = options.bias.lift(hit, &-next);
+        stats::count(|stats| stats.secondary_rays += 1);
+        let next_hit = scene.hit(&Ray::new(from, next));
+        let light = surface_radiance(scene, &next, next_hit, sample, options, depth + 1);
+        match (glass.absorption, next_hit) {
+            (Some(absorption), Some(next_hit)) if through => {
+                light * absorption.transmittance(next_hit.distance)
+            }
+            _ => light,
+        }
+    };
+    let mut light = follow(reflected, !entering) * reflectance;
+    if let Some(refracted) = refracted {
+        light += follow(refracted, entering) * (1.0 - reflectance);
+    }
+    light
+}
+
+/// The light leaving what the ray along `dir` hit towards where it came
+/// from, or the background: the camera ray, or one `depth` times off or
+/// through glass from it.
+fn surface_radiance(
+    scene: &Scene,
+    dir: &Vec3f,
+    hit: Option<HitRecord>,
+    sample: &PixelSample,
+    options: &RenderOptions,
+    depth: usize,
+) -> Vec3f {
+    let settings = &options.ambient_occlusion;
+    match (options.integrator, hit) {
+        (Integrator::Path, _) => clamp(
+            path(
+                scene,
+                dir,
+                hit,
+                sample,
+                options.max_depth,
+                options.roulette_depth,
+                &options.bias,
+            ),
+            options.clamp,
+        ),
+        (Integrator::Spectral, _) => {
+            // The time takes only the first coordinate of its dimension
+            let wavelength = spectrum::wavelength(sample.get_2d(sampler::TIME).1);
+            let sample = PixelSample {
+                wavelength: Some(wavelength),
+                ..*sample
+            };
+            let light = path(
+                scene,
+                dir,
+                hit,
+                &sample,
+                options.max_depth,
+                options.roulette_depth,
+                &options.bias,
+            );
+            clamp(light * spectrum::rgb(wavelength), options.clamp)
+        }
+        (Integrator::Direct, Some(hit)) => {
+            let (n, point) = (hit.normal, hit.point);
+            let lift = options.bias.distance(hit.distance);
+            let occlusion = if settings.diffuse {
+                let facing = if n.dot(dir) > 0.0 { -n } else { n };
+                let lifted = options.bias.lift(&hit, dir);
+                occlusion(scene, &facing, &lifted, sample, settings)
+            } else {
+                1.0
+            };
+            let material = surface_material(&hit, sample.width(hit.distance));
+            let caustics = scene
+                .caustics
+                .as_ref()
+                .map_or(Vec3f::new(0.0, 0.0, 0.0), |map| {
+                    map.irradiance(&point, &if n.dot(dir) > 0.0 { -n } else { n })
+                });
+            let emitted = emitted_light(
+                scene,
+                &n,
+                &point,
+                sample,
+                sampler::bounce(0, sampler::EMITTER),
+                false,
+                lift,
+            );
+            let through = material.glass.map_or(Vec3f::new(0.0, 0.0, 0.0), |glass| {
+                glass_light(scene, &glass, &hit, dir, sample, options, depth)
+            });
+            direct_light(scene, &material, &n, (&point, lift), dir, None, occlusion)
+                + emission(hit.object, &n, dir)
+                + material.diffuse_reflectance() * (emitted * occlusion + caustics)
+                + through
+        }
+        (Integrator::AmbientOcclusion, Some(hit)) => {
+            let n = if hit.normal.dot(dir) > 0.0 {
+                -hit.normal
+            } else {
+                hit.normal
+            };
+            let lifted = options.bias.lift(&hit, dir);
+            let open = occlusion(scene, &n, &lifted, sample, settings);
+            Vec3f::new(open, open, open)
+        }
+        (Integrator::Direct, None) => background_color(scene, dir),
+        (Integrator::AmbientOcclusion, None) => Vec3f::new(1.0, 1.0, 1.0),
+    }
+}
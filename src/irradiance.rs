@@ -0,0 +1,301 @@
+//! Irradiance caching: the light diffuse surfaces get from the surfaces
+//! around them changes slowly across them, so rather than following paths
+//! from every camera hit for it, it is worked out carefully at points
+//! spread over what the camera sees, before the render, and interpolated
+//! between them. Points go where the estimate of the error of interpolating
+//! from the points already there is too large, which is close together in
+//! corners and near other surfaces, where the light changes quickly, and
+//! far apart in the open.
+//!
+//! The error goes by the split sphere of Ward: how far a point is from a
+//! cached one, against the harmonic mean of how far the surfaces around
+//! the cached one are, and how differently the two surfaces face.
+
+use std::collections::HashMap;
+use std::thread;
+
+use crate::integrator::{indirect_light, pixel_footprint};
+use crate::object::{surface_material, HitRecord, Hittable};
+use crate::render::{RenderOptions, Tile};
+use crate::sampler::PixelSample;
+use crate::scene::Scene;
+use crate::vector::{Float, Vec3f};
+
+/// The stride of the grid of pixels the first cached points are placed
+/// at, halved until every pixel has been looked at
+const COARSEST_STRIDE: usize = 16;
+/// How many points are worked out at once, shared between the threads,
+/// before the pixels after them are looked at again
+const CHUNK: usize = 64;
+/// The radii of cached points are kept between these many pixels across,
+/// as wide as the pixels are where they are, so that the points neither
+/// crowd into corners nor spread too far where nothing is nearby
+const MIN_RADIUS_PIXELS: Float = 2.0;
+const MAX_RADIUS_PIXELS: Float = 32.0;
+
+struct Record {
+    position: Vec3f,
+    /// The normal of the surface, turned towards the camera
+    normal: Vec3f,
+    /// The light arriving from other surfaces, as the path tracer averages
+    /// it over cosine-weighted directions
+    indirect: Vec3f,
+    /// The harmonic mean of how far the surfaces around it are
+    radius: Float,
+}
+
+impl Record {
+    /// How much the point weighs in interpolating at `point` of a surface
+    /// facing `n`, if it is close enough for the error to stay within
+    /// `error`. The weight fades out to nothing at the edge, so that the
+    /// light doesn't jump where points come and go.
+    fn weight(&self, point: &Vec3f, n: &Vec3f, error: Float) -> Option<Float> {
+        let offset = *point - self.position;
+        // Points in front of the surface of the cached one see what it
+        // doesn't
+        if offset.dot(&(*n + self.normal)) < -0.02 * self.radius {
+            return None;
+        }
+        let divergence = (1.0 - n.dot(&self.normal)).max(0.0).sqrt();
+        let estimate = offset.norm().sqrt() / self.radius + divergence;
+        if estimate >= error {
+            return None;
+        }
+        Some(1.0 / estimate.max(1e-6) - 1.0 / error)
+    }
+}
+
+/// Points where the light from other surfaces was worked out, sorted by
+/// how far they reach into grids of cells as wide as the power of two just
+/// above it, so that looking around a point finds every one reaching it in
+/// the cells next to its own.
+pub struct IrradianceCache {
+    records: Vec<Record>,
+    error: Float,
+    /// The records by the power of two of the cells and the cell
+    cells: HashMap<(i32, i64, i64, i64), Vec<usize>>,
+    /// The powers of two there are records in
+    levels: Vec<i32>,
+}
+
+impl IrradianceCache {
+    /// Caches the light from other surfaces over what the camera sees in
+    /// an image of `width` by `height` pixels, or in its crop, with the
+    /// path tracer of the options running `samples` paths for each point.
+    /// Points go wherever interpolating would be further off than `error`,
+    /// which is around a tenth to a half: the smaller, the more points.
+    pub fn build(
+        scene: &Scene,
+        options: &RenderOptions,
+        (width, height): (usize, usize),
+        (error, samples): (Float, usize),
+    ) -> Self {
+        let mut cache = Self {
+            records: vec![],
+            error,
+            cells: HashMap::new(),
+            levels: vec![],
+        };
+        let footprint = pixel_footprint(&scene.camera, (width, height));
+        let area = options.crop.unwrap_or(Tile {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        let threads = options.threads.max(1);
+
+        let mut pending: Vec<(HitRecord, Vec3f)> = vec![];
+        let mut stride = COARSEST_STRIDE;
+        loop {
+            for y in (area.y..area.y + area.height).step_by(stride) {
+                for x in (area.x..area.x + area.width).step_by(stride) {
+                    // Pixels of the coarser grids were looked at already
+                    let coarser = stride * 2;
+                    if stride < COARSEST_STRIDE
+                        && (x - area.x).is_multiple_of(coarser)
+                        && (y - area.y).is_multiple_of(coarser)
+                    {
+                        continue;
+                    }
+                    let center = (x as Float + 0.5, y as Float + 0.5);
+                    let hit = match scene.camera.ray(center, (0.5, 0.5), width, height) {
+                        Some(ray) => scene.hit(&ray).map(|hit| (hit, ray.dir)),
+                        None => None,
+                    };
+                    let (hit, dir) = match hit {
+                        Some(hit) => hit,
+                        None => continue,
+                    };
+                    let diffuse = surface_material(&hit, 0.0).diffuse_reflectance();
+                    if diffuse.max_component() <= 0.0 {
+                        continue;
+                    }
+                    let n = facing(&hit, &dir);
+                    if cache.lookup(&hit.point, &n).is_none() {
+                        pending.push((hit, dir));
+                    }
+                    if pending.len() == CHUNK {
+                        cache.add(scene, options, &pending, (samples, footprint), threads);
+                        pending.clear();
+                    }
+                }
+            }
+            if stride == 1 {
+                break;
+            }
+            stride /= 2;
+        }
+        cache.add(scene, options, &pending, (samples, footprint), threads);
+        cache
+    }
+
+    /// Works out the points at the hits, each numbered by where it goes in
+    /// the cache so that it samples the same directions however many
+    /// threads share the work, and adds them.
+    fn add(
+        &mut self,
+        scene: &Scene,
+        options: &RenderOptions,
+        hits: &[(HitRecord, Vec3f)],
+        (samples, footprint): (usize, (Float, Float)),
+        threads: usize,
+    ) {
+        if hits.is_empty() {
+            return;
+        }
+        let sampler = options.sampler.build(samples, options.seed);
+        let first = self.records.len();
+        let share = hits.len().div_ceil(threads);
+        let records: Vec<Record> = thread::scope(|s| {
+            let workers: Vec<_> = hits
+                .chunks(share)
+                .enumerate()
+                .map(|(chunk, hits)| {
+                    let sampler = &*sampler;
+                    s.spawn(move || {
+                        hits.iter()
+                            .enumerate()
+                            .map(|(i, (hit, dir))| {
+                                let sample = PixelSample {
+                                    sampler,
+                                    x: first + chunk * share + i,
+                                    y: 0,
+                                    index: 0,
+                                    footprint,
+                                    wavelength: None,
+                                };
+                                record(scene, options, (hit, dir), &sample, samples)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        for record in records {
+            self.insert(record);
+        }
+    }
+
+    fn insert(&mut self, record: Record) {
+        let level = level(record.radius * self.error);
+        let (x, y, z) = cell(&record.position, level);
+        if !self.levels.contains(&level) {
+            self.levels.push(level);
+        }
+        self.cells
+            .entry((level, x, y, z))
+            .or_default()
+            .push(self.records.len());
+        self.records.push(record);
+    }
+
+    /// How many points were cached.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The light from other surfaces at `point` of a surface facing `n`,
+    /// interpolated between the cached points close enough to it, or `None`
+    /// if there aren't any.
+    pub fn lookup(&self, point: &Vec3f, n: &Vec3f) -> Option<Vec3f> {
+        let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+        let mut total = 0.0;
+        for &level in &self.levels {
+            let (x, y, z) = cell(point, level);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let nearby = match self.cells.get(&(level, x + dx, y + dy, z + dz)) {
+                            Some(nearby) => nearby,
+                            None => continue,
+                        };
+                        for record in nearby.iter().map(|&i| &self.records[i]) {
+                            if let Some(weight) = record.weight(point, n, self.error) {
+                                sum += record.indirect * weight;
+                                total += weight;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if total > 0.0 {
+            Some(sum * (1.0 / total))
+        } else {
+            None
+        }
+    }
+}
+
+/// The normal of the hit, turned towards where the ray came from.
+fn facing(hit: &HitRecord, dir: &Vec3f) -> Vec3f {
+    if hit.normal.dot(dir) > 0.0 {
+        -hit.normal
+    } else {
+        hit.normal
+    }
+}
+
+/// The cached point at the hit of a camera ray along `dir`, its light
+/// averaged over `samples` paths, and its radius kept to the width of the
+/// pixels there.
+fn record(
+    scene: &Scene,
+    options: &RenderOptions,
+    (hit, dir): (&HitRecord, &Vec3f),
+    sample: &PixelSample,
+    samples: usize,
+) -> Record {
+    let n = facing(hit, dir);
+    let lifted = options.bias.lift(hit, dir);
+    let (indirect, distance) = indirect_light(scene, (&lifted, &n), sample, samples, options);
+    let pixel = sample.width(hit.distance).max(Float::EPSILON);
+    Record {
+        position: hit.point,
+        normal: n,
+        indirect,
+        radius: distance.clamp(MIN_RADIUS_PIXELS * pixel, MAX_RADIUS_PIXELS * pixel),
+    }
+}
+
+/// The power of two just above `reach`.
+fn level(reach: Float) -> i32 {
+    reach.log2().ceil() as i32
+}
+
+/// The cell of the grid of cells two to the power `level` wide that `point`
+/// is in.
+fn cell(point: &Vec3f, level: i32) -> (i64, i64, i64) {
+    let size = (level as Float).exp2();
+    let index = |x: Float| (x / size).floor() as i64;
+    (index(point.0), index(point.1), index(point.2))
+}
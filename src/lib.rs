@@ -0,0 +1,42 @@
+#![cfg_attr(feature = "portable-simd", feature(portable_simd))]
+
+pub mod aabb;
+pub mod accel;
+pub mod aov;
+pub mod arena;
+pub mod background;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod denoise;
+pub mod error;
+pub mod ffi;
+pub mod files;
+pub mod ies;
+pub mod input;
+pub mod integrator;
+pub mod irradiance;
+pub mod json;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod object;
+pub mod output;
+pub mod photon;
+pub mod poly;
+pub mod preset;
+pub mod preview;
+pub mod ray;
+pub mod remote;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod script;
+pub mod serve;
+pub mod sky;
+pub mod spectrum;
+pub mod stats;
+pub mod temporal;
+pub mod texture;
+pub mod vector;
+pub mod volume;
@@ -0,0 +1,263 @@
+use std::cell::Cell;
+
+use crate::ies::Profile;
+use crate::vector::{consts::PI, Float, Vec3f};
+
+/// The lumens of a watt of the green light eyes are most sensitive to, which
+/// converts light given in lumens and lux into the watts the renderer works
+/// in
+pub const LUMENS_PER_WATT: Float = 683.0;
+
+/// The light one light sends towards a point.
+#[derive(Clone, Copy, Debug)]
+pub struct Incident {
+    /// Unit direction from the point towards the light
+    pub direction: Vec3f,
+    /// How far the light is along `direction`, infinite for lights that are
+    /// infinitely far away
+    pub distance: Float,
+    pub intensity: Vec3f,
+}
+
+pub trait Light: Send + Sync {
+    /// The light arriving at `point`, from the spot on the light picked by
+    /// the point `uv` of the unit square for lights that aren't points.
+    fn incident(&self, point: &Vec3f, uv: (Float, Float)) -> Incident;
+
+    /// The light group it is in, if any, for rendering the light of each
+    /// group into a pass of its own.
+    fn group(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// A light put in a light group.
+pub struct Grouped {
+    pub light: Box<dyn Light>,
+    pub group: String,
+}
+
+impl Light for Grouped {
+    fn incident(&self, point: &Vec3f, uv: (Float, Float)) -> Incident {
+        self.light.incident(point, uv)
+    }
+
+    fn group(&self) -> Option<&str> {
+        Some(&self.group)
+    }
+}
+
+/// A point or spot light shining as a real fixture does, dimmed in each
+/// direction by an IES profile, the profile's nadir along `axis` and its
+/// horizontal angles going around from `tangent` towards `bitangent`. The
+/// intensity of the light is its intensity at the peak of the profile.
+pub struct Profiled {
+    pub light: Box<dyn Light>,
+    pub profile: Profile,
+    pub axis: Vec3f,
+    pub tangent: Vec3f,
+    pub bitangent: Vec3f,
+}
+
+impl Light for Profiled {
+    fn incident(&self, point: &Vec3f, uv: (Float, Float)) -> Incident {
+        let mut incident = self.light.incident(point, uv);
+        let out = -incident.direction;
+        let vertical = out.dot(&self.axis).clamp(-1.0, 1.0).acos();
+        let horizontal = out.dot(&self.bitangent).atan2(out.dot(&self.tangent));
+        let candela = self
+            .profile
+            .candela(vertical.to_degrees(), horizontal.to_degrees());
+        incident.intensity *= candela / self.profile.peak();
+        incident
+    }
+
+    fn group(&self) -> Option<&str> {
+        self.light.group()
+    }
+}
+
+thread_local! {
+    /// The light group shining on its own on this thread, if one is
+    static ONLY: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with only the lights, emitters and background of `group`
+/// shining on this thread, the others dark, for the pass of the group.
+pub fn only_group<T>(group: &'static str, f: impl FnOnce() -> T) -> T {
+    let previous = ONLY.with(|only| only.replace(Some(group)));
+    let result = f();
+    ONLY.with(|only| only.set(previous));
+    result
+}
+
+/// Whether light from the light group `group`, or from outside any group,
+/// shines on this thread: always, unless a group shines on its own.
+pub fn shines(group: Option<&str>) -> bool {
+    ONLY.with(|only| only.get().is_none_or(|only| group == Some(only)))
+}
+
+/// Whether a light group shines on its own on this thread.
+pub fn grouped() -> bool {
+    ONLY.with(|only| only.get().is_some())
+}
+
+/// How the light of point and spot lights dims with distance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Falloff {
+    /// As bright at any distance, as in the tutorial
+    #[default]
+    None,
+    /// With the square of the distance, as physical lights do, so that the
+    /// intensity is the brightness at a distance of 1. Closer than `radius`
+    /// the light is as bright as at `radius`, which keeps points right next
+    /// to it from blowing out. Beyond `cutoff`, if given, it is dark; it
+    /// fades out smoothly on the way there.
+    InverseSquare {
+        radius: Float,
+        cutoff: Option<Float>,
+    },
+}
+
+impl Falloff {
+    /// The part of the intensity left at the given distance.
+    pub fn factor(self, distance: Float) -> Float {
+        match self {
+            Falloff::None => 1.0,
+            Falloff::InverseSquare { radius, cutoff } => {
+                let window = cutoff.map_or(1.0, |cutoff| {
+                    let t = (1.0 - (distance / cutoff).powi(4)).max(0.0);
+                    t * t
+                });
+                window / distance.max(radius).max(1e-4).powi(2)
+            }
+        }
+    }
+}
+
+/// A light shining equally in every direction from one point.
+pub struct PointLight {
+    pub position: Vec3f,
+    /// Brightness of each channel
+    pub intensity: Vec3f,
+    pub falloff: Falloff,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3f, intensity: Vec3f) -> Self {
+        Self {
+            position,
+            intensity,
+            falloff: Falloff::None,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn incident(&self, point: &Vec3f, _uv: (Float, Float)) -> Incident {
+        let to_light = self.position - *point;
+        let distance = to_light.len();
+        Incident {
+            direction: to_light.normalize(),
+            distance,
+            intensity: self.intensity * self.falloff.factor(distance),
+        }
+    }
+}
+
+/// A light infinitely far away, such as the sun, whose rays are parallel and
+/// equally bright everywhere. A disk of some angular diameter in the sky
+/// casts shadows that soften with distance from what casts them.
+pub struct DirectionalLight {
+    /// Unit direction the light travels in
+    pub direction: Vec3f,
+    pub intensity: Vec3f,
+    /// Angle the light spans across the sky, in radians
+    pub angular_diameter: Float,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3f, intensity: Vec3f, angular_diameter: Float) -> Self {
+        Self {
+            direction: direction.normalize(),
+            intensity,
+            angular_diameter,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn incident(&self, _point: &Vec3f, (u, v): (Float, Float)) -> Incident {
+        // A direction spread evenly over the cone the light spans
+        let axis = -self.direction;
+        let cos_max = (self.angular_diameter / 2.0).cos();
+        let cos = 1.0 - u * (1.0 - cos_max);
+        let (sin, phi) = ((1.0 - cos * cos).max(0.0).sqrt(), 2.0 * PI * v);
+        let (tangent, bitangent) = axis.perpendiculars();
+        Incident {
+            direction: (axis * cos + tangent * (sin * phi.cos()) + bitangent * (sin * phi.sin()))
+                .normalize(),
+            distance: Float::INFINITY,
+            intensity: self.intensity,
+        }
+    }
+}
+
+/// A point light shining in a cone: fully within `inner_angle` of its
+/// direction, fading out smoothly towards `outer_angle`, and dark beyond.
+pub struct SpotLight {
+    pub position: Vec3f,
+    /// Unit direction the cone points in
+    pub direction: Vec3f,
+    pub intensity: Vec3f,
+    /// Angles from the direction, in radians
+    pub inner_angle: Float,
+    pub outer_angle: Float,
+    pub falloff: Falloff,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vec3f,
+        direction: Vec3f,
+        intensity: Vec3f,
+        inner_angle: Float,
+        outer_angle: Float,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            inner_angle,
+            outer_angle: outer_angle.max(inner_angle),
+            falloff: Falloff::None,
+        }
+    }
+
+    /// How much of the intensity goes out at the given cosine of the angle
+    /// from the direction.
+    fn cone(&self, cos: Float) -> Float {
+        let (inner, outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        if cos >= inner {
+            1.0
+        } else if cos <= outer {
+            0.0
+        } else {
+            let t = (cos - outer) / (inner - outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn incident(&self, point: &Vec3f, _uv: (Float, Float)) -> Incident {
+        let to_light = self.position - *point;
+        let (direction, distance) = (to_light.normalize(), to_light.len());
+        let dimming = self.cone(-direction.dot(&self.direction)) * self.falloff.factor(distance);
+        Incident {
+            direction,
+            distance,
+            intensity: self.intensity * dimming,
+        }
+    }
+}
@@ -1,191 +1,2110 @@
-use std::fs::File;
-use std::io::prelude::*;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-mod vector;
+use tiny_raytracer::accel::AcceleratorKind;
+use tiny_raytracer::aov::{self, Aov};
+use tiny_raytracer::camera::{Camera, Projection, Stereo, StereoLayout, Turntable};
+use tiny_raytracer::checkpoint::{self, Fingerprint};
+use tiny_raytracer::color::Color;
+use tiny_raytracer::denoise::{self, Denoiser};
+use tiny_raytracer::error;
+use tiny_raytracer::files;
+use tiny_raytracer::input;
+use tiny_raytracer::integrator::Integrator;
+use tiny_raytracer::irradiance::IrradianceCache;
+use tiny_raytracer::output::{self, Alpha, Format, OutputOptions, Transfer, Video};
+use tiny_raytracer::photon::PhotonMap;
+use tiny_raytracer::preset;
+use tiny_raytracer::preview::{Event, Key, Window};
+use tiny_raytracer::remote::{self, Coordinator, Frame, Job};
+use tiny_raytracer::render::{
+    self, AdaptivePasses, AdaptiveSampling, Image, Progress, RenderOptions, RenderState, Tile,
+    HEIGHT, WIDTH,
+};
+use tiny_raytracer::scene::{self, Scene};
+use tiny_raytracer::serve;
+use tiny_raytracer::stats;
+use tiny_raytracer::temporal::{self, FrameView};
+use tiny_raytracer::vector::{consts::PI, Float, Vec3f};
 
-use vector::{Vec2f, Vec3f};
+const USAGE: &str = "usage: tiny-raytracer [options] [scene.json]
+       tiny-raytracer serve ADDRESS [options]
+       tiny-raytracer generate SEED [options]
+       tiny-raytracer bench [--threads N] [--accel KIND]
 
-#[derive(Copy, Clone)]
-struct Material {
-    diffuse_color: Vec3f,
-    albedo: Vec2f,
-    specular_exponent: f32,
+`serve` answers HTTP requests at ADDRESS, such as 127.0.0.1:8080, rendering
+the scenes posted to /jobs with the options given. `generate` renders a scene
+of random spheres, always the same for the same SEED. `bench` renders the
+preset scenes at fixed settings and prints how fast as JSON.
+
+options:
+    --preset tutorial|cornell|spheres
+                                    render a built-in scene instead of a scene
+                                    file (default: the tutorial's)
+    --count N                       spheres `generate` scatters (default: 450)
+    --save-scene FILE               write the scene `generate` made to FILE, to
+                                    render or edit later
+    -o, --output FILE               image to write, .ppm, .png, .exr or .hdr, or
+                                    - for standard output (default: out.ppm);
+                                    {scene}, {spp}, {seed}, {frame:04} and the
+                                    like are filled in from the settings
+    --format FORMAT                 ppm, ppm-ascii, png, exr or hdr, instead of
+                                    going by the extension (default for -: ppm)
+    --bit-depth 8|16                bits per channel of PNG and PPM (default: 8)
+    --alpha straight|premultiplied  make the background transparent and write
+                                    an alpha channel, to PNG or EXR only
+    --exposure EV                   brighten or darken the image by EV stops
+    --white-balance K|R,G,B         make light of K kelvin, or of the linear
+                                    color R,G,B, come out white
+    --tone-map clamp|reinhard|aces  curve bringing bright colors into range for
+                                    PNG and PPM (default: clamp)
+    --gamma G                       encode PNG and PPM colors with a power curve,
+                                    1 for linear (default: the sRGB curve)
+    --dither none|ordered|blue-noise
+                                    break up banding in PNG and PPM (default: none)
+    --aov depth,normal,albedo,id,cost,lights.GROUP
+                                    also write these passes, each next to the
+                                    image with its name before the extension,
+                                    or as layers of the image for EXR; a
+                                    lights pass has the light of the lights in
+                                    light group GROUP of the scene alone
+    --depth-out FILE                also write the depth as grays to FILE, white
+                                    near and black far
+    --depth-range NEAR,FAR          distances the grays of --depth-out span
+                                    (default: the nearest and furthest hits)
+    --heatmap FILE                  also write how much work finding the hit of
+                                    each pixel took to FILE, in false colors
+    --denoise                       smooth out the noise of the image, guided by
+                                    the normal and albedo passes
+    --denoise-radius N              pixels on each side the denoiser averages
+                                    (default: 4)
+    --camera-position X,Y,Z         move the camera of the scene, which keeps
+                                    looking at its target (default: 0,0,0)
+    --camera-target X,Y,Z           point the camera at X,Y,Z (default: 0,0,-1)
+    --camera-up X,Y,Z               roll the camera so that this points up
+                                    (default: 0,1,0)
+    --time T                        render the moment T of the scene's keyframes
+                                    (default: 0)
+    --frames N                      render N frames of the animation from --time
+                                    on, numbering the output, such as out_0001.ppm
+    --fps F                         frames per unit of time (default: 24)
+    --video FILE                    pipe the frames into ffmpeg instead, to
+                                    write them as a video such as out.mp4
+    --turntable                     circle the camera once around its target
+                                    over the frames
+    --temporal                      keep the passes of the frame before for
+                                    tiles where neither the camera nor what it
+                                    sees has moved
+    --turntable-radius D            distance of the circling camera from its
+                                    target (default: the camera's)
+    --turntable-elevation DEGREES   angle of the circling camera above its
+                                    target (default: the camera's)
+    --fov DEGREES                   angle the camera sees from the bottom of the
+                                    image to the top, or across the circle of a
+                                    fisheye lens (default: 90)
+    --orthographic WIDTH            look along parallel rays from a plane WIDTH
+                                    across, without perspective
+    --fisheye equidistant|equisolid look through a fisheye lens, its circle
+                                    spanning the field of view
+    --panorama                      render every direction around the camera, as
+                                    an equirectangular environment map
+    --aperture D                    blur what is out of focus through a lens D
+                                    across (default: 0, all sharp)
+    --focus-distance D              distance ahead of the camera that is in
+                                    focus (default: that of the target)
+    --stereo D                      render the two eyes D apart, side by side
+    --convergence D                 distance ahead the eyes look at, which is
+                                    at the depth of the screen (default: that
+                                    of the target)
+    --stereo-layout side-by-side|top-bottom
+                                    how the eyes share the image, the left one
+                                    first (default: side-by-side)
+    --shutter T                     blur moving objects over the time from 0 to T,
+                                    at most 1 (default: 0, no blur)
+    --width N, --height N           size of the image in pixels (default: 1024
+                                    by 768)
+    --res 720p|1080p|1440p|4k       size of the image by its usual name
+    --scale PERCENT                 scale the size of the image, and the crop,
+                                    such as 50% for a quick draft
+    --scale-samples                 scale the samples per pixel by as much too
+    --crop X,Y,W,H                  only render the W by H pixels from X,Y of
+                                    the top left, and write just those
+    --crop-in-frame                 write the cropped pixels into an image of
+                                    the full size instead, the rest black
+    --accel bvh|kdtree|grid|none    acceleration structure (default: bvh)
+    --threads N                     number of render threads (default: all cores)
+    --tile-size N                   size of the square render tiles (default: 32)
+    --passes N                      passes over the image, refining it each time
+    --spp N                         samples per pixel in every pass (default: 1)
+    --integrator direct|path|spectral|ao
+                                    light from the lights alone, bouncing around
+                                    the scene too, that one wavelength at a time,
+                                    or ambient occlusion in gray
+                                    (default: direct)
+    --max-depth N                   most bounces of a path, or of rays through
+                                    glass with the direct integrator
+                                    (default: 16)
+    --roulette-depth N              bounces after which dim paths may end early
+                                    (default: 3)
+    --clamp MAX                     dim path traced samples brighter than MAX
+    --ao-diffuse                    darken the diffuse light of the direct
+                                    integrator by the ambient occlusion
+    --ao-samples N                  occlusion rays per sample (default: 8)
+    --ao-distance D                 how far surfaces occlude (default: 1)
+    --bias EPSILON                  how far rays start off the surfaces they
+                                    leave, against specks of shadow (default:
+                                    0.001)
+    --bias-scaled                   start them further off the further away the
+                                    surfaces are, by EPSILON per unit
+    --bias-geometric                start them off along the normals of the
+                                    surfaces before normal and bump maps
+    --caustics N                    trace N photons off mirrors and polished
+                                    metal before rendering, for the light they
+                                    focus onto the surfaces around them
+    --caustic-radius R              how far around each point the photons
+                                    are gathered from (default: a hundredth of
+                                    the size of where they landed)
+    --irradiance-cache ERROR        work out the light diffuse surfaces get from
+                                    each other at points spread where
+                                    interpolating between them would be off
+                                    by more than ERROR, such as 0.2, before
+                                    rendering, and interpolate it after the
+                                    first bounce of the path integrator
+    --cache-samples N               paths for each cached point (default: 256)
+    --debug normals|bounds          show the normals of the surfaces as colors
+                                    instead of lighting them, or outline the
+                                    bounds of the objects and of the nodes of
+                                    the BVH over the image
+    --sampler r2|stratified|halton|sobol|blue-noise
+                                    how samples are spread over each pixel
+                                    (default: r2)
+    --seed N                        pick a different set of samples (default: 0)
+    --adaptive THRESHOLD            keep sampling pixels whose brightness has a
+                                    standard error above THRESHOLD
+    --max-spp N                     most samples per pixel and pass with
+                                    --adaptive (default: 64)
+    --adaptive-passes THRESHOLD     after --passes passes, keep rendering passes
+                                    over the tiles whose pixels' brightness has
+                                    a standard error above THRESHOLD, on
+                                    average, and report the passes they took
+    --max-passes N                  most passes over a tile with
+                                    --adaptive-passes (default: 16)
+    --write-every SECONDS           write the image so far while rendering
+    --checkpoint FILE               save the render state to FILE while rendering
+    --resume FILE                   continue the render saved in FILE, and keep
+                                    saving to it
+    --watch                         render again whenever the scene file, or a
+                                    file it refers to, changes
+    --preview                       show the image in a window while it renders,
+                                    if built with the preview feature
+    --listen ADDRESS                share the render out to workers connecting
+                                    at ADDRESS, such as 0.0.0.0:7878
+    --worker ADDRESS                render for the coordinator at ADDRESS
+                                    instead, with the threads of `--threads`
+    -q, --quiet                     don't show how far the render has got
+    --stats                         print the rays traced, tests made and time
+                                    taken by each stage after the render
+    --stats-json FILE               write them to FILE as JSON instead, or to
+                                    standard output if FILE is -
+    -h, --help                      print this message";
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+/// How often watched files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the progress bar is redrawn at most
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+/// Characters the progress bar fills
+const PROGRESS_WIDTH: usize = 30;
+/// How often the preview window is drawn at most
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(100);
+/// Degrees the camera turns around its target for every pixel the mouse is
+/// dragged in the preview window
+const DRAG_ANGLE: Float = 0.25;
+
+struct Options {
+    scene: Option<String>,
+    /// The built-in scene to render without a scene file
+    preset: Option<String>,
+    /// The seed and number of spheres of a random scene to render
+    generate: Option<(u64, usize)>,
+    /// Where to write the random scene
+    save_scene: Option<String>,
+    /// Times renders of the presets instead of rendering a scene
+    bench: bool,
+    output: String,
+    image: OutputOptions,
+    aovs: Vec<Aov>,
+    /// Where to write the depth as grays
+    depth_out: Option<String>,
+    /// The distances the grays of the depth span, instead of those of the
+    /// nearest and furthest hits
+    depth_range: Option<(Float, Float)>,
+    /// Where to write the cost of the pixels in false colors
+    heatmap: Option<String>,
+    denoise: Option<Denoiser>,
+    /// The photons traced for caustics
+    caustics: Option<usize>,
+    /// The radius photons are gathered within, instead of one going by
+    /// where they landed
+    caustic_radius: Option<Float>,
+    /// The error cached irradiance is placed by, if it is cached
+    irradiance_cache: Option<Float>,
+    /// The paths for each cached point
+    cache_samples: Option<usize>,
+    /// The position, target and up vector replacing the scene camera's
+    camera: (Option<Vec3f>, Option<Vec3f>, Option<Vec3f>),
+    /// The field of view replacing the scene camera's, in radians
+    fov: Option<Float>,
+    aperture: Option<Float>,
+    focus_distance: Option<Float>,
+    shutter: Option<Float>,
+    projection: Option<Projection>,
+    /// The time the scene is rendered at, or its first frame
+    time: Float,
+    frames: Option<usize>,
+    fps: Float,
+    video: Option<String>,
+    turntable: Option<Turntable>,
+    /// Whether frames keep the tiles of the frame before that look the same
+    temporal: bool,
+    /// The eye distance, convergence and layout of a stereo camera
+    stereo: (Option<Float>, Option<Float>, Option<StereoLayout>),
+    width: usize,
+    height: usize,
+    accel: AcceleratorKind,
+    render: RenderOptions,
+    /// Writes the pixels of a crop into the whole image, rather than on
+    /// their own
+    crop_in_frame: bool,
+    write_every: Option<Duration>,
+    checkpoint: Option<String>,
+    resume: bool,
+    watch: bool,
+    preview: bool,
+    /// Where to listen for workers to share the render out to
+    listen: Option<String>,
+    /// The coordinator to render for instead of rendering a scene
+    worker: Option<String>,
+    /// Where to answer HTTP requests to render the scenes posted
+    serve: Option<String>,
+    quiet: bool,
+    stats: bool,
+    stats_json: Option<String>,
 }
 
-impl Material {
-    fn new(albedo: Vec2f, diffuse_color: Vec3f, specular_exponent: f32) -> Self {
-        Self {
-            albedo,
-            diffuse_color,
-            specular_exponent,
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("`{}` expects a value", option))
+}
+
+fn positive(args: &mut impl Iterator<Item = String>, option: &str) -> Result<usize, String> {
+    match value(args, option)?.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("`{}` expects a positive number", option)),
+    }
+}
+
+fn vector(args: &mut impl Iterator<Item = String>, option: &str) -> Result<Vec3f, String> {
+    let components = value(args, option)?
+        .split(',')
+        .map(|c| c.trim().parse::<Float>().ok().filter(|c| c.is_finite()))
+        .collect::<Option<Vec<_>>>();
+    match components.as_deref() {
+        Some(&[x, y, z]) => Ok(Vec3f::new(x, y, z)),
+        _ => Err(format!("`{}` expects three numbers, such as 0,1,0", option)),
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        scene: None,
+        preset: None,
+        generate: None,
+        save_scene: None,
+        bench: false,
+        output: "out.ppm".to_string(),
+        image: OutputOptions::default(),
+        aovs: vec![],
+        depth_out: None,
+        depth_range: None,
+        heatmap: None,
+        denoise: None,
+        caustics: None,
+        caustic_radius: None,
+        irradiance_cache: None,
+        cache_samples: None,
+        camera: (None, None, None),
+        fov: None,
+        aperture: None,
+        focus_distance: None,
+        shutter: None,
+        projection: None,
+        stereo: (None, None, None),
+        time: 0.0,
+        frames: None,
+        fps: 24.0,
+        video: None,
+        turntable: None,
+        temporal: false,
+        width: WIDTH,
+        height: HEIGHT,
+        accel: AcceleratorKind::default(),
+        render: RenderOptions::default(),
+        crop_in_frame: false,
+        write_every: None,
+        checkpoint: None,
+        resume: false,
+        watch: false,
+        preview: false,
+        listen: None,
+        worker: None,
+        serve: None,
+        quiet: false,
+        stats: false,
+        stats_json: None,
+    };
+    let mut args = args.peekable();
+    if args.peek().map(String::as_str) == Some("serve") {
+        args.next();
+        let address = args.next().filter(|address| !address.starts_with('-'));
+        options.serve = Some(address.ok_or("`serve` expects an address, such as 127.0.0.1:8080")?);
+    } else if args.peek().map(String::as_str) == Some("generate") {
+        args.next();
+        let seed = args.next().and_then(|seed| seed.parse().ok());
+        let seed = seed.ok_or("`generate` expects a seed, a whole number")?;
+        options.generate = Some((seed, preset::COVER_SPHERES));
+    } else if args.peek().map(String::as_str) == Some("bench") {
+        args.next();
+        options.bench = true;
+    }
+    let mut count = None;
+    let mut format = None;
+    let mut threshold = None;
+    let mut max_samples = 64;
+    let (mut pass_threshold, mut max_passes) = (None, None);
+    let mut denoise_radius = None;
+    let mut scale = None;
+    let mut scale_samples = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preset" => {
+                let name = value(&mut args, &arg)?;
+                if !preset::NAMES.contains(&&name[..]) {
+                    return Err(format!(
+                        "`--preset` expects one of {}",
+                        preset::NAMES.join(", ")
+                    ));
+                }
+                options.preset = Some(name);
+            }
+            "--count" => count = Some(positive(&mut args, &arg)?),
+            "--save-scene" => options.save_scene = Some(value(&mut args, &arg)?),
+            "-o" | "--output" => options.output = value(&mut args, &arg)?,
+            "--format" => format = Some(value(&mut args, &arg)?.parse()?),
+            "--bit-depth" => options.image.bit_depth = value(&mut args, &arg)?.parse()?,
+            "--alpha" => {
+                options.image.alpha = value(&mut args, &arg)?.parse()?;
+                options.render.transparent_background = true;
+            }
+            "--exposure" => {
+                options.image.exposure = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(exposure) if exposure.is_finite() => exposure,
+                    _ => return Err("`--exposure` expects a number".to_string()),
+                }
+            }
+            "--white-balance" => {
+                options.image.white_balance = Some(value(&mut args, &arg)?.parse()?)
+            }
+            "--tone-map" => options.image.tone_map = value(&mut args, &arg)?.parse()?,
+            "--gamma" => {
+                options.image.transfer = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(gamma) if gamma > 0.0 => Transfer::Gamma(gamma),
+                    _ => return Err("`--gamma` expects a positive number".to_string()),
+                }
+            }
+            "--dither" => options.image.dither = value(&mut args, &arg)?.parse()?,
+            "--aov" => options.aovs = aov::parse_list(&value(&mut args, &arg)?)?,
+            "--depth-out" => options.depth_out = Some(value(&mut args, &arg)?),
+            "--heatmap" => options.heatmap = Some(value(&mut args, &arg)?),
+            "--depth-range" => {
+                let numbers = value(&mut args, &arg)?
+                    .split(',')
+                    .map(|n| n.trim().parse::<Float>().ok())
+                    .collect::<Option<Vec<_>>>();
+                options.depth_range =
+                    match numbers.as_deref() {
+                        Some(&[near, far]) if 0.0 <= near && near < far && far.is_finite() => {
+                            Some((near, far))
+                        }
+                        _ => return Err(
+                            "`--depth-range` expects two distances, the nearer first, such as 1,20"
+                                .to_string(),
+                        ),
+                    };
+            }
+            "--denoise" => options.denoise = Some(Denoiser::default()),
+            "--denoise-radius" => denoise_radius = Some(positive(&mut args, &arg)?),
+            "--camera-position" => options.camera.0 = Some(vector(&mut args, &arg)?),
+            "--camera-target" => options.camera.1 = Some(vector(&mut args, &arg)?),
+            "--camera-up" => options.camera.2 = Some(vector(&mut args, &arg)?),
+            "--time" => {
+                options.time = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(time) if time.is_finite() => time,
+                    _ => return Err("`--time` expects a number".to_string()),
+                }
+            }
+            "--frames" => options.frames = Some(positive(&mut args, &arg)?),
+            "--fps" => {
+                options.fps = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(fps) if fps > 0.0 && fps.is_finite() => fps,
+                    _ => return Err("`--fps` expects a positive number".to_string()),
+                }
+            }
+            "--video" => options.video = Some(value(&mut args, &arg)?),
+            "--turntable" => {
+                options.turntable.get_or_insert_with(Turntable::default);
+            }
+            "--temporal" => options.temporal = true,
+            "--turntable-radius" => {
+                options
+                    .turntable
+                    .get_or_insert_with(Turntable::default)
+                    .radius = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(radius) if radius > 0.0 => Some(radius),
+                    _ => return Err("`--turntable-radius` expects a positive number".to_string()),
+                }
+            }
+            "--turntable-elevation" => {
+                options
+                    .turntable
+                    .get_or_insert_with(Turntable::default)
+                    .elevation = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(elevation) if elevation.abs() < 90.0 => Some(elevation.to_radians()),
+                    _ => {
+                        return Err(
+                            "`--turntable-elevation` expects an angle between -90 and 90"
+                                .to_string(),
+                        )
+                    }
+                }
+            }
+            "--fov" => {
+                options.fov = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(fov) if fov > 0.0 && fov <= 360.0 => Some(fov.to_radians()),
+                    _ => return Err("`--fov` expects an angle between 0 and 360".to_string()),
+                }
+            }
+            "--aperture" => {
+                options.aperture = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(aperture) if aperture >= 0.0 => Some(aperture),
+                    _ => return Err("`--aperture` expects a number of at least 0".to_string()),
+                }
+            }
+            "--focus-distance" => {
+                options.focus_distance = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(distance) if distance > 0.0 => Some(distance),
+                    _ => return Err("`--focus-distance` expects a positive number".to_string()),
+                }
+            }
+            "--orthographic" => {
+                options.projection = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(width) if width > 0.0 => Some(Projection::Orthographic { width }),
+                    _ => return Err("`--orthographic` expects a positive number".to_string()),
+                }
+            }
+            "--fisheye" => {
+                options.projection = Some(Projection::Fisheye(value(&mut args, &arg)?.parse()?))
+            }
+            "--panorama" => options.projection = Some(Projection::Equirectangular),
+            "--stereo" => {
+                options.stereo.0 = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(distance) if distance >= 0.0 => Some(distance),
+                    _ => return Err("`--stereo` expects a number of at least 0".to_string()),
+                }
+            }
+            "--convergence" => {
+                options.stereo.1 = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(distance) if distance > 0.0 => Some(distance),
+                    _ => return Err("`--convergence` expects a positive number".to_string()),
+                }
+            }
+            "--stereo-layout" => options.stereo.2 = Some(value(&mut args, &arg)?.parse()?),
+            "--shutter" => {
+                options.shutter = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(shutter) if (0.0..=1.0).contains(&shutter) => Some(shutter),
+                    _ => return Err("`--shutter` expects a number from 0 to 1".to_string()),
+                }
+            }
+            "--width" => options.width = positive(&mut args, &arg)?,
+            "--height" => options.height = positive(&mut args, &arg)?,
+            "--res" => {
+                (options.width, options.height) = match &value(&mut args, &arg)?[..] {
+                    "720p" => (1280, 720),
+                    "1080p" => (1920, 1080),
+                    "1440p" => (2560, 1440),
+                    "4k" => (3840, 2160),
+                    res => {
+                        return Err(format!(
+                            "unknown resolution `{}`, expected 720p, 1080p, 1440p or 4k",
+                            res
+                        ))
+                    }
+                }
+            }
+            "--scale" => {
+                let percent = value(&mut args, &arg)?;
+                scale = match percent.trim_end_matches('%').parse::<Float>() {
+                    Ok(percent) if percent > 0.0 && percent.is_finite() => Some(percent / 100.0),
+                    _ => return Err("`--scale` expects a percentage, such as 50%".to_string()),
+                }
+            }
+            "--scale-samples" => scale_samples = true,
+            "--crop" => {
+                let numbers = value(&mut args, &arg)?
+                    .split(',')
+                    .map(|n| n.trim().parse::<usize>().ok())
+                    .collect::<Option<Vec<_>>>();
+                options.render.crop = match numbers.as_deref() {
+                    Some(&[x, y, width, height]) if width > 0 && height > 0 => Some(Tile {
+                        x,
+                        y,
+                        width,
+                        height,
+                    }),
+                    _ => {
+                        return Err(
+                            "`--crop` expects X,Y,W,H in pixels, such as 0,0,256,256".to_string()
+                        )
+                    }
+                }
+            }
+            "--crop-in-frame" => options.crop_in_frame = true,
+            "--accel" => options.accel = value(&mut args, &arg)?.parse()?,
+            "--threads" => options.render.threads = positive(&mut args, &arg)?,
+            "--tile-size" => options.render.tile_size = positive(&mut args, &arg)?,
+            "--passes" => options.render.passes = positive(&mut args, &arg)?,
+            "--spp" => options.render.samples_per_pixel = positive(&mut args, &arg)?,
+            "--integrator" => options.render.integrator = value(&mut args, &arg)?.parse()?,
+            "--max-depth" => options.render.max_depth = positive(&mut args, &arg)?,
+            "--roulette-depth" => options.render.roulette_depth = positive(&mut args, &arg)?,
+            "--clamp" => {
+                options.render.clamp = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(max) if max > 0.0 => Some(max),
+                    _ => return Err("`--clamp` expects a positive number".to_string()),
+                }
+            }
+            "--ao-diffuse" => options.render.ambient_occlusion.diffuse = true,
+            "--ao-samples" => options.render.ambient_occlusion.samples = positive(&mut args, &arg)?,
+            "--ao-distance" => {
+                options.render.ambient_occlusion.distance =
+                    match value(&mut args, &arg)?.parse::<Float>() {
+                        Ok(distance) if distance > 0.0 => distance,
+                        _ => return Err("`--ao-distance` expects a positive number".to_string()),
+                    }
+            }
+            "--bias" => {
+                options.render.bias.epsilon = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(epsilon) if epsilon > 0.0 && epsilon.is_finite() => epsilon,
+                    _ => return Err("`--bias` expects a positive number".to_string()),
+                }
+            }
+            "--bias-scaled" => options.render.bias.scaled = true,
+            "--bias-geometric" => options.render.bias.geometric = true,
+            "--caustics" => options.caustics = Some(positive(&mut args, &arg)?),
+            "--caustic-radius" => {
+                options.caustic_radius = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(radius) if radius > 0.0 && radius.is_finite() => Some(radius),
+                    _ => return Err("`--caustic-radius` expects a positive number".to_string()),
+                }
+            }
+            "--irradiance-cache" => {
+                options.irradiance_cache = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(error) if error > 0.0 && error.is_finite() => Some(error),
+                    _ => return Err("`--irradiance-cache` expects a positive number".to_string()),
+                }
+            }
+            "--cache-samples" => options.cache_samples = Some(positive(&mut args, &arg)?),
+            "--debug" => options.render.debug = Some(value(&mut args, &arg)?.parse()?),
+            "--sampler" => options.render.sampler = value(&mut args, &arg)?.parse()?,
+            "--seed" => {
+                options.render.seed = value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|_| "`--seed` expects a whole number".to_string())?
+            }
+            "--adaptive" => {
+                threshold = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(threshold) if threshold > 0.0 => Some(threshold),
+                    _ => return Err("`--adaptive` expects a positive number".to_string()),
+                }
+            }
+            "--max-spp" => max_samples = positive(&mut args, &arg)?,
+            "--adaptive-passes" => {
+                pass_threshold = match value(&mut args, &arg)?.parse::<Float>() {
+                    Ok(threshold) if threshold > 0.0 => Some(threshold),
+                    _ => return Err("`--adaptive-passes` expects a positive number".to_string()),
+                }
+            }
+            "--max-passes" => max_passes = Some(positive(&mut args, &arg)?),
+            "--write-every" => {
+                options.write_every = match value(&mut args, &arg)?.parse::<f64>() {
+                    Ok(seconds) if seconds > 0.0 => Some(Duration::from_secs_f64(seconds)),
+                    _ => return Err("`--write-every` expects a positive number".to_string()),
+                }
+            }
+            "--checkpoint" => options.checkpoint = Some(value(&mut args, &arg)?),
+            "--resume" => {
+                options.checkpoint = Some(value(&mut args, &arg)?);
+                options.resume = true;
+            }
+            "--watch" => options.watch = true,
+            "--preview" => options.preview = true,
+            "--listen" => options.listen = Some(value(&mut args, &arg)?),
+            "--worker" => options.worker = Some(value(&mut args, &arg)?),
+            "-q" | "--quiet" => options.quiet = true,
+            "--stats" => options.stats = true,
+            "--stats-json" => options.stats_json = Some(value(&mut args, &arg)?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if options.scene.is_none() => options.scene = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
         }
     }
+
+    if let Some(scale) = scale {
+        let scaled = |n: usize| ((n as Float * scale).round() as usize).max(1);
+        options.width = scaled(options.width);
+        options.height = scaled(options.height);
+        if let Some(crop) = &mut options.render.crop {
+            // The crop keeps to the same part of the image, as far as
+            // rounding allows
+            let x = ((crop.x as Float * scale) as usize).min(options.width - 1);
+            let y = ((crop.y as Float * scale) as usize).min(options.height - 1);
+            *crop = Tile {
+                x,
+                y,
+                width: scaled(crop.width).min(options.width - x),
+                height: scaled(crop.height).min(options.height - y),
+            };
+        }
+        if scale_samples {
+            options.render.samples_per_pixel = scaled(options.render.samples_per_pixel);
+            max_samples = scaled(max_samples);
+        }
+    } else if scale_samples {
+        return Err("`--scale-samples` needs `--scale`".to_string());
+    }
+    match options.width.checked_mul(options.height) {
+        Some(pixels) if pixels <= input::MAX_PIXELS => {}
+        _ => {
+            return Err(format!(
+                "{} by {} pixels is too large an image",
+                options.width, options.height
+            ))
+        }
+    }
+    options.render.adaptive = threshold.map(|threshold| AdaptiveSampling {
+        threshold,
+        max_samples,
+    });
+    options.render.adaptive_passes = match (pass_threshold, max_passes) {
+        (Some(threshold), max_passes) => Some(AdaptivePasses {
+            threshold,
+            max_passes: max_passes.unwrap_or(16),
+        }),
+        (None, Some(_)) => return Err("`--max-passes` needs `--adaptive-passes`".to_string()),
+        (None, None) => None,
+    };
+    if let (Some(denoiser), Some(radius)) = (&mut options.denoise, denoise_radius) {
+        denoiser.radius = radius;
+    }
+    options.image.format = match format {
+        Some(format) => format,
+        None if options.serve.is_some() => Format::Png,
+        None if options.output == "-" => Format::Ppm,
+        None => Format::from_path(&options.output)
+            .ok_or_else(|| format!("`{}`: unsupported image format", options.output))?,
+    };
+    if options.image.alpha != Alpha::None && !options.image.format.has_alpha() {
+        return Err(format!(
+            "`--alpha` needs a png or exr output, not {}",
+            options.image.format
+        ));
+    }
+    if !options.aovs.is_empty() && options.output == "-" {
+        return Err("`--aov` needs an output file, not standard output".to_string());
+    }
+    if options.frames.is_some() && options.output == "-" {
+        return Err("`--frames` needs an output file, not standard output".to_string());
+    }
+    if options.stats_json.as_deref() == Some("-") && options.output == "-" {
+        return Err("`--stats-json -` and `-o -` can't both write to standard output".to_string());
+    }
+    if options.turntable.is_some() && options.frames.is_none() {
+        return Err("`--turntable` needs `--frames`".to_string());
+    }
+    if options.video.is_some() && options.frames.is_none() {
+        return Err("`--video` needs `--frames`".to_string());
+    }
+    if options.temporal && options.frames.is_none() {
+        return Err("`--temporal` needs `--frames`".to_string());
+    }
+    if options.video.is_some() && !options.aovs.is_empty() {
+        return Err("`--aov` needs image files, not a video".to_string());
+    }
+    for (name, path) in [
+        ("depth-out", &options.depth_out),
+        ("heatmap", &options.heatmap),
+    ] {
+        match path {
+            Some(path) if Format::from_path(path).is_none() => {
+                return Err(format!("`{}`: unsupported image format", path));
+            }
+            Some(_) if options.video.is_some() => {
+                return Err(format!("`--{}` needs image files, not a video", name));
+            }
+            _ => {}
+        }
+    }
+    if options.depth_out.is_none() && options.depth_range.is_some() {
+        return Err("`--depth-range` needs `--depth-out`".to_string());
+    }
+    // Paths that would only fail once rendered are turned down now
+    let first_frame = options.frames.map(|_| 1);
+    for pattern in [
+        Some(&options.output),
+        options.depth_out.as_ref(),
+        options.heatmap.as_ref(),
+    ]
+    .iter()
+    .flatten()
+    {
+        // Output variables and frames are named after the file
+        let path = output_path(pattern, &options, first_frame)?;
+        if Path::new(&path).file_name().is_none() {
+            return Err(format!("`{}` names a directory, not a file", pattern));
+        }
+    }
+    if options.caustics.is_none() && options.caustic_radius.is_some() {
+        return Err("`--caustic-radius` needs `--caustics`".to_string());
+    }
+    if options.irradiance_cache.is_none() && options.cache_samples.is_some() {
+        return Err("`--cache-samples` needs `--irradiance-cache`".to_string());
+    }
+    if options.irradiance_cache.is_some() && options.render.integrator != Integrator::Path {
+        return Err("`--irradiance-cache` needs `--integrator path`".to_string());
+    }
+    if let Some(crop) = &options.render.crop {
+        if crop.x + crop.width > options.width || crop.y + crop.height > options.height {
+            return Err(format!(
+                "`--crop` goes past the edge of the {} by {} image",
+                options.width, options.height
+            ));
+        }
+    } else if options.crop_in_frame {
+        return Err("`--crop-in-frame` needs `--crop`".to_string());
+    }
+    match &mut options.generate {
+        Some(generate) => {
+            if options.scene.is_some() || options.preset.is_some() {
+                return Err(
+                    "`generate` can't be combined with a scene file or `--preset`".to_string(),
+                );
+            }
+            generate.1 = count.unwrap_or(generate.1);
+        }
+        None if count.is_some() || options.save_scene.is_some() => {
+            return Err("`--count` and `--save-scene` need `generate`".to_string());
+        }
+        None => {}
+    }
+    if options.preset.is_some() && options.scene.is_some() {
+        return Err("`--preset` can't be combined with a scene file".to_string());
+    }
+    if options.watch && options.scene.is_none() {
+        return Err("`--watch` needs a scene file".to_string());
+    }
+    if options.watch && options.resume {
+        return Err("`--watch` can't be combined with `--resume`".to_string());
+    }
+    if options.listen.is_some() && (options.watch || options.worker.is_some()) {
+        return Err("`--listen` can't be combined with `--watch` or `--worker`".to_string());
+    }
+    if options.serve.is_some() {
+        if options.scene.is_some() || options.preset.is_some() {
+            return Err(
+                "`serve` renders the scenes posted to it, not a scene file or preset".to_string(),
+            );
+        }
+        if options.frames.is_some()
+            || options.checkpoint.is_some()
+            || options.watch
+            || options.preview
+            || options.listen.is_some()
+            || options.worker.is_some()
+            || !options.aovs.is_empty()
+            || options.depth_out.is_some()
+            || options.heatmap.is_some()
+        {
+            return Err(concat!(
+                "`serve` can't be combined with `--frames`, `--checkpoint`, `--resume`, ",
+                "`--watch`, `--preview`, `--listen`, `--worker`, `--aov`, `--depth-out` ",
+                "or `--heatmap`"
+            )
+            .to_string());
+        }
+    }
+    if options.bench && (options.scene.is_some() || options.preset.is_some()) {
+        return Err("`bench` renders its own scenes, not a scene file or preset".to_string());
+    }
+    if options.frames.is_some() && options.checkpoint.is_some() {
+        return Err("`--frames` can't be combined with `--checkpoint` or `--resume`".to_string());
+    }
+    Ok(options)
 }
 
-struct Light {
-    position: Vec3f,
-    intensity: f32,
+/// Where an output variable is written: next to the image, with the name of
+/// the variable before the extension.
+fn aov_path(output: &str, aov: Aov) -> String {
+    let path = Path::new(output);
+    let name = match path.extension() {
+        Some(extension) => format!(
+            "{}.{}.{}",
+            path.file_stem().unwrap().to_string_lossy(),
+            aov,
+            extension.to_string_lossy()
+        ),
+        None => format!("{}.{}", path.file_name().unwrap().to_string_lossy(), aov),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
 }
 
-impl Light {
-    fn new(position: Vec3f, intensity: f32) -> Self {
-        Self {
-            position,
-            intensity,
+/// Where frame `frame` of an animation is written: the output with the
+/// number of the frame after its name, such as `out_0001.ppm`.
+fn frame_path(output: &str, frame: usize) -> String {
+    let path = Path::new(output);
+    let name = match path.extension() {
+        Some(extension) => format!(
+            "{}_{:04}.{}",
+            path.file_stem().unwrap().to_string_lossy(),
+            frame,
+            extension.to_string_lossy()
+        ),
+        None => format!(
+            "{}_{:04}",
+            path.file_name().unwrap().to_string_lossy(),
+            frame
+        ),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// The path `pattern` names for `frame` of an animation, if the image is
+/// one, with its placeholders filled in from the options: `{scene}`,
+/// `{width}`, `{height}`, `{spp}`, `{passes}`, `{seed}`, `{integrator}`,
+/// `{sampler}` and `{frame}`, whose numbers can be padded with zeros to a
+/// width, as `{frame:04}` is. Frames of patterns without `{frame}` are
+/// numbered as `frame_path` numbers them.
+fn output_path(pattern: &str, options: &Options, frame: Option<usize>) -> Result<String, String> {
+    let mut path = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        path.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(format!("`{}`: `{{` without a `}}`", pattern)),
+        };
+        let placeholder = &rest[start + 1..end];
+        let (name, width) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+        let render = &options.render;
+        let number = match name {
+            "frame" => Some(frame.ok_or("`{frame}` needs `--frames`")?),
+            "width" => Some(options.width),
+            "height" => Some(options.height),
+            "spp" => Some(render.samples_per_pixel),
+            "passes" => Some(render.passes),
+            "seed" => Some(render.seed as usize),
+            _ => None,
+        };
+        let value = match (name, number) {
+            (_, Some(number)) => match width {
+                "" => number.to_string(),
+                _ => match width.strip_prefix('0').map(str::parse::<usize>) {
+                    Some(Ok(width)) => format!("{:0width$}", number, width = width),
+                    _ => {
+                        return Err(format!(
+                            "`{{{}}}`: expected a width padded with zeros, such as `:04`",
+                            placeholder
+                        ))
+                    }
+                },
+            },
+            ("scene", None) => scene_name(options),
+            ("integrator", None) => render.integrator.to_string(),
+            ("sampler", None) => render.sampler.to_string(),
+            _ => return Err(format!("`{}`: unknown placeholder `{{{}}}`", pattern, name)),
+        };
+        if number.is_none() && !width.is_empty() {
+            return Err(format!("`{{{}}}` isn't a number to pad", name));
+        }
+        path.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    Ok(match frame {
+        Some(frame) if !pattern.contains("{frame") => frame_path(&path, frame),
+        _ => path,
+    })
+}
+
+/// The name of the scene: that of its file without the extension, or of the
+/// preset.
+fn scene_name(options: &Options) -> String {
+    match (&options.scene, &options.preset) {
+        (Some(scene), _) => Path::new(scene)
+            .file_stem()
+            .map_or_else(|| scene.clone(), |stem| stem.to_string_lossy().into_owned()),
+        (None, Some(preset)) => preset.clone(),
+        (None, None) => "tutorial".to_string(),
+    }
+}
+
+/// The settings an image was rendered with, to store in it: enough to
+/// render it again, along with how long it took.
+fn render_metadata(
+    options: &Options,
+    (time, frame): (Float, Option<(usize, usize)>),
+    elapsed: Duration,
+) -> Vec<(String, String)> {
+    let command = env::args()
+        .skip(1)
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("'{}'", arg)
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let render = &options.render;
+    let mut metadata = vec![
+        (
+            "Software",
+            format!("tiny-raytracer {}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("Command", command),
+        ("Scene", scene_name(options)),
+        (
+            "Resolution",
+            format!("{}x{}", options.width, options.height),
+        ),
+        ("Samples", render.samples_per_pixel.to_string()),
+        ("Passes", render.passes.to_string()),
+        ("Seed", render.seed.to_string()),
+        ("Integrator", render.integrator.to_string()),
+        ("Sampler", render.sampler.to_string()),
+        ("Time", time.to_string()),
+        ("RenderTime", format!("{:.3} s", elapsed.as_secs_f64())),
+    ];
+    if let Some(commit) = option_env!("TINY_RAYTRACER_COMMIT") {
+        metadata.insert(1, ("Commit", commit.to_string()));
+    }
+    if let Some((frame, frames)) = frame {
+        metadata.push(("Frame", format!("{} of {}", frame, frames)));
+    }
+    metadata
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+}
+
+/// The output variables to render: the ones asked for, and the ones the
+/// denoiser, the depth image and the heatmap need.
+fn render_aovs(options: &Options) -> Vec<Aov> {
+    let mut aovs = options.aovs.clone();
+    let mut needed = vec![];
+    if options.denoise.is_some() {
+        needed.extend(denoise::FEATURES);
+    }
+    if options.depth_out.is_some() {
+        needed.push(Aov::Depth);
+    }
+    if options.heatmap.is_some() {
+        needed.push(Aov::Cost);
+    }
+    for aov in needed {
+        if !aovs.contains(&aov) {
+            aovs.push(aov);
         }
     }
+    aovs
 }
 
-struct Sphere {
-    center: Vec3f,
-    radius: f32,
-    material: Material,
+/// The pixels of the crop on their own, unless the options ask for the whole
+/// image or don't crop it.
+fn crop(image: &Image, options: &Options) -> Option<Image> {
+    match &options.render.crop {
+        Some(crop) if !options.crop_in_frame => Some(image.crop(crop)),
+        _ => None,
+    }
 }
 
-impl Sphere {
-    fn new(center: Vec3f, radius: f32, material: Material) -> Self {
+/// Writes the image, denoised if asked to, and its output variables: as
+/// layers of the one file for EXR, and as files of their own next to it
+/// otherwise. The variables are stored without alpha and with their values
+/// left alone as far as the format allows; the ones only rendered for the
+/// denoiser, the depth image or the heatmap are left out. The `metadata`
+/// is stored in every file that can hold it.
+fn write_images(
+    image: &Image,
+    options: &Options,
+    output: &str,
+    metadata: &[(String, String)],
+) -> error::Result<()> {
+    let image_options = OutputOptions {
+        metadata: metadata.to_vec(),
+        ..options.image.clone()
+    };
+    let cropped = crop(image, options);
+    let image = cropped.as_ref().unwrap_or(image);
+    let denoised = options
+        .denoise
+        .as_ref()
+        .map(|denoiser| denoiser.apply(image));
+    let beauty = denoised.as_ref().unwrap_or(image);
+    if options.image.format == Format::Exr {
+        let layered = Image {
+            width: image.width,
+            height: image.height,
+            pixels: beauty.pixels.clone(),
+            alpha: beauty.alpha.clone(),
+            aovs: (image.aovs.iter())
+                .filter(|(aov, _)| options.aovs.contains(aov))
+                .cloned()
+                .collect(),
+        };
+        return output::write_as(&layered, output, &image_options);
+    }
+    output::write_as(beauty, output, &image_options)?;
+    let aov_options = OutputOptions {
+        format: options.image.format,
+        bit_depth: options.image.bit_depth,
+        transfer: Transfer::Gamma(1.0),
+        metadata: image_options.metadata,
+        ..OutputOptions::default()
+    };
+    for &(aov, _) in &image.aovs {
+        if !options.aovs.contains(&aov) {
+            continue;
+        }
+        output::write_as(
+            &image.aov(aov).unwrap(),
+            aov_path(output, aov),
+            &aov_options,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the depth of the image to `path` as grays, white at the near end
+/// of the range and black at the far end and where nothing was hit. The range
+/// is that of `--depth-range`, or else from the nearest hit to the furthest.
+fn write_depth(image: &Image, options: &Options, path: &str) -> error::Result<()> {
+    let cropped = crop(image, options);
+    let image = cropped.as_ref().unwrap_or(image);
+    let depth = image
+        .aov(Aov::Depth)
+        .ok_or_else(|| io::Error::other("the render has no depth to write"))?;
+    // Pixels partly covered by objects average their depth with the zero
+    // of the background, which the coverage undoes
+    let depths: Vec<_> = (depth.pixels.iter().zip(&image.alpha))
+        .map(|(depth, &alpha)| (alpha > 0.0).then(|| depth.r / alpha))
+        .collect();
+    let (near, far) = options.depth_range.unwrap_or_else(|| {
+        depths
+            .iter()
+            .flatten()
+            .fold((Float::INFINITY, 0.0), |(near, far), &d| {
+                (near.min(d), far.max(d))
+            })
+    });
+    let span = (far - near).max(1e-6);
+    let mut grays = Image::new(image.width, image.height);
+    for ((gray, depth), &alpha) in grays.pixels.iter_mut().zip(&depths).zip(&image.alpha) {
+        if let Some(depth) = depth {
+            let value = ((far - depth) / span).clamp(0.0, 1.0) * alpha;
+            *gray = Color::new(value, value, value);
+        }
+    }
+    let depth_options = OutputOptions {
+        format: Format::from_path(path).unwrap(),
+        bit_depth: options.image.bit_depth,
+        transfer: Transfer::Gamma(1.0),
+        ..OutputOptions::default()
+    };
+    output::write_as(&grays, path, &depth_options)
+}
+
+/// The colors of the heatmap, from no work at all to the most
+const HEAT: [(Float, Float, Float); 6] = [
+    (0.0, 0.0, 0.0),
+    (0.0, 0.0, 1.0),
+    (0.0, 1.0, 0.0),
+    (1.0, 1.0, 0.0),
+    (1.0, 0.0, 0.0),
+    (1.0, 1.0, 1.0),
+];
+
+/// Writes the cost of the pixels to `path` in the colors of `HEAT`, the
+/// last for the costliest pixel of the image, and says how costly that was.
+fn write_heatmap(image: &Image, options: &Options, path: &str) -> error::Result<()> {
+    let cropped = crop(image, options);
+    let image = cropped.as_ref().unwrap_or(image);
+    let cost = image
+        .aov(Aov::Cost)
+        .ok_or_else(|| io::Error::other("the render has no cost to write"))?;
+    let most = cost
+        .pixels
+        .iter()
+        .fold(0.0, |most: Float, cost| most.max(cost.r));
+    let mut colors = Image::new(image.width, image.height);
+    for (color, cost) in colors.pixels.iter_mut().zip(&cost.pixels) {
+        let x = cost.r / most.max(1.0) * (HEAT.len() - 1) as Float;
+        let i = (x as usize).min(HEAT.len() - 2);
+        let ((r0, g0, b0), (r1, g1, b1)) = (HEAT[i], HEAT[i + 1]);
+        let f = x - i as Float;
+        *color = Color::new(r0 + (r1 - r0) * f, g0 + (g1 - g0) * f, b0 + (b1 - b0) * f);
+    }
+    if !options.quiet {
+        eprintln!(
+            "{}: white is {} node visits and triangle tests",
+            path,
+            most.round()
+        );
+    }
+    let heatmap_options = OutputOptions {
+        format: Format::from_path(path).unwrap(),
+        bit_depth: options.image.bit_depth,
+        transfer: Transfer::Gamma(1.0),
+        ..OutputOptions::default()
+    };
+    output::write_as(&colors, path, &heatmap_options)
+}
+
+/// Set by the first Ctrl-C, to stop the render
+static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+#[cfg(unix)]
+fn handle_interrupts(flag: Arc<AtomicBool>) {
+    use std::os::raw::c_int;
+
+    const SIGINT: c_int = 2;
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    // Only async-signal-safe things in here: an atomic store, or giving up
+    // right away when interrupted a second time
+    extern "C" fn handler(_: c_int) {
+        if let Some(flag) = INTERRUPTED.get() {
+            if flag.swap(true, Ordering::SeqCst) {
+                unsafe { _exit(130) }
+            }
+        }
+    }
+
+    if INTERRUPTED.set(flag).is_ok() {
+        unsafe {
+            signal(SIGINT, handler);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn handle_interrupts(_: Arc<AtomicBool>) {}
+
+/// The scene as it is at `time`, with the camera settings of the command
+/// line, and the fraction `turn` of the way around the turntable if there
+/// is one.
+fn load_scene(options: &Options, time: Float, turn: Float) -> error::Result<Scene> {
+    let mut scene = match &options.scene {
+        Some(path) => Scene::load_at_time(path, options.accel, time)?,
+        None => match options.generate {
+            Some((seed, count)) => Scene::parse_at_time(
+                &preset::random_spheres(count, seed),
+                Path::new(""),
+                options.accel,
+                time,
+            )?,
+            None => {
+                let name = options.preset.as_deref().unwrap_or("tutorial");
+                preset::scene(name, options.accel).unwrap()
+            }
+        },
+    };
+    set_camera(options, &mut scene, turn).map_err(io::Error::other)?;
+    trace_caustics(options, &mut scene);
+    cache_irradiance(options, &mut scene);
+    Ok(scene)
+}
+
+/// What the scene is loaded from, for telling whether a checkpoint is of a
+/// render of the same scene: the bytes of its file, or those generated, or
+/// the name of the preset. The files the scene refers to aren't included.
+fn scene_source(options: &Options) -> io::Result<Vec<u8>> {
+    Ok(match (&options.scene, options.generate) {
+        (Some(path), _) => fs::read(path)?,
+        (None, Some((seed, count))) => preset::random_spheres(count, seed).into_bytes(),
+        (None, None) => {
+            let name = options.preset.as_deref().unwrap_or("tutorial");
+            format!("preset {}", name).into_bytes()
+        }
+    })
+}
+
+/// Traces the photons of the caustics of the scene, if the command line
+/// asks for them.
+fn trace_caustics(options: &Options, scene: &mut Scene) {
+    if let Some(photons) = options.caustics {
+        scene.caustics = Some(PhotonMap::trace(scene, photons, options.caustic_radius));
+    }
+}
+
+/// Caches the light diffuse surfaces get from each other over what the
+/// camera sees, if the command line asks for it.
+fn cache_irradiance(options: &Options, scene: &mut Scene) {
+    if let Some(error) = options.irradiance_cache {
+        let samples = options.cache_samples.unwrap_or(256);
+        let size = (options.width, options.height);
+        scene.irradiance_cache = Some(IrradianceCache::build(
+            scene,
+            &options.render,
+            size,
+            (error, samples),
+        ));
+    }
+}
+
+/// Applies the camera settings of the command line to the scene, and turns
+/// the camera the fraction `turn` of the way around the turntable if there
+/// is one.
+fn set_camera(options: &Options, scene: &mut Scene, turn: Float) -> Result<(), String> {
+    // What isn't given on the command line is kept from the scene
+    if let Some(fov) = options.fov {
+        scene.camera.fov = fov;
+    }
+    if let Some(projection) = options.projection {
+        scene.camera.projection = projection;
+    }
+    if scene.camera.projection == Projection::Perspective && scene.camera.fov >= PI {
+        return Err(
+            "perspective cameras see less than 180 degrees, fisheye ones up to 360".to_string(),
+        );
+    }
+    if let Some(aperture) = options.aperture {
+        scene.camera.aperture = aperture;
+    }
+    if let Some(distance) = options.focus_distance {
+        scene.camera.focus_distance = Some(distance);
+    }
+    if let Some(shutter) = options.shutter {
+        scene.camera.shutter = shutter;
+    }
+    let (eye_distance, convergence, layout) = options.stereo;
+    if let Some(eye_distance) = eye_distance {
+        scene
+            .camera
+            .stereo
+            .get_or_insert(Stereo {
+                eye_distance,
+                convergence: None,
+                layout: StereoLayout::default(),
+            })
+            .eye_distance = eye_distance;
+    }
+    if let Some(stereo) = &mut scene.camera.stereo {
+        stereo.convergence = convergence.or(stereo.convergence);
+        stereo.layout = layout.unwrap_or(stereo.layout);
+    } else if convergence.is_some() || layout.is_some() {
+        return Err("`--convergence` and `--stereo-layout` need a stereo camera".to_string());
+    }
+    let (position, target, up) = options.camera;
+    if position.is_some() || target.is_some() || up.is_some() {
+        let position = position.unwrap_or_else(|| scene.camera.position());
+        let target = target.unwrap_or_else(|| scene.camera.target());
+        let up = up.unwrap_or_else(|| scene.camera.up());
+        if (target - position).norm() == 0.0 {
+            return Err("the camera target is at its position".to_string());
+        }
+        scene.camera.aim(position, target, up);
+    }
+    if let Some(turntable) = &options.turntable {
+        turntable.apply(&mut scene.camera, turn);
+    }
+    Ok(())
+}
+
+/// A line on standard error showing how far the render has got and how much
+/// longer it should take, redrawn as tiles finish.
+struct ProgressBar {
+    /// The frame being rendered and how many there are, for animations
+    frame: Option<(usize, usize)>,
+    passes: usize,
+    samples_per_pixel: usize,
+    /// Whether noisy tiles get more passes, which leaves how many samples
+    /// the render will take unknown until it is done
+    adaptive: bool,
+    start: Instant,
+    /// Tiles finished before this run, for renders resumed from a checkpoint
+    previous: Option<usize>,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    fn new(options: &Options, frame: Option<(usize, usize)>) -> Self {
         Self {
-            center,
-            radius,
-            material,
+            frame,
+            passes: options.render.passes.max(1),
+            samples_per_pixel: options.render.samples_per_pixel.max(1),
+            adaptive: options.render.adaptive_passes.is_some(),
+            start: Instant::now(),
+            previous: None,
+            last_draw: None,
         }
     }
 
-    fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
-        let l = self.center - *orig;
-        let tca = l.dot(&dir);
-        let d2 = l.dot(&l) - tca * tca;
-        let radius2 = self.radius * self.radius;
-        if d2 > radius2 {
-            return None;
+    fn update(&mut self, progress: &Progress) {
+        let previous = *self.previous.get_or_insert(progress.completed - 1);
+        let finished = progress.completed == progress.total;
+        if !finished
+            && self
+                .last_draw
+                .is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
         }
-        let thc = (radius2 - d2).sqrt();
-        let t0 = tca - thc;
-        let t1 = tca + thc;
-        if t0 < 0.0 && t1 < 0.0 {
-            None
-        } else if t0 < 0.0 {
-            Some(t1)
-        } else if t1 < 0.0 {
-            Some(t0)
-        } else {
-            let distance = if t0 < t1 { t0 } else { t1 };
-            Some(distance)
+        self.last_draw = Some(Instant::now());
+
+        let fraction = progress.completed as f64 / progress.total as f64;
+        let filled = ((fraction * PROGRESS_WIDTH as f64) as usize).min(PROGRESS_WIDTH);
+        let mut line = match self.frame {
+            Some((frame, frames)) => format!("frame {}/{} ", frame, frames),
+            None => String::new(),
+        };
+        line += &format!(
+            "[{}{}] {:3.0}% {}/{} tiles",
+            "#".repeat(filled),
+            " ".repeat(PROGRESS_WIDTH - filled),
+            fraction * 100.0,
+            progress.completed,
+            progress.total
+        );
+        // Samples per pixel so far, averaged over the image
+        if !self.adaptive {
+            let samples = fraction * (self.passes * self.samples_per_pixel) as f64;
+            line += &format!(
+                ", {:.1}/{} spp",
+                samples,
+                self.passes * self.samples_per_pixel
+            );
+        }
+        // Going by how fast the tiles of this run have finished
+        let done = progress.completed - previous;
+        if !finished && done > 0 {
+            let left = self.start.elapsed().as_secs_f64() / done as f64
+                * (progress.total - progress.completed) as f64;
+            line += &format!(", {} left", duration(left.round() as u64));
+        }
+        eprint!("\r{}\x1b[K", line);
+    }
+
+    /// Ends the line, so that what is printed next goes below it.
+    fn finish(&self) {
+        if self.last_draw.is_some() {
+            eprintln!();
         }
     }
 }
 
-fn reflect(light_dir: &Vec3f, n: &Vec3f) -> Vec3f {
-    *light_dir * (*n * 2.0 * (*light_dir * *n))
+/// Seconds as minutes and seconds, or with hours too if there are any.
+fn duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
 }
 
-fn scene_intersect<'a>(
-    orig: &Vec3f,
-    dir: &Vec3f,
-    spheres: &'a Vec<Sphere>,
-) -> Option<(&'a Sphere, Vec3f, Vec3f)> {
-    // Find the closest intersecting sphere
-    let closest_intersecting = spheres
-        .iter()
-        // Are there other methods which can be used, so we only need to
-        // iterate a single time?
-        .filter_map(|sphere| match sphere.ray_intersect(&orig, &dir) {
-            Some(distance) => Some((distance, sphere)),
-            None => None,
+/// Renders the scene at `time` and `turn`, saving and resuming the checkpoint if there
+/// is one, and writing the image so far to `output` if asked to. Unless the
+/// options ask for quiet, a progress bar shows how far it has got, if
+/// standard error is a terminal, along with the `frame` of the animation.
+/// The image so far is shown in the `preview` window if there is one. With a
+/// `coordinator`, workers connected to it render some of the tiles. With
+/// `--temporal`, the tiles of the `previous` frame that look the same are
+/// kept, and what the camera saw comes back along with the state for the
+/// next frame.
+fn render_frame(
+    options: &Options,
+    (time, turn): (Float, Float),
+    frame: Option<(usize, usize)>,
+    output: Option<&str>,
+    preview: &mut Option<Window>,
+    coordinator: Option<&Coordinator>,
+    previous: Option<(RenderState, FrameView)>,
+) -> error::Result<(RenderState, Option<FrameView>)> {
+    let start = Instant::now();
+    let scene = load_scene(options, time, turn)?;
+    stats::count(|stats| stats.loading += start.elapsed());
+    let (size, tile_size, aovs) = (
+        (options.width, options.height),
+        options.render.tile_size,
+        render_aovs(options),
+    );
+    // The scene is only read again for checkpoints, which are all that keep it
+    let source = match &options.checkpoint {
+        Some(_) => scene_source(options)?,
+        None => vec![],
+    };
+    let fingerprint = Fingerprint::new(&options.render, &source);
+    let mut state = match &options.checkpoint {
+        Some(path) if options.resume => {
+            let (state, stored) = checkpoint::load(path)?;
+            checkpoint::check((&state, &stored), &fingerprint, size, tile_size, &aovs)?;
+            state
+        }
+        _ => RenderState::with_aovs(size.0, size.1, tile_size, &aovs),
+    };
+    let view = options
+        .temporal
+        .then(|| FrameView::capture(&scene, (options.width, options.height)));
+    if let (Some(view), Some((previous, before))) = (&view, &previous) {
+        let kept = temporal::carry_over(&mut state, previous, (before, view));
+        if !options.quiet {
+            eprintln!(
+                "kept {} of {} tiles from the frame before",
+                kept,
+                state.tile_passes.len()
+            );
+        }
+    }
+    drop(previous);
+
+    // Intermediate writes are best effort, a failure shows up at the end.
+    // Checkpoints are saved every minute unless asked to write more often.
+    let interval = options
+        .write_every
+        .or_else(|| options.checkpoint.as_ref().map(|_| CHECKPOINT_INTERVAL));
+    let mut last_write = Instant::now();
+    let mut last_preview: Option<Instant> = None;
+    let start = Instant::now();
+    let mut progress_bar =
+        (!options.quiet && io::stderr().is_terminal()).then(|| ProgressBar::new(options, frame));
+    let update = |progress: &Progress, state: &RenderState| {
+        if let Some(progress_bar) = &mut progress_bar {
+            progress_bar.update(progress);
+        }
+        if let Some(window) = preview.as_mut() {
+            if progress.completed == progress.total
+                || last_preview.is_none_or(|last| last.elapsed() >= PREVIEW_INTERVAL)
+            {
+                let _ = window.show(&state.image, &options.image);
+                last_preview = Some(Instant::now());
+            }
+        }
+        if preview.as_mut().is_some_and(preview_closed) {
+            *preview = None;
+        }
+        if let Some(interval) = interval {
+            if last_write.elapsed() >= interval && progress.completed < progress.total {
+                // Images streamed to standard output are only written once
+                if let Some(output) = output.filter(|&output| output != "-") {
+                    if options.write_every.is_some() {
+                        let _ = write_images(&state.image, options, output, &[]);
+                    }
+                }
+                if let Some(path) = &options.checkpoint {
+                    let _ = checkpoint::save(state, &fingerprint, path);
+                }
+                last_write = Instant::now();
+            }
+        }
+    };
+    let state = match coordinator {
+        Some(coordinator) => {
+            coordinator.resume(&scene, &options.render, state, (time, turn), update)
+        }
+        None => render::resume(&scene, &options.render, state, update),
+    };
+    stats::count(|stats| stats.rendering += start.elapsed());
+    if let Some(progress_bar) = &progress_bar {
+        progress_bar.finish();
+    }
+    if let Some(path) = &options.checkpoint {
+        checkpoint::save(&state, &fingerprint, path)?;
+    }
+    if options.render.adaptive_passes.is_some() && !options.quiet {
+        report_passes(&state);
+    }
+    Ok((state, view))
+}
+
+/// Prints how many tiles took each number of passes, of the tiles the
+/// render covered.
+fn report_passes(state: &RenderState) {
+    let rendered: Vec<_> = state.tile_passes.iter().filter(|&&n| n > 0).collect();
+    let most = rendered.iter().copied().max().copied().unwrap_or(0);
+    let counts: Vec<_> = (1..=most)
+        .filter_map(|passes| {
+            let tiles = rendered.iter().filter(|&&&n| n == passes).count();
+            (tiles > 0).then(|| format!("{}: {}", passes, tiles))
         })
-        .min_by_key(|(distance, _)| *distance as u32);
+        .collect();
+    let average = rendered.iter().copied().sum::<usize>() as f64 / rendered.len().max(1) as f64;
+    eprintln!(
+        "tiles by passes taken: {}; {:.1} passes a tile on average",
+        counts.join(", "),
+        average
+    );
+}
+
+fn main() {
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    });
 
-    match closest_intersecting {
-        Some((distance, sphere)) => {
-            let hit = *orig + (*dir * distance);
-            let n = (hit - sphere.center).normalize();
-            Some((sphere, n, hit))
+    if options.bench {
+        bench(&options);
+        return;
+    }
+    if let (Some((seed, count)), Some(path)) = (options.generate, &options.save_scene) {
+        if let Err(e) = fs::write(path, preset::random_spheres(count, seed)) {
+            eprintln!("error: {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    // Interrupting the service stops it at once
+    if let Some(address) = &options.serve {
+        if let Err(e) = serve(&options, address) {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+    handle_interrupts(options.render.cancel.clone());
+    if let Some(address) = &options.worker {
+        if let Err(e) = work(&options, address) {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let coordinator = options.listen.as_ref().map(|address| {
+        listen(&options, address).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        })
+    });
+    let mut preview = options.preview.then(|| {
+        let title = format!(
+            "tiny-raytracer: {}",
+            options
+                .scene
+                .as_deref()
+                .or(options.preset.as_deref())
+                .unwrap_or("tutorial")
+        );
+        Window::open(options.width, options.height, &title).unwrap_or_else(|e| {
+            eprintln!("error: can't open the preview window: {}", e);
+            process::exit(1);
+        })
+    });
+    if options.watch {
+        watch(&options, &mut preview);
+    }
+    if let Err(e) = run(&options, &mut preview, coordinator.as_ref()) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+    if let Some(window) = &mut preview {
+        if let Err(e) = explore(&options, window) {
+            eprintln!("error: {}", e);
+            process::exit(1);
         }
-        None => None,
     }
 }
 
-fn cast_ray(orig: &Vec3f, dir: &Vec3f, spheres: &Vec<Sphere>, lights: &Vec<Light>) -> Vec3f {
-    match scene_intersect(orig, dir, spheres) {
-        Some((sphere, n, hit)) => {
-            let mut diffuse_light_intensity = 0.0;
-            let mut specular_light_intensity = 0.0;
-            for light in lights {
-                let light_dir = (light.position - hit).normalize();
+/// Lets the camera be moved around the scene in the preview window once the
+/// image is rendered, until the window is closed. After every move the view
+/// is rendered again with a sample per pixel and pass, over as many passes
+/// as the image had samples, and drawn as it refines; the image written
+/// stays as it was.
+fn explore(options: &Options, window: &mut Window) -> error::Result<()> {
+    if !options.quiet {
+        eprintln!(
+            "move the camera with W, A, S, D, R and F, turn it around the target with the \
+             arrow keys or by dragging and zoom with the wheel; C prints the camera, \
+             Escape quits"
+        );
+    }
+    let mut scene = load_scene(options, options.time, 0.0)?;
+    let mut camera = scene.camera;
+    let render_options = RenderOptions {
+        crop: None,
+        passes: options.render.passes.max(1) * options.render.samples_per_pixel.max(1),
+        samples_per_pixel: 1,
+        adaptive: None,
+        adaptive_passes: None,
+        cancel: Arc::new(AtomicBool::new(false)),
+        ..options.render.clone()
+    };
+    let (mut moved, mut quit) = (false, false);
+    loop {
+        while let Some(event) = window.poll() {
+            match steer(&mut camera, event) {
+                Some(true) => moved = true,
+                Some(false) => {}
+                None => quit = true,
+            }
+        }
+        if quit || options.render.cancel.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if !moved {
+            thread::sleep(PREVIEW_INTERVAL / 4);
+            continue;
+        }
 
-                diffuse_light_intensity += light.intensity * 0.0_f32.max(light_dir.dot(&n));
-                specular_light_intensity += (0.0_f32
-                    .max((-reflect(&-light_dir, &n)).dot(dir))
-                    .powf(sphere.material.specular_exponent))
-                    * light.intensity;
+        // Moves made while rendering start the render over, once the first
+        // pass has shown where the camera is
+        moved = false;
+        scene.camera = camera;
+        render_options.cancel.store(false, Ordering::SeqCst);
+        let state = RenderState::new(options.width, options.height, render_options.tile_size);
+        let mut last_preview: Option<Instant> = None;
+        render::resume(&scene, &render_options, state, |progress, state| {
+            let first_pass = progress.completed == progress.total / render_options.passes;
+            if first_pass
+                || progress.completed == progress.total
+                || last_preview.is_none_or(|last| last.elapsed() >= PREVIEW_INTERVAL)
+            {
+                let _ = window.show(&state.image, &options.image);
+                last_preview = Some(Instant::now());
+            }
+            while let Some(event) = window.poll() {
+                match steer(&mut camera, event) {
+                    Some(true) => moved = true,
+                    Some(false) => {}
+                    None => quit = true,
+                }
             }
+            let shown = progress.completed >= progress.total / render_options.passes;
+            if quit || (moved && shown) || options.render.cancel.load(Ordering::SeqCst) {
+                render_options.cancel.store(true, Ordering::SeqCst);
+            }
+        });
+    }
+}
 
-            // return material.diffuse_color * diffuse_light_intensity * material.albedo[0] +
-            // Vec3f(1., 1., 1.)*specular_light_intensity * material.albedo[1];
-            let material = sphere.material;
-            let r = ((material.diffuse_color * diffuse_light_intensity) * material.albedo.0)
-                + (Vec3f::new(1.0, 1.0, 1.0) * (specular_light_intensity * material.albedo.1));
-            r
+/// Moves the camera as an event in the preview window asks, printing it in
+/// the syntax of a scene file for C. Whether the camera moved, or `None` if
+/// the window is to close.
+fn steer(camera: &mut Camera, event: Event) -> Option<bool> {
+    // Steps of a tenth of the distance to the target, and of 5 degrees
+    let step = camera.target().distance(&camera.position()) * 0.1;
+    let angle = PI / 36.0;
+    let zoom: Float = 0.9;
+    match event {
+        Event::Closed | Event::Key(Key::Escape) | Event::Key(Key::Char('q')) => return None,
+        Event::Key(Key::Char('c')) => {
+            println!("\"camera\": {}", camera.to_json());
+            return Some(false);
         }
-        None => Vec3f::new(0.2, 0.7, 0.8), // Background color
+        Event::Key(Key::Char('w')) => camera.fly(0.0, 0.0, step),
+        Event::Key(Key::Char('s')) => camera.fly(0.0, 0.0, -step),
+        Event::Key(Key::Char('a')) => camera.fly(-step, 0.0, 0.0),
+        Event::Key(Key::Char('d')) => camera.fly(step, 0.0, 0.0),
+        Event::Key(Key::Char('r')) => camera.fly(0.0, step, 0.0),
+        Event::Key(Key::Char('f')) => camera.fly(0.0, -step, 0.0),
+        Event::Key(Key::Left) => camera.orbit(-angle, 0.0),
+        Event::Key(Key::Right) => camera.orbit(angle, 0.0),
+        Event::Key(Key::Up) => camera.orbit(0.0, angle),
+        Event::Key(Key::Down) => camera.orbit(0.0, -angle),
+        // The scene turns with the mouse
+        Event::Drag(x, y) => camera.orbit(
+            -x as Float * DRAG_ANGLE.to_radians(),
+            y as Float * DRAG_ANGLE.to_radians(),
+        ),
+        Event::Scroll(steps) => camera.dolly(zoom.powi(steps)),
+        Event::Key(_) => return Some(false),
     }
+    Some(true)
 }
 
-fn render(spheres: &Vec<Sphere>, lights: &Vec<Light>) -> std::io::Result<()> {
-    const WIDTH: usize = 1024;
-    const HEIGHT: usize = 768;
-    const FOV: f32 = std::f32::consts::PI / 2.0;
+/// Whether the preview window was closed, or asked to close with Escape or
+/// Q, handling what else was done to it.
+fn preview_closed(window: &mut Window) -> bool {
+    while let Some(event) = window.poll() {
+        if let Event::Closed | Event::Key(Key::Escape) | Event::Key(Key::Char('q')) = event {
+            return true;
+        }
+    }
+    false
+}
+
+/// Renders the scene again every time its file, or a file it refers to, is
+/// changed, until interrupted. Errors, such as those of a scene saved half
+/// way through an edit, are reported without giving up. Closing the preview
+/// window, if there is one, stops watching.
+fn watch(options: &Options, preview: &mut Option<Window>) -> ! {
+    let scene = Path::new(options.scene.as_ref().unwrap());
+    let modified = |files: &[PathBuf]| -> Vec<Option<SystemTime>> {
+        files
+            .iter()
+            .map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+            .collect()
+    };
+    loop {
+        // Taken before rendering, so that changes made while it renders
+        // aren't missed
+        let mut files = vec![scene.to_path_buf()];
+        files.extend(scene::referenced_files(scene, options.time));
+        let before = modified(&files);
+
+        match run(options, preview, None) {
+            Ok(()) => eprintln!("wrote {}, waiting for changes", options.output),
+            Err(e) => eprintln!("error: {}", e),
+        }
+        let changed = loop {
+            if options.render.cancel.load(Ordering::SeqCst) {
+                process::exit(130);
+            }
+            if options.preview && preview.as_mut().is_none_or(preview_closed) {
+                process::exit(0);
+            }
+            let now = modified(&files);
+            if let Some(i) = (0..files.len()).find(|&i| now[i] != before[i]) {
+                break &files[i];
+            }
+            thread::sleep(WATCH_INTERVAL);
+        };
+        eprintln!("{} changed, rendering again", changed.display());
+    }
+}
 
-    // Initialize the frame buffer with empty [r,g,b] arrays
-    let mut framebuffer = vec![Vec3f::new(0.0, 0.0, 0.0); WIDTH * HEIGHT];
+/// Renders the image or the frames the options ask for, showing them in the
+/// `preview` window if there is one and sharing them out to the workers of
+/// the `coordinator` if there is one.
+fn run(
+    options: &Options,
+    preview: &mut Option<Window>,
+    coordinator: Option<&Coordinator>,
+) -> error::Result<()> {
+    let frames = match options.frames {
+        Some(frames) => (1..=frames)
+            .map(|frame| {
+                // The turntable ends just short of where it started, so
+                // that the frames loop
+                let time = options.time + (frame - 1) as Float / options.fps;
+                let turn = (frame - 1) as Float / frames as Float;
+                (
+                    (time, turn),
+                    Some((frame, frames)),
+                    output_path(&options.output, options, Some(frame)).unwrap(),
+                )
+            })
+            .collect(),
+        None => vec![(
+            (options.time, 0.0),
+            None,
+            output_path(&options.output, options, None).unwrap(),
+        )],
+    };
+    let mut video = match &options.video {
+        Some(path) => Some(Video::start(path, options.fps)?),
+        None => None,
+    };
+    // An interrupted animation stops at the frame it was rendering
+    let mut previous = None;
+    for (moment, frame, output) in frames {
+        let started = Instant::now();
+        let (state, view) = render_frame(
+            options,
+            moment,
+            frame,
+            video.is_none().then_some(&output[..]),
+            preview,
+            coordinator,
+            previous.take(),
+        )?;
+        let image = &state.image;
+        let start = Instant::now();
+        match &mut video {
+            Some(video) => {
+                let cropped = crop(image, options);
+                let image = cropped.as_ref().unwrap_or(image);
+                match &options.denoise {
+                    Some(denoiser) => video.write_frame(&denoiser.apply(image), &options.image)?,
+                    None => video.write_frame(image, &options.image)?,
+                }
+            }
+            None => {
+                let metadata = render_metadata(options, (moment.0, frame), started.elapsed());
+                write_images(image, options, &output, &metadata)?;
+                let numbered =
+                    |path: &str| output_path(path, options, frame.map(|(frame, _)| frame)).unwrap();
+                if let Some(path) = &options.depth_out {
+                    write_depth(image, options, &numbered(path))?;
+                }
+                if let Some(path) = &options.heatmap {
+                    write_heatmap(image, options, &numbered(path))?;
+                }
+            }
+        }
+        stats::count(|stats| stats.writing += start.elapsed());
+        previous = view.map(|view| (state, view));
+        if options.render.cancel.load(Ordering::SeqCst) {
+            match video {
+                Some(video) => {
+                    video.finish()?;
+                    eprintln!(
+                        "interrupted, the frames so far were written to {}",
+                        options.video.as_ref().unwrap()
+                    );
+                }
+                None => eprintln!("interrupted, the partial image was written to {}", output),
+            }
+            process::exit(130);
+        }
+    }
+    if let Some(video) = video {
+        video.finish()?;
+    }
+    report_stats(options)
+}
 
-    for j in 0..HEIGHT {
-        for i in 0..WIDTH {
-            let x =
-                (2.0 * (i as f32 + 0.5) / WIDTH as f32 - 1.0) * (FOV / 2.0).tan() * WIDTH as f32
-                    / HEIGHT as f32;
-            let y = -(2.0 * (j as f32 + 0.5) / HEIGHT as f32 - 1.0) * (FOV / 2.0).tan();
-            let dir = Vec3f::new(x, y, -1.0).normalize();
-            framebuffer[i + j * WIDTH] =
-                cast_ray(&Vec3f::new(0.0, 0.0, 0.0), &dir, &spheres, &lights);
+/// Listens for workers at `address`, to send them the job of rendering with
+/// the command line of this one, less `--listen`.
+fn listen(options: &Options, address: &str) -> Result<Coordinator, String> {
+    let mut args = vec![];
+    let mut given = env::args().skip(1);
+    while let Some(arg) = given.next() {
+        match arg.as_str() {
+            "--listen" => {
+                given.next();
+            }
+            _ => args.push(arg),
         }
     }
+    let scene = options.scene.as_deref().map(Path::new);
+    let job = Job::new(args, scene, options.time)
+        .map_err(|e| format!("{}: {}", scene.unwrap().display(), e))?;
+    let coordinator = Coordinator::listen(address, job)
+        .map_err(|e| format!("can't listen for workers at {}: {}", address, e))?;
+    if !options.quiet {
+        eprintln!("listening for workers at {}", address);
+    }
+    Ok(coordinator)
+}
 
-    let mut f = File::create("out.ppm")?;
+/// Renders for the coordinator at `address` until it is done. The scene and
+/// the rest of the options come from the job it sends.
+fn work(options: &Options, address: &str) -> error::Result<()> {
+    remote::work(
+        address,
+        options.render.threads,
+        |args, scene, (time, turn)| {
+            let mut job = parse_args(args.iter().cloned()).map_err(io::Error::other)?;
+            job.scene = scene.map(|path| path.to_string_lossy().into_owned());
+            Ok(Frame {
+                scene: load_scene(&job, time, turn)?,
+                options: job.render.clone(),
+                image_size: (job.width, job.height),
+                aovs: render_aovs(&job),
+            })
+        },
+    )
+}
 
-    // Write the header
-    write!(f, "P6\n{} {}\n255\n", &WIDTH, &HEIGHT)?;
+/// Answers HTTP requests at `address`, rendering the scenes posted with the
+/// options, and with files they refer to relative to the working directory,
+/// which they can't read outside of.
+fn serve(options: &Options, address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    if !options.quiet {
+        eprintln!("serving at http://{}", listener.local_addr()?);
+    }
+    let image_type = match options.image.format {
+        Format::Png => "image/png",
+        Format::Ppm | Format::PpmAscii => "image/x-portable-pixmap",
+        Format::Exr => "image/x-exr",
+        Format::Hdr => "image/vnd.radiance",
+    };
+    serve::serve(listener, image_type, |source, cancel, progress| {
+        let mut scene = files::within(Path::new("."), || {
+            Scene::parse_at_time(source, Path::new(""), options.accel, options.time)
+        })??;
+        set_camera(options, &mut scene, 0.0).map_err(io::Error::other)?;
+        trace_caustics(options, &mut scene);
+        cache_irradiance(options, &mut scene);
+        let render_options = RenderOptions {
+            cancel: Arc::clone(cancel),
+            ..options.render.clone()
+        };
+        let state = RenderState::with_aovs(
+            options.width,
+            options.height,
+            render_options.tile_size,
+            &render_aovs(options),
+        );
+        let state = render::resume(&scene, &render_options, state, |done, _| {
+            progress(done.completed as Float / done.total as Float)
+        });
+        if cancel.load(Ordering::SeqCst) {
+            return Err(io::Error::other("cancelled").into());
+        }
+        let cropped = crop(&state.image, options);
+        let image = cropped.as_ref().unwrap_or(&state.image);
+        let mut encoded = vec![];
+        match &options.denoise {
+            Some(denoiser) => output::encode(&denoiser.apply(image), &options.image, &mut encoded)?,
+            None => output::encode(image, &options.image, &mut encoded)?,
+        }
+        Ok(encoded)
+    })
+}
 
-    for frame in framebuffer.iter().take(HEIGHT * WIDTH) {
-        for i in 0..3 {
-            let z = match i {
-                0 => frame.0,
-                1 => frame.1,
-                2 => frame.2,
-                _ => 0.0,
-            };
-            let color = (255.0 * 0.0_f32.max(1.0_f32.min(z))) as u8;
-            f.write_all(&[color])?;
+/// The scenes `bench` renders, with the integrator, size and samples per
+/// pixel of each, picked to take a second or so on one core.
+const BENCH_SCENES: [(&str, Integrator, usize, usize, usize); 3] = [
+    ("tutorial", Integrator::Direct, 640, 480, 4),
+    ("spheres", Integrator::Path, 320, 180, 4),
+    ("cornell", Integrator::Path, 128, 128, 8),
+];
+
+/// Renders the benchmark scenes with the threads and acceleration structure
+/// of the options, and prints the time each took and the rays it traced per
+/// second as JSON, along with the totals. Nothing else of the options is
+/// used, so that runs compare.
+fn bench(options: &Options) {
+    let mut features = vec![];
+    for (feature, on) in [
+        ("simd", cfg!(feature = "simd")),
+        ("f64", cfg!(feature = "f64")),
+        ("portable-simd", cfg!(feature = "portable-simd")),
+    ] {
+        if on {
+            features.push(format!("\"{}\"", feature));
+        }
+    }
+    let (mut scenes, mut total_rays, mut total_time) = (vec![], 0, 0.0);
+    for &(name, integrator, width, height, samples_per_pixel) in &BENCH_SCENES {
+        let start = Instant::now();
+        let scene = preset::scene(name, options.accel).unwrap();
+        let loading = start.elapsed().as_secs_f64();
+        let render_options = RenderOptions {
+            integrator,
+            samples_per_pixel,
+            threads: options.render.threads,
+            ..RenderOptions::default()
+        };
+        stats::take();
+        let start = Instant::now();
+        let state = RenderState::new(width, height, render_options.tile_size);
+        render::resume(&scene, &render_options, state, |_, _| {});
+        let rendering = start.elapsed().as_secs_f64();
+        let rays = stats::take().rays();
+        let mrays = rays as f64 / rendering / 1e6;
+        if !options.quiet {
+            eprintln!("{}: {:.3} s, {:.2} Mrays/s", name, rendering, mrays);
         }
+        scenes.push(format!(
+            concat!(
+                "{{\"scene\": \"{}\", \"integrator\": \"{}\", \"width\": {}, \"height\": {}, ",
+                "\"spp\": {}, \"loading\": {:.6}, \"rendering\": {:.6}, \"rays\": {}, ",
+                "\"mrays_per_second\": {:.3}}}"
+            ),
+            name, integrator, width, height, samples_per_pixel, loading, rendering, rays, mrays
+        ));
+        total_rays += rays;
+        total_time += rendering;
     }
+    println!(
+        concat!(
+            "{{\"threads\": {}, \"accel\": \"{}\", \"features\": [{}], \"scenes\": [{}], ",
+            "\"rendering\": {:.6}, \"rays\": {}, \"mrays_per_second\": {:.3}}}"
+        ),
+        options.render.threads,
+        options.accel,
+        features.join(", "),
+        scenes.join(", "),
+        total_time,
+        total_rays,
+        total_rays as f64 / total_time / 1e6
+    );
+}
 
+/// Prints or writes the work done by the render, if the options ask for it.
+fn report_stats(options: &Options) -> error::Result<()> {
+    let stats = stats::take();
+    if options.stats {
+        eprintln!("{}", stats);
+    }
+    match options.stats_json.as_deref() {
+        Some("-") => println!("{}", stats.to_json()),
+        Some(path) => fs::write(path, stats.to_json() + "\n")?,
+        None => {}
+    }
     Ok(())
 }
 
-fn main() -> std::io::Result<()> {
-    let ivory = Material::new(Vec2f::new(0.6, 0.3), Vec3f::new(0.4, 0.4, 0.3), 50.0);
-    let red_rubber = Material::new(Vec2f::new(0.9, 0.1), Vec3f::new(0.3, 0.1, 0.1), 10.0);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut spheres = vec![];
-    spheres.push(Sphere::new(Vec3f::new(7., 5., -18.), 4.0, ivory));
-    spheres.push(Sphere::new(Vec3f::new(-3.0, 0.0, -16.0), 2.0, ivory));
-    spheres.push(Sphere::new(Vec3f::new(-1.0, -1.5, -12.), 2.0, red_rubber));
-    spheres.push(Sphere::new(Vec3f::new(1.5, -0.5, -18.), 3.0, red_rubber));
+    /// The error the arguments are turned down with, if they are.
+    fn refused(args: &str) -> Option<String> {
+        parse_args(args.split_whitespace().map(str::to_string)).err()
+    }
 
-    let mut lights = vec![];
-    lights.push(Light::new(Vec3f::new(-20., 20., 20.), 1.5));
-    lights.push(Light::new(Vec3f::new(30., 50., -25.), 1.8));
-    lights.push(Light::new(Vec3f::new(30., 20., 30.), 1.7));
+    #[test]
+    fn refuses_images_too_large_to_hold() {
+        assert_eq!(refused("--width 16384 --height 16384"), None);
+        assert_eq!(
+            refused("--width 100000 --height 100000").as_deref(),
+            Some("100000 by 100000 pixels is too large an image")
+        );
+        assert_eq!(refused("--width 1024 --height 768 --scale 1500%"), None);
+        assert!(refused("--scale 1e9").is_some_and(|e| e.contains("too large")));
+        assert!(refused(&format!("--width {} --height 2", usize::MAX)).is_some());
+    }
 
-    render(&spheres, &lights)
+    #[test]
+    fn refuses_outputs_that_name_no_file() {
+        for output in &["/", "renders/..", "{scene}/.."] {
+            assert_eq!(
+                refused(&format!("-o {} --format png --aov depth", output)),
+                Some(format!("`{}` names a directory, not a file", output))
+            );
+        }
+        assert_eq!(refused("-o renders/out.png --aov depth --frames 2"), None);
+        assert_eq!(
+            aov_path("renders/out.png", Aov::Depth),
+            "renders/out.depth.png"
+        );
+        assert_eq!(frame_path("renders/out", 3), "renders/out_0003");
+    }
 }
@@ -0,0 +1,546 @@
+use std::sync::Arc;
+
+use crate::render::luminance;
+use crate::texture::Texture;
+use crate::vector::{consts::PI, reflect, refract, Float, Vec2f, Vec3f};
+
+/// How far apart, in texture coordinates, bump maps are compared to find
+/// their slope
+const BUMP_DELTA: Float = 1e-3;
+
+/// The physically based model of glTF and most modelling tools, in place of
+/// the Phong one: the diffuse color is the base color, which dielectrics
+/// scatter diffusely and metals reflect, with GGX microfacets as rough as
+/// `roughness`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetallicRoughness {
+    /// From 0 for dielectrics to 1 for metals
+    pub metallic: Float,
+    /// From 0 for a mirror to 1, on the perceptual scale of glTF, the
+    /// square root of the GGX roughness
+    pub roughness: Float,
+}
+
+impl MetallicRoughness {
+    /// The square of the GGX roughness, kept off zero so that highlights
+    /// stay finite
+    fn alpha2(&self) -> Float {
+        (self.roughness * self.roughness).max(1e-3).powi(2)
+    }
+
+    /// Fresnel after Schlick, from 4% for dielectrics to the base color for
+    /// metals at normal incidence, where light arrives `cos` from the
+    /// microfacet normal.
+    fn fresnel(&self, base_color: &Vec3f, cos: Float) -> Vec3f {
+        let white = Vec3f::new(1.0, 1.0, 1.0);
+        let f0 = white * (0.04 * (1.0 - self.metallic)) + *base_color * self.metallic;
+        f0 + (white - f0) * (1.0 - cos.max(0.0)).powi(5)
+    }
+
+    /// The shadowing and masking of microfacets, after Smith, for light and
+    /// view `cos_light` and `cos_view` from the normal.
+    fn geometry(alpha2: Float, cos_light: Float, cos_view: Float) -> Float {
+        let masking = |cos: Float| 2.0 * cos / (cos + (alpha2 + (1.0 - alpha2) * cos * cos).sqrt());
+        masking(cos_light) * masking(cos_view)
+    }
+
+    /// The diffuse and specular light a surface of the base color, with
+    /// normal `n`, reflects towards the unit direction `view` from a light
+    /// of intensity one along the unit direction `light`, in the units of
+    /// the Phong model: a white diffuse surface facing the light reflects
+    /// one.
+    pub fn reflect(
+        &self,
+        base_color: &Vec3f,
+        n: &Vec3f,
+        light: &Vec3f,
+        view: &Vec3f,
+    ) -> (Vec3f, Vec3f) {
+        let black = Vec3f::new(0.0, 0.0, 0.0);
+        let (cos_light, cos_view) = (n.dot(light), n.dot(view));
+        if cos_light <= 0.0 || cos_view <= 0.0 {
+            return (black, black);
+        }
+        let half = (*light + *view).normalize();
+        let alpha2 = self.alpha2();
+        let fresnel = self.fresnel(base_color, view.dot(&half));
+        let cos_half = n.dot(&half).max(0.0);
+        let d = cos_half * cos_half * (alpha2 - 1.0) + 1.0;
+        let distribution = alpha2 / (PI * d * d);
+        let geometry = Self::geometry(alpha2, cos_light, cos_view);
+
+        let white = Vec3f::new(1.0, 1.0, 1.0);
+        let diffuse = (white - fresnel) * *base_color * ((1.0 - self.metallic) * cos_light);
+        let specular = fresnel * (distribution * geometry * PI / (4.0 * cos_view));
+        (diffuse, specular)
+    }
+
+    /// The chance of a path bouncing off the microfacets rather than
+    /// scattering diffusely, seen from `cos_view` off the normal, going by
+    /// how much light each reflects.
+    pub fn specular_chance(&self, base_color: &Vec3f, cos_view: Float) -> Float {
+        let specular = luminance(&self.fresnel(base_color, cos_view));
+        let diffuse = luminance(base_color) * (1.0 - self.metallic);
+        if specular + diffuse > 0.0 {
+            specular / (specular + diffuse)
+        } else {
+            0.0
+        }
+    }
+
+    /// A direction the microfacets reflect the unit direction `view` into,
+    /// off a microfacet normal picked by the sample where the GGX
+    /// distribution has most of them, along with the reflected color over
+    /// the density of the direction. Reflections that would go into the
+    /// surface give none.
+    pub fn sample_specular(
+        &self,
+        base_color: &Vec3f,
+        n: &Vec3f,
+        view: &Vec3f,
+        (u, v): (Float, Float),
+    ) -> Option<(Vec3f, Vec3f)> {
+        let alpha2 = self.alpha2();
+        let cos_half = ((1.0 - u) / (1.0 + (alpha2 - 1.0) * u)).sqrt();
+        let sin_half = (1.0 - cos_half * cos_half).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let (tangent, bitangent) = n.perpendiculars();
+        let half =
+            tangent * (sin_half * phi.cos()) + bitangent * (sin_half * phi.sin()) + *n * cos_half;
+
+        let cos_view_half = view.dot(&half);
+        let light = half * (2.0 * cos_view_half) - *view;
+        let (cos_light, cos_view) = (n.dot(&light), n.dot(view));
+        if cos_light <= 0.0 || cos_view <= 0.0 || cos_view_half <= 0.0 {
+            return None;
+        }
+        // The distribution cancels out against the density of the normals
+        let weight =
+            Self::geometry(alpha2, cos_light, cos_view) * cos_view_half / (cos_view * cos_half);
+        Some((light, self.fresnel(base_color, cos_view_half) * weight))
+    }
+}
+
+/// Light that goes in on the side of the surface facing away from a light
+/// and scatters through to the other, as through wax, skin and marble.
+#[derive(Clone, Copy, Debug)]
+pub struct Subsurface {
+    /// The color the light takes on inside
+    pub color: Vec3f,
+    /// How far the light gets through before half of it is gone
+    pub radius: Float,
+}
+
+/// A clear dielectric, such as glass or water, that light passes into and
+/// out of rather than scattering off, bending by Snell's law on the way.
+/// How much of it is reflected instead goes by the Fresnel equations: a few
+/// percent head on, and all of it at grazing angles, or past the critical
+/// angle on the way out.
+#[derive(Clone, Copy, Debug)]
+pub struct Glass {
+    /// How many times slower light is inside than outside, 1.5 for window
+    /// glass and 1.33 for water
+    pub refractive_index: Float,
+    /// How the light going through is dimmed and colored, if it is; clear
+    /// glass lets all of it through
+    pub absorption: Option<Absorption>,
+    /// How little the refractive index changes with the wavelength, for
+    /// the spectral integrator, about 60 for window glass and 30 for flint
+    /// glass; the index is the same for every wavelength without one
+    pub abbe_number: Option<Float>,
+}
+
+/// The light going through colored glass takes on `color` over every
+/// `distance` it travels, by the Beer–Lambert law, so that thick glass comes
+/// out darker and more deeply colored than thin glass of the same color.
+#[derive(Clone, Copy, Debug)]
+pub struct Absorption {
+    pub color: Vec3f,
+    pub distance: Float,
+}
+
+impl Absorption {
+    /// The fraction of each channel of the light left after `distance`
+    /// through the glass.
+    pub fn transmittance(&self, distance: Float) -> Vec3f {
+        let lengths = distance / self.distance.max(1e-6);
+        let channel = |c: Float| c.max(0.0).powf(lengths);
+        Vec3f::new(
+            channel(self.color.0),
+            channel(self.color.1),
+            channel(self.color.2),
+        )
+    }
+}
+
+impl Glass {
+    /// The refractive index for light of `wavelength` nanometres, by
+    /// Cauchy's equation through `refractive_index` at the yellow helium
+    /// line and the Abbe number.
+    pub fn index_at(&self, wavelength: Float) -> Float {
+        let abbe_number = match self.abbe_number {
+            Some(abbe_number) => abbe_number,
+            None => return self.refractive_index,
+        };
+        // The helium and hydrogen lines the Abbe number is measured at
+        let (yellow, blue, red): (Float, Float, Float) = (587.6, 486.1, 656.3);
+        let inverse_square = |wavelength: Float| 1.0 / (wavelength * wavelength);
+        let spread = (self.refractive_index - 1.0)
+            / (abbe_number * (inverse_square(blue) - inverse_square(red)));
+        self.refractive_index + spread * (inverse_square(wavelength) - inverse_square(yellow))
+    }
+
+    /// The glass as light of `wavelength` nanometres sees it, if it is a
+    /// single wavelength.
+    pub fn at_wavelength(&self, wavelength: Option<Float>) -> Self {
+        Self {
+            refractive_index: wavelength.map_or(self.refractive_index, |w| self.index_at(w)),
+            ..*self
+        }
+    }
+
+    /// The fraction of the light along the unit direction `dir` that the
+    /// surface with the unit normal `n`, pointing out of the glass, reflects
+    /// rather than lets through, from either side.
+    pub fn reflectance(&self, dir: &Vec3f, n: &Vec3f) -> Float {
+        let cos_i = -dir.dot(n).clamp(-1.0, 1.0);
+        let (cos_i, eta_i, eta_t) = if cos_i < 0.0 {
+            (-cos_i, self.refractive_index, 1.0)
+        } else {
+            (cos_i, 1.0, self.refractive_index)
+        };
+        let sin_t2 = (eta_i / eta_t).powi(2) * (1.0 - cos_i * cos_i);
+        if sin_t2 >= 1.0 {
+            return 1.0;
+        }
+        let cos_t = (1.0 - sin_t2).sqrt();
+        let across = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+        let along = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+        (across * across + along * along) / 2.0
+    }
+
+    /// The directions the light along `dir` is reflected and refracted
+    /// into at the surface with the normal `n`, pointing out of the glass,
+    /// none for the refraction past the critical angle, and the fraction of
+    /// the light that is reflected.
+    pub fn split(&self, dir: &Vec3f, n: &Vec3f) -> (Vec3f, Option<Vec3f>, Float) {
+        (
+            reflect(dir, n),
+            refract(dir, n, self.refractive_index),
+            self.reflectance(dir, n),
+        )
+    }
+}
+
+/// What becomes of rays hitting a surface from behind, the side its normal
+/// points away from.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum BackFace {
+    /// Shaded like the front, with the normal the way it points
+    #[default]
+    Shade,
+    /// Passed through, as if there were no surface, so that single-sided
+    /// cards and open meshes are only seen and cast shadows from the front
+    Cull,
+    /// Shaded like the front, with the normal turned towards the ray
+    Flip,
+    /// Shaded with this diffuse color instead of the material's
+    Color(Vec3f),
+}
+
+#[derive(Clone)]
+pub struct Material {
+    pub diffuse_color: Vec3f,
+    /// Takes the place of the diffuse color where it covers the surface
+    pub diffuse_texture: Option<Arc<dyn Texture>>,
+    pub albedo: Vec2f,
+    /// Takes the place of the diffuse and specular weights of the albedo,
+    /// from its red and green channels, where it covers the surface
+    pub albedo_texture: Option<Arc<dyn Texture>>,
+    pub specular_exponent: Float,
+    /// Takes the place of the specular exponent, from its red channel, where
+    /// it covers the surface
+    pub specular_exponent_texture: Option<Arc<dyn Texture>>,
+    /// Light the surface gives off by itself, on the side its normal faces
+    pub emission: Vec3f,
+    /// The light group the light it gives off is in, if any
+    pub light_group: Option<Arc<str>>,
+    /// Normals in the frame of the tangents and the normal of the surface,
+    /// encoded as colors from `0..1` for each of `-1..1`, that take the place
+    /// of the normal
+    pub normal_map: Option<Arc<dyn Texture>>,
+    /// Heights off the surface, as bright as they are high, that bend the
+    /// normal as if the surface were raised by them
+    pub bump_map: Option<Arc<dyn Texture>>,
+    /// The height of white in the bump map
+    pub bump_strength: Float,
+    /// Shades the surface with the metallic-roughness model instead of the
+    /// albedo and specular exponent
+    pub metallic_roughness: Option<MetallicRoughness>,
+    pub subsurface: Option<Subsurface>,
+    /// Passes light through the surface and reflects it as glass does, on
+    /// top of the diffuse and specular light of the albedo
+    pub glass: Option<Glass>,
+    pub back_face: BackFace,
+}
+
+impl Material {
+    pub fn new(albedo: Vec2f, diffuse_color: Vec3f, specular_exponent: Float) -> Self {
+        Self {
+            albedo,
+            diffuse_color,
+            diffuse_texture: None,
+            albedo_texture: None,
+            specular_exponent,
+            specular_exponent_texture: None,
+            emission: Vec3f::new(0.0, 0.0, 0.0),
+            light_group: None,
+            normal_map: None,
+            bump_map: None,
+            bump_strength: 0.1,
+            metallic_roughness: None,
+            subsurface: None,
+            glass: None,
+            back_face: BackFace::default(),
+        }
+    }
+
+    /// The materials of the tutorial, `ivory`, `red_rubber` and `glass`, and
+    /// a `mirror`, by name.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "ivory" => Some(Self::new(
+                Vec2f::new(0.6, 0.3),
+                Vec3f::new(0.4, 0.4, 0.3),
+                50.0,
+            )),
+            "red_rubber" => Some(Self::new(
+                Vec2f::new(0.9, 0.1),
+                Vec3f::new(0.3, 0.1, 0.1),
+                10.0,
+            )),
+            "glass" => Some(Self::glass(
+                Vec2f::new(0.0, 0.5),
+                Vec3f::new(0.6, 0.7, 0.8),
+                125.0,
+                1.5,
+            )),
+            "mirror" => Some(Self::metallic_roughness(
+                Vec3f::new(0.9, 0.9, 0.9),
+                1.0,
+                0.0,
+            )),
+            _ => None,
+        }
+    }
+
+    /// A material of the metallic-roughness model.
+    pub fn metallic_roughness(base_color: Vec3f, metallic: Float, roughness: Float) -> Self {
+        Self {
+            metallic_roughness: Some(MetallicRoughness {
+                metallic,
+                roughness,
+            }),
+            ..Self::new(Vec2f::new(1.0, 0.0), base_color, 1.0)
+        }
+    }
+
+    /// A material of glass of the given refractive index, with the diffuse
+    /// and specular light of the Phong model as well.
+    pub fn glass(
+        albedo: Vec2f,
+        diffuse_color: Vec3f,
+        specular_exponent: Float,
+        refractive_index: Float,
+    ) -> Self {
+        Self {
+            glass: Some(Glass {
+                refractive_index,
+                absorption: None,
+                abbe_number: None,
+            }),
+            ..Self::new(albedo, diffuse_color, specular_exponent)
+        }
+    }
+
+    /// The color the surface scatters diffusely, which is all that the
+    /// bounces of paths carry on.
+    pub fn diffuse_reflectance(&self) -> Vec3f {
+        match &self.metallic_roughness {
+            Some(pbr) => self.diffuse_color * (1.0 - pbr.metallic),
+            None => self.diffuse_color * self.albedo.0,
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission.0 > 0.0 || self.emission.1 > 0.0 || self.emission.2 > 0.0
+    }
+
+    pub fn is_textured(&self) -> bool {
+        self.diffuse_texture.is_some()
+            || self.albedo_texture.is_some()
+            || self.specular_exponent_texture.is_some()
+    }
+
+    pub fn bends_normal(&self) -> bool {
+        self.normal_map.is_some() || self.bump_map.is_some()
+    }
+
+    /// The normal `n` at `point` of a surface with the texture coordinates
+    /// `uv` and the `tangents` there, bent by the normal map and then the
+    /// bump map. Without tangents, any two directions along the surface
+    /// stand in for them, which is fine for patterns through space.
+    pub fn shading_normal(
+        &self,
+        n: &Vec3f,
+        point: &Vec3f,
+        uv: Option<Vec2f>,
+        tangents: Option<(Vec3f, Vec3f)>,
+    ) -> Vec3f {
+        let (du, dv) = tangents.unwrap_or_else(|| n.perpendiculars());
+        let mut n = *n;
+        if let Some(color) = self
+            .normal_map
+            .as_ref()
+            .and_then(|map| map.color(point, uv))
+        {
+            let tangent = (du - n * n.dot(&du)).normalize();
+            let bitangent = n.cross(&tangent);
+            let bitangent = if bitangent.dot(&dv) < 0.0 {
+                -bitangent
+            } else {
+                bitangent
+            };
+            let m = color * 2.0 - Vec3f::new(1.0, 1.0, 1.0);
+            n = (tangent * m.0 + bitangent * m.1 + n * m.2).normalize();
+        }
+        if let Some(map) = &self.bump_map {
+            let height = |offset: Vec3f, du: Float, dv: Float| {
+                let uv = uv.map(|uv| Vec2f::new(uv.0 + du, uv.1 + dv));
+                map.color(&(*point + offset), uv)
+                    .map(|color| luminance(&color) * self.bump_strength)
+            };
+            let heights = (
+                height(Vec3f::new(0.0, 0.0, 0.0), 0.0, 0.0),
+                height(du * BUMP_DELTA, BUMP_DELTA, 0.0),
+                height(dv * BUMP_DELTA, 0.0, BUMP_DELTA),
+            );
+            if let (Some(h), Some(hu), Some(hv)) = heights {
+                // The surface raised by the heights moves with the texture
+                // coordinates along the tangents plus their slope along n
+                let (slope_u, slope_v) = ((hu - h) / BUMP_DELTA, (hv - h) / BUMP_DELTA);
+                let normal = du.cross(&dv);
+                let bent = normal + n.cross(&dv) * slope_u + du.cross(&n) * slope_v;
+                let bent = bent.normalize();
+                n = if normal.dot(&n) < 0.0 { -bent } else { bent };
+            }
+        }
+        n
+    }
+
+    /// The material at `point` of a surface with the texture coordinates
+    /// `uv`, with its textures looked up there, averaged over `footprint`
+    /// in texture coordinates.
+    pub fn at(&self, point: &Vec3f, uv: Option<Vec2f>, footprint: Float) -> Material {
+        let lookup = |texture: &Option<Arc<dyn Texture>>| {
+            texture
+                .as_ref()
+                .and_then(|texture| texture.filtered(point, uv, footprint))
+        };
+        let mut material = self.clone();
+        if let Some(color) = lookup(&self.diffuse_texture) {
+            material.diffuse_color = color;
+        }
+        if let Some(weights) = lookup(&self.albedo_texture) {
+            material.albedo = Vec2f::new(weights.0, weights.1);
+        }
+        if let Some(exponent) = lookup(&self.specular_exponent_texture) {
+            material.specular_exponent = exponent.0;
+        }
+        material
+    }
+}
+
+impl Default for Material {
+    /// A plain grey, purely diffuse material.
+    fn default() -> Self {
+        Self::new(Vec2f::new(1.0, 0.0), Vec3f::new(0.5, 0.5, 0.5), 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glass_reflects_by_fresnel() {
+        let glass = Glass {
+            refractive_index: 1.5,
+            absorption: None,
+            abbe_number: None,
+        };
+        let n = Vec3f::new(0.0, 1.0, 0.0);
+        // 4% head on, from either side
+        let down = Vec3f::new(0.0, -1.0, 0.0);
+        assert!((glass.reflectance(&down, &n) - 0.04).abs() < 1e-6);
+        assert!((glass.reflectance(&-down, &n) - 0.04).abs() < 1e-6);
+        // More at a slant, and all of it grazing
+        let slant = Vec3f::new(1.0, -1.0, 0.0).normalize();
+        assert!(glass.reflectance(&slant, &n) > 0.04);
+        assert!(glass.reflectance(&Vec3f::new(1.0, -1e-4, 0.0).normalize(), &n) > 0.99);
+    }
+
+    #[test]
+    fn glass_reflects_everything_past_the_critical_angle() {
+        let glass = Glass {
+            refractive_index: 1.5,
+            absorption: None,
+            abbe_number: None,
+        };
+        let n = Vec3f::new(0.0, 1.0, 0.0);
+        // Leaving the glass 60° off the normal, past the critical 41.8°
+        let out = Vec3f::new((3.0 as Float).sqrt(), 1.0, 0.0).normalize();
+        let (reflected, refracted, reflectance) = glass.split(&out, &n);
+        assert_eq!(reflectance, 1.0);
+        assert!(refracted.is_none());
+        assert!(reflected.1 < 0.0);
+        // Going in at the same angle bends towards the normal
+        let (_, refracted, reflectance) = glass.split(&-out, &n);
+        assert!(reflectance < 1.0);
+        assert!(refracted.unwrap().1 < -0.8);
+    }
+
+    #[test]
+    fn absorption_compounds_with_distance() {
+        let absorption = Absorption {
+            color: Vec3f::new(1.0, 0.5, 0.25),
+            distance: 2.0,
+        };
+        let channels = |distance| <[Float; 3]>::from(absorption.transmittance(distance));
+        assert_eq!(channels(0.0), [1.0; 3]);
+        assert_eq!(channels(2.0), [1.0, 0.5, 0.25]);
+        // Twice as thick lets the square through
+        assert_eq!(channels(4.0), [1.0, 0.25, 0.0625]);
+    }
+
+    #[test]
+    fn glass_disperses_by_its_abbe_number() {
+        let glass = Glass {
+            refractive_index: 1.6,
+            absorption: None,
+            abbe_number: Some(30.0),
+        };
+        assert!((glass.index_at(587.6) - 1.6).abs() < 1e-5);
+        // The Abbe number is how many times the index exceeds one over its
+        // difference between blue and red
+        let spread = glass.index_at(486.1) - glass.index_at(656.3);
+        assert!((spread - 0.6 / 30.0).abs() < 1e-5, "{}", spread);
+        assert!(glass.index_at(400.0) > glass.index_at(486.1));
+        let clear = Glass {
+            abbe_number: None,
+            ..glass
+        };
+        assert_eq!(clear.index_at(400.0), 1.6);
+        assert_eq!(clear.at_wavelength(Some(400.0)).refractive_index, 1.6);
+    }
+}
@@ -0,0 +1,458 @@
+//! glTF 2.0 import for `.gltf` (with embedded or external buffers) and `.glb`
+//! files. Every triangle primitive becomes its own mesh with the node
+//! transforms baked into its vertices.
+
+use std::path::Path;
+
+use super::Mesh;
+use crate::accel::AcceleratorKind;
+use crate::error::{self, Error};
+use crate::files;
+use crate::json::{self, Value};
+use crate::material::Material;
+use crate::vector::{Float, Mat4, Vec3f};
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+const MODE_TRIANGLES: f64 = 4.0;
+
+fn invalid(message: String) -> Error {
+    Error::parse("gltf", message)
+}
+
+/// The meshes of a `.gltf` or `.glb` file, whose external buffers are
+/// looked for in `base_dir`.
+pub fn parse(bytes: &[u8], base_dir: &Path, accel: AcceleratorKind) -> error::Result<Vec<Mesh>> {
+    let (document, bin) = if read_u32(bytes, 0) == Some(GLB_MAGIC) {
+        parse_glb(bytes)?
+    } else {
+        let source = std::str::from_utf8(bytes).map_err(|_| invalid("not UTF-8".to_string()))?;
+        (source.to_string(), None)
+    };
+    let root = json::parse(&document).map_err(|e| Error::json("gltf", e))?;
+
+    let buffers = array(&root, "buffers")
+        .iter()
+        .enumerate()
+        .map(
+            |(i, buffer)| match buffer.get("uri").and_then(Value::as_str) {
+                Some(uri) => load_uri(uri, base_dir),
+                None if i == 0 => bin.clone().ok_or_else(|| {
+                    invalid("buffer 0 has no uri and there is no BIN chunk".to_string())
+                }),
+                None => Err(invalid(format!("buffer {} has no uri", i))),
+            },
+        )
+        .collect::<error::Result<Vec<_>>>()?;
+
+    let loader = Loader {
+        root: &root,
+        buffers,
+        accel,
+    };
+
+    let mut meshes = vec![];
+    for node in loader.root_nodes()? {
+        loader.visit_node(node, &Mat4::identity(), &mut meshes, 0)?;
+    }
+    Ok(meshes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn parse_glb(bytes: &[u8]) -> error::Result<(String, Option<Vec<u8>>)> {
+    let truncated = || invalid("truncated GLB file".to_string());
+    let mut json = None;
+    let mut bin = None;
+
+    let mut offset = 12;
+    while offset < bytes.len() {
+        let length = read_u32(bytes, offset).ok_or_else(truncated)? as usize;
+        let kind = read_u32(bytes, offset + 4).ok_or_else(truncated)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(truncated)?;
+        match kind {
+            GLB_CHUNK_JSON => json = Some(data),
+            GLB_CHUNK_BIN => bin = Some(data.to_vec()),
+            _ => {}
+        }
+        offset += 8 + length;
+    }
+
+    let json = json.ok_or_else(|| invalid("GLB file without a JSON chunk".to_string()))?;
+    let json = String::from_utf8(json.to_vec()).map_err(|_| invalid("not UTF-8".to_string()))?;
+    Ok((json, bin))
+}
+
+fn load_uri(uri: &str, base_dir: &Path) -> error::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        let data = uri
+            .find(";base64,")
+            .map(|start| &uri[start + 8..])
+            .ok_or_else(|| invalid("only base64 data uris are supported".to_string()))?;
+        decode_base64(data).ok_or_else(|| invalid("invalid base64 data".to_string()))
+    } else {
+        Ok(files::read(&base_dir.join(uri))?)
+    }
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in data.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).unwrap_or(&[])
+}
+
+fn index(value: &Value, key: &str) -> Option<usize> {
+    value.get(key).and_then(Value::as_f64).map(|n| n as usize)
+}
+
+fn numbers(value: &Value, key: &str) -> Option<Vec<Float>> {
+    value.get(key).and_then(Value::as_array).map(|values| {
+        values
+            .iter()
+            .map(|v| v.as_f64().unwrap_or(0.0) as Float)
+            .collect()
+    })
+}
+
+/// A matrix given by columns, as glTF gives them.
+fn from_columns(m: &[Float]) -> Mat4 {
+    let mut rows = [[0.0; 4]; 4];
+    for (c, column) in m.chunks(4).enumerate() {
+        for (r, &value) in column.iter().enumerate() {
+            rows[r][c] = value;
+        }
+    }
+    Mat4(rows)
+}
+
+/// Returns the local transform of a node, given either as a `matrix` or as
+/// `translation`, `rotation` (a quaternion) and `scale`.
+fn node_transform(node: &Value) -> Mat4 {
+    if let Some(m) = numbers(node, "matrix").filter(|m| m.len() == 16) {
+        return from_columns(&m);
+    }
+
+    let t = numbers(node, "translation").unwrap_or_else(|| vec![0.0; 3]);
+    let r = numbers(node, "rotation").unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]);
+    let s = numbers(node, "scale").unwrap_or_else(|| vec![1.0; 3]);
+    if t.len() != 3 || r.len() != 4 || s.len() != 3 {
+        return Mat4::identity();
+    }
+
+    let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+    from_columns(&[
+        (1.0 - 2.0 * (y * y + z * z)) * s[0],
+        (2.0 * (x * y + z * w)) * s[0],
+        (2.0 * (x * z - y * w)) * s[0],
+        0.0,
+        (2.0 * (x * y - z * w)) * s[1],
+        (1.0 - 2.0 * (x * x + z * z)) * s[1],
+        (2.0 * (y * z + x * w)) * s[1],
+        0.0,
+        (2.0 * (x * z + y * w)) * s[2],
+        (2.0 * (y * z - x * w)) * s[2],
+        (1.0 - 2.0 * (x * x + y * y)) * s[2],
+        0.0,
+        t[0],
+        t[1],
+        t[2],
+        1.0,
+    ])
+}
+
+/// A metallic–roughness material as it is, along with its emissive factor.
+fn convert_material(material: &Value) -> Material {
+    let pbr = material.get("pbrMetallicRoughness");
+    let base_color = pbr
+        .and_then(|pbr| numbers(pbr, "baseColorFactor"))
+        .filter(|c| c.len() == 4)
+        .map(|c| Vec3f::new(c[0], c[1], c[2]))
+        .unwrap_or_else(|| Vec3f::new(1.0, 1.0, 1.0));
+    let factor = |key, default| {
+        pbr.and_then(|pbr| pbr.get(key))
+            .and_then(Value::as_f64)
+            .map_or(default, |f| f as Float)
+    };
+    let metallic = factor("metallicFactor", 1.0);
+    let roughness = factor("roughnessFactor", 1.0);
+
+    let mut converted = Material::metallic_roughness(base_color, metallic, roughness);
+    if let Some(e) = numbers(material, "emissiveFactor").filter(|e| e.len() == 3) {
+        converted.emission = Vec3f::new(e[0], e[1], e[2]);
+    }
+    converted
+}
+
+struct Loader<'a> {
+    root: &'a Value,
+    buffers: Vec<Vec<u8>>,
+    accel: AcceleratorKind,
+}
+
+impl<'a> Loader<'a> {
+    fn root_nodes(&self) -> error::Result<Vec<usize>> {
+        let scenes = array(self.root, "scenes");
+        if let Some(scene) = scenes.get(index(self.root, "scene").unwrap_or(0)) {
+            return Ok(array(scene, "nodes")
+                .iter()
+                .filter_map(|n| n.as_f64().map(|n| n as usize))
+                .collect());
+        }
+
+        // Without a scene, every node that isn't somebody's child is a root
+        let nodes = array(self.root, "nodes");
+        let children: Vec<usize> = nodes
+            .iter()
+            .flat_map(|node| array(node, "children"))
+            .filter_map(|n| n.as_f64().map(|n| n as usize))
+            .collect();
+        Ok((0..nodes.len()).filter(|i| !children.contains(i)).collect())
+    }
+
+    fn visit_node(
+        &self,
+        node_index: usize,
+        parent: &Mat4,
+        meshes: &mut Vec<Mesh>,
+        depth: usize,
+    ) -> error::Result<()> {
+        if depth > 64 {
+            return Err(invalid("node hierarchy is too deep or cyclic".to_string()));
+        }
+        let node = array(self.root, "nodes")
+            .get(node_index)
+            .ok_or_else(|| invalid(format!("node {} does not exist", node_index)))?;
+        let transform = *parent * node_transform(node);
+
+        if let Some(mesh_index) = index(node, "mesh") {
+            let mesh = array(self.root, "meshes")
+                .get(mesh_index)
+                .ok_or_else(|| invalid(format!("mesh {} does not exist", mesh_index)))?;
+            for primitive in array(mesh, "primitives") {
+                if let Some(mesh) = self.primitive(primitive, &transform)? {
+                    meshes.push(mesh);
+                }
+            }
+        }
+
+        for child in array(node, "children") {
+            if let Some(child) = child.as_f64() {
+                self.visit_node(child as usize, &transform, meshes, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn primitive(&self, primitive: &Value, transform: &Mat4) -> error::Result<Option<Mesh>> {
+        let mode = primitive
+            .get("mode")
+            .and_then(Value::as_f64)
+            .unwrap_or(MODE_TRIANGLES);
+        if mode != MODE_TRIANGLES {
+            // Points, lines and strips have no use in the tracer
+            return Ok(None);
+        }
+
+        let position = primitive
+            .get("attributes")
+            .and_then(|attributes| index(attributes, "POSITION"))
+            .ok_or_else(|| invalid("primitive without POSITION attribute".to_string()))?;
+        let vertices: Vec<Vec3f> = self
+            .accessor(position, 3)?
+            .chunks(3)
+            .map(|p| Vec3f::new(p[0] as Float, p[1] as Float, p[2] as Float))
+            .map(|p| transform.transform_point(&p))
+            .collect();
+
+        let indices: Vec<usize> = match index(primitive, "indices") {
+            Some(accessor) => self
+                .accessor(accessor, 1)?
+                .into_iter()
+                .map(|i| i as usize)
+                .collect(),
+            None => (0..vertices.len()).collect(),
+        };
+        if let Some(&i) = indices.iter().find(|&&i| i >= vertices.len()) {
+            return Err(invalid(format!("vertex index {} is out of range", i)));
+        }
+        let faces = indices
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+            .collect();
+
+        let material = index(primitive, "material")
+            .and_then(|i| array(self.root, "materials").get(i))
+            .map(convert_material)
+            .unwrap_or_default();
+
+        Ok(Some(Mesh::with_accelerator(
+            vertices, faces, material, self.accel,
+        )))
+    }
+
+    /// Reads an accessor with `components` values per element, converting
+    /// every value to `f64` so that integer indices survive exactly.
+    fn accessor(&self, accessor_index: usize, components: usize) -> error::Result<Vec<f64>> {
+        let accessor = array(self.root, "accessors")
+            .get(accessor_index)
+            .ok_or_else(|| invalid(format!("accessor {} does not exist", accessor_index)))?;
+        let count = index(accessor, "count").unwrap_or(0);
+        let component_type = index(accessor, "componentType").unwrap_or(0);
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => {
+                return Err(invalid(format!(
+                    "unsupported component type {}",
+                    component_type
+                )))
+            }
+        };
+
+        let view = index(accessor, "bufferView")
+            .and_then(|i| array(self.root, "bufferViews").get(i))
+            .ok_or_else(|| invalid(format!("accessor {} has no buffer view", accessor_index)))?;
+        let buffer = index(view, "buffer")
+            .and_then(|i| self.buffers.get(i))
+            .ok_or_else(|| invalid("buffer view refers to a missing buffer".to_string()))?;
+        // Only the bytes of the view, so an accessor can't read the rest of
+        // the buffer
+        let view_start = index(view, "byteOffset").unwrap_or(0);
+        let view_bytes = buffer
+            .get(view_start..)
+            .and_then(|rest| match index(view, "byteLength") {
+                Some(length) => rest.get(..length),
+                None => Some(rest),
+            })
+            .ok_or_else(|| {
+                invalid(format!(
+                    "the buffer view of accessor {} lies outside its buffer",
+                    accessor_index
+                ))
+            })?;
+        let start = index(accessor, "byteOffset").unwrap_or(0);
+        let element_size = component_size * components;
+        let stride = index(view, "byteStride").unwrap_or(element_size);
+        if stride < element_size {
+            return Err(invalid(format!(
+                "the elements of accessor {} overlap",
+                accessor_index
+            )));
+        }
+
+        // The end of the last element, checked before allocating anything
+        // sized by the count
+        let past_view = || {
+            invalid(format!(
+                "accessor {} reads past its buffer view",
+                accessor_index
+            ))
+        };
+        if count > 0 {
+            let end = (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(start))
+                .and_then(|last| last.checked_add(element_size));
+            if end.is_none_or(|end| end > view_bytes.len()) {
+                return Err(past_view());
+            }
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let offset = start + element * stride + component * component_size;
+                let bytes = view_bytes
+                    .get(offset..offset + component_size)
+                    .ok_or_else(past_view)?;
+                let value = match component_type {
+                    5120 => f64::from(bytes[0] as i8),
+                    5121 => f64::from(bytes[0]),
+                    5122 => f64::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+                    5123 => f64::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+                    5125 => f64::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+                    _ => f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+                };
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads accessor 0 as pairs of bytes from a view of `view` over a
+    /// buffer of sixteen bytes counting up.
+    fn read(view: &str, accessor: &str) -> error::Result<Vec<f64>> {
+        let root = json::parse(&format!(
+            r#"{{"bufferViews": [{{"buffer": 0, {}}}],
+                 "accessors": [{{"bufferView": 0, "componentType": 5121, {}}}]}}"#,
+            view, accessor
+        ))
+        .unwrap();
+        let loader = Loader {
+            root: &root,
+            buffers: vec![(0..16).collect()],
+            accel: AcceleratorKind::None,
+        };
+        loader.accessor(0, 2)
+    }
+
+    #[test]
+    fn reads_within_the_view() {
+        let values = read(
+            r#""byteOffset": 4, "byteLength": 8"#,
+            r#""count": 2, "byteOffset": 2"#,
+        );
+        assert_eq!(values.unwrap(), vec![6.0, 7.0, 8.0, 9.0]);
+        let strided = read(r#""byteStride": 5"#, r#""count": 3"#).unwrap();
+        assert_eq!(strided, vec![0.0, 1.0, 5.0, 6.0, 10.0, 11.0]);
+    }
+
+    #[test]
+    fn refuses_to_read_past_the_view() {
+        // The buffer goes on, but the view ends after six bytes
+        assert!(read(r#""byteLength": 6"#, r#""count": 4"#).is_err());
+        assert!(read(r#""byteOffset": 12, "byteLength": 8"#, r#""count": 1"#).is_err());
+    }
+
+    #[test]
+    fn refuses_counts_the_buffer_cannot_hold_without_allocating() {
+        let e = read(r#""byteLength": 16"#, r#""count": 4000000000000000"#).unwrap_err();
+        assert_eq!(e.to_string(), "gltf: accessor 0 reads past its buffer view");
+        assert!(read(r#""byteStride": 0"#, r#""count": 4000000000000000"#).is_err());
+    }
+}
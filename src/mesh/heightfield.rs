@@ -0,0 +1,40 @@
+//! Terrain raised from a grayscale image: a grid of triangles with a vertex
+//! at every pixel, as high as the pixel is bright.
+
+use crate::render::Image;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// The vertices, faces and texture coordinates of the grid of the image,
+/// `width` along X and `depth` along Z, centered on the origin with the top
+/// of the image towards -Z, and white `height` up Y. The faces face up, and
+/// the image covers the grid once. Images need two pixels each way.
+pub fn grid(
+    image: &Image,
+    (width, depth): (Float, Float),
+    height: Float,
+) -> (Vec<Vec3f>, Vec<[usize; 3]>, Vec<[Vec2f; 3]>) {
+    let (columns, rows) = (image.width, image.height);
+    let (last_column, last_row) = ((columns - 1) as Float, (rows - 1) as Float);
+    let mut vertices = Vec::with_capacity(columns * rows);
+    let mut uvs = Vec::with_capacity(columns * rows);
+    for y in 0..rows {
+        for x in 0..columns {
+            let (u, v) = (x as Float / last_column, y as Float / last_row);
+            let elevation = image.pixels[y * columns + x].luminance() * height;
+            vertices.push(Vec3f::new((u - 0.5) * width, elevation, (v - 0.5) * depth));
+            uvs.push(Vec2f::new(u, 1.0 - v));
+        }
+    }
+
+    let mut faces = Vec::with_capacity((columns - 1) * (rows - 1) * 2);
+    for y in 0..rows - 1 {
+        for x in 0..columns - 1 {
+            let (a, b) = (y * columns + x, y * columns + x + 1);
+            let (c, d) = (a + columns, b + columns);
+            faces.push([a, c, b]);
+            faces.push([b, c, d]);
+        }
+    }
+    let texture_coordinates = faces.iter().map(|face| face.map(|i| uvs[i])).collect();
+    (vertices, faces, texture_coordinates)
+}
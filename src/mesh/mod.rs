@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::aabb::Aabb;
+use crate::accel::{Accelerator, AcceleratorKind};
+use crate::error::{self, Error};
+use crate::files;
+use crate::material::Material;
+use crate::object::{triangle, Object};
+use crate::ray::Ray;
+use crate::render::Image;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+mod gltf;
+mod heightfield;
+mod mtl;
+mod obj;
+mod stl;
+
+/// The model file formats that can be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Obj,
+    /// Both `.gltf` and binary `.glb` files, told apart by their contents
+    Gltf,
+    Stl,
+}
+
+impl Format {
+    /// The format of files with the extension, in any case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "obj" => Some(Format::Obj),
+            "gltf" | "glb" => Some(Format::Gltf),
+            "stl" => Some(Format::Stl),
+            _ => None,
+        }
+    }
+}
+
+/// Loads the meshes of a model file, picking the format from the extension,
+/// as [`parse`] does those of the bytes of one.
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    material: Option<Material>,
+    smooth: Option<bool>,
+    accel: AcceleratorKind,
+) -> error::Result<Vec<Mesh>> {
+    let path = path.as_ref();
+    let format = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(Format::from_extension)
+        .ok_or_else(|| Error::unsupported_format(path, ".obj, .gltf, .glb or .stl"))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(
+        &files::read(path)?,
+        format,
+        base_dir,
+        material,
+        smooth,
+        accel,
+    )
+}
+
+/// The meshes of the bytes of a model file of `format`, whose material
+/// libraries and buffers are looked for in `base_dir`. `material` overrides
+/// any materials defined by the file itself, and `accel` picks the
+/// structure used to intersect the faces. OBJ models are split into a mesh
+/// for each material of their MTL libraries, which are only read without
+/// `material`. Meshes are shaded smoothly with the vertex
+/// normals of the file if it has them; `smooth` forces smooth shading on,
+/// working the normals out where the file has none, or off.
+pub fn parse(
+    bytes: &[u8],
+    format: Format,
+    base_dir: &Path,
+    material: Option<Material>,
+    smooth: Option<bool>,
+    accel: AcceleratorKind,
+) -> error::Result<Vec<Mesh>> {
+    let mut meshes = match format {
+        Format::Obj => {
+            let model = obj::parse(bytes)?;
+            let mut library = HashMap::new();
+            if material.is_none() {
+                for file in &model.material_libraries {
+                    library.extend(mtl::load(&base_dir.join(file)).map_err(|e| e.in_file(file))?);
+                }
+            }
+            obj_meshes(model, &library, accel)
+        }
+        Format::Gltf => gltf::parse(bytes, base_dir, accel)?,
+        Format::Stl => {
+            let (vertices, faces) = stl::parse(bytes)?;
+            vec![Mesh::with_accelerator(
+                vertices,
+                faces,
+                stl::default_material(),
+                accel,
+            )]
+        }
+    };
+    if let Some(material) = material {
+        for mesh in &mut meshes {
+            mesh.material = material.clone();
+        }
+    }
+    match smooth {
+        Some(true) => {
+            meshes = meshes
+                .into_iter()
+                .map(|mesh| {
+                    if mesh.normals.is_empty() {
+                        mesh.smoothed()
+                    } else {
+                        mesh
+                    }
+                })
+                .collect()
+        }
+        Some(false) => meshes.iter_mut().for_each(|mesh| mesh.normals.clear()),
+        None => {}
+    }
+    Ok(meshes)
+}
+
+/// The faces of an OBJ model grouped by material into meshes, each with
+/// just the vertices its faces use. Materials missing from the library are
+/// left the default.
+fn obj_meshes(
+    model: obj::Model,
+    library: &HashMap<String, Material>,
+    accel: AcceleratorKind,
+) -> Vec<Mesh> {
+    let material = |index: Option<usize>| {
+        index
+            .and_then(|i| library.get(&model.materials[i]))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let mut groups: Vec<Option<usize>> = vec![];
+    for &index in &model.face_materials {
+        if !groups.contains(&index) {
+            groups.push(index);
+        }
+    }
+    if groups.len() <= 1 {
+        let material = material(groups.first().copied().flatten());
+        return vec![
+            Mesh::with_accelerator(model.vertices, model.faces, material, accel)
+                .with_texture_coordinates(model.texture_coordinates)
+                .with_normals(model.normals),
+        ];
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let mut remap = HashMap::new();
+            let mut vertices = vec![];
+            let mut faces = vec![];
+            let mut texture_coordinates = vec![];
+            let mut normals = vec![];
+            for (i, face) in model.faces.iter().enumerate() {
+                if model.face_materials[i] != group {
+                    continue;
+                }
+                faces.push(face.map(|v| {
+                    *remap.entry(v).or_insert_with(|| {
+                        vertices.push(model.vertices[v]);
+                        vertices.len() - 1
+                    })
+                }));
+                if let Some(tc) = model.texture_coordinates.get(i) {
+                    texture_coordinates.push(*tc);
+                }
+                if let Some(n) = model.normals.get(i) {
+                    normals.push(*n);
+                }
+            }
+            Mesh::with_accelerator(vertices, faces, material(group), accel)
+                .with_texture_coordinates(texture_coordinates)
+                .with_normals(normals)
+        })
+        .collect()
+}
+
+pub struct Mesh {
+    vertices: Vec<Vec3f>,
+    faces: Vec<[usize; 3]>,
+    accel: Box<dyn Accelerator>,
+    bounds: Aabb,
+    material: Material,
+    /// Texture coordinates of the vertices of each face, or none at all
+    texture_coordinates: Vec<[Vec2f; 3]>,
+    /// Normals of the vertices of each face, interpolated across it for
+    /// smooth shading, or none at all for flat faces
+    normals: Vec<[Vec3f; 3]>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3f>, faces: Vec<[usize; 3]>, material: Material) -> Self {
+        Self::with_accelerator(vertices, faces, material, AcceleratorKind::default())
+    }
+
+    pub fn with_accelerator(
+        vertices: Vec<Vec3f>,
+        faces: Vec<[usize; 3]>,
+        material: Material,
+        accel: AcceleratorKind,
+    ) -> Self {
+        let face_bounds: Vec<_> = faces
+            .iter()
+            .map(|face| Aabb::from_points(face.iter().map(|&i| vertices[i])).padded(1e-4))
+            .collect();
+        let bounds = face_bounds
+            .iter()
+            .fold(Aabb::empty(), |bounds, b| bounds.union(b));
+        Self {
+            accel: accel.build(&face_bounds),
+            bounds,
+            vertices,
+            faces,
+            material,
+            texture_coordinates: vec![],
+            normals: vec![],
+        }
+    }
+
+    /// The mesh with the given texture coordinates for the vertices of each
+    /// face.
+    pub fn with_texture_coordinates(mut self, texture_coordinates: Vec<[Vec2f; 3]>) -> Self {
+        self.texture_coordinates = texture_coordinates;
+        self
+    }
+
+    /// The mesh with the given normals for the vertices of each face.
+    pub fn with_normals(mut self, normals: Vec<[Vec3f; 3]>) -> Self {
+        self.normals = normals;
+        self
+    }
+
+    /// The mesh shaded smoothly, each vertex given the average of the
+    /// normals of the faces around it, weighted by their areas. Vertices at
+    /// the same position count as one, as files listing every face with
+    /// vertices of its own, like STL, would otherwise stay faceted.
+    pub fn smoothed(self) -> Self {
+        let (mut sums, mut slots) = (vec![], HashMap::new());
+        let slot: Vec<_> = self
+            .vertices
+            .iter()
+            .map(|v| {
+                let key = (v.0.to_bits(), v.1.to_bits(), v.2.to_bits());
+                *slots.entry(key).or_insert_with(|| {
+                    sums.push(Vec3f::new(0.0, 0.0, 0.0));
+                    sums.len() - 1
+                })
+            })
+            .collect();
+        for face in &self.faces {
+            let (v0, v1, v2) = self.face_vertices(face);
+            // As long as twice the area of the face
+            let n = (*v1 - *v0).cross(&(*v2 - *v0));
+            for &v in face {
+                sums[slot[v]] += n;
+            }
+        }
+        let normals = self
+            .faces
+            .iter()
+            .map(|face| face.map(|v| sums[slot[v]].normalize()))
+            .collect();
+        self.with_normals(normals)
+    }
+
+    /// Terrain raised from the grayscale `image`, `size` across along X and
+    /// Z and white `height` high, centered on the origin with the top of the
+    /// image towards -Z. The image covers it as texture, and it is shaded
+    /// smoothly. `None` if the image is less than two pixels either way.
+    pub fn heightfield(
+        image: &Image,
+        size: (Float, Float),
+        height: Float,
+        material: Material,
+        accel: AcceleratorKind,
+    ) -> Option<Self> {
+        if image.width < 2 || image.height < 2 {
+            return None;
+        }
+        let (vertices, faces, texture_coordinates) = heightfield::grid(image, size, height);
+        Some(
+            Self::with_accelerator(vertices, faces, material, accel)
+                .with_texture_coordinates(texture_coordinates)
+                .smoothed(),
+        )
+    }
+
+    /// Loads a mesh from a Wavefront `.obj` file.
+    pub fn from_obj<P: AsRef<Path>>(path: P, material: Material) -> error::Result<Self> {
+        let file = files::open(path.as_ref())?;
+        let model = obj::parse(BufReader::new(file))?;
+        Ok(Self::new(model.vertices, model.faces, material)
+            .with_texture_coordinates(model.texture_coordinates)
+            .with_normals(model.normals))
+    }
+
+    fn face_vertices(&self, face: &[usize; 3]) -> (&Vec3f, &Vec3f, &Vec3f) {
+        (
+            &self.vertices[face[0]],
+            &self.vertices[face[1]],
+            &self.vertices[face[2]],
+        )
+    }
+
+    /// The face `point` is on, where a ray along `dir` hit the mesh, and the
+    /// barycentric coordinates of the point on it. Which face was hit is lost
+    /// by then, so it is found again from just before the point.
+    fn face_at(&self, point: &Vec3f, dir: &Vec3f) -> Option<(usize, Float, Float)> {
+        let ray = Ray::new(*point - *dir * 1e-3, *dir);
+        self.accel
+            .intersect(&ray, |i, ray| {
+                let (v0, v1, v2) = self.face_vertices(&self.faces[i]);
+                triangle::intersect(v0, v1, v2, &ray.origin, &ray.dir)
+                    .filter(|(t, _, _)| ray.contains(*t))
+                    .map(|(t, u, v)| (t, (i, u, v)))
+            })
+            .map(|(_, face)| face)
+    }
+}
+
+impl Object for Mesh {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.accel
+            .intersect(ray, |i, ray| {
+                let (v0, v1, v2) = self.face_vertices(&self.faces[i]);
+                triangle::intersect(v0, v1, v2, &ray.origin, &ray.dir)
+                    .filter(|(t, _, _)| ray.contains(*t))
+                    .map(|(t, u, v)| (t, (i, u, v)))
+            })
+            .map(|(t, (i, u, v))| match self.normals.get(i) {
+                Some(normals) => (t, triangle::interpolate_normal(normals, u, v)),
+                None => {
+                    let (v0, v1, v2) = self.face_vertices(&self.faces[i]);
+                    (t, triangle::normal(v0, v1, v2))
+                }
+            })
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn texture_coordinates(&self, point: &Vec3f, dir: &Vec3f) -> Option<Vec2f> {
+        if self.texture_coordinates.is_empty() {
+            return None;
+        }
+        let (i, u, v) = self.face_at(point, dir)?;
+        Some(triangle::interpolate(&self.texture_coordinates[i], u, v))
+    }
+
+    fn tangents(&self, point: &Vec3f, dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        if self.texture_coordinates.is_empty() {
+            return None;
+        }
+        let (i, _, _) = self.face_at(point, dir)?;
+        let (v0, v1, v2) = self.face_vertices(&self.faces[i]);
+        triangle::tangents(v0, v1, v2, &self.texture_coordinates[i])
+    }
+}
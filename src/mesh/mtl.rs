@@ -0,0 +1,207 @@
+//! Wavefront MTL material libraries, which OBJ models name with `mtllib`.
+//! The diffuse color `Kd` or texture `map_Kd`, the specular color `Ks`, the
+//! exponent `Ns` and the emission `Ke` are used. `d`, how opaque the
+//! material is, is left out, as nothing in the renderer is see-through.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::{self, Error};
+use crate::files;
+use crate::input;
+use crate::material::Material;
+use crate::render::luminance;
+use crate::texture::ImageTexture;
+use crate::vector::{Vec2f, Vec3f};
+
+fn invalid(line: usize, message: &str) -> Error {
+    Error::at_line("mtl", line, message)
+}
+
+/// The materials of the library at `path` by name. Texture files are
+/// resolved against the directory of the library.
+pub fn load(path: &Path) -> error::Result<HashMap<String, Material>> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+
+    for (i, line) in BufReader::new(files::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            materials.extend(current.take());
+            current = Some((
+                name,
+                Material::new(Vec2f::new(1.0, 0.0), Vec3f::new(0.8, 0.8, 0.8), 1.0),
+            ));
+            continue;
+        }
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None => continue,
+        };
+        let color = |tokens: &mut dyn Iterator<Item = &str>| -> error::Result<Vec3f> {
+            let mut channels = [0.0; 3];
+            for channel in channels.iter_mut() {
+                *channel = tokens
+                    .next()
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| invalid(line_number, "expected three color channels"))?;
+            }
+            Ok(Vec3f::new(channels[0], channels[1], channels[2]))
+        };
+        match keyword {
+            "Kd" => material.diffuse_color = color(&mut tokens)?,
+            // The specular highlights of the Phong model are white, so only
+            // how bright the specular color is carries over
+            "Ks" => material.albedo.1 = luminance(&color(&mut tokens)?),
+            "Ke" => material.emission = color(&mut tokens)?,
+            "Ns" => {
+                material.specular_exponent = tokens
+                    .next()
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| invalid(line_number, "expected a specular exponent"))?
+            }
+            "map_Kd" => {
+                // Options such as `-s` come before the file name
+                let file = tokens
+                    .last()
+                    .ok_or_else(|| invalid(line_number, "expected a texture file"))?;
+                let image = input::read(base_dir.join(file))
+                    .map_err(|e| invalid(line_number, &format!("{}: {}", file, e)))?;
+                material.diffuse_texture = Some(Arc::new(ImageTexture::new(image)));
+            }
+            _ => {}
+        }
+    }
+    materials.extend(current);
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::*;
+    use crate::accel::AcceleratorKind;
+    use crate::vector::{Float, Vec3f};
+
+    /// A directory of its own for each test, holding `files`.
+    fn directory(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tiny-raytracer-mtl-{}-{}", name, process::id()));
+        for (file, contents) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn components(v: Vec3f) -> [Float; 3] {
+        v.into()
+    }
+
+    /// A single red pixel
+    const TEXTURE: &[u8] = b"P6\n1 1\n255\n\xff\x00\x00";
+
+    const LIBRARY: &[u8] = b"# Before any material, so ignored
+Kd 0 0 1
+
+newmtl red paint
+Kd 1 0 0
+Ks 0.5 0.5 0.5
+Ns 50
+map_Kd -s 1 1 1 textures/red.ppm
+
+newmtl lamp
+Ke 4 4 2
+";
+
+    #[test]
+    fn loads_the_materials_by_name() {
+        let dir = directory("load", &[("a.mtl", LIBRARY), ("textures/red.ppm", TEXTURE)]);
+        let materials = load(&dir.join("a.mtl"));
+        fs::remove_dir_all(&dir).unwrap();
+        let materials = materials.unwrap();
+
+        let mut names: Vec<_> = materials.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["lamp", "red paint"]);
+        let red = &materials["red paint"];
+        assert_eq!(components(red.diffuse_color), [1.0, 0.0, 0.0]);
+        assert!((red.albedo.1 - 0.5).abs() < 1e-6);
+        assert_eq!(red.specular_exponent, 50.0);
+        assert!(red.diffuse_texture.is_some());
+        let lamp = &materials["lamp"];
+        assert_eq!(components(lamp.emission), [4.0, 4.0, 2.0]);
+        assert_eq!(components(lamp.diffuse_color), [0.8, 0.8, 0.8]);
+        assert!(lamp.diffuse_texture.is_none());
+    }
+
+    #[test]
+    fn gives_the_faces_of_a_model_the_materials_they_use() {
+        let dir = directory(
+            "model",
+            &[("a.mtl", LIBRARY), ("textures/red.ppm", TEXTURE)],
+        );
+        let source = "mtllib a.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                      usemtl lamp\nf 1 2 3\nusemtl red paint\nf 1 3 4\nf 2 3 4\n\
+                      usemtl unknown\nf 1 2 4\n";
+        let meshes = super::super::parse(
+            source.as_bytes(),
+            super::super::Format::Obj,
+            &dir,
+            None,
+            None,
+            AcceleratorKind::default(),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        let meshes = meshes.unwrap();
+
+        let faces: Vec<_> = meshes.iter().map(|mesh| mesh.faces.len()).collect();
+        assert_eq!(faces, vec![1, 2, 1]);
+        assert_eq!(components(meshes[0].material.emission), [4.0, 4.0, 2.0]);
+        assert_eq!(
+            components(meshes[1].material.diffuse_color),
+            [1.0, 0.0, 0.0]
+        );
+        assert!(meshes[1].material.diffuse_texture.is_some());
+        // Materials missing from the libraries are the default one
+        let default = Material::default();
+        assert_eq!(
+            components(meshes[2].material.diffuse_color),
+            components(default.diffuse_color)
+        );
+    }
+
+    #[test]
+    fn refuses_bad_values() {
+        let dir = directory(
+            "bad",
+            &[
+                ("color.mtl", b"newmtl a\nKd 1 0\n"),
+                ("exponent.mtl", b"newmtl a\nNs high\n"),
+                ("texture.mtl", b"newmtl a\nmap_Kd missing.ppm\n"),
+            ],
+        );
+        let errors: Vec<_> = ["color", "exponent", "texture"]
+            .iter()
+            .map(|name| load(&dir.join(format!("{}.mtl", name))).err())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        for error in errors {
+            assert!(error.unwrap().to_string().contains("line 2"));
+        }
+    }
+}
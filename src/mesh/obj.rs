@@ -0,0 +1,261 @@
+//! A minimal Wavefront OBJ reader. Only vertex positions, texture
+//! coordinates, normals, faces and the materials they use are read;
+//! polygons with more than three vertices are fan-triangulated.
+
+use std::io::BufRead;
+
+use crate::error::{self, Error};
+use crate::object::triangle::{self, DEFAULT_TEXTURE_COORDINATES};
+use crate::vector::{Vec2f, Vec3f};
+
+fn invalid(line: usize, message: &str) -> Error {
+    Error::at_line("obj", line, message)
+}
+
+pub struct Model {
+    pub vertices: Vec<Vec3f>,
+    pub faces: Vec<[usize; 3]>,
+    /// Texture coordinates of the vertices of each face, if the file has
+    /// any. Faces given without them get the `DEFAULT_TEXTURE_COORDINATES`
+    /// of triangles.
+    pub texture_coordinates: Vec<[Vec2f; 3]>,
+    /// Normals of the vertices of each face, if the file has any. Faces
+    /// given without them keep the normal of the face at every vertex.
+    pub normals: Vec<[Vec3f; 3]>,
+    /// The MTL files named by `mtllib`
+    pub material_libraries: Vec<String>,
+    /// The names of the materials the faces use, in the order they first
+    /// come up
+    pub materials: Vec<String>,
+    /// The index into `materials` of the material of each face, if any
+    pub face_materials: Vec<Option<usize>>,
+}
+
+pub fn parse<R: BufRead>(reader: R) -> error::Result<Model> {
+    let mut vertices = vec![];
+    let mut texture_coordinates = vec![];
+    let mut faces = vec![];
+    let mut face_texture_coordinates = vec![];
+    let mut normals = vec![];
+    let mut face_normals = vec![];
+    let mut material_libraries = vec![];
+    let mut materials: Vec<String> = vec![];
+    let mut face_materials = vec![];
+    let mut material = None;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut coords = [0.0; 3];
+                for coord in coords.iter_mut() {
+                    *coord = tokens
+                        .next()
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| invalid(line_number, "expected three vertex coordinates"))?;
+                }
+                vertices.push(Vec3f::new(coords[0], coords[1], coords[2]));
+            }
+            Some("vt") => {
+                let mut coords = [0.0; 2];
+                for coord in coords.iter_mut() {
+                    *coord = tokens
+                        .next()
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| invalid(line_number, "expected two texture coordinates"))?;
+                }
+                texture_coordinates.push(Vec2f::new(coords[0], coords[1]));
+            }
+            Some("vn") => {
+                let mut coords = [0.0; 3];
+                for coord in coords.iter_mut() {
+                    *coord = tokens
+                        .next()
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| invalid(line_number, "expected three normal coordinates"))?;
+                }
+                normals.push(Vec3f::new(coords[0], coords[1], coords[2]).normalize());
+            }
+            Some("f") => {
+                let tokens: Vec<_> = tokens.collect();
+                let indices = tokens
+                    .iter()
+                    .map(|token| index(token, 0, vertices.len(), line_number))
+                    .collect::<error::Result<Option<Vec<_>>>>()?
+                    .ok_or_else(|| invalid(line_number, "face vertex without a position"))?;
+                let texture_indices = tokens
+                    .iter()
+                    .map(|token| index(token, 1, texture_coordinates.len(), line_number))
+                    .collect::<error::Result<Option<Vec<_>>>>()?;
+                let normal_indices = tokens
+                    .iter()
+                    .map(|token| index(token, 2, normals.len(), line_number))
+                    .collect::<error::Result<Option<Vec<_>>>>()?;
+                if indices.len() < 3 {
+                    return Err(invalid(line_number, "a face needs at least three vertices"));
+                }
+                for k in 1..indices.len() - 1 {
+                    faces.push([indices[0], indices[k], indices[k + 1]]);
+                    face_texture_coordinates.push(match &texture_indices {
+                        Some(t) => [
+                            texture_coordinates[t[0]],
+                            texture_coordinates[t[k]],
+                            texture_coordinates[t[k + 1]],
+                        ],
+                        None => DEFAULT_TEXTURE_COORDINATES,
+                    });
+                    face_normals.push(match &normal_indices {
+                        Some(n) => [normals[n[0]], normals[n[k]], normals[n[k + 1]]],
+                        None => {
+                            let (v0, v1, v2) = (
+                                &vertices[indices[0]],
+                                &vertices[indices[k]],
+                                &vertices[indices[k + 1]],
+                            );
+                            [triangle::normal(v0, v1, v2); 3]
+                        }
+                    });
+                    face_materials.push(material);
+                }
+            }
+            Some("mtllib") => material_libraries.extend(tokens.map(str::to_string)),
+            Some("usemtl") => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                material = Some(match materials.iter().position(|m| *m == name) {
+                    Some(index) => index,
+                    None => {
+                        materials.push(name);
+                        materials.len() - 1
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if texture_coordinates.is_empty() {
+        face_texture_coordinates.clear();
+    }
+    if normals.is_empty() {
+        face_normals.clear();
+    }
+    Ok(Model {
+        vertices,
+        faces,
+        texture_coordinates: face_texture_coordinates,
+        normals: face_normals,
+        material_libraries,
+        materials,
+        face_materials,
+    })
+}
+
+/// Resolves one of the indices of a face token, the position index for
+/// `field` 0, the texture coordinate index for 1 or the normal index for 2
+/// in a token such as `3`, `3/1` or `-1//2`, into a zero based index into
+/// the `count` values read so far. Indices left out are `None`.
+fn index(token: &str, field: usize, count: usize, line: usize) -> error::Result<Option<usize>> {
+    let index: i64 = match token.split('/').nth(field) {
+        None | Some("") => return Ok(None),
+        Some(index) => index
+            .parse()
+            .map_err(|_| invalid(line, &format!("invalid face index `{}`", token)))?,
+    };
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(invalid(
+            line,
+            &format!("face index {} is out of range", index),
+        ));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::Float;
+
+    fn parse_str(source: &str) -> error::Result<Model> {
+        parse(source.as_bytes())
+    }
+
+    fn components<T: Copy + Into<[Float; N]>, const N: usize>(values: &[T; 3]) -> [[Float; N]; 3] {
+        values.map(Into::into)
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn splits_polygons_into_fans() {
+        let model = parse_str(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        assert_eq!(model.vertices.len(), 4);
+        assert_eq!(model.faces, vec![[0, 1, 2], [0, 2, 3]]);
+        let model = parse_str(&format!("{}v 0.5 2 0\nf 1 2 3 5 4\n", SQUARE)).unwrap();
+        assert_eq!(model.faces, vec![[0, 1, 2], [0, 2, 4], [0, 4, 3]]);
+    }
+
+    #[test]
+    fn counts_negative_indices_back_from_the_last() {
+        let model = parse_str(&format!("{}f -4 -3 -2\nv 2 2 2\nf -1 -5 -4\n", SQUARE)).unwrap();
+        assert_eq!(model.faces, vec![[0, 1, 2], [4, 0, 1]]);
+    }
+
+    #[test]
+    fn refuses_indices_out_of_range() {
+        for face in &[
+            "f 1 2 5",
+            "f 0 1 2",
+            "f -5 1 2",
+            "f 1/1 2/1 3/1",
+            "f 1//2 2 3",
+        ] {
+            let error = parse_str(&format!("{}{}\n", SQUARE, face)).err().unwrap();
+            assert!(error.to_string().contains("out of range"), "{}", error);
+        }
+        // Only the vertices read so far count
+        assert!(parse_str("v 0 0 0\nv 1 0 0\nf 1 2 3\nv 0 1 0\n").is_err());
+        assert!(parse_str(&format!("{}f 1 2 x\n", SQUARE)).is_err());
+        assert!(parse_str(&format!("{}f 1 2\n", SQUARE)).is_err());
+        assert!(parse_str(&format!("{}f /1 2 3\n", SQUARE)).is_err());
+    }
+
+    #[test]
+    fn leaves_out_missing_normals_and_texture_coordinates() {
+        let model = parse_str(&format!("{}f 1 2 3\n", SQUARE)).unwrap();
+        assert!(model.texture_coordinates.is_empty() && model.normals.is_empty());
+
+        // Faces without them, in files with some, get the defaults
+        let source = format!(
+            "{}vt 0.5 0.5\nvn 0 0 2\nf 1/1/1 2/1/1 3/1/1\nf 1 3 4\nf 1//1 3//1 4//1\n",
+            SQUARE
+        );
+        let model = parse_str(&source).unwrap();
+        let uv = [[0.5, 0.5]; 3];
+        let default = components(&DEFAULT_TEXTURE_COORDINATES);
+        let texture_coordinates: Vec<_> =
+            model.texture_coordinates.iter().map(components).collect();
+        assert_eq!(texture_coordinates, vec![uv, default, default]);
+        let normals: Vec<_> = model.normals.iter().map(components).collect();
+        assert_eq!(normals, vec![[[0.0, 0.0, 1.0]; 3]; 3]);
+    }
+
+    #[test]
+    fn keeps_the_materials_of_the_faces() {
+        let source = format!(
+            "mtllib a.mtl b.mtl\n{}f 1 2 3\nusemtl red paint\nf 1 3 4\nusemtl blue\nf 1 2 4\n\
+             usemtl red paint\nf 2 3 4\n",
+            SQUARE
+        );
+        let model = parse_str(&source).unwrap();
+        assert_eq!(model.material_libraries, vec!["a.mtl", "b.mtl"]);
+        assert_eq!(model.materials, vec!["red paint", "blue"]);
+        assert_eq!(model.face_materials, vec![None, Some(0), Some(1), Some(0)]);
+    }
+}
@@ -0,0 +1,206 @@
+//! Binary and ASCII STL import. The normals stored in STL files are ignored,
+//! since the mesh computes its face normals from the vertex winding anyway.
+
+use crate::error::{self, Error};
+use crate::material::Material;
+use crate::vector::{from_f32, Float, Vec2f, Vec3f};
+
+fn invalid(message: String) -> Error {
+    Error::parse("stl", message)
+}
+
+/// STL carries no material information, so models get a light grey plastic.
+pub fn default_material() -> Material {
+    Material::new(Vec2f::new(0.8, 0.2), Vec3f::new(0.6, 0.6, 0.6), 30.0)
+}
+
+pub fn parse(bytes: &[u8]) -> error::Result<(Vec<Vec3f>, Vec<[usize; 3]>)> {
+    if is_binary(bytes) {
+        parse_binary(bytes)
+    } else {
+        let source = std::str::from_utf8(bytes).map_err(|_| invalid("not UTF-8".to_string()))?;
+        parse_ascii(source)
+    }
+}
+
+/// Binary files may also start with `solid`, so the size implied by the
+/// triangle count is what tells the two flavours apart.
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < 84 {
+        return false;
+    }
+    bytes.len() == binary_size(triangle_count(bytes)) || !bytes.starts_with(b"solid")
+}
+
+fn triangle_count(bytes: &[u8]) -> usize {
+    u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize
+}
+
+/// The size of a binary file of `count` triangles, or the largest there is
+/// where that doesn't fit.
+fn binary_size(count: usize) -> usize {
+    count
+        .checked_mul(50)
+        .and_then(|size| size.checked_add(84))
+        .unwrap_or(usize::MAX)
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> Float {
+    from_f32(f32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ]))
+}
+
+fn parse_binary(bytes: &[u8]) -> error::Result<(Vec<Vec3f>, Vec<[usize; 3]>)> {
+    let count = triangle_count(bytes);
+    if bytes.len() < binary_size(count) {
+        return Err(invalid(format!(
+            "file is truncated, expected {} triangles",
+            count
+        )));
+    }
+
+    let mut vertices = Vec::with_capacity(count * 3);
+    let mut faces = Vec::with_capacity(count);
+    for i in 0..count {
+        // Each record is a normal, three vertices and a two byte attribute
+        let record = 84 + i * 50;
+        for v in 0..3 {
+            let offset = record + 12 + v * 12;
+            vertices.push(Vec3f::new(
+                read_f32(bytes, offset),
+                read_f32(bytes, offset + 4),
+                read_f32(bytes, offset + 8),
+            ));
+        }
+        faces.push([i * 3, i * 3 + 1, i * 3 + 2]);
+    }
+    Ok((vertices, faces))
+}
+
+fn parse_ascii(source: &str) -> error::Result<(Vec<Vec3f>, Vec<[usize; 3]>)> {
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    let mut facet = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("vertex") => {
+                let coords = tokens
+                    .map(|token| token.parse::<Float>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|coords| coords.len() == 3)
+                    .ok_or_else(|| {
+                        Error::at_line("stl", i + 1, "expected three vertex coordinates")
+                    })?;
+                facet.push(Vec3f::new(coords[0], coords[1], coords[2]));
+            }
+            Some("endloop") => {
+                if facet.len() < 3 {
+                    return Err(Error::at_line(
+                        "stl",
+                        i + 1,
+                        "a facet needs at least three vertices",
+                    ));
+                }
+                let first = vertices.len();
+                for k in 1..facet.len() - 1 {
+                    faces.push([first, first + k, first + k + 1]);
+                }
+                vertices.append(&mut facet);
+            }
+            _ => {}
+        }
+    }
+    Ok((vertices, faces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::to_f32;
+
+    const TRIANGLES: [[[Float; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        [[1.0, 0.0, 0.0], [1.0, 1.0, 0.5], [0.0, 1.0, -0.25]],
+    ];
+
+    fn ascii(triangles: &[[[Float; 3]; 3]]) -> String {
+        let mut source = "solid test\n".to_string();
+        for triangle in triangles {
+            source.push_str("  facet normal 0 0 1\n    outer loop\n");
+            for [x, y, z] in triangle {
+                source.push_str(&format!("      vertex {} {} {}\n", x, y, z));
+            }
+            source.push_str("    endloop\n  endfacet\n");
+        }
+        source + "endsolid test\n"
+    }
+
+    fn binary(triangles: &[[[Float; 3]; 3]]) -> Vec<u8> {
+        let mut bytes = vec![0; 80];
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            bytes.extend_from_slice(&[0; 12]);
+            for c in triangle.iter().flatten() {
+                bytes.extend_from_slice(&to_f32(*c).to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 2]);
+        }
+        bytes
+    }
+
+    /// The corners of each face of a parsed mesh.
+    fn triangles((vertices, faces): (Vec<Vec3f>, Vec<[usize; 3]>)) -> Vec<[[Float; 3]; 3]> {
+        faces
+            .iter()
+            .map(|face| face.map(|i| vertices[i].into()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_ascii() {
+        let source = ascii(&TRIANGLES);
+        assert_eq!(triangles(parse(source.as_bytes()).unwrap()), TRIANGLES);
+    }
+
+    #[test]
+    fn parses_binary() {
+        assert_eq!(triangles(parse(&binary(&TRIANGLES)).unwrap()), TRIANGLES);
+        // Even with a header that starts like an ASCII file
+        let mut bytes = binary(&TRIANGLES);
+        bytes[..5].copy_from_slice(b"solid");
+        assert_eq!(triangles(parse(&bytes).unwrap()), TRIANGLES);
+    }
+
+    #[test]
+    fn splits_ascii_polygons() {
+        let source = "solid quad\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\n\
+                      vertex 1 0 0\nvertex 1 1 0\nvertex 0 1 0\nendloop\nendfacet\n";
+        let (vertices, faces) = parse(source.as_bytes()).unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn refuses_more_triangles_than_the_file_holds() {
+        let mut bytes = binary(&TRIANGLES);
+        bytes[80..84].copy_from_slice(&3u32.to_le_bytes());
+        assert!(parse(&bytes).is_err());
+        bytes[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn refuses_bad_ascii_facets() {
+        let source = ascii(&TRIANGLES).replacen("vertex 0 0 0", "vertex 0 0", 1);
+        assert!(parse(source.as_bytes()).is_err());
+        let source = "solid bad\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n";
+        assert!(parse(source.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use super::cylinder::{cap_intersect, positive_roots, sort_crossings};
+use super::disk::disk_bounds;
+use super::{Object, Solid};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec3f};
+
+/// A cone with a capped base of `radius` at `base`, narrowing to its apex
+/// `height` along `axis`.
+pub struct Cone {
+    base: Vec3f,
+    axis: Vec3f,
+    height: Float,
+    radius: Float,
+    material: Material,
+}
+
+impl Cone {
+    pub fn new(base: Vec3f, axis: Vec3f, height: Float, radius: Float, material: Material) -> Self {
+        Self {
+            base,
+            axis: axis.normalize(),
+            height,
+            radius,
+            material,
+        }
+    }
+}
+
+impl Solid for Cone {
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)> {
+        let o = *orig - self.base;
+        let (o_a, d_a) = (o.dot(&self.axis), dir.dot(&self.axis));
+        let o_perp = o - self.axis * o_a;
+        let d_perp = *dir - self.axis * d_a;
+
+        // The radius shrinks linearly: |p_perp| = k (height - p_axis)
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h = self.height - o_a;
+        let mut crossings: Vec<_> = positive_roots(
+            d_perp.dot(&d_perp) - k2 * d_a * d_a,
+            2.0 * (o_perp.dot(&d_perp) + k2 * h * d_a),
+            o_perp.dot(&o_perp) - k2 * h * h,
+        )
+        .into_iter()
+        .filter(|t| (0.0..=self.height).contains(&(o_a + t * d_a)))
+        .map(|t| {
+            let radial = (o_perp + d_perp * t).normalize();
+            (t, (radial + self.axis * k).normalize())
+        })
+        .collect();
+
+        crossings.extend(
+            cap_intersect(&self.base, &self.axis, self.radius, orig, dir).map(|t| (t, -self.axis)),
+        );
+        sort_crossings(&mut crossings);
+        crossings
+    }
+}
+
+impl Object for Cone {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.crossings(&ray.origin, &ray.dir)
+            .into_iter()
+            .find(|(t, _)| ray.contains(*t))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let apex = self.base + self.axis * self.height;
+        Some(disk_bounds(&self.base, &self.axis, self.radius).union(&Aabb::new(apex, apex)))
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Object, Solid};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec3f};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// The left solid with the right one cut away
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Two solids combined with a boolean operation, found by walking the
+/// surface crossings of both children along the ray.
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Solid>,
+    right: Box<dyn Solid>,
+    material: Material,
+}
+
+impl Csg {
+    pub fn new(
+        operation: CsgOperation,
+        left: Box<dyn Solid>,
+        right: Box<dyn Solid>,
+        material: Material,
+    ) -> Self {
+        Self {
+            operation,
+            left,
+            right,
+            material,
+        }
+    }
+}
+
+impl Solid for Csg {
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)> {
+        let left = self.left.crossings(orig, dir);
+        let right = self.right.crossings(orig, dir);
+
+        let mut in_left = left.len() % 2 == 1;
+        let mut in_right = right.len() % 2 == 1;
+        let mut inside = self.operation.inside(in_left, in_right);
+
+        let mut crossings = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() || j < right.len() {
+            let (t, n) = if j == right.len() || (i < left.len() && left[i].0 <= right[j].0) {
+                in_left = !in_left;
+                i += 1;
+                left[i - 1]
+            } else {
+                in_right = !in_right;
+                j += 1;
+                let (t, n) = right[j - 1];
+                // The surface of a cut away solid faces into what remains
+                if self.operation == CsgOperation::Difference {
+                    (t, -n)
+                } else {
+                    (t, n)
+                }
+            };
+
+            let now_inside = self.operation.inside(in_left, in_right);
+            if now_inside != inside {
+                crossings.push((t, n));
+                inside = now_inside;
+            }
+        }
+        crossings
+    }
+}
+
+impl Object for Csg {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.crossings(&ray.origin, &ray.dir)
+            .into_iter()
+            .find(|(t, _)| ray.contains(*t))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounds()?, self.right.bounds()?);
+        Some(match self.operation {
+            CsgOperation::Union => left.union(&right),
+            CsgOperation::Intersection => left.intersection(&right),
+            CsgOperation::Difference => left,
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use super::{Object, Solid};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec3f};
+
+/// A solid axis-aligned box.
+pub struct Cuboid {
+    bounds: Aabb,
+    material: Material,
+}
+
+impl Cuboid {
+    pub fn new(min: Vec3f, max: Vec3f, material: Material) -> Self {
+        Self {
+            bounds: Aabb::new(min, max),
+            material,
+        }
+    }
+}
+
+impl Object for Cuboid {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        let (t_near, t_far) = self.bounds.intersect(&ray.origin, &ray.dir)?;
+        // Rays starting inside the box hit it on the way out
+        let t = if t_near < ray.t_min { t_far } else { t_near };
+        if !ray.contains(t) {
+            return None;
+        }
+        Some((t, self.bounds.normal(&ray.at(t))))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl Solid for Cuboid {
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)> {
+        match self.bounds.intersect(orig, dir) {
+            Some((t_near, t_far)) => [t_near, t_far]
+                .iter()
+                .filter(|&&t| t >= 0.0)
+                .map(|&t| (t, self.bounds.normal(&(*orig + (*dir * t)))))
+                .collect(),
+            None => vec![],
+        }
+    }
+}
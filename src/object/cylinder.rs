@@ -0,0 +1,117 @@
+use super::disk::disk_bounds;
+use super::{Object, Solid};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::poly;
+use crate::ray::Ray;
+use crate::vector::{to_f64, Float, Vec3f};
+
+/// A capped cylinder standing on `base` and extending `height` along `axis`.
+pub struct Cylinder {
+    base: Vec3f,
+    axis: Vec3f,
+    height: Float,
+    radius: Float,
+    material: Material,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3f, axis: Vec3f, height: Float, radius: Float, material: Material) -> Self {
+        Self {
+            base,
+            axis: axis.normalize(),
+            height,
+            radius,
+            material,
+        }
+    }
+}
+
+/// Intersects the ray with a disk shaped cap, returning the hit distance.
+pub(super) fn cap_intersect(
+    center: &Vec3f,
+    normal: &Vec3f,
+    radius: Float,
+    orig: &Vec3f,
+    dir: &Vec3f,
+) -> Option<Float> {
+    let denom = normal.dot(dir);
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+    let t = normal.dot(&(*center - *orig)) / denom;
+    let offset = *orig + (*dir * t) - *center;
+    if t >= 0.0 && offset.norm() <= radius * radius {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// The non-negative roots of `a t^2 + b t + c`.
+pub(super) fn positive_roots(a: Float, b: Float, c: Float) -> Vec<Float> {
+    if a.abs() < 1e-8 {
+        return vec![];
+    }
+    poly::solve_quadratic(to_f64(a), to_f64(b), to_f64(c))
+        .into_iter()
+        .map(|t| t as Float)
+        .filter(|&t| t >= 0.0)
+        .collect()
+}
+
+pub(super) fn sort_crossings(crossings: &mut [(Float, Vec3f)]) {
+    crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+}
+
+impl Solid for Cylinder {
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)> {
+        let o = *orig - self.base;
+        let (o_a, d_a) = (o.dot(&self.axis), dir.dot(&self.axis));
+        let o_perp = o - self.axis * o_a;
+        let d_perp = *dir - self.axis * d_a;
+
+        let mut crossings: Vec<_> = positive_roots(
+            d_perp.dot(&d_perp),
+            2.0 * o_perp.dot(&d_perp),
+            o_perp.dot(&o_perp) - self.radius * self.radius,
+        )
+        .into_iter()
+        .filter(|t| (0.0..=self.height).contains(&(o_a + t * d_a)))
+        .map(|t| (t, (o_perp + d_perp * t).normalize()))
+        .collect();
+
+        let top = self.base + self.axis * self.height;
+        crossings.extend(
+            cap_intersect(&self.base, &self.axis, self.radius, orig, dir).map(|t| (t, -self.axis)),
+        );
+        crossings.extend(
+            cap_intersect(&top, &self.axis, self.radius, orig, dir).map(|t| (t, self.axis)),
+        );
+        sort_crossings(&mut crossings);
+        crossings
+    }
+}
+
+impl Object for Cylinder {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.crossings(&ray.origin, &ray.dir)
+            .into_iter()
+            .find(|(t, _)| ray.contains(*t))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let top = self.base + self.axis * self.height;
+        Some(
+            disk_bounds(&self.base, &self.axis, self.radius).union(&disk_bounds(
+                &top,
+                &self.axis,
+                self.radius,
+            )),
+        )
+    }
+}
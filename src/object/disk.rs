@@ -0,0 +1,70 @@
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{consts::PI, Float, Vec3f};
+
+pub struct Disk {
+    center: Vec3f,
+    normal: Vec3f,
+    radius: Float,
+    material: Material,
+}
+
+impl Disk {
+    pub fn new(center: Vec3f, normal: Vec3f, radius: Float, material: Material) -> Self {
+        Self {
+            center,
+            normal: normal.normalize(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Object for Disk {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        let denom = self.normal.dot(&ray.dir);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(self.center - ray.origin)) / denom;
+        if !ray.contains(t) {
+            return None;
+        }
+
+        let offset = ray.at(t) - self.center;
+        if offset.norm() <= self.radius * self.radius {
+            Some((t, self.normal))
+        } else {
+            None
+        }
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(disk_bounds(&self.center, &self.normal, self.radius))
+    }
+
+    fn area(&self) -> Option<Float> {
+        Some(PI * self.radius * self.radius)
+    }
+
+    fn sample_surface(&self, (u, v): (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        let (tangent, bitangent) = self.normal.perpendiculars();
+        let (r, phi) = (self.radius * u.sqrt(), 2.0 * PI * v);
+        let point = self.center + tangent * (r * phi.cos()) + bitangent * (r * phi.sin());
+        Some((point, self.normal))
+    }
+}
+
+/// Bounds of a disk, whose extent along each axis shrinks the more the disk
+/// faces that axis.
+pub(super) fn disk_bounds(center: &Vec3f, normal: &Vec3f, radius: Float) -> Aabb {
+    let extent = |n: Float| radius * (1.0 - n * n).max(0.0).sqrt();
+    let e = Vec3f::new(extent(normal.0), extent(normal.1), extent(normal.2));
+    Aabb::new(*center - e, *center + e).padded(1e-4)
+}
@@ -0,0 +1,43 @@
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// An object with its material replaced, for lights given by the power they
+/// give off, whose emission goes by the area of the object, which is only
+/// known once it is built.
+pub struct Emitting {
+    pub object: Box<dyn Object>,
+    pub material: Material,
+}
+
+impl Object for Emitting {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.object.ray_intersect(ray)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.object.bounds()
+    }
+
+    fn area(&self) -> Option<Float> {
+        self.object.area()
+    }
+
+    fn sample_surface(&self, uv: (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        self.object.sample_surface(uv)
+    }
+
+    fn texture_coordinates(&self, point: &Vec3f, dir: &Vec3f) -> Option<Vec2f> {
+        self.object.texture_coordinates(point, dir)
+    }
+
+    fn tangents(&self, point: &Vec3f, dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        self.object.tangents(point, dir)
+    }
+}
@@ -0,0 +1,50 @@
+//! Objects shared between several places in the scene, such as a mesh
+//! loaded once and placed many times over, which keeps a forest of the same
+//! tree as small as the one tree.
+
+use std::sync::Arc;
+
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// A use of a shared object, in a material of its own or in the object's.
+/// Placing it elsewhere is left to a transform around it.
+pub struct Instance {
+    pub object: Arc<dyn Object>,
+    pub material: Option<Material>,
+}
+
+impl Object for Instance {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.object.ray_intersect(ray)
+    }
+
+    fn material(&self) -> &Material {
+        self.material
+            .as_ref()
+            .unwrap_or_else(|| self.object.material())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.object.bounds()
+    }
+
+    fn area(&self) -> Option<Float> {
+        self.object.area()
+    }
+
+    fn sample_surface(&self, uv: (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        self.object.sample_surface(uv)
+    }
+
+    fn texture_coordinates(&self, point: &Vec3f, dir: &Vec3f) -> Option<Vec2f> {
+        self.object.texture_coordinates(point, dir)
+    }
+
+    fn tangents(&self, point: &Vec3f, dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        self.object.tangents(point, dir)
+    }
+}
@@ -0,0 +1,165 @@
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec3f};
+
+/// How many steps the ray takes across the smallest ball, looking for
+/// where the field crosses the threshold
+const STEPS_PER_RADIUS: Float = 16.0;
+/// How many times the step a crossing was found in is halved
+const BISECTIONS: usize = 24;
+
+/// One of the balls of a metaball surface, adding `strength` to the field
+/// at its center and falling off smoothly to nothing `radius` away. A
+/// negative strength carves into the balls around it instead.
+#[derive(Clone, Copy, Debug)]
+pub struct Metaball {
+    pub center: Vec3f,
+    pub radius: Float,
+    pub strength: Float,
+}
+
+impl Metaball {
+    /// The falloff of Wyvill, `(1 - r²/R²)³`, which is smooth at the center
+    /// and at the radius, and never needs a square root.
+    fn field(&self, p: &Vec3f) -> Float {
+        let q = (*p - self.center).norm() / (self.radius * self.radius);
+        if q >= 1.0 {
+            return 0.0;
+        }
+        let f = 1.0 - q;
+        self.strength * f * f * f
+    }
+
+    fn gradient(&self, p: &Vec3f) -> Vec3f {
+        let offset = *p - self.center;
+        let r2 = self.radius * self.radius;
+        let q = offset.norm() / r2;
+        if q >= 1.0 {
+            return Vec3f::new(0.0, 0.0, 0.0);
+        }
+        let f = 1.0 - q;
+        offset * (-6.0 * self.strength * f * f / r2)
+    }
+
+    /// Where along the ray it is within the radius, if it ever is.
+    fn span(&self, ray: &Ray) -> Option<(Float, Float)> {
+        let l = self.center - ray.origin;
+        let tca = l.dot(&ray.dir);
+        let d2 = l.norm() - tca * tca;
+        let radius2 = self.radius * self.radius;
+        if d2 > radius2 {
+            return None;
+        }
+        let thc = (radius2 - d2).sqrt();
+        Some((tca - thc, tca + thc))
+    }
+}
+
+/// Blobs that merge into each other as they come close: the surface where
+/// the fields of the balls add up to the `threshold`. There is no formula
+/// for where a ray meets it, so it is found by stepping along the ray
+/// through the balls until the field crosses the threshold, then halving
+/// the step it crossed in.
+pub struct Metaballs {
+    balls: Vec<Metaball>,
+    threshold: Float,
+    material: Material,
+    bounds: Aabb,
+    /// How far apart the steps along the ray are
+    step: Float,
+}
+
+impl Metaballs {
+    pub fn new(balls: Vec<Metaball>, threshold: Float, material: Material) -> Self {
+        // Only where balls of positive strength reach can the field get
+        // above the threshold
+        let positive = || balls.iter().filter(|ball| ball.strength > 0.0);
+        let bounds = positive().fold(Aabb::empty(), |bounds, ball| {
+            let r = Vec3f::new(ball.radius, ball.radius, ball.radius);
+            bounds.union(&Aabb::new(ball.center - r, ball.center + r))
+        });
+        let smallest = balls
+            .iter()
+            .map(|ball| ball.radius)
+            .fold(Float::MAX, Float::min);
+        Self {
+            step: smallest / STEPS_PER_RADIUS,
+            balls,
+            threshold,
+            material,
+            bounds,
+        }
+    }
+
+    /// How far the field at `p` is above the threshold.
+    fn excess(&self, p: &Vec3f) -> Float {
+        self.balls.iter().map(|ball| ball.field(p)).sum::<Float>() - self.threshold
+    }
+
+    /// The outward normal at `p`, down the slope of the field.
+    fn normal(&self, p: &Vec3f) -> Vec3f {
+        let gradient = self
+            .balls
+            .iter()
+            .fold(Vec3f::new(0.0, 0.0, 0.0), |sum, ball| {
+                sum + ball.gradient(p)
+            });
+        (-gradient).normalize()
+    }
+}
+
+impl Object for Metaballs {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        // The stretches of the ray within the positive balls, merged where
+        // they overlap, outside of which the field is below the threshold
+        let mut spans: Vec<_> = self
+            .balls
+            .iter()
+            .filter(|ball| ball.strength > 0.0)
+            .filter_map(|ball| ball.span(ray))
+            .map(|(near, far)| (near.max(ray.t_min), far.min(ray.t_max)))
+            .filter(|(near, far)| near < far)
+            .collect();
+        spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut merged: Vec<(Float, Float)> = vec![];
+        for (near, far) in spans {
+            match merged.last_mut() {
+                Some(last) if near <= last.1 => last.1 = last.1.max(far),
+                _ => merged.push((near, far)),
+            }
+        }
+
+        for (near, far) in merged {
+            let (mut t, mut excess) = (near, self.excess(&ray.at(near)));
+            while t < far {
+                let next = (t + self.step).min(far);
+                let next_excess = self.excess(&ray.at(next));
+                if (excess > 0.0) != (next_excess > 0.0) {
+                    let (mut a, mut b) = (t, next);
+                    for _ in 0..BISECTIONS {
+                        let middle = (a + b) * 0.5;
+                        if (self.excess(&ray.at(middle)) > 0.0) == (excess > 0.0) {
+                            a = middle;
+                        } else {
+                            b = middle;
+                        }
+                    }
+                    return Some((b, self.normal(&ray.at(b))));
+                }
+                t = next;
+                excess = next_excess;
+            }
+        }
+        None
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
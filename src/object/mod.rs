@@ -0,0 +1,220 @@
+use std::any::Any;
+
+use crate::aabb::Aabb;
+use crate::material::{BackFace, Material};
+use crate::ray::Ray;
+use crate::scene::ObjectHandle;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+mod cone;
+mod csg;
+mod cuboid;
+mod cylinder;
+mod disk;
+mod emitting;
+mod instance;
+mod metaballs;
+pub mod moving;
+mod quad;
+mod sdf;
+mod sphere;
+mod sphere_packet;
+mod torus;
+mod transformed;
+pub mod triangle;
+
+pub use cone::Cone;
+pub use csg::{Csg, CsgOperation};
+pub use cuboid::Cuboid;
+pub use cylinder::Cylinder;
+pub use disk::Disk;
+pub use emitting::Emitting;
+pub use instance::Instance;
+pub use metaballs::{Metaball, Metaballs};
+pub use moving::Moving;
+pub use quad::Quad;
+pub use sdf::{Sdf, SdfObject};
+pub use sphere::Sphere;
+pub use sphere_packet::{SpherePacket, LANES};
+pub use torus::Torus;
+pub use transformed::Transformed;
+pub use triangle::Triangle;
+
+pub trait Object: Any + Send + Sync {
+    /// Returns the distance along the ray and the surface normal of the
+    /// closest intersection within the ray's range, if any.
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)>;
+    fn material(&self) -> &Material;
+    /// A box enclosing the object, or `None` if it can't be bounded.
+    fn bounds(&self) -> Option<Aabb>;
+
+    /// The area of the surface, for objects whose surface can be sampled.
+    /// Emissive objects that can be are sampled as lights; the others only
+    /// light the scene when rays happen to hit them.
+    fn area(&self) -> Option<Float> {
+        None
+    }
+
+    /// The point of the surface, and the normal there, for the point `uv`
+    /// of the unit square, spread evenly over the area.
+    fn sample_surface(&self, _uv: (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        None
+    }
+
+    /// The texture coordinates at `point`, where a ray along `dir` hit the
+    /// surface, for objects that have them.
+    fn texture_coordinates(&self, _point: &Vec3f, _dir: &Vec3f) -> Option<Vec2f> {
+        None
+    }
+
+    /// How fast the point moves along the surface with each of the texture
+    /// coordinates there, for objects that have them.
+    fn tangents(&self, _point: &Vec3f, _dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        None
+    }
+}
+
+/// The material of the object where the ray hit it, with its textures
+/// looked up there, and the color of its back face if it was hit from behind.
+/// Images are averaged over the part of the surface the pixel covers, which
+/// is `width` across where the ray hit, or looked up at the point for 0.
+pub fn surface_material(hit: &HitRecord, width: Float) -> Material {
+    let (object, point) = (hit.object, &hit.point);
+    let material = object.material();
+    let mut material = if material.is_textured() {
+        let uv = object.texture_coordinates(point, &hit.dir);
+        let footprint = match uv {
+            Some(uv) if width > 0.0 => texture_footprint(hit, uv, width),
+            _ => 0.0,
+        };
+        material.at(point, uv, footprint)
+    } else {
+        material.clone()
+    };
+    if let (BackFace::Color(color), true) = (material.back_face, hit.back) {
+        material.diffuse_color = color;
+    }
+    material
+}
+
+/// How far across in texture coordinates the patch of the surface around
+/// the hit is that a pixel `width` across covers, stretched along the ray
+/// where it meets the surface at a slant. The texture coordinates are
+/// looked up again at the edges of the patch, wrapping around as textures
+/// repeat, so that the seam of a sphere isn't taken for a jump in them.
+fn texture_footprint(hit: &HitRecord, uv: Vec2f, width: Float) -> Float {
+    let n = hit.geometric_normal;
+    let along = hit.dir - n * n.dot(&hit.dir);
+    let (along, across) = if along.norm() > 0.0 {
+        let along = along.normalize();
+        (along, n.cross(&along))
+    } else {
+        n.perpendiculars()
+    };
+    let slant = n.dot(&hit.dir).abs().max(0.1);
+    [along * (width / slant), across * width]
+        .iter()
+        .filter_map(|offset| {
+            hit.object
+                .texture_coordinates(&(hit.point + *offset), &hit.dir)
+        })
+        .map(|edge| {
+            let (du, dv) = (edge.0 - uv.0, edge.1 - uv.1);
+            (du - du.round()).hypot(dv - dv.round())
+        })
+        .fold(0.0, Float::max)
+}
+
+/// Whether a hit facing `n` of a ray along `dir` is passed through, as it is
+/// on the back of a surface whose material culls back faces.
+pub(crate) fn is_culled(object: &dyn Object, n: &Vec3f, dir: &Vec3f) -> bool {
+    matches!(object.material().back_face, BackFace::Cull) && n.dot(dir) > 0.0
+}
+
+/// The normal `n` of the object at `point`, where a ray along `dir` hit it,
+/// bent by the normal or bump map of its material.
+pub fn shading_normal(object: &dyn Object, n: &Vec3f, point: &Vec3f, dir: &Vec3f) -> Vec3f {
+    let material = object.material();
+    if !material.bends_normal() {
+        return *n;
+    }
+    let uv = object.texture_coordinates(point, dir);
+    let tangents = uv.and(object.tangents(point, dir));
+    material.shading_normal(n, point, uv, tangents)
+}
+
+/// Where a ray hit something, and what.
+#[derive(Clone, Copy)]
+pub struct HitRecord<'a> {
+    pub object: &'a dyn Object,
+    /// The object among those of the scene, for hits found through one
+    pub handle: Option<ObjectHandle>,
+    /// The distance along the ray
+    pub distance: Float,
+    pub point: Vec3f,
+    /// The shading normal, bent by any normal or bump map
+    pub normal: Vec3f,
+    /// The normal of the surface itself, before any map bends it
+    pub geometric_normal: Vec3f,
+    /// Whether the ray hit the back of the surface, the side its normal
+    /// points away from, before a material flipping it turned it around
+    pub back: bool,
+    /// The direction of the ray
+    dir: Vec3f,
+}
+
+impl<'a> HitRecord<'a> {
+    /// The hit of the object at `distance` along the ray, facing `n` there
+    /// before any normal or bump map bends it.
+    pub fn new(object: &'a dyn Object, ray: &Ray, distance: Float, n: &Vec3f) -> Self {
+        let point = ray.at(distance);
+        let back = n.dot(&ray.dir) > 0.0;
+        let n = if back && matches!(object.material().back_face, BackFace::Flip) {
+            -*n
+        } else {
+            *n
+        };
+        Self {
+            object,
+            handle: None,
+            distance,
+            point,
+            normal: shading_normal(object, &n, &point, &ray.dir),
+            geometric_normal: n,
+            back,
+            dir: ray.dir,
+        }
+    }
+
+    pub fn material(&self) -> &'a Material {
+        self.object.material()
+    }
+
+    /// The texture coordinates of the point, for objects that have them.
+    /// They are only worked out when asked for, as meshes have to find the
+    /// face they were hit on again.
+    pub fn uv(&self) -> Option<Vec2f> {
+        self.object.texture_coordinates(&self.point, &self.dir)
+    }
+}
+
+/// Anything a ray can hit: each object on its own, or the whole scene.
+pub trait Hittable {
+    /// The closest hit within the ray's range, if any.
+    fn hit(&self, ray: &Ray) -> Option<HitRecord<'_>>;
+}
+
+impl<T: Object> Hittable for T {
+    fn hit(&self, ray: &Ray) -> Option<HitRecord<'_>> {
+        let (distance, n) = self.ray_intersect(ray)?;
+        Some(HitRecord::new(self, ray, distance, &n))
+    }
+}
+
+/// A closed object with a well defined inside, usable in CSG operations.
+pub trait Solid: Object {
+    /// Every point ahead of the origin where the ray crosses the surface, as
+    /// distance and outward normal, sorted by distance. An odd number of
+    /// crossings means that the ray starts inside the solid.
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)>;
+}
@@ -0,0 +1,85 @@
+//! Objects moving in a straight line while the shutter is open, which
+//! streak across the image as motion blur.
+//!
+//! Rays carry no time of their own. Each render thread traces one camera
+//! sample at a time, along with every ray spawned from it, so the time of
+//! the sample is kept per thread instead, and moving objects are wherever
+//! they are then.
+
+use std::cell::Cell;
+
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+thread_local! {
+    /// The time the rays traced on this thread are at
+    static TIME: Cell<Float> = const { Cell::new(0.0) };
+}
+
+/// Sets the time of the rays traced on this thread from now on, from 0
+/// when the shutter opens to 1 at the latest.
+pub fn set_time(time: Float) {
+    TIME.with(|t| t.set(time));
+}
+
+fn time() -> Float {
+    TIME.with(Cell::get)
+}
+
+/// An object moving by `velocity` per unit of time from where it is when the
+/// shutter opens.
+pub struct Moving {
+    pub object: Box<dyn Object>,
+    pub velocity: Vec3f,
+}
+
+impl Moving {
+    fn offset(&self) -> Vec3f {
+        self.velocity * time()
+    }
+}
+
+impl Object for Moving {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.object.ray_intersect(&Ray {
+            origin: ray.origin - self.offset(),
+            ..*ray
+        })
+    }
+
+    fn material(&self) -> &Material {
+        self.object.material()
+    }
+
+    /// The bounds of everywhere the object gets to by the time 1.
+    fn bounds(&self) -> Option<Aabb> {
+        self.object.bounds().map(|bounds| {
+            bounds.union(&Aabb::new(
+                bounds.min + self.velocity,
+                bounds.max + self.velocity,
+            ))
+        })
+    }
+
+    fn area(&self) -> Option<Float> {
+        self.object.area()
+    }
+
+    fn sample_surface(&self, uv: (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        self.object
+            .sample_surface(uv)
+            .map(|(point, n)| (point + self.offset(), n))
+    }
+
+    fn texture_coordinates(&self, point: &Vec3f, dir: &Vec3f) -> Option<Vec2f> {
+        self.object
+            .texture_coordinates(&(*point - self.offset()), dir)
+    }
+
+    fn tangents(&self, point: &Vec3f, dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        self.object.tangents(&(*point - self.offset()), dir)
+    }
+}
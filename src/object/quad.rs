@@ -0,0 +1,87 @@
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// A parallelogram spanned by the edges `u` and `v` from `corner`.
+pub struct Quad {
+    corner: Vec3f,
+    u: Vec3f,
+    v: Vec3f,
+    normal: Vec3f,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3f, u: Vec3f, v: Vec3f, material: Material) -> Self {
+        Self {
+            corner,
+            u,
+            v,
+            normal: u.cross(&v).normalize(),
+            material,
+        }
+    }
+
+    /// The point expressed in the (u, v) basis of the quad.
+    fn coordinates(&self, point: &Vec3f) -> (Float, Float) {
+        let p = *point - self.corner;
+        let w = self.u.cross(&self.v);
+        let w = w * w.dot(&w).recip();
+        (w.dot(&p.cross(&self.v)), w.dot(&self.u.cross(&p)))
+    }
+}
+
+impl Object for Quad {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        let denom = self.normal.dot(&ray.dir);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(self.corner - ray.origin)) / denom;
+        if !ray.contains(t) {
+            return None;
+        }
+
+        let (a, b) = self.coordinates(&ray.at(t));
+        if (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b) {
+            Some((t, self.normal))
+        } else {
+            None
+        }
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let points = vec![
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        Some(Aabb::from_points(points).padded(1e-4))
+    }
+
+    fn area(&self) -> Option<Float> {
+        Some(self.u.cross(&self.v).len())
+    }
+
+    fn sample_surface(&self, (a, b): (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        Some((self.corner + self.u * a + self.v * b, self.normal))
+    }
+
+    /// How far along `u` and `v` the point is, so that the texture stretches
+    /// over the quad once.
+    fn texture_coordinates(&self, point: &Vec3f, _dir: &Vec3f) -> Option<Vec2f> {
+        let (a, b) = self.coordinates(point);
+        Some(Vec2f::new(a, b))
+    }
+
+    fn tangents(&self, _point: &Vec3f, _dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        Some((self.u, self.v))
+    }
+}
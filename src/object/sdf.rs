@@ -0,0 +1,208 @@
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec3f};
+
+/// A signed distance function built from primitives and (smooth) boolean
+/// operators.
+pub enum Sdf {
+    Sphere {
+        center: Vec3f,
+        radius: Float,
+    },
+    Box {
+        center: Vec3f,
+        half_size: Vec3f,
+        rounding: Float,
+    },
+    /// A torus lying in the XZ plane
+    Torus {
+        center: Vec3f,
+        major_radius: Float,
+        minor_radius: Float,
+    },
+    Capsule {
+        a: Vec3f,
+        b: Vec3f,
+        radius: Float,
+    },
+    /// Union of the children, blended over distance `k` when `k` is positive
+    Union(Vec<Sdf>, Float),
+    Intersection(Vec<Sdf>, Float),
+    /// The first shape with the second one cut away
+    Difference(Box<Sdf>, Box<Sdf>, Float),
+}
+
+/// Polynomial smooth minimum, blending the two distances over `k`.
+fn smooth_min(a: Float, b: Float, k: Float) -> Float {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+fn smooth_max(a: Float, b: Float, k: Float) -> Float {
+    -smooth_min(-a, -b, k)
+}
+
+impl Sdf {
+    /// Conservative bounds of the surface, growing blended unions by their
+    /// smoothing distance.
+    pub fn bounds(&self) -> Aabb {
+        let cube = |center: &Vec3f, r: Float| {
+            let r = Vec3f::new(r, r, r);
+            Aabb::new(*center - r, *center + r)
+        };
+        match self {
+            Sdf::Sphere { center, radius } => cube(center, *radius),
+            Sdf::Box {
+                center, half_size, ..
+            } => Aabb::new(*center - *half_size, *center + *half_size),
+            Sdf::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let e = Vec3f::new(
+                    major_radius + minor_radius,
+                    *minor_radius,
+                    major_radius + minor_radius,
+                );
+                Aabb::new(*center - e, *center + e)
+            }
+            Sdf::Capsule { a, b, radius } => cube(a, *radius).union(&cube(b, *radius)),
+            Sdf::Union(children, k) => children
+                .iter()
+                .fold(Aabb::empty(), |bounds, child| bounds.union(&child.bounds()))
+                .padded(k.max(0.0)),
+            Sdf::Intersection(children, _) => children
+                .iter()
+                .map(Sdf::bounds)
+                .reduce(|a, b| a.intersection(&b))
+                .unwrap_or_else(Aabb::empty),
+            Sdf::Difference(a, _, _) => a.bounds(),
+        }
+    }
+
+    pub fn distance(&self, p: &Vec3f) -> Float {
+        match self {
+            Sdf::Sphere { center, radius } => p.distance(center) - radius,
+            Sdf::Box {
+                center,
+                half_size,
+                rounding,
+            } => {
+                let d = *p - *center;
+                let q = Vec3f::new(
+                    d.0.abs() - half_size.0 + rounding,
+                    d.1.abs() - half_size.1 + rounding,
+                    d.2.abs() - half_size.2 + rounding,
+                );
+                let outside = q.max(&Vec3f::new(0.0, 0.0, 0.0)).len();
+                let inside = q.0.max(q.1).max(q.2).min(0.0);
+                outside + inside - rounding
+            }
+            Sdf::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let d = *p - *center;
+                let ring = (d.0 * d.0 + d.2 * d.2).sqrt() - major_radius;
+                (ring * ring + d.1 * d.1).sqrt() - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let pa = *p - *a;
+                let ba = *b - *a;
+                let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+                (pa - ba * h).len() - radius
+            }
+            Sdf::Union(children, k) => children
+                .iter()
+                .map(|child| child.distance(p))
+                .reduce(|a, b| smooth_min(a, b, *k))
+                .unwrap_or(Float::INFINITY),
+            Sdf::Intersection(children, k) => children
+                .iter()
+                .map(|child| child.distance(p))
+                .reduce(|a, b| smooth_max(a, b, *k))
+                .unwrap_or(Float::INFINITY),
+            Sdf::Difference(a, b, k) => smooth_max(a.distance(p), -b.distance(p), *k),
+        }
+    }
+}
+
+/// An object rendered by sphere tracing its distance function.
+pub struct SdfObject {
+    sdf: Sdf,
+    bounds: Aabb,
+    material: Material,
+    max_steps: usize,
+    max_distance: Float,
+    epsilon: Float,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, material: Material) -> Self {
+        Self {
+            bounds: sdf.bounds().padded(1e-2),
+            sdf,
+            material,
+            max_steps: 256,
+            max_distance: 100.0,
+            epsilon: 1e-3,
+        }
+    }
+
+    pub fn with_max_distance(mut self, max_distance: Float) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// Central differences of the distance field.
+    fn normal(&self, p: &Vec3f) -> Vec3f {
+        let h = self.epsilon;
+        let (dx, dy, dz) = (
+            Vec3f::new(h, 0.0, 0.0),
+            Vec3f::new(0.0, h, 0.0),
+            Vec3f::new(0.0, 0.0, h),
+        );
+        Vec3f::new(
+            self.sdf.distance(&(*p + dx)) - self.sdf.distance(&(*p - dx)),
+            self.sdf.distance(&(*p + dy)) - self.sdf.distance(&(*p - dy)),
+            self.sdf.distance(&(*p + dz)) - self.sdf.distance(&(*p - dz)),
+        )
+        .normalize()
+    }
+}
+
+impl Object for SdfObject {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        // Only march the part of the ray within its range and the bounds
+        let (t_near, t_far) = self.bounds.intersect(&ray.origin, &ray.dir)?;
+        let mut t = t_near.max(ray.t_min);
+        let t_far = t_far.min(self.max_distance).min(ray.t_max);
+        for _ in 0..self.max_steps {
+            let p = ray.at(t);
+            let d = self.sdf.distance(&p);
+            if d.abs() < self.epsilon * t.max(1.0) {
+                return Some((t, self.normal(&p)));
+            }
+            t += d.abs();
+            if t > t_far {
+                break;
+            }
+        }
+        None
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
@@ -0,0 +1,194 @@
+use super::{Object, Solid};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{consts::PI, Float, Vec2f, Vec3f};
+
+pub struct Sphere {
+    center: Vec3f,
+    radius: Float,
+    material: Material,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3f, radius: Float, material: Material) -> Self {
+        Self {
+            center,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self) -> Vec3f {
+        self.center
+    }
+
+    pub fn radius(&self) -> Float {
+        self.radius
+    }
+
+    /// The distance to the nearer crossing within the ray's range.
+    fn distance(&self, ray: &Ray) -> Option<Float> {
+        let l = self.center - ray.origin;
+        let tca = l.dot(&ray.dir);
+        let d2 = l.dot(&l) - tca * tca;
+        let radius2 = self.radius * self.radius;
+        if d2 > radius2 {
+            return None;
+        }
+        let thc = (radius2 - d2).sqrt();
+        [tca - thc, tca + thc]
+            .iter()
+            .copied()
+            .find(|&t| ray.contains(t))
+    }
+}
+
+impl Object for Sphere {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.distance(ray)
+            .map(|distance| (distance, (ray.at(distance) - self.center).normalize()))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = Vec3f::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn area(&self) -> Option<Float> {
+        Some(4.0 * PI * self.radius * self.radius)
+    }
+
+    fn sample_surface(&self, (u, v): (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        let z = 1.0 - 2.0 * u;
+        let (r, phi) = ((1.0 - z * z).max(0.0).sqrt(), 2.0 * PI * v);
+        let normal = Vec3f::new(r * phi.cos(), r * phi.sin(), z);
+        Some((self.center + normal * self.radius, normal))
+    }
+
+    /// Longitude across and latitude up, with the middle of the texture
+    /// facing +Z and its top and bottom edges at the poles on the Y axis.
+    fn texture_coordinates(&self, point: &Vec3f, _dir: &Vec3f) -> Option<Vec2f> {
+        let n = (*point - self.center).normalize();
+        Some(Vec2f::new(
+            0.5 + n.0.atan2(n.2) / (2.0 * PI),
+            0.5 + n.1.clamp(-1.0, 1.0).asin() / PI,
+        ))
+    }
+
+    fn tangents(&self, point: &Vec3f, _dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        let n = (*point - self.center).normalize();
+        // The cosine of the latitude, kept off zero at the poles
+        let cos = (n.0 * n.0 + n.2 * n.2).sqrt().max(1e-6);
+        Some((
+            Vec3f::new(n.2, 0.0, -n.0) * (2.0 * PI * self.radius),
+            Vec3f::new(-n.0 * n.1 / cos, cos, -n.2 * n.1 / cos) * (PI * self.radius),
+        ))
+    }
+}
+
+impl Solid for Sphere {
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)> {
+        let l = self.center - *orig;
+        let tca = l.dot(dir);
+        let d2 = l.dot(&l) - tca * tca;
+        let radius2 = self.radius * self.radius;
+        if d2 > radius2 {
+            return vec![];
+        }
+        let thc = (radius2 - d2).sqrt();
+        [tca - thc, tca + thc]
+            .iter()
+            .filter(|&&t| t >= 0.0)
+            .map(|&t| (t, (*orig + (*dir * t) - self.center).normalize()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Hittable;
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(Vec3f::new(0.0, 0.0, -5.0), 1.0, Material::default())
+    }
+
+    const FORWARD: Vec3f = Vec3f::new(0.0, 0.0, -1.0);
+
+    #[test]
+    fn hits_the_near_side() {
+        let (distance, n) = unit_sphere()
+            .ray_intersect(&Ray::new(Vec3f::new(0.0, 0.0, 0.0), FORWARD))
+            .unwrap();
+        assert!((distance - 4.0).abs() < 1e-6);
+        assert!(n.approx_eq(&Vec3f::new(0.0, 0.0, 1.0), 1e-6));
+    }
+
+    #[test]
+    fn hits_the_far_side_from_inside() {
+        let (distance, n) = unit_sphere()
+            .ray_intersect(&Ray::new(Vec3f::new(0.0, 0.0, -5.0), FORWARD))
+            .unwrap();
+        assert!((distance - 1.0).abs() < 1e-6);
+        // The normal still points out of the sphere
+        assert!(n.approx_eq(&FORWARD, 1e-6));
+    }
+
+    #[test]
+    fn misses() {
+        let sphere = unit_sphere();
+        let origin = Vec3f::new(0.0, 0.0, 0.0);
+        // Off to the side, pointing away, and past it
+        assert!(sphere
+            .ray_intersect(&Ray::new(origin, Vec3f::new(0.0, 1.0, 0.0)))
+            .is_none());
+        assert!(sphere.ray_intersect(&Ray::new(origin, -FORWARD)).is_none());
+        assert!(sphere
+            .ray_intersect(&Ray::new(Vec3f::new(0.0, 0.0, -7.0), FORWARD))
+            .is_none());
+        assert!(sphere
+            .ray_intersect(&Ray::new(Vec3f::new(1.001, 0.0, 0.0), FORWARD))
+            .is_none());
+    }
+
+    #[test]
+    fn grazes_the_edge() {
+        let (distance, n) = unit_sphere()
+            .ray_intersect(&Ray::new(Vec3f::new(1.0, 0.0, 0.0), FORWARD))
+            .unwrap();
+        assert!((distance - 5.0).abs() < 1e-3);
+        assert!(n.approx_eq(&Vec3f::new(1.0, 0.0, 0.0), 1e-3));
+    }
+
+    #[test]
+    fn records_the_hit() {
+        let sphere = unit_sphere();
+        let hit = sphere
+            .hit(&Ray::new(Vec3f::new(0.0, 0.0, 0.0), FORWARD))
+            .unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-6);
+        assert!(hit.point.approx_eq(&Vec3f::new(0.0, 0.0, -4.0), 1e-6));
+        assert!(hit.normal.approx_eq(&Vec3f::new(0.0, 0.0, 1.0), 1e-6));
+        // Facing +Z is the middle of the texture
+        let uv = hit.uv().unwrap();
+        assert!(uv.approx_eq(&Vec2f::new(0.5, 0.5), 1e-6));
+    }
+
+    #[test]
+    fn keeps_to_the_range_of_the_ray() {
+        let sphere = unit_sphere();
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), FORWARD);
+        // Starting past the near side hits the far one
+        let (distance, _) = sphere.ray_intersect(&ray.with_range(4.5, 10.0)).unwrap();
+        assert!((distance - 6.0).abs() < 1e-6);
+        // Ending before the near side, or right on it, hits nothing
+        assert!(sphere.ray_intersect(&ray.with_range(0.0, 3.0)).is_none());
+        assert!(sphere.ray_intersect(&ray.with_range(0.0, 4.0)).is_none());
+        assert!(sphere.ray_intersect(&ray.with_range(6.5, 10.0)).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use super::Sphere;
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::vector::{Float, Vec3f};
+
+/// Number of spheres in a packet.
+pub const LANES: usize = 4;
+
+/// Up to `LANES` spheres stored component by component, so that a ray can be
+/// intersected with all of them at once. With the `simd` feature on x86-64
+/// this is done with SSE instructions, in single precision only.
+pub struct SpherePacket {
+    x: [Float; LANES],
+    y: [Float; LANES],
+    z: [Float; LANES],
+    /// Squared radii, negative in unused lanes so that they never hit
+    radius2: [Float; LANES],
+    len: usize,
+}
+
+impl SpherePacket {
+    pub fn new(spheres: &[&Sphere]) -> Self {
+        assert!(spheres.len() <= LANES, "too many spheres for a packet");
+        let mut packet = Self {
+            x: [0.0; LANES],
+            y: [0.0; LANES],
+            z: [0.0; LANES],
+            radius2: [-1.0; LANES],
+            len: spheres.len(),
+        };
+        for (lane, sphere) in spheres.iter().enumerate() {
+            let center = sphere.center();
+            packet.x[lane] = center.0;
+            packet.y[lane] = center.1;
+            packet.z[lane] = center.2;
+            packet.radius2[lane] = sphere.radius() * sphere.radius();
+        }
+        packet
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn center(&self, lane: usize) -> Vec3f {
+        Vec3f::new(self.x[lane], self.y[lane], self.z[lane])
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        (0..self.len).fold(Aabb::empty(), |bounds, lane| {
+            let r = self.radius2[lane].sqrt();
+            let r = Vec3f::new(r, r, r);
+            let center = self.center(lane);
+            bounds.union(&Aabb::new(center - r, center + r))
+        })
+    }
+
+    /// Returns the distance to the closest sphere hit by the ray within its
+    /// range, along with the lane of that sphere.
+    pub fn intersect(&self, ray: &Ray) -> Option<(Float, usize)> {
+        let distances = self.distances(ray);
+        (0..self.len)
+            .filter(|&lane| ray.contains(distances[lane]))
+            .map(|lane| (distances[lane], lane))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// Distance along the ray to each sphere, past the start of its range,
+    /// or infinity for a miss. Same math as `Sphere::ray_intersect`, one
+    /// sphere per lane.
+    #[cfg(not(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64")))]
+    fn distances(&self, ray: &Ray) -> [Float; LANES] {
+        let mut distances = [Float::INFINITY; LANES];
+        for (lane, distance) in distances.iter_mut().enumerate() {
+            let l = self.center(lane) - ray.origin;
+            let tca = l.dot(&ray.dir);
+            let h = self.radius2[lane] - (l.dot(&l) - tca * tca);
+            let thc = h.max(0.0).sqrt();
+            let t = if tca - thc >= ray.t_min {
+                tca - thc
+            } else {
+                tca + thc
+            };
+            if h >= 0.0 && t >= ray.t_min {
+                *distance = t;
+            }
+        }
+        distances
+    }
+
+    #[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+    fn distances(&self, ray: &Ray) -> [Float; LANES] {
+        use std::arch::x86_64::*;
+
+        let (orig, dir) = (&ray.origin, &ray.dir);
+        let mut distances = [0.0; LANES];
+        // SSE2 is part of the x86-64 baseline, so these are always available
+        unsafe {
+            let lx = _mm_sub_ps(_mm_loadu_ps(self.x.as_ptr()), _mm_set1_ps(orig.0));
+            let ly = _mm_sub_ps(_mm_loadu_ps(self.y.as_ptr()), _mm_set1_ps(orig.1));
+            let lz = _mm_sub_ps(_mm_loadu_ps(self.z.as_ptr()), _mm_set1_ps(orig.2));
+            let dot = |ax, ay, az, bx, by, bz| {
+                _mm_add_ps(
+                    _mm_add_ps(_mm_mul_ps(ax, bx), _mm_mul_ps(ay, by)),
+                    _mm_mul_ps(az, bz),
+                )
+            };
+            let (dx, dy, dz) = (_mm_set1_ps(dir.0), _mm_set1_ps(dir.1), _mm_set1_ps(dir.2));
+            let tca = dot(lx, ly, lz, dx, dy, dz);
+            let d2 = _mm_sub_ps(dot(lx, ly, lz, lx, ly, lz), _mm_mul_ps(tca, tca));
+            let h = _mm_sub_ps(_mm_loadu_ps(self.radius2.as_ptr()), d2);
+            let zero = _mm_setzero_ps();
+            let thc = _mm_sqrt_ps(_mm_max_ps(h, zero));
+            let t0 = _mm_sub_ps(tca, thc);
+            let t1 = _mm_add_ps(tca, thc);
+
+            // The near crossing unless it's before the start of the range,
+            // without SSE4.1 blends
+            let t_min = _mm_set1_ps(ray.t_min);
+            let near_ahead = _mm_cmpge_ps(t0, t_min);
+            let t = _mm_or_ps(_mm_and_ps(near_ahead, t0), _mm_andnot_ps(near_ahead, t1));
+            let hit = _mm_and_ps(_mm_cmpge_ps(h, zero), _mm_cmpge_ps(t, t_min));
+            let t = _mm_or_ps(
+                _mm_and_ps(hit, t),
+                _mm_andnot_ps(hit, _mm_set1_ps(Float::INFINITY)),
+            );
+            _mm_storeu_ps(distances.as_mut_ptr(), t);
+        }
+        distances
+    }
+}
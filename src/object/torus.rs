@@ -0,0 +1,191 @@
+use super::cylinder::sort_crossings;
+use super::{Object, Solid};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::poly;
+use crate::ray::Ray;
+use crate::vector::{to_f64, Float, Vec3f};
+
+/// A torus around `center`, with its ring of `major_radius` in the plane
+/// perpendicular to `axis` and a tube of `minor_radius`.
+pub struct Torus {
+    center: Vec3f,
+    // Orthonormal basis with the axis as its last vector
+    basis: (Vec3f, Vec3f, Vec3f),
+    major_radius: Float,
+    minor_radius: Float,
+    material: Material,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3f,
+        axis: Vec3f,
+        major_radius: Float,
+        minor_radius: Float,
+        material: Material,
+    ) -> Self {
+        let w = axis.normalize();
+        let helper = if w.0.abs() > 0.9 {
+            Vec3f::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3f::new(1.0, 0.0, 0.0)
+        };
+        let u = helper.cross(&w).normalize();
+        let v = w.cross(&u);
+        Self {
+            center,
+            basis: (u, v, w),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+
+    fn to_local(&self, v: &Vec3f) -> [f64; 3] {
+        let (u, v_, w) = &self.basis;
+        [to_f64(v.dot(u)), to_f64(v.dot(v_)), to_f64(v.dot(w))]
+    }
+}
+
+impl Solid for Torus {
+    fn crossings(&self, orig: &Vec3f, dir: &Vec3f) -> Vec<(Float, Vec3f)> {
+        // Skip to the bounding sphere first, both as an early out and to keep
+        // the quartic coefficients small
+        let bound = self.major_radius + self.minor_radius;
+        let l = self.center - *orig;
+        let tca = l.dot(dir);
+        let d2 = l.dot(&l) - tca * tca;
+        if d2 > bound * bound {
+            return vec![];
+        }
+        let t_start = (tca - (bound * bound - d2).sqrt()).max(0.0);
+
+        let o = self.to_local(&(*orig + (*dir * t_start) - self.center));
+        let d = self.to_local(dir);
+        let dot = |a: &[f64; 3], b: &[f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+        let r2 = to_f64(self.major_radius).powi(2);
+        let s2 = to_f64(self.minor_radius).powi(2);
+        let dd = dot(&d, &d);
+        let e = dot(&o, &o) - r2 - s2;
+        let f = dot(&o, &d);
+        let four_r2 = 4.0 * r2;
+
+        let c4 = dd * dd;
+        let c3 = 4.0 * dd * f;
+        let c2 = 2.0 * dd * e + 4.0 * f * f + four_r2 * d[2] * d[2];
+        let c1 = 4.0 * f * e + 2.0 * four_r2 * o[2] * d[2];
+        let c0 = e * e - four_r2 * (s2 - o[2] * o[2]);
+
+        let (_, _, w) = &self.basis;
+        let mut crossings: Vec<_> = poly::solve_quartic(c3 / c4, c2 / c4, c1 / c4, c0 / c4)
+            .into_iter()
+            .filter(|&t| t >= 0.0)
+            .map(|t| {
+                let distance = t_start + t as Float;
+                let hit = *orig + (*dir * distance) - self.center;
+                let in_plane = hit - *w * hit.dot(w);
+                let ring = in_plane.normalize() * self.major_radius;
+                (distance, (hit - ring).normalize())
+            })
+            .collect();
+        sort_crossings(&mut crossings);
+        crossings
+    }
+}
+
+impl Object for Torus {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.crossings(&ray.origin, &ray.dir)
+            .into_iter()
+            .find(|(t, _)| ray.contains(*t))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = self.major_radius + self.minor_radius;
+        let r = Vec3f::new(r, r, r);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A torus around the origin, its ring of radius 2 in the xz plane and
+    /// its tube of radius 0.5.
+    fn torus() -> Torus {
+        Torus::new(
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            Material::default(),
+        )
+    }
+
+    const RIGHT: Vec3f = Vec3f::new(1.0, 0.0, 0.0);
+
+    #[test]
+    fn crosses_both_sides_of_the_tube() {
+        let crossings = torus().crossings(&Vec3f::new(-5.0, 0.0, 0.0), &RIGHT);
+        let distances: Vec<_> = crossings.iter().map(|&(t, _)| t).collect();
+        assert_eq!(distances.len(), 4, "{:?}", distances);
+        for (t, expected) in distances.iter().zip(&[2.5, 3.5, 6.5, 7.5]) {
+            assert!((t - expected).abs() < 1e-4, "{:?}", distances);
+        }
+        assert!(crossings[0].1.approx_eq(&-RIGHT, 1e-4));
+        assert!(crossings[1].1.approx_eq(&RIGHT, 1e-4));
+    }
+
+    #[test]
+    fn hits_the_tube_from_the_hole() {
+        let (distance, n) = torus()
+            .ray_intersect(&Ray::new(Vec3f::new(0.0, 0.0, 0.0), RIGHT))
+            .unwrap();
+        assert!((distance - 1.5).abs() < 1e-4);
+        assert!(n.approx_eq(&-RIGHT, 1e-4));
+    }
+
+    #[test]
+    fn misses_through_the_hole() {
+        let down = Vec3f::new(0.0, -1.0, 0.0);
+        let torus = torus();
+        assert!(torus
+            .ray_intersect(&Ray::new(Vec3f::new(0.0, 5.0, 0.0), down))
+            .is_none());
+        // Down through the hole, but close to the inside of the tube
+        assert!(torus
+            .ray_intersect(&Ray::new(Vec3f::new(1.45, 5.0, 0.0), down))
+            .is_none());
+        assert!(torus
+            .ray_intersect(&Ray::new(Vec3f::new(-5.0, 0.75, 0.0), RIGHT))
+            .is_none());
+    }
+
+    #[test]
+    fn hits_tangent_to_the_top_of_the_tube() {
+        let (distance, n) = torus()
+            .ray_intersect(&Ray::new(Vec3f::new(-5.0, 0.5, 0.0), RIGHT))
+            .unwrap();
+        assert!((distance - 3.0).abs() < 1e-2, "{}", distance);
+        assert!(n.approx_eq(&Vec3f::new(0.0, 1.0, 0.0), 1e-2));
+    }
+
+    #[test]
+    fn hits_down_through_the_tube() {
+        let (distance, n) = torus()
+            .ray_intersect(&Ray::new(
+                Vec3f::new(0.0, 5.0, 2.0),
+                Vec3f::new(0.0, -1.0, 0.0),
+            ))
+            .unwrap();
+        assert!((distance - 4.5).abs() < 1e-4);
+        assert!(n.approx_eq(&Vec3f::new(0.0, 1.0, 0.0), 1e-4));
+    }
+}
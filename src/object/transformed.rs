@@ -0,0 +1,102 @@
+//! Objects moved, turned and resized by a transform. Rays are carried into
+//! the space of the object for it to intersect, and what it finds is carried
+//! back out.
+
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector::{Float, Mat4, Vec2f, Vec3f};
+
+/// An object placed in the scene by the transform `to_world`.
+pub struct Transformed {
+    object: Box<dyn Object>,
+    to_world: Mat4,
+    to_object: Mat4,
+}
+
+impl Transformed {
+    /// Places `object` by `to_world`, given along with its inverse.
+    pub fn new(object: Box<dyn Object>, to_world: Mat4, to_object: Mat4) -> Self {
+        Self {
+            object,
+            to_world,
+            to_object,
+        }
+    }
+}
+
+impl Object for Transformed {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        // Scaling stretches the direction, and distances along it with it
+        let local_dir = self.to_object.transform_vector(&ray.dir);
+        let stretch = local_dir.len();
+        let local = Ray::new(
+            self.to_object.transform_point(&ray.origin),
+            local_dir * (1.0 / stretch),
+        )
+        .with_range(ray.t_min * stretch, ray.t_max * stretch);
+        let (distance, n) = self.object.ray_intersect(&local)?;
+        Some((
+            distance / stretch,
+            self.to_object.transform_normal(&n).normalize(),
+        ))
+    }
+
+    fn material(&self) -> &Material {
+        self.object.material()
+    }
+
+    /// The bounds of the corners of the object's bounds, which are looser
+    /// than they need to be when the object is turned.
+    fn bounds(&self) -> Option<Aabb> {
+        let Aabb { min, max } = self.object.bounds()?;
+        Some(Aabb::from_points((0..8).map(|corner| {
+            let pick =
+                |bit: usize, min: Float, max: Float| if corner & bit == 0 { min } else { max };
+            self.to_world.transform_point(&Vec3f::new(
+                pick(1, min.0, max.0),
+                pick(2, min.1, max.1),
+                pick(4, min.2, max.2),
+            ))
+        })))
+    }
+
+    /// The area of the object scaled as the transform scales areas, which
+    /// is exact unless it stretches the object more along some directions
+    /// than others.
+    fn area(&self) -> Option<Float> {
+        let scale = self.to_world.determinant().abs().powf(2.0 / 3.0);
+        self.object.area().map(|area| area * scale)
+    }
+
+    fn sample_surface(&self, uv: (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        self.object.sample_surface(uv).map(|(point, n)| {
+            (
+                self.to_world.transform_point(&point),
+                self.to_object.transform_normal(&n).normalize(),
+            )
+        })
+    }
+
+    fn texture_coordinates(&self, point: &Vec3f, dir: &Vec3f) -> Option<Vec2f> {
+        self.object.texture_coordinates(
+            &self.to_object.transform_point(point),
+            &self.to_object.transform_vector(dir).normalize(),
+        )
+    }
+
+    fn tangents(&self, point: &Vec3f, dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        self.object
+            .tangents(
+                &self.to_object.transform_point(point),
+                &self.to_object.transform_vector(dir).normalize(),
+            )
+            .map(|(u, v)| {
+                (
+                    self.to_world.transform_vector(&u),
+                    self.to_world.transform_vector(&v),
+                )
+            })
+    }
+}
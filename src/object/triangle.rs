@@ -0,0 +1,184 @@
+use super::Object;
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// Texture coordinates of the vertices of triangles that aren't given any,
+/// covering the lower left half of the texture.
+pub const DEFAULT_TEXTURE_COORDINATES: [Vec2f; 3] = [
+    Vec2f::new(0.0, 0.0),
+    Vec2f::new(1.0, 0.0),
+    Vec2f::new(0.0, 1.0),
+];
+
+#[derive(Clone)]
+pub struct Triangle {
+    v0: Vec3f,
+    v1: Vec3f,
+    v2: Vec3f,
+    material: Material,
+    texture_coordinates: [Vec2f; 3],
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3f, v1: Vec3f, v2: Vec3f, material: Material) -> Self {
+        Self {
+            v0,
+            v1,
+            v2,
+            material,
+            texture_coordinates: DEFAULT_TEXTURE_COORDINATES,
+        }
+    }
+
+    /// The triangle with the given texture coordinates at its vertices.
+    pub fn with_texture_coordinates(mut self, texture_coordinates: [Vec2f; 3]) -> Self {
+        self.texture_coordinates = texture_coordinates;
+        self
+    }
+
+    pub fn barycentric_intersect(
+        &self,
+        orig: &Vec3f,
+        dir: &Vec3f,
+    ) -> Option<(Float, Float, Float)> {
+        intersect(&self.v0, &self.v1, &self.v2, orig, dir)
+    }
+
+    pub fn normal(&self) -> Vec3f {
+        normal(&self.v0, &self.v1, &self.v2)
+    }
+}
+
+/// Möller–Trumbore intersection, returning the distance along the ray and
+/// the barycentric coordinates `(u, v)` of the hit.
+pub fn intersect(
+    v0: &Vec3f,
+    v1: &Vec3f,
+    v2: &Vec3f,
+    orig: &Vec3f,
+    dir: &Vec3f,
+) -> Option<(Float, Float, Float)> {
+    stats::count(|stats| stats.triangle_tests += 1);
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let pvec = dir.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < 1e-8 {
+        // The ray is parallel to the triangle
+        return None;
+    }
+    let inv_det = det.recip();
+
+    let tvec = *orig - *v0;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let v = dir.dot(&qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if t < 0.0 {
+        None
+    } else {
+        Some((t, u, v))
+    }
+}
+
+pub fn normal(v0: &Vec3f, v1: &Vec3f, v2: &Vec3f) -> Vec3f {
+    (*v1 - *v0).cross(&(*v2 - *v0)).normalize()
+}
+
+/// How fast points of the triangle move with each of the texture
+/// coordinates, or `None` if the coordinates of the vertices don't span an
+/// area.
+pub fn tangents(
+    v0: &Vec3f,
+    v1: &Vec3f,
+    v2: &Vec3f,
+    texture_coordinates: &[Vec2f; 3],
+) -> Option<(Vec3f, Vec3f)> {
+    let [t0, t1, t2] = texture_coordinates;
+    let (edge1, edge2) = (*v1 - *v0, *v2 - *v0);
+    let (du1, dv1, du2, dv2) = (t1.0 - t0.0, t1.1 - t0.1, t2.0 - t0.0, t2.1 - t0.1);
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = det.recip();
+    Some((
+        (edge1 * dv2 - edge2 * dv1) * inv_det,
+        (edge2 * du1 - edge1 * du2) * inv_det,
+    ))
+}
+
+/// The texture coordinates at barycentric coordinates `(u, v)`, between
+/// those of the vertices.
+pub fn interpolate(texture_coordinates: &[Vec2f; 3], u: Float, v: Float) -> Vec2f {
+    let [t0, t1, t2] = texture_coordinates;
+    let w = 1.0 - u - v;
+    Vec2f::new(
+        t0.0 * w + t1.0 * u + t2.0 * v,
+        t0.1 * w + t1.1 * u + t2.1 * v,
+    )
+}
+
+/// The normal at barycentric coordinates `(u, v)`, blended from those of
+/// the vertices.
+pub fn interpolate_normal(normals: &[Vec3f; 3], u: Float, v: Float) -> Vec3f {
+    let [n0, n1, n2] = normals;
+    (*n0 * (1.0 - u - v) + *n1 * u + *n2 * v).normalize()
+}
+
+impl Object for Triangle {
+    fn ray_intersect(&self, ray: &Ray) -> Option<(Float, Vec3f)> {
+        self.barycentric_intersect(&ray.origin, &ray.dir)
+            .filter(|(t, _, _)| ray.contains(*t))
+            .map(|(t, _, _)| (t, self.normal()))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(vec![self.v0, self.v1, self.v2]).padded(1e-4))
+    }
+
+    fn area(&self) -> Option<Float> {
+        Some((self.v1 - self.v0).cross(&(self.v2 - self.v0)).len() / 2.0)
+    }
+
+    fn sample_surface(&self, (u, v): (Float, Float)) -> Option<(Vec3f, Vec3f)> {
+        // Folding the square onto the triangle would bunch points up at v0
+        let s = u.sqrt();
+        let (b1, b2) = (s * (1.0 - v), s * v);
+        let point = self.v0 + (self.v1 - self.v0) * b1 + (self.v2 - self.v0) * b2;
+        Some((point, self.normal()))
+    }
+
+    fn texture_coordinates(&self, point: &Vec3f, _dir: &Vec3f) -> Option<Vec2f> {
+        // Barycentric coordinates from the areas the point splits the
+        // triangle into
+        let (edge1, edge2, p) = (self.v1 - self.v0, self.v2 - self.v0, *point - self.v0);
+        let n = edge1.cross(&edge2);
+        let area = n.norm();
+        if area <= 0.0 {
+            return None;
+        }
+        let u = p.cross(&edge2).dot(&n) / area;
+        let v = edge1.cross(&p).dot(&n) / area;
+        Some(interpolate(&self.texture_coordinates, u, v))
+    }
+
+    fn tangents(&self, _point: &Vec3f, _dir: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        tangents(&self.v0, &self.v1, &self.v2, &self.texture_coordinates)
+    }
+}
@@ -0,0 +1,103 @@
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::sky::xyy_to_rgb;
+use crate::vector::Float;
+
+/// The temperatures the color of a black body is known for, in kelvin
+const MIN_TEMPERATURE: Float = 1667.0;
+const MAX_TEMPERATURE: Float = 25000.0;
+/// The temperature whose black body is close enough to the white of sRGB to
+/// be left as it is
+const NEUTRAL_TEMPERATURE: Float = 6504.0;
+
+/// Which color of light comes out white, as a camera balances the light of
+/// a room lit by warm bulbs or of a shady day.
+#[derive(Clone, Copy, Debug)]
+pub enum WhiteBalance {
+    /// The light of a black body at a temperature, in kelvin, from the warm
+    /// 2700 K of a bulb to the 6500 K of daylight, which stays as it is, and
+    /// beyond to the blue of shade
+    Temperature(Float),
+    /// A linear color, such as that of a white wall in the render
+    White(Color),
+}
+
+impl WhiteBalance {
+    /// How much each channel is scaled by to make the white white, keeping
+    /// its brightness.
+    pub fn gains(self) -> Color {
+        let white = match self {
+            WhiteBalance::White(white) => white,
+            WhiteBalance::Temperature(kelvin) => {
+                let (color, neutral) = (black_body(kelvin), black_body(NEUTRAL_TEMPERATURE));
+                Color::new(
+                    color.r / neutral.r,
+                    color.g / neutral.g,
+                    color.b / neutral.b,
+                )
+            }
+        };
+        let brightness = white.luminance();
+        Color::new(
+            brightness / white.r,
+            brightness / white.g,
+            brightness / white.b,
+        )
+    }
+}
+
+/// The linear sRGB color of the light of a black body at `kelvin`, of a
+/// luminance of 1, by the fit of the Planckian locus of Kim et al. (2002).
+fn black_body(kelvin: Float) -> Color {
+    let t = kelvin.clamp(MIN_TEMPERATURE, MAX_TEMPERATURE);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.17991
+    } else {
+        -3.025847e9 / t3 + 2.107038e6 / t2 + 0.2226347e3 / t + 0.24039
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.106381 * x3 - 1.34811 * x2 + 2.185558 * x - 0.2021968
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.374186 * x2 + 2.09137 * x - 0.1674887
+    } else {
+        3.081758 * x3 - 5.873387 * x2 + 3.75113 * x - 0.3700148
+    };
+    xyy_to_rgb(x, y, 1.0).into()
+}
+
+impl FromStr for WhiteBalance {
+    type Err = String;
+
+    /// A temperature such as `3200` or `3200K`, or a color as `r,g,b`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expected = || {
+            format!(
+                "expected a temperature from {} to {} K or a color as r,g,b, not `{}`",
+                MIN_TEMPERATURE, MAX_TEMPERATURE, s
+            )
+        };
+        if s.contains(',') {
+            let channels = s
+                .split(',')
+                .map(|c| c.trim().parse::<Float>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| expected())?;
+            return match channels[..] {
+                [r, g, b] if r > 0.0 && g > 0.0 && b > 0.0 => {
+                    Ok(WhiteBalance::White(Color::new(r, g, b)))
+                }
+                _ => Err(expected()),
+            };
+        }
+        let kelvin = s.strip_suffix('K').unwrap_or(s);
+        match kelvin.parse::<Float>() {
+            Ok(kelvin) if (MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&kelvin) => {
+                Ok(WhiteBalance::Temperature(kelvin))
+            }
+            _ => Err(expected()),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Thresholds added before quantizing, which trade the bands of smooth
+//! gradients for fine noise.
+
+use std::str::FromStr;
+
+use crate::sampler::blue_noise::{self, MASK_SIZE};
+use crate::vector::Float;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    #[default]
+    None,
+    /// An 8x8 Bayer matrix, cheap but with a visible cross-hatch
+    Ordered,
+    /// A 64x64 blue noise mask, whose noise has no low frequencies to catch
+    /// the eye
+    BlueNoise,
+}
+
+impl Dither {
+    /// The threshold for the pixel, in `0..1`. Truncating `value + threshold`
+    /// rounds it up as often as its fraction asks for, on average.
+    pub fn threshold(self, x: usize, y: usize) -> Float {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => (bayer(x % 8, y % 8) as Float + 0.5) / 64.0,
+            Dither::BlueNoise => blue_noise::mask()[(y % MASK_SIZE) * MASK_SIZE + x % MASK_SIZE],
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Dither::None),
+            "ordered" => Ok(Dither::Ordered),
+            "blue-noise" => Ok(Dither::BlueNoise),
+            _ => Err(format!(
+                "unknown dithering `{}`, expected none, ordered or blue-noise",
+                s
+            )),
+        }
+    }
+}
+
+/// The rank of the cell in the 8x8 Bayer matrix: the bits of `x ^ y` and
+/// `y` interleaved, then reversed.
+fn bayer(x: usize, y: usize) -> usize {
+    let mut rank = 0;
+    for bit in 0..3 {
+        rank = (rank << 2) | (((x ^ y) >> bit & 1) << 1) | (y >> bit & 1);
+    }
+    rank
+}
@@ -0,0 +1,172 @@
+use std::io::{self, Write};
+
+use super::{zlib, Alpha};
+use crate::aov::Aov;
+use crate::render::Image;
+use crate::vector::{to_f32, Float};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+/// Version 2, single part scanline image
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+const ZIP_COMPRESSION: u8 = 3;
+/// Scanlines per block with ZIP compression
+const BLOCK_LINES: usize = 16;
+const FLOAT: i32 = 2;
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn i32s(values: &[i32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn header(
+    width: usize,
+    height: usize,
+    names: &[&String],
+    metadata: &[(String, String)],
+) -> Vec<u8> {
+    let mut channels = vec![];
+    for name in names {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&FLOAT.to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&i32s(&[1, 1]));
+    }
+    channels.push(0);
+
+    let window = i32s(&[0, 0, width as i32 - 1, height as i32 - 1]);
+    let mut header = vec![];
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[ZIP_COMPRESSION],
+    );
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    for (name, text) in metadata {
+        attribute(&mut header, name, "string", text.as_bytes());
+    }
+    header.push(0);
+    header
+}
+
+/// Prepares a block for zlib the way EXR expects: the bytes at even offsets
+/// followed by those at odd ones, then stored as differences.
+fn zip_block(raw: &[u8]) -> Vec<u8> {
+    let mut reordered: Vec<u8> = raw.iter().step_by(2).copied().collect();
+    reordered.extend(raw.iter().skip(1).step_by(2));
+    for i in (1..reordered.len()).rev() {
+        reordered[i] = reordered[i]
+            .wrapping_sub(reordered[i - 1])
+            .wrapping_add(128);
+    }
+    let compressed = zlib::compress(&reordered);
+    // Blocks that don't shrink are stored as they are
+    if compressed.len() < raw.len() {
+        compressed
+    } else {
+        raw.to_vec()
+    }
+}
+
+/// The channels of the layer of an output variable, named as Blender names
+/// them: `Z` for depth, `X`, `Y` and `Z` for directions, `R`, `G` and `B`
+/// for colors, and `X` for anything else of one value.
+fn layer_channels(aov: Aov) -> &'static [&'static str] {
+    match aov {
+        Aov::Depth => &["Z"],
+        Aov::Normal => &["X", "Y", "Z"],
+        Aov::Albedo | Aov::Lights(_) => &["R", "G", "B"],
+        Aov::Id | Aov::Cost => &["X"],
+    }
+}
+
+/// Writes the image as an OpenEXR file with 32-bit float channels, keeping
+/// the linear values as they are. Its output variables are written along
+/// with it as layers, each channel named after the variable, such as
+/// `normal.X`, as compositing packages expect of multilayer files. The
+/// metadata goes into the header as string attributes.
+pub fn encode<W: Write>(
+    image: &Image,
+    alpha: Alpha,
+    metadata: &[(String, String)],
+    w: &mut W,
+) -> io::Result<()> {
+    let names: &[&str] = match alpha {
+        Alpha::None => &["R", "G", "B"],
+        _ => &["R", "G", "B", "A"],
+    };
+    let colors = super::channels(image, alpha);
+    let mut channels: Vec<(String, Vec<Float>)> = names
+        .iter()
+        .enumerate()
+        .map(|(c, name)| {
+            let values = colors.iter().skip(c).step_by(names.len()).copied();
+            (name.to_string(), values.collect())
+        })
+        .collect();
+    for (aov, values) in &image.aovs {
+        for (c, component) in layer_channels(*aov).iter().enumerate() {
+            let values = values.iter().map(|&v| <[Float; 3]>::from(v)[c]);
+            channels.push((format!("{}.{}", aov, component), values.collect()));
+        }
+    }
+    // Channels are stored in the order of their names
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut blocks = vec![];
+    for first in (0..image.height).step_by(BLOCK_LINES) {
+        let mut raw = vec![];
+        for y in first..(first + BLOCK_LINES).min(image.height) {
+            // A row of each channel
+            for (_, values) in &channels {
+                for &value in &values[y * image.width..(y + 1) * image.width] {
+                    raw.extend_from_slice(&to_f32(value).to_le_bytes());
+                }
+            }
+        }
+        blocks.push((first, zip_block(&raw)));
+    }
+
+    let names: Vec<_> = channels.iter().map(|(name, _)| name).collect();
+    let header = header(image.width, image.height, &names, metadata);
+    let mut offset = (MAGIC.len() + VERSION.len() + header.len() + 8 * blocks.len()) as u64;
+    w.write_all(&MAGIC)?;
+    w.write_all(&VERSION)?;
+    w.write_all(&header)?;
+    for (_, data) in &blocks {
+        w.write_all(&offset.to_le_bytes())?;
+        offset += 8 + data.len() as u64;
+    }
+    for (first, data) in &blocks {
+        w.write_all(&(*first as i32).to_le_bytes())?;
+        w.write_all(&(data.len() as i32).to_le_bytes())?;
+        w.write_all(data)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+
+use crate::color::Color;
+use crate::render::Image;
+use crate::vector::Float;
+
+/// Shares one exponent between the three channels, keeping 8 bits of
+/// mantissa for each. Negative and non-finite values come out black.
+fn rgbe(pixel: &Color) -> [u8; 4] {
+    let clean = |c: Float| if c.is_finite() { c.max(0.0) } else { 0.0 };
+    let (r, g, b) = (clean(pixel.r), clean(pixel.g), clean(pixel.b));
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0; 4];
+    }
+
+    // v = mantissa * 2^exponent, with the mantissa in 0.5..1
+    let mut exponent = v.log2().floor() as i32 + 1;
+    let mut mantissa = v / Float::powi(2.0, exponent);
+    if mantissa >= 1.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    let scale = mantissa * 256.0 / v;
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/// Run-length encodes one channel of a scanline: runs of a repeated byte
+/// are stored as 128 plus their length, anything else as a count and the
+/// bytes as they are.
+fn encode_channel(values: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+    let mut i = 0;
+    while i < values.len() {
+        // Find the next run long enough to be worth it
+        let mut run_start = i;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = values[run_start..]
+                .iter()
+                .take(127)
+                .take_while(|&&v| v == values[run_start])
+                .count();
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+            run_length = 0;
+        }
+
+        // Everything before it goes out as literals
+        while i < run_start {
+            let count = (run_start - i).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&values[i..i + count]);
+            i += count;
+        }
+        if run_length > 0 {
+            out.push(128 + run_length as u8);
+            out.push(values[run_start]);
+            i += run_length;
+        }
+    }
+}
+
+/// Writes the image as a Radiance RGBE `.hdr` file, keeping the linear
+/// values as they are.
+pub fn encode<W: Write>(image: &Image, w: &mut W) -> io::Result<()> {
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )?;
+
+    let mut line = vec![];
+    for row in image.pixels.chunks(image.width) {
+        line.clear();
+        let pixels: Vec<_> = row.iter().map(rgbe).collect();
+        // Run-length encoding is only defined for these widths
+        if !(8..=0x7fff).contains(&image.width) {
+            pixels.iter().for_each(|p| line.extend_from_slice(p));
+        } else {
+            line.extend_from_slice(&[2, 2, (image.width >> 8) as u8, image.width as u8]);
+            for channel in 0..4 {
+                let values: Vec<_> = pixels.iter().map(|p| p[channel]).collect();
+                encode_channel(&values, &mut line);
+            }
+        }
+        w.write_all(&line)?;
+    }
+    Ok(())
+}
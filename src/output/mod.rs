@@ -0,0 +1,299 @@
+//! Writing rendered images to files or standard output.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::render::Image;
+use crate::vector::Float;
+
+mod balance;
+mod dither;
+mod exr;
+mod hdr;
+mod png;
+mod ppm;
+mod tonemap;
+mod transfer;
+mod video;
+pub(crate) mod zlib;
+
+pub use balance::WhiteBalance;
+pub use dither::Dither;
+pub use tonemap::ToneMap;
+pub use transfer::Transfer;
+pub use video::Video;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Ppm,
+    /// Plain text PPM, for tools that don't read the binary one
+    PpmAscii,
+    Png,
+    Exr,
+    Hdr,
+}
+
+const FORMAT_NAMES: [(&str, Format); 5] = [
+    ("ppm", Format::Ppm),
+    ("ppm-ascii", Format::PpmAscii),
+    ("png", Format::Png),
+    ("exr", Format::Exr),
+    ("hdr", Format::Hdr),
+];
+
+impl Format {
+    /// Picks the format from the extension of the path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(Format::Ppm),
+            "png" => Some(Format::Png),
+            "exr" => Some(Format::Exr),
+            "hdr" => Some(Format::Hdr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format can store an alpha channel.
+    pub fn has_alpha(self) -> bool {
+        matches!(self, Format::Png | Format::Exr)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FORMAT_NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|&(_, format)| format)
+            .ok_or_else(|| {
+                format!(
+                    "unknown image format `{}`, expected ppm, ppm-ascii, png, exr or hdr",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = FORMAT_NAMES
+            .iter()
+            .find(|(_, format)| format == self)
+            .unwrap();
+        f.write_str(name)
+    }
+}
+
+/// Bits per channel of the integer formats. EXR and HDR always store floats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    /// The largest channel value.
+    pub fn max(self) -> u16 {
+        match self {
+            BitDepth::Eight => 255,
+            BitDepth::Sixteen => 65535,
+        }
+    }
+
+    /// Bytes per channel.
+    pub fn bytes(self) -> usize {
+        match self {
+            BitDepth::Eight => 1,
+            BitDepth::Sixteen => 2,
+        }
+    }
+}
+
+impl FromStr for BitDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(BitDepth::Eight),
+            "16" => Ok(BitDepth::Sixteen),
+            _ => Err(format!("unknown bit depth `{}`, expected 8 or 16", s)),
+        }
+    }
+}
+
+/// Whether and how an alpha channel is written. Only PNG and EXR files have
+/// one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alpha {
+    #[default]
+    None,
+    /// Colors divided by the alpha, as PNG expects
+    Straight,
+    /// Colors scaled by the alpha, as EXR expects and as they are rendered
+    /// with a transparent background
+    Premultiplied,
+}
+
+impl FromStr for Alpha {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "straight" => Ok(Alpha::Straight),
+            "premultiplied" => Ok(Alpha::Premultiplied),
+            _ => Err(format!(
+                "unknown alpha mode `{}`, expected straight or premultiplied",
+                s
+            )),
+        }
+    }
+}
+
+/// How the image is written out.
+#[derive(Clone, Debug, Default)]
+pub struct OutputOptions {
+    pub format: Format,
+    pub bit_depth: BitDepth,
+    pub alpha: Alpha,
+    /// Exposure in stops, scaling the colors by `2^exposure` before anything
+    /// else
+    pub exposure: Float,
+    /// Scales the channels, after the exposure, so that light of the color
+    /// it names comes out white
+    pub white_balance: Option<WhiteBalance>,
+    /// Brings the colors into `0..1` for the integer formats
+    pub tone_map: ToneMap,
+    /// Applied to the tone mapped colors of the integer formats
+    pub transfer: Transfer,
+    /// Breaks up banding when quantizing for the integer formats
+    pub dither: Dither,
+    /// Named text stored in the file, such as the settings it was rendered
+    /// with, as text chunks of PNG and string attributes of EXR
+    pub metadata: Vec<(String, String)>,
+}
+
+/// The channels of every pixel, from the top left: red, green and blue,
+/// followed by alpha unless it is left out. The image colors are taken to be
+/// premultiplied when there is alpha.
+pub fn channels(image: &Image, alpha: Alpha) -> Vec<Float> {
+    let mut channels = Vec::with_capacity(image.pixels.len() * 4);
+    for (pixel, &a) in image.pixels.iter().zip(&image.alpha) {
+        match alpha {
+            Alpha::None => channels.extend_from_slice(&<[Float; 3]>::from(*pixel)),
+            Alpha::Premultiplied => channels.extend_from_slice(&[pixel.r, pixel.g, pixel.b, a]),
+            Alpha::Straight => {
+                let scale = if a > 0.0 { 1.0 / a } else { 0.0 };
+                channels.extend_from_slice(&[pixel.r * scale, pixel.g * scale, pixel.b * scale, a])
+            }
+        }
+    }
+    channels
+}
+
+/// Clamps a color channel to `0..1` and scales it to the bit depth, adding
+/// a dithering threshold in `0..1` before the fraction is cut off.
+pub fn quantize(c: Float, depth: BitDepth, threshold: Float) -> u16 {
+    let max = depth.max() as Float;
+    (max * Float::max(0.0, Float::min(1.0, c)) + threshold).min(max) as u16
+}
+
+/// The channels of the image, tone mapped, encoded and quantized for the
+/// integer formats. Alpha is kept linear, but dithered like the colors.
+pub fn samples(image: &Image, options: &OutputOptions) -> Vec<u16> {
+    let stride = if options.alpha == Alpha::None { 3 } else { 4 };
+    let mut channels = channels(image, options.alpha);
+    for pixel in channels.chunks_mut(stride) {
+        let color = options
+            .tone_map
+            .apply(Color::new(pixel[0], pixel[1], pixel[2]));
+        pixel[..3].copy_from_slice(&color.encode(options.transfer));
+    }
+    let mut samples = Vec::with_capacity(channels.len());
+    for (i, pixel) in channels.chunks(stride).enumerate() {
+        let threshold = options.dither.threshold(i % image.width, i / image.width);
+        samples.extend(
+            pixel
+                .iter()
+                .map(|&c| quantize(c, options.bit_depth, threshold)),
+        );
+    }
+    samples
+}
+
+/// Like `samples`, as bytes. 16-bit channels are big-endian, as both PNG and
+/// PPM want them.
+pub fn sample_bytes(image: &Image, options: &OutputOptions) -> Vec<u8> {
+    let channels = samples(image, options);
+    match options.bit_depth {
+        BitDepth::Eight => channels.iter().map(|&c| c as u8).collect(),
+        BitDepth::Sixteen => channels.iter().flat_map(|c| c.to_be_bytes()).collect(),
+    }
+}
+
+pub fn encode<W: Write>(image: &Image, options: &OutputOptions, w: &mut W) -> io::Result<()> {
+    let exposed;
+    let image = if options.exposure != 0.0 || options.white_balance.is_some() {
+        let gains = options
+            .white_balance
+            .map_or(Color::WHITE, WhiteBalance::gains);
+        let scale = gains * options.exposure.exp2();
+        exposed = Image {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.iter().map(|&pixel| pixel * scale).collect(),
+            alpha: image.alpha.clone(),
+            aovs: image.aovs.clone(),
+        };
+        &exposed
+    } else {
+        image
+    };
+    match options.format {
+        Format::Ppm => ppm::encode(image, options, w),
+        Format::PpmAscii => ppm::encode_ascii(image, options, w),
+        Format::Png => png::encode(image, options, w),
+        Format::Exr => exr::encode(image, options.alpha, &options.metadata, w),
+        Format::Hdr => hdr::encode(image, w),
+    }
+}
+
+/// Writes the image to `path`, in the format its extension asks for.
+pub fn write<P: AsRef<Path>>(image: &Image, path: P) -> error::Result<()> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)
+        .ok_or_else(|| Error::unsupported_format(path, ".ppm, .png, .exr or .hdr"))?;
+    let options = OutputOptions {
+        format,
+        ..OutputOptions::default()
+    };
+    write_as(image, path, &options)
+}
+
+/// Writes the image to `path` as the options say, or to standard output if
+/// the path is `-`.
+pub fn write_as<P: AsRef<Path>>(
+    image: &Image,
+    path: P,
+    options: &OutputOptions,
+) -> error::Result<()> {
+    let path = path.as_ref();
+    if path == Path::new("-") {
+        let stdout = io::stdout();
+        let mut w = BufWriter::new(stdout.lock());
+        encode(image, options, &mut w)?;
+        return Ok(w.flush()?);
+    }
+    let mut f = BufWriter::new(File::create(path)?);
+    encode(image, options, &mut f)?;
+    Ok(f.flush()?)
+}
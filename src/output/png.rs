@@ -0,0 +1,147 @@
+use std::io::{self, Write};
+
+use super::{zlib, Alpha, OutputOptions};
+use crate::render::Image;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut body = kind.to_vec();
+    body.extend_from_slice(data);
+    w.write_all(&body)?;
+    w.write_all(&crc32(&body).to_be_bytes())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Filters every row with whichever of the five PNG filters gives the
+/// smallest sum of absolute differences, a common guess at what compresses
+/// best.
+fn filter(rows: &[u8], stride: usize, bytes_per_pixel: usize) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity(rows.len() + rows.len() / stride);
+    let zero = vec![0; stride];
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+    for (y, row) in rows.chunks(stride).enumerate() {
+        let above = if y > 0 {
+            &rows[(y - 1) * stride..y * stride]
+        } else {
+            &zero[..]
+        };
+
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+        for kind in 0..5u8 {
+            for x in 0..stride {
+                let a = if x >= bytes_per_pixel {
+                    row[x - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let c = if x >= bytes_per_pixel {
+                    above[x - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let b = above[x];
+                let predicted = match kind {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                candidate[x] = row[x].wrapping_sub(predicted);
+            }
+            let cost = candidate
+                .iter()
+                .map(|&v| (v as i8).unsigned_abs() as u64)
+                .sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = kind;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+    filtered
+}
+
+/// The chunk of a named text: Latin-1 `tEXt` when the text is ASCII, and
+/// UTF-8 `iTXt` otherwise.
+fn text_chunk<W: Write>(w: &mut W, key: &str, text: &str) -> io::Result<()> {
+    let mut data = key.as_bytes().to_vec();
+    data.push(0);
+    if text.is_ascii() {
+        data.extend_from_slice(text.as_bytes());
+        return chunk(w, b"tEXt", &data);
+    }
+    // Uncompressed, with no language tag or translated key
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    chunk(w, b"iTXt", &data)
+}
+
+/// Writes the image as an RGB or RGBA PNG with 8 or 16 bits per channel,
+/// along with its metadata as text.
+pub fn encode<W: Write>(image: &Image, options: &OutputOptions, w: &mut W) -> io::Result<()> {
+    let depth = options.bit_depth;
+    let (color_type, channels) = match options.alpha {
+        Alpha::None => (2, 3),
+        _ => (6, 4),
+    };
+    w.write_all(&SIGNATURE)?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // Bit depth, color type, compression, filter and interlace method
+    header.extend_from_slice(&[8 * depth.bytes() as u8, color_type, 0, 0, 0]);
+    chunk(w, b"IHDR", &header)?;
+    for (key, text) in &options.metadata {
+        text_chunk(w, key, text)?;
+    }
+
+    // Filters work on bytes, so a 16-bit RGB pixel counts as six
+    let bytes_per_pixel = channels * depth.bytes();
+    let rows = super::sample_bytes(image, options);
+    let data = zlib::compress(&filter(
+        &rows,
+        image.width * bytes_per_pixel,
+        bytes_per_pixel,
+    ));
+    chunk(w, b"IDAT", &data)?;
+    chunk(w, b"IEND", &[])
+}
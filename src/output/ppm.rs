@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+
+use super::{Alpha, OutputOptions};
+use crate::render::Image;
+
+/// PPM has no alpha channel, so any is left out.
+fn without_alpha(options: &OutputOptions) -> OutputOptions {
+    OutputOptions {
+        alpha: Alpha::None,
+        ..options.clone()
+    }
+}
+
+/// Writes the image as a binary PPM.
+pub fn encode<W: Write>(image: &Image, options: &OutputOptions, w: &mut W) -> io::Result<()> {
+    let max = options.bit_depth.max();
+    write!(w, "P6\n{} {}\n{}\n", image.width, image.height, max)?;
+    w.write_all(&super::sample_bytes(image, &without_alpha(options)))
+}
+
+/// Writes the image as a plain text PPM, a pixel per line.
+pub fn encode_ascii<W: Write>(image: &Image, options: &OutputOptions, w: &mut W) -> io::Result<()> {
+    let max = options.bit_depth.max();
+    write!(w, "P3\n{} {}\n{}\n", image.width, image.height, max)?;
+    for rgb in super::samples(image, &without_alpha(options)).chunks(3) {
+        writeln!(w, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
+    }
+    Ok(())
+}
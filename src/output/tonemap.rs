@@ -0,0 +1,47 @@
+use std::str::FromStr;
+
+use crate::color::Color;
+
+/// Curves mapping linear colors to `0..1`, applied before quantizing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMap {
+    /// Cuts off anything brighter than white
+    #[default]
+    Clamp,
+    /// `c / (1 + c)`, which never quite reaches white
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve, with a slight toe
+    /// and a soft shoulder
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(self, color: Color) -> Color {
+        color.map(|c| {
+            let c = c.max(0.0);
+            match self {
+                ToneMap::Clamp => c.min(1.0),
+                ToneMap::Reinhard => c / (1.0 + c),
+                ToneMap::Aces => {
+                    ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+                }
+            }
+        })
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!(
+                "unknown tone mapping `{}`, expected clamp, reinhard or aces",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::vector::Float;
+
+/// How linear values in `0..1` are encoded in the integer formats, which
+/// viewers take to be sRGB unless told otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transfer {
+    /// The sRGB curve: linear near black, then close to a gamma of 2.2
+    #[default]
+    Srgb,
+    /// A plain power curve, raising values to `1 / gamma`. A gamma of 1
+    /// keeps them linear.
+    Gamma(Float),
+}
+
+impl Transfer {
+    pub fn encode(self, c: Float) -> Float {
+        match self {
+            Transfer::Srgb if c <= 0.003_130_8 => 12.92 * c,
+            Transfer::Srgb => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+            Transfer::Gamma(gamma) => c.max(0.0).powf(1.0 / gamma),
+        }
+    }
+
+    /// The linear value an encoded one in `0..1` stands for.
+    pub fn decode(self, c: Float) -> Float {
+        match self {
+            Transfer::Srgb if c <= 0.040_45 => c / 12.92,
+            Transfer::Srgb => ((c + 0.055) / 1.055).powf(2.4),
+            Transfer::Gamma(gamma) => c.max(0.0).powf(gamma),
+        }
+    }
+}
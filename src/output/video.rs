@@ -0,0 +1,59 @@
+//! Videos, encoded by ffmpeg from frames piped into it one after another,
+//! so that an animation needs no image files on the way.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use super::{encode, Alpha, Format, OutputOptions};
+use crate::render::Image;
+use crate::vector::Float;
+
+/// A video being written by ffmpeg, in whatever format its extension asks
+/// ffmpeg for, such as `.mp4` or `.webm`.
+pub struct Video {
+    ffmpeg: Child,
+    frames: ChildStdin,
+}
+
+impl Video {
+    /// Starts ffmpeg writing the video to `path`, at `fps` frames per
+    /// second. Its colors are subsampled the way most players want them,
+    /// which needs the width and the height of the frames to be even.
+    pub fn start<P: AsRef<Path>>(path: P, fps: Float) -> io::Result<Self> {
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "image2pipe", "-c:v", "ppm"])
+            .args(["-framerate", &fps.to_string(), "-i", "-"])
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path.as_ref())
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not run ffmpeg: {}", e)))?;
+        let frames = ffmpeg.stdin.take().unwrap();
+        Ok(Video { ffmpeg, frames })
+    }
+
+    /// Adds the image as the next frame, tone mapped and encoded as the
+    /// options say for PPM.
+    pub fn write_frame(&mut self, image: &Image, options: &OutputOptions) -> io::Result<()> {
+        let options = OutputOptions {
+            format: Format::Ppm,
+            alpha: Alpha::None,
+            ..options.clone()
+        };
+        encode(image, &options, &mut self.frames)?;
+        self.frames.flush()
+    }
+
+    /// Ends the video and waits for ffmpeg to finish writing it.
+    pub fn finish(self) -> io::Result<()> {
+        let Video { mut ffmpeg, frames } = self;
+        drop(frames);
+        let status = ffmpeg.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg failed, {}", status)));
+        }
+        Ok(())
+    }
+}
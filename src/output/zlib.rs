@@ -0,0 +1,177 @@
+//! zlib compression with LZ77 matching and the fixed Huffman codes of
+//! deflate. Not as tight as a full encoder, but it needs no code tables in the
+//! stream and does well on the large flat areas of rendered images.
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Candidates looked at per position before settling for the best so far
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(crate) const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Writes bits least significant first, the way deflate packs them.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, count: u32) {
+        self.buffer |= (bits as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are stored most significant bit first.
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+
+    fn literal_length(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn copy(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASE
+            .iter()
+            .rposition(|&base| base as usize <= length)
+            .unwrap();
+        self.literal_length(257 + code as u32);
+        self.write(
+            (length - LENGTH_BASE[code] as usize) as u32,
+            LENGTH_EXTRA[code] as u32,
+        );
+
+        let code = DISTANCE_BASE
+            .iter()
+            .rposition(|&base| base as usize <= distance)
+            .unwrap();
+        self.write_code(code as u32, 5);
+        self.write(
+            (distance - DISTANCE_BASE[code] as usize) as u32,
+            DISTANCE_EXTRA[code] as u32,
+        );
+    }
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let v = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// Compresses the data into a zlib stream.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        bytes: vec![0x78, 0x9c],
+        buffer: 0,
+        count: 0,
+    };
+    // A single final block with fixed codes
+    w.write(1, 1);
+    w.write(1, 2);
+
+    // Chains of earlier positions with the same hash, newest first
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW];
+    let insert = |head: &mut [usize], previous: &mut [usize], position: usize| {
+        if position + MIN_MATCH <= data.len() {
+            let h = hash(&data[position..]);
+            previous[position % WINDOW] = head[h];
+            head[h] = position;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(&data[i..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || i - candidate > WINDOW {
+                    break;
+                }
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = previous[candidate % WINDOW];
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            w.copy(best_length, best_distance);
+            for position in i..i + best_length {
+                insert(&mut head, &mut previous, position);
+            }
+            i += best_length;
+        } else {
+            w.literal_length(data[i] as u32);
+            insert(&mut head, &mut previous, i);
+            i += 1;
+        }
+    }
+    w.literal_length(256);
+
+    let mut bytes = w.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
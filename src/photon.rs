@@ -0,0 +1,314 @@
+//! Caustics: the light of the lights focused by mirrors and polished metal
+//! onto the surfaces around them, such as the bright spots a curved mirror
+//! throws on the floor. Neither integrator finds it on its own, since the
+//! light would have to be aimed at through the mirror, so it is traced the
+//! other way before the render, from the lights off the shiny surfaces, and
+//! left as photons where it lands on diffuse ones. The light reaching a
+//! point then goes by how many photons landed around it.
+//!
+//! Photons start out from points spread over the shiny surfaces, carrying
+//! the light of each light falling on the bit of surface around the point,
+//! rather than from the lights themselves, so that none are wasted on parts
+//! of the scene with nothing to focus them.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::aabb::Aabb;
+use crate::light;
+use crate::material::{Material, MetallicRoughness};
+use crate::object::{surface_material, Hittable};
+use crate::ray::Ray;
+use crate::render::occluded_along;
+use crate::sampler::{self, Sampler, SamplerKind};
+use crate::scene::{ObjectHandle, Scene};
+use crate::vector::{consts::PI, Float, Vec3f};
+
+/// How rough a metallic-roughness surface may be and still focus light
+/// sharply enough to throw caustics; rougher ones spread it about as much
+/// as the path tracer finds anyway
+const MAX_ROUGHNESS: Float = 0.2;
+/// How many times photons are reflected from shiny surface to shiny surface
+/// before they are given up on
+const MAX_BOUNCES: usize = 8;
+/// How far photons start off the surfaces they leave
+const LIFT: Float = 1e-3;
+/// The default gathering radius as a fraction of the size of the bounds of
+/// the photons
+const RADIUS_FRACTION: Float = 0.01;
+
+struct Photon {
+    position: Vec3f,
+    /// The direction it arrived along
+    dir: Vec3f,
+    /// The light it carries, as the light of a light arrives at a point
+    /// times the area the photon stands for
+    power: Vec3f,
+    /// Which of the lights of the scene it came from
+    light: usize,
+}
+
+/// Photons left where reflected light landed, sorted into a grid of cells
+/// as wide as the radius they are gathered within.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    /// The light groups of the lights of the scene
+    groups: Vec<Option<String>>,
+    radius: Float,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+    /// The objects the photons start out from
+    sources: HashSet<ObjectHandle>,
+}
+
+/// The part of the material that reflects like a mirror, if it is shiny
+/// enough to throw caustics.
+pub(crate) fn shiny(material: &Material) -> Option<MetallicRoughness> {
+    material
+        .metallic_roughness
+        .filter(|pbr| pbr.metallic > 0.0 && pbr.roughness <= MAX_ROUGHNESS)
+}
+
+impl PhotonMap {
+    /// Traces about `count` photons off the shiny surfaces of the scene,
+    /// gathered within `radius`, or a hundredth of the size of where they
+    /// landed if not given. Only objects whose surfaces can be sampled,
+    /// such as spheres, quads, disks and triangles, throw caustics.
+    pub fn trace(scene: &Scene, count: usize, radius: Option<Float>) -> Self {
+        let sources: Vec<_> = scene
+            .objects()
+            .iter()
+            .filter(|(_, object)| shiny(object.material()).is_some())
+            .filter_map(|(i, object)| Some((i, object, object.area()?)))
+            .collect();
+        let total_area: Float = sources.iter().map(|(_, _, area)| area).sum();
+        let halton = SamplerKind::Halton.build(1, 0);
+        let handles = sources.iter().map(|&(handle, _, _)| handle).collect();
+
+        let mut photons = vec![];
+        for (handle, object, area) in sources {
+            let i = handle.index();
+            let share = (count as Float * area / total_area).ceil() as usize;
+            for j in 0..share {
+                let (point, n) = match object.sample_surface(halton.get_2d(i, 0, j, 0)) {
+                    Some(surface) => surface,
+                    None => continue,
+                };
+                for (k, light) in scene.lights.iter().enumerate() {
+                    let incident = light.incident(&point, halton.get_2d(i, k, j, 1));
+                    let cos = n.dot(&incident.direction);
+                    let start = point + n * LIFT;
+                    if cos <= 0.0
+                        || occluded_along(scene, &start, &incident.direction, incident.distance)
+                    {
+                        continue;
+                    }
+                    let power = incident.intensity * (cos * area / share as Float);
+                    photons.extend(follow(
+                        scene,
+                        object.material(),
+                        (point, n),
+                        -incident.direction,
+                        (power, k),
+                        (&*halton, i * scene.lights.len() + k, j),
+                    ));
+                }
+            }
+        }
+
+        let radius = radius.unwrap_or_else(|| {
+            let bounds = Aabb::from_points(photons.iter().map(|photon| photon.position));
+            (bounds.max - bounds.min).norm().sqrt() * RADIUS_FRACTION
+        });
+        let radius = if radius > 0.0 { radius } else { 1.0 };
+        let mut cells: HashMap<_, Vec<_>> = HashMap::new();
+        for (i, photon) in photons.iter().enumerate() {
+            cells
+                .entry(cell(&photon.position, radius))
+                .or_default()
+                .push(i);
+        }
+        let groups = scene
+            .lights
+            .iter()
+            .map(|light| light.group().map(str::to_string))
+            .collect();
+        Self {
+            photons,
+            groups,
+            radius,
+            cells,
+            sources: handles,
+        }
+    }
+
+    /// Whether photons start out from the object, so that the light of the
+    /// lights it reflects onto diffuse surfaces is in the map.
+    pub fn is_source(&self, handle: Option<ObjectHandle>) -> bool {
+        handle.is_some_and(|handle| self.sources.contains(&handle))
+    }
+
+    /// How many photons landed.
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// The light the photons bring to `point` of a surface facing `n`,
+    /// as the light of the lights arrives: the photons within the radius
+    /// that arrived on the side it faces, over the area of the circle. Only
+    /// the photons of lights that shine count.
+    pub fn irradiance(&self, point: &Vec3f, n: &Vec3f) -> Vec3f {
+        let (x, y, z) = cell(point, self.radius);
+        let radius2 = self.radius * self.radius;
+        let mut sum = Vec3f::new(0.0, 0.0, 0.0);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let nearby = match self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        Some(nearby) => nearby,
+                        None => continue,
+                    };
+                    for photon in nearby.iter().map(|&i| &self.photons[i]) {
+                        if photon.dir.dot(n) < 0.0
+                            && (photon.position - *point).norm() <= radius2
+                            && light::shines(self.groups[photon.light].as_deref())
+                        {
+                            sum += photon.power;
+                        }
+                    }
+                }
+            }
+        }
+        sum * (1.0 / (PI * radius2))
+    }
+}
+
+/// The cell of the grid `point` is in.
+fn cell(point: &Vec3f, size: Float) -> (i64, i64, i64) {
+    let index = |x: Float| (x / size).floor() as i64;
+    (index(point.0), index(point.1), index(point.2))
+}
+
+/// The photons left by light arriving along `dir` at `point` of a shiny
+/// surface of `material` facing `n`, with `power`, from light `light` of the
+/// scene, as it is reflected from shiny surface to shiny surface until it
+/// lands on diffuse ones. Each bounce picks its direction from the sampler
+/// by the numbers of the stream of the light and of the photon.
+fn follow(
+    scene: &Scene,
+    material: &Material,
+    (mut point, mut n): (Vec3f, Vec3f),
+    mut dir: Vec3f,
+    (mut power, light): (Vec3f, usize),
+    (sampler, stream, photon): (&dyn Sampler, usize, usize),
+) -> Vec<Photon> {
+    let mut photons = vec![];
+    let (mut pbr, mut color) = (shiny(material), material.diffuse_color);
+    for depth in 0..MAX_BOUNCES {
+        let shiny_part = match pbr {
+            Some(pbr) => pbr,
+            None => break,
+        };
+        let n_facing = if n.dot(&dir) > 0.0 { -n } else { n };
+        let uv = sampler.get_2d(
+            stream,
+            0,
+            photon,
+            sampler::bounce(depth, sampler::DIRECTION),
+        );
+        let (next, weight) = match shiny_part.sample_specular(&color, &n_facing, &-dir, uv) {
+            Some(reflected) => reflected,
+            None => break,
+        };
+        power *= weight;
+        let hit = match scene.hit(&Ray::new(point + n_facing * LIFT, next)) {
+            Some(hit) => hit,
+            None => break,
+        };
+        let material = surface_material(&hit, 0.0);
+        let diffuse = material.diffuse_reflectance();
+        if diffuse.0 > 0.0 || diffuse.1 > 0.0 || diffuse.2 > 0.0 {
+            photons.push(Photon {
+                position: hit.point,
+                dir: next,
+                power,
+                light,
+            });
+        }
+        pbr = shiny(&material);
+        color = material.diffuse_color;
+        n = hit.normal;
+        point = hit.point;
+        dir = next;
+    }
+    photons
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::integrator::Integrator;
+    use crate::render::{self, RenderOptions, RenderState, Tile};
+
+    /// The average brightness of the floor in front of a wall of `wall`,
+    /// lit by a light between them, path traced with the caustics of
+    /// `photons` photons if any.
+    fn floor_brightness(wall: &str, photons: Option<usize>) -> Float {
+        let source = format!(
+            r#"{{
+                "camera": {{ "position": [0, 3, 4], "target": [0, 0, -2] }},
+                "background": [0, 0, 0],
+                "objects": [
+                    {{ "type": "quad", "corner": [-4, 0, 2], "u": [8, 0, 0], "v": [0, 0, -8],
+                       "material": {{ "albedo": [1, 0], "diffuse_color": [0.8, 0.8, 0.8] }} }},
+                    {{ "type": "quad", "corner": [-4, 0, -2], "u": [8, 0, 0], "v": [0, 4, 0],
+                       "material": {} }}
+                ],
+                "lights": [{{ "position": [0, 2, 0], "intensity": 1 }}]
+            }}"#,
+            wall
+        );
+        let mut scene = Scene::parse(&source, Path::new("")).unwrap();
+        if let Some(photons) = photons {
+            scene.caustics = Some(PhotonMap::trace(&scene, photons, None));
+        }
+        // Only the rows that see the floor in front of the wall
+        let (width, height) = (32, 24);
+        let options = RenderOptions {
+            integrator: Integrator::Path,
+            samples_per_pixel: 512,
+            crop: Some(Tile {
+                x: 0,
+                y: 16,
+                width,
+                height: 8,
+            }),
+            ..RenderOptions::default()
+        };
+        let state = RenderState::new(width, height, options.tile_size);
+        let image = render::resume(&scene, &options, state, |_, _| {}).image;
+        let floor = &image.pixels[width * 16..];
+        floor.iter().map(|color| color.luminance()).sum::<Float>() / floor.len() as Float
+    }
+
+    #[test]
+    fn caustics_add_no_light_the_path_tracer_finds() {
+        let metal = r#"{ "base_color": [0.9, 0.9, 0.9], "metallic": 1, "roughness": 0.15 }"#;
+        let black = r#"{ "albedo": [0, 0], "diffuse_color": [0, 0, 0] }"#;
+        let unlit = floor_brightness(black, None);
+        let traced = floor_brightness(metal, None) - unlit;
+        let photons = floor_brightness(metal, Some(400_000)) - unlit;
+        assert!(traced > 0.0);
+        // Counting it twice would double it
+        assert!(
+            (photons - traced).abs() < 0.5 * traced,
+            "the path tracer finds {} of reflected light, and {} with photons",
+            traced,
+            photons
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Real roots of low-degree polynomials, in `f64` since quartic coefficients
+//! quickly lose precision in single precision.
+
+/// Roots of `a x^2 + b x + c`.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < 1e-12 {
+        return if b.abs() < 1e-12 {
+            vec![]
+        } else {
+            vec![-c / b]
+        };
+    }
+    // A discriminant within rounding of zero is a repeated root, such as
+    // where a ray grazes a surface
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < -1e-12 * b * b {
+        return vec![];
+    }
+    let discriminant = discriminant.max(0.0);
+    // Avoids the cancellation of the textbook formula
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    vec![q / a, c / q]
+}
+
+/// Roots of `x^3 + a x^2 + b x + c`, a repeated one listed twice but a
+/// triple one once.
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+    let q3 = q * q * q;
+    // A repeated root is where the two cases meet, which rounding may put on
+    // either side
+    if q > 0.0 && r * r <= q3 * (1.0 + 1e-9) {
+        let theta = (r / q3.sqrt()).clamp(-1.0, 1.0).acos();
+        let m = -2.0 * q.sqrt();
+        let tau = 2.0 * std::f64::consts::PI;
+        vec![
+            m * (theta / 3.0).cos() - shift,
+            m * ((theta + tau) / 3.0).cos() - shift,
+            m * ((theta - tau) / 3.0).cos() - shift,
+        ]
+    } else {
+        let s = -r.signum() * (r.abs() + (r * r - q3).sqrt()).cbrt();
+        let t = if s == 0.0 { 0.0 } else { q / s };
+        vec![s + t - shift]
+    }
+}
+
+/// Roots of `x^4 + a x^3 + b x^2 + c x + d`, using Ferrari's method followed
+/// by a few Newton iterations to polish the results.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Depress the quartic with x = y - a / 4 into y^4 + p y^2 + q y + r
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut roots = if q.abs() < 1e-12 {
+        // Biquadratic
+        solve_quadratic(1.0, p, r)
+            .into_iter()
+            .filter(|&z| z >= 0.0)
+            .flat_map(|z| vec![z.sqrt(), -z.sqrt()])
+            .collect::<Vec<_>>()
+    } else {
+        // Any positive root of the resolvent cubic splits the quartic into two
+        // quadratics
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        let mut roots = solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s));
+        roots.extend(solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s)));
+        roots
+    };
+
+    for root in &mut roots {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df.abs() > 1e-12 {
+                *root = x - f / df;
+            }
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `roots` are `expected`, in any order, each as many times
+    /// as it is listed.
+    fn assert_roots(mut roots: Vec<f64>, expected: &[f64]) {
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn solves_quadratics() {
+        // (x - 1)(2x + 3)
+        assert_roots(solve_quadratic(2.0, 1.0, -3.0), &[-1.5, 1.0]);
+        // (x - 2)^2
+        assert_roots(solve_quadratic(1.0, -4.0, 4.0), &[2.0, 2.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        assert_roots(solve_quadratic(1.0, 3.0, 0.0), &[-3.0, 0.0]);
+        // (x - 4)^2, rounded to a discriminant just below zero
+        assert_roots(solve_quadratic(1.0, -8.0, 16.000000000000014), &[4.0, 4.0]);
+    }
+
+    #[test]
+    fn solves_quadratics_without_a_square() {
+        assert_roots(solve_quadratic(0.0, 2.0, -3.0), &[1.5]);
+        assert_roots(solve_quadratic(0.0, 0.0, 1.0), &[]);
+    }
+
+    #[test]
+    fn solves_cubics() {
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(solve_cubic(0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(solve_cubic(-2.0, 1.0, -2.0), &[2.0]);
+        // (x - 1)^2 (x + 2)
+        assert_roots(solve_cubic(0.0, -3.0, 2.0), &[-2.0, 1.0, 1.0]);
+        // (x + 1)^3
+        assert_roots(solve_cubic(3.0, 3.0, 1.0), &[-1.0]);
+    }
+
+    #[test]
+    fn solves_quartics() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            solve_quartic(-10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x - 1)(x + 2)(x^2 + 1)
+        assert_roots(solve_quartic(1.0, -1.0, 1.0, -2.0), &[-2.0, 1.0]);
+        // (x^2 + 1)(x^2 + 4)
+        assert_roots(solve_quartic(0.0, 5.0, 0.0, 4.0), &[]);
+        // (x^2 - 1)^2, with a biquadratic of repeated roots
+        assert_roots(solve_quartic(0.0, -2.0, 0.0, 1.0), &[-1.0, -1.0, 1.0, 1.0]);
+        // (x - 1)^2 (x - 2)(x - 3)
+        assert_roots(solve_quartic(-7.0, 17.0, -17.0, 6.0), &[1.0, 1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,265 @@
+//! Well-known scenes built in, to render without writing a scene file and to
+//! check the renderer against: the scene of the tinyraytracer tutorial, the
+//! Cornell box, and the random spheres on the cover of Ray Tracing in One
+//! Weekend.
+
+use std::path::Path;
+
+use crate::accel::AcceleratorKind;
+use crate::background::Background;
+use crate::camera::{decimal, Camera};
+use crate::material::Material;
+use crate::object::{Object, Quad};
+use crate::scene::Scene;
+use crate::vector::{consts::PI, Float, Vec2f, Vec3f};
+
+/// The names of the presets, for listing them.
+pub const NAMES: [&str; 3] = ["tutorial", "cornell", "spheres"];
+
+/// The preset scene named `name`, if there is one, built with the given
+/// kind of acceleration structure.
+pub fn scene(name: &str, accel: AcceleratorKind) -> Option<Scene> {
+    match name {
+        "tutorial" => Some(Scene::tutorial_with_accelerator(accel)),
+        "cornell" => Some(cornell_box(accel)),
+        "spheres" => Some(spheres(accel)),
+        _ => None,
+    }
+}
+
+/// A surface scattering light diffusely in the color.
+fn diffuse(r: Float, g: Float, b: Float) -> Material {
+    Material::new(Vec2f::new(1.0, 0.0), Vec3f::new(r, g, b), 1.0)
+}
+
+/// The Cornell box as it was measured, 555 units across: a white room with
+/// a red wall on the left and a green one on the right, lit by a light in
+/// the ceiling, with a short and a tall block turned towards each other.
+/// Only the light lights it, which is best seen path traced.
+pub fn cornell_box(accel: AcceleratorKind) -> Scene {
+    let (white, red, green) = (
+        diffuse(0.73, 0.73, 0.73),
+        diffuse(0.65, 0.05, 0.05),
+        diffuse(0.12, 0.45, 0.15),
+    );
+    let mut light = diffuse(0.0, 0.0, 0.0);
+    light.emission = Vec3f::new(17.0, 12.0, 4.0);
+    let size = 555.0;
+    let (x, y, z) = (
+        Vec3f::new(size, 0.0, 0.0),
+        Vec3f::new(0.0, size, 0.0),
+        Vec3f::new(0.0, 0.0, size),
+    );
+    let origin = Vec3f::new(0.0, 0.0, 0.0);
+
+    // The walls face into the room, and the light down from just under the
+    // ceiling
+    let mut objects: Vec<Box<dyn Object>> = vec![
+        Box::new(Quad::new(origin, z, x, white.clone())),
+        Box::new(Quad::new(y, x, z, white.clone())),
+        Box::new(Quad::new(z, y, x, white.clone())),
+        Box::new(Quad::new(origin, y, z, green)),
+        Box::new(Quad::new(x, z, y, red)),
+        Box::new(Quad::new(
+            Vec3f::new(213.0, 554.0, 227.0),
+            Vec3f::new(130.0, 0.0, 0.0),
+            Vec3f::new(0.0, 0.0, 105.0),
+            light,
+        )),
+    ];
+    objects.extend(block((185.0, 169.0), 165.0, 165.0, -18.0, &white));
+    objects.extend(block((368.0, 351.0), 165.0, 330.0, 15.0, &white));
+
+    let mut scene = Scene::with_accelerator(objects, vec![], accel);
+    scene.background = Background::Color(Vec3f::new(0.0, 0.0, 0.0));
+    scene.camera = Camera::look_at(
+        Vec3f::new(278.0, 278.0, -800.0),
+        Vec3f::new(278.0, 278.0, 0.0),
+        Vec3f::new(0.0, 1.0, 0.0),
+    );
+    scene.camera.fov = 40.0 * PI / 180.0;
+    scene
+}
+
+/// The top and sides of a block standing on the floor, centered at `x` and
+/// `z`, `width` across and `height` high, turned by `angle` degrees about
+/// the vertical.
+fn block(
+    (x, z): (Float, Float),
+    width: Float,
+    height: Float,
+    angle: Float,
+    material: &Material,
+) -> Vec<Box<dyn Object>> {
+    let (sin, cos) = (angle * PI / 180.0).sin_cos();
+    let u = Vec3f::new(cos, 0.0, -sin) * width;
+    let v = Vec3f::new(sin, 0.0, cos) * width;
+    let up = Vec3f::new(0.0, height, 0.0);
+    let corner = Vec3f::new(x, 0.0, z) - (u + v) * 0.5;
+    // Each faces out of the block
+    let faces = [
+        (corner + up, v, u),
+        (corner, up, u),
+        (corner + u, up, v),
+        (corner + u + v, up, -u),
+        (corner + v, up, -v),
+    ];
+    faces
+        .iter()
+        .map(|&(corner, a, b)| Box::new(Quad::new(corner, a, b, material.clone())) as _)
+        .collect()
+}
+
+/// A small generator of pseudo-random numbers, xorshift64*, enough for
+/// scattering spheres the same way from the same seed.
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        // Mixed, so that nearby seeds start far apart, and never zero
+        Self((seed ^ 0x9e37_79b9_7f4a_7c15).wrapping_mul(0xbf58_476d_1ce4_e5b9) | 1)
+    }
+
+    /// A number from 0 up to 1.
+    pub(crate) fn next(&mut self) -> Float {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+        bits as Float / (1u64 << 24) as Float
+    }
+
+    fn range(&mut self, min: Float, max: Float) -> Float {
+        min + (max - min) * self.next()
+    }
+}
+
+/// How many small spheres the cover has, about
+pub const COVER_SPHERES: usize = 450;
+
+/// The cover of Ray Tracing in One Weekend: a field of small spheres around
+/// three large ones, under a blue sky and a sun, as `random_spheres` scatters
+/// them from seed 0.
+pub fn spheres(accel: AcceleratorKind) -> Scene {
+    Scene::parse_with_accelerator(&random_spheres(COVER_SPHERES, 0), Path::new(""), accel).unwrap()
+}
+
+/// A scene description like the cover of Ray Tracing in One Weekend with
+/// `count` small spheres, scattered over a grid around the three large ones
+/// and given random materials from `seed`: mostly diffuse, some metal of
+/// random roughness, and a few that would be glass on the cover, clear
+/// coated white instead as there is no glass to render them with.
+pub fn random_spheres(count: usize, seed: u64) -> String {
+    let large = [
+        (
+            0.0,
+            "{\"base_color\": [1, 1, 1], \"metallic\": 0, \"roughness\": 0}",
+        ),
+        (
+            -4.0,
+            "{\"albedo\": [1, 0], \"diffuse_color\": [0.4, 0.2, 0.1]}",
+        ),
+        (
+            4.0,
+            "{\"base_color\": [0.7, 0.6, 0.5], \"metallic\": 1, \"roughness\": 0}",
+        ),
+    ];
+    // Cells of the grid around the origin, but not under the large spheres,
+    // in a grid just large enough
+    let clear = |a: i64, b: i64| {
+        let (x, z) = (a as Float + 0.5, b as Float + 0.5);
+        large.iter().all(|&(lx, _)| (x - lx).hypot(z) > 1.5)
+    };
+    let mut side = 1;
+    let cells = loop {
+        let half = side as i64 / 2;
+        let range = -half..side as i64 - half;
+        let cells: Vec<_> = range
+            .clone()
+            .flat_map(|a| range.clone().map(move |b| (a, b)))
+            .filter(|&(a, b)| clear(a, b))
+            .collect();
+        if cells.len() >= count {
+            break cells;
+        }
+        side += 1;
+    };
+
+    let mut random = Random::new(seed);
+    let mut cells = cells;
+    // Picked at random, so that fewer spheres than cells spread out
+    for i in (1..cells.len()).rev() {
+        cells.swap(i, (random.next() * (i + 1) as Float) as usize % (i + 1));
+    }
+    cells.truncate(count);
+    cells.sort_unstable();
+
+    let vector =
+        |x: Float, y: Float, z: Float| format!("[{}, {}, {}]", decimal(x), decimal(y), decimal(z));
+    let sphere = |center: String, radius: Float, material: &str| {
+        format!(
+            "        {{\"type\": \"sphere\", \"center\": {}, \"radius\": {}, \"material\": {}}}",
+            center,
+            decimal(radius),
+            material
+        )
+    };
+    let mut objects = vec![sphere(
+        vector(0.0, -1000.0, 0.0),
+        1000.0,
+        "{\"albedo\": [1, 0], \"diffuse_color\": [0.5, 0.5, 0.5]}",
+    )];
+    for (a, b) in cells {
+        let center = vector(
+            a as Float + 0.1 + 0.8 * random.next(),
+            0.2,
+            b as Float + 0.1 + 0.8 * random.next(),
+        );
+        let choice = random.next();
+        let material = if choice < 0.8 {
+            let mut color = || random.next() * random.next();
+            format!(
+                "{{\"albedo\": [1, 0], \"diffuse_color\": {}}}",
+                vector(color(), color(), color())
+            )
+        } else if choice < 0.95 {
+            let mut color = || random.range(0.5, 1.0);
+            let color = vector(color(), color(), color());
+            format!(
+                "{{\"base_color\": {}, \"metallic\": 1, \"roughness\": {}}}",
+                color,
+                decimal(random.range(0.0, 0.5))
+            )
+        } else {
+            large[0].1.to_string()
+        };
+        objects.push(sphere(center, 0.2, &material));
+    }
+    for &(x, material) in &large {
+        objects.push(sphere(vector(x, 1.0, 0.0), 1.0, material));
+    }
+
+    let mut camera = Camera::look_at(
+        Vec3f::new(13.0, 2.0, 3.0),
+        Vec3f::new(0.0, 0.0, 0.0),
+        Vec3f::new(0.0, 1.0, 0.0),
+    );
+    camera.fov = 20.0 * PI / 180.0;
+    camera.aperture = 0.1;
+    camera.focus_distance = Some(10.0);
+    format!(
+        concat!(
+            "{{\n",
+            "    \"camera\": {},\n",
+            "    \"background\": {{\"type\": \"gradient\", \"top\": [0.5, 0.7, 1], \"bottom\": [1, 1, 1]}},\n",
+            "    \"lights\": [\n",
+            "        {{\"type\": \"directional\", \"direction\": [-1, -2, -1], \"intensity\": 1}}\n",
+            "    ],\n",
+            "    \"objects\": [\n{}\n",
+            "    ]\n",
+            "}}\n"
+        ),
+        camera.to_json(),
+        objects.join(",\n")
+    )
+}
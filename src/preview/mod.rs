@@ -0,0 +1,98 @@
+//! A window showing the image while it renders. It is drawn by talking to
+//! the X server of `$DISPLAY` directly, which is only built with the
+//! `preview` feature on Unix; without it, opening a window fails.
+
+use std::io;
+
+use crate::output::{self, Alpha, BitDepth, OutputOptions};
+use crate::render::Image;
+
+#[cfg(all(unix, feature = "preview"))]
+mod x11;
+
+#[cfg(not(all(unix, feature = "preview")))]
+mod x11 {
+    use std::io;
+
+    use super::Event;
+
+    /// Stands in for the X11 client, when it isn't built
+    pub(super) enum Display {}
+
+    impl Display {
+        pub(super) fn open(_: usize, _: usize, _: &str) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "built without the `preview` feature",
+            ))
+        }
+
+        pub(super) fn draw(&mut self, _: usize, _: usize, _: &[u8]) -> io::Result<()> {
+            match *self {}
+        }
+
+        pub(super) fn poll(&mut self) -> Option<Event> {
+            match *self {}
+        }
+    }
+}
+
+/// Something done to the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The window was closed, or the connection to the display lost
+    Closed,
+    Key(Key),
+    /// The mouse moved across and down by so many pixels with the left
+    /// button held
+    Drag(i32, i32),
+    /// The wheel turned by so many steps away from the user, or towards
+    /// them if negative
+    Scroll(i32),
+}
+
+/// A key pressed in the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Escape,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// A key typing a character, in lower case for letters
+    Char(char),
+}
+
+pub struct Window {
+    display: x11::Display,
+}
+
+impl Window {
+    /// Opens a window of `width` by `height` pixels, black until an image is
+    /// shown in it.
+    pub fn open(width: usize, height: usize, title: &str) -> io::Result<Self> {
+        Ok(Self {
+            display: x11::Display::open(width, height, title)?,
+        })
+    }
+
+    /// Shows the image, tone mapped and encoded as it would be written to a
+    /// PNG with the options, but without alpha.
+    pub fn show(&mut self, image: &Image, options: &OutputOptions) -> io::Result<()> {
+        let options = OutputOptions {
+            alpha: Alpha::None,
+            bit_depth: BitDepth::Eight,
+            ..options.clone()
+        };
+        let rgb: Vec<_> = output::samples(image, &options)
+            .iter()
+            .map(|&c| c as u8)
+            .collect();
+        self.display.draw(image.width, image.height, &rgb)
+    }
+
+    /// The next thing done to the window since the last, if any.
+    pub fn poll(&mut self) -> Option<Event> {
+        self.display.poll()
+    }
+}
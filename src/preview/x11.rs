@@ -0,0 +1,647 @@
+//! Just enough of the X11 protocol to show an image in a window and hear of
+//! the keys pressed and the mouse dragged in it, spoken straight to the X server over its socket
+//! so that no library is needed.
+
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use super::{Event, Key};
+
+// Requests
+const CREATE_WINDOW: u8 = 1;
+const MAP_WINDOW: u8 = 8;
+const INTERN_ATOM: u8 = 16;
+const CHANGE_PROPERTY: u8 = 18;
+const CREATE_GC: u8 = 55;
+const PUT_IMAGE: u8 = 72;
+const GET_KEYBOARD_MAPPING: u8 = 101;
+
+// What the server sends back
+const ERROR: u8 = 0;
+const REPLY: u8 = 1;
+const KEY_PRESS: u8 = 2;
+const BUTTON_PRESS: u8 = 4;
+const BUTTON_RELEASE: u8 = 5;
+const MOTION_NOTIFY: u8 = 6;
+const EXPOSE: u8 = 12;
+const CLIENT_MESSAGE: u8 = 33;
+
+// Predefined atoms
+const ATOM: u32 = 4;
+const STRING: u32 = 31;
+const WM_NAME: u32 = 39;
+const WM_NORMAL_HINTS: u32 = 40;
+const WM_SIZE_HINTS: u32 = 41;
+
+const KEY_PRESS_MASK: u32 = 1;
+const BUTTON_PRESS_MASK: u32 = 1 << 2;
+const BUTTON_RELEASE_MASK: u32 = 1 << 3;
+const BUTTON_1_MOTION_MASK: u32 = 1 << 8;
+const EXPOSURE_MASK: u32 = 1 << 15;
+const TRUE_COLOR: u8 = 4;
+const DIRECT_COLOR: u8 = 5;
+
+fn error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+/// The connection to the server, over a Unix socket when it runs on this
+/// machine.
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Stream::Unix(stream) => Stream::Unix(stream.try_clone()?),
+            Stream::Tcp(stream) => Stream::Tcp(stream.try_clone()?),
+        })
+    }
+
+    fn shutdown(&self) {
+        let _ = match self {
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Both),
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(stream) => stream.read(buf),
+            Stream::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(stream) => stream.write(buf),
+            Stream::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Unix(stream) => stream.flush(),
+            Stream::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A request being put together, in the little-endian byte order asked
+/// for when connecting.
+struct Request(Vec<u8>);
+
+impl Request {
+    fn new(opcode: u8, detail: u8) -> Self {
+        Request(vec![opcode, detail, 0, 0])
+    }
+
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    fn u16(mut self, value: u16) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Bytes padded to a whole number of words
+    fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self.pad()
+    }
+
+    fn pad(mut self) -> Self {
+        self.0.resize(self.0.len().next_multiple_of(4), 0);
+        self
+    }
+
+    /// The request with its length in words filled in.
+    fn finish(mut self) -> Vec<u8> {
+        let words = self.0.len() / 4;
+        self.0[2..4].copy_from_slice(&(words as u16).to_le_bytes());
+        self.0
+    }
+}
+
+/// Reads the fields of what the server sent, failing rather than
+/// panicking if there are fewer than there should be.
+struct Fields<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + n)
+            .ok_or_else(|| error("X server sent too little"))?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn skip(&mut self, n: usize) -> io::Result<()> {
+        self.take(n).map(|_| ())
+    }
+}
+
+/// Where the server of `$DISPLAY` listens, and the number of its display.
+fn address() -> io::Result<(Stream, String)> {
+    let display = env::var("DISPLAY").map_err(|_| error("no display, `$DISPLAY` isn't set"))?;
+    let (host, number) = display
+        .rsplit_once(':')
+        .ok_or_else(|| error(format!("can't make out the display `{}`", display)))?;
+    // The screen after the display number is left to the server
+    let number = number.split('.').next().unwrap();
+    let port = number
+        .parse::<u16>()
+        .ok()
+        .and_then(|n| 6000u16.checked_add(n))
+        .ok_or_else(|| error(format!("can't make out the display `{}`", display)))?;
+    let stream = match host {
+        "" | "unix" => UnixStream::connect(format!("/tmp/.X11-unix/X{}", number)).map(Stream::Unix),
+        host => TcpStream::connect((host, port)).map(Stream::Tcp),
+    }
+    .map_err(|e| error(format!("can't connect to the display `{}`: {}", display, e)))?;
+    Ok((stream, number.to_string()))
+}
+
+/// The name and data of the authorization for the display, from the
+/// `.Xauthority` file, if there is one.
+fn authorization(display: &str) -> (Vec<u8>, Vec<u8>) {
+    let path = env::var_os("XAUTHORITY")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".Xauthority")));
+    let data = path
+        .and_then(|path| fs::read(path).ok())
+        .unwrap_or_default();
+
+    // Entries of a family and four counted strings: the address, the
+    // display number, and the name and data of the cookie
+    let mut rest = &data[..];
+    while rest.len() >= 2 {
+        rest = &rest[2..];
+        let entry = (
+            counted(&mut rest),
+            counted(&mut rest),
+            counted(&mut rest),
+            counted(&mut rest),
+        );
+        let (number, name, cookie) = match entry {
+            (Some(_), Some(number), Some(name), Some(cookie)) => (number, name, cookie),
+            _ => break,
+        };
+        if number == display.as_bytes() && name == b"MIT-MAGIC-COOKIE-1" {
+            return (name.to_vec(), cookie.to_vec());
+        }
+    }
+    (vec![], vec![])
+}
+
+/// The string at the start of `data`, after its big-endian length, moving
+/// `data` past it.
+fn counted<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = u16::from_be_bytes(data.get(..2)?.try_into().unwrap()) as usize;
+    let value = data.get(2..2 + length)?;
+    *data = &data[2 + length..];
+    Some(value)
+}
+
+/// Reads the reply to the last request, passing over any events before it.
+fn reply(stream: &mut Stream) -> io::Result<Vec<u8>> {
+    loop {
+        let mut packet = vec![0; 32];
+        stream.read_exact(&mut packet)?;
+        match packet[0] {
+            ERROR => return Err(error(format!("X server error {}", packet[1]))),
+            REPLY => {
+                let extra = u32::from_le_bytes(packet[4..8].try_into().unwrap()) as usize * 4;
+                packet.resize(32 + extra, 0);
+                stream.read_exact(&mut packet[32..])?;
+                return Ok(packet);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How the pixels of the window are laid out in the images sent to it.
+struct PixelFormat {
+    red: u32,
+    green: u32,
+    blue: u32,
+    depth: u8,
+    big_endian: bool,
+}
+
+impl PixelFormat {
+    fn pack(&self, rgb: &[u8], packed: &mut Vec<u8>) {
+        packed.clear();
+        for p in rgb.chunks(3) {
+            let pixel =
+                channel(p[0], self.red) | channel(p[1], self.green) | channel(p[2], self.blue);
+            packed.extend_from_slice(&if self.big_endian {
+                pixel.to_be_bytes()
+            } else {
+                pixel.to_le_bytes()
+            });
+        }
+    }
+}
+
+/// An 8-bit channel fitted into the bits of `mask`.
+fn channel(c: u8, mask: u32) -> u32 {
+    let bits = mask.count_ones();
+    let c = if bits < 8 {
+        u32::from(c) >> (8 - bits)
+    } else {
+        u32::from(c) << (bits - 8)
+    };
+    (c << mask.trailing_zeros()) & mask
+}
+
+/// What the thread reading from the server passes on.
+enum Message {
+    /// Part of the window was uncovered and needs drawing again
+    Expose,
+    Event(Event),
+}
+
+/// The keys of the keycodes from `first` on, from the keyboard mapping of
+/// the server, going by the symbol of each without modifiers.
+struct Keyboard {
+    first: u8,
+    keys: Vec<Option<Key>>,
+}
+
+impl Keyboard {
+    fn key(&self, keycode: u8) -> Option<Key> {
+        let index = keycode.checked_sub(self.first)?;
+        self.keys.get(index as usize).copied().flatten()
+    }
+}
+
+fn key(keysym: u32) -> Option<Key> {
+    match keysym {
+        0xff1b => Some(Key::Escape),
+        0xff51 => Some(Key::Left),
+        0xff52 => Some(Key::Up),
+        0xff53 => Some(Key::Right),
+        0xff54 => Some(Key::Down),
+        // The printable keysyms of Latin-1 are its characters
+        0x20..=0x7e => char::from_u32(keysym).map(|c| Key::Char(c.to_ascii_lowercase())),
+        _ => None,
+    }
+}
+
+/// Passes the events of the window on to `sender` until the connection
+/// closes, and then tells it so.
+fn read_events(mut stream: Stream, keyboard: Keyboard, close: (u32, u32), sender: Sender<Message>) {
+    let mut packet = [0; 32];
+    // Where the pointer was last seen while the left button is held
+    let mut dragging: Option<(i32, i32)> = None;
+    while stream.read_exact(&mut packet).is_ok() {
+        let field =
+            |offset: usize| u32::from_le_bytes(packet[offset..offset + 4].try_into().unwrap());
+        let pointer = (
+            i16::from_le_bytes([packet[24], packet[25]]) as i32,
+            i16::from_le_bytes([packet[26], packet[27]]) as i32,
+        );
+        let message = match packet[0] & 0x7f {
+            REPLY => {
+                let extra = field(4) as u64 * 4;
+                if io::copy(&mut (&mut stream).take(extra), &mut io::sink()).is_err() {
+                    break;
+                }
+                continue;
+            }
+            // Only the last of a run of exposures, to draw once
+            EXPOSE if packet[16..18] == [0, 0] => Message::Expose,
+            KEY_PRESS => match keyboard.key(packet[1]) {
+                Some(key) => Message::Event(Event::Key(key)),
+                None => continue,
+            },
+            BUTTON_PRESS => match packet[1] {
+                1 => {
+                    dragging = Some(pointer);
+                    continue;
+                }
+                4 => Message::Event(Event::Scroll(1)),
+                5 => Message::Event(Event::Scroll(-1)),
+                _ => continue,
+            },
+            BUTTON_RELEASE if packet[1] == 1 => {
+                dragging = None;
+                continue;
+            }
+            MOTION_NOTIFY => match dragging.replace(pointer) {
+                Some(last) => Message::Event(Event::Drag(pointer.0 - last.0, pointer.1 - last.1)),
+                None => continue,
+            },
+            CLIENT_MESSAGE if (field(8), field(12)) == close => Message::Event(Event::Closed),
+            _ => continue,
+        };
+        if sender.send(message).is_err() {
+            return;
+        }
+    }
+    let _ = sender.send(Message::Event(Event::Closed));
+}
+
+/// A window on the X server of `$DISPLAY`.
+pub(super) struct Display {
+    stream: Stream,
+    window: u32,
+    gc: u32,
+    format: PixelFormat,
+    /// The most rows of the image sent in one request
+    max_rows: usize,
+    messages: Receiver<Message>,
+    /// The image last drawn, packed for the server, to draw again when the
+    /// window is uncovered
+    width: usize,
+    height: usize,
+    frame: Vec<u8>,
+}
+
+impl Display {
+    pub(super) fn open(width: usize, height: usize, title: &str) -> io::Result<Self> {
+        let (mut stream, display) = address()?;
+        let (name, data) = authorization(&display);
+        // The byte order, the version of the protocol and the authorization,
+        // which has no length in words to fill in like a request
+        let setup = Request(vec![b'l', 0])
+            .u16(11)
+            .u16(0)
+            .u16(name.len() as u16)
+            .u16(data.len() as u16)
+            .u16(0)
+            .bytes(&name)
+            .bytes(&data);
+        stream.write_all(&setup.0)?;
+
+        let mut header = [0; 8];
+        stream.read_exact(&mut header)?;
+        let mut info = vec![0; u16::from_le_bytes([header[6], header[7]]) as usize * 4];
+        stream.read_exact(&mut info)?;
+        if header[0] != 1 {
+            let length = if header[0] == 0 {
+                header[1] as usize
+            } else {
+                info.len()
+            };
+            let reason = String::from_utf8_lossy(&info[..length.min(info.len())]);
+            return Err(error(format!(
+                "X server refused the connection: {}",
+                reason.trim_end_matches('\0').trim()
+            )));
+        }
+
+        let mut fields = Fields::new(&info);
+        fields.skip(4)?;
+        let id_base = fields.u32()?;
+        let id_mask = fields.u32()?;
+        fields.skip(4)?;
+        let vendor = fields.u16()? as usize;
+        let max_request = fields.u16()? as usize;
+        let screens = fields.u8()?;
+        let formats = fields.u8()?;
+        let big_endian = fields.u8()? == 1;
+        fields.skip(3)?;
+        let min_keycode = fields.u8()?;
+        let max_keycode = fields.u8()?;
+        fields.skip(4)?;
+        fields.skip(vendor.next_multiple_of(4))?;
+        let mut bits_per_pixel = vec![];
+        for _ in 0..formats {
+            let depth = fields.u8()?;
+            bits_per_pixel.push((depth, fields.u8()?));
+            fields.skip(6)?;
+        }
+        if screens == 0 {
+            return Err(error("X server has no screens"));
+        }
+        // The window goes on the first screen, with the visual of its root
+        let root = fields.u32()?;
+        fields.skip(8)?;
+        let black = fields.u32()?;
+        fields.skip(16)?;
+        let root_visual = fields.u32()?;
+        fields.skip(2)?;
+        let root_depth = fields.u8()?;
+        let depths = fields.u8()?;
+        let mut masks = None;
+        for _ in 0..depths {
+            fields.skip(2)?;
+            let visuals = fields.u16()?;
+            fields.skip(4)?;
+            for _ in 0..visuals {
+                let id = fields.u32()?;
+                let class = fields.u8()?;
+                fields.skip(3)?;
+                let rgb = (fields.u32()?, fields.u32()?, fields.u32()?);
+                fields.skip(4)?;
+                if id == root_visual && (class == TRUE_COLOR || class == DIRECT_COLOR) {
+                    masks = Some(rgb);
+                }
+            }
+        }
+        let (red, green, blue) = masks.ok_or_else(|| error("display isn't true color"))?;
+        if !bits_per_pixel.contains(&(root_depth, 32)) {
+            return Err(error(format!(
+                "display of depth {} isn't 32 bits a pixel",
+                root_depth
+            )));
+        }
+        // Each pixel is a word, after the six words of the request
+        let max_rows = max_request.saturating_sub(6) / width.max(1);
+        if max_rows == 0 {
+            return Err(error("window too wide for the X server"));
+        }
+
+        let atom = |stream: &mut Stream, name: &str| -> io::Result<u32> {
+            let request = Request::new(INTERN_ATOM, 0)
+                .u16(name.len() as u16)
+                .u16(0)
+                .bytes(name.as_bytes());
+            stream.write_all(&request.finish())?;
+            Fields::new(&reply(stream)?[8..]).u32()
+        };
+        let protocols = atom(&mut stream, "WM_PROTOCOLS")?;
+        let delete = atom(&mut stream, "WM_DELETE_WINDOW")?;
+
+        let count = max_keycode - min_keycode + 1;
+        let request = Request::new(GET_KEYBOARD_MAPPING, 0)
+            .u8(min_keycode)
+            .u8(count)
+            .pad();
+        stream.write_all(&request.finish())?;
+        let mapping = reply(&mut stream)?;
+        let per_keycode = (mapping[1] as usize).max(1);
+        let keys = mapping[32..]
+            .chunks_exact(4 * per_keycode)
+            .map(|keysyms| key(u32::from_le_bytes(keysyms[..4].try_into().unwrap())))
+            .collect();
+        let keyboard = Keyboard {
+            first: min_keycode,
+            keys,
+        };
+
+        let id = |n: u32| id_base | ((n << id_mask.trailing_zeros()) & id_mask);
+        let (window, gc) = (id(1), id(2));
+        let (w, h) = (width as u16, height as u16);
+        let mut requests = vec![];
+        requests.push(
+            Request::new(CREATE_WINDOW, root_depth)
+                .u32(window)
+                .u32(root)
+                .u16(0)
+                .u16(0)
+                .u16(w)
+                .u16(h)
+                .u16(0)
+                .u16(1)
+                .u32(root_visual)
+                // The background pixel and the events wanted
+                .u32(0x2 | 0x800)
+                .u32(black)
+                .u32(
+                    KEY_PRESS_MASK
+                        | BUTTON_PRESS_MASK
+                        | BUTTON_RELEASE_MASK
+                        | BUTTON_1_MOTION_MASK
+                        | EXPOSURE_MASK,
+                ),
+        );
+        let property = |property: u32, kind: u32, format: u8, count: usize, data: &[u8]| {
+            Request::new(CHANGE_PROPERTY, 0)
+                .u32(window)
+                .u32(property)
+                .u32(kind)
+                .u8(format)
+                .pad()
+                .u32(count as u32)
+                .bytes(data)
+        };
+        requests.push(property(WM_NAME, STRING, 8, title.len(), title.as_bytes()));
+        requests.push(property(protocols, ATOM, 32, 1, &delete.to_le_bytes()));
+        // Hints keeping the window the size of the image
+        let mut hints = [0u32; 18];
+        hints[0] = 16 | 32;
+        hints[5..9].copy_from_slice(&[w as u32, h as u32, w as u32, h as u32]);
+        let hints: Vec<u8> = hints.iter().flat_map(|word| word.to_le_bytes()).collect();
+        requests.push(property(WM_NORMAL_HINTS, WM_SIZE_HINTS, 32, 18, &hints));
+        requests.push(Request::new(CREATE_GC, 0).u32(gc).u32(window).u32(0));
+        requests.push(Request::new(MAP_WINDOW, 0).u32(window));
+        for request in requests {
+            stream.write_all(&request.finish())?;
+        }
+
+        let (sender, messages) = mpsc::channel();
+        let reader = stream.try_clone()?;
+        thread::spawn(move || read_events(reader, keyboard, (protocols, delete), sender));
+        Ok(Self {
+            stream,
+            window,
+            gc,
+            format: PixelFormat {
+                red,
+                green,
+                blue,
+                depth: root_depth,
+                big_endian,
+            },
+            max_rows,
+            messages,
+            width: 0,
+            height: 0,
+            frame: vec![],
+        })
+    }
+
+    /// Draws an image of 8-bit RGB pixels into the top left of the window.
+    pub(super) fn draw(&mut self, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+        self.format.pack(rgb, &mut self.frame);
+        self.width = width;
+        self.height = height;
+        self.redraw()
+    }
+
+    /// Draws the last image again, in strips as tall as a request allows.
+    fn redraw(&mut self) -> io::Result<()> {
+        let rows = self.frame.chunks(4 * self.width.max(1) * self.max_rows);
+        for (i, strip) in rows.enumerate() {
+            let request = Request::new(PUT_IMAGE, 2)
+                .u32(self.window)
+                .u32(self.gc)
+                .u16(self.width as u16)
+                .u16((strip.len() / 4 / self.width) as u16)
+                .u16(0)
+                .u16((i * self.max_rows) as u16)
+                .u8(0)
+                .u8(self.format.depth)
+                .pad()
+                .bytes(strip);
+            self.stream.write_all(&request.finish())?;
+        }
+        Ok(())
+    }
+
+    /// The next event, if any has happened since the last.
+    pub(super) fn poll(&mut self) -> Option<Event> {
+        loop {
+            match self.messages.try_recv() {
+                Ok(Message::Expose) => {
+                    let _ = self.redraw();
+                }
+                Ok(Message::Event(event)) => return Some(event),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Event::Closed),
+            }
+        }
+    }
+}
+
+impl Drop for Display {
+    /// Closes the connection, which closes the window, and ends the thread
+    /// reading from it.
+    fn drop(&mut self) {
+        self.stream.shutdown();
+    }
+}
@@ -0,0 +1,40 @@
+use crate::vector::{Float, Vec3f};
+
+/// A ray from `origin` along the unit direction `dir`, which only hits what
+/// it meets at least `t_min` and less than `t_max` along the way.
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vec3f,
+    pub dir: Vec3f,
+    pub t_min: Float,
+    pub t_max: Float,
+}
+
+impl Ray {
+    /// A ray hitting everything ahead of its origin.
+    pub fn new(origin: Vec3f, dir: Vec3f) -> Self {
+        Self {
+            origin,
+            dir,
+            t_min: 0.0,
+            t_max: Float::INFINITY,
+        }
+    }
+
+    /// The ray only hitting what it meets within `t_min..t_max`, such as a
+    /// shadow ray stopping short of its light.
+    pub fn with_range(mut self, t_min: Float, t_max: Float) -> Self {
+        self.t_min = t_min;
+        self.t_max = t_max;
+        self
+    }
+
+    pub fn at(&self, t: Float) -> Vec3f {
+        self.origin + (self.dir * t)
+    }
+
+    /// Whether a hit at the distance `t` along the ray counts.
+    pub fn contains(&self, t: Float) -> bool {
+        (self.t_min..self.t_max).contains(&t)
+    }
+}
@@ -0,0 +1,1035 @@
+//! Sharing a render out to workers on other machines. Workers connect to the
+//! coordinator over TCP and are sent the job: the command line of the
+//! render, the scene file and the files it refers to. They are then handed
+//! passes over tiles to render, and send back the samples of each.
+//!
+//! Messages are little-endian: a byte for the kind of message, then its
+//! fields, with counts and lengths as `u32` and floats as `f64`, which tiles
+//! come back in exactly as the worker rendered them. A worker starts with an
+//! 8 byte magic.
+
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::accel::AcceleratorKind;
+use crate::aov::{self, Aov};
+use crate::color::Color;
+use crate::error::{self, Error};
+use crate::files;
+use crate::render::{self, Image, Progress, RenderOptions, RenderState, Tile, TilePass};
+use crate::scene::Scene;
+use crate::vector::{from_f64, to_f64, Float, Vec3f};
+
+const MAGIC: &[u8; 8] = b"TRWORK01";
+/// Longest string or file a message may hold
+const MAX_LENGTH: usize = 1 << 30;
+/// How long threads out of passes to render wait for more, as passes a
+/// worker failed to render are handed out again
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+/// How long a worker may go without answering, well beyond how long a pass
+/// over a tile takes, before it is left out and its passes handed to others
+const WORKER_TIMEOUT: Duration = Duration::from_secs(120);
+
+// The kinds of message
+const HELLO: u8 = 1;
+const JOB: u8 = 2;
+const RENDER: u8 = 3;
+const RENDERED: u8 = 4;
+const FAILED: u8 = 5;
+
+/// What workers are sent to render.
+#[derive(Clone, Debug, Default)]
+pub struct Job {
+    /// The command line of the render
+    pub args: Vec<String>,
+    /// The name of the scene file and its source, unless the tutorial scene
+    /// is rendered
+    pub scene: Option<(String, String)>,
+    /// Files the scene refers to, by their paths relative to it
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Job {
+    /// The job of rendering with the command line `args` and the scene file
+    /// at `scene`, if there is one, along with every file loading the scene
+    /// as of `time` reads: its meshes and textures, and the materials and
+    /// textures of those meshes in turn. Those all have to be in the
+    /// directory of the scene or below it, where the workers have them too.
+    pub fn new(args: Vec<String>, scene: Option<&Path>, time: Float) -> error::Result<Self> {
+        let mut job = Job {
+            args,
+            ..Job::default()
+        };
+        if let Some(path) = scene {
+            let source = fs::read_to_string(path)?;
+            let (loaded, read) =
+                files::recording(|| Scene::load_at_time(path, AcceleratorKind::None, time));
+            loaded?;
+            let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+            for file in read {
+                let name = relative_to(&file, base_dir);
+                let name = name.as_deref().and_then(Path::to_str).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "workers are only sent the files in the directory of the scene, \
+                             not {}",
+                            file.display()
+                        ),
+                    )
+                })?;
+                if !job.files.iter().any(|(sent, _)| sent == name) {
+                    job.files.push((name.to_string(), fs::read(&file)?));
+                }
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            job.scene = Some((name.into_owned(), source));
+        }
+        Ok(job)
+    }
+}
+
+/// All a worker needs to render passes over the tiles of a frame.
+pub struct Frame {
+    pub scene: Scene,
+    pub options: RenderOptions,
+    pub image_size: (usize, usize),
+    pub aovs: Vec<Aov>,
+}
+
+enum Message {
+    /// From a worker, on connecting: how many passes it renders at once
+    Hello {
+        threads: usize,
+    },
+    Job(Job),
+    /// A pass over a tile of the frame at a time and turn of the turntable
+    Render {
+        moment: (Float, Float),
+        pass: TilePass,
+    },
+    Rendered {
+        pass: TilePass,
+        samples: Image,
+    },
+    /// From a worker that can't render the job, with the reason
+    Failed(String),
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::parse("worker message", message)
+}
+
+/// A message being put together, to be sent in one write.
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: usize) -> error::Result<()> {
+        let value = u32::try_from(value).map_err(|_| invalid("value out of range"))?;
+        self.0.extend_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn float(&mut self, value: Float) {
+        self.0.extend_from_slice(&to_f64(value).to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> error::Result<()> {
+        self.u32(bytes.len())?;
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn pass(&mut self, pass: &TilePass) -> error::Result<()> {
+        let tile = &pass.tile;
+        for value in [
+            pass.index,
+            pass.pass,
+            tile.x,
+            tile.y,
+            tile.width,
+            tile.height,
+        ] {
+            self.u32(value)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_u8<R: Read>(r: &mut R) -> error::Result<u8> {
+    let mut bytes = [0; 1];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32<R: Read>(r: &mut R) -> error::Result<usize> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn read_float<R: Read>(r: &mut R) -> error::Result<Float> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(from_f64(f64::from_le_bytes(bytes)))
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> error::Result<Vec<u8>> {
+    let length = read_u32(r)?;
+    if length > MAX_LENGTH {
+        return Err(invalid("string too long"));
+    }
+    let mut bytes = vec![];
+    r.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(bytes)
+}
+
+fn read_string<R: Read>(r: &mut R) -> error::Result<String> {
+    String::from_utf8(read_bytes(r)?).map_err(|_| invalid("string isn't UTF-8"))
+}
+
+fn read_pass<R: Read>(r: &mut R) -> error::Result<TilePass> {
+    Ok(TilePass {
+        index: read_u32(r)?,
+        pass: read_u32(r)?,
+        tile: Tile {
+            x: read_u32(r)?,
+            y: read_u32(r)?,
+            width: read_u32(r)?,
+            height: read_u32(r)?,
+        },
+    })
+}
+
+impl Message {
+    fn write<W: Write>(&self, w: &mut W) -> error::Result<()> {
+        let mut e = Encoder::default();
+        match self {
+            Message::Hello { threads } => {
+                e.u8(HELLO);
+                e.u32(*threads)?;
+            }
+            Message::Job(job) => {
+                e.u8(JOB);
+                e.u32(job.args.len())?;
+                for arg in &job.args {
+                    e.bytes(arg.as_bytes())?;
+                }
+                match &job.scene {
+                    Some((name, source)) => {
+                        e.u8(1);
+                        e.bytes(name.as_bytes())?;
+                        e.bytes(source.as_bytes())?;
+                    }
+                    None => e.u8(0),
+                }
+                e.u32(job.files.len())?;
+                for (path, data) in &job.files {
+                    e.bytes(path.as_bytes())?;
+                    e.bytes(data)?;
+                }
+            }
+            Message::Render { moment, pass } => {
+                e.u8(RENDER);
+                e.float(moment.0);
+                e.float(moment.1);
+                e.pass(pass)?;
+            }
+            Message::Rendered { pass, samples } => {
+                e.u8(RENDERED);
+                e.pass(pass)?;
+                for (pixel, &alpha) in samples.pixels.iter().zip(&samples.alpha) {
+                    for c in [pixel.r, pixel.g, pixel.b, alpha] {
+                        e.float(c);
+                    }
+                }
+                e.u32(samples.aovs.len())?;
+                for (kind, values) in &samples.aovs {
+                    // Passes of light groups come one past the others, with
+                    // the name of the group
+                    match kind {
+                        Aov::Lights(group) => {
+                            e.u32(aov::ALL.len())?;
+                            e.bytes(group.as_bytes())?;
+                        }
+                        _ => e.u32(aov::ALL.iter().position(|a| a == kind).unwrap())?,
+                    }
+                    for value in values {
+                        for c in *value {
+                            e.float(c);
+                        }
+                    }
+                }
+            }
+            Message::Failed(reason) => {
+                e.u8(FAILED);
+                e.bytes(reason.as_bytes())?;
+            }
+        }
+        Ok(w.write_all(&e.0)?)
+    }
+
+    /// Reads a message, which may only be a rendered pass if it is one of
+    /// `rendering`, the passes handed out to whoever sent it, so that none
+    /// can have room made for samples that weren't asked for.
+    fn read<R: Read>(r: &mut R, rendering: &[TilePass]) -> error::Result<Self> {
+        Ok(match read_u8(r)? {
+            HELLO => Message::Hello {
+                threads: read_u32(r)?,
+            },
+            JOB => {
+                let args = (0..read_u32(r)?)
+                    .map(|_| read_string(r))
+                    .collect::<error::Result<_>>()?;
+                let scene = match read_u8(r)? {
+                    0 => None,
+                    _ => Some((read_string(r)?, read_string(r)?)),
+                };
+                let files = (0..read_u32(r)?)
+                    .map(|_| Ok((read_string(r)?, read_bytes(r)?)))
+                    .collect::<error::Result<_>>()?;
+                Message::Job(Job { args, scene, files })
+            }
+            RENDER => Message::Render {
+                moment: (read_float(r)?, read_float(r)?),
+                pass: read_pass(r)?,
+            },
+            RENDERED => {
+                let pass = read_pass(r)?;
+                if !rendering.contains(&pass) {
+                    return Err(invalid("rendered a pass it wasn't given"));
+                }
+                let (width, height) = (pass.tile.width, pass.tile.height);
+                let mut samples = Image::new(width, height);
+                for (pixel, alpha) in samples.pixels.iter_mut().zip(&mut samples.alpha) {
+                    *pixel = Color::new(read_float(r)?, read_float(r)?, read_float(r)?);
+                    *alpha = read_float(r)?;
+                }
+                for _ in 0..read_u32(r)? {
+                    let kind = match read_u32(r)? {
+                        i if i == aov::ALL.len() => Aov::lights(&read_string(r)?),
+                        i => *aov::ALL
+                            .get(i)
+                            .ok_or_else(|| invalid("unknown output variable"))?,
+                    };
+                    let values = (0..width * height)
+                        .map(|_| Ok(Vec3f::new(read_float(r)?, read_float(r)?, read_float(r)?)))
+                        .collect::<error::Result<_>>()?;
+                    samples.aovs.push((kind, values));
+                }
+                Message::Rendered { pass, samples }
+            }
+            FAILED => Message::Failed(read_string(r)?),
+            kind => return Err(invalid(format!("unknown kind of message {}", kind))),
+        })
+    }
+}
+
+/// A connected worker, sent the job and ready to render.
+struct Worker {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    threads: usize,
+}
+
+/// Greets a worker that has just connected and sends it the job.
+fn greet(stream: TcpStream, job: &Job) -> error::Result<Worker> {
+    stream.set_read_timeout(Some(WORKER_TIMEOUT))?;
+    stream.set_write_timeout(Some(WORKER_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a worker"));
+    }
+    let threads = match Message::read(&mut reader, &[])? {
+        Message::Hello { threads } => threads.max(1),
+        _ => return Err(invalid("expected a greeting")),
+    };
+    let mut writer = stream;
+    writer.set_nodelay(true)?;
+    Message::Job(job.clone()).write(&mut writer)?;
+    Ok(Worker {
+        reader,
+        writer,
+        threads,
+    })
+}
+
+/// Hands out the passes over the tiles of renders to the threads of this
+/// machine and to the workers connected to it.
+pub struct Coordinator {
+    /// Workers waiting for passes to render
+    idle: Arc<Mutex<Vec<Worker>>>,
+    /// How long a worker rendering passes may go without answering
+    timeout: Duration,
+}
+
+impl Coordinator {
+    /// Listens for workers at `address`, sending each the job as it
+    /// connects.
+    pub fn listen(address: impl ToSocketAddrs, job: Job) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let idle = Arc::new(Mutex::new(vec![]));
+        let (waiting, job) = (Arc::clone(&idle), Arc::new(job));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // Greeted on their own, so that one slow to answer holds up
+                // no others
+                let (waiting, job) = (Arc::clone(&waiting), Arc::clone(&job));
+                thread::spawn(move || {
+                    if let Ok(worker) = greet(stream, &job) {
+                        waiting.lock().unwrap().push(worker);
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            idle,
+            timeout: WORKER_TIMEOUT,
+        })
+    }
+
+    /// Renders like `render::resume`, sharing the passes out between the
+    /// threads of the options and the workers, including any that connect
+    /// meanwhile. The workers render the frame at `moment`, the time and
+    /// the turn of the turntable. A worker that fails, by disconnecting,
+    /// going without answering for two minutes or otherwise, is left out
+    /// from then on and its passes rendered by the others.
+    pub fn resume<F: FnMut(&Progress, &RenderState)>(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        mut state: RenderState,
+        moment: (Float, Float),
+        mut progress: F,
+    ) -> RenderState {
+        // Adaptive passes come in rounds, as in `render::resume`
+        loop {
+            let work = state.remaining(options);
+            if work.0.is_empty() || options.cancel.load(Ordering::Relaxed) {
+                return state;
+            }
+            state = self.render_passes(scene, options, (state, work), moment, &mut progress);
+        }
+    }
+
+    /// Renders the passes over tiles into the state, out of `total` passes
+    /// of the whole render.
+    fn render_passes<F: FnMut(&Progress, &RenderState)>(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        (mut state, (work, total)): (RenderState, (Vec<TilePass>, usize)),
+        moment: (Float, Float),
+        progress: &mut F,
+    ) -> RenderState {
+        let image_size = (state.image.width, state.image.height);
+        let aovs: Vec<_> = state.image.aovs.iter().map(|&(aov, _)| aov).collect();
+        let (count, previous) = (work.len(), total - work.len());
+        let queue = Mutex::new(VecDeque::from(work));
+        let done = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|s| {
+            let mut threads = vec![];
+            for _ in 0..options.threads.max(1) {
+                let sender = sender.clone();
+                let (queue, done, aovs) = (&queue, &done, &aovs);
+                threads.push(s.spawn(move || loop {
+                    if done.load(Ordering::SeqCst) || options.cancel.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let next = queue.lock().unwrap().pop_front();
+                    match next {
+                        Some(pass) => {
+                            let samples =
+                                render::render_pass(scene, options, image_size, &pass, aovs);
+                            if sender.send((pass, samples)).is_err() {
+                                break;
+                            }
+                        }
+                        None => thread::sleep(IDLE_WAIT),
+                    }
+                }));
+            }
+
+            let mut completed = 0;
+            while completed < count {
+                // Workers that connected since the last look join in
+                for worker in self.idle.lock().unwrap().drain(..) {
+                    // One that stalls is left out, like one that fails
+                    let stream = &worker.writer;
+                    let timeout = Some(self.timeout);
+                    if stream.set_read_timeout(timeout).is_err()
+                        || stream.set_write_timeout(timeout).is_err()
+                    {
+                        continue;
+                    }
+                    let sender = sender.clone();
+                    let (queue, done, aovs) = (&queue, &done, &aovs);
+                    let (idle, cancel) = (&self.idle, &options.cancel);
+                    threads.push(s.spawn(move || {
+                        let worker = hand_out(worker, moment, aovs, queue, done, cancel, sender);
+                        if let Some(worker) = worker {
+                            idle.lock().unwrap().push(worker);
+                        }
+                    }));
+                }
+                match receiver.recv_timeout(IDLE_WAIT * 10) {
+                    Ok((pass, samples)) => {
+                        state.add(&pass, &samples);
+                        completed += 1;
+                        progress(
+                            &Progress {
+                                tile: pass.tile,
+                                pass: pass.pass,
+                                completed: previous + completed,
+                                total,
+                            },
+                            &state,
+                        );
+                    }
+                    // Once cancelled, the passes being rendered are waited
+                    // for, and no more
+                    Err(_) if options.cancel.load(Ordering::SeqCst) => {
+                        if threads.iter().all(|thread| thread.is_finished()) {
+                            // Passes may have been sent after the last look
+                            while let Ok((pass, samples)) = receiver.try_recv() {
+                                state.add(&pass, &samples);
+                            }
+                            break;
+                        }
+                    }
+                    Err(_) => {}
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+        state
+    }
+}
+
+/// Keeps the worker rendering as many passes at a time as it has threads
+/// until the queue runs out and the render is done, giving it back. If it
+/// fails, the passes it was rendering go back on the queue.
+fn hand_out(
+    mut worker: Worker,
+    moment: (Float, Float),
+    aovs: &[Aov],
+    queue: &Mutex<VecDeque<TilePass>>,
+    done: &AtomicBool,
+    cancel: &AtomicBool,
+    sender: mpsc::Sender<(TilePass, Image)>,
+) -> Option<Worker> {
+    let mut rendering: Vec<TilePass> = vec![];
+    let result = (|| -> error::Result<()> {
+        loop {
+            while rendering.len() < worker.threads && !cancel.load(Ordering::Relaxed) {
+                let next = queue.lock().unwrap().pop_front();
+                let pass = match next {
+                    Some(pass) => pass,
+                    None => break,
+                };
+                rendering.push(pass);
+                Message::Render { moment, pass }.write(&mut worker.writer)?;
+            }
+            if rendering.is_empty() {
+                if done.load(Ordering::SeqCst) || cancel.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                thread::sleep(IDLE_WAIT);
+                continue;
+            }
+            match Message::read(&mut worker.reader, &rendering)? {
+                Message::Rendered { pass, samples } => {
+                    let i = rendering
+                        .iter()
+                        .position(|p| *p == pass)
+                        .ok_or_else(|| invalid("rendered a pass it wasn't given"))?;
+                    let kinds = samples.aovs.iter().map(|&(aov, _)| aov);
+                    if !kinds.eq(aovs.iter().copied()) {
+                        return Err(invalid("rendered the wrong output variables"));
+                    }
+                    rendering.swap_remove(i);
+                    if sender.send((pass, samples)).is_err() {
+                        return Ok(());
+                    }
+                }
+                Message::Failed(reason) => return Err(invalid(reason)),
+                _ => return Err(invalid("expected a rendered pass")),
+            }
+        }
+    })();
+    match result {
+        Ok(()) => Some(worker),
+        Err(_) => {
+            queue.lock().unwrap().extend(rendering);
+            None
+        }
+    }
+}
+
+/// Whether a path from a job stays inside the directory it is written to.
+fn is_relative(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// `path` relative to `dir`, with its `.` and `..` taken out, if it is in
+/// `dir` or below it.
+fn relative_to(path: &Path, dir: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.strip_prefix(dir).ok()?.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            Component::ParentDir if relative.pop() => {}
+            _ => return None,
+        }
+    }
+    Some(relative)
+}
+
+/// Makes a new directory for the files of a job in the temporary one,
+/// under a name no other can know beforehand. One that is there already,
+/// or a link by that name, is never used.
+fn job_dir() -> io::Result<PathBuf> {
+    let mut attempts = 0;
+    loop {
+        let random = RandomState::new().build_hasher().finish();
+        let dir = env::temp_dir().join(format!(
+            "tiny-raytracer-worker-{}-{:016x}",
+            process::id(),
+            random
+        ));
+        match fs::create_dir(&dir) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 16 => attempts += 1,
+            result => return result.map(|_| dir),
+        }
+    }
+}
+
+/// Writes the files of the job to `dir`, returning where the scene is.
+fn unpack(job: &Job, dir: &Path) -> error::Result<Option<PathBuf>> {
+    for (path, data) in &job.files {
+        let path = Path::new(path);
+        if !is_relative(path) {
+            return Err(invalid(format!(
+                "`{}` isn't a relative path",
+                path.display()
+            )));
+        }
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, data)?;
+    }
+    match &job.scene {
+        Some((name, source)) => {
+            if !is_relative(Path::new(name)) {
+                return Err(invalid(format!("`{}` isn't a file name", name)));
+            }
+            let path = dir.join(name);
+            fs::write(&path, source)?;
+            Ok(Some(path))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Works for the coordinator at `address`, rendering as many passes at a
+/// time as `threads`, until it is done and disconnects. The files of the
+/// job are written to a directory of their own, and `load` gets the frame
+/// at a moment from the command line of the job and the path of the scene
+/// in it, if there is one.
+pub fn work<F>(address: impl ToSocketAddrs, threads: usize, load: F) -> error::Result<()>
+where
+    F: Fn(&[String], Option<&Path>, (Float, Float)) -> error::Result<Frame> + Sync,
+{
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Mutex::new(stream);
+    {
+        let mut writer = writer.lock().unwrap();
+        writer.write_all(MAGIC)?;
+        Message::Hello { threads }.write(&mut *writer)?;
+    }
+    let job = match Message::read(&mut reader, &[])? {
+        Message::Job(job) => job,
+        _ => return Err(invalid("expected a job")),
+    };
+    let dir = job_dir()?;
+    let result = unpack(&job, &dir).and_then(|scene| {
+        serve(&mut reader, &writer, threads, |moment| {
+            load(&job.args, scene.as_deref(), moment)
+        })
+    });
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Renders the passes the coordinator asks for on a pool of threads, sending
+/// each back as it is done. The frame is loaded again whenever the moment
+/// changes.
+fn serve<F>(
+    reader: &mut BufReader<TcpStream>,
+    writer: &Mutex<TcpStream>,
+    threads: usize,
+    load: F,
+) -> error::Result<()>
+where
+    F: Fn((Float, Float)) -> error::Result<Frame> + Sync,
+{
+    type Loaded = Option<((Float, Float), Arc<Frame>)>;
+    let frame: Mutex<Loaded> = Mutex::new(None);
+    let failure = Mutex::new(None);
+    let (sender, receiver) = mpsc::channel::<((Float, Float), TilePass)>();
+    let receiver = Mutex::new(receiver);
+
+    let result = thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            let (frame, failure, receiver, load) = (&frame, &failure, &receiver, &load);
+            s.spawn(move || loop {
+                let next = receiver.lock().unwrap().recv();
+                let (moment, pass) = match next {
+                    Ok(next) => next,
+                    Err(_) => break,
+                };
+                let current = {
+                    let mut frame = frame.lock().unwrap();
+                    match &*frame {
+                        Some((loaded, current)) if *loaded == moment => Ok(Arc::clone(current)),
+                        _ => load(moment).map(|current| {
+                            let current = Arc::new(current);
+                            *frame = Some((moment, Arc::clone(&current)));
+                            current
+                        }),
+                    }
+                };
+                let message = match &current {
+                    Ok(current) => Message::Rendered {
+                        pass,
+                        samples: render::render_pass(
+                            &current.scene,
+                            &current.options,
+                            current.image_size,
+                            &pass,
+                            &current.aovs,
+                        ),
+                    },
+                    Err(e) => Message::Failed(e.to_string()),
+                };
+                // The coordinator leaves a worker out once it fails, which
+                // ends the connection. Failing to send means it has ended
+                // already, which the reading notices.
+                let _ = message.write(&mut *writer.lock().unwrap());
+                if let Err(e) = current {
+                    failure.lock().unwrap().get_or_insert(e);
+                    break;
+                }
+            });
+        }
+
+        // A coordinator done with the worker disconnects between messages
+        let result = loop {
+            match Message::read(reader, &[]) {
+                Ok(Message::Render { moment, pass }) => {
+                    if sender.send((moment, pass)).is_err() {
+                        break Ok(());
+                    }
+                }
+                Ok(_) => break Err(invalid("expected a pass to render")),
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        drop(sender);
+        result
+    });
+    match failure.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_the_files_the_meshes_read() {
+        let dir = env::temp_dir().join(format!("tiny-raytracer-job-{}", process::id()));
+        fs::create_dir_all(dir.join("models")).unwrap();
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        let obj = "mtllib red.mtl\nusemtl red\nv 0 0 -3\nv 1 0 -3\nv 0 1 -3\nf 1 2 3\n";
+        fs::write(dir.join("models/tri.obj"), obj).unwrap();
+        fs::write(
+            dir.join("models/red.mtl"),
+            "newmtl red\nmap_Kd ../textures/red.ppm\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("textures/red.ppm"),
+            "P3 2 2 255 255 0 0 255 0 0 255 0 0 255 0 0",
+        )
+        .unwrap();
+        let scene = dir.join("scene.json");
+        fs::write(
+            &scene,
+            r#"{"objects": [{"type": "mesh", "file": "models/tri.obj"}]}"#,
+        )
+        .unwrap();
+
+        let job = Job::new(vec![], Some(&scene), 0.0).unwrap();
+        let mut names: Vec<_> = job.files.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            ["models/red.mtl", "models/tri.obj", "textures/red.ppm"]
+        );
+
+        // Workers couldn't find a texture from outside the directory
+        let outside = env::temp_dir().join(format!("tiny-raytracer-job-{}.ppm", process::id()));
+        fs::copy(dir.join("textures/red.ppm"), &outside).unwrap();
+        let mtl = format!("newmtl red\nmap_Kd {}\n", outside.display());
+        fs::write(dir.join("models/red.mtl"), mtl).unwrap();
+        assert!(Job::new(vec![], Some(&scene), 0.0).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&outside).unwrap();
+    }
+
+    /// The message as it is read back once written.
+    fn round_trip(message: Message, rendering: &[TilePass]) -> Message {
+        let mut bytes = vec![];
+        message.write(&mut bytes).unwrap();
+        let mut reader = &bytes[..];
+        let read = Message::read(&mut reader, rendering).unwrap();
+        assert!(reader.is_empty(), "{} bytes left over", reader.len());
+        read
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        assert!(matches!(
+            round_trip(Message::Hello { threads: 3 }, &[]),
+            Message::Hello { threads: 3 }
+        ));
+
+        let job = Job {
+            args: vec!["--spp".to_string(), "4".to_string()],
+            scene: Some(("scene.json".to_string(), "{}".to_string())),
+            files: vec![("models/tri.obj".to_string(), vec![0, 1, 255])],
+        };
+        match round_trip(Message::Job(job.clone()), &[]) {
+            Message::Job(read) => {
+                assert_eq!(read.args, job.args);
+                assert_eq!(read.scene, job.scene);
+                assert_eq!(read.files, job.files);
+            }
+            _ => panic!("expected a job"),
+        }
+
+        let pass = TilePass {
+            index: 3,
+            tile: Tile {
+                x: 4,
+                y: 8,
+                width: 2,
+                height: 1,
+            },
+            pass: 1,
+        };
+        assert!(matches!(
+            round_trip(Message::Render { moment: (0.5, 0.25), pass }, &[]),
+            Message::Render { moment: (t, turn), pass: read } if t == 0.5 && turn == 0.25 && read == pass
+        ));
+
+        let mut samples = Image::with_aovs(2, 1, &[Aov::Depth, Aov::lights("key")]);
+        samples.pixels[1] = Color::new(0.25, 2.0, -1.0);
+        samples.alpha[1] = 0.5;
+        samples.aovs[1].1[0] = Vec3f::new(1.0, 2.0, 3.0);
+        match round_trip(Message::Rendered { pass, samples }, &[pass]) {
+            Message::Rendered {
+                pass: read,
+                samples,
+            } => {
+                assert_eq!(read, pass);
+                assert_eq!(samples.pixels[1], Color::new(0.25, 2.0, -1.0));
+                assert_eq!(samples.alpha, [0.0, 0.5]);
+                let kinds: Vec<_> = samples.aovs.iter().map(|&(kind, _)| kind).collect();
+                assert_eq!(kinds, [Aov::Depth, Aov::lights("key")]);
+                assert_eq!(<[Float; 3]>::from(samples.aovs[1].1[0]), [1.0, 2.0, 3.0]);
+            }
+            _ => panic!("expected a rendered pass"),
+        }
+
+        assert!(matches!(
+            round_trip(Message::Failed("no scene".to_string()), &[]),
+            Message::Failed(reason) if reason == "no scene"
+        ));
+    }
+
+    #[test]
+    fn refuses_malformed_messages() {
+        let refused = |bytes: &[u8]| Message::read(&mut &bytes[..], &[]).is_err();
+        assert!(refused(&[]));
+        assert!(refused(&[99]));
+        // Cut short
+        let mut job = vec![];
+        Message::Job(Job {
+            args: vec!["--spp".to_string()],
+            ..Job::default()
+        })
+        .write(&mut job)
+        .unwrap();
+        assert!(!refused(&job));
+        assert!(refused(&job[..job.len() - 1]));
+        // Too long, and not UTF-8
+        let mut e = Encoder::default();
+        e.u8(FAILED);
+        e.u32(MAX_LENGTH + 1).unwrap();
+        assert!(refused(&e.0));
+        let mut e = Encoder::default();
+        e.u8(FAILED);
+        e.bytes(&[0xff, 0xfe]).unwrap();
+        assert!(refused(&e.0));
+        // An output variable that doesn't exist
+        let pass = TilePass {
+            index: 0,
+            tile: Tile {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            },
+            pass: 0,
+        };
+        let mut e = Encoder::default();
+        e.u8(RENDERED);
+        e.pass(&pass).unwrap();
+        (0..4).for_each(|_| e.float(0.0));
+        e.u32(1).unwrap();
+        e.u32(aov::ALL.len() + 1).unwrap();
+        assert!(Message::read(&mut &e.0[..], &[pass]).is_err());
+    }
+
+    #[test]
+    fn unpacks_jobs_into_directories_of_their_own() {
+        let (a, b) = (job_dir().unwrap(), job_dir().unwrap());
+        assert_ne!(a, b);
+        let job = Job {
+            scene: Some(("scene.json".to_string(), "{}".to_string())),
+            files: vec![("models/tri.obj".to_string(), b"v 0 0 0".to_vec())],
+            ..Job::default()
+        };
+        assert_eq!(unpack(&job, &a).unwrap(), Some(a.join("scene.json")));
+        assert_eq!(fs::read(a.join("models/tri.obj")).unwrap(), b"v 0 0 0");
+        // Files outside the directory are refused
+        for name in &["../escaped.obj", "/tmp/escaped.obj"] {
+            let job = Job {
+                files: vec![(name.to_string(), vec![])],
+                ..Job::default()
+            };
+            assert!(unpack(&job, &b).is_err());
+        }
+        fs::remove_dir_all(&a).unwrap();
+        fs::remove_dir_all(&b).unwrap();
+    }
+
+    #[test]
+    fn hands_the_passes_of_a_stalled_worker_to_others() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (handed_out, passes) = mpsc::channel();
+        // Takes passes to render and never answers, until disconnected
+        let stalled = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(MAGIC).unwrap();
+            Message::Hello { threads: 1 }.write(&mut writer).unwrap();
+            while let Ok(message) = Message::read(&mut reader, &[]) {
+                if let Message::Render { pass, .. } = message {
+                    handed_out.send(pass).unwrap();
+                }
+            }
+        });
+        let worker = greet(listener.accept().unwrap().0, &Job::default()).unwrap();
+        let coordinator = Coordinator {
+            idle: Arc::new(Mutex::new(vec![worker])),
+            timeout: Duration::from_millis(200),
+        };
+
+        let scene = Scene::parse("{}", Path::new(".")).unwrap();
+        let options = RenderOptions {
+            threads: 1,
+            ..RenderOptions::default()
+        };
+        let state = RenderState::new(64, 64, 4);
+        let state = coordinator.resume(&scene, &options, state, (0.0, 0.0), |_, _| {});
+        assert!(state.tile_passes.iter().all(|&done| done == 1));
+        assert!(passes.try_recv().is_ok(), "the worker was handed no pass");
+        assert!(coordinator.idle.lock().unwrap().is_empty());
+        stalled.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_samples_of_passes_not_handed_out() {
+        let tile = |size| Tile {
+            x: 0,
+            y: 0,
+            width: size,
+            height: size,
+        };
+        let given = TilePass {
+            index: 0,
+            tile: tile(2),
+            pass: 0,
+        };
+        // A header claiming a huge tile, with no samples after it
+        let mut e = Encoder::default();
+        e.u8(RENDERED);
+        e.pass(&TilePass {
+            tile: tile(1 << 12),
+            ..given
+        })
+        .unwrap();
+        let error = Message::read(&mut &e.0[..], &[given]).err().unwrap();
+        assert!(error.to_string().contains("wasn't given"), "{}", error);
+
+        let mut bytes = vec![];
+        Message::Rendered {
+            pass: given,
+            samples: Image::new(2, 2),
+        }
+        .write(&mut bytes)
+        .unwrap();
+        assert!(Message::read(&mut &bytes[..], &[]).is_err());
+        assert!(matches!(
+            Message::read(&mut &bytes[..], &[given]),
+            Ok(Message::Rendered { pass, .. }) if pass == given
+        ));
+    }
+}
@@ -0,0 +1,877 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::aov::Aov;
+use crate::color::Color;
+use crate::integrator::{self, AmbientOcclusion, Bias, DebugView, Integrator};
+use crate::light;
+use crate::material::{Material, Subsurface};
+use crate::object::{moving, HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::{self, PixelSample, Sampler, SamplerKind};
+use crate::scene::Scene;
+use crate::stats;
+use crate::vector::{reflect, Float, Vec3f};
+
+/// Whether something blocks the way from `point` to `target`.
+pub(crate) fn occluded(scene: &Scene, point: &Vec3f, target: &Vec3f) -> bool {
+    let to_target = *target - *point;
+    occluded_along(scene, point, &to_target.normalize(), to_target.len())
+}
+
+/// Whether something is in the way within `distance` of `point` along the
+/// unit direction `dir`.
+pub(crate) fn occluded_along(scene: &Scene, point: &Vec3f, dir: &Vec3f, distance: Float) -> bool {
+    let ray = Ray::new(*point, *dir).with_range(0.0, distance);
+    stats::count(|stats| stats.shadow_rays += 1);
+    scene.hit(&ray).is_some()
+}
+
+/// The light of intensity one along the unit direction `light_dir` that
+/// scatters through the object from its far side to `hit`, the near side,
+/// facing `n` away from the light: dimmed by the thickness of the object
+/// along the way, and left out with `shadows` if something else is in the
+/// way of the light first. Rays leave the surfaces `lift` off them.
+fn subsurface_light(
+    scene: &Scene,
+    subsurface: &Subsurface,
+    n: &Vec3f,
+    (hit, lift): (&Vec3f, Float),
+    light_dir: &Vec3f,
+    distance: Float,
+    shadows: bool,
+) -> Vec3f {
+    let black = Vec3f::new(0.0, 0.0, 0.0);
+    let inside = *hit - *n * (2.0 * lift);
+    stats::count(|stats| stats.secondary_rays += 1);
+    let (far_n, far) = match scene.hit(&Ray::new(inside, *light_dir)) {
+        Some(hit) => (hit.normal, hit.point),
+        None => return black,
+    };
+    let thickness = far.distance(&inside);
+    if thickness >= distance
+        || (shadows
+            && occluded_along(
+                scene,
+                &(far + *light_dir * lift),
+                light_dir,
+                distance - thickness,
+            ))
+    {
+        return black;
+    }
+    let transmitted = Float::powf(0.5, thickness / subsurface.radius.max(1e-6));
+    subsurface.color * (transmitted * far_n.dot(light_dir).abs())
+}
+
+/// The light reaching the eye along `dir` from a point of a surface of the
+/// given material, with normal `n`, straight from the lights. With `shadows`, a sample and the
+/// dimension to pick points on the lights in, lights hidden by other
+/// objects are left out, for which the point should be lifted off the
+/// surface a little, by `lift`; without, lights are taken at their middle. The diffuse
+/// light is scaled by `occlusion`, how much of the surroundings of the
+/// point are open. Subsurface scattering adds the light shining through the
+/// object from lights behind it.
+pub(crate) fn direct_light(
+    scene: &Scene,
+    material: &Material,
+    n: &Vec3f,
+    (hit, lift): (&Vec3f, Float),
+    dir: &Vec3f,
+    shadows: Option<(&PixelSample, usize)>,
+    occlusion: Float,
+) -> Vec3f {
+    let (diffuse, specular) =
+        direct_light_lobes(scene, material, n, (hit, lift), dir, shadows, occlusion);
+    diffuse + specular
+}
+
+/// The light `direct_light` finds, split into the light scattered diffusely
+/// or through the object and the light reflected off the highlights.
+pub(crate) fn direct_light_lobes(
+    scene: &Scene,
+    material: &Material,
+    n: &Vec3f,
+    (hit, lift): (&Vec3f, Float),
+    dir: &Vec3f,
+    shadows: Option<(&PixelSample, usize)>,
+    occlusion: Float,
+) -> (Vec3f, Vec3f) {
+    let mut diffuse_light_intensity = Vec3f::new(0.0, 0.0, 0.0);
+    let mut specular_light_intensity = Vec3f::new(0.0, 0.0, 0.0);
+    let mut subsurface_light_intensity = Vec3f::new(0.0, 0.0, 0.0);
+    for (i, light) in scene.lights.iter().enumerate() {
+        if !light::shines(light.group()) {
+            continue;
+        }
+        let uv = match shadows {
+            Some((sample, dimension)) => sample.split(scene.lights.len(), i).get_2d(dimension),
+            None => (0.5, 0.5),
+        };
+        let incident = light.incident(hit, uv);
+        let light_dir = incident.direction;
+        if let Some(subsurface) = &material.subsurface {
+            if light_dir.dot(n) < 0.0 {
+                subsurface_light_intensity += incident.intensity
+                    * subsurface_light(
+                        scene,
+                        subsurface,
+                        n,
+                        (hit, lift),
+                        &light_dir,
+                        incident.distance,
+                        shadows.is_some(),
+                    );
+                continue;
+            }
+        }
+        if shadows.is_some() && occluded_along(scene, hit, &light_dir, incident.distance) {
+            continue;
+        }
+
+        if let Some(pbr) = &material.metallic_roughness {
+            let (diffuse, specular) = pbr.reflect(&material.diffuse_color, n, &light_dir, &-*dir);
+            diffuse_light_intensity += incident.intensity * diffuse;
+            specular_light_intensity += incident.intensity * specular;
+            continue;
+        }
+        diffuse_light_intensity += incident.intensity * Float::max(0.0, light_dir.dot(n));
+        specular_light_intensity += incident.intensity
+            * Float::max(0.0, (-reflect(&-light_dir, n)).dot(dir)).powf(material.specular_exponent);
+    }
+
+    let (diffuse, specular) = if material.metallic_roughness.is_some() {
+        (
+            diffuse_light_intensity * occlusion,
+            specular_light_intensity,
+        )
+    } else {
+        // return material.diffuse_color * diffuse_light_intensity * material.albedo[0] +
+        // Vec3f(1., 1., 1.)*specular_light_intensity * material.albedo[1];
+        (
+            (material.diffuse_color * (diffuse_light_intensity * occlusion)) * material.albedo.0,
+            specular_light_intensity * material.albedo.1,
+        )
+    };
+    (diffuse + subsurface_light_intensity, specular)
+}
+
+pub const WIDTH: usize = 1024;
+pub const HEIGHT: usize = 768;
+/// Width and height of the square packets primary rays are traced in
+const PACKET_SIZE: usize = 2;
+
+#[derive(Clone)]
+pub struct RenderOptions {
+    /// Number of threads rendering tiles of the image
+    pub threads: usize,
+    /// Width and height of the square tiles the image is rendered in
+    pub tile_size: usize,
+    /// Only the pixels within this rectangle are rendered, the others are
+    /// left black
+    pub crop: Option<Tile>,
+    /// Number of passes over the image
+    pub passes: usize,
+    /// Samples every pass takes per pixel, which should stay the same when a
+    /// render is resumed
+    pub samples_per_pixel: usize,
+    /// Keeps sampling the pixels that are still noisy after
+    /// `samples_per_pixel` samples
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Keeps rendering passes over the tiles that are still noisy after
+    /// `passes` passes
+    pub adaptive_passes: Option<AdaptivePasses>,
+    /// Places the samples within each pixel
+    pub sampler: SamplerKind,
+    /// Picks a different set of samples, to get the same render with
+    /// different noise
+    pub seed: u32,
+    pub integrator: Integrator,
+    /// Most surfaces a path bounces off, with the path integrator, or rays
+    /// go on through glass, with the direct one
+    pub max_depth: usize,
+    /// Bounces after which paths may be ended at random, dimmer ones sooner
+    pub roulette_depth: usize,
+    /// Brightest any channel of a path traced sample may be, dimming the
+    /// rare very bright ones that would otherwise stand out as single
+    /// pixels, at the cost of a little of the light
+    pub clamp: Option<Float>,
+    /// How occlusion is estimated, for the ambient occlusion integrator and
+    /// for darkening the diffuse light of the direct one
+    pub ambient_occlusion: AmbientOcclusion,
+    /// How far rays leave off the surfaces they start from
+    pub bias: Bias,
+    /// Shows a view of the scene for debugging instead of its light
+    pub debug: Option<DebugView>,
+    /// Lets rays that miss everything add no color and no alpha, leaving the
+    /// colors premultiplied by the alpha for compositing
+    pub transparent_background: bool,
+    /// Stops the render when set. Tiles already being rendered are finished,
+    /// so the state it returns is good to resume from.
+    pub cancel: Arc<AtomicBool>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 32,
+            crop: None,
+            passes: 1,
+            samples_per_pixel: 1,
+            adaptive: None,
+            adaptive_passes: None,
+            sampler: SamplerKind::default(),
+            seed: 0,
+            integrator: Integrator::default(),
+            max_depth: 16,
+            roulette_depth: 3,
+            clamp: None,
+            ambient_occlusion: AmbientOcclusion::default(),
+            bias: Bias::default(),
+            debug: None,
+            transparent_background: false,
+            cancel: Arc::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    /// The standard error of a pixel's brightness it takes to be sampled
+    /// again, in the linear units of the image
+    pub threshold: Float,
+    /// Samples per pixel and pass never go beyond this
+    pub max_samples: usize,
+}
+
+/// Passes over the noisiest tiles of the image beyond those over all of
+/// it, for spending the samples of a render where they are needed most.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptivePasses {
+    /// The root mean square over the pixels of a tile of the standard error
+    /// of their brightness it takes for the tile to get another pass, in
+    /// the linear units of the image
+    pub threshold: Float,
+    /// Passes a tile never goes beyond
+    pub max_passes: usize,
+}
+
+/// A rectangle of the image, in pixels. Tiles at the right and bottom edges
+/// may be smaller than the tile size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    /// The part of the tile that is inside `other` too, if any.
+    pub fn intersection(&self, other: &Tile) -> Option<Tile> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Tile {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+}
+
+/// Reported once for every tile that has finished rendering a pass.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub tile: Tile,
+    /// The pass the tile finished, counting from zero
+    pub pass: usize,
+    /// Number of tiles finished so far over all passes, this one included
+    pub completed: usize,
+    pub total: usize,
+}
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// Colors in rows from the top left
+    pub pixels: Vec<Color>,
+    /// The fraction of each pixel covered by objects rather than background
+    pub alpha: Vec<Float>,
+    /// Output variables rendered along with the colors, laid out like them
+    pub aovs: Vec<(Aov, Vec<Vec3f>)>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::with_aovs(width, height, &[])
+    }
+
+    pub fn with_aovs(width: usize, height: usize, aovs: &[Aov]) -> Self {
+        let black = vec![Vec3f::new(0.0, 0.0, 0.0); width * height];
+        Self {
+            width,
+            height,
+            pixels: vec![Color::BLACK; width * height],
+            alpha: vec![0.0; width * height],
+            aovs: aovs.iter().map(|&aov| (aov, black.clone())).collect(),
+        }
+    }
+
+    /// The output variable as an image of its own, with the alpha of this
+    /// one.
+    pub fn aov(&self, aov: Aov) -> Option<Image> {
+        let (_, values) = self.aovs.iter().find(|(a, _)| *a == aov)?;
+        Some(Image {
+            width: self.width,
+            height: self.height,
+            pixels: values.iter().map(|&value| Color::from(value)).collect(),
+            alpha: self.alpha.clone(),
+            aovs: vec![],
+        })
+    }
+
+    /// The rectangle of the image, as far as it lies within it, with its
+    /// output variables.
+    pub fn crop(&self, rect: &Tile) -> Image {
+        let whole = Tile {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+        let rect = rect.intersection(&whole).unwrap_or(Tile {
+            width: 0,
+            height: 0,
+            ..whole
+        });
+        Image {
+            width: rect.width,
+            height: rect.height,
+            pixels: crop_rows(&self.pixels, self.width, &rect),
+            alpha: crop_rows(&self.alpha, self.width, &rect),
+            aovs: self
+                .aovs
+                .iter()
+                .map(|(aov, values)| (*aov, crop_rows(values, self.width, &rect)))
+                .collect(),
+        }
+    }
+
+    /// Blends an image with the same output variables into the rectangle
+    /// of this one at `x` and `y`, as the latest of a running average with
+    /// the given weight: one over the number of images averaged so far.
+    /// Variables that are not averaged keep the first image's values.
+    fn blend(&mut self, x: usize, y: usize, sample: &Image, weight: Float) {
+        for row in 0..sample.height {
+            let start = (y + row) * self.width + x;
+            let span = start..start + sample.width;
+            let row = row * sample.width..(row + 1) * sample.width;
+            for (average, &color) in self.pixels[span.clone()]
+                .iter_mut()
+                .zip(&sample.pixels[row.clone()])
+            {
+                *average = *average + ((color - *average) * weight);
+            }
+            for (average, &alpha) in self.alpha[span.clone()]
+                .iter_mut()
+                .zip(&sample.alpha[row.clone()])
+            {
+                *average += (alpha - *average) * weight;
+            }
+            for ((aov, averages), (_, values)) in self.aovs.iter_mut().zip(&sample.aovs) {
+                if !aov.is_averaged() && weight < 1.0 {
+                    continue;
+                }
+                for (average, &value) in averages[span.clone()].iter_mut().zip(&values[row.clone()])
+                {
+                    *average = *average + ((value - *average) * weight);
+                }
+            }
+        }
+    }
+}
+
+/// The values of the rectangle of an image `width` wide, in rows from its
+/// top left.
+fn crop_rows<T: Copy>(values: &[T], width: usize, rect: &Tile) -> Vec<T> {
+    (rect.y..rect.y + rect.height)
+        .flat_map(|y| &values[y * width + rect.x..y * width + rect.x + rect.width])
+        .copied()
+        .collect()
+}
+
+/// Splits the image into tiles, in rows from the top left.
+pub fn tiles(width: usize, height: usize, tile_size: usize) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let mut tiles = vec![];
+    for y in (0..height).step_by(tile_size) {
+        for x in (0..width).step_by(tile_size) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+/// The pixels of the tile, grouped into the square packets their primary
+/// rays are traced in, which may be cut short at the edges of the tile.
+fn packet_order(tile: &Tile) -> Vec<(usize, usize)> {
+    let mut positions = vec![];
+    for y in (0..tile.height).step_by(PACKET_SIZE) {
+        for x in (0..tile.width).step_by(PACKET_SIZE) {
+            for dy in 0..PACKET_SIZE.min(tile.height - y) {
+                for dx in 0..PACKET_SIZE.min(tile.width - x) {
+                    positions.push((x + dx, y + dy));
+                }
+            }
+        }
+    }
+    positions
+}
+
+/// Traces sample `index` through each of the given pixels of the tile,
+/// calling `f` with the index of the pixel in the tile, the ray and what it
+/// hit, except for the pixels the camera sees nothing through, which stay
+/// black and transparent. Rays through pixels next to each other in
+/// `positions` are traced together in packets, unless moving objects are
+/// blurred: each sample is then traced on its own, at its own time.
+fn trace_pixels<'a, F>(
+    scene: &'a Scene,
+    tile: &Tile,
+    (width, height): (usize, usize),
+    positions: &[(usize, usize)],
+    sampler: &dyn Sampler,
+    index: usize,
+    mut f: F,
+) where
+    F: FnMut(usize, &Ray, Option<HitRecord<'a>>),
+{
+    let ray = |x: usize, y: usize| {
+        let (x, y) = (tile.x + x, tile.y + y);
+        let (dx, dy) = sampler.get_2d(x, y, index, sampler::PIXEL);
+        scene.camera.ray(
+            (x as Float + dx, y as Float + dy),
+            sampler.get_2d(x, y, index, sampler::LENS),
+            width,
+            height,
+        )
+    };
+    if scene.camera.shutter > 0.0 && scene.has_motion() {
+        for &(x, y) in positions {
+            let time = sampler
+                .get_2d(tile.x + x, tile.y + y, index, sampler::TIME)
+                .0;
+            moving::set_time(time * scene.camera.shutter);
+            if let Some(ray) = ray(x, y) {
+                stats::count(|stats| stats.primary_rays += 1);
+                f(x + y * tile.width, &ray, scene.hit(&ray));
+            }
+        }
+        moving::set_time(0.0);
+        return;
+    }
+
+    for packet in positions.chunks(PACKET_SIZE * PACKET_SIZE) {
+        let (packet, rays): (Vec<_>, Vec<_>) = packet
+            .iter()
+            .filter_map(|&(x, y)| Some(((x, y), ray(x, y)?)))
+            .unzip();
+        stats::count(|stats| stats.primary_rays += rays.len() as u64);
+        let hits = scene.intersect_packet(&rays);
+        for ((&(x, y), ray), hit) in packet.iter().zip(&rays).zip(hits) {
+            f(x + y * tile.width, ray, hit);
+        }
+    }
+}
+
+/// Relative brightness of a color, as the eye sees it.
+pub(crate) fn luminance(color: &Vec3f) -> Float {
+    0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2
+}
+
+/// The samples per pixel every pass starts with, and the most it may take.
+fn sample_counts(options: &RenderOptions) -> (usize, usize) {
+    let samples = options.samples_per_pixel.max(1);
+    match options.adaptive {
+        // Variance needs two samples to go on
+        Some(adaptive) => (samples.max(2), adaptive.max_samples.max(samples.max(2))),
+        None => (samples, samples),
+    }
+}
+
+/// Renders a pass over the tile, as an image the size of the tile with the
+/// given output variables. Each pass takes its own run of samples, so that
+/// no two passes sample the same places.
+///
+/// With adaptive sampling, pixels are sampled again for as long as the
+/// standard error of their mean brightness is above the threshold.
+fn render_tile(
+    scene: &Scene,
+    tile: &Tile,
+    image_size: (usize, usize),
+    pass: usize,
+    aovs: &[Aov],
+    sampler: &dyn Sampler,
+    options: &RenderOptions,
+) -> Image {
+    let (samples, max_samples) = sample_counts(options);
+    let mut image = Image::with_aovs(tile.width, tile.height, aovs);
+    let mut counts = vec![0; tile.width * tile.height];
+    // Sums of squared differences from the mean brightness
+    let mut spread = vec![0.0; tile.width * tile.height];
+
+    let footprint = integrator::pixel_footprint(&scene.camera, image_size);
+    let bounds = options.debug == Some(DebugView::Bounds);
+
+    let mut positions = packet_order(tile);
+    for sample in 0..max_samples {
+        if let Some(adaptive) = options.adaptive.filter(|_| sample >= samples) {
+            positions.retain(|&(x, y)| {
+                let i = x + y * tile.width;
+                let n = counts[i] as Float;
+                (spread[i] / ((n - 1.0) * n)).sqrt() > adaptive.threshold
+            });
+            if positions.is_empty() {
+                break;
+            }
+        }
+
+        let index = pass * max_samples + sample;
+        trace_pixels(
+            scene,
+            tile,
+            image_size,
+            &positions,
+            sampler,
+            index,
+            |i, ray, hit| {
+                counts[i] += 1;
+                let weight = 1.0 / counts[i] as Float;
+                let sample = PixelSample {
+                    sampler,
+                    x: tile.x + i % tile.width,
+                    y: tile.y + i / tile.width,
+                    index,
+                    footprint,
+                    wavelength: None,
+                };
+                let radiance = || {
+                    if hit.is_some() || !options.transparent_background {
+                        integrator::radiance(scene, &ray.origin, &ray.dir, hit, &sample, options)
+                    } else {
+                        Vec3f::new(0.0, 0.0, 0.0)
+                    }
+                };
+                let color = radiance();
+                // The light worked out is linear, as the image holds it
+                let color = Color::from(if bounds {
+                    integrator::outline_bounds(scene, ray, hit, color, footprint)
+                } else {
+                    color
+                });
+                let alpha = if hit.is_some() { 1.0 } else { 0.0 };
+
+                let previous = image.pixels[i].luminance();
+                image.pixels[i] = image.pixels[i] + ((color - image.pixels[i]) * weight);
+                image.alpha[i] += (alpha - image.alpha[i]) * weight;
+                spread[i] += (color.luminance() - previous)
+                    * (color.luminance() - image.pixels[i].luminance());
+                for (aov, values) in &mut image.aovs {
+                    // Unaveraged variables keep the first sample
+                    if aov.is_averaged() || counts[i] == 1 {
+                        let value = match *aov {
+                            Aov::Lights(group) => light::only_group(group, radiance),
+                            _ => aov.value(ray, hit, scene),
+                        };
+                        values[i] = values[i] + ((value - values[i]) * weight);
+                    }
+                }
+            },
+        );
+    }
+    image
+}
+
+/// The passes rendered so far, which a render can be resumed from. Samplers
+/// place samples by pixel and number alone, so there is no random state to
+/// keep.
+pub struct RenderState {
+    /// The average of the passes each pixel has had
+    pub image: Image,
+    pub tile_size: usize,
+    /// Passes finished by each tile, in the order of `tiles()`
+    pub tile_passes: Vec<usize>,
+    /// For each tile, the squared differences of the brightness of its
+    /// pixels in each pass from their average over the passes, summed, for
+    /// how noisy the tile still is
+    pub tile_spread: Vec<Float>,
+}
+
+impl RenderState {
+    pub fn new(width: usize, height: usize, tile_size: usize) -> Self {
+        Self::with_aovs(width, height, tile_size, &[])
+    }
+
+    pub fn with_aovs(width: usize, height: usize, tile_size: usize, aovs: &[Aov]) -> Self {
+        Self {
+            image: Image::with_aovs(width, height, aovs),
+            tile_size,
+            tile_passes: vec![0; tiles(width, height, tile_size).len()],
+            tile_spread: vec![0.0; tiles(width, height, tile_size).len()],
+        }
+    }
+
+    /// The root mean square of the standard error of the brightness of the
+    /// pixels of the tile, or infinite before it has had two passes.
+    pub fn tile_error(&self, index: usize, tile: &Tile) -> Float {
+        let passes = self.tile_passes[index] as Float;
+        if passes < 2.0 {
+            return Float::INFINITY;
+        }
+        let pixels = (tile.width * tile.height) as Float;
+        (self.tile_spread[index] / ((passes - 1.0) * passes * pixels)).sqrt()
+    }
+}
+
+/// Renders the scene, calling `progress` on the calling thread as each tile
+/// finishes a pass. Along with it comes the state so far, whose image
+/// averages the passes each pixel has had.
+pub fn render_image<F: FnMut(&Progress, &RenderState)>(
+    scene: &Scene,
+    options: &RenderOptions,
+    progress: F,
+) -> Image {
+    let state = RenderState::new(WIDTH, HEIGHT, options.tile_size);
+    resume(scene, options, state, progress).image
+}
+
+/// A pass over one of the tiles of the image, the piece of work a render
+/// is shared out in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TilePass {
+    /// Which of `tiles()` it is
+    pub index: usize,
+    /// The tile, or the part of it inside the crop
+    pub tile: Tile,
+    pub pass: usize,
+}
+
+impl RenderState {
+    /// The passes over tiles still to render, every pass over the image
+    /// queued after the one before, along with how many the whole render
+    /// has. A crop leaves out the tiles outside it and cuts down the ones
+    /// across its edge.
+    ///
+    /// With adaptive passes, once every tile has had the passes over the
+    /// image, the tiles still too noisy get one more each, and the render
+    /// has as many more passes as they have had so far; rendering them and
+    /// asking again gives the next round, until none is left.
+    pub fn remaining(&self, options: &RenderOptions) -> (Vec<TilePass>, usize) {
+        let (width, height) = (self.image.width, self.image.height);
+        let tiles = tiles(width, height, self.tile_size);
+        assert_eq!(tiles.len(), self.tile_passes.len(), "mismatched tiles");
+        let passes = options.passes.max(1);
+        let cropped: Vec<_> = tiles
+            .iter()
+            .map(|tile| match &options.crop {
+                Some(crop) => tile.intersection(crop),
+                None => Some(*tile),
+            })
+            .collect();
+        let mut remaining = vec![];
+        for pass in 0..passes {
+            for (index, (&done, tile)) in self.tile_passes.iter().zip(&cropped).enumerate() {
+                if let Some(tile) = tile.filter(|_| pass >= done) {
+                    remaining.push(TilePass { index, tile, pass });
+                }
+            }
+        }
+        let mut total = cropped.iter().flatten().count() * passes;
+        if let Some(adaptive) = options.adaptive_passes {
+            let done_over_image = remaining.is_empty();
+            for (index, (&done, tile)) in self.tile_passes.iter().zip(&cropped).enumerate() {
+                let tile = match tile {
+                    Some(tile) => *tile,
+                    None => continue,
+                };
+                total += done.saturating_sub(passes);
+                if done_over_image
+                    && done < adaptive.max_passes
+                    && self.tile_error(index, &tile) > adaptive.threshold
+                {
+                    remaining.push(TilePass {
+                        index,
+                        tile,
+                        pass: done,
+                    });
+                    total += 1;
+                }
+            }
+        }
+        (remaining, total)
+    }
+
+    /// Averages a rendered pass over a tile into the image. Passes of a
+    /// tile may be added out of order, which makes no difference to the
+    /// average.
+    pub fn add(&mut self, pass: &TilePass, samples: &Image) {
+        self.tile_passes[pass.index] += 1;
+        let weight = 1.0 / self.tile_passes[pass.index] as Float;
+        // The differences from the average before and after the pass, as
+        // Welford's running variance sums them
+        let mut spread = 0.0;
+        for row in 0..samples.height {
+            let start = (pass.tile.y + row) * self.image.width + pass.tile.x;
+            let averages = &self.image.pixels[start..start + samples.width];
+            let colors = &samples.pixels[row * samples.width..(row + 1) * samples.width];
+            for (average, color) in averages.iter().zip(colors) {
+                let (average, brightness) = (average.luminance(), color.luminance());
+                let next = average + (brightness - average) * weight;
+                spread += (brightness - average) * (brightness - next);
+            }
+        }
+        self.tile_spread[pass.index] += spread;
+        self.image.blend(pass.tile.x, pass.tile.y, samples, weight);
+    }
+}
+
+/// Renders a pass over a tile of an image of the given size on its own, for
+/// sharing a render out beyond the threads of `resume`, such as to other
+/// machines. Adding the passes to a state renders the same image as
+/// `resume` would.
+pub fn render_pass(
+    scene: &Scene,
+    options: &RenderOptions,
+    image_size: (usize, usize),
+    pass: &TilePass,
+    aovs: &[Aov],
+) -> Image {
+    let sampler = options
+        .sampler
+        .build(sample_counts(options).1, options.seed);
+    render_tile(
+        scene,
+        &pass.tile,
+        image_size,
+        pass.pass,
+        aovs,
+        sampler.as_ref(),
+        options,
+    )
+}
+
+/// Continues a render from an earlier state, rendering the passes each tile
+/// is missing. The tile size and output variables of the state take
+/// precedence over the options.
+pub fn resume<F: FnMut(&Progress, &RenderState)>(
+    scene: &Scene,
+    options: &RenderOptions,
+    mut state: RenderState,
+    mut progress: F,
+) -> RenderState {
+    // Adaptive passes come in rounds, each once the one before has shown
+    // which tiles are still noisy
+    loop {
+        let work = state.remaining(options);
+        if work.0.is_empty() || options.cancel.load(Ordering::Relaxed) {
+            return state;
+        }
+        state = render_passes(scene, options, state, work, &mut progress);
+    }
+}
+
+/// Renders the passes over tiles into the state, out of `total` passes of
+/// the whole render.
+fn render_passes<F: FnMut(&Progress, &RenderState)>(
+    scene: &Scene,
+    options: &RenderOptions,
+    mut state: RenderState,
+    (work, total): (Vec<TilePass>, usize),
+    progress: &mut F,
+) -> RenderState {
+    let image_size = (state.image.width, state.image.height);
+    let aovs: Vec<_> = state.image.aovs.iter().map(|&(aov, _)| aov).collect();
+    let sampler = options
+        .sampler
+        .build(sample_counts(options).1, options.seed);
+    let previous = total - work.len();
+
+    let mut finish = |completed: usize, (pass, samples): (TilePass, Image)| {
+        state.add(&pass, &samples);
+        progress(
+            &Progress {
+                tile: pass.tile,
+                pass: pass.pass,
+                completed: previous + completed + 1,
+                total,
+            },
+            &state,
+        );
+    };
+
+    // With a single thread, the tiles are rendered on the calling one,
+    // which also works where no threads can be started, as in WebAssembly
+    if options.threads <= 1 {
+        for (completed, pass) in work.iter().enumerate() {
+            if options.cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let samples = render_tile(
+                scene,
+                &pass.tile,
+                image_size,
+                pass.pass,
+                &aovs,
+                sampler.as_ref(),
+                options,
+            );
+            finish(completed, (*pass, samples));
+        }
+        return state;
+    }
+
+    // Threads take tiles off a shared counter until none are left, which
+    // keeps them all busy even when some parts of the image are slower to
+    // render, and send the finished ones back to be added to the image
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|s| {
+        for _ in 0..options.threads.max(1) {
+            let sender = sender.clone();
+            let (work, next, aovs) = (&work, &next, &aovs);
+            let sampler = sampler.as_ref();
+            let cancel = &options.cancel;
+            s.spawn(move || {
+                while let Some(&pass) = work.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if cancel.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let samples = render_tile(
+                        scene, &pass.tile, image_size, pass.pass, aovs, sampler, options,
+                    );
+                    if sender.send((pass, samples)).is_err() {
+                        break;
+                    }
+                }
+                stats::flush();
+            });
+        }
+        drop(sender);
+        for (completed, finished) in receiver.iter().enumerate() {
+            finish(completed, finished);
+        }
+    });
+
+    state
+}
@@ -0,0 +1,138 @@
+//! Blue noise: values over the pixels that are evenly spread, with no two
+//! close ones alike, so that what they leave behind has no low frequencies
+//! to catch the eye. Dithering takes its thresholds from it, and the blue
+//! noise sampler shifts the samples of each pixel by it.
+
+use std::sync::OnceLock;
+
+use super::r2::{A1, A2};
+use super::{hash, Sampler};
+use crate::vector::{to_f64, Float};
+
+/// The R2 sequence within each pixel, shifted in every pixel and dimension
+/// by the blue noise mask, each dimension reading it at an offset of its
+/// own. At a few samples per pixel neighbouring pixels then err opposite
+/// ways rather than at random, in where they aim within the pixel, which
+/// light they pick and where they bounce alike, which looks like fine grain
+/// rather than blotches at the same amount of noise.
+pub struct BlueNoise {
+    pub seed: u32,
+}
+
+impl Sampler for BlueNoise {
+    fn get_2d(&self, x: usize, y: usize, index: usize, dimension: usize) -> (Float, Float) {
+        let mask = mask();
+        let shift = |h: u32| {
+            let (dx, dy) = (h as usize % MASK_SIZE, (h >> 16) as usize % MASK_SIZE);
+            to_f64(mask[(y + dy) % MASK_SIZE * MASK_SIZE + (x + dx) % MASK_SIZE])
+        };
+        let h = hash(self.seed, &[dimension as u32]);
+        let n = index as f64;
+        (
+            (shift(h) + A1 * n).fract() as Float,
+            (shift(hash(self.seed, &[h])) + A2 * n).fract() as Float,
+        )
+    }
+}
+
+/// Width and height of the mask, which repeats beyond them
+pub const MASK_SIZE: usize = 64;
+/// Spread of the filter that measures how crowded the mask is around a pixel
+const SIGMA: Float = 1.5;
+
+/// Pixels of a binary pattern on a torus, with the energy each pixel gets
+/// from the set ones nearby. Set pixels with the most energy are the
+/// tightest clusters, unset ones with the least the largest voids.
+struct Pattern {
+    set: Vec<bool>,
+    energy: Vec<Float>,
+}
+
+impl Pattern {
+    fn toggle(&mut self, kernel: &[Float], p: usize) {
+        let sign = if self.set[p] { -1.0 } else { 1.0 };
+        self.set[p] = !self.set[p];
+        let (px, py) = (p % MASK_SIZE, p / MASK_SIZE);
+        for (q, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (q % MASK_SIZE + MASK_SIZE - px) % MASK_SIZE;
+            let dy = (q / MASK_SIZE + MASK_SIZE - py) % MASK_SIZE;
+            *energy += sign * kernel[dy * MASK_SIZE + dx];
+        }
+    }
+
+    /// The set pixel with the most energy, or the unset one with the least.
+    fn extreme(&self, set: bool) -> usize {
+        let candidates = (0..self.set.len()).filter(|&p| self.set[p] == set);
+        let energy = |p: &usize| self.energy[*p];
+        if set {
+            candidates.max_by(|a, b| energy(a).total_cmp(&energy(b)))
+        } else {
+            candidates.min_by(|a, b| energy(a).total_cmp(&energy(b)))
+        }
+        .unwrap()
+    }
+}
+
+/// A blue noise mask made with a void-and-cluster method: a random pattern
+/// is relaxed until it is evenly spread, then pixels are ranked by taking
+/// the tightest clusters out of it and filling the largest voids around it.
+/// It is made once, the first time it is needed, with its values in `0..1`
+/// row by row.
+pub fn mask() -> &'static [Float] {
+    static MASK: OnceLock<Vec<Float>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        let n = MASK_SIZE * MASK_SIZE;
+        let mut kernel = vec![0.0; n];
+        for (i, k) in kernel.iter_mut().enumerate() {
+            let wrap = |d: usize| d.min(MASK_SIZE - d) as Float;
+            let (dx, dy) = (wrap(i % MASK_SIZE), wrap(i / MASK_SIZE));
+            *k = (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp();
+        }
+
+        // One pixel in ten, picked by a fixed linear congruential generator
+        let mut pattern = Pattern {
+            set: vec![false; n],
+            energy: vec![0.0; n],
+        };
+        let mut state = 1u64;
+        let mut initial = 0;
+        while initial < n / 10 {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let p = (state >> 33) as usize % n;
+            if !pattern.set[p] {
+                pattern.toggle(&kernel, p);
+                initial += 1;
+            }
+        }
+        for _ in 0..n {
+            let cluster = pattern.extreme(true);
+            pattern.toggle(&kernel, cluster);
+            let void = pattern.extreme(false);
+            pattern.toggle(&kernel, void);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0; n];
+        let mut shrinking = Pattern {
+            set: pattern.set.clone(),
+            energy: pattern.energy.clone(),
+        };
+        for r in (0..initial).rev() {
+            let cluster = shrinking.extreme(true);
+            shrinking.toggle(&kernel, cluster);
+            rank[cluster] = r;
+        }
+        for r in initial..n {
+            let void = pattern.extreme(false);
+            pattern.toggle(&kernel, void);
+            rank[void] = r;
+        }
+        rank.iter()
+            .map(|&r| (r as Float + 0.5) / n as Float)
+            .collect()
+    })
+}
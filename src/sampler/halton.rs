@@ -0,0 +1,38 @@
+use super::{hash, unit, Sampler};
+use crate::vector::Float;
+
+const PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+/// The digits of `index` in the given base, mirrored around the decimal
+/// point.
+fn radical_inverse(mut index: u64, base: u32) -> f64 {
+    let base = base as u64;
+    let (mut reversed, mut scale) = (0u64, 1.0);
+    while index > 0 {
+        reversed = reversed * base + index % base;
+        scale /= base as f64;
+        index /= base;
+    }
+    reversed as f64 * scale
+}
+
+/// The Halton sequence, with a pair of prime bases for each of the first
+/// dimensions and the ones past those reusing them. Every pixel shifts the
+/// sequence by its own random amount, so that neighbouring pixels don't
+/// repeat each other's pattern.
+pub struct Halton {
+    pub seed: u32,
+}
+
+impl Sampler for Halton {
+    fn get_2d(&self, x: usize, y: usize, index: usize, dimension: usize) -> (Float, Float) {
+        let pair = dimension % (PRIMES.len() / 2);
+        let shift = hash(self.seed, &[x as u32, y as u32, dimension as u32]);
+        let sample =
+            |base, shift| (radical_inverse(index as u64, base) + unit(shift) as f64).fract();
+        (
+            sample(PRIMES[2 * pair], shift) as Float,
+            sample(PRIMES[2 * pair + 1], hash(self.seed, &[shift])) as Float,
+        )
+    }
+}
@@ -0,0 +1,181 @@
+//! Where samples go. Everything random in a render, from where in the pixel a
+//! ray goes to where on a light it aims, takes its numbers from a sampler, so
+//! that they are spread evenly and the same render always comes out the
+//! same.
+//!
+//! Samplers are stateless: a sample is picked by its pixel, its index among
+//! the samples of the pixel and its dimension, the use it is put to. That
+//! keeps them shareable between threads and lets a render resume without
+//! keeping any random state around. A seed picks between different but
+//! equally good sets of samples; seed 0 is the one renders use by default.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::vector::Float;
+
+pub mod blue_noise;
+mod halton;
+mod r2;
+mod sobol;
+mod stratified;
+
+pub use self::blue_noise::BlueNoise;
+pub use self::halton::Halton;
+pub use self::r2::R2;
+pub use self::sobol::Sobol;
+pub use self::stratified::Stratified;
+
+/// The dimension of the position within the pixel
+pub const PIXEL: usize = 0;
+/// The dimension of the position on the lens
+pub const LENS: usize = 1;
+/// The dimension of the time within the shutter interval
+pub const TIME: usize = 2;
+/// Dimensions each bounce of a path takes, following the camera ones
+pub const PER_BOUNCE: usize = 8;
+/// The dimension of the direction a path bounces off in, within a bounce
+pub const DIRECTION: usize = 0;
+/// The dimension of the points on the lights sampled at a bounce
+pub const LIGHT: usize = 1;
+/// The dimension of the rays looking for nearby surfaces at a bounce
+pub const OCCLUSION: usize = 2;
+/// The dimension deciding whether a path carries on after a bounce
+pub const ROULETTE: usize = 3;
+/// The dimension of the points on emissive objects sampled at a bounce
+pub const EMITTER: usize = 4;
+/// The dimension of the directions the background is sampled in at a bounce
+pub const ENVIRONMENT: usize = 5;
+/// The dimension picking which way a surface reflects a path at a bounce
+pub const LOBE: usize = 6;
+/// The dimension offsetting the steps camera rays take through volumes
+pub const MEDIUM: usize = 7;
+
+/// The dimension of a sample taken at the given bounce of a path, counting
+/// from zero at the first hit.
+pub fn bounce(depth: usize, dimension: usize) -> usize {
+    TIME + 1 + depth * PER_BOUNCE + dimension
+}
+
+pub trait Sampler: Send + Sync {
+    /// A point in the unit square for sample `index` of the pixel at `x` and
+    /// `y`, in the given dimension.
+    fn get_2d(&self, x: usize, y: usize, index: usize, dimension: usize) -> (Float, Float);
+}
+
+/// One sample of a pixel, which can be taken in any dimension.
+#[derive(Clone, Copy)]
+pub struct PixelSample<'a> {
+    pub sampler: &'a dyn Sampler,
+    pub x: usize,
+    pub y: usize,
+    pub index: usize,
+    /// How wide the pixel is where its ray leaves the camera, and how much
+    /// wider it gets along each unit of the ray, as `pixel_footprint` of
+    /// the integrator works them out
+    pub footprint: (Float, Float),
+    /// The wavelength of the light the sample follows, in nanometres, if it
+    /// follows a single one
+    pub wavelength: Option<Float>,
+}
+
+impl PixelSample<'_> {
+    /// How wide the pixel is `distance` along its ray, or along the path
+    /// the ray bounced along, as though it had gone straight on.
+    pub fn width(&self, distance: Float) -> Float {
+        self.footprint.0 + self.footprint.1 * distance
+    }
+
+    pub fn get_2d(&self, dimension: usize) -> (Float, Float) {
+        self.sampler.get_2d(self.x, self.y, self.index, dimension)
+    }
+
+    /// Sample `i` of `count` taken within this one, for estimates that take
+    /// several samples of their own every time.
+    pub fn split(&self, count: usize, i: usize) -> Self {
+        Self {
+            index: self.index * count + i,
+            ..*self
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// The R2 sequence, shared by every pixel
+    #[default]
+    R2,
+    /// Jittered samples, one in each cell of a grid over the pixel
+    Stratified,
+    /// The Halton sequence, shifted differently in every pixel
+    Halton,
+    /// The Sobol sequence, scrambled differently in every pixel
+    Sobol,
+    /// The R2 sequence, shifted by a blue noise mask over the pixels
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Builds the sampler for renders taking the given number of samples per
+    /// pixel at a time, with the given seed.
+    pub fn build(self, samples_per_pixel: usize, seed: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::R2 => Box::new(R2 { seed }),
+            SamplerKind::Stratified => Box::new(Stratified::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(Halton { seed }),
+            SamplerKind::Sobol => Box::new(Sobol { seed }),
+            SamplerKind::BlueNoise => Box::new(BlueNoise { seed }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "r2" => Ok(SamplerKind::R2),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "blue-noise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler `{}`, expected r2, stratified, halton, sobol or blue-noise",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SamplerKind::R2 => "r2",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue-noise",
+        })
+    }
+}
+
+/// Mixes the values into 32 bits that look random, for decorrelating pixels
+/// and dimensions, differently for every seed.
+fn hash(seed: u32, values: &[u32]) -> u32 {
+    let mut h = 0x9e37_79b9u32 ^ seed;
+    for &v in values {
+        // Chris Wellons' lowbias32
+        h ^= v;
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x7feb_352d);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x846c_a68b);
+        h ^= h >> 16;
+    }
+    h
+}
+
+/// The top 24 bits as a number in `0..1`.
+fn unit(bits: u32) -> Float {
+    (bits >> 8) as Float / (1 << 24) as Float
+}
@@ -0,0 +1,32 @@
+use super::{hash, unit, Sampler, PIXEL};
+use crate::vector::Float;
+
+/// The R2 sequence, which spreads any number of samples evenly over the
+/// unit square. It starts in the center, and every pixel takes the same
+/// positions; other dimensions are shifted by a fixed amount each so that
+/// they don't line up with the pixel positions. Seeds other than 0 shift the
+/// pixel positions too.
+pub struct R2 {
+    pub seed: u32,
+}
+
+/// The steps of the sequence along each axis, one over the plastic number
+/// and its square
+pub(super) const A1: f64 = 0.754_877_666_246_692_8;
+pub(super) const A2: f64 = 0.569_840_290_998_053_3;
+
+impl Sampler for R2 {
+    fn get_2d(&self, _x: usize, _y: usize, index: usize, dimension: usize) -> (Float, Float) {
+        let (shift_x, shift_y) = if dimension == PIXEL && self.seed == 0 {
+            (0.5, 0.5)
+        } else {
+            let h = hash(self.seed, &[dimension as u32]);
+            (unit(h) as f64, unit(h.rotate_left(16)) as f64)
+        };
+        let n = index as f64;
+        (
+            (shift_x + A1 * n).fract() as Float,
+            (shift_y + A2 * n).fract() as Float,
+        )
+    }
+}
@@ -0,0 +1,36 @@
+use super::{hash, unit, Sampler};
+use crate::vector::Float;
+
+/// The second dimension of the Sobol sequence, whose direction numbers
+/// follow from the primitive polynomial `x + 1`.
+fn sobol_second(mut index: u32) -> u32 {
+    let (mut v, mut result) = (1u32 << 31, 0);
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+/// The first two dimensions of the Sobol sequence, which stratify every
+/// power of two samples perfectly. Each pixel and dimension flips the bits
+/// of the points by its own random mask, which keeps them stratified but
+/// decorrelated from each other.
+pub struct Sobol {
+    pub seed: u32,
+}
+
+impl Sampler for Sobol {
+    fn get_2d(&self, x: usize, y: usize, index: usize, dimension: usize) -> (Float, Float) {
+        let index = index as u32;
+        let scramble = hash(self.seed, &[x as u32, y as u32, dimension as u32]);
+        let (sx, sy) = (
+            index.reverse_bits() ^ scramble,
+            sobol_second(index) ^ hash(self.seed, &[scramble]),
+        );
+        (unit(sx), unit(sy))
+    }
+}
@@ -0,0 +1,40 @@
+use super::{hash, unit, Sampler};
+use crate::vector::Float;
+
+/// Divides the unit square into a grid with at least as many cells as
+/// samples are taken at a time, and puts each sample at a random spot within
+/// its own cell. Pixels and dimensions visit the cells in different orders.
+pub struct Stratified {
+    /// Cells along each side of the grid
+    side: usize,
+    seed: u32,
+}
+
+impl Stratified {
+    pub fn new(samples_per_pixel: usize, seed: u32) -> Self {
+        let mut side = 1;
+        while side * side < samples_per_pixel {
+            side += 1;
+        }
+        Self { side, seed }
+    }
+}
+
+impl Sampler for Stratified {
+    fn get_2d(&self, x: usize, y: usize, index: usize, dimension: usize) -> (Float, Float) {
+        let cells = self.side * self.side;
+        let (round, cell) = (index / cells, index % cells);
+        let key = [x as u32, y as u32, dimension as u32, round as u32];
+        let cell = (cell + hash(self.seed, &key) as usize) % cells;
+
+        let jitter = hash(
+            self.seed,
+            &[x as u32, y as u32, dimension as u32, index as u32],
+        );
+        let (jx, jy) = (unit(jitter), unit(hash(self.seed, &[jitter])));
+        (
+            ((cell % self.side) as Float + jx) / self.side as Float,
+            ((cell / self.side) as Float + jy) / self.side as Float,
+        )
+    }
+}
@@ -0,0 +1,2720 @@
+//! Scene descriptions, either built in or loaded from a JSON file:
+//!
+//! ```json
+//! {
+//!     "objects": [
+//!         {
+//!             "type": "sphere",
+//!             "center": [-3, 0, -16],
+//!             "radius": 2,
+//!             "material": { "albedo": [0.6, 0.3], "diffuse_color": [0.4, 0.4, 0.3], "specular_exponent": 50 }
+//!         },
+//!         { "type": "mesh", "file": "duck.obj", "material": { "diffuse_color": [0.3, 0.1, 0.1] } }
+//!     ],
+//!     "lights": [
+//!         { "position": [-20, 20, 20], "intensity": 1.5 }
+//!     ]
+//! }
+//! ```
+//!
+//! Every object has a `type` and a `material`:
+//!
+//! - `sphere`: `center` and `radius`
+//! - `triangle`: three `vertices`, with optional texture coordinates `uvs`
+//! - `quad`: a `corner` and the edge vectors `u` and `v`
+//! - `disk`: `center`, `normal` and `radius`
+//! - `box`: the opposite corners `min` and `max`
+//! - `cylinder`, `cone`: a `base` center, `axis` direction, `height` and
+//!   `radius`; cones narrow to their apex
+//! - `torus`: `center`, `axis`, `major_radius` and `minor_radius`
+//! - `csg`: the `union`, `intersection` or `difference` `operation` of the
+//!   solids (spheres, boxes, cylinders, cones, tori and other CSG nodes)
+//!   `left` and `right`, which default to the material of the CSG node
+//! - `sdf`: a distance field `shape`, rendered by sphere tracing up to an
+//!   optional `max_distance`. Shapes are of `type` `sphere` (`center`,
+//!   `radius`), `box` (`center`, `half_size` and optional `rounding`),
+//!   `torus` (`center`, `major_radius`, `minor_radius`), `capsule` (end
+//!   points `a` and `b`, `radius`), `union` or `intersection` of `children`,
+//!   or `difference` of `left` and `right`. The operators are blended by
+//!   their optional smoothing distance `k`.
+//! - `metaballs`: `balls`, each a `center`, `radius` and `strength`, 1 by
+//!   default, whose fields add up and merge the balls into blobs where they
+//!   come close; the surface is where the sum reaches the `threshold`, 0.5
+//!   by default. The field of a ball falls off smoothly from its strength at
+//!   its center to nothing at its radius, and a negative strength carves the
+//!   ball out of the blobs around it.
+//! - `mesh`: an `.obj`, `.gltf`, `.glb` or `.stl` `file`, shaded smoothly
+//!   across its faces with the vertex normals of the file if it has any.
+//!   `smooth` set to `true` works them out where the file has none, and
+//!   `false` shades every face flat.
+//! - `heightfield`: terrain raised from the grayscale image `file`, a grid
+//!   of triangles with a vertex at every pixel, `size` across along X and Z,
+//!   `[1, 1]` by default, and white `height` up Y, 1 by default. It lies
+//!   centered on the origin with the top of the image towards -Z, the image
+//!   covering it once by its texture coordinates, and it is shaded smoothly.
+//!
+//! Any object may be placed by a `transform`, from the space it is
+//! described in into the scene: its `scale`, a number or one per axis,
+//! then its `rotate`, degrees about X, Y and Z in that order, then its
+//! `translate`, such as a model turned to face the camera and moved in
+//! front of it. A `matrix` of four rows of four numbers may be given
+//! instead, such as one exported from a modelling tool; its last row is
+//! taken to be `[0, 0, 0, 1]`.
+//!
+//! ```json
+//! { "type": "mesh", "file": "duck.obj", "transform": { "scale": 2, "rotate": [0, 90, 0], "translate": [0, -1, -10] } }
+//! ```
+//!
+//! A `group` holds other objects, and groups of them, as its `children`,
+//! which are moved along with it by its `transform` and its `velocity`,
+//! such as a snowman built of spheres around its own origin and placed in
+//! the scene as a whole:
+//!
+//! ```json
+//! {
+//!     "type": "group",
+//!     "transform": { "rotate": [0, 30, 0], "translate": [2, -4, -15] },
+//!     "children": [
+//!         { "type": "sphere", "center": [0, 1.5, 0], "radius": 1.5, "material": "ivory" },
+//!         { "type": "sphere", "center": [0, 3.8, 0], "radius": 1, "material": "ivory" },
+//!         { "type": "cone", "base": [0, 3.8, 0.9], "axis": [0, 0, 1], "height": 0.8, "radius": 0.2, "material": "red_rubber" }
+//!     ]
+//! }
+//! ```
+//!
+//! Objects using the same mesh file share a single copy of its meshes,
+//! whatever their material and transform, so that a model can be placed
+//! many times over for little more memory than once.
+//!
+//! Lights are `point` lights by default, at a `position`, or of `type`
+//! `directional`, shining in a `direction` from infinitely far away like the
+//! sun, with an optional `angular_diameter` in degrees that softens their
+//! shadows in the path tracer, or `spot` lights at a `position` shining in
+//! a `direction`, fully up to `inner_angle` degrees from it and fading out
+//! smoothly up to `outer_angle`. All of them have an `intensity`, a
+//! number for white light or the brightness of each channel for colored light.
+//! Point and spot lights are as bright at any distance unless their
+//! `falloff` is `inverse_square`, making the intensity the brightness at a
+//! distance of 1. They then stop getting brighter within an optional
+//! `radius`, and fade out to nothing at an optional `cutoff` distance.
+//!
+//! Instead of an intensity, point and spot lights can be given the `power`
+//! they give off in watts, or in `lumens`, as a real bulb is, spread over
+//! the sphere or the cone they shine into, and directional lights the
+//! `irradiance` they shed on a surface facing them in watts per square
+//! metre, or in `lux`, with an optional `color` that only tints the light.
+//! Lights given like this fall off with the square of the distance unless
+//! their `falloff` says otherwise, with distances in metres. Watts are the
+//! units the renderer works in; a lumen is a 683rd of a watt.
+//!
+//! Lights can be put in a light group by its name, a string `group`, as can
+//! the background, and with it the sun of a sky, and emissive materials by
+//! their `light_group`. Each group can then be rendered into a pass of its
+//! own alongside the image, for balancing the lights in compositing.
+//!
+//! The `background` is a color, `[0.2, 0.7, 0.8]` by default, a `gradient`
+//! from a `bottom` color straight down to a `top` color straight up, or an
+//! `environment` map: an equirectangular `.hdr` or `.ppm` image `file` of
+//! the surroundings, its middle straight ahead, with an optional
+//! `intensity` to scale it by. The path tracer samples environment maps as
+//! lights, so they can light a scene without any lights.
+//!
+//! `portals` are the windows and doorways of rooms lit by the background
+//! from outside, each a parallelogram spanned by the edges `u` and `v` from
+//! its `corner`, like a quad. The path tracer samples the background
+//! through them instead of all around, whatever the background, which
+//! finds the light coming in far more often than bouncing around the room
+//! until leaving through one. They are neither seen nor in the way.
+//!
+//! A `sky` background is a clear daytime sky, with the sun at `sun_elevation`
+//! degrees above the horizon and `sun_azimuth` degrees to the right of
+//! straight ahead, both 45 by default. The haze of the air is given by its
+//! `turbidity`, 3 by default, and the ground below the horizon reflects the
+//! light with its `ground_albedo`, 0.3 by default or a color. The sun itself
+//! is added to the lights, as a directional light of `sun_angular_diameter`,
+//! 0.53 degrees by default; `intensity` scales both it and the sky.
+//!
+//! The `camera` is at a `position`, the origin by default, looking at a
+//! `target`, one unit down -Z from the position by default, rolled so that
+//! its `up` direction, +Y by default, points up in the image. Its `fov` is
+//! the angle from the bottom of the image to the top, 90 degrees by
+//! default; the image is as much wider as its aspect ratio makes it. Its
+//! `projection` is `perspective` by default, or `orthographic`: parallel
+//! rays from a plane through the camera `view_width` across, for diagrams
+//! free of perspective, or `fisheye`: a circle as large as the image is
+//! high, or wide if it is narrower, seeing up to `fov` degrees across, 360
+//! at most, with the angles from the middle spread out by an `equidistant`
+//! or `equisolid` `mapping`, equidistant by default, or `equirectangular`:
+//! all the way around, as an environment map of the scene's surroundings,
+//! which needs an image twice as wide as high to be undistorted.
+//!
+//! A `stereo` camera has two eyes `eye_distance` apart, either side of its
+//! position, whose views meet at the `convergence` distance ahead, that of
+//! the target by default: there things appear at the depth of the screen.
+//! Their images share the image `side_by_side`, the left eye's on the left,
+//! by default, or `top_bottom`, the left eye's on top. Panoramic eyes look
+//! straight out each way, as VR headsets expect.
+//!
+//! The camera may also move through `keyframes`, each with a `time` and
+//! any of a `position`, a `target` and a `fov`, which default to those of
+//! the camera. In between them it moves along a `smooth` curve, or in
+//! straight `linear` lines with `"interpolation": "linear"`, and before the
+//! first and after the last it stays put. An
+//! `aperture`, the diameter of the lens, blurs what is nearer or further
+//! than the `focus_distance` ahead, the distance of the target by default;
+//! it takes many samples per pixel to smooth out.
+//!
+//! Objects with a `velocity` move by it per unit of time, from where they
+//! are at the time 0, and are blurred along their way over the time the
+//! camera's `shutter` is open, 0 by default and at most 1.
+//!
+//! Any other value may change over time too, as `keyframes` of `[time,
+//! value]` pairs: numbers and arrays of them move in straight lines from
+//! one keyframe to the next, and anything else changes at each keyframe.
+//! Before the first keyframe and after the last the value stays put:
+//!
+//! ```json
+//! { "type": "sphere", "center": { "keyframes": [[0, [-3, 0, -16]], [2, [3, 0, -16]]] }, "radius": 2, "material": "ivory" }
+//! ```
+//!
+//! `fog`, with a `color` and a `density`, fades objects into the color the
+//! further away they are, by the fraction `density` per unit of distance,
+//! compounded. `volumes` are boxes from `min` to `max` filled with a medium
+//! of some `absorption` and `scattering` per unit of distance, a number or
+//! a color each: camera rays through them are dimmed by both, and pick up
+//! the light the scattering sends their way from the lights that reach it,
+//! which shows shafts of light and the shadows of objects in the medium.
+//!
+//! A material's `diffuse_color` may also be a texture: the file name of a
+//! `.png`, `.ppm` or `.hdr` image, or an object of `type` `image` with the
+//! `file`, which is wrapped over the surface by its texture coordinates:
+//! longitude and latitude on spheres, the edges of quads, the `uvs` of
+//! triangles, which default to the lower left half of the image, and the
+//! `vt` coordinates of `.obj` meshes. Other objects keep the plain gray of
+//! the default material. Patterns through space cover any object, alternating
+//! between or blending two `colors`, white and black by default:
+//!
+//! - `checker`: cubes of `size`, 1 by default
+//! - `stripes`: slabs of `width`, 1 by default, along an `axis`, X by default
+//! - `noise`: Perlin noise, with features about 1 / `scale` across
+//! - `marble`: veins along the `axis`, 2π / `scale` apart, bent by
+//!   `turbulence`, 5 by default
+//!
+//! Noise and marble take an optional `seed` for a different pattern.
+//!
+//! The `albedo` and the `specular_exponent` may be textures too, such as
+//! stripes of glossy paint over a matte base. Their images are read as data:
+//! red and green are the diffuse and specular weights of the albedo, and red
+//! is the exponent, which needs an `.hdr` image to go above 1. Their
+//! patterns' `colors` are pairs of weights and plain numbers.
+//!
+//! A `normal_map` bends the normal of the surface the way a shape too fine
+//! to model would: an image of tangent space normals, with red along the
+//! texture's `u`, green along its `v` and blue out of the surface, read as
+//! data rather than colors. A `bump_map` does the same from heights
+//! instead, as bright as they are high, white being `bump_strength` high,
+//! 0.1 by default. Both take any texture, though patterns bend the normal
+//! along arbitrary directions on objects without texture coordinates.
+//!
+//! A material with a `metallic` or a `roughness`, 0 and 0.5 by default, is
+//! shaded with the physically based metallic-roughness model of glTF
+//! instead: its `base_color`, the same as the `diffuse_color`, is scattered
+//! diffusely by dielectrics and reflected by metals, with highlights of GGX
+//! microfacets, sharp at a `roughness` of 0 and broad at 1. The `albedo` and
+//! the `specular_exponent` have no say in it. The path tracer reflects the
+//! surroundings off the microfacets as well, for glossy reflections.
+//!
+//! Wax, skin, marble and the like let light through: a `subsurface` with
+//! a `color` the light takes on inside and a `radius`, how far it gets before
+//! half of it is gone, lights the side of an object away from a light with
+//! the light shining through it, so that thin parts glow when lit from
+//! behind. Only the lights shine through, not emissive objects or the
+//! background.
+//!
+//! Glass, water and other clear materials have a `refractive_index`, 1.5
+//! for window glass: light passes through them, bending as it goes in and
+//! out, and is reflected off them as well, more so at grazing angles. The
+//! direct integrator follows both a few times over, as the tutorial does,
+//! and the path tracer picks one at random by how much light goes each way.
+//! Colored glass has an `absorption` with the `color` light going through
+//! it takes on over every `distance` it travels, so that thick parts come
+//! out darker and more deeply colored than thin ones. An `abbe_number`, about
+//! 60 for window glass and 30 for flint glass, the lower the more so, has
+//! the spectral integrator bend short wavelengths more than long ones, which
+//! splits white light into its colors. Like anything else in
+//! the way, glass casts a shadow. Its `albedo` and
+//! `specular_exponent` are those of the `glass` preset unless given: no
+//! diffuse light, and sharp highlights. It has no metallic-roughness model.
+//!
+//! Materials may also have an `emission` color, the light the surface gives
+//! off on the side its normal faces: the outside of closed objects, and the
+//! side of quads, disks and triangles their vertices wind anticlockwise
+//! around. Emissive spheres, quads, disks and triangles are sampled as lights
+//! by the path tracer; other emissive objects only light what bounced rays
+//! happen to find them from. The `luminance` of a material gives the light
+//! of its surface in candelas per square metre instead, with the emission
+//! only tinting it, and an object given the `power` it gives off in watts,
+//! or in `lumens`, shares it out over its area, so that a larger panel of
+//! the same power is dimmer.
+//!
+//! The `back_face` of a material says what becomes of rays hitting it from
+//! the side its normal points away from: `shade` shades it like the front,
+//! as by default, `cull` lets the rays through as if it weren't there, for
+//! open meshes and cards seen and casting shadows from one side only, `flip`
+//! turns the normal around to face the ray, and a color shades the back
+//! diffusely in that color instead.
+//!
+//! Instead of a material of its own, an object may name one of the scene's
+//! `materials`, an object of materials by name, which may name the ones
+//! before them too, or one of the presets: `ivory`, `red_rubber` and
+//! `glass` from the tutorial, and a `mirror`, a smooth metal that the path
+//! tracer reflects the scene in:
+//!
+//! ```json
+//! {
+//!     "materials": {
+//!         "gold": { "base_color": [1.0, 0.8, 0.3], "metallic": 1, "roughness": 0.3 },
+//!         "trim": "gold"
+//!     },
+//!     "objects": [
+//!         { "type": "sphere", "center": [-3, 0, -16], "radius": 2, "material": "gold" },
+//!         { "type": "sphere", "center": [3, 0, -16], "radius": 2, "material": "ivory" }
+//!     ]
+//! }
+//! ```
+//!
+//! Missing material fields fall back to a plain grey diffuse material. A
+//! mesh's `material` is optional and, when given, replaces the materials of
+//! the model file, such as those of the `.mtl` libraries of `.obj` files,
+//! which are only read without one. Relative mesh paths are resolved
+//! against the directory of the scene file.
+//!
+//! Scene files ending in `.script` are scripts building the description
+//! instead, as `script` describes.
+//!
+//! A description is checked before it is loaded for what would load but
+//! render wrongly, such as radii that aren't positive or lights inside
+//! spheres, and every problem found is reported at once.
+
+use std::any::Any;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::accel::{Accelerator, AcceleratorKind};
+use crate::arena::{Arena, Handle};
+use crate::background::{Background, EnvironmentMap, Portal};
+use crate::camera::{
+    Camera, CameraPath, FisheyeMapping, Interpolation, Keyframe, Projection, Stereo, StereoLayout,
+};
+use crate::error::{self, Error};
+use crate::files;
+use crate::ies::Profile;
+use crate::input;
+use crate::irradiance::IrradianceCache;
+use crate::json::{self, Value};
+use crate::light::{
+    DirectionalLight, Falloff, Grouped, Light, PointLight, Profiled, SpotLight, LUMENS_PER_WATT,
+};
+use crate::material::{Absorption, BackFace, Glass, Material, MetallicRoughness, Subsurface};
+use crate::mesh;
+use crate::object::{
+    is_culled, Cone, Csg, CsgOperation, Cuboid, Cylinder, Disk, Emitting, HitRecord, Hittable,
+    Instance, Metaball, Metaballs, Moving, Object, Quad, Sdf, SdfObject, Solid, Sphere,
+    SpherePacket, Torus, Transformed, Triangle, LANES,
+};
+use crate::photon::PhotonMap;
+use crate::ray::Ray;
+use crate::render::luminance;
+use crate::script;
+use crate::sky::Sky;
+use crate::texture::{Checker, ImageTexture, Marble, Noise, Perlin, Stripes, Texture};
+use crate::vector::{consts::PI, to_f64, Float, Mat4, Vec2f, Vec3f};
+use crate::volume::{Fog, Volume};
+
+/// Refers to an object of a scene by its place among those the scene was
+/// built from, which for loaded scenes is its place in the file, counting
+/// every object a mesh expands into.
+pub type ObjectHandle = Handle<Box<dyn Object>>;
+
+pub struct Scene {
+    objects: Arena<Box<dyn Object>>,
+    pub lights: Vec<Box<dyn Light>>,
+    pub background: Background,
+    pub camera: Camera,
+    /// Where the camera moves over time, if anywhere
+    pub camera_path: Option<CameraPath>,
+    pub fog: Option<Fog>,
+    pub volumes: Vec<Volume>,
+    /// The light focused by shiny surfaces, traced before the render if
+    /// caustics are asked for
+    pub caustics: Option<PhotonMap>,
+    /// The light group of the background, if any
+    pub background_group: Option<String>,
+    /// The windows and doorways the background is sampled through
+    pub portals: Vec<Portal>,
+    /// The light diffuse surfaces get from each other, worked out before
+    /// the render at points over what the camera sees if irradiance caching
+    /// is asked for
+    pub irradiance_cache: Option<IrradianceCache>,
+    /// The spheres at the start of `objects`, packed `LANES` at a time
+    sphere_packets: Vec<SpherePacket>,
+    sphere_count: usize,
+    /// The spheres of the packets followed by the other bounded objects, in
+    /// the order the accelerator refers to them
+    primitives: Vec<ObjectHandle>,
+    /// Intersects the sphere packets followed by the other bounded objects
+    accel: Box<dyn Accelerator>,
+    /// Objects without bounds, which are tested against every ray
+    unbounded: Vec<ObjectHandle>,
+    /// The emissive objects whose surface can be sampled
+    emitters: Vec<ObjectHandle>,
+    /// Whether any of the objects move
+    motion: bool,
+}
+
+/// Interleaves the bits of the coordinates, which are expected to be within
+/// `0..1024`, so that points close in space tend to be close in the order.
+fn morton_code(x: u32, y: u32, z: u32) -> u32 {
+    let spread = |mut v: u32| {
+        v = (v | (v << 16)) & 0x0300_00ff;
+        v = (v | (v << 8)) & 0x0300_f00f;
+        v = (v | (v << 4)) & 0x030c_30c3;
+        (v | (v << 2)) & 0x0924_9249
+    };
+    spread(x) | (spread(y) << 1) | (spread(z) << 2)
+}
+
+/// The object as a sphere that can go in a packet, which can't cull back
+/// faces.
+fn as_sphere(object: &dyn Object) -> Option<&Sphere> {
+    if matches!(object.material().back_face, BackFace::Cull) {
+        return None;
+    }
+    (object as &dyn Any).downcast_ref()
+}
+
+/// Whether the object is sampled as a light.
+pub(crate) fn is_emitter(object: &dyn Object) -> bool {
+    object.material().is_emissive() && object.area().is_some()
+}
+
+impl Scene {
+    pub fn new(objects: Vec<Box<dyn Object>>, lights: Vec<Box<dyn Light>>) -> Self {
+        Self::with_accelerator(objects, lights, AcceleratorKind::default())
+    }
+
+    /// Builds the scene, along with the acceleration structure used to
+    /// intersect rays with its objects.
+    pub fn with_accelerator(
+        objects: Vec<Box<dyn Object>>,
+        lights: Vec<Box<dyn Light>>,
+        accel: AcceleratorKind,
+    ) -> Self {
+        let bounds: Vec<_> = objects.iter().map(|object| object.bounds()).collect();
+        let mut spheres: Vec<_> = (0..objects.len())
+            .filter(|&i| as_sphere(objects[i].as_ref()).is_some())
+            .collect();
+        let bounded: Vec<_> = (0..objects.len())
+            .filter(|&i| bounds[i].is_some() && as_sphere(objects[i].as_ref()).is_none())
+            .collect();
+        let unbounded: Vec<_> = (0..objects.len())
+            .filter(|&i| bounds[i].is_none())
+            .collect();
+
+        // Pack spheres that are near each other, so that the packets stay
+        // small and rays can skip most of them
+        let sphere_bounds = Aabb::from_points(spheres.iter().map(|&i| bounds[i].unwrap().center()));
+        let extent = sphere_bounds.max - sphere_bounds.min;
+        let quantize = |v: Float, min: Float, extent: Float| {
+            if extent > 0.0 {
+                ((v - min) / extent * 1023.0) as u32
+            } else {
+                0
+            }
+        };
+        spheres.sort_by_key(|&i| {
+            let c = bounds[i].unwrap().center();
+            morton_code(
+                quantize(c.0, sphere_bounds.min.0, extent.0),
+                quantize(c.1, sphere_bounds.min.1, extent.1),
+                quantize(c.2, sphere_bounds.min.2, extent.2),
+            )
+        });
+        let sphere_packets: Vec<_> = spheres
+            .chunks(LANES)
+            .map(|chunk| {
+                let spheres: Vec<_> = chunk
+                    .iter()
+                    .map(|&i| as_sphere(objects[i].as_ref()).unwrap())
+                    .collect();
+                SpherePacket::new(&spheres)
+            })
+            .collect();
+
+        let accel = accel.build(
+            &sphere_packets
+                .iter()
+                .map(SpherePacket::bounds)
+                .chain(bounded.iter().map(|&i| bounds[i].unwrap()))
+                .collect::<Vec<_>>(),
+        );
+
+        // The objects keep the order they were given in, the primitives
+        // referring to them by handle
+        let objects: Arena<_> = objects.into_iter().collect();
+        let primitives = spheres
+            .iter()
+            .chain(&bounded)
+            .map(|&i| Handle::new(i))
+            .collect();
+        let unbounded = unbounded.into_iter().map(Handle::new).collect();
+        let emitters = objects
+            .iter()
+            .filter(|(_, object)| is_emitter(object.as_ref()))
+            .map(|(handle, _)| handle)
+            .collect();
+        let motion = objects
+            .iter()
+            .any(|(_, object)| (object.as_ref() as &dyn Any).is::<Moving>());
+
+        Self {
+            objects,
+            lights,
+            background: Background::default(),
+            camera: Camera::default(),
+            camera_path: None,
+            fog: None,
+            volumes: vec![],
+            caustics: None,
+            background_group: None,
+            portals: vec![],
+            irradiance_cache: None,
+            sphere_packets,
+            sphere_count: spheres.len(),
+            primitives,
+            accel,
+            unbounded,
+            emitters,
+            motion,
+        }
+    }
+
+    pub fn objects(&self) -> &Arena<Box<dyn Object>> {
+        &self.objects
+    }
+
+    /// Whether any of the objects move while the shutter is open.
+    pub fn has_motion(&self) -> bool {
+        self.motion
+    }
+
+    /// The objects that are sampled as lights: the emissive ones whose
+    /// surface can be sampled.
+    pub fn emitters(&self) -> impl Iterator<Item = &dyn Object> + '_ {
+        self.emitters
+            .iter()
+            .map(move |&handle| self.objects[handle].as_ref())
+    }
+
+    /// The objects the accelerator refers to as the primitive `index`: a
+    /// packet of spheres, or one of the other bounded objects.
+    fn primitive_objects(&self, index: usize) -> impl Iterator<Item = ObjectHandle> + '_ {
+        let packets = self.sphere_packets.len();
+        let objects = if index < packets {
+            index * LANES..((index + 1) * LANES).min(self.sphere_count)
+        } else {
+            let i = index - packets + self.sphere_count;
+            i..i + 1
+        };
+        self.primitives[objects].iter().copied()
+    }
+
+    /// Intersects the ray with the primitive the accelerator refers to by
+    /// `index`, returning the distance, normal and object of the hit.
+    fn intersect_primitive(
+        &self,
+        index: usize,
+        ray: &Ray,
+    ) -> Option<(Float, (Vec3f, ObjectHandle))> {
+        if let Some(packet) = self.sphere_packets.get(index) {
+            return packet.intersect(ray).map(|(distance, lane)| {
+                let n = (ray.at(distance) - packet.center(lane)).normalize();
+                (distance, (n, self.primitives[index * LANES + lane]))
+            });
+        }
+        let handle = self.primitive_objects(index).next()?;
+        let object = self.objects[handle].as_ref();
+        object
+            .ray_intersect(ray)
+            .filter(|(_, n)| !is_culled(object, n, &ray.dir))
+            .map(|(distance, n)| (distance, (n, handle)))
+    }
+
+    /// Visits the bounds of the nodes of the acceleration structure the ray
+    /// passes through, with how deep in it they are, and then the bounds of
+    /// the objects it passes through, without a depth, whatever it hits.
+    pub fn visit_bounds(&self, ray: &Ray, visit: &mut dyn FnMut(&Aabb, Option<usize>)) {
+        self.accel
+            .visit_nodes(ray, &mut |bounds, depth| visit(bounds, Some(depth)));
+        self.accel.traverse(ray, &mut |index| {
+            for handle in self.primitive_objects(index) {
+                if let Some(bounds) = self.objects[handle].bounds() {
+                    if bounds.intersect(&ray.origin, &ray.dir).is_some() {
+                        visit(&bounds, None);
+                    }
+                }
+            }
+            None
+        });
+    }
+
+    /// Tests the unbounded objects against the ray, up to the closest hit so
+    /// far, then turns the closest hit into the object, normal and point of
+    /// the hit.
+    fn finish_hit(
+        &self,
+        ray: &Ray,
+        mut closest: Option<(Float, (Vec3f, ObjectHandle))>,
+    ) -> Option<HitRecord<'_>> {
+        let mut bounded = *ray;
+        for &handle in &self.unbounded {
+            if let Some((distance, _)) = closest {
+                bounded.t_max = distance;
+            }
+            let object = self.objects[handle].as_ref();
+            if let Some((distance, n)) = object.ray_intersect(&bounded) {
+                if !is_culled(object, &n, &ray.dir) {
+                    closest = Some((distance, (n, handle)));
+                }
+            }
+        }
+
+        closest.map(|(distance, (n, handle))| {
+            let mut hit = HitRecord::new(self.objects[handle].as_ref(), ray, distance, &n);
+            hit.handle = Some(handle);
+            hit
+        })
+    }
+
+    /// Finds the closest hits of a packet of rays. Traversing coherent rays
+    /// together, such as those through neighbouring pixels, is cheaper than
+    /// tracing them one by one.
+    pub fn intersect_packet(&self, rays: &[Ray]) -> Vec<Option<HitRecord<'_>>> {
+        self.accel
+            .intersect_packet(rays, |i, ray| self.intersect_primitive(i, ray))
+            .into_iter()
+            .zip(rays)
+            .map(|(closest, ray)| self.finish_hit(ray, closest))
+            .collect()
+    }
+
+    /// The scene from the tinyraytracer tutorial.
+    pub fn tutorial() -> Self {
+        Self::tutorial_with_accelerator(AcceleratorKind::default())
+    }
+
+    pub fn tutorial_with_accelerator(accel: AcceleratorKind) -> Self {
+        let ivory = Material::preset("ivory").unwrap();
+        let red_rubber = Material::preset("red_rubber").unwrap();
+
+        let objects: Vec<Box<dyn Object>> = vec![
+            Box::new(Sphere::new(Vec3f::new(7., 5., -18.), 4.0, ivory.clone())),
+            Box::new(Sphere::new(Vec3f::new(-3.0, 0.0, -16.0), 2.0, ivory)),
+            Box::new(Sphere::new(
+                Vec3f::new(-1.0, -1.5, -12.),
+                2.0,
+                red_rubber.clone(),
+            )),
+            Box::new(Sphere::new(
+                Vec3f::new(1.5, -0.5, -18.),
+                3.0,
+                red_rubber.clone(),
+            )),
+            Box::new(Triangle::new(
+                Vec3f::new(-14.0, -6.0, -24.),
+                Vec3f::new(-7.0, -6.0, -22.),
+                Vec3f::new(-11.0, 3.0, -25.),
+                red_rubber,
+            )),
+        ];
+
+        let lights: Vec<Box<dyn Light>> = vec![
+            Box::new(PointLight::new(Vec3f::new(-20., 20., 20.), white(1.5))),
+            Box::new(PointLight::new(Vec3f::new(30., 50., -25.), white(1.8))),
+            Box::new(PointLight::new(Vec3f::new(30., 20., 30.), white(1.7))),
+        ];
+
+        Self::with_accelerator(objects, lights, accel)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> error::Result<Self> {
+        Self::load_with_accelerator(path, AcceleratorKind::default())
+    }
+
+    /// Loads a scene file, intersecting the scene and its meshes through the
+    /// given kind of acceleration structure.
+    pub fn load_with_accelerator<P: AsRef<Path>>(
+        path: P,
+        accel: AcceleratorKind,
+    ) -> error::Result<Self> {
+        Self::load_at_time(path, accel, 0.0)
+    }
+
+    /// Loads a scene file as it is at `time`, with its keyframed values
+    /// and its camera path there. Files ending in `.script` are run as
+    /// scene scripts.
+    pub fn load_at_time<P: AsRef<Path>>(
+        path: P,
+        accel: AcceleratorKind,
+        time: Float,
+    ) -> error::Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "script")
+        {
+            let root = script::run(&source, to_f64(time))?;
+            return Self::from_description(&root, base_dir, accel, time);
+        }
+        Self::parse_at_time(&source, base_dir, accel, time)
+    }
+
+    /// Parses a JSON scene description. Files referenced by the scene are
+    /// resolved relative to `base_dir`.
+    pub fn parse(source: &str, base_dir: &Path) -> error::Result<Self> {
+        Self::parse_with_accelerator(source, base_dir, AcceleratorKind::default())
+    }
+
+    pub fn parse_with_accelerator(
+        source: &str,
+        base_dir: &Path,
+        accel: AcceleratorKind,
+    ) -> error::Result<Self> {
+        Self::parse_at_time(source, base_dir, accel, 0.0)
+    }
+
+    pub fn parse_at_time(
+        source: &str,
+        base_dir: &Path,
+        accel: AcceleratorKind,
+        time: Float,
+    ) -> error::Result<Self> {
+        let root = json::parse(source).map_err(|e| Error::json("scene", e))?;
+        Self::from_description(&root, base_dir, accel, time)
+    }
+
+    /// The scene a description of one, parsed from JSON or built by a
+    /// script, describes as it is at `time`.
+    fn from_description(
+        root: &Value,
+        base_dir: &Path,
+        accel: AcceleratorKind,
+        time: Float,
+    ) -> error::Result<Self> {
+        let root = at_time(root, time);
+        let problems = check(&root, base_dir);
+        if !problems.is_empty() {
+            return Err(Error::Invalid(problems));
+        }
+
+        let mut materials = HashMap::new();
+        if let Some(table) = root.get("materials") {
+            let table = table
+                .as_object()
+                .ok_or_else(|| invalid("materials", "expected an object"))?;
+            for (name, value) in table {
+                let path = format!("materials.{}", name);
+                let material = object_material(value, &path, base_dir, &materials)?;
+                materials.insert(name.clone(), material);
+            }
+        }
+
+        let mut objects = vec![];
+        let mut meshes = MeshCache::new();
+        for (i, value) in optional_array(&root, "objects", "scene")?
+            .iter()
+            .enumerate()
+        {
+            objects.extend(parse_placed(
+                value,
+                &format!("objects[{}]", i),
+                base_dir,
+                &materials,
+                &mut meshes,
+                accel,
+                (None, None),
+            )?);
+        }
+
+        let mut lights = optional_array(&root, "lights", "scene")?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| parse_light(value, &format!("lights[{}]", i), base_dir))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let background_group = match root.get("background").and_then(|v| v.get("group")) {
+            Some(group) => Some(string(group, "background.group")?.to_string()),
+            None => None,
+        };
+        let background = match root.get("background") {
+            Some(value) => {
+                let (background, sun) = parse_background(value, "background", base_dir)?;
+                lights.extend(sun.map(|sun| in_group(Box::new(sun), background_group.clone())));
+                background
+            }
+            None => Background::default(),
+        };
+        let portals = optional_array(&root, "portals", "scene")?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| parse_portal(value, &format!("portals[{}]", i)))
+            .collect::<error::Result<Vec<_>>>()?;
+        let (camera, camera_path) = match root.get("camera") {
+            Some(value) => {
+                let camera = parse_camera(value, "camera")?;
+                let camera_path = parse_camera_path(value, "camera", &camera)?;
+                (camera, camera_path)
+            }
+            None => (Camera::default(), None),
+        };
+        let fog = match root.get("fog") {
+            Some(value) => Some(Fog {
+                color: vec3_field(value, "color", "fog")?,
+                density: number_field(value, "density", "fog")?,
+            }),
+            None => None,
+        };
+        let volumes = optional_array(&root, "volumes", "scene")?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| parse_volume(value, &format!("volumes[{}]", i)))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let mut scene = Self::with_accelerator(objects, lights, accel);
+        scene.background = background;
+        scene.background_group = background_group;
+        scene.portals = portals;
+        scene.camera = camera;
+        if let Some(path) = &camera_path {
+            path.apply(&mut scene.camera, time);
+        }
+        scene.camera_path = camera_path;
+        scene.fog = fog;
+        scene.volumes = volumes;
+        Ok(scene)
+    }
+}
+
+impl Hittable for Scene {
+    /// The closest hit of any of the objects within the ray's range.
+    fn hit(&self, ray: &Ray) -> Option<HitRecord<'_>> {
+        let closest = self
+            .accel
+            .intersect(ray, |i, ray| self.intersect_primitive(i, ray));
+        self.finish_hit(ray, closest)
+    }
+}
+
+/// The keyframes of a value, as `[time, value]` pairs, if it is keyframed.
+fn value_keyframes(value: &Value) -> Option<Vec<(Float, &Value)>> {
+    match value.as_object()? {
+        [(key, keyframes)] if key == "keyframes" => keyframes
+            .as_array()?
+            .iter()
+            .map(|pair| match pair.as_array()? {
+                [time, value] => Some((time.as_f64()? as Float, value)),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// The value between `a` and `b`, a fraction `t` of the way: numbers and
+/// arrays of them are interpolated, anything else is `a` until `b` is
+/// reached.
+fn interpolate(a: &Value, b: &Value, t: Float) -> Value {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Value::Number(a + (b - a) * to_f64(t)),
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            Value::Array(a.iter().zip(b).map(|(a, b)| interpolate(a, b, t)).collect())
+        }
+        _ if t < 1.0 => a.clone(),
+        _ => b.clone(),
+    }
+}
+
+/// The scene description as it is at `time`, with every keyframed value
+/// replaced by its value then.
+fn at_time(value: &Value, time: Float) -> Value {
+    if let Some(mut keyframes) = value_keyframes(value).filter(|k| !k.is_empty()) {
+        keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let i = keyframes.partition_point(|&(t, _)| t <= time);
+        let value = match (keyframes.get(i.wrapping_sub(1)), keyframes.get(i)) {
+            (Some(&(t0, a)), Some(&(t1, b))) => interpolate(a, b, (time - t0) / (t1 - t0)),
+            (Some(&(_, a)), None) | (None, Some(&(_, a))) => a.clone(),
+            (None, None) => unreachable!(),
+        };
+        return at_time(&value, time);
+    }
+    match value {
+        Value::Array(values) => Value::Array(values.iter().map(|v| at_time(v, time)).collect()),
+        Value::Object(members) => Value::Object(
+            members
+                .iter()
+                .map(|(key, v)| (key.clone(), at_time(v, time)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// The files the scene file at `path` refers to, such as meshes, textures
+/// and environment maps: the strings of its description that name a file
+/// relative to the scene, once each. Scene scripts are run as of `time` for
+/// the description they build. A scene that can't be read, parsed or run
+/// refers to none.
+pub fn referenced_files(path: &Path, time: Float) -> Vec<PathBuf> {
+    fn collect(value: &Value, base_dir: &Path, files: &mut Vec<PathBuf>) {
+        match value {
+            Value::String(s) => {
+                let file = base_dir.join(s);
+                if !s.is_empty() && file.is_file() && !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    collect(value, base_dir, files);
+                }
+            }
+            Value::Object(members) => {
+                for (_, value) in members {
+                    collect(value, base_dir, files);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_) => return vec![],
+    };
+    let root = if path.extension().is_some_and(|e| e == "script") {
+        script::run(&source, to_f64(time)).ok()
+    } else {
+        json::parse(&source).ok()
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut files = vec![];
+    if let Some(root) = root {
+        collect(&root, base_dir, &mut files);
+    }
+    files
+}
+
+/// A sphere, by its center and radius, or a box, by its corners, that a
+/// light can be hidden inside.
+enum Shape {
+    Sphere(Vec3f, Float),
+    Box(Vec3f, Vec3f),
+}
+
+/// A shape of the object at `path`, in the space of the object, with the
+/// transform into that space from the scene.
+struct Enclosure {
+    path: String,
+    to_object: Option<Mat4>,
+    shape: Shape,
+}
+
+impl Enclosure {
+    fn contains(&self, point: Vec3f) -> bool {
+        let point = self.to_object.map_or(point, |m| m.transform_point(&point));
+        match self.shape {
+            Shape::Sphere(center, radius) => (point - center).norm() < radius * radius,
+            Shape::Box(min, max) => {
+                min.0 < point.0
+                    && point.0 < max.0
+                    && min.1 < point.1
+                    && point.1 < max.1
+                    && min.2 < point.2
+                    && point.2 < max.2
+            }
+        }
+    }
+}
+
+/// Every problem of a scene description that would still load, but render
+/// wrongly: sizes that aren't positive, directions and camera `up` vectors
+/// of no length, point and spot lights hidden inside spheres and boxes,
+/// texture, mesh and environment files that aren't there, and numbers and
+/// transforms that aren't finite. Values of the wrong type are left for
+/// the parser to report.
+fn check(root: &Value, base_dir: &Path) -> Vec<Error> {
+    let mut problems = vec![];
+    check_finite(root, "", &mut problems);
+
+    if let Some(table) = root.get("materials").and_then(Value::as_object) {
+        for (name, value) in table {
+            check_material(
+                value,
+                &format!("materials.{}", name),
+                base_dir,
+                &mut problems,
+            );
+        }
+    }
+    let mut enclosures = vec![];
+    if let Some(objects) = root.get("objects").and_then(Value::as_array) {
+        for (i, value) in objects.iter().enumerate() {
+            let path = format!("objects[{}]", i);
+            check_object(value, &path, base_dir, None, &mut enclosures, &mut problems);
+        }
+    }
+
+    if let Some(camera) = root.get("camera") {
+        let position = camera.get("position").and_then(|v| vec3(v, "").ok());
+        let target = camera.get("target").and_then(|v| vec3(v, "").ok());
+        let position = position.unwrap_or_else(|| Vec3f::new(0.0, 0.0, 0.0));
+        let forward = target.unwrap_or(position + Vec3f::new(0.0, 0.0, -1.0)) - position;
+        if let Some(up) = camera.get("up").and_then(|v| vec3(v, "").ok()) {
+            if up.norm() == 0.0 {
+                problems.push(invalid("camera.up", "expected a vector of some length"));
+            } else if forward.cross(&up).norm() == 0.0 && forward.norm() > 0.0 {
+                problems.push(invalid(
+                    "camera.up",
+                    "points along the view, leaving which way is up undefined",
+                ));
+            }
+        }
+    }
+
+    if let Some(lights) = root.get("lights").and_then(Value::as_array) {
+        for (i, light) in lights.iter().enumerate() {
+            let path = format!("lights[{}]", i);
+            check_nonzero(light, "direction", &path, &mut problems);
+            if let Some(file) = light.get("ies") {
+                check_file(file, &format!("{}.ies", path), base_dir, &mut problems);
+            }
+            let position = light.get("position").and_then(|v| vec3(v, "").ok());
+            let enclosure = position.and_then(|p| enclosures.iter().find(|e| e.contains(p)));
+            if let Some(enclosure) = enclosure {
+                problems.push(invalid(
+                    &format!("{}.position", path),
+                    format!("inside {}, which hides the light", enclosure.path),
+                ));
+            }
+        }
+    }
+
+    if let Some(background) = root.get("background") {
+        if background.get("type").and_then(Value::as_str) == Some("environment") {
+            if let Some(file) = background.get("file") {
+                check_file(file, "background.file", base_dir, &mut problems);
+            }
+        }
+    }
+    problems
+}
+
+/// Reports the numbers within `value` that are infinite, which JSON can
+/// only give as numbers too large to hold.
+fn check_finite(value: &Value, path: &str, problems: &mut Vec<Error>) {
+    match value {
+        Value::Number(n) if !n.is_finite() => {
+            problems.push(invalid(path, "expected a finite number"))
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                check_finite(value, &format!("{}[{}]", path, i), problems);
+            }
+        }
+        Value::Object(members) => {
+            for (key, value) in members {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                check_finite(value, &path, problems);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks an object, and the objects of a group, placed by the transform
+/// into their space `outer` of the groups around them, keeping the spheres
+/// and boxes among them that lights could be hidden inside.
+fn check_object(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+    outer: Option<Mat4>,
+    enclosures: &mut Vec<Enclosure>,
+    problems: &mut Vec<Error>,
+) {
+    let mut to_object = outer;
+    if let Some(transform) = value.get("transform") {
+        let transform_path = format!("{}.transform", path);
+        if let Ok((to_world, inverse)) = parse_transform(transform, &transform_path) {
+            let finite = |m: &Mat4| m.0.iter().flatten().all(|n| n.is_finite());
+            // Unless its numbers are infinite already
+            let reported = problems.iter().any(|problem| match problem {
+                Error::Parse {
+                    field: Some(field), ..
+                } => field.starts_with(&transform_path),
+                _ => false,
+            });
+            if !(finite(&to_world) && finite(&inverse) || reported) {
+                problems.push(invalid(&transform_path, "expected a finite transform"));
+            }
+            to_object = Some(outer.map_or(inverse, |outer| inverse * outer));
+        }
+    }
+    if let Some(material) = value.get("material") {
+        check_material(material, &format!("{}.material", path), base_dir, problems);
+    }
+
+    let vector = |key: &str| value.get(key).and_then(|v| vec3(v, "").ok());
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    match kind {
+        "group" => {
+            if let Some(children) = value.get("children").and_then(Value::as_array) {
+                for (i, child) in children.iter().enumerate() {
+                    let path = format!("{}.children[{}]", path, i);
+                    check_object(child, &path, base_dir, to_object, enclosures, problems);
+                }
+            }
+        }
+        "sphere" => {
+            check_positive(value, "radius", path, problems);
+            let radius = value.get("radius").and_then(Value::as_f64);
+            if let (Some(center), Some(radius)) = (vector("center"), radius) {
+                enclosures.push(Enclosure {
+                    path: path.to_string(),
+                    to_object,
+                    shape: Shape::Sphere(center, radius as Float),
+                });
+            }
+        }
+        "box" => {
+            if let (Some(min), Some(max)) = (vector("min"), vector("max")) {
+                if min.0 >= max.0 || min.1 >= max.1 || min.2 >= max.2 {
+                    problems.push(invalid(path, "expected `max` beyond `min` on every axis"));
+                } else {
+                    enclosures.push(Enclosure {
+                        path: path.to_string(),
+                        to_object,
+                        shape: Shape::Box(min, max),
+                    });
+                }
+            }
+        }
+        "disk" => {
+            check_positive(value, "radius", path, problems);
+            check_nonzero(value, "normal", path, problems);
+        }
+        "cylinder" | "cone" => {
+            check_positive(value, "radius", path, problems);
+            check_positive(value, "height", path, problems);
+            check_nonzero(value, "axis", path, problems);
+        }
+        "torus" => {
+            check_positive(value, "major_radius", path, problems);
+            check_positive(value, "minor_radius", path, problems);
+            check_nonzero(value, "axis", path, problems);
+        }
+        "quad" => {
+            if let (Some(u), Some(v)) = (vector("u"), vector("v")) {
+                if u.cross(&v).norm() == 0.0 {
+                    problems.push(invalid(path, "`u` and `v` leave the quad no area"));
+                }
+            }
+        }
+        "triangle" => {
+            let vertices = value.get("vertices").and_then(|v| v.as_array());
+            let vertices: Option<Vec<_>> = vertices
+                .map(|vertices| vertices.iter().map(|v| vec3(v, "").ok()).collect())
+                .unwrap_or_default();
+            if let Some([a, b, c]) = vertices.as_deref() {
+                if (*b - *a).cross(&(*c - *a)).norm() == 0.0 {
+                    problems.push(invalid(path, "the `vertices` leave the triangle no area"));
+                }
+            }
+        }
+        "csg" => {
+            // The inside of an operand isn't the inside of the operation
+            for key in &["left", "right"] {
+                if let Some(operand) = value.get(key) {
+                    let path = format!("{}.{}", path, key);
+                    check_object(operand, &path, base_dir, None, &mut vec![], problems);
+                }
+            }
+        }
+        "sdf" => {
+            if let Some(shape) = value.get("shape") {
+                check_sdf(shape, &format!("{}.shape", path), problems);
+            }
+        }
+        "metaballs" => {
+            check_positive(value, "threshold", path, problems);
+            if let Some(balls) = value.get("balls").and_then(Value::as_array) {
+                for (i, ball) in balls.iter().enumerate() {
+                    let path = format!("{}.balls[{}]", path, i);
+                    check_positive(ball, "radius", &path, problems);
+                }
+            }
+        }
+        "mesh" | "heightfield" => {
+            if let Some(file) = value.get("file") {
+                check_file(file, &format!("{}.file", path), base_dir, problems);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_sdf(value: &Value, path: &str, problems: &mut Vec<Error>) {
+    for key in &["radius", "major_radius", "minor_radius"] {
+        check_positive(value, key, path, problems);
+    }
+    if let Some(children) = value.get("children").and_then(Value::as_array) {
+        for (i, child) in children.iter().enumerate() {
+            check_sdf(child, &format!("{}.children[{}]", path, i), problems);
+        }
+    }
+    for key in &["left", "right"] {
+        if let Some(operand) = value.get(key) {
+            check_sdf(operand, &format!("{}.{}", path, key), problems);
+        }
+    }
+}
+
+/// Checks that the image files of a material's textures are there.
+fn check_material(value: &Value, path: &str, base_dir: &Path, problems: &mut Vec<Error>) {
+    if value.as_object().is_none() {
+        return;
+    }
+    let keys = [
+        "diffuse_color",
+        "base_color",
+        "albedo",
+        "specular_exponent",
+        "normal_map",
+        "bump_map",
+    ];
+    for key in &keys {
+        let texture = match value.get(key) {
+            Some(texture) => texture,
+            None => continue,
+        };
+        let path = format!("{}.{}", path, key);
+        if texture.get("type").and_then(Value::as_str) == Some("image") {
+            if let Some(file) = texture.get("file") {
+                check_file(file, &format!("{}.file", path), base_dir, problems);
+            }
+        } else {
+            check_file(texture, &path, base_dir, problems);
+        }
+    }
+}
+
+/// Reports the file named by `value` relative to `base_dir` if it isn't
+/// there.
+fn check_file(value: &Value, path: &str, base_dir: &Path, problems: &mut Vec<Error>) {
+    if let Some(file) = value.as_str() {
+        if !files::is_file(&base_dir.join(file)) {
+            problems.push(invalid(path, format!("no file `{}`", file)));
+        }
+    }
+}
+
+/// Reports the number at `key` if it isn't above zero.
+fn check_positive(value: &Value, key: &str, path: &str, problems: &mut Vec<Error>) {
+    if let Some(n) = value.get(key).and_then(Value::as_f64) {
+        if n <= 0.0 {
+            problems.push(invalid(
+                &format!("{}.{}", path, key),
+                format!("expected a positive number, not {}", n),
+            ));
+        }
+    }
+}
+
+/// Reports the vector at `key` if it has no length, and so no direction.
+fn check_nonzero(value: &Value, key: &str, path: &str, problems: &mut Vec<Error>) {
+    let path = format!("{}.{}", path, key);
+    if let Some(Ok(v)) = value.get(key).map(|v| vec3(v, &path)) {
+        if v.norm() == 0.0 {
+            problems.push(invalid(&path, "expected a vector of some length"));
+        }
+    }
+}
+
+fn invalid(field: &str, message: impl Into<String>) -> Error {
+    Error::in_field("scene", field, message)
+}
+
+fn field<'a>(value: &'a Value, key: &str, path: &str) -> error::Result<&'a Value> {
+    value
+        .get(key)
+        .ok_or_else(|| invalid(path, format!("missing field `{}`", key)))
+}
+
+fn optional_array<'a>(value: &'a Value, key: &str, path: &str) -> error::Result<&'a [Value]> {
+    match value.get(key) {
+        None => Ok(&[]),
+        Some(array) => array
+            .as_array()
+            .ok_or_else(|| invalid(&format!("{}.{}", path, key), "expected an array")),
+    }
+}
+
+fn number(value: &Value, path: &str) -> error::Result<Float> {
+    value
+        .as_f64()
+        .map(|n| n as Float)
+        .ok_or_else(|| invalid(path, "expected a number"))
+}
+
+fn string<'a>(value: &'a Value, path: &str) -> error::Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| invalid(path, "expected a string"))
+}
+
+fn numbers(value: &Value, len: usize, path: &str) -> error::Result<Vec<Float>> {
+    match value.as_array() {
+        Some(values) if values.len() == len => values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| number(v, &format!("{}[{}]", path, i)))
+            .collect(),
+        _ => Err(invalid(
+            path,
+            format!("expected an array of {} numbers", len),
+        )),
+    }
+}
+
+fn vec3(value: &Value, path: &str) -> error::Result<Vec3f> {
+    let n = numbers(value, 3, path)?;
+    Ok(Vec3f::new(n[0], n[1], n[2]))
+}
+
+/// A gray of the given brightness.
+fn white(intensity: Float) -> Vec3f {
+    Vec3f::new(intensity, intensity, intensity)
+}
+
+/// A color, or a number for a gray of that brightness.
+fn gray_or_color(value: &Value, path: &str) -> error::Result<Vec3f> {
+    match value.as_f64() {
+        Some(n) => Ok(white(n as Float)),
+        None => vec3(value, path),
+    }
+}
+
+fn vec2(value: &Value, path: &str) -> error::Result<Vec2f> {
+    let n = numbers(value, 2, path)?;
+    Ok(Vec2f::new(n[0], n[1]))
+}
+
+/// The material of an object: the name of one of the scene's `materials`
+/// or of a preset, or a material of its own.
+fn object_material(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+    materials: &HashMap<String, Material>,
+) -> error::Result<Material> {
+    match value.as_str() {
+        Some(name) => materials
+            .get(name)
+            .cloned()
+            .or_else(|| Material::preset(name))
+            .ok_or_else(|| invalid(path, format!("unknown material `{}`", name))),
+        None => parse_material(value, path, base_dir),
+    }
+}
+
+fn parse_material(value: &Value, path: &str, base_dir: &Path) -> error::Result<Material> {
+    if value.as_object().is_none() {
+        return Err(invalid(path, "expected an object"));
+    }
+
+    let mut material = Material::default();
+    if let Some(v) = value.get("refractive_index") {
+        let index_path = format!("{}.refractive_index", path);
+        let refractive_index = number(v, &index_path)?;
+        if refractive_index < 1.0 {
+            return Err(invalid(
+                &index_path,
+                format!(
+                    "expected a refractive index of 1 or more, not {}",
+                    refractive_index
+                ),
+            ));
+        }
+        if value.get("metallic").is_some() || value.get("roughness").is_some() {
+            return Err(invalid(
+                &index_path,
+                "glass has no `metallic` or `roughness`",
+            ));
+        }
+        // Without a say otherwise, glass is clear but for its highlights
+        let preset = Material::preset("glass").unwrap();
+        material.albedo = preset.albedo;
+        material.specular_exponent = preset.specular_exponent;
+        let absorption = match value.get("absorption") {
+            Some(v) => {
+                let path = format!("{}.absorption", path);
+                let distance = number_field(v, "distance", &path)?;
+                if distance <= 0.0 {
+                    return Err(invalid(
+                        &format!("{}.distance", path),
+                        format!("expected a positive distance, not {}", distance),
+                    ));
+                }
+                Some(Absorption {
+                    color: vec3_field(v, "color", &path)?,
+                    distance,
+                })
+            }
+            None => None,
+        };
+        let abbe_number = match value.get("abbe_number") {
+            Some(v) => {
+                let path = format!("{}.abbe_number", path);
+                let abbe_number = number(v, &path)?;
+                if abbe_number <= 0.0 {
+                    return Err(invalid(
+                        &path,
+                        format!("expected a positive Abbe number, not {}", abbe_number),
+                    ));
+                }
+                Some(abbe_number)
+            }
+            None => None,
+        };
+        material.glass = Some(Glass {
+            refractive_index,
+            absorption,
+            abbe_number,
+        });
+    }
+    if value.get("metallic").is_some() || value.get("roughness").is_some() {
+        let optional_number = |key: &str, default: Float| match value.get(key) {
+            Some(v) => number(v, &format!("{}.{}", path, key)),
+            None => Ok(default),
+        };
+        material.metallic_roughness = Some(MetallicRoughness {
+            metallic: optional_number("metallic", 0.0)?,
+            roughness: optional_number("roughness", 0.5)?,
+        });
+    }
+    let diffuse_color = ["diffuse_color", "base_color"]
+        .iter()
+        .find_map(|&key| value.get(key).map(|v| (key, v)));
+    if let Some((key, v)) = diffuse_color {
+        let path = format!("{}.{}", path, key);
+        match v.as_array() {
+            Some(_) => material.diffuse_color = vec3(v, &path)?,
+            None => material.diffuse_texture = Some(parse_texture(v, &path, base_dir, false)?),
+        }
+    }
+    if let Some(v) = value.get("albedo") {
+        let path = format!("{}.albedo", path);
+        match v.as_array() {
+            Some(_) => material.albedo = vec2(v, &path)?,
+            None => material.albedo_texture = Some(parse_texture(v, &path, base_dir, true)?),
+        }
+    }
+    if let Some(v) = value.get("specular_exponent") {
+        let path = format!("{}.specular_exponent", path);
+        match v.as_f64() {
+            Some(_) => material.specular_exponent = number(v, &path)?,
+            None => {
+                material.specular_exponent_texture = Some(parse_texture(v, &path, base_dir, true)?)
+            }
+        }
+    }
+    if let Some(v) = value.get("emission") {
+        material.emission = vec3(v, &format!("{}.emission", path))?;
+    }
+    if let Some(v) = value.get("luminance") {
+        let nits = number(v, &format!("{}.luminance", path))?;
+        let color = match value.get("emission") {
+            Some(_) => normalized_tint(material.emission, &format!("{}.emission", path))?,
+            None => white(1.0),
+        };
+        material.emission = color * (nits / LUMENS_PER_WATT);
+    }
+    if let Some(v) = value.get("light_group") {
+        material.light_group = Some(string(v, &format!("{}.light_group", path))?.into());
+    }
+    if let Some(v) = value.get("subsurface") {
+        let path = format!("{}.subsurface", path);
+        material.subsurface = Some(Subsurface {
+            color: vec3_field(v, "color", &path)?,
+            radius: number_field(v, "radius", &path)?,
+        });
+    }
+    if let Some(v) = value.get("normal_map") {
+        let path = format!("{}.normal_map", path);
+        material.normal_map = Some(parse_texture(v, &path, base_dir, true)?);
+    }
+    if let Some(v) = value.get("bump_map") {
+        let path = format!("{}.bump_map", path);
+        material.bump_map = Some(parse_texture(v, &path, base_dir, true)?);
+    }
+    if let Some(v) = value.get("bump_strength") {
+        material.bump_strength = number(v, &format!("{}.bump_strength", path))?;
+    }
+    if let Some(v) = value.get("back_face") {
+        let path = format!("{}.back_face", path);
+        material.back_face = match v.as_str() {
+            Some("shade") => BackFace::Shade,
+            Some("cull") => BackFace::Cull,
+            Some("flip") => BackFace::Flip,
+            Some(other) => {
+                return Err(invalid(
+                    &path,
+                    format!(
+                        "unknown back face `{}`, expected shade, cull, flip or a color",
+                        other
+                    ),
+                ))
+            }
+            None => BackFace::Color(vec3(v, &path)?),
+        };
+    }
+    Ok(material)
+}
+
+/// A texture: the file name of an image, or an object of some `type`.
+/// Images of `data`, such as normals and heights, are read as they are
+/// rather than as colors.
+fn parse_texture(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+    data: bool,
+) -> error::Result<Arc<dyn Texture>> {
+    let image = |file: &str, path: &str| -> error::Result<Arc<dyn Texture>> {
+        let file_path = base_dir.join(file);
+        let image = if data {
+            input::read_data(file_path)
+        } else {
+            input::read(file_path)
+        }
+        .map_err(|e| invalid(path, format!("{}: {}", file, e)))?;
+        Ok(Arc::new(ImageTexture::new(image)))
+    };
+    if let Some(file) = value.as_str() {
+        return image(file, path);
+    }
+    let optional_number = |key: &str, default: Float| match value.get(key) {
+        Some(v) => number(v, &format!("{}.{}", path, key)),
+        None => Ok(default),
+    };
+    let colors = match value.get("colors") {
+        Some(colors) => {
+            let colors_path = format!("{}.colors", path);
+            let colors = colors
+                .as_array()
+                .filter(|colors| colors.len() == 2)
+                .ok_or_else(|| invalid(&colors_path, "expected two colors"))?;
+            [
+                texture_color(&colors[0], &format!("{}[0]", colors_path))?,
+                texture_color(&colors[1], &format!("{}[1]", colors_path))?,
+            ]
+        }
+        None => [white(0.9), white(0.1)],
+    };
+    let axis = match value.get("axis") {
+        Some(v) => vec3(v, &format!("{}.axis", path))?.normalize(),
+        None => Vec3f::new(1.0, 0.0, 0.0),
+    };
+    let perlin =
+        || -> error::Result<Perlin> { Ok(Perlin::new(optional_number("seed", 0.0)? as u32)) };
+    Ok(match string_field(value, "type", path)? {
+        "image" => {
+            let file_path = format!("{}.file", path);
+            return image(string_field(value, "file", path)?, &file_path);
+        }
+        "checker" => Arc::new(Checker {
+            size: optional_number("size", 1.0)?,
+            colors,
+        }),
+        "stripes" => Arc::new(Stripes {
+            width: optional_number("width", 1.0)?,
+            axis,
+            colors,
+        }),
+        "noise" => Arc::new(Noise {
+            scale: optional_number("scale", 1.0)?,
+            colors,
+            perlin: perlin()?,
+        }),
+        "marble" => Arc::new(Marble {
+            scale: optional_number("scale", 1.0)?,
+            turbulence: optional_number("turbulence", 5.0)?,
+            axis,
+            colors,
+            perlin: perlin()?,
+        }),
+        kind => {
+            return Err(invalid(
+                &format!("{}.type", path),
+                format!("unknown texture type `{}`", kind),
+            ))
+        }
+    })
+}
+
+/// A color of a pattern, which may also be a single number, for a gray or
+/// a specular exponent, or a pair of numbers, for an albedo.
+fn texture_color(value: &Value, path: &str) -> error::Result<Vec3f> {
+    if let Some(n) = value.as_f64() {
+        return Ok(white(n as Float));
+    }
+    match value.as_array().map(|values| values.len()) {
+        Some(2) => {
+            let n = vec2(value, path)?;
+            Ok(Vec3f::new(n.0, n.1, 0.0))
+        }
+        _ => vec3(value, path),
+    }
+}
+
+fn vec3_field(value: &Value, key: &str, path: &str) -> error::Result<Vec3f> {
+    vec3(field(value, key, path)?, &format!("{}.{}", path, key))
+}
+
+fn number_field(value: &Value, key: &str, path: &str) -> error::Result<Float> {
+    number(field(value, key, path)?, &format!("{}.{}", path, key))
+}
+
+fn string_field<'a>(value: &'a Value, key: &str, path: &str) -> error::Result<&'a str> {
+    string(field(value, key, path)?, &format!("{}.{}", path, key))
+}
+
+/// Object types with a well defined inside, which can take part in CSG.
+const SOLIDS: &[&str] = &["sphere", "box", "cylinder", "cone", "torus", "csg"];
+
+/// Parses an object, or the objects of a group and of the groups within it,
+/// placed in the scene by their transforms after the transform of the
+/// groups around them, and moving by their velocities on top of the
+/// groups', both given by `outer`.
+fn parse_placed(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+    materials: &HashMap<String, Material>,
+    meshes: &mut MeshCache,
+    accel: AcceleratorKind,
+    outer: (Option<(Mat4, Mat4)>, Option<Vec3f>),
+) -> error::Result<Vec<Box<dyn Object>>> {
+    let transform = match value.get("transform") {
+        Some(transform) => {
+            let (to_world, to_object) = parse_transform(transform, &format!("{}.transform", path))?;
+            Some(match outer.0 {
+                Some((outer_to_world, outer_to_object)) => {
+                    (outer_to_world * to_world, to_object * outer_to_object)
+                }
+                None => (to_world, to_object),
+            })
+        }
+        None => outer.0,
+    };
+    let velocity = match value.get("velocity") {
+        Some(velocity) => {
+            let velocity = vec3(velocity, &format!("{}.velocity", path))?;
+            Some(outer.1.map_or(velocity, |outer| outer + velocity))
+        }
+        None => outer.1,
+    };
+
+    if string_field(value, "type", path)? == "group" {
+        let mut objects = vec![];
+        for (i, child) in optional_array(value, "children", path)?.iter().enumerate() {
+            objects.extend(parse_placed(
+                child,
+                &format!("{}.children[{}]", path, i),
+                base_dir,
+                materials,
+                meshes,
+                accel,
+                (transform, velocity),
+            )?);
+        }
+        return Ok(objects);
+    }
+
+    let parsed = parse_object(value, path, base_dir, materials, meshes, accel)?;
+    let parsed: Vec<_> = parsed
+        .into_iter()
+        .map(|object| match transform {
+            Some((to_world, to_object)) => {
+                Box::new(Transformed::new(object, to_world, to_object)) as Box<dyn Object>
+            }
+            None => object,
+        })
+        .collect();
+    let parsed = match light_amount(value, path, ("power", "lumens"))? {
+        Some(power) => emitting(parsed, power, path)?,
+        None => parsed,
+    };
+    Ok(parsed
+        .into_iter()
+        .map(|object| match velocity {
+            Some(velocity) => Box::new(Moving { object, velocity }),
+            None => object,
+        })
+        .collect())
+}
+
+/// The objects giving off `power` watts between them, from the side their
+/// normals face, spread evenly over their area, in the colors of the
+/// emission of their materials, or white if they have none.
+fn emitting(
+    objects: Vec<Box<dyn Object>>,
+    power: Float,
+    path: &str,
+) -> error::Result<Vec<Box<dyn Object>>> {
+    let area = objects
+        .iter()
+        .map(|object| object.area())
+        .sum::<Option<Float>>()
+        .filter(|&area| area > 0.0)
+        .ok_or_else(|| invalid(path, "`power` needs an object whose area is known"))?;
+    // A surface giving off the same light in every direction sends π times
+    // it out of each unit of its area
+    let radiance = power / (PI * area);
+    objects
+        .into_iter()
+        .map(|object| {
+            let mut material = object.material().clone();
+            let color = match luminance(&material.emission) {
+                brightness if brightness > 0.0 => material.emission * (1.0 / brightness),
+                _ => white(1.0),
+            };
+            material.emission = color * radiance;
+            Ok(Box::new(Emitting { object, material }) as Box<dyn Object>)
+        })
+        .collect()
+}
+
+/// The meshes of the files loaded so far, keyed by the path of the file,
+/// whether its materials are replaced and how it is shaded.
+type MeshCache = HashMap<(PathBuf, bool, Option<bool>), Vec<Arc<dyn Object>>>;
+
+fn parse_object(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+    materials: &HashMap<String, Material>,
+    meshes: &mut MeshCache,
+    accel: AcceleratorKind,
+) -> error::Result<Vec<Box<dyn Object>>> {
+    let kind = string_field(value, "type", path)?;
+    if SOLIDS.contains(&kind) {
+        return Ok(vec![
+            parse_solid(value, path, base_dir, materials, None)? as Box<dyn Object>
+        ]);
+    }
+
+    let material_path = format!("{}.material", path);
+    let material = || {
+        object_material(
+            field(value, "material", path)?,
+            &material_path,
+            base_dir,
+            materials,
+        )
+    };
+
+    let object: Box<dyn Object> = match kind {
+        "triangle" => {
+            let vertices_path = format!("{}.vertices", path);
+            let vertices = field(value, "vertices", path)?
+                .as_array()
+                .filter(|vertices| vertices.len() == 3)
+                .ok_or_else(|| invalid(&vertices_path, "expected three vertices"))?;
+            let mut triangle = Triangle::new(
+                vec3(&vertices[0], &format!("{}[0]", vertices_path))?,
+                vec3(&vertices[1], &format!("{}[1]", vertices_path))?,
+                vec3(&vertices[2], &format!("{}[2]", vertices_path))?,
+                material()?,
+            );
+            if let Some(uvs) = value.get("uvs") {
+                let uvs_path = format!("{}.uvs", path);
+                let uvs = uvs
+                    .as_array()
+                    .filter(|uvs| uvs.len() == 3)
+                    .ok_or_else(|| invalid(&uvs_path, "expected three pairs"))?;
+                triangle = triangle.with_texture_coordinates([
+                    vec2(&uvs[0], &format!("{}[0]", uvs_path))?,
+                    vec2(&uvs[1], &format!("{}[1]", uvs_path))?,
+                    vec2(&uvs[2], &format!("{}[2]", uvs_path))?,
+                ]);
+            }
+            Box::new(triangle)
+        }
+        "quad" => Box::new(Quad::new(
+            vec3_field(value, "corner", path)?,
+            vec3_field(value, "u", path)?,
+            vec3_field(value, "v", path)?,
+            material()?,
+        )),
+        "disk" => Box::new(Disk::new(
+            vec3_field(value, "center", path)?,
+            vec3_field(value, "normal", path)?,
+            number_field(value, "radius", path)?,
+            material()?,
+        )),
+        "sdf" => {
+            let sdf = parse_sdf(field(value, "shape", path)?, &format!("{}.shape", path))?;
+            let mut object = SdfObject::new(sdf, material()?);
+            if value.get("max_distance").is_some() {
+                object = object.with_max_distance(number_field(value, "max_distance", path)?);
+            }
+            Box::new(object)
+        }
+        "metaballs" => {
+            let balls_path = format!("{}.balls", path);
+            let balls = field(value, "balls", path)?
+                .as_array()
+                .filter(|balls| !balls.is_empty())
+                .ok_or_else(|| invalid(&balls_path, "expected an array of balls"))?
+                .iter()
+                .enumerate()
+                .map(|(i, ball)| {
+                    let path = format!("{}[{}]", balls_path, i);
+                    Ok(Metaball {
+                        center: vec3_field(ball, "center", &path)?,
+                        radius: number_field(ball, "radius", &path)?,
+                        strength: match ball.get("strength") {
+                            Some(_) => number_field(ball, "strength", &path)?,
+                            None => 1.0,
+                        },
+                    })
+                })
+                .collect::<error::Result<Vec<_>>>()?;
+            let threshold = match value.get("threshold") {
+                Some(_) => number_field(value, "threshold", path)?,
+                None => 0.5,
+            };
+            Box::new(Metaballs::new(balls, threshold, material()?))
+        }
+        "heightfield" => {
+            let file = string_field(value, "file", path)?;
+            let file_path = format!("{}.file", path);
+            let image = input::read_data(base_dir.join(file))
+                .map_err(|e| invalid(&file_path, format!("{}: {}", file, e)))?;
+            let size = match value.get("size") {
+                Some(size) => vec2(size, &format!("{}.size", path))?,
+                None => Vec2f::new(1.0, 1.0),
+            };
+            let height = match value.get("height") {
+                Some(_) => number_field(value, "height", path)?,
+                None => 1.0,
+            };
+            let heightfield =
+                mesh::Mesh::heightfield(&image, (size.0, size.1), height, material()?, accel);
+            Box::new(
+                heightfield
+                    .ok_or_else(|| invalid(&file_path, "expected at least 2 by 2 pixels"))?,
+            )
+        }
+        "mesh" => {
+            let file = string_field(value, "file", path)?;
+            let material = match value.get("material") {
+                Some(material) => Some(object_material(
+                    material,
+                    &material_path,
+                    base_dir,
+                    materials,
+                )?),
+                None => None,
+            };
+            let smooth = match value.get("smooth") {
+                Some(smooth) => Some(smooth.as_bool().ok_or_else(|| {
+                    invalid(&format!("{}.smooth", path), "expected true or false")
+                })?),
+                None => None,
+            };
+            // Each file is loaded once, and its meshes are shared by every
+            // object using it, each in its own material
+            let key = (base_dir.join(file), material.is_some(), smooth);
+            let shared = match meshes.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let replaced = material.as_ref().map(|_| Material::default());
+                    let loaded =
+                        mesh::load(&entry.key().0, replaced, smooth, accel).map_err(|e| {
+                            invalid(&format!("{}.file", path), format!("{}: {}", file, e))
+                        })?;
+                    entry.insert(
+                        loaded
+                            .into_iter()
+                            .map(|mesh| Arc::new(mesh) as Arc<dyn Object>)
+                            .collect(),
+                    )
+                }
+            };
+            return Ok(shared
+                .iter()
+                .map(|mesh| {
+                    Box::new(Instance {
+                        object: mesh.clone(),
+                        material: material.clone(),
+                    }) as Box<dyn Object>
+                })
+                .collect());
+        }
+        _ => {
+            return Err(invalid(
+                &format!("{}.type", path),
+                format!("unknown object type `{}`", kind),
+            ))
+        }
+    };
+    Ok(vec![object])
+}
+
+/// The transform of an object, along with its inverse: its `scale`, a
+/// number or one per axis, then its `rotate`, degrees about X, Y and Z in
+/// that order, then its `translate`, or a `matrix` of four rows instead.
+fn parse_transform(value: &Value, path: &str) -> error::Result<(Mat4, Mat4)> {
+    if let Some(matrix) = value.get("matrix") {
+        if ["scale", "rotate", "translate"]
+            .iter()
+            .any(|key| value.get(key).is_some())
+        {
+            return Err(invalid(
+                path,
+                "expected either a `matrix` or `scale`, `rotate` and `translate`",
+            ));
+        }
+        let matrix_path = format!("{}.matrix", path);
+        let rows = match matrix.as_array() {
+            Some(rows) if rows.len() == 4 => rows,
+            _ => return Err(invalid(&matrix_path, "expected four rows")),
+        };
+        let mut to_world = Mat4::identity();
+        for (r, row) in rows.iter().enumerate() {
+            let values = numbers(row, 4, &format!("{}[{}]", matrix_path, r))?;
+            to_world.0[r].copy_from_slice(&values);
+        }
+        // Nothing is seen in perspective within the scene
+        to_world.0[3] = [0.0, 0.0, 0.0, 1.0];
+        let to_object = to_world
+            .inverse()
+            .ok_or_else(|| invalid(&matrix_path, "expected an invertible matrix"))?;
+        return Ok((to_world, to_object));
+    }
+    let mut to_world = Mat4::identity();
+    let mut to_object = Mat4::identity();
+    if let Some(scale) = value.get("scale") {
+        let scale_path = format!("{}.scale", path);
+        let factors = gray_or_color(scale, &scale_path)?;
+        if factors.0 == 0.0 || factors.1 == 0.0 || factors.2 == 0.0 {
+            return Err(invalid(&scale_path, "expected nonzero factors"));
+        }
+        to_world = Mat4::scaling(factors);
+        to_object = Mat4::scaling(Vec3f::new(
+            1.0 / factors.0,
+            1.0 / factors.1,
+            1.0 / factors.2,
+        ));
+    }
+    if value.get("rotate").is_some() {
+        let angles = vec3_field(value, "rotate", path)?;
+        let axes = [
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        ];
+        for (axis, angle) in axes.iter().zip([angles.0, angles.1, angles.2]) {
+            let angle = angle.to_radians();
+            to_world = Mat4::rotation(*axis, angle) * to_world;
+            to_object = to_object * Mat4::rotation(*axis, -angle);
+        }
+    }
+    if value.get("translate").is_some() {
+        let offset = vec3_field(value, "translate", path)?;
+        to_world = Mat4::translation(offset) * to_world;
+        to_object = to_object * Mat4::translation(-offset);
+    }
+    Ok((to_world, to_object))
+}
+
+/// Parses a solid object. Solids nested in a CSG operation may leave out
+/// their material and use the `inherited` one of the operation instead.
+fn parse_solid(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+    materials: &HashMap<String, Material>,
+    inherited: Option<&Material>,
+) -> error::Result<Box<dyn Solid>> {
+    let kind = string_field(value, "type", path)?;
+    let material_path = format!("{}.material", path);
+    let material = || match (value.get("material"), inherited) {
+        (Some(material), _) => object_material(material, &material_path, base_dir, materials),
+        (None, Some(material)) => Ok(material.clone()),
+        (None, None) => Err(invalid(path, "missing field `material`")),
+    };
+
+    let solid: Box<dyn Solid> = match kind {
+        "sphere" => Box::new(Sphere::new(
+            vec3_field(value, "center", path)?,
+            number_field(value, "radius", path)?,
+            material()?,
+        )),
+        "box" => Box::new(Cuboid::new(
+            vec3_field(value, "min", path)?,
+            vec3_field(value, "max", path)?,
+            material()?,
+        )),
+        "cylinder" | "cone" => {
+            let base = vec3_field(value, "base", path)?;
+            let axis = vec3_field(value, "axis", path)?;
+            let height = number_field(value, "height", path)?;
+            let radius = number_field(value, "radius", path)?;
+            if kind == "cylinder" {
+                Box::new(Cylinder::new(base, axis, height, radius, material()?))
+            } else {
+                Box::new(Cone::new(base, axis, height, radius, material()?))
+            }
+        }
+        "torus" => Box::new(Torus::new(
+            vec3_field(value, "center", path)?,
+            vec3_field(value, "axis", path)?,
+            number_field(value, "major_radius", path)?,
+            number_field(value, "minor_radius", path)?,
+            material()?,
+        )),
+        "csg" => {
+            let operation = match string_field(value, "operation", path)? {
+                "union" => CsgOperation::Union,
+                "intersection" => CsgOperation::Intersection,
+                "difference" => CsgOperation::Difference,
+                operation => {
+                    return Err(invalid(
+                        &format!("{}.operation", path),
+                        format!("unknown operation `{}`", operation),
+                    ))
+                }
+            };
+            let material = material()?;
+            let left_path = format!("{}.left", path);
+            let right_path = format!("{}.right", path);
+            Box::new(Csg::new(
+                operation,
+                parse_solid(
+                    field(value, "left", path)?,
+                    &left_path,
+                    base_dir,
+                    materials,
+                    Some(&material),
+                )?,
+                parse_solid(
+                    field(value, "right", path)?,
+                    &right_path,
+                    base_dir,
+                    materials,
+                    Some(&material),
+                )?,
+                material,
+            ))
+        }
+        _ => {
+            return Err(invalid(
+                &format!("{}.type", path),
+                format!("`{}` is not a solid object", kind),
+            ))
+        }
+    };
+    Ok(solid)
+}
+
+fn parse_sdf(value: &Value, path: &str) -> error::Result<Sdf> {
+    let optional_number = |key| match value.get(key) {
+        Some(_) => number_field(value, key, path),
+        None => Ok(0.0),
+    };
+    let children = || {
+        field(value, "children", path)?
+            .as_array()
+            .ok_or_else(|| invalid(&format!("{}.children", path), "expected an array"))?
+            .iter()
+            .enumerate()
+            .map(|(i, child)| parse_sdf(child, &format!("{}.children[{}]", path, i)))
+            .collect::<error::Result<Vec<_>>>()
+    };
+
+    Ok(match string_field(value, "type", path)? {
+        "sphere" => Sdf::Sphere {
+            center: vec3_field(value, "center", path)?,
+            radius: number_field(value, "radius", path)?,
+        },
+        "box" => Sdf::Box {
+            center: vec3_field(value, "center", path)?,
+            half_size: vec3_field(value, "half_size", path)?,
+            rounding: optional_number("rounding")?,
+        },
+        "torus" => Sdf::Torus {
+            center: vec3_field(value, "center", path)?,
+            major_radius: number_field(value, "major_radius", path)?,
+            minor_radius: number_field(value, "minor_radius", path)?,
+        },
+        "capsule" => Sdf::Capsule {
+            a: vec3_field(value, "a", path)?,
+            b: vec3_field(value, "b", path)?,
+            radius: number_field(value, "radius", path)?,
+        },
+        "union" => Sdf::Union(children()?, optional_number("k")?),
+        "intersection" => Sdf::Intersection(children()?, optional_number("k")?),
+        "difference" => Sdf::Difference(
+            Box::new(parse_sdf(
+                field(value, "left", path)?,
+                &format!("{}.left", path),
+            )?),
+            Box::new(parse_sdf(
+                field(value, "right", path)?,
+                &format!("{}.right", path),
+            )?),
+            optional_number("k")?,
+        ),
+        kind => {
+            return Err(invalid(
+                &format!("{}.type", path),
+                format!("unknown distance field shape `{}`", kind),
+            ))
+        }
+    })
+}
+
+/// Parses a field of view in degrees, which fisheye lenses take up to all
+/// the way around, into radians.
+fn parse_fov(value: &Value, path: &str, projection: Projection) -> error::Result<Float> {
+    let fov = number(value, path)?;
+    let (valid, most) = match projection {
+        Projection::Fisheye(_) => (fov <= 360.0, 360),
+        _ => (fov < 180.0, 180),
+    };
+    if fov <= 0.0 || !valid {
+        return Err(invalid(
+            path,
+            format!("expected an angle between 0 and {} degrees", most),
+        ));
+    }
+    Ok(fov.to_radians())
+}
+
+fn parse_camera(value: &Value, path: &str) -> error::Result<Camera> {
+    let position = match value.get("position") {
+        Some(position) => vec3(position, &format!("{}.position", path))?,
+        None => Vec3f::new(0.0, 0.0, 0.0),
+    };
+    let target = match value.get("target") {
+        Some(target) => vec3(target, &format!("{}.target", path))?,
+        None => position + Vec3f::new(0.0, 0.0, -1.0),
+    };
+    let up = match value.get("up") {
+        Some(up) => vec3(up, &format!("{}.up", path))?,
+        None => Vec3f::new(0.0, 1.0, 0.0),
+    };
+    if (target - position).norm() == 0.0 {
+        return Err(invalid(path, "`target` is at `position`"));
+    }
+    let mut camera = Camera::look_at(position, target, up);
+    if let Some(projection) = value.get("projection") {
+        camera.projection = match string(projection, &format!("{}.projection", path))? {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic {
+                width: number_field(value, "view_width", path)?,
+            },
+            "fisheye" => Projection::Fisheye(match value.get("mapping") {
+                Some(mapping) => string(mapping, &format!("{}.mapping", path))?
+                    .parse()
+                    .map_err(|e| invalid(&format!("{}.mapping", path), e))?,
+                None => FisheyeMapping::default(),
+            }),
+            "equirectangular" => Projection::Equirectangular,
+            kind => {
+                return Err(invalid(&format!("{}.projection", path), format!("unknown projection `{}`, expected perspective, orthographic, fisheye or equirectangular", kind)))
+            }
+        };
+    }
+    if let Some(fov) = value.get("fov") {
+        camera.fov = parse_fov(fov, &format!("{}.fov", path), camera.projection)?;
+    }
+    if let Some(aperture) = value.get("aperture") {
+        camera.aperture = number(aperture, &format!("{}.aperture", path))?.max(0.0);
+    }
+    if let Some(distance) = value.get("focus_distance") {
+        camera.focus_distance = Some(number(distance, &format!("{}.focus_distance", path))?);
+    }
+    if let Some(shutter) = value.get("shutter") {
+        camera.shutter = number(shutter, &format!("{}.shutter", path))?.clamp(0.0, 1.0);
+    }
+    if let Some(stereo) = value.get("stereo") {
+        let stereo_path = format!("{}.stereo", path);
+        camera.stereo = Some(Stereo {
+            eye_distance: number_field(stereo, "eye_distance", &stereo_path)?,
+            convergence: match stereo.get("convergence") {
+                Some(v) => Some(number(v, &format!("{}.convergence", stereo_path))?),
+                None => None,
+            },
+            layout: match stereo.get("layout") {
+                None => StereoLayout::default(),
+                Some(layout) => match string(layout, &format!("{}.layout", stereo_path))? {
+                    "side_by_side" => StereoLayout::SideBySide,
+                    "top_bottom" => StereoLayout::TopBottom,
+                    kind => {
+                        return Err(invalid(
+                            &format!("{}.layout", stereo_path),
+                            format!(
+                                "unknown layout `{}`, expected side_by_side or top_bottom",
+                                kind
+                            ),
+                        ))
+                    }
+                },
+            },
+        });
+    }
+    Ok(camera)
+}
+
+/// Parses the `keyframes` of the camera, which default to the position,
+/// target and field of view of the camera itself.
+fn parse_camera_path(
+    value: &Value,
+    path: &str,
+    camera: &Camera,
+) -> error::Result<Option<CameraPath>> {
+    let values = optional_array(value, "keyframes", path)?;
+    if values.is_empty() {
+        return Ok(None);
+    }
+    let mut keyframes = vec![];
+    for (i, keyframe) in values.iter().enumerate() {
+        let keyframe_path = format!("{}.keyframes[{}]", path, i);
+        let vector = |key: &str, default: Vec3f| match keyframe.get(key) {
+            Some(v) => vec3(v, &format!("{}.{}", keyframe_path, key)),
+            None => Ok(default),
+        };
+        let position = vector("position", camera.position())?;
+        let target = vector("target", camera.target())?;
+        if (target - position).norm() == 0.0 {
+            return Err(invalid(&keyframe_path, "`target` is at `position`"));
+        }
+        keyframes.push(Keyframe {
+            time: number_field(keyframe, "time", &keyframe_path)?,
+            position,
+            target,
+            fov: match keyframe.get("fov") {
+                Some(v) => parse_fov(v, &format!("{}.fov", keyframe_path), camera.projection)?,
+                None => camera.fov,
+            },
+        });
+    }
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    if keyframes
+        .windows(2)
+        .any(|pair| pair[0].time == pair[1].time)
+    {
+        return Err(invalid(
+            &format!("{}.keyframes", path),
+            "two keyframes at the same time",
+        ));
+    }
+    let interpolation = match value.get("interpolation") {
+        None => Interpolation::default(),
+        Some(v) => match string(v, &format!("{}.interpolation", path))? {
+            "linear" => Interpolation::Linear,
+            "smooth" => Interpolation::Smooth,
+            kind => {
+                return Err(invalid(
+                    &format!("{}.interpolation", path),
+                    format!(
+                        "unknown interpolation `{}`, expected linear or smooth",
+                        kind
+                    ),
+                ))
+            }
+        },
+    };
+    Ok(Some(CameraPath {
+        keyframes,
+        interpolation,
+    }))
+}
+
+fn parse_volume(value: &Value, path: &str) -> error::Result<Volume> {
+    let coefficient = |key: &str| match value.get(key) {
+        Some(v) => gray_or_color(v, &format!("{}.{}", path, key)),
+        None => Ok(white(0.0)),
+    };
+    Ok(Volume {
+        bounds: Aabb::new(
+            vec3_field(value, "min", path)?,
+            vec3_field(value, "max", path)?,
+        ),
+        absorption: coefficient("absorption")?,
+        scattering: coefficient("scattering")?,
+    })
+}
+
+fn parse_portal(value: &Value, path: &str) -> error::Result<Portal> {
+    let (u, v) = (vec3_field(value, "u", path)?, vec3_field(value, "v", path)?);
+    if u.cross(&v).norm() == 0.0 {
+        return Err(invalid(path, "`u` and `v` leave the portal no area"));
+    }
+    Ok(Portal::new(vec3_field(value, "corner", path)?, u, v))
+}
+
+/// The background, and the sun that lights the scene along with it for skies.
+fn parse_background(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+) -> error::Result<(Background, Option<DirectionalLight>)> {
+    if value.as_array().is_some() {
+        return Ok((Background::Color(vec3(value, path)?), None));
+    }
+    let intensity = match value.get("intensity") {
+        Some(v) => number(v, &format!("{}.intensity", path))?,
+        None => 1.0,
+    };
+    match string_field(value, "type", path)? {
+        "gradient" => Ok((
+            Background::Gradient {
+                top: vec3_field(value, "top", path)?,
+                bottom: vec3_field(value, "bottom", path)?,
+            },
+            None,
+        )),
+        "environment" => {
+            let file = string_field(value, "file", path)?;
+            let image = input::read(base_dir.join(file))
+                .map_err(|e| invalid(&format!("{}.file", path), format!("{}: {}", file, e)))?;
+            Ok((
+                Background::Environment(EnvironmentMap::new(image, intensity)),
+                None,
+            ))
+        }
+        "sky" => {
+            let degrees = |key: &str, default: Float| match value.get(key) {
+                Some(v) => Ok(number(v, &format!("{}.{}", path, key))?.to_radians()),
+                None => Ok::<_, Error>(default.to_radians()),
+            };
+            let (elevation, azimuth) = (
+                degrees("sun_elevation", 45.0)?,
+                degrees("sun_azimuth", 45.0)?,
+            );
+            let sun = Vec3f::new(
+                elevation.cos() * azimuth.sin(),
+                elevation.sin(),
+                -elevation.cos() * azimuth.cos(),
+            );
+            let turbidity = match value.get("turbidity") {
+                Some(v) => number(v, &format!("{}.turbidity", path))?,
+                None => 3.0,
+            };
+            let ground_albedo = match value.get("ground_albedo") {
+                Some(v) => gray_or_color(v, &format!("{}.ground_albedo", path))?,
+                None => white(0.3),
+            };
+            let sky = Sky::new(sun, turbidity, ground_albedo, intensity);
+            let sun = sky.sun_light(degrees("sun_angular_diameter", 0.53)?);
+            Ok((Background::Sky(sky), Some(sun)))
+        }
+        kind => Err(invalid(
+            &format!("{}.type", path),
+            format!("unknown background type `{}`", kind),
+        )),
+    }
+}
+
+/// The falloff of a light, by default none, or with the square of the
+/// distance for lights given in physical units.
+fn parse_falloff(value: &Value, path: &str, physical: bool) -> error::Result<Falloff> {
+    let kind = match value.get("falloff") {
+        Some(kind) => string(kind, &format!("{}.falloff", path))?,
+        None if physical => "inverse_square",
+        None => "none",
+    };
+    match kind {
+        "none" => Ok(Falloff::None),
+        "inverse_square" => Ok(Falloff::InverseSquare {
+            radius: match value.get("radius") {
+                Some(v) => number(v, &format!("{}.radius", path))?,
+                None => 0.0,
+            },
+            cutoff: match value.get("cutoff") {
+                Some(v) => Some(number(v, &format!("{}.cutoff", path))?),
+                None => None,
+            },
+        }),
+        kind => Err(invalid(
+            &format!("{}.falloff", path),
+            format!(
+                "unknown falloff `{}`, expected none or inverse_square",
+                kind
+            ),
+        )),
+    }
+}
+
+/// The intensity of a light: its `intensity`, a number for white light or the
+/// brightness of each channel, or the light it gives off in physical units,
+/// in watts under the key `watts` or in lumens under `lumens`, spread evenly
+/// over the `solid_angle` it shines into and tinted by its `color`. Lights
+/// with an IES profile spread it as the profile does, and are as bright as
+/// the profile says if given neither, with the intensity being that at the
+/// peak of the profile. Along with it comes whether it was given in
+/// physical units.
+fn parse_intensity(
+    value: &Value,
+    path: &str,
+    (watts, lumens): (&str, &str),
+    (solid_angle, profile): (Float, Option<&Profile>),
+) -> error::Result<(Vec3f, bool)> {
+    // Lights shed π times their intensity on a surface facing them a unit
+    // away, as the tutorial leaves the π of diffuse reflection out
+    match (light_amount(value, path, (watts, lumens))?, profile) {
+        (Some(amount), Some(profile)) => Ok((
+            tint(value, path)? * (amount / (PI * profile.solid_angle())),
+            true,
+        )),
+        (Some(amount), None) => Ok((tint(value, path)? * (amount / (PI * solid_angle)), true)),
+        (None, Some(profile)) if value.get("intensity").is_none() => Ok((
+            tint(value, path)? * (profile.peak() / (LUMENS_PER_WATT * PI)),
+            true,
+        )),
+        (None, _) => {
+            let intensity = field(value, "intensity", path)?;
+            let intensity = match intensity.as_f64() {
+                Some(intensity) => white(intensity as Float),
+                None => vec3(intensity, &format!("{}.intensity", path))?,
+            };
+            Ok((intensity, false))
+        }
+    }
+}
+
+/// How much light something gives off in watts, given in watts under the key
+/// `watts` or in lumens under `lumens`, if either is given, but not both or
+/// along with an `intensity`.
+fn light_amount(
+    value: &Value,
+    path: &str,
+    (watts, lumens): (&str, &str),
+) -> error::Result<Option<Float>> {
+    let given: Vec<_> = ["intensity", watts, lumens]
+        .iter()
+        .copied()
+        .filter(|key| value.get(key).is_some())
+        .collect();
+    if given.len() > 1 {
+        return Err(invalid(
+            path,
+            format!("expected only one of `{}`", given.join("`, `")),
+        ));
+    }
+    let amount = |key: &str| match value.get(key) {
+        Some(v) => {
+            let path = format!("{}.{}", path, key);
+            match number(v, &path)? {
+                amount if amount >= 0.0 => Ok(Some(amount)),
+                _ => Err(invalid(&path, "expected a number no less than 0")),
+            }
+        }
+        None => Ok(None),
+    };
+    Ok(match (amount(watts)?, amount(lumens)?) {
+        (Some(watts), _) => Some(watts),
+        (_, Some(lumens)) => Some(lumens / LUMENS_PER_WATT),
+        _ => None,
+    })
+}
+
+/// The `color` of light given in physical units, white by default, scaled
+/// to the brightness of white so that it only tints the light.
+fn tint(value: &Value, path: &str) -> error::Result<Vec3f> {
+    let color = match value.get("color") {
+        Some(v) => vec3(v, &format!("{}.color", path))?,
+        None => white(1.0),
+    };
+    normalized_tint(color, &format!("{}.color", path))
+}
+
+/// The color scaled to the brightness of white.
+fn normalized_tint(color: Vec3f, path: &str) -> error::Result<Vec3f> {
+    match luminance(&color) {
+        brightness if brightness > 0.0 => Ok(color * (1.0 / brightness)),
+        _ => Err(invalid(path, "expected a color brighter than black")),
+    }
+}
+
+fn parse_light(value: &Value, path: &str, base_dir: &Path) -> error::Result<Box<dyn Light>> {
+    if value.as_object().is_none() {
+        return Err(invalid(path, "expected an object"));
+    }
+    let group = match value.get("group") {
+        Some(group) => Some(string(group, &format!("{}.group", path))?.to_string()),
+        None => None,
+    };
+    Ok(in_group(
+        parse_ungrouped_light(value, path, base_dir)?,
+        group,
+    ))
+}
+
+/// The light, put in the light group if there is one.
+fn in_group(light: Box<dyn Light>, group: Option<String>) -> Box<dyn Light> {
+    match group {
+        Some(group) => Box::new(Grouped { light, group }),
+        None => light,
+    }
+}
+
+/// The IES profile in the file at `ies`, if given.
+fn parse_profile(value: &Value, path: &str, base_dir: &Path) -> error::Result<Option<Profile>> {
+    match value.get("ies") {
+        Some(file) => {
+            let file = string(file, &format!("{}.ies", path))?;
+            let profile = Profile::load(&base_dir.join(file)).map_err(|e| e.in_file(file))?;
+            Ok(Some(profile))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The light dimmed by its IES profile, if it has one, the nadir of the
+/// profile along `axis` and its horizontal angles turned about it by
+/// `ies_rotation` degrees.
+fn profiled(
+    light: Box<dyn Light>,
+    profile: Option<Profile>,
+    axis: Vec3f,
+    (value, path): (&Value, &str),
+) -> error::Result<Box<dyn Light>> {
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return Ok(light),
+    };
+    let rotation = match value.get("ies_rotation") {
+        Some(v) => number(v, &format!("{}.ies_rotation", path))?.to_radians(),
+        None => 0.0,
+    };
+    let axis = axis.normalize();
+    let (u, v) = axis.perpendiculars();
+    let (sin, cos) = rotation.sin_cos();
+    Ok(Box::new(Profiled {
+        light,
+        profile,
+        axis,
+        tangent: u * cos + v * sin,
+        bitangent: v * cos - u * sin,
+    }))
+}
+
+fn parse_ungrouped_light(
+    value: &Value,
+    path: &str,
+    base_dir: &Path,
+) -> error::Result<Box<dyn Light>> {
+    let kind = match value.get("type") {
+        Some(kind) => string(kind, &format!("{}.type", path))?,
+        None => "point",
+    };
+    Ok(match kind {
+        "point" => {
+            let profile = parse_profile(value, path, base_dir)?;
+            let (intensity, physical) = parse_intensity(
+                value,
+                path,
+                ("power", "lumens"),
+                (4.0 * PI, profile.as_ref()),
+            )?;
+            let mut light = PointLight::new(vec3_field(value, "position", path)?, intensity);
+            light.falloff = parse_falloff(value, path, physical)?;
+            // Fixtures hang pointing down unless turned
+            let axis = match value.get("direction") {
+                Some(v) => vec3(v, &format!("{}.direction", path))?,
+                None => Vec3f::new(0.0, -1.0, 0.0),
+            };
+            profiled(Box::new(light), profile, axis, (value, path))?
+        }
+        "directional" => {
+            let angular_diameter = match value.get("angular_diameter") {
+                Some(v) => number(v, &format!("{}.angular_diameter", path))?.to_radians(),
+                None => 0.0,
+            };
+            let (intensity, _) = parse_intensity(value, path, ("irradiance", "lux"), (1.0, None))?;
+            Box::new(DirectionalLight::new(
+                vec3_field(value, "direction", path)?,
+                intensity,
+                angular_diameter,
+            ))
+        }
+        "spot" => {
+            let inner_angle = number_field(value, "inner_angle", path)?.to_radians();
+            let outer_angle = number_field(value, "outer_angle", path)?.to_radians();
+            // The cone the light shines into, half of the way through its
+            // fading out counting for all of it
+            let solid_angle = 2.0 * PI * (1.0 - 0.5 * (inner_angle.cos() + outer_angle.cos()));
+            let profile = parse_profile(value, path, base_dir)?;
+            let (intensity, physical) = parse_intensity(
+                value,
+                path,
+                ("power", "lumens"),
+                (solid_angle, profile.as_ref()),
+            )?;
+            let direction = vec3_field(value, "direction", path)?;
+            let mut light = SpotLight::new(
+                vec3_field(value, "position", path)?,
+                direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            );
+            light.falloff = parse_falloff(value, path, physical)?;
+            profiled(Box::new(light), profile, direction, (value, path))?
+        }
+        kind => {
+            return Err(invalid(
+                &format!("{}.type", path),
+                format!("unknown light type `{}`", kind),
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::surface_material;
+
+    #[test]
+    fn reports_every_problem() {
+        let source = r#"{
+            "camera": { "up": [0, 0, 0] },
+            "objects": [
+                { "type": "sphere", "center": [0, 0, -10], "radius": 2, "material": "ivory" },
+                { "type": "disk", "center": [0, 0, 0], "normal": [0, 1, 0], "radius": -1, "material": "ivory" },
+                { "type": "mesh", "file": "missing.obj" }
+            ],
+            "lights": [{ "position": [0, 1, -10], "intensity": 1 }]
+        }"#;
+        let problems = match Scene::parse(source, Path::new("")) {
+            Err(Error::Invalid(problems)) => problems,
+            _ => panic!("expected the problems of the scene"),
+        };
+        let problems: Vec<_> = problems.iter().map(Error::to_string).collect();
+        assert_eq!(
+            problems,
+            [
+                "scene: objects[1].radius: expected a positive number, not -1",
+                "scene: objects[2].file: no file `missing.obj`",
+                "scene: camera.up: expected a vector of some length",
+                "scene: lights[0].position: inside objects[0], which hides the light",
+            ]
+        );
+    }
+
+    #[test]
+    fn hits_refer_to_objects_by_their_place_in_the_file() {
+        // Spheres go in packets ahead of the other objects, which the
+        // handles don't show
+        let source = r#"{"objects": [
+            { "type": "quad", "corner": [-1, -1, -5], "u": [2, 0, 0], "v": [0, 2, 0],
+              "material": "ivory" },
+            { "type": "sphere", "center": [3, 0, -5], "radius": 1, "material": "ivory" },
+            { "type": "triangle", "vertices": [[-1, 3, -5], [1, 3, -5], [0, 5, -5]],
+              "material": "ivory" },
+            { "type": "sphere", "center": [-3, 0, -5], "radius": 1, "material": "ivory" }
+        ]}"#;
+        let scene = Scene::parse(source, Path::new("")).unwrap();
+        let origin = Vec3f::new(0.0, 0.0, 0.0);
+        for (target, id) in [
+            Vec3f::new(0.0, 0.0, -5.0),
+            Vec3f::new(3.0, 0.0, -5.0),
+            Vec3f::new(0.0, 4.0, -5.0),
+            Vec3f::new(-3.0, 0.0, -5.0),
+        ]
+        .iter()
+        .zip(0..)
+        {
+            let hit = scene.hit(&Ray::new(origin, (*target - origin).normalize()));
+            assert_eq!(hit.and_then(|hit| hit.handle).map(|h| h.index()), Some(id));
+        }
+    }
+
+    #[test]
+    fn handles_back_faces() {
+        // A quad facing away from a ray coming down onto it, in front of a
+        // quad facing up
+        let scene = |back_face: &str| {
+            let source = format!(
+                r#"{{"objects": [
+                    {{ "type": "quad", "corner": [-1, 0, -1], "u": [2, 0, 0], "v": [0, 0, 2],
+                       "material": {{ "back_face": {} }} }},
+                    {{ "type": "quad", "corner": [-1, -1, -1], "u": [0, 0, 2], "v": [2, 0, 0],
+                       "material": "ivory" }}
+                ]}}"#,
+                back_face
+            );
+            Scene::parse(&source, Path::new("")).unwrap()
+        };
+        let ray = Ray::new(Vec3f::new(0.0, 1.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+
+        let (culled, flipped, colored) = (scene("\"cull\""), scene("\"flip\""), scene("[1, 0, 0]"));
+        assert_eq!(culled.hit(&ray).unwrap().point.1, -1.0);
+        let hit = flipped.hit(&ray).unwrap();
+        assert!(hit.back && hit.point.1 == 0.0 && hit.normal.1 > 0.0);
+        let hit = colored.hit(&ray).unwrap();
+        assert!(hit.back && hit.normal.1 < 0.0);
+        assert_eq!(surface_material(&hit, 0.0).diffuse_color.0, 1.0);
+    }
+
+    #[test]
+    fn finds_the_files_of_scripts() {
+        let dir = std::env::temp_dir().join(format!("tiny-raytracer-refs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        // A name the description only has once the script has run
+        let script = r#"let objects = [#{ type: "mesh", file: "tri" + ".obj" }];"#;
+        fs::write(dir.join("scene.script"), script).unwrap();
+        fs::write(
+            dir.join("scene.json"),
+            r#"{"objects": [{"type": "mesh", "file": "tri.obj"}]}"#,
+        )
+        .unwrap();
+        for name in ["scene.script", "scene.json"].iter().copied() {
+            assert_eq!(
+                referenced_files(&dir.join(name), 0.0),
+                vec![dir.join("tri.obj")]
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
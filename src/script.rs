@@ -0,0 +1,1116 @@
+//! Scene scripts: scene files ending in `.script`, which build the scene
+//! with loops and branches rather than spelling out every object, such as a
+//! grid of a thousand spheres of varying materials:
+//!
+//! ```text
+//! // A row of spheres, alternating between the two materials
+//! let objects = [];
+//! for i in 0..10 {
+//!     let material = if i % 2 == 0 { "ivory" } else { "red_rubber" };
+//!     objects.push(#{ type: "sphere", center: [i * 2 - 9, 0, -16], radius: 0.8, material: material });
+//! }
+//! let lights = [#{ position: [-20, 20, 20], intensity: 1.5 }];
+//! ```
+//!
+//! The variables of the script named like the fields of a scene file,
+//! `objects`, `lights`, `materials`, `camera`, `background`, `fog` and
+//! `volumes`, make up the scene, which is then loaded as a scene file would
+//! be; its other variables are the script's own.
+//!
+//! Values are those of JSON: numbers, strings, `true` and `false`, `null`,
+//! arrays in `[]` and objects in `#{}`, whose keys are names or strings.
+//! Statements are `let` declarations and assignments, `=`, `+=`, `-=`, `*=`
+//! and `/=`, to variables and to the items of arrays and fields of objects
+//! within them, ending in `;`, loops `for x in array { }` and `while
+//! condition { }`, and `if condition { } else { }`, which also gives the
+//! value of the last expression of the block it runs when that doesn't end
+//! in `;`. `a..b` is the array of the whole numbers from `a` up to `b`.
+//!
+//! The operators are those of Rust, `+` also joining strings and arrays.
+//! Arrays have the methods `push(value)` and `len()`, and the functions are
+//! `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `atan2`, `sqrt`, `abs`,
+//! `floor`, `ceil`, `round`, `min`, `max`, `pow`, `exp` and `ln`, as in Rust,
+//! `random()`, a number from 0 up to 1 that is the same each time the script
+//! runs, and `seed(n)`, which starts `random` over from another seed. `PI`
+//! is π, and `time` the time the scene is loaded at. `//` comments out the
+//! rest of the line.
+
+use crate::error::{self, Error};
+use crate::json::{ParseError, Value};
+use crate::preset::Random;
+use crate::vector::to_f64;
+
+/// How deeply expressions and blocks may be nested, well short of running
+/// out of stack parsing or running them
+const MAX_DEPTH: usize = 128;
+/// How many statements and loop iterations a script may run, so that one
+/// looping forever fails instead of hanging
+const MAX_STEPS: usize = 10_000_000;
+/// Longest array a range may make
+const MAX_RANGE: f64 = 1_000_000.0;
+/// Longest string, in bytes, or array joining or pushing may make, so that
+/// a script doubling one over and over fails instead of running out of
+/// memory
+const MAX_LENGTH: usize = 1 << 20;
+
+/// The names of the variables making up the scene.
+const FIELDS: [&str; 7] = [
+    "objects",
+    "lights",
+    "materials",
+    "camera",
+    "background",
+    "fog",
+    "volumes",
+];
+
+/// Runs a scene script as of `time`, giving the scene description it
+/// builds.
+pub fn run(source: &str, time: f64) -> error::Result<Value> {
+    let program = Parser::new(source)
+        .program()
+        .map_err(|e| Error::json("script", e))?;
+    let mut interpreter = Interpreter {
+        scopes: vec![vec![
+            ("PI".to_string(), Value::Number(std::f64::consts::PI)),
+            ("time".to_string(), Value::Number(time)),
+        ]],
+        random: Random::new(0),
+        line: 1,
+        steps: 0,
+    };
+    interpreter
+        .statements(&program)
+        .map_err(|message| Error::at_line("script", interpreter.line, message))?;
+    let globals = interpreter.scopes.remove(0);
+    Ok(Value::Object(
+        globals
+            .into_iter()
+            .filter(|(name, _)| FIELDS.contains(&&name[..]))
+            .collect(),
+    ))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// The symbols, the longer ones first so that they are taken over the
+/// shorter ones they start with.
+const SYMBOLS: [&str; 30] = [
+    "..", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "#{", "+", "-", "*", "/",
+    "%", "<", ">", "=", "!", "(", ")", "[", "]", "{", "}", ",", ":", ";",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Range,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Variable(String),
+    Array(Vec<Expr>),
+    Object(Vec<(String, Expr)>),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Field(Box<Expr>, String),
+    Call(String, Vec<Expr>),
+    Method(Box<Expr>, String, Vec<Expr>),
+    If(Box<Expr>, Block, Option<Box<Expr>>),
+    Block(Block),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Let(String, Expr),
+    Assign(Expr, Option<Op>, Expr),
+    For(String, Expr, Block),
+    While(Expr, Block),
+    Expr(Expr),
+}
+
+/// Statements, each with the line it starts on, and the expression ending
+/// the block without a `;`, whose value is the block's.
+#[derive(Debug, Default)]
+struct Block {
+    statements: Vec<(usize, Statement)>,
+    value: Option<(usize, Box<Expr>)>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// The next token and where it starts, once it has been read
+    peeked: Option<(Option<Token>, usize)>,
+    /// Expressions and blocks the parser is inside of
+    depth: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            peeked: None,
+            depth: 0,
+        }
+    }
+
+    fn error_at(&self, pos: usize, message: &str) -> ParseError {
+        let consumed = &self.chars[..pos.min(self.chars.len())];
+        let line = consumed.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = consumed.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        ParseError {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    /// An error at the next token.
+    fn error(&mut self, message: &str) -> ParseError {
+        let pos = match self.peek_at() {
+            Ok((_, pos)) => pos,
+            Err(e) => return e,
+        };
+        self.error_at(pos, message)
+    }
+
+    /// The line the next token is on.
+    fn line(&mut self) -> usize {
+        let pos = self.peek_at().map_or(self.pos, |(_, pos)| pos);
+        self.error_at(pos, "").line
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.chars.get(self.pos) {
+                Some(c) if c.is_whitespace() => self.pos += 1,
+                Some('/') if self.chars.get(self.pos + 1) == Some(&'/') => {
+                    while self.chars.get(self.pos).is_some_and(|&c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> Result<(Option<Token>, usize), ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let c = match self.chars.get(self.pos) {
+            Some(&c) => c,
+            None => return Ok((None, start)),
+        };
+        let token = if c.is_ascii_digit() {
+            while self
+                .chars
+                .get(self.pos)
+                .is_some_and(|&c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E')
+            {
+                // A range after a whole number, rather than a fraction
+                if self.chars[self.pos] == '.' && self.chars.get(self.pos + 1) == Some(&'.') {
+                    break;
+                }
+                let exponent = matches!(self.chars[self.pos], 'e' | 'E');
+                self.pos += 1;
+                if exponent && matches!(self.chars.get(self.pos), Some('+') | Some('-')) {
+                    self.pos += 1;
+                }
+            }
+            let text: String = self.chars[start..self.pos].iter().collect();
+            let n = text
+                .parse()
+                .map_err(|_| self.error_at(start, &format!("invalid number `{}`", text)))?;
+            Token::Number(n)
+        } else if c.is_alphabetic() || c == '_' {
+            while self
+                .chars
+                .get(self.pos)
+                .is_some_and(|&c| c.is_alphanumeric() || c == '_')
+            {
+                self.pos += 1;
+            }
+            Token::Name(self.chars[start..self.pos].iter().collect())
+        } else if c == '"' {
+            self.pos += 1;
+            let mut s = String::new();
+            loop {
+                match self.chars.get(self.pos) {
+                    Some('"') => break,
+                    Some('\\') => {
+                        s.push(match self.chars.get(self.pos + 1) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(&c @ '"') | Some(&c @ '\\') => c,
+                            _ => return Err(self.error_at(self.pos, "invalid escape sequence")),
+                        });
+                        self.pos += 2;
+                    }
+                    Some(&c) => {
+                        s.push(c);
+                        self.pos += 1;
+                    }
+                    None => return Err(self.error_at(start, "unterminated string")),
+                }
+            }
+            self.pos += 1;
+            Token::String(s)
+        } else if c == '.' && self.chars.get(self.pos + 1) != Some(&'.') {
+            self.pos += 1;
+            Token::Symbol(".")
+        } else {
+            let rest = &self.chars[self.pos..];
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| {
+                    symbol.len() <= rest.len() && symbol.chars().zip(rest).all(|(a, &b)| a == b)
+                })
+                .ok_or_else(|| self.error_at(start, &format!("unexpected `{}`", c)))?;
+            self.pos += symbol.len();
+            Token::Symbol(symbol)
+        };
+        Ok((Some(token), start))
+    }
+
+    fn peek_at(&mut self) -> Result<(Option<Token>, usize), ParseError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read_token()?);
+        }
+        Ok(self.peeked.clone().unwrap())
+    }
+
+    fn peek(&mut self) -> Result<Option<Token>, ParseError> {
+        Ok(self.peek_at()?.0)
+    }
+
+    fn next(&mut self) -> Result<Option<Token>, ParseError> {
+        let token = self.peek()?;
+        self.peeked = None;
+        Ok(token)
+    }
+
+    /// Whether the next token is the symbol or keyword `word`, taking it if
+    /// it is.
+    fn eat(&mut self, word: &str) -> Result<bool, ParseError> {
+        let found = match self.peek()? {
+            Some(Token::Symbol(symbol)) => symbol == word,
+            Some(Token::Name(name)) => name == word,
+            _ => false,
+        };
+        if found {
+            self.next()?;
+        }
+        Ok(found)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), ParseError> {
+        if self.eat(word)? {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", word)))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        match self.peek()? {
+            Some(Token::Name(name)) if !is_keyword(&name) => {
+                self.next()?;
+                Ok(name)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn program(&mut self) -> Result<Block, ParseError> {
+        let block = self.statements()?;
+        if self.peek()?.is_some() {
+            return Err(self.error("expected a statement"));
+        }
+        Ok(block)
+    }
+
+    fn nested<T>(
+        &mut self,
+        parse: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn block(&mut self) -> Result<Block, ParseError> {
+        self.expect("{")?;
+        let block = self.nested(Self::statements)?;
+        self.expect("}")?;
+        Ok(block)
+    }
+
+    /// Statements up to the end of a block or of the script.
+    fn statements(&mut self) -> Result<Block, ParseError> {
+        let mut block = Block::default();
+        loop {
+            match self.peek()? {
+                None | Some(Token::Symbol("}")) => return Ok(block),
+                _ => {}
+            }
+            if let Some((line, value)) = block.value.take() {
+                // Only blocks ending in a block may go without a `;`
+                if !matches!(*value, Expr::If(..) | Expr::Block(_)) {
+                    return Err(self.error("expected `;`"));
+                }
+                block.statements.push((line, Statement::Expr(*value)));
+            }
+            let line = self.line();
+            let statement = if self.eat("let")? {
+                let name = self.name()?;
+                self.expect("=")?;
+                let value = self.expr()?;
+                self.expect(";")?;
+                Statement::Let(name, value)
+            } else if self.eat("for")? {
+                let name = self.name()?;
+                self.expect("in")?;
+                let values = self.expr()?;
+                Statement::For(name, values, self.block()?)
+            } else if self.eat("while")? {
+                let condition = self.expr()?;
+                Statement::While(condition, self.block()?)
+            } else {
+                let expr = self.expr()?;
+                let assignment = [
+                    ("=", None),
+                    ("+=", Some(Op::Add)),
+                    ("-=", Some(Op::Sub)),
+                    ("*=", Some(Op::Mul)),
+                    ("/=", Some(Op::Div)),
+                ];
+                let mut op = None;
+                for &(symbol, assign) in &assignment {
+                    if self.eat(symbol)? {
+                        op = Some(assign);
+                        break;
+                    }
+                }
+                match op {
+                    Some(op) => {
+                        if !matches!(expr, Expr::Variable(_) | Expr::Index(..) | Expr::Field(..)) {
+                            return Err(self.error_at(self.pos, "can't assign to this"));
+                        }
+                        let value = self.expr()?;
+                        self.expect(";")?;
+                        Statement::Assign(expr, op, value)
+                    }
+                    None if self.eat(";")? => Statement::Expr(expr),
+                    None => {
+                        block.value = Some((line, Box::new(expr)));
+                        continue;
+                    }
+                }
+            };
+            block.statements.push((line, statement));
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        self.nested(|parser| {
+            let from = parser.binary(0)?;
+            if parser.eat("..")? {
+                let to = parser.binary(0)?;
+                return Ok(Expr::Binary(Op::Range, Box::new(from), Box::new(to)));
+            }
+            Ok(from)
+        })
+    }
+
+    /// Operands joined by the operators of at least the precedence `level`,
+    /// the loosest being 0.
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        const LEVELS: [&[(&str, Op)]; 5] = [
+            &[("||", Op::Or)],
+            &[("&&", Op::And)],
+            &[
+                ("==", Op::Eq),
+                ("!=", Op::Ne),
+                ("<=", Op::Le),
+                (">=", Op::Ge),
+                ("<", Op::Lt),
+                (">", Op::Gt),
+            ],
+            &[("+", Op::Add), ("-", Op::Sub)],
+            &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'operators: loop {
+            for &(symbol, op) in LEVELS[level] {
+                if self.eat(symbol)? {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("-")? {
+            return Ok(Expr::Negate(Box::new(self.nested(Self::unary)?)));
+        }
+        if self.eat("!")? {
+            return Ok(Expr::Not(Box::new(self.nested(Self::unary)?)));
+        }
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[")? {
+                let index = self.expr()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(".")? {
+                let name = self.name()?;
+                expr = if self.eat("(")? {
+                    Expr::Method(Box::new(expr), name, self.arguments()?)
+                } else {
+                    Expr::Field(Box::new(expr), name)
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// The arguments of a call, after its `(`.
+    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut arguments = vec![];
+        while !self.eat(")")? {
+            arguments.push(self.expr()?);
+            if !self.eat(",")? {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let (token, start) = self.peek_at()?;
+        if let Some(Token::Symbol("{")) = token {
+            return Ok(Expr::Block(self.block()?));
+        }
+        if self.eat("if")? {
+            return self.if_else();
+        }
+        self.next()?;
+        Ok(match token {
+            Some(Token::Number(n)) => Expr::Literal(Value::Number(n)),
+            Some(Token::String(s)) => Expr::Literal(Value::String(s)),
+            Some(Token::Name(name)) => match &name[..] {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                _ if is_keyword(&name) => {
+                    return Err(self.error_at(start, "expected an expression"))
+                }
+                _ if self.eat("(")? => Expr::Call(name, self.arguments()?),
+                _ => Expr::Variable(name),
+            },
+            Some(Token::Symbol("(")) => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                expr
+            }
+            Some(Token::Symbol("[")) => {
+                let mut items = vec![];
+                while !self.eat("]")? {
+                    items.push(self.expr()?);
+                    if !self.eat(",")? {
+                        self.expect("]")?;
+                        break;
+                    }
+                }
+                Expr::Array(items)
+            }
+            Some(Token::Symbol("#{")) => {
+                let mut members = vec![];
+                while !self.eat("}")? {
+                    let key = match self.next()? {
+                        Some(Token::Name(name)) | Some(Token::String(name)) => name,
+                        _ => return Err(self.error("expected a key")),
+                    };
+                    self.expect(":")?;
+                    members.push((key, self.expr()?));
+                    if !self.eat(",")? {
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                Expr::Object(members)
+            }
+            _ => return Err(self.error_at(start, "expected an expression")),
+        })
+    }
+
+    /// The rest of an `if`, after the keyword.
+    fn if_else(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.expr()?;
+        let then = self.block()?;
+        let otherwise = if self.eat("else")? {
+            Some(Box::new(if self.eat("if")? {
+                self.nested(Self::if_else)?
+            } else {
+                Expr::Block(self.block()?)
+            }))
+        } else {
+            None
+        };
+        Ok(Expr::If(Box::new(condition), then, otherwise))
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    [
+        "let", "for", "in", "while", "if", "else", "true", "false", "null",
+    ]
+    .contains(&name)
+}
+
+/// A value as messages give it: numbers, strings, `true`, `false` and
+/// `null` as they are written, and arrays and objects by what they are.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("{:?}", s),
+        Value::Array(_) => "an array".to_string(),
+        Value::Object(_) => "an object".to_string(),
+    }
+}
+
+/// A step from an array or object of a variable to one of its items or
+/// fields, on the way to what an assignment assigns to.
+enum Key {
+    Index(usize),
+    Field(String),
+}
+
+struct Interpreter {
+    /// The variables of each block the script is in, the outermost first
+    scopes: Vec<Vec<(String, Value)>>,
+    random: Random,
+    /// The line of the statement running
+    line: usize,
+    steps: usize,
+}
+
+impl Interpreter {
+    fn step(&mut self) -> Result<(), String> {
+        self.take_steps(1)
+    }
+
+    fn take_steps(&mut self, count: usize) -> Result<(), String> {
+        self.steps = self.steps.saturating_add(count);
+        if self.steps > MAX_STEPS {
+            return Err("the script ran too long".to_string());
+        }
+        Ok(())
+    }
+
+    /// Takes a step for each of the items and bytes of a value being
+    /// stored, so that a script storing ever larger values, copies of
+    /// arrays nested in one another among them, runs out of steps long
+    /// before it runs out of memory.
+    fn store(&mut self, value: &Value) -> Result<(), String> {
+        let size = size(value, 0)?;
+        self.take_steps(size)
+    }
+
+    fn variable(&mut self, name: &str) -> Result<&mut Value, String> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("no variable `{}`", name))
+    }
+
+    /// Runs a block in a scope of its own, giving its value.
+    fn block(&mut self, block: &Block) -> Result<Value, String> {
+        self.scopes.push(vec![]);
+        let value = self.statements(block);
+        self.scopes.pop();
+        value
+    }
+
+    /// Runs the statements of a block in the innermost scope.
+    fn statements(&mut self, block: &Block) -> Result<Value, String> {
+        for (line, statement) in &block.statements {
+            self.line = *line;
+            self.step()?;
+            self.statement(statement)?;
+        }
+        match &block.value {
+            Some((line, value)) => {
+                self.line = *line;
+                self.expr(value)
+            }
+            None => Ok(Value::Null),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Let(name, value) => {
+                let value = self.expr(value)?;
+                self.store(&value)?;
+                self.scopes.last_mut().unwrap().push((name.clone(), value));
+            }
+            Statement::Assign(place, op, value) => {
+                let value = self.expr(value)?;
+                let value = match op {
+                    Some(op) => {
+                        let current = self.expr(place)?;
+                        binary(*op, current, value)?
+                    }
+                    None => value,
+                };
+                self.store(&value)?;
+                *self.place(place)? = value;
+            }
+            Statement::For(name, values, body) => {
+                let values = match self.expr(values)? {
+                    Value::Array(values) => values,
+                    value => return Err(format!("can't loop over {}", describe(&value))),
+                };
+                let line = self.line;
+                for value in values {
+                    self.step()?;
+                    self.scopes.push(vec![(name.clone(), value)]);
+                    let result = self.block(body);
+                    self.scopes.pop();
+                    result?;
+                    self.line = line;
+                }
+            }
+            Statement::While(condition, body) => {
+                let line = self.line;
+                while self.condition(condition)? {
+                    self.step()?;
+                    self.block(body)?;
+                    self.line = line;
+                }
+            }
+            Statement::Expr(expr) => {
+                self.expr(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self, condition: &Expr) -> Result<bool, String> {
+        match self.expr(condition)? {
+            Value::Bool(b) => Ok(b),
+            value => Err(format!("expected true or false, not {}", describe(&value))),
+        }
+    }
+
+    /// The variable, or the item or field within one, that `place` names.
+    fn place(&mut self, place: &Expr) -> Result<&mut Value, String> {
+        let mut keys = vec![];
+        let mut expr = place;
+        let name = loop {
+            match expr {
+                Expr::Variable(name) => break name,
+                Expr::Index(inner, index) => {
+                    keys.push(match self.expr(index)? {
+                        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Key::Index(n as usize),
+                        Value::String(s) => Key::Field(s),
+                        value => return Err(format!("can't index with {}", describe(&value))),
+                    });
+                    expr = inner;
+                }
+                Expr::Field(inner, name) => {
+                    keys.push(Key::Field(name.clone()));
+                    expr = inner;
+                }
+                _ => return Err("can't assign to this".to_string()),
+            }
+        };
+        let mut value = self.variable(name)?;
+        for key in keys.into_iter().rev() {
+            value = match (value, key) {
+                (Value::Array(items), Key::Index(i)) => {
+                    let len = items.len();
+                    items
+                        .get_mut(i)
+                        .ok_or_else(|| format!("index {} is past the end of {} items", i, len))?
+                }
+                (Value::Object(members), Key::Field(key)) => {
+                    match members.iter().position(|(k, _)| *k == key) {
+                        Some(i) => &mut members[i].1,
+                        None => {
+                            members.push((key, Value::Null));
+                            &mut members.last_mut().unwrap().1
+                        }
+                    }
+                }
+                (value, _) => return Err(format!("can't index {}", describe(value))),
+            };
+        }
+        Ok(value)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Variable(name) => self.variable(name)?.clone(),
+            Expr::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.expr(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Object(members) => Value::Object(
+                members
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), self.expr(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            Expr::Negate(operand) => match self.expr(operand)? {
+                Value::Number(n) => Value::Number(-n),
+                value => return Err(format!("can't negate {}", describe(&value))),
+            },
+            Expr::Not(operand) => Value::Bool(!self.condition(operand)?),
+            Expr::Binary(Op::And, left, right) => {
+                Value::Bool(self.condition(left)? && self.condition(right)?)
+            }
+            Expr::Binary(Op::Or, left, right) => {
+                Value::Bool(self.condition(left)? || self.condition(right)?)
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.expr(left)?;
+                binary(*op, left, self.expr(right)?)?
+            }
+            Expr::Index(value, index) => {
+                let value = self.expr(value)?;
+                match (&value, self.expr(index)?) {
+                    (Value::Array(items), Value::Number(n)) => {
+                        let item = (n >= 0.0 && n.fract() == 0.0)
+                            .then(|| items.get(n as usize))
+                            .flatten();
+                        item.cloned()
+                            .ok_or_else(|| format!("index {} is out of {} items", n, items.len()))?
+                    }
+                    (Value::Object(_), Value::String(key)) => field(&value, &key)?,
+                    (_, index) => {
+                        return Err(format!(
+                            "can't index {} with {}",
+                            describe(&value),
+                            describe(&index)
+                        ))
+                    }
+                }
+            }
+            Expr::Field(value, key) => field(&self.expr(value)?, key)?,
+            Expr::Call(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.expr(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, &arguments)?
+            }
+            Expr::Method(receiver, name, arguments) => {
+                let mut arguments = arguments
+                    .iter()
+                    .map(|argument| self.expr(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match (&name[..], arguments.len()) {
+                    ("push", 1) => {
+                        let item = arguments.pop().unwrap();
+                        self.store(&item)?;
+                        match self.place(receiver)? {
+                            Value::Array(items) if items.len() >= MAX_LENGTH => {
+                                return Err(too_long("array"))
+                            }
+                            Value::Array(items) => {
+                                items.push(item);
+                                Value::Null
+                            }
+                            value => return Err(format!("can't push to {}", describe(value))),
+                        }
+                    }
+                    ("len", 0) => match self.expr(receiver)? {
+                        Value::Array(items) => Value::Number(items.len() as f64),
+                        Value::String(s) => Value::Number(s.chars().count() as f64),
+                        value => return Err(format!("{} has no length", describe(&value))),
+                    },
+                    _ => {
+                        return Err(format!(
+                            "no method `{}` of {} arguments",
+                            name,
+                            arguments.len()
+                        ))
+                    }
+                }
+            }
+            Expr::If(condition, then, otherwise) => {
+                if self.condition(condition)? {
+                    self.block(then)?
+                } else {
+                    match otherwise {
+                        Some(otherwise) => self.expr(otherwise)?,
+                        None => Value::Null,
+                    }
+                }
+            }
+            Expr::Block(block) => self.block(block)?,
+        })
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Value]) -> Result<Value, String> {
+        let numbers: Option<Vec<f64>> = arguments.iter().map(Value::as_f64).collect();
+        let n = match (name, numbers.as_deref()) {
+            ("random", Some([])) => to_f64(self.random.next()),
+            ("seed", Some(&[seed])) => {
+                self.random = Random::new(seed as u64);
+                return Ok(Value::Null);
+            }
+            ("sin", Some(&[x])) => x.sin(),
+            ("cos", Some(&[x])) => x.cos(),
+            ("tan", Some(&[x])) => x.tan(),
+            ("asin", Some(&[x])) => x.asin(),
+            ("acos", Some(&[x])) => x.acos(),
+            ("atan", Some(&[x])) => x.atan(),
+            ("atan2", Some(&[y, x])) => y.atan2(x),
+            ("sqrt", Some(&[x])) => x.sqrt(),
+            ("abs", Some(&[x])) => x.abs(),
+            ("floor", Some(&[x])) => x.floor(),
+            ("ceil", Some(&[x])) => x.ceil(),
+            ("round", Some(&[x])) => x.round(),
+            ("min", Some(&[a, b])) => a.min(b),
+            ("max", Some(&[a, b])) => a.max(b),
+            ("pow", Some(&[x, y])) => x.powf(y),
+            ("exp", Some(&[x])) => x.exp(),
+            ("ln", Some(&[x])) => x.ln(),
+            _ => {
+                return Err(format!(
+                    "no function `{}` of {} numbers",
+                    name,
+                    arguments.len()
+                ))
+            }
+        };
+        Ok(Value::Number(n))
+    }
+}
+
+fn field(value: &Value, key: &str) -> Result<Value, String> {
+    match value {
+        Value::Object(_) => value
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("no field `{}`", key)),
+        _ => Err(format!("{} has no field `{}`", describe(value), key)),
+    }
+}
+
+fn binary(op: Op, left: Value, right: Value) -> Result<Value, String> {
+    use Value::*;
+    Ok(match (op, left, right) {
+        (Op::Eq, a, b) => Bool(a == b),
+        (Op::Ne, a, b) => Bool(a != b),
+        (Op::Add, String(a), b) => join(a, &text(&b))?,
+        (Op::Add, a, String(b)) => join(text(&a), &b)?,
+        (Op::Add, Array(mut a), Array(b)) => {
+            if a.len() + b.len() > MAX_LENGTH {
+                return Err(too_long("array"));
+            }
+            a.extend(b);
+            Array(a)
+        }
+        (Op::Lt, String(a), String(b)) => Bool(a < b),
+        (Op::Le, String(a), String(b)) => Bool(a <= b),
+        (Op::Gt, String(a), String(b)) => Bool(a > b),
+        (Op::Ge, String(a), String(b)) => Bool(a >= b),
+        (op, Number(a), Number(b)) => match op {
+            Op::Add => Number(a + b),
+            Op::Sub => Number(a - b),
+            Op::Mul => Number(a * b),
+            Op::Div => Number(a / b),
+            Op::Rem => Number(a % b),
+            Op::Lt => Bool(a < b),
+            Op::Le => Bool(a <= b),
+            Op::Gt => Bool(a > b),
+            Op::Ge => Bool(a >= b),
+            _ => {
+                let (from, to) = (a.ceil(), b.ceil());
+                if !from.is_finite() || !to.is_finite() || to - from > MAX_RANGE {
+                    return Err(format!("the range {}..{} is too long", a, b));
+                }
+                let len = (to - from).max(0.0) as usize;
+                Array((0..len).map(|i| Number(from + i as f64)).collect())
+            }
+        },
+        (op, a, b) => {
+            let symbol = match op {
+                Op::Add => "add",
+                Op::Sub => "subtract",
+                Op::Mul => "multiply",
+                Op::Div => "divide",
+                Op::Rem => "take the remainder of",
+                Op::Range => "make a range of",
+                _ => "compare",
+            };
+            return Err(format!(
+                "can't {} {} and {}",
+                symbol,
+                describe(&a),
+                describe(&b)
+            ));
+        }
+    })
+}
+
+/// The strings one after the other, unless that makes one too long.
+fn join(a: String, b: &str) -> Result<Value, String> {
+    if a.len() + b.len() > MAX_LENGTH {
+        return Err(too_long("string"));
+    }
+    Ok(Value::String(a + b))
+}
+
+fn too_long(what: &str) -> String {
+    format!("the {} would be longer than {}", what, MAX_LENGTH)
+}
+
+/// How many items and bytes make up the value, and those of the values
+/// within it, `depth` values in already.
+fn size(value: &Value, depth: usize) -> Result<usize, String> {
+    if depth > MAX_DEPTH {
+        return Err("the value is nested too deeply".to_string());
+    }
+    Ok(match value {
+        Value::String(s) => s.len(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| Ok(1 + size(item, depth + 1)?))
+            .sum::<Result<_, String>>()?,
+        Value::Object(members) => members
+            .iter()
+            .map(|(key, value)| Ok(1 + key.len() + size(value, depth + 1)?))
+            .sum::<Result<_, String>>()?,
+        _ => 0,
+    })
+}
+
+/// A value as it is joined to a string.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => describe(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_scene_from_its_variables() {
+        let source = r#"
+            // Every other sphere in ivory
+            let objects = [];
+            let count = 0;
+            for i in 0..4 {
+                let material = if i % 2 == 0 { "ivory" } else { "red_rubber" };
+                objects.push(#{ type: "sphere", center: [i, 0, -5], radius: 0.5, material: material });
+                count += 1;
+            }
+            objects[3].radius = count / 8;
+            let lights = [#{ position: [0, 5, 0], intensity: time * 2 }];
+        "#;
+        let scene = run(source, 0.5).unwrap();
+        let objects = scene.get("objects").and_then(Value::as_array).unwrap();
+        assert_eq!(objects.len(), 4);
+        assert_eq!(
+            objects[1].get("material"),
+            Some(&Value::String("red_rubber".into()))
+        );
+        assert_eq!(objects[3].get("radius"), Some(&Value::Number(0.5)));
+        let intensity = scene
+            .get("lights")
+            .and_then(|l| l.as_array()?[0].get("intensity"));
+        assert_eq!(intensity, Some(&Value::Number(1.0)));
+        assert!(scene.get("count").is_none());
+    }
+
+    #[test]
+    fn says_where_the_script_fails() {
+        let error = |source| run(source, 0.0).unwrap_err().to_string();
+        assert_eq!(
+            error("let x = 1;\nlet y = [1, 2;"),
+            "script 2:14: expected `]`"
+        );
+        assert_eq!(
+            error("let x = 1;\n\nx = x + \"a\" * 2;"),
+            "script line 3: can't multiply \"a\" and 2"
+        );
+        assert_eq!(
+            error("while true { }"),
+            "script line 1: the script ran too long"
+        );
+    }
+
+    #[test]
+    fn stops_values_growing_without_bound() {
+        let error = |source| run(source, 0.0).unwrap_err().to_string();
+        assert_eq!(
+            error("let s = \"xxxxxxxx\";\nfor i in 0..45 { s = s + s; }"),
+            "script line 2: the string would be longer than 1048576"
+        );
+        assert_eq!(
+            error("let a = [0];\nfor i in 0..45 { a += a; }"),
+            "script line 2: the array would be longer than 1048576"
+        );
+        assert_eq!(
+            error("let a = (0..1000000) + (0..48576);\na.push(0);"),
+            "script line 2: the array would be longer than 1048576"
+        );
+        // Arrays of copies of one another get no longer, only larger
+        assert!(error("let a = [0];\nfor i in 0..45 { a = [a, a]; }").contains("line 2"));
+        assert_eq!(
+            error("let a = [];\nfor i in 0..1000 { a = [a]; }"),
+            "script line 2: the value is nested too deeply"
+        );
+    }
+}
@@ -0,0 +1,509 @@
+//! A small HTTP service rendering the scenes posted to it, one at a time, so
+//! that the tracer can back a web page or be called from other programs:
+//!
+//! - `POST /jobs` with a scene description as the body queues it, answering
+//!   `201 Created` with the status of the job and its `Location`
+//! - `GET /jobs/ID` answers the status of the job as JSON, such as
+//!   `{"id": 1, "status": "rendering", "progress": 0.25}`; the status is
+//!   `queued`, `rendering`, `done` or `failed`, the last along with an
+//!   `error`
+//! - `GET /jobs/ID/image` answers the finished image, or `409 Conflict`
+//!   until it is done
+//! - `DELETE /jobs/ID` stops the job if it hasn't finished, and forgets it
+//!
+//! Every answer allows any origin, for pages served from elsewhere. Requests
+//! are read with a limit on their size, and the connection closed after
+//! each. Only so many connections are answered and so many jobs may wait at
+//! once, answering `503 Service Unavailable` beyond that, and only the last
+//! few finished are kept for their images; older ones are forgotten as if
+//! deleted.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error;
+use crate::vector::{to_f64, Float};
+
+/// Longest scene description that may be posted
+const MAX_BODY: usize = 16 << 20;
+/// Longest request line and headers
+const MAX_HEAD: u64 = 64 << 10;
+/// How long a connection may take to send its request
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Most jobs waiting to be rendered
+const MAX_QUEUED: usize = 64;
+/// Most finished jobs kept, with their images or errors
+const MAX_FINISHED: usize = 16;
+/// Most connections answered at once, each on a thread of its own
+const MAX_CONNECTIONS: usize = 64;
+
+enum Status {
+    Queued,
+    Rendering,
+    /// The encoded image
+    Done(Vec<u8>),
+    Failed(String),
+}
+
+struct Job {
+    /// The scene description, until the job is rendered
+    source: String,
+    status: Status,
+    /// The fraction of the image rendered so far
+    progress: Float,
+    cancel: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: HashMap<usize, Job>,
+    queue: VecDeque<usize>,
+    /// The jobs done or failed, oldest first
+    finished: VecDeque<usize>,
+    last_id: usize,
+}
+
+impl Jobs {
+    fn forget(&mut self, id: usize) {
+        self.jobs.remove(&id);
+        self.queue.retain(|&queued| queued != id);
+        self.finished.retain(|&finished| finished != id);
+    }
+}
+
+type Shared = Arc<(Mutex<Jobs>, Condvar)>;
+
+/// A string as a JSON string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn status_json(id: usize, job: &Job) -> String {
+    let (status, error) = match &job.status {
+        Status::Queued => ("queued", None),
+        Status::Rendering => ("rendering", None),
+        Status::Done(_) => ("done", None),
+        Status::Failed(message) => ("failed", Some(message)),
+    };
+    // To a hundredth of a percent, rather than all the digits of a float
+    let progress = (to_f64(job.progress) * 1e4).round() / 1e4;
+    let mut json = format!(
+        "{{\"id\": {}, \"status\": \"{}\", \"progress\": {}",
+        id, status, progress
+    );
+    if let Some(message) = error {
+        json += &format!(", \"error\": {}", quote(message));
+    }
+    json + "}"
+}
+
+fn error_json(message: &str) -> Vec<u8> {
+    format!("{{\"error\": {}}}", quote(message)).into_bytes()
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    headers: Vec<String>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            headers: vec![],
+            body: body.into_bytes(),
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            body: error_json(message),
+            ..Self::json(status, String::new())
+        }
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\nConnection: close\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        );
+        for header in &self.headers {
+            head += header;
+            head += "\r\n";
+        }
+        head += "\r\n";
+        w.write_all(head.as_bytes())?;
+        w.write_all(&self.body)?;
+        w.flush()
+    }
+}
+
+/// The method, path and body of a request, or the answer to give if it
+/// can't be read.
+fn read_request<R: Read>(stream: R) -> Result<(String, String, Vec<u8>), Response> {
+    let bad = |_| Response::error("400 Bad Request", "malformed request");
+    let too_large = || {
+        Response::error(
+            "431 Request Header Fields Too Large",
+            "request line and headers too long",
+        )
+    };
+    let mut reader = BufReader::new(stream).take(MAX_HEAD);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(bad)?;
+    let mut words = line.split_whitespace();
+    let (method, path) = match (words.next(), words.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(Response::error("400 Bad Request", "malformed request")),
+    };
+    let mut length = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(bad)? == 0 {
+            if reader.limit() == 0 {
+                return Err(too_large());
+            }
+            return Err(Response::error("400 Bad Request", "malformed request"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse().map_err(|_| {
+                    Response::error("400 Bad Request", "malformed `Content-Length`")
+                })?);
+            }
+        }
+    }
+    let length = match length {
+        Some(length) => length,
+        None if method == "POST" => {
+            return Err(Response::error(
+                "411 Length Required",
+                "a `Content-Length` is needed",
+            ))
+        }
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Response::error("413 Payload Too Large", "scene too large"));
+    }
+    let mut reader = reader.into_inner();
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(bad)?;
+    Ok((method, path, body))
+}
+
+fn respond(
+    shared: &Shared,
+    (method, path, body): (String, String, Vec<u8>),
+    image_type: &'static str,
+) -> Response {
+    let (jobs, queued) = &**shared;
+    let path = path.split('?').next().unwrap_or_default();
+    let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+    let id = segments.get(1).and_then(|id| id.parse::<usize>().ok());
+    match (&method[..], &segments[..]) {
+        // Preflight requests of pages elsewhere, before they post
+        ("OPTIONS", _) => Response {
+            status: "204 No Content",
+            headers: vec![
+                "Access-Control-Allow-Methods: GET, POST, DELETE".to_string(),
+                "Access-Control-Allow-Headers: Content-Type".to_string(),
+            ],
+            ..Response::json("204 No Content", String::new())
+        },
+        ("POST", ["jobs"]) => {
+            let source = match String::from_utf8(body) {
+                Ok(source) => source,
+                Err(_) => return Response::error("400 Bad Request", "scene isn't UTF-8"),
+            };
+            let mut jobs = jobs.lock().unwrap();
+            if jobs.queue.len() >= MAX_QUEUED {
+                return Response::error("503 Service Unavailable", "too many jobs queued");
+            }
+            jobs.last_id += 1;
+            let id = jobs.last_id;
+            let job = Job {
+                source,
+                status: Status::Queued,
+                progress: 0.0,
+                cancel: Arc::new(AtomicBool::new(false)),
+            };
+            let json = status_json(id, &job);
+            jobs.jobs.insert(id, job);
+            jobs.queue.push_back(id);
+            queued.notify_one();
+            Response {
+                headers: vec![format!("Location: /jobs/{}", id)],
+                ..Response::json("201 Created", json)
+            }
+        }
+        (_, ["jobs"]) => Response::error("405 Method Not Allowed", "only POST is allowed"),
+        (_, ["jobs", _]) | (_, ["jobs", _, "image"]) if id.is_some() => {
+            let id = id.unwrap();
+            let mut jobs = jobs.lock().unwrap();
+            let job = match jobs.jobs.get(&id) {
+                Some(job) => job,
+                None => return Response::error("404 Not Found", "no such job"),
+            };
+            match (&method[..], segments.len()) {
+                ("GET", 2) => Response::json("200 OK", status_json(id, job)),
+                ("GET", _) => match &job.status {
+                    Status::Done(image) => Response {
+                        status: "200 OK",
+                        content_type: image_type,
+                        headers: vec![],
+                        body: image.clone(),
+                    },
+                    _ => Response::error("409 Conflict", "the job isn't done"),
+                },
+                ("DELETE", 2) => {
+                    job.cancel.store(true, Ordering::SeqCst);
+                    jobs.forget(id);
+                    Response::json("204 No Content", String::new())
+                }
+                _ => Response::error("405 Method Not Allowed", "method not allowed"),
+            }
+        }
+        _ => Response::error("404 Not Found", "no such resource"),
+    }
+}
+
+/// Counts a connection as being answered for as long as it lives.
+struct Answering(Arc<AtomicUsize>);
+
+impl Drop for Answering {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Answers the requests of the connections to the listener, each on a
+/// thread of its own, turning down those beyond `MAX_CONNECTIONS` at once.
+fn answer(listener: TcpListener, shared: Shared, image_type: &'static str) {
+    let answering = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming().flatten() {
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let _ = stream.set_write_timeout(Some(TIMEOUT));
+        if answering.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            answering.fetch_sub(1, Ordering::SeqCst);
+            let busy = Response::error("503 Service Unavailable", "too many connections");
+            let _ = busy.write(&mut &stream);
+            continue;
+        }
+        let (shared, answering) = (Arc::clone(&shared), Answering(Arc::clone(&answering)));
+        thread::spawn(move || {
+            let _answering = answering;
+            let response = match read_request(&stream) {
+                Ok(request) => respond(&shared, request, image_type),
+                Err(response) => response,
+            };
+            let _ = response.write(&mut &stream);
+        });
+    }
+}
+
+/// Answers requests at the listener while rendering the jobs posted to it
+/// in turn, never returning. `render` renders a scene description into an
+/// image encoded as `image_type`, such as `image/png`, reporting the
+/// fraction done as it goes, and stops early without an image once its
+/// flag is set.
+pub fn serve<F>(listener: TcpListener, image_type: &'static str, mut render: F) -> !
+where
+    F: FnMut(&str, &Arc<AtomicBool>, &mut dyn FnMut(Float)) -> error::Result<Vec<u8>>,
+{
+    let shared: Shared = Arc::default();
+    let connections = Arc::clone(&shared);
+    thread::spawn(move || answer(listener, connections, image_type));
+
+    let (jobs, queued) = &*shared;
+    loop {
+        let (id, source, cancel) = {
+            let mut jobs = jobs.lock().unwrap();
+            let id = loop {
+                match jobs.queue.pop_front() {
+                    Some(id) => break id,
+                    None => jobs = queued.wait(jobs).unwrap(),
+                }
+            };
+            let job = jobs.jobs.get_mut(&id).unwrap();
+            job.status = Status::Rendering;
+            (id, std::mem::take(&mut job.source), Arc::clone(&job.cancel))
+        };
+        let result = render(&source, &cancel, &mut |progress| {
+            if let Some(job) = jobs.lock().unwrap().jobs.get_mut(&id) {
+                job.progress = progress;
+            }
+        });
+        // A job deleted while it rendered is gone already
+        let mut jobs = jobs.lock().unwrap();
+        if let Some(job) = jobs.jobs.get_mut(&id) {
+            job.status = match result {
+                Ok(image) => {
+                    job.progress = 1.0;
+                    Status::Done(image)
+                }
+                Err(e) => Status::Failed(e.to_string()),
+            };
+            jobs.finished.push_back(id);
+            while jobs.finished.len() > MAX_FINISHED {
+                let oldest = jobs.finished[0];
+                jobs.forget(oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+
+    /// The status a request is answered with, if it can't be read.
+    fn refused(request: &[u8]) -> Option<&'static str> {
+        read_request(request).err().map(|response| response.status)
+    }
+
+    #[test]
+    fn reads_requests() {
+        let request = b"POST /jobs HTTP/1.1\r\nHost: localhost\r\ncontent-length: 2\r\n\r\n{}";
+        let (method, path, body) = read_request(&request[..]).ok().unwrap();
+        assert_eq!(
+            (&method[..], &path[..], &body[..]),
+            ("POST", "/jobs", &b"{}"[..])
+        );
+        // Only posts need a length
+        let (method, _, body) = read_request(&b"GET /jobs/1 HTTP/1.1\r\n\r\n"[..])
+            .ok()
+            .unwrap();
+        assert!(method == "GET" && body.is_empty());
+
+        assert_eq!(refused(b""), Some("400 Bad Request"));
+        assert_eq!(refused(b"GET\r\n\r\n"), Some("400 Bad Request"));
+        assert_eq!(
+            refused(b"GET /jobs HTTP/1.1\r\nHost: x\r\n"),
+            Some("400 Bad Request")
+        );
+        assert_eq!(
+            refused(b"POST /jobs HTTP/1.1\r\n\r\n"),
+            Some("411 Length Required")
+        );
+        for length in &["-1", "two", ""] {
+            let request = format!("POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n", length);
+            assert_eq!(refused(request.as_bytes()), Some("400 Bad Request"));
+        }
+        // A body shorter than its length
+        let request = b"POST /jobs HTTP/1.1\r\nContent-Length: 5\r\n\r\n{}";
+        assert_eq!(refused(request), Some("400 Bad Request"));
+    }
+
+    #[test]
+    fn limits_the_size_of_requests() {
+        let post =
+            |length: usize| format!("POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n", length);
+        let mut request = post(MAX_BODY).into_bytes();
+        request.resize(request.len() + MAX_BODY, b' ');
+        assert!(read_request(&request[..]).is_ok());
+        assert_eq!(
+            refused(post(MAX_BODY + 1).as_bytes()),
+            Some("413 Payload Too Large")
+        );
+
+        let header = format!("X-Padding: {}\r\n", "x".repeat(MAX_HEAD as usize));
+        let request = format!("GET /jobs/1 HTTP/1.1\r\n{}\r\n", header);
+        assert_eq!(
+            refused(request.as_bytes()),
+            Some("431 Request Header Fields Too Large")
+        );
+    }
+
+    #[test]
+    fn answers_what_it_can_and_refuses_the_rest() {
+        let shared = Shared::default();
+        let request = |method: &str, path: &str, body: &[u8]| {
+            let request = (method.to_string(), path.to_string(), body.to_vec());
+            respond(&shared, request, "image/png")
+        };
+
+        let created = request("POST", "/jobs", b"{}");
+        assert_eq!(created.status, "201 Created");
+        assert_eq!(created.headers, ["Location: /jobs/1"]);
+        assert_eq!(request("GET", "/jobs/1", b"").status, "200 OK");
+        assert_eq!(request("GET", "/jobs/1/image", b"").status, "409 Conflict");
+        assert_eq!(request("GET", "/jobs/2", b"").status, "404 Not Found");
+        assert_eq!(request("GET", "/jobs/one", b"").status, "404 Not Found");
+        assert_eq!(request("GET", "/scenes", b"").status, "404 Not Found");
+        assert_eq!(
+            request("GET", "/jobs", b"").status,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            request("PUT", "/jobs/1", b"").status,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(request("DELETE", "/jobs/1", b"").status, "204 No Content");
+        assert_eq!(request("GET", "/jobs/1", b"").status, "404 Not Found");
+
+        for _ in 0..MAX_QUEUED {
+            assert_eq!(request("POST", "/jobs", b"{}").status, "201 Created");
+        }
+        assert_eq!(
+            request("POST", "/jobs", b"{}").status,
+            "503 Service Unavailable"
+        );
+    }
+
+    #[test]
+    fn turns_down_connections_beyond_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || answer(listener, Shared::default(), "image/png"));
+        // Connections that send nothing hold their threads until they close
+        let waiting: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        let mut answer = String::new();
+        let mut busy = TcpStream::connect(address).unwrap();
+        busy.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 503"), "{}", answer);
+
+        drop(waiting);
+        // The threads let go of the closed connections in a moment
+        let answered = (0..100).any(|_| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"GET /jobs/1 HTTP/1.1\r\n\r\n").unwrap();
+            let mut answer = String::new();
+            stream.read_to_string(&mut answer).unwrap();
+            answer.starts_with("HTTP/1.1 404") || {
+                thread::sleep(Duration::from_millis(10));
+                false
+            }
+        });
+        assert!(answered);
+    }
+}
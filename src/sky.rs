@@ -0,0 +1,221 @@
+//! A clear daytime sky worked out from where the sun is, after the model of
+//! Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight"
+//! (1999). The sky's brightness and color follow the Perez formula fitted to
+//! the turbidity, how hazy the air is; the sun itself is a directional light
+//! dimmed and reddened by the air it shines through, and the ground below the
+//! horizon reflects the light of both.
+
+use crate::background::EnvironmentMap;
+use crate::light::DirectionalLight;
+use crate::render::Image;
+use crate::vector::{consts::PI, Float, Vec3f};
+
+/// Scales the luminance of the model, in kcd/m², to colors of about one for
+/// the sky overhead at midday.
+const SKY_SCALE: Float = 0.05;
+/// Intensity of the sun before the air dims it.
+const SUN_INTENSITY: Float = 3.0;
+/// Size of the image of the sky that is sampled for its light.
+const MAP_WIDTH: usize = 128;
+const MAP_HEIGHT: usize = 64;
+
+/// The five coefficients of the Perez formula for one of luminance and the
+/// two chromaticities, fitted linearly to the turbidity.
+fn perez_coefficients(turbidity: Float, fit: [(Float, Float); 5]) -> [Float; 5] {
+    let mut coefficients = [0.0; 5];
+    for (coefficient, (slope, offset)) in coefficients.iter_mut().zip(fit.iter()) {
+        *coefficient = slope * turbidity + offset;
+    }
+    coefficients
+}
+
+/// The Perez formula for the sky at `cos_theta` from the zenith and `gamma`
+/// from the sun.
+fn perez([a, b, c, d, e]: [Float; 5], cos_theta: Float, gamma: Float) -> Float {
+    let cos_gamma = gamma.cos();
+    (1.0 + a * (b / cos_theta.max(0.01)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// The linear sRGB color of the luminance `big_y` at the chromaticity `x`,
+/// `y`.
+pub(crate) fn xyy_to_rgb(x: Float, y: Float, big_y: Float) -> Vec3f {
+    let (big_x, big_z) = (x / y * big_y, (1.0 - x - y) / y * big_y);
+    Vec3f::new(
+        3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z,
+    )
+}
+
+/// The sky above the horizon.
+struct Model {
+    /// Unit direction towards the sun
+    sun: Vec3f,
+    /// Angle of the sun from the zenith, kept above the horizon
+    sun_theta: Float,
+    /// Perez coefficients and zenith values of the luminance and the two
+    /// chromaticities
+    coefficients: [[Float; 5]; 3],
+    zenith: [Float; 3],
+    /// Scales the luminance to colors
+    scale: Float,
+}
+
+impl Model {
+    /// The color of the sky along a unit direction above the horizon.
+    fn color(&self, dir: &Vec3f) -> Vec3f {
+        let cos_theta = dir.1.max(0.0);
+        let gamma = dir.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let [big_y, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez(self.coefficients[i], cos_theta, gamma)
+                / perez(self.coefficients[i], 1.0, self.sun_theta)
+        });
+        let color = xyy_to_rgb(x, y, big_y) * self.scale;
+        color.max(&Vec3f::new(0.0, 0.0, 0.0))
+    }
+}
+
+pub struct Sky {
+    model: Model,
+    /// The light of the sun once it is through the air
+    sun_intensity: Vec3f,
+    /// Color of everything below the horizon
+    ground: Vec3f,
+    /// The sky drawn out, to sample its light from
+    map: EnvironmentMap,
+}
+
+impl Sky {
+    /// The sky with the sun in the unit direction `sun`, through air of the
+    /// given turbidity, from 2 for very clear air to 10 or so for haze, over
+    /// ground of the given albedo.
+    pub fn new(sun: Vec3f, turbidity: Float, ground_albedo: Vec3f, intensity: Float) -> Self {
+        let t = turbidity;
+        let sun = sun.normalize();
+        let sun_theta = sun.1.clamp(0.0, 1.0).acos();
+        let coefficients = [
+            perez_coefficients(
+                t,
+                [
+                    (0.1787, -1.4630),
+                    (-0.3554, 0.4275),
+                    (-0.0227, 5.3251),
+                    (0.1206, -2.5771),
+                    (-0.0670, 0.3703),
+                ],
+            ),
+            perez_coefficients(
+                t,
+                [
+                    (-0.0193, -0.2592),
+                    (-0.0665, 0.0008),
+                    (-0.0004, 0.2125),
+                    (-0.0641, -0.8989),
+                    (-0.0033, 0.0452),
+                ],
+            ),
+            perez_coefficients(
+                t,
+                [
+                    (-0.0167, -0.2608),
+                    (-0.0950, 0.0092),
+                    (-0.0079, 0.2102),
+                    (-0.0441, -1.6537),
+                    (-0.0109, 0.0529),
+                ],
+            ),
+        ];
+
+        // The zenith, from the fits of the paper's appendix
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s, s2, s3) = (sun_theta, sun_theta * sun_theta, sun_theta.powi(3));
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        // Rayleigh and aerosol extinction through the air mass the sun
+        // shines through, at wavelengths standing in for each channel
+        let sun_intensity = if sun.1 > 0.0 {
+            let air_mass =
+                1.0 / (sun_theta.cos() + 0.15 * (93.885 - sun_theta.to_degrees()).powf(-1.253));
+            let beta = 0.04608 * t - 0.04586;
+            let transmittance = |wavelength: Float| {
+                (-(0.008735 * wavelength.powf(-4.08) + beta * wavelength.powf(-1.3)) * air_mass)
+                    .exp()
+            };
+            Vec3f::new(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44),
+            ) * (SUN_INTENSITY * intensity)
+        } else {
+            Vec3f::new(0.0, 0.0, 0.0)
+        };
+
+        let model = Model {
+            sun,
+            sun_theta,
+            coefficients,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            scale: SKY_SCALE * intensity,
+        };
+
+        // Draw the sky, adding up the light it sheds on the ground on the way,
+        // and then the ground lit by it and by the sun
+        let mut image = Image::new(MAP_WIDTH, MAP_HEIGHT);
+        let mut irradiance = Vec3f::new(0.0, 0.0, 0.0);
+        let solid_angle = 2.0 * PI * PI / (MAP_WIDTH * MAP_HEIGHT) as Float;
+        for y in 0..MAP_HEIGHT / 2 {
+            for x in 0..MAP_WIDTH {
+                let (u, v) = (
+                    (x as Float + 0.5) / MAP_WIDTH as Float,
+                    (y as Float + 0.5) / MAP_HEIGHT as Float,
+                );
+                let (dir, sin) = EnvironmentMap::direction(u, v);
+                let color = model.color(&dir);
+                irradiance += color * (dir.1 * sin * solid_angle);
+                image.pixels[y * MAP_WIDTH + x] = color.into();
+            }
+        }
+        let sun_irradiance = sun_intensity * (PI * sun.1.max(0.0));
+        let ground = ground_albedo * (irradiance + sun_irradiance) * (1.0 / PI);
+        for pixel in &mut image.pixels[MAP_WIDTH * MAP_HEIGHT / 2..] {
+            *pixel = ground.into();
+        }
+        Self {
+            model,
+            sun_intensity,
+            ground,
+            map: EnvironmentMap::new(image, 1.0),
+        }
+    }
+
+    /// The color seen along the unit direction `dir`, leaving out the sun,
+    /// which lights the scene as the light `sun_light` gives.
+    pub fn color(&self, dir: &Vec3f) -> Vec3f {
+        if dir.1 < 0.0 {
+            self.ground
+        } else {
+            self.model.color(dir)
+        }
+    }
+
+    /// The sun, as a directional light of the given angular diameter in
+    /// radians.
+    pub fn sun_light(&self, angular_diameter: Float) -> DirectionalLight {
+        DirectionalLight::new(-self.model.sun, self.sun_intensity, angular_diameter)
+    }
+
+    pub(crate) fn sample(&self, uv: (Float, Float)) -> Option<(Vec3f, Float)> {
+        self.map.sample(uv)
+    }
+
+    pub(crate) fn pdf(&self, dir: &Vec3f) -> Option<Float> {
+        self.map.pdf(dir)
+    }
+}
@@ -0,0 +1,82 @@
+//! Single wavelengths of light as the colors they add to an image, for the
+//! spectral integrator.
+//!
+//! Each sample of a pixel follows one wavelength, picked evenly over the
+//! visible ones, and adds the color of that wavelength to the pixel by the
+//! CIE 1931 color matching functions, as fitted by Wyman, Sloan and Shirley.
+//! The colors are scaled so that light of every wavelength together adds up
+//! to white, so that the scene looks as the path tracer renders it except
+//! where glass bends each wavelength its own way.
+
+use crate::vector::{Float, Vec3f};
+
+/// The shortest and longest wavelengths followed, in nanometres
+pub const VISIBLE: (Float, Float) = (380.0, 720.0);
+
+/// What the colors of the wavelengths add up to over the visible ones,
+/// before scaling them to white
+const WHITE: [Float; 3] = [1.9298, 2.9469, 3.1108];
+
+/// The wavelength in nanometres at `u` of the way from the shortest visible
+/// one to the longest.
+pub fn wavelength(u: Float) -> Float {
+    VISIBLE.0 + (VISIBLE.1 - VISIBLE.0) * u
+}
+
+/// The linear sRGB color light of `wavelength` nanometres adds, scaled so
+/// that wavelengths picked evenly average to white. Colors outside sRGB are
+/// cut off at its edge.
+pub fn rgb(wavelength: Float) -> Vec3f {
+    let (x, y, z) = xyz(wavelength);
+    let channel = |value: Float, white: Float| value.max(0.0) * white;
+    Vec3f::new(
+        channel(3.2406 * x - 1.5372 * y - 0.4986 * z, WHITE[0]),
+        channel(-0.9689 * x + 1.8758 * y + 0.0415 * z, WHITE[1]),
+        channel(0.0557 * x - 0.2040 * y + 1.0570 * z, WHITE[2]),
+    )
+}
+
+/// The CIE 1931 color matching functions at `wavelength` nanometres, as
+/// sums of lopsided Gaussians.
+fn xyz(wavelength: Float) -> (Float, Float, Float) {
+    let lobe = |mean: Float, below: Float, above: Float| {
+        let spread = if wavelength < mean { below } else { above };
+        (-0.5 * ((wavelength - mean) / spread).powi(2)).exp()
+    };
+    (
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wavelengths_average_to_white() {
+        let count = 1000;
+        let sum = (0..count)
+            .map(|i| rgb(wavelength((i as Float + 0.5) / count as Float)))
+            .fold(Vec3f::new(0.0, 0.0, 0.0), |sum, color| sum + color);
+        for channel in <[Float; 3]>::from(sum * (1.0 / count as Float)).iter() {
+            assert!((channel - 1.0).abs() < 0.01, "{:?}", channel);
+        }
+    }
+
+    #[test]
+    fn short_wavelengths_are_blue_and_long_ones_red() {
+        let (blue, green, red) = (rgb(450.0), rgb(530.0), rgb(650.0));
+        assert!(blue.2 > blue.0 && blue.2 > blue.1);
+        assert!(green.1 > green.0 && green.1 > green.2);
+        assert!(red.0 > red.1 && red.0 > red.2);
+        let infrared = <[Float; 3]>::from(rgb(1000.0));
+        assert!(
+            infrared.iter().all(|&channel| channel < 1e-3),
+            "{:?}",
+            infrared
+        );
+    }
+}
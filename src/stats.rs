@@ -0,0 +1,137 @@
+//! Counts of the work a render does, to measure how optimizations pay off.
+//!
+//! Rays and tests are counted on the thread doing them, which costs next to
+//! nothing, and only added up when the render threads finish.
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::AddAssign;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How many rays were traced and tests made, and how long each stage of
+/// turning a scene into an image took.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Rays from the camera
+    pub primary_rays: u64,
+    /// Rays checking whether something is in the way of a light
+    pub shadow_rays: u64,
+    /// Rays bouncing off or passing through surfaces
+    pub secondary_rays: u64,
+    /// Nodes of the acceleration structures, or cells of grids, visited
+    pub node_visits: u64,
+    /// Rays tested against a triangle, on their own or of meshes
+    pub triangle_tests: u64,
+    pub loading: Duration,
+    pub rendering: Duration,
+    pub writing: Duration,
+}
+
+impl Stats {
+    const ZERO: Stats = Stats {
+        primary_rays: 0,
+        shadow_rays: 0,
+        secondary_rays: 0,
+        node_visits: 0,
+        triangle_tests: 0,
+        loading: Duration::ZERO,
+        rendering: Duration::ZERO,
+        writing: Duration::ZERO,
+    };
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.shadow_rays + self.secondary_rays
+    }
+
+    /// The stats as a JSON object, with the times in seconds.
+    pub fn to_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\"primary_rays\": {}, \"shadow_rays\": {}, \"secondary_rays\": {}, ",
+                "\"node_visits\": {}, \"triangle_tests\": {}, ",
+                "\"loading\": {}, \"rendering\": {}, \"writing\": {}}}"
+            ),
+            self.primary_rays,
+            self.shadow_rays,
+            self.secondary_rays,
+            self.node_visits,
+            self.triangle_tests,
+            self.loading.as_secs_f64(),
+            self.rendering.as_secs_f64(),
+            self.writing.as_secs_f64()
+        )
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.primary_rays += other.primary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.node_visits += other.node_visits;
+        self.triangle_tests += other.triangle_tests;
+        self.loading += other.loading;
+        self.rendering += other.rendering;
+        self.writing += other.writing;
+    }
+}
+
+/// A summary over several lines, with the rays traced per second of
+/// rendering.
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "primary rays    {:>14}", self.primary_rays)?;
+        writeln!(f, "shadow rays     {:>14}", self.shadow_rays)?;
+        writeln!(f, "secondary rays  {:>14}", self.secondary_rays)?;
+        writeln!(f, "node visits     {:>14}", self.node_visits)?;
+        writeln!(f, "triangle tests  {:>14}", self.triangle_tests)?;
+        writeln!(f, "loading         {:>13.3}s", self.loading.as_secs_f64())?;
+        writeln!(f, "rendering       {:>13.3}s", self.rendering.as_secs_f64())?;
+        writeln!(f, "writing         {:>13.3}s", self.writing.as_secs_f64())?;
+        let seconds = self.rendering.as_secs_f64();
+        if seconds > 0.0 {
+            write!(f, "rays per second {:>14.0}", self.rays() as f64 / seconds)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// The work done on this thread since it was last added to the total
+    static COUNTS: Cell<Stats> = const { Cell::new(Stats::ZERO) };
+}
+
+/// The work of the threads that have finished
+static TOTAL: Mutex<Stats> = Mutex::new(Stats::ZERO);
+
+/// Counts work done on this thread, or time spent on a stage.
+pub fn count(f: impl FnOnce(&mut Stats)) {
+    COUNTS.with(|counts| {
+        let mut stats = counts.get();
+        f(&mut stats);
+        counts.set(stats);
+    });
+}
+
+/// Runs `f`, returning what it returns and the work it did on this thread,
+/// which is left out of the counts.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Stats) {
+    let before = COUNTS.with(|counts| counts.replace(Stats::ZERO));
+    let result = f();
+    (result, COUNTS.with(|counts| counts.replace(before)))
+}
+
+/// Adds the work done on this thread to the total, for threads about to
+/// finish.
+pub(crate) fn flush() {
+    let stats = COUNTS.with(|counts| counts.replace(Stats::ZERO));
+    *TOTAL.lock().unwrap() += stats;
+}
+
+/// The work done so far by this thread and the render threads, starting
+/// the count over.
+pub fn take() -> Stats {
+    flush();
+    std::mem::take(&mut *TOTAL.lock().unwrap())
+}
@@ -0,0 +1,131 @@
+//! Temporal accumulation: the frames of an animation whose camera holds
+//! still over parts of the scene that don't move see the same thing there
+//! from one frame to the next, so the passes those parts had in the frame
+//! before are kept rather than rendered again. A tile is kept when every
+//! one of its pixels looks along the same ray as before and sees the same
+//! point of the same object, or nothing, through its center.
+//!
+//! Only what the camera sees is compared, so the light the tiles kept were
+//! rendered in is taken to stay as it was: lights that move or change, and
+//! the shadows and reflections of moving objects falling on still ones, are
+//! only rendered again where a tile moved for the camera too.
+
+use crate::object::Hittable;
+use crate::render::{tiles, RenderState};
+use crate::scene::Scene;
+use crate::vector::{Float, Vec3f};
+
+/// How far rays and hits may have moved and still count as the same, as a
+/// fraction of how far the hit is, or of a unit for rays seeing nothing
+const TOLERANCE: Float = 1e-4;
+
+/// What the camera saw through the center of each pixel: the ray and, if
+/// it hit something, the index of the object and the point hit.
+struct Sighting {
+    origin: Vec3f,
+    dir: Vec3f,
+    hit: Option<(usize, Vec3f)>,
+}
+
+impl Sighting {
+    fn same(&self, other: &Sighting) -> bool {
+        let reach = match self.hit {
+            Some((_, point)) => point.distance(&self.origin).max(1.0),
+            None => 1.0,
+        };
+        let close = |a: &Vec3f, b: &Vec3f, scale: Float| a.distance(b) <= TOLERANCE * scale;
+        let hits = match (self.hit, other.hit) {
+            (Some((a, p)), Some((b, q))) => a == b && close(&p, &q, reach),
+            (None, None) => true,
+            _ => false,
+        };
+        hits && close(&self.origin, &other.origin, reach) && close(&self.dir, &other.dir, 1.0)
+    }
+}
+
+/// What the camera of a frame saw in each pixel, for telling which tiles
+/// of the next frame look the same.
+pub struct FrameView {
+    width: usize,
+    height: usize,
+    /// In rows from the top left, `None` for pixels that see nothing
+    sightings: Vec<Option<Sighting>>,
+}
+
+impl FrameView {
+    /// Traces a ray through the center of each pixel of an image of `width`
+    /// by `height` pixels.
+    pub fn capture(scene: &Scene, (width, height): (usize, usize)) -> Self {
+        let sightings = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let center = (x as Float + 0.5, y as Float + 0.5);
+                let ray = scene.camera.ray(center, (0.5, 0.5), width, height)?;
+                let hit = scene
+                    .hit(&ray)
+                    .map(|hit| (hit.handle.map_or(usize::MAX, |h| h.index()), hit.point));
+                Some(Sighting {
+                    origin: ray.origin,
+                    dir: ray.dir,
+                    hit,
+                })
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            sightings,
+        }
+    }
+
+    fn same_pixel(&self, other: &FrameView, i: usize) -> bool {
+        match (&self.sightings[i], &other.sightings[i]) {
+            (Some(a), Some(b)) => a.same(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Copies the tiles of the `previous` frame whose pixels saw the same in
+/// both views into the state of the next, passes and all, leaving the rest
+/// to be rendered. How many tiles were kept; none are if the frames differ
+/// in size or tiles.
+pub fn carry_over(
+    state: &mut RenderState,
+    previous: &RenderState,
+    (before, now): (&FrameView, &FrameView),
+) -> usize {
+    let (width, height) = (state.image.width, state.image.height);
+    if (
+        previous.image.width,
+        previous.image.height,
+        previous.tile_size,
+    ) != (width, height, state.tile_size)
+        || (before.width, before.height) != (width, height)
+        || (now.width, now.height) != (width, height)
+        || previous.image.aovs.len() != state.image.aovs.len()
+    {
+        return 0;
+    }
+    let mut kept = 0;
+    for (index, tile) in tiles(width, height, state.tile_size).iter().enumerate() {
+        let pixels: Vec<_> = (tile.y..tile.y + tile.height)
+            .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| y * width + x))
+            .collect();
+        if previous.tile_passes[index] == 0 || !pixels.iter().all(|&i| before.same_pixel(now, i)) {
+            continue;
+        }
+        for &i in &pixels {
+            state.image.pixels[i] = previous.image.pixels[i];
+            state.image.alpha[i] = previous.image.alpha[i];
+            for (aov, (_, values)) in state.image.aovs.iter_mut().enumerate() {
+                values[i] = previous.image.aovs[aov].1[i];
+            }
+        }
+        state.tile_passes[index] = previous.tile_passes[index];
+        state.tile_spread[index] = previous.tile_spread[index];
+        kept += 1;
+    }
+    kept
+}
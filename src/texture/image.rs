@@ -0,0 +1,102 @@
+use super::Texture;
+use crate::color::Color;
+use crate::render::Image;
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// An image wrapped around a surface, repeating beyond the unit square.
+/// Along with the image it keeps a chain of copies each half the size of
+/// the last, down to a single pixel, so that surfaces far enough away for
+/// many pixels of the image to shrink into one pixel of the render average
+/// them rather than picking a few and shimmering.
+pub struct ImageTexture {
+    /// The image, then each of its copies halved in size
+    levels: Vec<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        let mut levels = vec![image];
+        while let Some(level) = levels.last().and_then(halved) {
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    /// The color at the texture coordinates `uv`, interpolated between the
+    /// nearest pixels.
+    pub fn lookup(&self, uv: Vec2f) -> Vec3f {
+        bilinear(&self.levels[0], uv)
+    }
+
+    /// The color at the texture coordinates `uv` averaged over a patch
+    /// `footprint` across in texture coordinates, interpolated between the
+    /// two copies of the image whose pixels are nearest that size.
+    pub fn filtered(&self, uv: Vec2f, footprint: Float) -> Vec3f {
+        let image = &self.levels[0];
+        let pixels = footprint * image.width.max(image.height) as Float;
+        if pixels <= 1.0 {
+            return self.lookup(uv);
+        }
+        let level = pixels.log2().min((self.levels.len() - 1) as Float);
+        let below = level.floor() as usize;
+        let color = bilinear(&self.levels[below], uv);
+        match self.levels.get(below + 1) {
+            Some(above) => color.lerp(&bilinear(above, uv), level - below as Float),
+            None => color,
+        }
+    }
+}
+
+/// The image at half its size, each pixel averaging the two by two pixels
+/// it covers, or `None` once it is a single pixel. An odd row or column
+/// left over is averaged into the last pixel.
+fn halved(image: &Image) -> Option<Image> {
+    if image.width == 1 && image.height == 1 {
+        return None;
+    }
+    let (width, height) = ((image.width / 2).max(1), (image.height / 2).max(1));
+    let mut half = Image::new(width, height);
+    for y in 0..height {
+        let rows = y * 2..(y * 2 + 2 + (y + 1 == height) as usize).min(image.height);
+        for x in 0..width {
+            let columns = x * 2..(x * 2 + 2 + (x + 1 == width) as usize).min(image.width);
+            let count = (rows.len() * columns.len()) as Float;
+            let sum = rows
+                .clone()
+                .flat_map(|y| columns.clone().map(move |x| (x, y)))
+                .fold(Color::BLACK, |sum, (x, y)| {
+                    sum + image.pixels[y * image.width + x]
+                });
+            half.pixels[y * width + x] = sum * (1.0 / count);
+        }
+    }
+    Some(half)
+}
+
+/// The color of the image at the texture coordinates `uv`, interpolated
+/// between the nearest pixels.
+fn bilinear(image: &Image, uv: Vec2f) -> Vec3f {
+    let (width, height) = (image.width, image.height);
+    let x = uv.0 * width as Float - 0.5;
+    let y = (1.0 - uv.1) * height as Float - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let wrap = |i: Float, size: usize| (i as isize).rem_euclid(size as isize) as usize;
+    let (x0, x1) = (wrap(x0, width), wrap(x0 + 1.0, width));
+    let (y0, y1) = (wrap(y0, height), wrap(y0 + 1.0, height));
+    let pixel = |x: usize, y: usize| image.pixels[y * width + x];
+    let top = pixel(x0, y0).lerp(&pixel(x1, y0), tx);
+    let bottom = pixel(x0, y1).lerp(&pixel(x1, y1), tx);
+    top.lerp(&bottom, ty).into()
+}
+
+impl Texture for ImageTexture {
+    /// Surfaces without texture coordinates aren't covered.
+    fn color(&self, _point: &Vec3f, uv: Option<Vec2f>) -> Option<Vec3f> {
+        uv.map(|uv| self.lookup(uv))
+    }
+
+    fn filtered(&self, _point: &Vec3f, uv: Option<Vec2f>, footprint: Float) -> Option<Vec3f> {
+        uv.map(|uv| self.filtered(uv, footprint))
+    }
+}
@@ -0,0 +1,28 @@
+//! Colors that vary over the surfaces of objects: images looked up at the
+//! texture coordinates of the surface, `(0, 0)` at the bottom left of an
+//! image and `(1, 1)` at its top right, and patterns worked out from the
+//! point in space, which need no texture coordinates.
+
+use crate::vector::{Float, Vec2f, Vec3f};
+
+mod image;
+mod perlin;
+mod procedural;
+
+pub use image::ImageTexture;
+pub use perlin::Perlin;
+pub use procedural::{Checker, Marble, Noise, Stripes};
+
+pub trait Texture: Send + Sync {
+    /// The color at `point` of a surface with the texture coordinates `uv`,
+    /// or `None` where the texture doesn't cover it.
+    fn color(&self, point: &Vec3f, uv: Option<Vec2f>) -> Option<Vec3f>;
+
+    /// The color averaged over a patch of the surface `footprint` across in
+    /// texture coordinates around `uv`, seen from far enough away that
+    /// detail finer than that can't be told apart. Only images average
+    /// anything; patterns are looked up at the point.
+    fn filtered(&self, point: &Vec3f, uv: Option<Vec2f>, _footprint: Float) -> Option<Vec3f> {
+        self.color(point, uv)
+    }
+}
@@ -0,0 +1,114 @@
+//! Ken Perlin's improved gradient noise, "Improving Noise" (2002): smooth
+//! random ups and downs through space, the same at the same point every
+//! time.
+
+use crate::vector::{Float, Vec3f};
+
+/// Octaves summed up for turbulence
+const OCTAVES: usize = 7;
+
+pub struct Perlin {
+    /// A shuffle of `0..256`, twice over so that lookups need no wrapping
+    permutation: [u8; 512],
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+fn fade(t: Float) -> Float {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    a + t * (b - a)
+}
+
+/// The dot product of the offset with one of twelve gradients picked by
+/// the hash.
+fn gradient(hash: u8, x: Float, y: Float, z: Float) -> Float {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0..=3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Perlin {
+    /// Noise shuffled by the seed, so that different seeds give different
+    /// patterns.
+    pub fn new(seed: u32) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        // Fisher-Yates with xorshift, which is plenty random for this
+        let mut state = seed.wrapping_mul(0x9e37_79b9) | 1;
+        for i in (1..values.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            values.swap(i, state as usize % (i + 1));
+        }
+        let mut permutation = [0; 512];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = values[i % 256];
+        }
+        Self { permutation }
+    }
+
+    /// The noise at a point, between about -1 and 1, and 0 at the corners
+    /// of the unit cubes of the lattice.
+    pub fn noise(&self, point: &Vec3f) -> Float {
+        let p = &self.permutation;
+        let (fx, fy, fz) = (point.0.floor(), point.1.floor(), point.2.floor());
+        let (x, y, z) = (point.0 - fx, point.1 - fy, point.2 - fz);
+        let cell = |f: Float| (f as i64).rem_euclid(256) as usize;
+        let (cx, cy, cz) = (cell(fx), cell(fy), cell(fz));
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = p[cx] as usize + cy;
+        let (aa, ab) = (p[a] as usize + cz, p[a + 1] as usize + cz);
+        let b = p[cx + 1] as usize + cy;
+        let (ba, bb) = (p[b] as usize + cz, p[b + 1] as usize + cz);
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, gradient(p[aa], x, y, z), gradient(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    gradient(p[ab], x, y - 1.0, z),
+                    gradient(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    gradient(p[aa + 1], x, y, z - 1.0),
+                    gradient(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    gradient(p[ab + 1], x, y - 1.0, z - 1.0),
+                    gradient(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    /// Noise of ever finer detail summed up, each octave half as strong as
+    /// the one before, for the swirls of marble, clouds and smoke.
+    pub fn turbulence(&self, point: &Vec3f) -> Float {
+        let (mut sum, mut scale) = (0.0, 1.0);
+        for _ in 0..OCTAVES {
+            sum += self.noise(&(*point * scale)).abs() / scale;
+            scale *= 2.0;
+        }
+        sum
+    }
+}
@@ -0,0 +1,66 @@
+use super::{Perlin, Texture};
+use crate::vector::{Float, Vec2f, Vec3f};
+
+/// Cubes of `size` alternating between two colors through space, so that
+/// any surface cut through them is checkered.
+pub struct Checker {
+    pub size: Float,
+    pub colors: [Vec3f; 2],
+}
+
+impl Texture for Checker {
+    fn color(&self, point: &Vec3f, _uv: Option<Vec2f>) -> Option<Vec3f> {
+        let cell = |c: Float| (c / self.size).floor() as i64;
+        let parity = (cell(point.0) + cell(point.1) + cell(point.2)).rem_euclid(2);
+        Some(self.colors[parity as usize])
+    }
+}
+
+/// Slabs of `width` alternating between two colors along the unit `axis`.
+pub struct Stripes {
+    pub width: Float,
+    pub axis: Vec3f,
+    pub colors: [Vec3f; 2],
+}
+
+impl Texture for Stripes {
+    fn color(&self, point: &Vec3f, _uv: Option<Vec2f>) -> Option<Vec3f> {
+        let parity = ((point.dot(&self.axis) / self.width).floor() as i64).rem_euclid(2);
+        Some(self.colors[parity as usize])
+    }
+}
+
+/// A blend between two colors by Perlin noise, of features about `1 / scale`
+/// across.
+pub struct Noise {
+    pub scale: Float,
+    pub colors: [Vec3f; 2],
+    pub perlin: Perlin,
+}
+
+impl Texture for Noise {
+    fn color(&self, point: &Vec3f, _uv: Option<Vec2f>) -> Option<Vec3f> {
+        let t = 0.5 * (1.0 + self.perlin.noise(&(*point * self.scale)));
+        Some(self.colors[0].lerp(&self.colors[1], t))
+    }
+}
+
+/// Veins of the second color through the first, running across the unit
+/// `axis` 2π / `scale` apart, and bent by turbulence as strong as
+/// `turbulence`.
+pub struct Marble {
+    pub scale: Float,
+    pub turbulence: Float,
+    pub axis: Vec3f,
+    pub colors: [Vec3f; 2],
+    pub perlin: Perlin,
+}
+
+impl Texture for Marble {
+    fn color(&self, point: &Vec3f, _uv: Option<Vec2f>) -> Option<Vec3f> {
+        let p = *point * self.scale;
+        let phase = p.dot(&self.axis) + self.turbulence * self.perlin.turbulence(&p);
+        let t = 0.5 * (1.0 + phase.sin());
+        Some(self.colors[1].lerp(&self.colors[0], t))
+    }
+}
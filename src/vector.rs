@@ -1,76 +0,0 @@
-use std::ops::{Add, Mul, Neg, Sub};
-
-#[derive(Copy, Clone, Debug)]
-pub struct Vec3f(pub f32, pub f32, pub f32);
-
-impl Vec3f {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
-        Self(x, y, z)
-    }
-
-    pub fn len(&self) -> f32 {
-        self.norm().sqrt()
-    }
-
-    pub fn norm(&self) -> f32 {
-        self.0 * self.0 + self.1 * self.1 + self.2 * self.2
-    }
-
-    pub fn normalize(&self) -> Self {
-        let inv_len = self.len().recip();
-        Self(self.0 * inv_len, self.1 * inv_len, self.2 * inv_len)
-    }
-
-    pub fn dot(&self, other: &Self) -> f32 {
-        self.0 * other.0 + self.1 * other.1 + self.2 * other.2
-    }
-}
-
-impl Add for Vec3f {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self {
-        Self(self.0 + other.0, self.1 + other.1, self.2 + other.2)
-    }
-}
-
-impl Sub for Vec3f {
-    type Output = Self;
-
-    fn sub(self, other: Self) -> Self {
-        Self(self.0 - other.0, self.1 - other.1, self.2 - other.2)
-    }
-}
-
-impl Mul for Vec3f {
-    type Output = Self;
-
-    fn mul(self, other: Self) -> Self {
-        Self(self.0 * other.0, self.1 * other.1, self.2 * other.2)
-    }
-}
-
-impl Mul<f32> for Vec3f {
-    type Output = Self;
-
-    fn mul(self, other: f32) -> Self {
-        Self(self.0 * other, self.1 * other, self.2 * other)
-    }
-}
-
-impl Neg for Vec3f {
-    type Output = Self;
-
-    fn neg(self) -> Self {
-        Self(-self.0, -self.1, -self.2)
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
-pub struct Vec2f(pub f32, pub f32);
-
-impl Vec2f {
-    pub fn new(x: f32, y: f32) -> Self {
-        Self(x, y)
-    }
-}
@@ -0,0 +1,923 @@
+use std::ops::{
+    Add, AddAssign, Deref, DerefMut, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
+
+/// The scalar of all the math of the renderer: `f32`, or `f64` with the
+/// `f64` feature for scenes too large for the precision of `f32`, such as
+/// ones across astronomical distances.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+/// The mathematical constants of the [`Float`] type.
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+/// `x` in double precision, for the math that needs more than `f32` has
+/// even without the `f64` feature.
+#[allow(clippy::useless_conversion)]
+pub fn to_f64(x: Float) -> f64 {
+    f64::from(x)
+}
+
+/// `x` in single precision, as the file formats store it.
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(x: Float) -> f32 {
+    x as f32
+}
+
+/// `x` read in single precision from a file.
+#[allow(clippy::useless_conversion)]
+pub fn from_f32(x: f32) -> Float {
+    Float::from(x)
+}
+
+/// `x` from double precision, as sent between machines, which gives back
+/// exactly what `to_f64` was given.
+#[allow(clippy::unnecessary_cast)]
+pub fn from_f64(x: f64) -> Float {
+    x as Float
+}
+
+/// A vector of `N` components of type `T`, X first.
+///
+/// Vectors of two to four components deref to [`Xy`], [`Xyz`] and [`Xyzw`],
+/// which name their components as the fields `.0` to `.3`.
+#[derive(Copy, Clone, Debug)]
+pub struct Vector<T, const N: usize> {
+    components: [T; N],
+}
+
+pub type Vec2f = Vector<Float, 2>;
+#[cfg(not(feature = "portable-simd"))]
+pub type Vec3f = Vector<Float, 3>;
+pub type Vec4f = Vector<Float, 4>;
+
+#[cfg(feature = "portable-simd")]
+mod simd;
+#[cfg(feature = "portable-simd")]
+pub use simd::Vec3f;
+
+/// The components of a two-component vector as fields.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Xy<T>(pub T, pub T);
+
+/// The components of a three-component vector as fields.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Xyz<T>(pub T, pub T, pub T);
+
+/// The components of a four-component vector as fields.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Xyzw<T>(pub T, pub T, pub T, pub T);
+
+macro_rules! fields {
+    ($n:literal, $fields:ident) => {
+        impl<T> Deref for Vector<T, $n> {
+            type Target = $fields<T>;
+
+            fn deref(&self) -> &$fields<T> {
+                // The fields are laid out in order like the elements of an
+                // array, with nothing between them
+                unsafe { &*(self.components.as_ptr() as *const $fields<T>) }
+            }
+        }
+
+        impl<T> DerefMut for Vector<T, $n> {
+            fn deref_mut(&mut self) -> &mut $fields<T> {
+                unsafe { &mut *(self.components.as_mut_ptr() as *mut $fields<T>) }
+            }
+        }
+    };
+}
+
+fields!(2, Xy);
+fields!(3, Xyz);
+fields!(4, Xyzw);
+
+impl<T: Copy, const N: usize> Vector<T, N> {
+    fn map(self, f: impl Fn(T) -> T) -> Self {
+        Self {
+            components: self.components.map(f),
+        }
+    }
+
+    fn zip(self, other: Self, f: impl Fn(T, T) -> T) -> Self {
+        Self {
+            components: std::array::from_fn(|i| f(self.components[i], other.components[i])),
+        }
+    }
+}
+
+impl<T: Copy + Add<Output = T> + Mul<Output = T>, const N: usize> Vector<T, N> {
+    pub fn dot(&self, other: &Self) -> T {
+        let (a, b) = (&self.components, &other.components);
+        (1..N).fold(a[0] * b[0], |sum, i| sum + a[i] * b[i])
+    }
+
+    /// The squared length.
+    pub fn norm(&self) -> T {
+        self.dot(self)
+    }
+
+    /// The product of each component with that of `other`, the same as `*`.
+    pub fn hadamard(&self, other: &Self) -> Self {
+        *self * *other
+    }
+}
+
+impl<const N: usize> Vector<Float, N> {
+    pub fn len(&self) -> Float {
+        self.norm().sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let inv_len = self.len().recip();
+        self.map(|c| c * inv_len)
+    }
+
+    pub fn distance(&self, other: &Self) -> Float {
+        (*self - *other).len()
+    }
+
+    /// The part of this vector along `other`.
+    pub fn project_onto(&self, other: &Self) -> Self {
+        *other * (self.dot(other) / other.norm())
+    }
+
+    /// The smaller of each component and that of `other`.
+    pub fn min(&self, other: &Self) -> Self {
+        self.zip(*other, Float::min)
+    }
+
+    /// The larger of each component and that of `other`.
+    pub fn max(&self, other: &Self) -> Self {
+        self.zip(*other, Float::max)
+    }
+
+    pub fn min_component(&self) -> Float {
+        let c = &self.components;
+        c[1..].iter().fold(c[0], |min, &c| min.min(c))
+    }
+
+    pub fn max_component(&self) -> Float {
+        let c = &self.components;
+        c[1..].iter().fold(c[0], |max, &c| max.max(c))
+    }
+
+    /// Each component brought within `min..=max`.
+    pub fn clamp(&self, min: Float, max: Float) -> Self {
+        self.map(|c| c.max(min).min(max))
+    }
+
+    /// The point the fraction `t` of the way from this one to `other`,
+    /// exactly either end at 0 and 1.
+    pub fn lerp(&self, other: &Self, t: Float) -> Self {
+        *self * (1.0 - t) + *other * t
+    }
+
+    /// Whether each component is within `epsilon` of that of `other`.
+    pub fn approx_eq(&self, other: &Self, epsilon: Float) -> bool {
+        (0..N).all(|i| (self.components[i] - other.components[i]).abs() <= epsilon)
+    }
+}
+
+impl Vec2f {
+    pub const fn new(x: Float, y: Float) -> Self {
+        Self { components: [x, y] }
+    }
+}
+
+#[cfg(not(feature = "portable-simd"))]
+impl Vec3f {
+    pub const fn new(x: Float, y: Float, z: Float) -> Self {
+        Self {
+            components: [x, y, z],
+        }
+    }
+
+    pub fn cross(&self, other: &Self) -> Self {
+        Self::new(
+            self.1 * other.2 - self.2 * other.1,
+            self.2 * other.0 - self.0 * other.2,
+            self.0 * other.1 - self.1 * other.0,
+        )
+    }
+}
+
+impl Vec3f {
+    /// This vector with a fourth component `w`, such as 1 for a point and
+    /// 0 for a direction in homogeneous coordinates.
+    pub fn extend(&self, w: Float) -> Vec4f {
+        Vec4f::new(self.0, self.1, self.2, w)
+    }
+
+    /// The angle between the two vectors, in radians from 0 to π.
+    pub fn angle_between(&self, other: &Self) -> Float {
+        // The arctangent keeps its precision for nearly parallel vectors,
+        // where the arccosine of the dot product loses it
+        self.cross(other).len().atan2(self.dot(other))
+    }
+
+    /// Two unit vectors perpendicular to this one, which should be a unit
+    /// vector too, and to each other.
+    pub fn perpendiculars(&self) -> (Self, Self) {
+        let helper = if self.0.abs() > 0.9 {
+            Self::new(0.0, 1.0, 0.0)
+        } else {
+            Self::new(1.0, 0.0, 0.0)
+        };
+        let tangent = helper.cross(self).normalize();
+        (tangent, self.cross(&tangent))
+    }
+}
+
+impl Vec4f {
+    pub const fn new(x: Float, y: Float, z: Float, w: Float) -> Self {
+        Self {
+            components: [x, y, z, w],
+        }
+    }
+
+    /// The first three components, dropping the fourth.
+    pub fn truncate(&self) -> Vec3f {
+        Vec3f::new(self.0, self.1, self.2)
+    }
+}
+
+/// The components by axis, X first.
+impl<T, const N: usize> Index<usize> for Vector<T, N> {
+    type Output = T;
+
+    fn index(&self, axis: usize) -> &T {
+        match self.components.get(axis) {
+            Some(component) => component,
+            None => panic!("axis {} out of range for a vector of {}", axis, N),
+        }
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for Vector<T, N> {
+    fn index_mut(&mut self, axis: usize) -> &mut T {
+        match self.components.get_mut(axis) {
+            Some(component) => component,
+            None => panic!("axis {} out of range for a vector of {}", axis, N),
+        }
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for Vector<T, N> {
+    fn from(components: [T; N]) -> Self {
+        Self { components }
+    }
+}
+
+impl<T, const N: usize> From<Vector<T, N>> for [T; N] {
+    fn from(v: Vector<T, N>) -> Self {
+        v.components
+    }
+}
+
+/// The components, X first.
+impl<T, const N: usize> IntoIterator for Vector<T, N> {
+    type Item = T;
+    type IntoIter = std::array::IntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self.components)
+    }
+}
+
+impl<T: Copy + Add<Output = T>, const N: usize> Add for Vector<T, N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.zip(other, T::add)
+    }
+}
+
+impl<T: Copy + Sub<Output = T>, const N: usize> Sub for Vector<T, N> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.zip(other, T::sub)
+    }
+}
+
+impl<T: Copy + Mul<Output = T>, const N: usize> Mul for Vector<T, N> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.zip(other, T::mul)
+    }
+}
+
+impl<T: Copy + Mul<Output = T>, const N: usize> Mul<T> for Vector<T, N> {
+    type Output = Self;
+
+    fn mul(self, other: T) -> Self {
+        self.map(|c| c * other)
+    }
+}
+
+impl<const N: usize> Mul<Vector<Float, N>> for Float {
+    type Output = Vector<Float, N>;
+
+    fn mul(self, other: Vector<Float, N>) -> Vector<Float, N> {
+        other * self
+    }
+}
+
+impl<T: Copy + Div<Output = T>, const N: usize> Div<T> for Vector<T, N> {
+    type Output = Self;
+
+    fn div(self, other: T) -> Self {
+        self.map(|c| c / other)
+    }
+}
+
+impl<T: Copy + Neg<Output = T>, const N: usize> Neg for Vector<T, N> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.map(T::neg)
+    }
+}
+
+impl<T: Copy + Add<Output = T>, const N: usize> AddAssign for Vector<T, N> {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl<T: Copy + Sub<Output = T>, const N: usize> SubAssign for Vector<T, N> {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl<T: Copy + Mul<Output = T>, const N: usize> MulAssign for Vector<T, N> {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+impl<T: Copy + Mul<Output = T>, const N: usize> MulAssign<T> for Vector<T, N> {
+    fn mul_assign(&mut self, other: T) {
+        *self = *self * other;
+    }
+}
+
+/// The direction `incident` leaves in after bouncing off a surface with
+/// the unit normal `n` like off a mirror.
+pub fn reflect(incident: &Vec3f, n: &Vec3f) -> Vec3f {
+    *incident - *n * (2.0 * incident.dot(n))
+}
+
+/// The direction the unit vector `incident` bends into after passing into
+/// a medium `eta` times as dense optically, by Snell's law, or `None` if
+/// it is all reflected back instead. The unit normal `n` points out of the
+/// medium; rays going the way it points are leaving the medium instead,
+/// and bend the other way.
+pub fn refract(incident: &Vec3f, n: &Vec3f, eta: Float) -> Option<Vec3f> {
+    let cos_i = -incident.dot(n).clamp(-1.0, 1.0);
+    let (cos_i, n, eta) = if cos_i < 0.0 {
+        (-cos_i, -*n, eta)
+    } else {
+        (cos_i, *n, eta.recip())
+    };
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        return None;
+    }
+    Some(*incident * eta + n * (eta * cos_i - k.sqrt()))
+}
+
+/// A transform as a 4x4 matrix, by rows, applied to column vectors. Points
+/// and vectors are taken to be affine, without a perspective divide.
+#[derive(Copy, Clone, Debug)]
+pub struct Mat4(pub [[Float; 4]; 4]);
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        Self::scaling(Vec3f::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3f) -> Self {
+        Self([
+            [1.0, 0.0, 0.0, offset.0],
+            [0.0, 1.0, 0.0, offset.1],
+            [0.0, 0.0, 1.0, offset.2],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: Vec3f) -> Self {
+        Self([
+            [factors.0, 0.0, 0.0, 0.0],
+            [0.0, factors.1, 0.0, 0.0],
+            [0.0, 0.0, factors.2, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// A rotation by `angle` radians about the unit vector `axis`,
+    /// anticlockwise when looking down the axis towards the origin.
+    pub fn rotation(axis: Vec3f, angle: Float) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let [x, y, z] = <[Float; 3]>::from(axis);
+        let t = 1.0 - cos;
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transform_point(&self, p: &Vec3f) -> Vec3f {
+        self.transform_vector(p) + Vec3f::new(self.0[0][3], self.0[1][3], self.0[2][3])
+    }
+
+    /// Transforms a direction, which translations leave alone.
+    pub fn transform_vector(&self, v: &Vec3f) -> Vec3f {
+        let row = |r: [Float; 4]| r[0] * v.0 + r[1] * v.1 + r[2] * v.2;
+        Vec3f::new(row(self.0[0]), row(self.0[1]), row(self.0[2]))
+    }
+
+    /// Transforms a direction by the transpose of the matrix. Normals are
+    /// carried through a transform by the transpose of its inverse, so this
+    /// is how the inverse of a transform maps normals the other way.
+    pub fn transform_normal(&self, n: &Vec3f) -> Vec3f {
+        let column = |c: usize| self.0[0][c] * n.0 + self.0[1][c] * n.1 + self.0[2][c] * n.2;
+        Vec3f::new(column(0), column(1), column(2))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = self.0[c][r];
+            }
+        }
+        Self(m)
+    }
+
+    /// The transform undoing this one, or `None` if it flattens space and
+    /// can't be undone.
+    pub fn inverse(&self) -> Option<Self> {
+        // Gauss-Jordan elimination, picking the largest pivot of each
+        // column to keep the rounding errors down. A pivot within rounding
+        // of zero, next to the column it came from, is what is left of a
+        // column that depends on the others.
+        let mut m = self.0;
+        let mut inverse = Self::identity().0;
+        for c in 0..4 {
+            let pivot = (c..4).max_by(|&a, &b| m[a][c].abs().total_cmp(&m[b][c].abs()))?;
+            let column = (0..4).fold(0.0, |size: Float, r| size.max(self.0[r][c].abs()));
+            if m[pivot][c].abs() <= column * 16.0 * Float::EPSILON {
+                return None;
+            }
+            m.swap(c, pivot);
+            inverse.swap(c, pivot);
+            let scale = 1.0 / m[c][c];
+            for k in 0..4 {
+                m[c][k] *= scale;
+                inverse[c][k] *= scale;
+            }
+            for r in (0..4).filter(|&r| r != c) {
+                let factor = m[r][c];
+                for k in 0..4 {
+                    m[r][k] -= factor * m[c][k];
+                    inverse[r][k] -= factor * inverse[c][k];
+                }
+            }
+        }
+        Some(Self(inverse))
+    }
+
+    /// The factor the transform scales volumes by, negative if it mirrors
+    /// them.
+    pub fn determinant(&self) -> Float {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
+
+/// The transform applying `other` first and then this one.
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[r][k] * other.0[k][c]).sum();
+            }
+        }
+        Self(m)
+    }
+}
+
+/// The homogeneous vector transformed, with its fourth component too.
+impl Mul<Vec4f> for Mat4 {
+    type Output = Vec4f;
+
+    fn mul(self, v: Vec4f) -> Vec4f {
+        let row = |r: [Float; 4]| r[0] * v.0 + r[1] * v.1 + r[2] * v.2 + r[3] * v.3;
+        Vec4f::new(
+            row(self.0[0]),
+            row(self.0[1]),
+            row(self.0[2]),
+            row(self.0[3]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3f, b: Vec3f) {
+        assert!(a.approx_eq(&b, 1e-6), "{:?} is not {:?}", a, b);
+    }
+
+    #[test]
+    fn cross_follows_the_right_hand_rule() {
+        let (x, y, z) = (
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        );
+        assert_close(x.cross(&y), z);
+        assert_close(y.cross(&z), x);
+        assert_close(z.cross(&x), y);
+        assert_close(y.cross(&x), -z);
+    }
+
+    #[test]
+    fn cross_is_perpendicular_to_both() {
+        let (a, b) = (Vec3f::new(1.0, 2.0, 3.0), Vec3f::new(-2.0, 0.5, 4.0));
+        let c = a.cross(&b);
+        assert!(c.dot(&a).abs() < 1e-5);
+        assert!(c.dot(&b).abs() < 1e-5);
+        assert_close(a.cross(&a), Vec3f::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn operators() {
+        let v = Vec3f::new(1.0, -2.0, 4.0);
+        assert_close(-v, Vec3f::new(-1.0, 2.0, -4.0));
+        assert_close(v / 2.0, Vec3f::new(0.5, -1.0, 2.0));
+        assert_close(2.0 * v, v * 2.0);
+        let mut w = v;
+        w += Vec3f::new(1.0, 1.0, 1.0);
+        assert_close(w, Vec3f::new(2.0, -1.0, 5.0));
+        w -= v;
+        assert_close(w, Vec3f::new(1.0, 1.0, 1.0));
+        w *= 3.0;
+        assert_close(w, Vec3f::new(3.0, 3.0, 3.0));
+        w *= v;
+        assert_close(w, Vec3f::new(3.0, -6.0, 12.0));
+    }
+
+    #[test]
+    fn components() {
+        let mut v = Vec3f::from([1.0, 2.0, 3.0]);
+        assert_eq!((v[0], v[1], v[2]), (1.0, 2.0, 3.0));
+        v[1] = 5.0;
+        assert_eq!(<[Float; 3]>::from(v), [1.0, 5.0, 3.0]);
+        assert_eq!(v.into_iter().collect::<Vec<_>>(), vec![1.0, 5.0, 3.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_range() {
+        let _ = Vec3f::new(1.0, 2.0, 3.0)[3];
+    }
+
+    #[test]
+    fn approx_eq() {
+        let v = Vec3f::new(1.0, 2.0, 3.0);
+        assert!(v.approx_eq(&v, 0.0));
+        assert!(v.approx_eq(&Vec3f::new(1.05, 1.95, 3.0), 0.1));
+        assert!(!v.approx_eq(&Vec3f::new(1.05, 1.95, 3.0), 0.01));
+        assert!(!v.approx_eq(&Vec3f::new(1.0, 2.0, Float::NAN), 1.0));
+        assert!(Vec2f::new(0.5, 0.5).approx_eq(&Vec2f::new(0.5, 0.501), 1e-2));
+    }
+
+    #[test]
+    fn normalize() {
+        for v in [
+            Vec3f::new(3.0, 4.0, 0.0),
+            // Not much smaller, or the squared length goes subnormal and
+            // loses digits in single precision
+            Vec3f::new(-1e-15, 0.0, 1e-15),
+            Vec3f::new(1e15, -2e15, 3e15),
+        ] {
+            let n = v.normalize();
+            assert!(
+                (n.len() - 1.0).abs() < 1e-6,
+                "{:?} has length {}",
+                n,
+                n.len()
+            );
+            assert!(n.approx_eq(&(v * (1.0 / v.len())), 1e-6));
+        }
+        assert_close(
+            Vec3f::new(0.0, -2.0, 0.0).normalize(),
+            Vec3f::new(0.0, -1.0, 0.0),
+        );
+        // There's no direction to keep
+        assert!(Vec3f::new(0.0, 0.0, 0.0).normalize().0.is_nan());
+    }
+
+    #[test]
+    fn dot() {
+        let (a, b) = (Vec3f::new(1.0, 2.0, 3.0), Vec3f::new(-4.0, 5.0, 0.5));
+        assert_eq!(a.dot(&b), 7.5);
+        assert_eq!(a.dot(&b), b.dot(&a));
+        assert_eq!(a.dot(&a), a.norm());
+        assert_eq!(a.dot(&a.perpendiculars().0.cross(&a)), 0.0);
+        assert_eq!(
+            Vec3f::new(1.0, 0.0, 0.0).dot(&Vec3f::new(0.0, 1.0, 1.0)),
+            0.0
+        );
+        assert!((a.normalize().dot(&(-a).normalize()) + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reflect_mirrors_about_the_normal() {
+        let n = Vec3f::new(0.0, 1.0, 0.0);
+        let incident = Vec3f::new(1.0, -1.0, 0.0).normalize();
+        assert_close(
+            reflect(&incident, &n),
+            Vec3f::new(1.0, 1.0, 0.0).normalize(),
+        );
+        // Straight back when head on, and unchanged when grazing
+        assert_close(reflect(&-n, &n), n);
+        assert_close(
+            reflect(&Vec3f::new(1.0, 0.0, 0.0), &n),
+            Vec3f::new(1.0, 0.0, 0.0),
+        );
+        // The normal may face either way
+        assert_close(reflect(&incident, &-n), reflect(&incident, &n));
+        let v = Vec3f::new(0.3, -0.5, 0.8).normalize();
+        let n = Vec3f::new(1.0, 2.0, 2.0).normalize();
+        assert!((reflect(&v, &n).len() - 1.0).abs() < 1e-6);
+        assert!((reflect(&v, &n).dot(&n) + v.dot(&n)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn refract_bends_by_snells_law() {
+        let n = Vec3f::new(0.0, 1.0, 0.0);
+        let incident = Vec3f::new(1.0, -1.0, 0.0).normalize();
+        let refracted = refract(&incident, &n, 1.5).unwrap();
+        let sin = |v: Vec3f| v.cross(&n).len();
+        assert!((sin(incident) - 1.5 * sin(refracted)).abs() < 1e-6);
+        assert!((refracted.len() - 1.0).abs() < 1e-6);
+        assert!(refracted.1 < 0.0 && refracted.0 > 0.0);
+        // Head on rays go straight through
+        assert_close(refract(&-n, &n, 1.5).unwrap(), -n);
+        // Leaving the medium undoes entering it
+        let back = refract(&-refracted, &n, 1.5).unwrap();
+        assert_close(back, -incident);
+    }
+
+    #[test]
+    fn refract_reflects_past_the_critical_angle() {
+        let n = Vec3f::new(0.0, 1.0, 0.0);
+        // Leaving glass at 60 degrees, past its critical angle of about 42
+        let incident = Vec3f::new(Float::sqrt(3.0), 1.0, 0.0).normalize();
+        assert!(refract(&incident, &n, 1.5).is_none());
+        assert!(refract(&Vec3f::new(0.5, 1.0, 0.0).normalize(), &n, 1.5).is_some());
+    }
+
+    #[test]
+    fn distance() {
+        let (a, b) = (Vec3f::new(1.0, 2.0, 3.0), Vec3f::new(4.0, 6.0, 3.0));
+        assert_eq!(a.distance(&b), 5.0);
+        assert_eq!(b.distance(&a), 5.0);
+        assert_eq!(a.distance(&a), 0.0);
+    }
+
+    #[test]
+    fn angle_between() {
+        let x = Vec3f::new(2.0, 0.0, 0.0);
+        let angle = |v: Vec3f| x.angle_between(&v);
+        assert_eq!(angle(Vec3f::new(3.0, 0.0, 0.0)), 0.0);
+        assert!((angle(Vec3f::new(0.0, 0.0, 5.0)) - consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((angle(Vec3f::new(1.0, 1.0, 0.0)) - consts::FRAC_PI_4).abs() < 1e-6);
+        assert!((angle(Vec3f::new(-1.0, 0.0, 0.0)) - consts::PI).abs() < 1e-6);
+        // The arccosine of the dot product would round this to 0
+        assert!(angle(Vec3f::new(1.0, 1e-4, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn project_onto() {
+        let v = Vec3f::new(3.0, 4.0, 5.0);
+        assert_close(
+            v.project_onto(&Vec3f::new(0.0, 2.0, 0.0)),
+            Vec3f::new(0.0, 4.0, 0.0),
+        );
+        assert_close(
+            v.project_onto(&Vec3f::new(1.0, 1.0, 0.0)),
+            Vec3f::new(3.5, 3.5, 0.0),
+        );
+        let rest = v - v.project_onto(&Vec3f::new(1.0, 1.0, 0.0));
+        assert!(rest.dot(&Vec3f::new(1.0, 1.0, 0.0)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn componentwise() {
+        let a = Vec3f::new(1.0, -2.0, 3.0);
+        let b = Vec3f::new(-1.0, 4.0, 2.0);
+        assert_close(a.min(&b), Vec3f::new(-1.0, -2.0, 2.0));
+        assert_close(a.max(&b), Vec3f::new(1.0, 4.0, 3.0));
+        assert_close(a.hadamard(&b), Vec3f::new(-1.0, -8.0, 6.0));
+        assert_eq!(a.min_component(), -2.0);
+        assert_eq!(a.max_component(), 3.0);
+        assert_close(a.clamp(0.0, 2.0), Vec3f::new(1.0, 0.0, 2.0));
+    }
+
+    #[test]
+    fn lerp() {
+        let a = Vec3f::new(0.1, 0.2, 0.3);
+        let b = Vec3f::new(0.7, -0.3, 1.9);
+        assert_eq!(<[Float; 3]>::from(a.lerp(&b, 0.0)), <[Float; 3]>::from(a));
+        assert_eq!(<[Float; 3]>::from(a.lerp(&b, 1.0)), <[Float; 3]>::from(b));
+        assert_close(a.lerp(&b, 0.5), Vec3f::new(0.4, -0.05, 1.1));
+    }
+
+    #[test]
+    fn other_sizes() {
+        let uv = Vec2f::new(0.25, 0.5) + Vec2f::new(0.5, 0.25) * 2.0;
+        assert_eq!((uv.0, uv.1), (1.25, 1.0));
+        assert_eq!(Vec2f::new(3.0, 4.0).len(), 5.0);
+        let rgba = Vec4f::new(0.5, 0.25, 1.0, 0.5) * Vec4f::new(2.0, 4.0, 1.0, 2.0);
+        assert_eq!(<[Float; 4]>::from(rgba), [1.0; 4]);
+        assert_eq!(rgba.dot(&rgba), 4.0);
+    }
+
+    #[test]
+    fn homogeneous() {
+        let m =
+            Mat4::translation(Vec3f::new(1.0, 2.0, 3.0)) * Mat4::scaling(Vec3f::new(2.0, 2.0, 2.0));
+        let p = Vec3f::new(1.0, 1.0, 1.0);
+        let point = m * p.extend(1.0);
+        assert_eq!(point.3, 1.0);
+        assert_close(point.truncate(), m.transform_point(&p));
+        let direction = m * p.extend(0.0);
+        assert_eq!(direction.3, 0.0);
+        assert_close(direction.truncate(), m.transform_vector(&p));
+    }
+
+    fn assert_matrix_close(a: &Mat4, b: &Mat4, epsilon: Float) {
+        let close =
+            a.0.iter()
+                .flatten()
+                .zip(b.0.iter().flatten())
+                .all(|(a, b)| (a - b).abs() <= epsilon);
+        assert!(close, "{:?} is not {:?}", a, b);
+    }
+
+    /// A transform with a bit of everything: scaled unevenly, turned about
+    /// a slanted axis and moved.
+    fn transform() -> Mat4 {
+        Mat4::translation(Vec3f::new(1.0, -2.0, 3.0))
+            * Mat4::rotation(Vec3f::new(1.0, 2.0, 2.0).normalize(), 0.7)
+            * Mat4::scaling(Vec3f::new(2.0, 3.0, 0.5))
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let m = transform();
+        let inverse = m.inverse().unwrap();
+        assert_matrix_close(&(m * inverse), &Mat4::identity(), 1e-5);
+        assert_matrix_close(&(inverse * m), &Mat4::identity(), 1e-5);
+        let p = Vec3f::new(0.5, -4.0, 7.0);
+        assert!(inverse
+            .transform_point(&m.transform_point(&p))
+            .approx_eq(&p, 1e-5));
+        // Rows have to be swapped for this one, its first pivot being zero
+        let swap = Mat4([
+            [0.0, 1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_matrix_close(&swap.inverse().unwrap(), &swap, 0.0);
+        assert_matrix_close(&Mat4::identity().inverse().unwrap(), &Mat4::identity(), 0.0);
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        // Flattened onto a plane, and onto a line
+        assert!(Mat4::scaling(Vec3f::new(1.0, 0.0, 1.0)).inverse().is_none());
+        let line = Mat4([
+            [1.0, 2.0, 3.0, 0.0],
+            [2.0, 4.0, 6.0, 0.0],
+            [-1.0, -2.0, -3.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert!(line.inverse().is_none());
+        assert!(Mat4([[0.0; 4]; 4]).inverse().is_none());
+        // The third column is twice the second less the first, which
+        // rounding leaves a little off
+        let rounded = Mat4([
+            [0.1, 0.2, 0.3, 0.0],
+            [0.4, 0.5, 0.6, 0.0],
+            [0.7, 0.8, 0.9, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert!(rounded.inverse().is_none());
+        // Small, but not singular
+        let small = Mat4::translation(Vec3f::new(1000.0, 0.0, 0.0))
+            * Mat4::scaling(Vec3f::new(1e-6, 1.0, 1.0));
+        assert!(small.inverse().is_some());
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let m = transform();
+        let t = m.transpose();
+        for r in 0..4 {
+            for c in 0..4 {
+                assert_eq!(t.0[r][c], m.0[c][r]);
+            }
+        }
+        assert_matrix_close(&t.transpose(), &m, 0.0);
+        // Of a product, the product of the transposes the other way round
+        let other = Mat4::rotation(Vec3f::new(0.0, 0.0, 1.0), 1.2);
+        assert_matrix_close(&(m * other).transpose(), &(other.transpose() * t), 1e-5);
+    }
+
+    #[test]
+    fn rotations_turn_anticlockwise_about_their_axis() {
+        let (x, y, z) = (
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        );
+        let quarter = |axis| Mat4::rotation(axis, consts::FRAC_PI_2);
+        assert_close(quarter(z).transform_vector(&x), y);
+        assert_close(quarter(x).transform_vector(&y), z);
+        assert_close(quarter(y).transform_vector(&z), x);
+        // The axis stays put, and lengths and angles are kept
+        let axis = Vec3f::new(1.0, 2.0, 2.0).normalize();
+        let m = Mat4::rotation(axis, 0.7);
+        assert_close(m.transform_vector(&axis), axis);
+        let (a, b) = (m.transform_vector(&x), m.transform_vector(&y));
+        assert!((a.len() - 1.0).abs() < 1e-6 && a.dot(&b).abs() < 1e-6);
+        assert!((m.determinant() - 1.0).abs() < 1e-5);
+        // Turning back is the transpose
+        assert_matrix_close(&m.inverse().unwrap(), &m.transpose(), 1e-5);
+        assert_matrix_close(&Mat4::rotation(axis, -0.7), &m.transpose(), 1e-6);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_uneven_scaling() {
+        // The plane x = y, with a direction along it and its normal
+        let (along, normal) = (
+            Vec3f::new(1.0, 1.0, 0.0),
+            Vec3f::new(1.0, -1.0, 0.0).normalize(),
+        );
+        let m = transform();
+        let moved = m.transform_vector(&along);
+        // Transformed like a direction, the normal leans over
+        assert!(m.transform_vector(&normal).dot(&moved).abs() > 0.1);
+        let n = m.inverse().unwrap().transform_normal(&normal);
+        assert!(n.normalize().dot(&moved.normalize()).abs() < 1e-5);
+        // Scaling alone divides normals by the factors
+        let scaling = Mat4::scaling(Vec3f::new(4.0, 1.0, 0.5));
+        assert_close(
+            scaling
+                .inverse()
+                .unwrap()
+                .transform_normal(&Vec3f::new(1.0, 1.0, 1.0)),
+            Vec3f::new(0.25, 1.0, 2.0),
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! `Vec3f` held in the lanes of a SIMD vector, a fourth one left over, for
+//! the `portable-simd` feature. It has the same methods and operators as
+//! the plain one, and the same results to the bit: the components are
+//! worked on side by side, but sums across them are still added up in
+//! order.
+
+use std::ops::{
+    Add, AddAssign, Deref, DerefMut, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
+use std::simd::num::SimdFloat;
+use std::simd::{simd_swizzle, Simd};
+
+use super::{Float, Xyz};
+
+/// A vector of three components, X first.
+#[derive(Copy, Clone)]
+pub struct Vec3f {
+    /// X, Y, Z and a fourth lane, which nothing reads
+    lanes: Simd<Float, 4>,
+}
+
+impl Vec3f {
+    pub const fn new(x: Float, y: Float, z: Float) -> Self {
+        Self {
+            lanes: Simd::from_array([x, y, z, 0.0]),
+        }
+    }
+
+    fn components(&self) -> [Float; 3] {
+        let [x, y, z, _] = self.lanes.to_array();
+        [x, y, z]
+    }
+
+    fn splat(value: Float) -> Simd<Float, 4> {
+        Simd::splat(value)
+    }
+
+    pub fn dot(&self, other: &Self) -> Float {
+        let [x, y, z, _] = (self.lanes * other.lanes).to_array();
+        x + y + z
+    }
+
+    /// The squared length.
+    pub fn norm(&self) -> Float {
+        self.dot(self)
+    }
+
+    /// The product of each component with that of `other`, the same as `*`.
+    pub fn hadamard(&self, other: &Self) -> Self {
+        *self * *other
+    }
+
+    pub fn len(&self) -> Float {
+        self.norm().sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let inv_len = self.len().recip();
+        *self * inv_len
+    }
+
+    pub fn distance(&self, other: &Self) -> Float {
+        (*self - *other).len()
+    }
+
+    /// The part of this vector along `other`.
+    pub fn project_onto(&self, other: &Self) -> Self {
+        *other * (self.dot(other) / other.norm())
+    }
+
+    /// The smaller of each component and that of `other`.
+    pub fn min(&self, other: &Self) -> Self {
+        Self {
+            lanes: self.lanes.simd_min(other.lanes),
+        }
+    }
+
+    /// The larger of each component and that of `other`.
+    pub fn max(&self, other: &Self) -> Self {
+        Self {
+            lanes: self.lanes.simd_max(other.lanes),
+        }
+    }
+
+    pub fn min_component(&self) -> Float {
+        let [x, y, z] = self.components();
+        x.min(y).min(z)
+    }
+
+    pub fn max_component(&self) -> Float {
+        let [x, y, z] = self.components();
+        x.max(y).max(z)
+    }
+
+    /// Each component brought within `min..=max`.
+    pub fn clamp(&self, min: Float, max: Float) -> Self {
+        Self {
+            lanes: self
+                .lanes
+                .simd_max(Self::splat(min))
+                .simd_min(Self::splat(max)),
+        }
+    }
+
+    /// The point the fraction `t` of the way from this one to `other`,
+    /// exactly either end at 0 and 1.
+    pub fn lerp(&self, other: &Self, t: Float) -> Self {
+        *self * (1.0 - t) + *other * t
+    }
+
+    /// Whether each component is within `epsilon` of that of `other`.
+    pub fn approx_eq(&self, other: &Self, epsilon: Float) -> bool {
+        let [x, y, z] = (*self - *other).components();
+        x.abs() <= epsilon && y.abs() <= epsilon && z.abs() <= epsilon
+    }
+
+    pub fn cross(&self, other: &Self) -> Self {
+        let (a, b) = (self.lanes, other.lanes);
+        let yzx = |v| simd_swizzle!(v, [1, 2, 0, 3]);
+        let zxy = |v| simd_swizzle!(v, [2, 0, 1, 3]);
+        Self {
+            lanes: yzx(a) * zxy(b) - zxy(a) * yzx(b),
+        }
+    }
+}
+
+impl std::fmt::Debug for Vec3f {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Vector")
+            .field("components", &self.components())
+            .finish()
+    }
+}
+
+impl Deref for Vec3f {
+    type Target = Xyz<Float>;
+
+    fn deref(&self) -> &Xyz<Float> {
+        // The first three lanes are laid out like the fields
+        unsafe { &*(self.lanes.as_array().as_ptr() as *const Xyz<Float>) }
+    }
+}
+
+impl DerefMut for Vec3f {
+    fn deref_mut(&mut self) -> &mut Xyz<Float> {
+        unsafe { &mut *(self.lanes.as_mut_array().as_mut_ptr() as *mut Xyz<Float>) }
+    }
+}
+
+/// The components by axis, X first.
+impl Index<usize> for Vec3f {
+    type Output = Float;
+
+    fn index(&self, axis: usize) -> &Float {
+        match axis {
+            0..=2 => &self.lanes[axis],
+            _ => panic!("axis {} out of range for a vector of 3", axis),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec3f {
+    fn index_mut(&mut self, axis: usize) -> &mut Float {
+        match axis {
+            0..=2 => &mut self.lanes[axis],
+            _ => panic!("axis {} out of range for a vector of 3", axis),
+        }
+    }
+}
+
+impl From<[Float; 3]> for Vec3f {
+    fn from([x, y, z]: [Float; 3]) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<Vec3f> for [Float; 3] {
+    fn from(v: Vec3f) -> Self {
+        v.components()
+    }
+}
+
+/// The components, X first.
+impl IntoIterator for Vec3f {
+    type Item = Float;
+    type IntoIter = std::array::IntoIter<Float, 3>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self.components())
+    }
+}
+
+impl Add for Vec3f {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            lanes: self.lanes + other.lanes,
+        }
+    }
+}
+
+impl Sub for Vec3f {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            lanes: self.lanes - other.lanes,
+        }
+    }
+}
+
+impl Mul for Vec3f {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            lanes: self.lanes * other.lanes,
+        }
+    }
+}
+
+impl Mul<Float> for Vec3f {
+    type Output = Self;
+
+    fn mul(self, other: Float) -> Self {
+        Self {
+            lanes: self.lanes * Self::splat(other),
+        }
+    }
+}
+
+impl Mul<Vec3f> for Float {
+    type Output = Vec3f;
+
+    fn mul(self, other: Vec3f) -> Vec3f {
+        other * self
+    }
+}
+
+impl Div<Float> for Vec3f {
+    type Output = Self;
+
+    fn div(self, other: Float) -> Self {
+        Self {
+            lanes: self.lanes / Self::splat(other),
+        }
+    }
+}
+
+impl Neg for Vec3f {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self { lanes: -self.lanes }
+    }
+}
+
+impl AddAssign for Vec3f {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Vec3f {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for Vec3f {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+impl MulAssign<Float> for Vec3f {
+    fn mul_assign(&mut self, other: Float) {
+        *self = *self * other;
+    }
+}
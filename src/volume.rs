@@ -0,0 +1,129 @@
+//! Fog, and media such as smoke and haze filling boxes of the scene. Fog
+//! fades what camera rays hit into its color the further away it is; the
+//! media dim the light along camera rays through them and scatter the light
+//! of the lights into them, which shows the shafts of light past objects.
+
+use crate::aabb::Aabb;
+use crate::light;
+use crate::render::occluded_along;
+use crate::scene::Scene;
+use crate::vector::{Float, Vec3f};
+
+/// Steps camera rays take through each volume they cross
+const STEPS: usize = 32;
+
+/// Distance fog, thickening evenly with distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Fog {
+    pub color: Vec3f,
+    /// The fraction of the view lost to the fog per unit of distance,
+    /// compounded
+    pub density: Float,
+}
+
+/// A homogeneous medium filling a box, with coefficients per unit of
+/// distance for each channel.
+#[derive(Clone, Copy, Debug)]
+pub struct Volume {
+    pub bounds: Aabb,
+    /// Light the medium takes in
+    pub absorption: Vec3f,
+    /// Light the medium sends off in other directions, evenly in all of them
+    pub scattering: Vec3f,
+}
+
+fn exp(v: Vec3f) -> Vec3f {
+    Vec3f::new(v.0.exp(), v.1.exp(), v.2.exp())
+}
+
+impl Volume {
+    /// The light the medium scatters back along the ray from `orig` along
+    /// the unit direction `dir`, up to `distance`, and the fraction of the
+    /// light from beyond that makes it through, with the distance the ray
+    /// enters at. Steps are offset by `jitter`, from 0 to 1, so that many
+    /// samples smooth out the banding of the steps.
+    ///
+    /// Light is taken to reach the medium from the lights undimmed by the
+    /// medium itself.
+    fn march(
+        &self,
+        scene: &Scene,
+        orig: &Vec3f,
+        dir: &Vec3f,
+        distance: Float,
+        jitter: Float,
+    ) -> Option<(Float, Vec3f, Vec3f)> {
+        let (enter, leave) = self.bounds.intersect(orig, dir)?;
+        let (enter, leave) = (enter.max(0.0), leave.min(distance));
+        if leave <= enter {
+            return None;
+        }
+        let step = (leave - enter) / STEPS as Float;
+        let extinction = self.absorption + self.scattering;
+        let step_transmittance = exp(extinction * -step);
+
+        let mut transmittance = Vec3f::new(1.0, 1.0, 1.0);
+        let mut scattered = Vec3f::new(0.0, 0.0, 0.0);
+        for i in 0..STEPS {
+            let point = *orig + *dir * (enter + (i as Float + jitter) * step);
+            let mut incoming = Vec3f::new(0.0, 0.0, 0.0);
+            for light in scene
+                .lights
+                .iter()
+                .filter(|light| light::shines(light.group()))
+            {
+                let incident = light.incident(&point, (0.5, 0.5));
+                if !occluded_along(scene, &point, &incident.direction, incident.distance) {
+                    incoming += incident.intensity;
+                }
+            }
+            // Lights of intensity one shed π on a surface facing them, a
+            // quarter of which an even scattering over the sphere sends
+            // towards each unit of solid angle
+            scattered += transmittance * self.scattering * incoming * (0.25 * step);
+            transmittance *= step_transmittance;
+        }
+        Some((enter, scattered, transmittance))
+    }
+}
+
+/// The light arriving at `orig` along the unit direction `dir` through the
+/// volumes and the fog of the scene, from `color` seen at `distance`, or
+/// from the background without one, which the fog leaves as it is.
+pub fn along_ray(
+    scene: &Scene,
+    orig: &Vec3f,
+    dir: &Vec3f,
+    distance: Option<Float>,
+    color: Vec3f,
+    jitter: Float,
+) -> Vec3f {
+    let mut crossed: Vec<_> = scene
+        .volumes
+        .iter()
+        .filter_map(|volume| {
+            volume.march(
+                scene,
+                orig,
+                dir,
+                distance.unwrap_or(Float::INFINITY),
+                jitter,
+            )
+        })
+        .collect();
+    // From the furthest volume back towards the origin
+    crossed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let color = crossed
+        .iter()
+        .fold(color, |color, &(_, scattered, transmittance)| {
+            scattered + transmittance * color
+        });
+
+    match (&scene.fog, distance) {
+        (Some(fog), Some(distance)) => {
+            let visible = (-fog.density * distance).exp();
+            fog.color.lerp(&color, visible)
+        }
+        _ => color,
+    }
+}
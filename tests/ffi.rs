@@ -0,0 +1,112 @@
+//! Checks the C interface: that `include/tiny_raytracer.h` declares every
+//! function `src/ffi.rs` exports, and that a scene put together through the
+//! functions renders.
+
+use std::ffi::CStr;
+use std::fs;
+use std::path::Path;
+use std::ptr;
+
+use tiny_raytracer::ffi::*;
+
+fn read(path: &str) -> String {
+    fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap()
+}
+
+#[test]
+fn header_declares_exports() {
+    let (source, header) = (read("src/ffi.rs"), read("include/tiny_raytracer.h"));
+    let exports: Vec<_> = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .map(|rest| &rest[..rest.find('(').unwrap()])
+        .collect();
+    assert!(exports.len() >= 8, "found only {:?}", exports);
+    for name in exports {
+        assert!(
+            header.contains(&format!(" {}(", name)) || header.contains(&format!("*{}(", name)),
+            "the header doesn't declare {}",
+            name
+        );
+    }
+}
+
+#[test]
+fn renders_a_scene() {
+    unsafe {
+        let mut ivory = std::mem::zeroed();
+        assert_eq!(
+            trt_material_preset(b"ivory\0".as_ptr().cast(), &mut ivory),
+            0
+        );
+        assert_eq!(
+            trt_material_preset(b"marble\0".as_ptr().cast(), &mut ivory),
+            -1
+        );
+        assert!(!CStr::from_ptr(trt_last_error()).to_bytes().is_empty());
+
+        let scene = trt_scene_new();
+        assert_eq!(trt_scene_add_sphere(scene, 0.0, 0.0, -5.0, 1.0, &ivory), 0);
+        assert_eq!(trt_scene_add_sphere(scene, 0.0, 0.0, -5.0, 0.0, &ivory), -1);
+        assert_eq!(trt_scene_add_light(scene, 5.0, 5.0, 0.0, 1.0, 1.0, 1.0), 0);
+        let (position, target, up) = ([0.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]);
+        let camera = (position.as_ptr(), target.as_ptr(), up.as_ptr());
+        assert_eq!(
+            trt_scene_set_camera(scene, camera.0, camera.1, camera.2, 60.0),
+            0
+        );
+        assert_eq!(
+            trt_scene_set_camera(scene, camera.0, camera.0, camera.2, 60.0),
+            -1
+        );
+
+        let (width, height) = (16, 12);
+        let mut rgba = vec![0; width * height * 4];
+        assert_eq!(trt_render(scene, width, height, 1, rgba.as_mut_ptr()), 0);
+        assert_eq!(trt_render(scene, width, height, 1, ptr::null_mut()), -1);
+        trt_scene_free(scene);
+
+        // The sphere covers the middle and the background the corners
+        let alpha = |x: usize, y: usize| rgba[(x + y * width) * 4 + 3];
+        assert_eq!(alpha(width / 2, height / 2), 255);
+        assert_eq!(alpha(0, 0), 0);
+        assert!(rgba[(width / 2 + height / 2 * width) * 4] > 0);
+    }
+}
+
+#[test]
+fn renders_a_scene_source() {
+    let source = br#"{
+        "objects": [{ "type": "sphere", "center": [0, 0, -5], "radius": 1, "material": "ivory" }],
+        "lights": [{ "position": [5, 5, 0], "intensity": 1 }]
+    }"#;
+    let (width, height) = (16, 12);
+    unsafe {
+        let length = width * height * 4;
+        let rgba = trt_alloc(length);
+        assert!(!rgba.is_null());
+        assert_eq!(
+            trt_render_source(source.as_ptr(), source.len(), width, height, 1, rgba),
+            0
+        );
+        let pixels = std::slice::from_raw_parts(rgba, length).to_vec();
+        let bad = b"{ \"objects\": [{ \"type\": \"cone\" }] }";
+        assert_eq!(
+            trt_render_source(bad.as_ptr(), bad.len(), width, height, 1, rgba),
+            -1
+        );
+        assert!(!CStr::from_ptr(trt_last_error()).to_bytes().is_empty());
+        assert_eq!(
+            trt_render_source(source.as_ptr(), source.len(), 0, height, 1, rgba),
+            -1
+        );
+        trt_free(rgba, length);
+        trt_free(ptr::null_mut(), length);
+
+        // The sphere covers the middle and the background the corners
+        let pixel = |x: usize, y: usize| &pixels[(x + y * width) * 4..][..4];
+        assert!(pixel(width / 2, height / 2)[0] > 0);
+        assert_eq!(pixel(width / 2, height / 2)[3], 255);
+        assert_eq!(pixel(0, 0)[3], 0);
+    }
+}
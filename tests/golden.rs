@@ -0,0 +1,302 @@
+//! Renders small scenes and compares them with the reference images in
+//! `tests/golden`, so that changes to the shading can't go unnoticed.
+//!
+//! The images are compared by their structural similarity and by how much
+//! brighter or darker they are overall, which allows for the rounding
+//! differences between builds, such as with `--features f64`, but not for a
+//! change anyone would see. After a change to the images
+//! that is meant to be, write new references with
+//!
+//! ```sh
+//! UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+//!
+//! and look them over before committing them. A render that doesn't match
+//! is written to `target/golden` for comparing by eye.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tiny_raytracer::accel::AcceleratorKind;
+use tiny_raytracer::input;
+use tiny_raytracer::integrator::{AmbientOcclusion, Integrator};
+use tiny_raytracer::output::{self, Format, OutputOptions};
+use tiny_raytracer::preset;
+use tiny_raytracer::render::{self, Image, RenderOptions, RenderState};
+use tiny_raytracer::scene::Scene;
+use tiny_raytracer::vector::Float;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 48;
+/// The least structural similarity a render may have with its reference
+const MIN_SIMILARITY: Float = 0.99;
+/// How much brighter or darker a render may be than its reference on
+/// average, a quarter of a step of 8-bit color
+const MAX_BIAS: Float = 0.25 / 255.0;
+
+fn render(scene: &Scene, options: &RenderOptions) -> Image {
+    let state = RenderState::new(WIDTH, HEIGHT, options.tile_size);
+    render::resume(scene, options, state, |_, _| {}).image
+}
+
+fn brightness(r: Float, g: Float, b: Float) -> Float {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// The brightness of each pixel of a render as it is written to a PNG, from
+/// 0 to 1.
+fn encoded_brightness(image: &Image) -> Vec<Float> {
+    output::samples(image, &OutputOptions::default())
+        .chunks(3)
+        .map(|rgb| brightness(rgb[0] as Float, rgb[1] as Float, rgb[2] as Float) / 255.0)
+        .collect()
+}
+
+/// The brightness of each pixel of a PNG, from 0 to 1, read as it is stored.
+fn stored_brightness(path: &Path) -> Vec<Float> {
+    let image = input::read_data(path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {}, run with UPDATE_GOLDEN=1 to write it",
+            path.display(),
+            e
+        )
+    });
+    assert_eq!(
+        (image.width, image.height),
+        (WIDTH, HEIGHT),
+        "{}: wrong size",
+        path.display()
+    );
+    image
+        .pixels
+        .iter()
+        .map(|p| brightness(p.r, p.g, p.b))
+        .collect()
+}
+
+/// The mean structural similarity of the brightness of two images of the
+/// same size, over 8 by 8 windows half overlapping: 1 for the same image,
+/// and less the more their shapes, contrast and brightness differ.
+fn similarity(a: &[Float], b: &[Float]) -> Float {
+    const WINDOW: usize = 8;
+    const C1: Float = 0.01 * 0.01;
+    const C2: Float = 0.03 * 0.03;
+    let (width, height) = (WIDTH, HEIGHT);
+    let (mut sum, mut windows) = (0.0, 0);
+    for y in (0..=height - WINDOW).step_by(WINDOW / 2) {
+        for x in (0..=width - WINDOW).step_by(WINDOW / 2) {
+            let pixels: Vec<_> = (y..y + WINDOW)
+                .flat_map(|y| (x..x + WINDOW).map(move |x| x + y * width))
+                .collect();
+            let n = pixels.len() as Float;
+            let mean = |v: &[Float]| pixels.iter().map(|&i| v[i]).sum::<Float>() / n;
+            let (mean_a, mean_b) = (mean(a), mean(b));
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for &i in &pixels {
+                let (da, db) = (a[i] - mean_a, b[i] - mean_b);
+                var_a += da * da / n;
+                var_b += db * db / n;
+                covariance += da * db / n;
+            }
+            sum += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    sum / windows as Float
+}
+
+fn png(image: &Image, path: &Path) {
+    let options = OutputOptions {
+        format: Format::Png,
+        ..OutputOptions::default()
+    };
+    output::write_as(image, path, &options).unwrap();
+}
+
+/// Renders the scene and compares it with the reference named `name`, or
+/// replaces the reference if asked to.
+fn check(name: &str, scene: &Scene, options: &RenderOptions) {
+    let image = render(scene, options);
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        png(&image, &reference);
+        return;
+    }
+
+    let (actual, expected) = (encoded_brightness(&image), stored_brightness(&reference));
+    let similarity = similarity(&actual, &expected);
+    let bias = actual
+        .iter()
+        .zip(&expected)
+        .map(|(a, b)| a - b)
+        .sum::<Float>()
+        / actual.len() as Float;
+    if similarity < MIN_SIMILARITY || bias.abs() > MAX_BIAS {
+        let actual: PathBuf = [env!("CARGO_MANIFEST_DIR"), "target", "golden"]
+            .iter()
+            .collect();
+        fs::create_dir_all(&actual).unwrap();
+        let actual = actual.join(format!("{}.png", name));
+        png(&image, &actual);
+        panic!(
+            "{} is {:.4} similar to {} and off by {:+.5} on average, see {}",
+            name,
+            similarity,
+            reference.display(),
+            bias,
+            actual.display()
+        );
+    }
+}
+
+fn load(name: &str) -> Scene {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("scenes")
+        .join(format!("{}.json", name));
+    Scene::load(path).unwrap()
+}
+
+fn path_traced() -> RenderOptions {
+    RenderOptions {
+        integrator: Integrator::Path,
+        samples_per_pixel: 4,
+        ..RenderOptions::default()
+    }
+}
+
+#[test]
+fn tutorial() {
+    check("tutorial", &Scene::tutorial(), &RenderOptions::default());
+}
+
+#[test]
+fn primitives() {
+    check("primitives", &load("primitives"), &RenderOptions::default());
+}
+
+#[test]
+fn mesh_path_traced() {
+    check("mesh-path", &load("mesh"), &path_traced());
+}
+
+#[test]
+fn emissive_path_traced() {
+    check("emissive-path", &load("emissive"), &path_traced());
+}
+
+#[test]
+fn cornell_box_path_traced() {
+    let scene = preset::cornell_box(AcceleratorKind::default());
+    check("cornell-path", &scene, &path_traced());
+}
+
+#[test]
+fn blobs() {
+    check("blobs", &load("blobs"), &RenderOptions::default());
+}
+
+#[test]
+fn ambient_occlusion() {
+    let options = RenderOptions {
+        integrator: Integrator::AmbientOcclusion,
+        ambient_occlusion: AmbientOcclusion {
+            distance: 8.0,
+            ..AmbientOcclusion::default()
+        },
+        ..RenderOptions::default()
+    };
+    check("emissive-ao", &load("emissive"), &options);
+}
+
+#[test]
+fn glass() {
+    check("glass", &load("glass"), &RenderOptions::default());
+}
+
+#[test]
+fn glass_path_traced() {
+    check("glass-path", &load("glass"), &path_traced());
+}
+
+/// The color through the middle of a sphere of green-absorbing glass of
+/// `radius` in front of a white background.
+fn through_colored_glass(radius: Float, options: &RenderOptions) -> [Float; 3] {
+    let source = format!(
+        r#"{{
+            "objects": [{{
+                "type": "sphere",
+                "center": [0, 0, -10],
+                "radius": {},
+                "material": {{
+                    "refractive_index": 1.5,
+                    "absorption": {{ "color": [1, 0.5, 1], "distance": 1 }}
+                }}
+            }}],
+            "background": [1, 1, 1]
+        }}"#,
+        radius
+    );
+    let scene = Scene::parse(&source, Path::new(".")).unwrap();
+    let image = render(&scene, options);
+    image.pixels[HEIGHT / 2 * WIDTH + WIDTH / 2].into()
+}
+
+#[test]
+fn colored_glass_darkens_with_thickness() {
+    let options = RenderOptions::default();
+    let (thin, thick) = (
+        through_colored_glass(0.5, &options),
+        through_colored_glass(1.0, &options),
+    );
+    // Through one and two lengths of the glass, less what the surfaces
+    // reflect of the white around it
+    assert!((thin[0] - 1.0).abs() < 0.01 && (thin[2] - 1.0).abs() < 0.01);
+    assert!((thin[1] - 0.5).abs() < 0.06, "{:?}", thin);
+    assert!((thick[1] - 0.25).abs() < 0.06, "{:?}", thick);
+}
+
+#[test]
+fn max_depth_cuts_off_rays_through_glass() {
+    // Through the sphere takes bouncing off the front and the back
+    let through = |max_depth| {
+        through_colored_glass(
+            1.0,
+            &RenderOptions {
+                max_depth,
+                ..RenderOptions::default()
+            },
+        )
+    };
+    let (cut, full) = (through(1), through(3));
+    assert!(cut.iter().all(|&channel| channel < 0.01), "{:?}", cut);
+    assert!((full[1] - 0.25).abs() < 0.06, "{:?}", full);
+}
+
+#[test]
+fn dispersion_spectral() {
+    let options = RenderOptions {
+        integrator: Integrator::Spectral,
+        samples_per_pixel: 16,
+        ..RenderOptions::default()
+    };
+    check("dispersion-spectral", &load("dispersion"), &options);
+}
+
+#[test]
+fn wavelengths_add_up_to_the_colors_of_the_path_tracer() {
+    let options = |integrator| RenderOptions {
+        integrator,
+        samples_per_pixel: 256,
+        ..RenderOptions::default()
+    };
+    let path = through_colored_glass(0.5, &options(Integrator::Path));
+    let spectral = through_colored_glass(0.5, &options(Integrator::Spectral));
+    for (path, spectral) in path.iter().zip(&spectral) {
+        assert!((path - spectral).abs() < 0.03, "{:?} {:?}", path, spectral);
+    }
+}